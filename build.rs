@@ -0,0 +1,6 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Builds protoc from source, so that no protoc has to be installed.
+    std::env::set_var("PROTOC", protobuf_src::protoc());
+    tonic_build::compile_protos("proto/photon.proto")?;
+    Ok(())
+}
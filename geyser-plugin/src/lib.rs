@@ -0,0 +1,277 @@
+//! Geyser plugin that forwards the blocks of a validator to Photon over a Unix socket, so that
+//! operators who run their own validator can index without going through RPC. Start Photon with
+//! `--geyser-plugin-socket` pointing to the `socket_path` of the plugin config.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::thread;
+
+use log::{error, info, warn};
+use photon_indexer::common::typedefs::hash::Hash;
+use photon_indexer::ingester::fetchers::socket::encode_block_frame;
+use photon_indexer::ingester::parser::ACCOUNT_COMPRESSION_PROGRAM_ID;
+use photon_indexer::ingester::typedefs::block_info::{
+    parse_sanitized_transaction, BlockInfo, BlockMetadata, TransactionInfo,
+};
+use serde::Deserialize;
+use solana_geyser_plugin_interface::geyser_plugin_interface::{
+    GeyserPlugin, GeyserPluginError, ReplicaBlockInfoVersions, ReplicaTransactionInfoVersions,
+    Result, SlotStatus,
+};
+use solana_sdk::clock::Slot;
+
+// Number of confirmed blocks that are buffered for Photon. Blocks are dropped once the buffer is
+// full, e.g. while Photon is not connected, and Photon then fetches them over RPC instead.
+const BLOCK_BUFFER_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    socket_path: String,
+    /// Only forward transactions that use the account compression program. Photon then misses the
+    /// other transactions in `getLatestNonVotingSignatures`.
+    #[serde(default = "default_compression_transactions_only")]
+    compression_transactions_only: bool,
+}
+
+fn default_compression_transactions_only() -> bool {
+    true
+}
+
+#[derive(Debug, Default)]
+pub struct PhotonGeyserPlugin {
+    compression_transactions_only: bool,
+    // Transactions of the slots whose block has not been frozen yet, with their index in the block.
+    transactions: Mutex<HashMap<Slot, Vec<(usize, TransactionInfo)>>>,
+    // Frozen blocks that have not been confirmed yet. Photon indexes at confirmed commitment.
+    blocks: Mutex<BTreeMap<Slot, BlockInfo>>,
+    sender: Option<SyncSender<Vec<u8>>>,
+}
+
+impl PhotonGeyserPlugin {
+    fn send_block(&self, block: &BlockInfo) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let frame = match encode_block_frame(block) {
+            Ok(frame) => frame,
+            Err(e) => {
+                error!("Failed to encode block {}: {}", block.metadata.slot, e);
+                return;
+            }
+        };
+        match sender.try_send(frame) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    "Dropping block {} because Photon is not keeping up",
+                    block.metadata.slot
+                );
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!(
+                    "Dropping block {} because the socket thread exited",
+                    block.metadata.slot
+                );
+            }
+        }
+    }
+}
+
+impl GeyserPlugin for PhotonGeyserPlugin {
+    fn name(&self) -> &'static str {
+        "photon-geyser-plugin"
+    }
+
+    fn on_load(&mut self, config_file: &str, _is_reload: bool) -> Result<()> {
+        let config: Config = serde_json::from_str(&std::fs::read_to_string(config_file)?)
+            .map_err(|e| GeyserPluginError::ConfigFileReadError { msg: e.to_string() })?;
+        // A socket file left behind by a previous run would make binding fail.
+        let _ = std::fs::remove_file(&config.socket_path);
+        let listener = UnixListener::bind(&config.socket_path)?;
+        let (sender, receiver) = sync_channel(BLOCK_BUFFER_SIZE);
+        thread::Builder::new()
+            .name("photon-socket".to_string())
+            .spawn(move || serve_socket(listener, receiver))?;
+        info!("Serving blocks to Photon on {}", config.socket_path);
+        self.compression_transactions_only = config.compression_transactions_only;
+        self.sender = Some(sender);
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        // Closing the channel ends the socket thread once it accepts the next connection.
+        self.sender = None;
+    }
+
+    fn notify_transaction(
+        &self,
+        transaction: ReplicaTransactionInfoVersions,
+        slot: Slot,
+    ) -> Result<()> {
+        // Validators since 1.16 only send V0_0_2, which is the first version with the index of the
+        // transaction in the block.
+        let ReplicaTransactionInfoVersions::V0_0_2(transaction) = transaction else {
+            return Ok(());
+        };
+        if transaction.is_vote {
+            return Ok(());
+        }
+        if self.compression_transactions_only
+            && !transaction
+                .transaction
+                .message()
+                .account_keys()
+                .iter()
+                .any(|key| *key == ACCOUNT_COMPRESSION_PROGRAM_ID)
+        {
+            return Ok(());
+        }
+        match parse_sanitized_transaction(
+            transaction.transaction,
+            transaction.transaction_status_meta,
+        ) {
+            Ok(transaction_info) => self
+                .transactions
+                .lock()
+                .unwrap()
+                .entry(slot)
+                .or_default()
+                .push((transaction.index, transaction_info)),
+            Err(e) => error!(
+                "Dropping malformed transaction {} in slot {}: {}",
+                transaction.signature, slot, e
+            ),
+        }
+        Ok(())
+    }
+
+    fn notify_block_metadata(&self, block_info: ReplicaBlockInfoVersions) -> Result<()> {
+        let (slot, parent_slot, blockhash, parent_blockhash, block_time, block_height) =
+            match block_info {
+                ReplicaBlockInfoVersions::V0_0_3(info) => (
+                    info.slot,
+                    info.parent_slot,
+                    info.blockhash,
+                    info.parent_blockhash,
+                    info.block_time,
+                    info.block_height,
+                ),
+                ReplicaBlockInfoVersions::V0_0_2(info) => (
+                    info.slot,
+                    info.parent_slot,
+                    info.blockhash,
+                    info.parent_blockhash,
+                    info.block_time,
+                    info.block_height,
+                ),
+                // Lacks the parent of the block, which Photon needs to detect forks.
+                ReplicaBlockInfoVersions::V0_0_1(_) => return Ok(()),
+            };
+        let invalid_block = |msg: String| GeyserPluginError::Custom(msg.into());
+        let mut transactions = self
+            .transactions
+            .lock()
+            .unwrap()
+            .remove(&slot)
+            .unwrap_or_default();
+        transactions.sort_by_key(|(index, _)| *index);
+        let block = BlockInfo {
+            metadata: BlockMetadata {
+                slot,
+                parent_slot,
+                block_time: block_time
+                    .ok_or_else(|| invalid_block(format!("Missing block time in {}", slot)))?,
+                blockhash: Hash::try_from(blockhash)
+                    .map_err(|e| invalid_block(format!("Invalid blockhash: {}", e)))?,
+                parent_blockhash: Hash::try_from(parent_blockhash)
+                    .map_err(|e| invalid_block(format!("Invalid parent blockhash: {}", e)))?,
+                block_height: block_height
+                    .ok_or_else(|| invalid_block(format!("Missing block height in {}", slot)))?,
+                received_at: None,
+            },
+            transactions: transactions
+                .into_iter()
+                .map(|(_, transaction)| transaction)
+                .collect(),
+        };
+        self.blocks.lock().unwrap().insert(slot, block);
+        Ok(())
+    }
+
+    fn update_slot_status(
+        &self,
+        slot: Slot,
+        _parent: Option<u64>,
+        status: SlotStatus,
+    ) -> Result<()> {
+        if status != SlotStatus::Confirmed {
+            return Ok(());
+        }
+        // Confirming a slot confirms all its ancestors, which might not have been reported as
+        // confirmed themselves. Blocks that were sent before are no longer buffered.
+        let mut blocks = self.blocks.lock().unwrap();
+        let mut confirmed_blocks = Vec::new();
+        let mut next_slot = Some(slot);
+        while let Some(block) = next_slot.and_then(|slot| blocks.remove(&slot)) {
+            next_slot = Some(block.metadata.parent_slot);
+            confirmed_blocks.push(block);
+        }
+        // The remaining blocks up to the confirmed slot are on abandoned forks.
+        blocks.retain(|block_slot, _| *block_slot > slot);
+        drop(blocks);
+        self.transactions
+            .lock()
+            .unwrap()
+            .retain(|transaction_slot, _| *transaction_slot > slot);
+
+        for block in confirmed_blocks.iter().rev() {
+            self.send_block(block);
+        }
+        Ok(())
+    }
+
+    fn transaction_notifications_enabled(&self) -> bool {
+        true
+    }
+
+    fn account_data_notifications_enabled(&self) -> bool {
+        false
+    }
+}
+
+// Accepts one Photon connection at a time and writes the encoded blocks to it.
+fn serve_socket(listener: UnixListener, receiver: Receiver<Vec<u8>>) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("Failed to accept Photon connection: {}", e);
+                continue;
+            }
+        };
+        info!("Photon connected");
+        loop {
+            let Ok(frame) = receiver.recv() else {
+                return;
+            };
+            if let Err(e) = stream.write_all(&frame) {
+                warn!("Photon disconnected: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+/// # Safety
+///
+/// Called by the validator when loading the plugin. The returned pointer is owned by the
+/// validator.
+pub unsafe extern "C" fn _create_plugin() -> *mut dyn GeyserPlugin {
+    let plugin: Box<dyn GeyserPlugin> = Box::<PhotonGeyserPlugin>::default();
+    Box::into_raw(plugin)
+}
@@ -1,20 +1,43 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+use futures::future::join_all;
+use log::warn;
 use sea_orm::{ConnectionTrait, DatabaseConnection, Statement};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::task::JoinHandle;
 use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
 use utoipa::ToSchema;
 
 use crate::api::method::utils::GetNonPaginatedSignaturesResponse;
 use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::ingester::indexer::{backfill_progress, BackfillProgress};
+use crate::ingester::persist::batch_size::SqlBatchConfig;
+use crate::ingester::persist::in_memory_tree::InMemoryTrees;
+
+use super::cache::ApiCache;
+use super::cors::CorsConfig;
+use super::graphql::GraphQLLimits;
+use super::rate_limit::{RateLimitConfig, RateLimiter};
+use super::read_replicas::ReadReplicas;
+use super::request_limits::{MethodFilter, RequestLimits};
+use super::warm_up::warm_up_database;
 
 use super::method::get_compressed_account::AccountResponse;
+use super::method::get_compressed_address_info::{
+    get_compressed_address_info, GetCompressedAddressInfoRequest, GetCompressedAddressInfoResponse,
+};
 use super::method::get_compressed_balance_by_owner::{
     get_compressed_balance_by_owner, GetCompressedBalanceByOwnerRequest,
 };
 use super::method::get_compressed_mint_token_holders::{
     get_compressed_mint_token_holders, GetCompressedMintTokenHoldersRequest, OwnerBalancesResponse,
 };
+use super::method::get_compressed_token_account_by_owner_and_mint::{
+    get_compressed_token_account_by_owner_and_mint, GetCompressedTokenAccountByOwnerAndMintRequest,
+    TokenAccountListWithTotalResponse,
+};
 use super::method::get_compressed_token_balances_by_owner::{
     get_compressed_token_balances_by_owner, get_compressed_token_balances_by_owner_v2,
     GetCompressedTokenBalancesByOwnerRequest, TokenBalancesResponse, TokenBalancesResponseV2,
@@ -29,12 +52,21 @@ use super::method::get_compression_signatures_for_owner::{
 use super::method::get_compression_signatures_for_token_owner::{
     get_compression_signatures_for_token_owner, GetCompressionSignaturesForTokenOwnerRequest,
 };
+use super::method::get_compression_stats::{
+    get_compression_stats, GetCompressionStatsRequest, GetCompressionStatsResponse,
+};
+use super::method::get_historical_compressed_account_proof::{
+    get_historical_compressed_account_proof, GetHistoricalCompressedAccountProofRequest,
+};
 use super::method::get_latest_compression_signatures::get_latest_compression_signatures;
 use super::method::get_latest_non_voting_signatures::get_latest_non_voting_signatures;
 use super::method::get_multiple_new_address_proofs::{
     get_multiple_new_address_proofs, get_multiple_new_address_proofs_v2, AddressList,
     AddressListWithTrees, GetMultipleNewAddressProofsResponse,
 };
+use super::method::get_top_compressed_sol_owners::{
+    get_top_compressed_sol_owners, GetTopCompressedSolOwnersRequest,
+};
 use super::method::get_transaction_with_compression_info::{
     get_transaction_with_compression_info, GetTransactionRequest, GetTransactionResponse,
 };
@@ -43,7 +75,7 @@ use super::method::get_validity_proof::{
 };
 use super::method::utils::{AccountBalanceResponse, GetPaginatedSignaturesResponse, HashRequest};
 use super::method::utils::{
-    GetLatestSignaturesRequest, GetNonPaginatedSignaturesResponseWithError,
+    GetLatestSignaturesRequest, GetNonPaginatedSignaturesResponseWithError, PAGE_LIMIT,
 };
 use super::{
     error::PhotonApiError,
@@ -62,7 +94,8 @@ use super::{
         },
         get_compressed_token_accounts_by_delegate::get_compressed_account_token_accounts_by_delegate,
         get_compressed_token_accounts_by_owner::get_compressed_token_accounts_by_owner,
-        get_indexer_health::get_indexer_health,
+        get_indexer_health::{get_indexer_health, HEALTH_CHECK_SLOT_DISTANCE},
+        get_indexer_metadata::get_indexer_metadata,
         get_indexer_slot::get_indexer_slot,
         get_multiple_compressed_account_proofs::{
             get_multiple_compressed_account_proofs, GetMultipleCompressedAccountProofsResponse,
@@ -73,16 +106,54 @@ use super::{
             GetMultipleCompressedAccountsResponse,
         },
         utils::{
-            CompressedAccountRequest, GetCompressedTokenAccountsByDelegate,
+            CompressedAccountRequest, Context, GetCompressedTokenAccountsByDelegate,
             GetCompressedTokenAccountsByOwner, TokenAccountListResponse,
         },
     },
 };
 
+/// Settings that `photon serve` applies to the API, whichever transport a request comes in on.
+#[derive(Clone)]
+pub struct ApiConfig {
+    pub graphql_limits: Option<GraphQLLimits>,
+    pub rate_limits: Option<RateLimitConfig>,
+    pub cors: CorsConfig,
+    pub method_filter: MethodFilter,
+    pub request_limits: RequestLimits,
+    pub max_slots_behind: u64,
+    pub max_accounts_by_owner_page_size: u64,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            graphql_limits: None,
+            rate_limits: None,
+            cors: CorsConfig::default(),
+            method_filter: MethodFilter::default(),
+            request_limits: RequestLimits::default(),
+            max_slots_behind: HEALTH_CHECK_SLOT_DISTANCE as u64,
+            max_accounts_by_owner_page_size: PAGE_LIMIT,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct PhotonApi {
-    db_conn: Arc<DatabaseConnection>,
+    read_replicas: Arc<ReadReplicas>,
     rpc_client: Arc<RpcClient>,
     prover_url: String,
+    warmed_up: Arc<AtomicBool>,
+    graphql_limits: Option<GraphQLLimits>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    cors: CorsConfig,
+    method_filter: Arc<MethodFilter>,
+    request_limits: RequestLimits,
+    max_slots_behind: u64,
+    max_accounts_by_owner_page_size: u64,
+    sql_batch_config: SqlBatchConfig,
+    api_cache: ApiCache,
+    in_memory_trees: InMemoryTrees,
 }
 
 impl PhotonApi {
@@ -91,11 +162,167 @@ impl PhotonApi {
         rpc_client: Arc<RpcClient>,
         prover_url: String,
     ) -> Self {
+        let sql_batch_config = SqlBatchConfig::new(db_conn.get_database_backend());
         Self {
-            db_conn,
+            read_replicas: Arc::new(ReadReplicas::new(db_conn, vec![])),
             rpc_client,
             prover_url,
+            warmed_up: Arc::new(AtomicBool::new(false)),
+            graphql_limits: None,
+            rate_limiter: None,
+            cors: CorsConfig::default(),
+            method_filter: Arc::new(MethodFilter::default()),
+            request_limits: RequestLimits::default(),
+            max_slots_behind: HEALTH_CHECK_SLOT_DISTANCE as u64,
+            max_accounts_by_owner_page_size: PAGE_LIMIT,
+            sql_batch_config,
+            api_cache: ApiCache::default(),
+            in_memory_trees: InMemoryTrees::default(),
+        }
+    }
+
+    /// Applies all of `config`. The HTTP and gRPC servers of `photon serve` run clones of the same
+    /// configured API, so that they enforce the same settings and share the rate limit buckets.
+    pub fn with_config(self, config: ApiConfig) -> Self {
+        let mut api = self
+            .with_cors(config.cors)
+            .with_method_filter(config.method_filter)
+            .with_request_limits(config.request_limits)
+            .with_max_slots_behind(config.max_slots_behind)
+            .with_max_accounts_by_owner_page_size(config.max_accounts_by_owner_page_size);
+        if let Some(limits) = config.graphql_limits {
+            api = api.with_graphql(limits);
+        }
+        if let Some(rate_limits) = config.rate_limits {
+            api = api.with_rate_limits(rate_limits);
+        }
+        api
+    }
+
+    /// Routes the queries of the API to `read_replicas` instead of the connection passed to
+    /// [`PhotonApi::new`].
+    pub fn with_read_replicas(mut self, read_replicas: Arc<ReadReplicas>) -> Self {
+        self.read_replicas = read_replicas;
+        self
+    }
+
+    /// Serves the GraphQL endpoint alongside the JSON-RPC API, validating queries against `limits`.
+    pub fn with_graphql(mut self, limits: GraphQLLimits) -> Self {
+        self.graphql_limits = Some(limits);
+        self
+    }
+
+    pub fn graphql_limits(&self) -> Option<GraphQLLimits> {
+        self.graphql_limits
+    }
+
+    /// Throttles the HTTP and gRPC requests of each client, see [`RateLimitConfig`].
+    pub fn with_rate_limits(mut self, rate_limits: RateLimitConfig) -> Self {
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(rate_limits)));
+        self
+    }
+
+    pub fn rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+        self.rate_limiter.clone()
+    }
+
+    /// Replaces the default CORS policy, which allows GET and POST requests from any origin.
+    pub fn with_cors(mut self, cors: CorsConfig) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    pub fn cors(&self) -> &CorsConfig {
+        &self.cors
+    }
+
+    /// Restricts the methods that are served over any transport, see [`MethodFilter`].
+    pub fn with_method_filter(mut self, method_filter: MethodFilter) -> Self {
+        self.method_filter = Arc::new(method_filter);
+        self
+    }
+
+    pub fn method_filter(&self) -> &MethodFilter {
+        &self.method_filter
+    }
+
+    /// Fails if the operator disabled `method`. The JSON-RPC, gRPC and GraphQL servers call this
+    /// with the JSON-RPC name of a method before running its handler.
+    pub fn ensure_method_enabled(&self, method: &str) -> Result<(), PhotonApiError> {
+        if !self.method_filter.is_enabled(method) {
+            return Err(PhotonApiError::MethodDisabled(method.to_string()));
         }
+        Ok(())
+    }
+
+    pub fn with_request_limits(mut self, request_limits: RequestLimits) -> Self {
+        self.request_limits = request_limits;
+        self
+    }
+
+    pub fn request_limits(&self) -> RequestLimits {
+        self.request_limits
+    }
+
+    /// Sets how many slots the indexed slot may lag behind the chain before `/readyz` fails.
+    pub fn with_max_slots_behind(mut self, max_slots_behind: u64) -> Self {
+        self.max_slots_behind = max_slots_behind;
+        self
+    }
+
+    /// Caps the pages of `getCompressedAccountsByOwner`, whatever limit clients request, so that
+    /// owners with many accounts cannot tie up the database. At most `PAGE_LIMIT`.
+    pub fn with_max_accounts_by_owner_page_size(mut self, max_page_size: u64) -> Self {
+        self.max_accounts_by_owner_page_size = max_page_size.min(PAGE_LIMIT);
+        self
+    }
+
+    /// Replaces the limits that hold for every version of the database, see
+    /// [`SqlBatchConfig::detect`].
+    pub fn with_sql_batch_config(mut self, sql_batch_config: SqlBatchConfig) -> Self {
+        self.sql_batch_config = sql_batch_config;
+        self
+    }
+
+    /// Serves the cached API methods from `api_cache`. It has to be shared with the indexer that
+    /// writes the database, which invalidates the entries of the accounts that it writes.
+    pub fn with_api_cache(mut self, api_cache: ApiCache) -> Self {
+        self.api_cache = api_cache;
+        self
+    }
+
+    /// Reads the nodes of proofs from `in_memory_trees`. They have to be shared with the indexer
+    /// that writes the database, which updates the nodes that it writes.
+    pub fn with_in_memory_trees(mut self, in_memory_trees: InMemoryTrees) -> Self {
+        self.in_memory_trees = in_memory_trees;
+        self
+    }
+
+    /// Connection to run the next query of the API on.
+    pub fn db_conn(&self) -> Arc<DatabaseConnection> {
+        self.read_replicas.connection()
+    }
+
+    pub fn read_replicas(&self) -> Arc<ReadReplicas> {
+        self.read_replicas.clone()
+    }
+
+    /// Warms up the database connections in the background. The API only reports ready once the
+    /// warm-up has finished.
+    pub fn start_warm_up(&self) -> JoinHandle<()> {
+        let connections = self.read_replicas.connections();
+        let warmed_up = self.warmed_up.clone();
+        let sql_batch_config = self.sql_batch_config;
+        let in_memory_trees = self.in_memory_trees.clone();
+        tokio::spawn(async move {
+            join_all(
+                connections
+                    .iter()
+                    .map(|db_conn| warm_up_database(db_conn, sql_batch_config, &in_memory_trees)),
+            )
+            .await;
+            warmed_up.store(true, Ordering::SeqCst);
+        })
     }
 }
 
@@ -111,9 +338,13 @@ impl PhotonApi {
     }
 
     pub async fn readiness(&self) -> Result<(), PhotonApiError> {
-        self.db_conn
+        if !self.warmed_up.load(Ordering::SeqCst) {
+            return Err(PhotonApiError::WarmingUp);
+        }
+        let db_conn = self.db_conn();
+        db_conn
             .execute(Statement::from_string(
-                self.db_conn.as_ref().get_database_backend(),
+                db_conn.get_database_backend(),
                 "SELECT 1".to_string(),
             ))
             .await
@@ -121,152 +352,272 @@ impl PhotonApi {
             .map_err(Into::into)
     }
 
+    /// Checks that the node can serve current data: it is warmed up, the database is reachable and
+    /// the indexed slot is at most `max_slots_behind` slots behind the chain. Returns the indexed
+    /// slot. The lag is not checked while the RPC node is unreachable, so that an RPC outage does
+    /// not take all replicas out of rotation at once.
+    pub async fn check_ready(&self) -> Result<u64, PhotonApiError> {
+        self.readiness().await?;
+        let indexed_slot = Context::extract(self.db_conn().as_ref()).await?.slot;
+        match self.rpc_client.get_slot().await {
+            Ok(slot) if slot.saturating_sub(indexed_slot) > self.max_slots_behind => {
+                Err(PhotonApiError::StaleSlot(slot - indexed_slot))
+            }
+            Ok(_) => Ok(indexed_slot),
+            Err(e) => {
+                warn!("Skipping the lag check of the readiness probe: {}", e);
+                Ok(indexed_slot)
+            }
+        }
+    }
+
+    // Results that depend on indexed data are incomplete while the indexer backfills, and clients
+    // would cache e.g. a missing account as nonexistent. Lagging by up to the health check
+    // distance is normal operation, so short backfills after a restart are not reported.
+    fn ensure_synced(&self) -> Result<(), PhotonApiError> {
+        match backfill_progress() {
+            Some(BackfillProgress {
+                current_slot,
+                target_slot,
+            }) if target_slot.saturating_sub(current_slot) > HEALTH_CHECK_SLOT_DISTANCE as u64 => {
+                Err(PhotonApiError::IndexerSyncing {
+                    current_slot,
+                    target_slot,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub async fn get_compressed_account(
         &self,
         request: CompressedAccountRequest,
     ) -> Result<AccountResponse, PhotonApiError> {
-        get_compressed_account(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_account(&self.db_conn(), request, &self.api_cache).await
     }
 
     pub async fn get_compressed_account_proof(
         &self,
         request: HashRequest,
     ) -> Result<GetCompressedAccountProofResponse, PhotonApiError> {
-        get_compressed_account_proof(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_account_proof(
+            &self.db_conn(),
+            request,
+            self.sql_batch_config,
+            &self.in_memory_trees,
+        )
+        .await
+    }
+
+    pub async fn get_historical_compressed_account_proof(
+        &self,
+        request: GetHistoricalCompressedAccountProofRequest,
+    ) -> Result<GetCompressedAccountProofResponse, PhotonApiError> {
+        self.ensure_synced()?;
+        get_historical_compressed_account_proof(&self.db_conn(), request, self.sql_batch_config)
+            .await
     }
 
     pub async fn get_multiple_compressed_account_proofs(
         &self,
         request: HashList,
     ) -> Result<GetMultipleCompressedAccountProofsResponse, PhotonApiError> {
-        get_multiple_compressed_account_proofs(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_multiple_compressed_account_proofs(
+            self.db_conn().as_ref(),
+            request,
+            self.sql_batch_config,
+            &self.in_memory_trees,
+        )
+        .await
     }
 
     pub async fn get_multiple_new_address_proofs(
         &self,
         request: AddressList,
     ) -> Result<GetMultipleNewAddressProofsResponse, PhotonApiError> {
-        get_multiple_new_address_proofs(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_multiple_new_address_proofs(self.db_conn().as_ref(), request, self.sql_batch_config)
+            .await
     }
 
     pub async fn get_multiple_new_address_proofs_v2(
         &self,
         request: AddressListWithTrees,
     ) -> Result<GetMultipleNewAddressProofsResponse, PhotonApiError> {
-        get_multiple_new_address_proofs_v2(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_multiple_new_address_proofs_v2(self.db_conn().as_ref(), request, self.sql_batch_config)
+            .await
     }
 
     pub async fn get_compressed_token_accounts_by_owner(
         &self,
         request: GetCompressedTokenAccountsByOwner,
     ) -> Result<TokenAccountListResponse, PhotonApiError> {
-        get_compressed_token_accounts_by_owner(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_token_accounts_by_owner(&self.db_conn(), request).await
+    }
+
+    pub async fn get_compressed_token_account_by_owner_and_mint(
+        &self,
+        request: GetCompressedTokenAccountByOwnerAndMintRequest,
+    ) -> Result<TokenAccountListWithTotalResponse, PhotonApiError> {
+        self.ensure_synced()?;
+        get_compressed_token_account_by_owner_and_mint(&self.db_conn(), request).await
     }
 
     pub async fn get_compressed_token_accounts_by_delegate(
         &self,
         request: GetCompressedTokenAccountsByDelegate,
     ) -> Result<TokenAccountListResponse, PhotonApiError> {
-        get_compressed_account_token_accounts_by_delegate(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_account_token_accounts_by_delegate(&self.db_conn(), request).await
     }
 
     pub async fn get_compressed_balance_by_owner(
         &self,
         request: GetCompressedBalanceByOwnerRequest,
     ) -> Result<AccountBalanceResponse, PhotonApiError> {
-        get_compressed_balance_by_owner(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_balance_by_owner(&self.db_conn(), request).await
     }
 
     pub async fn get_compressed_token_balances_by_owner(
         &self,
         request: GetCompressedTokenBalancesByOwnerRequest,
     ) -> Result<TokenBalancesResponse, PhotonApiError> {
-        get_compressed_token_balances_by_owner(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_token_balances_by_owner(&self.db_conn(), request, &self.api_cache).await
     }
 
     pub async fn get_compressed_token_balances_by_owner_v2(
         &self,
         request: GetCompressedTokenBalancesByOwnerRequest,
     ) -> Result<TokenBalancesResponseV2, PhotonApiError> {
-        get_compressed_token_balances_by_owner_v2(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_token_balances_by_owner_v2(&self.db_conn(), request, &self.api_cache).await
     }
 
     pub async fn get_compressed_token_account_balance(
         &self,
         request: CompressedAccountRequest,
     ) -> Result<GetCompressedTokenAccountBalanceResponse, PhotonApiError> {
-        get_compressed_token_account_balance(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_token_account_balance(&self.db_conn(), request, &self.api_cache).await
     }
 
     pub async fn get_compressed_account_balance(
         &self,
         request: CompressedAccountRequest,
     ) -> Result<AccountBalanceResponse, PhotonApiError> {
-        get_compressed_account_balance(&self.db_conn, request).await
+        self.ensure_synced()?;
+        get_compressed_account_balance(&self.db_conn(), request).await
     }
 
     pub async fn get_indexer_health(&self) -> Result<String, PhotonApiError> {
-        get_indexer_health(self.db_conn.as_ref(), &self.rpc_client).await
+        get_indexer_health(self.db_conn().as_ref(), &self.rpc_client).await
     }
 
     pub async fn get_indexer_slot(&self) -> Result<UnsignedInteger, PhotonApiError> {
-        get_indexer_slot(self.db_conn.as_ref()).await
+        get_indexer_slot(self.db_conn().as_ref()).await
+    }
+
+    pub async fn get_indexer_metadata(&self) -> Result<BTreeMap<String, String>, PhotonApiError> {
+        get_indexer_metadata(self.db_conn().as_ref()).await
+    }
+
+    pub async fn get_compressed_address_info(
+        &self,
+        request: GetCompressedAddressInfoRequest,
+    ) -> Result<GetCompressedAddressInfoResponse, PhotonApiError> {
+        self.ensure_synced()?;
+        get_compressed_address_info(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_compressed_accounts_by_owner(
         &self,
         request: GetCompressedAccountsByOwnerRequest,
     ) -> Result<GetCompressedAccountsByOwnerResponse, PhotonApiError> {
-        get_compressed_accounts_by_owner(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_compressed_accounts_by_owner(
+            self.db_conn().as_ref(),
+            request,
+            self.max_accounts_by_owner_page_size,
+        )
+        .await
     }
 
     pub async fn get_compressed_mint_token_holders(
         &self,
         request: GetCompressedMintTokenHoldersRequest,
     ) -> Result<OwnerBalancesResponse, PhotonApiError> {
-        get_compressed_mint_token_holders(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_compressed_mint_token_holders(self.db_conn().as_ref(), request).await
+    }
+
+    pub async fn get_top_compressed_sol_owners(
+        &self,
+        request: GetTopCompressedSolOwnersRequest,
+    ) -> Result<OwnerBalancesResponse, PhotonApiError> {
+        self.ensure_synced()?;
+        get_top_compressed_sol_owners(self.db_conn().as_ref(), request).await
+    }
+
+    pub async fn get_compression_stats(
+        &self,
+        request: GetCompressionStatsRequest,
+    ) -> Result<GetCompressionStatsResponse, PhotonApiError> {
+        self.ensure_synced()?;
+        get_compression_stats(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_multiple_compressed_accounts(
         &self,
         request: GetMultipleCompressedAccountsRequest,
     ) -> Result<GetMultipleCompressedAccountsResponse, PhotonApiError> {
-        get_multiple_compressed_accounts(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_multiple_compressed_accounts(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_compression_signatures_for_account(
         &self,
         request: HashRequest,
     ) -> Result<GetNonPaginatedSignaturesResponse, PhotonApiError> {
-        get_compression_signatures_for_account(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_compression_signatures_for_account(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_compression_signatures_for_address(
         &self,
         request: GetCompressionSignaturesForAddressRequest,
     ) -> Result<GetPaginatedSignaturesResponse, PhotonApiError> {
-        get_compression_signatures_for_address(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_compression_signatures_for_address(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_compression_signatures_for_owner(
         &self,
         request: GetCompressionSignaturesForOwnerRequest,
     ) -> Result<GetPaginatedSignaturesResponse, PhotonApiError> {
-        get_compression_signatures_for_owner(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_compression_signatures_for_owner(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_compression_signatures_for_token_owner(
         &self,
         request: GetCompressionSignaturesForTokenOwnerRequest,
     ) -> Result<GetPaginatedSignaturesResponse, PhotonApiError> {
-        get_compression_signatures_for_token_owner(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_compression_signatures_for_token_owner(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_transaction_with_compression_info(
         &self,
         request: GetTransactionRequest,
     ) -> Result<GetTransactionResponse, PhotonApiError> {
-        get_transaction_with_compression_info(self.db_conn.as_ref(), &self.rpc_client, request)
+        get_transaction_with_compression_info(self.db_conn().as_ref(), &self.rpc_client, request)
             .await
     }
 
@@ -274,21 +625,31 @@ impl PhotonApi {
         &self,
         request: GetValidityProofRequest,
     ) -> Result<GetValidityProofResponse, PhotonApiError> {
-        get_validity_proof(self.db_conn.as_ref(), &self.prover_url, request).await
+        self.ensure_synced()?;
+        get_validity_proof(
+            self.db_conn().as_ref(),
+            &self.prover_url,
+            request,
+            self.sql_batch_config,
+            &self.in_memory_trees,
+        )
+        .await
     }
 
     pub async fn get_latest_compression_signatures(
         &self,
         request: GetLatestSignaturesRequest,
     ) -> Result<GetPaginatedSignaturesResponse, PhotonApiError> {
-        get_latest_compression_signatures(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_latest_compression_signatures(self.db_conn().as_ref(), request).await
     }
 
     pub async fn get_latest_non_voting_signatures(
         &self,
         request: GetLatestSignaturesRequest,
     ) -> Result<GetNonPaginatedSignaturesResponseWithError, PhotonApiError> {
-        get_latest_non_voting_signatures(self.db_conn.as_ref(), request).await
+        self.ensure_synced()?;
+        get_latest_non_voting_signatures(self.db_conn().as_ref(), request).await
     }
 
     pub fn method_api_specs() -> Vec<OpenApiSpec> {
@@ -333,6 +694,16 @@ impl PhotonApi {
                 request: Some(GetCompressedMintTokenHoldersRequest::schema().1),
                 response: OwnerBalancesResponse::schema().1,
             },
+            OpenApiSpec {
+                name: "getTopCompressedSolOwners".to_string(),
+                request: Some(GetTopCompressedSolOwnersRequest::schema().1),
+                response: OwnerBalancesResponse::schema().1,
+            },
+            OpenApiSpec {
+                name: "getCompressionStats".to_string(),
+                request: Some(GetCompressionStatsRequest::schema().1),
+                response: GetCompressionStatsResponse::schema().1,
+            },
             OpenApiSpec {
                 name: "getMultipleCompressedAccounts".to_string(),
                 request: Some(GetMultipleCompressedAccountsRequest::adjusted_schema()),
@@ -343,6 +714,11 @@ impl PhotonApi {
                 request: Some(GetCompressedTokenAccountsByOwner::schema().1),
                 response: TokenAccountListResponse::schema().1,
             },
+            OpenApiSpec {
+                name: "getCompressedTokenAccountByOwnerAndMint".to_string(),
+                request: Some(GetCompressedTokenAccountByOwnerAndMintRequest::schema().1),
+                response: TokenAccountListWithTotalResponse::schema().1,
+            },
             OpenApiSpec {
                 name: "getCompressedTokenAccountsByDelegate".to_string(),
                 request: Some(GetCompressedTokenAccountsByDelegate::schema().1),
@@ -358,11 +734,21 @@ impl PhotonApi {
                 request: Some(HashRequest::schema().1),
                 response: GetCompressedAccountProofResponse::schema().1,
             },
+            OpenApiSpec {
+                name: "getHistoricalCompressedAccountProof".to_string(),
+                request: Some(GetHistoricalCompressedAccountProofRequest::schema().1),
+                response: GetCompressedAccountProofResponse::schema().1,
+            },
             OpenApiSpec {
                 name: "getMultipleCompressedAccountProofs".to_string(),
                 request: Some(HashList::schema().1),
                 response: GetMultipleCompressedAccountProofsResponse::schema().1,
             },
+            OpenApiSpec {
+                name: "getCompressedAddressInfo".to_string(),
+                request: Some(GetCompressedAddressInfoRequest::schema().1),
+                response: GetCompressedAddressInfoResponse::schema().1,
+            },
             OpenApiSpec {
                 name: "getMultipleNewAddressProofs".to_string(),
                 request: Some(AddressList::schema().1),
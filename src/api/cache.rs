@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::hash::Hash as StdHash;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use cadence_macros::statsd_count;
+use lru::LruCache;
+use sea_orm::{
+    ColumnTrait, DatabaseTransaction, EntityTrait, FromQueryResult, QueryFilter, QuerySelect,
+};
+
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::hash::Hash;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::common::typedefs::u64_string::U64String;
+use crate::dao::generated::token_accounts;
+use crate::ingester::error::IngesterError;
+use crate::ingester::parser::state_update::StateUpdate;
+use crate::ingester::persist::batch_size::SqlBatchConfig;
+use crate::ingester::persist::parse_token_data;
+use crate::metric;
+
+use super::error::PhotonApiError;
+use super::method::get_compressed_token_balances_by_owner::TokenBalanceList;
+use super::method::utils::AccountIdentifier;
+
+/// Key of a page of the token balances of an owner.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TokenBalancesKey {
+    pub owner: SerializablePubkey,
+    pub mint: Option<SerializablePubkey>,
+    pub cursor: Option<Vec<u8>>,
+    pub limit: Option<u64>,
+}
+
+pub struct ApiCacheEntries {
+    // Bumped by every invalidation, so that reads which started before an invalidation do not
+    // cache what they read.
+    generation: u64,
+    accounts: LruCache<AccountIdentifier, Option<Account>>,
+    token_account_balances: LruCache<Hash, U64String>,
+    token_balances: LruCache<TokenBalancesKey, TokenBalanceList>,
+}
+
+impl ApiCacheEntries {
+    fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            generation: 0,
+            accounts: LruCache::new(capacity),
+            token_account_balances: LruCache::new(capacity),
+            token_balances: LruCache::new(capacity),
+        }
+    }
+
+    pub fn accounts(&mut self) -> &mut LruCache<AccountIdentifier, Option<Account>> {
+        &mut self.accounts
+    }
+
+    pub fn token_account_balances(&mut self) -> &mut LruCache<Hash, U64String> {
+        &mut self.token_account_balances
+    }
+
+    pub fn token_balances(&mut self) -> &mut LruCache<TokenBalancesKey, TokenBalanceList> {
+        &mut self.token_balances
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.accounts.clear();
+        self.token_account_balances.clear();
+        self.token_balances.clear();
+    }
+
+    fn invalidate(&mut self, invalidations: &PendingCacheInvalidations) {
+        let PendingCacheInvalidations {
+            hashes,
+            addresses,
+            token_owners,
+        } = invalidations;
+        self.generation += 1;
+        let stale_accounts = self
+            .accounts
+            .iter()
+            .filter(|(id, account)| {
+                let stale_key = match id {
+                    AccountIdentifier::Hash(hash) => hashes.contains(hash),
+                    AccountIdentifier::Address(address) => addresses.contains(address),
+                };
+                // Spent accounts are only known by hash, also when they were looked up by address.
+                stale_key
+                    || account
+                        .as_ref()
+                        .is_some_and(|account| hashes.contains(&account.hash))
+            })
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in stale_accounts {
+            self.accounts.pop(&id);
+        }
+        for hash in hashes {
+            self.token_account_balances.pop(hash);
+        }
+        let stale_token_balances = self
+            .token_balances
+            .iter()
+            .filter(|(key, _)| token_owners.contains(&key.owner))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in stale_token_balances {
+            self.token_balances.pop(&key);
+        }
+    }
+}
+
+/// Results of the cached API methods, shared by the API and the indexer that invalidates them.
+/// Only enabled in processes that also run the indexer, since entries are invalidated when the
+/// indexer of this process writes the accounts. Reads from replicas could be stale.
+#[derive(Clone, Default)]
+pub struct ApiCache(Option<Arc<Mutex<ApiCacheEntries>>>);
+
+impl ApiCache {
+    /// Caches up to `capacity` results of each cached API method. 0 disables the cache.
+    pub fn new(capacity: usize) -> Self {
+        Self(
+            NonZeroUsize::new(capacity)
+                .map(|capacity| Arc::new(Mutex::new(ApiCacheEntries::new(capacity)))),
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Drops all cached results, e.g. after blocks of an orphaned fork were rolled back.
+    pub fn clear(&self) {
+        if let Some(entries) = &self.0 {
+            entries.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns the result for `key` from the cache entries that `entries` selects, or runs `fetch`
+    /// and caches its result.
+    pub async fn read_through<K, V>(
+        &self,
+        name: &'static str,
+        entries: fn(&mut ApiCacheEntries) -> &mut LruCache<K, V>,
+        key: K,
+        fetch: impl Future<Output = Result<V, PhotonApiError>>,
+    ) -> Result<V, PhotonApiError>
+    where
+        K: StdHash + Eq,
+        V: Clone,
+    {
+        let Some(cache) = &self.0 else {
+            return fetch.await;
+        };
+        let generation = {
+            let mut cache = cache.lock().unwrap();
+            if let Some(value) = entries(&mut cache).get(&key) {
+                metric! {
+                    statsd_count!("api_cache_hit", 1, "cache" => name);
+                }
+                return Ok(value.clone());
+            }
+            cache.generation
+        };
+        metric! {
+            statsd_count!("api_cache_miss", 1, "cache" => name);
+        }
+        let value = fetch.await?;
+        let mut cache = cache.lock().unwrap();
+        if cache.generation == generation {
+            entries(&mut cache).put(key, value.clone());
+        }
+        Ok(value)
+    }
+}
+
+#[derive(FromQueryResult)]
+struct TokenOwnerModel {
+    owner: Vec<u8>,
+}
+
+/// Cache entries that a state update makes stale. They are invalidated once the state update is
+/// committed, since invalidating earlier lets concurrent reads cache the state before the update.
+pub struct PendingCacheInvalidations {
+    hashes: HashSet<Hash>,
+    addresses: HashSet<SerializablePubkey>,
+    token_owners: HashSet<SerializablePubkey>,
+}
+
+impl PendingCacheInvalidations {
+    /// Has to run before `state_update` is persisted, since it looks up the owners of the token
+    /// accounts that it spends. Returns `None` if the cache is disabled.
+    pub async fn collect(
+        txn: &DatabaseTransaction,
+        batch_config: SqlBatchConfig,
+        api_cache: &ApiCache,
+        state_update: &StateUpdate,
+    ) -> Result<Option<Self>, IngesterError> {
+        if !api_cache.is_enabled() {
+            return Ok(None);
+        }
+        let mut invalidations = Self {
+            hashes: state_update.in_accounts.clone(),
+            addresses: HashSet::new(),
+            token_owners: HashSet::new(),
+        };
+        for account in &state_update.out_accounts {
+            invalidations.hashes.insert(account.hash.clone());
+            invalidations.addresses.extend(account.address);
+            if let Some(token_data) = parse_token_data(account)? {
+                invalidations.token_owners.insert(token_data.owner);
+            }
+        }
+        let in_accounts = state_update
+            .in_accounts
+            .iter()
+            .map(|hash| hash.to_vec())
+            .collect::<Vec<_>>();
+        for chunk in in_accounts.chunks(batch_config.batch_size(1)) {
+            let owners = token_accounts::Entity::find()
+                .select_only()
+                .column(token_accounts::Column::Owner)
+                .filter(token_accounts::Column::Hash.is_in(chunk.to_vec()))
+                .into_model::<TokenOwnerModel>()
+                .all(txn)
+                .await?;
+            for model in owners {
+                let owner = SerializablePubkey::try_from(model.owner).map_err(|e| {
+                    IngesterError::DatabaseError(format!("Invalid token owner: {}", e))
+                })?;
+                invalidations.token_owners.insert(owner);
+            }
+        }
+        Ok(Some(invalidations))
+    }
+
+    pub fn apply(self, api_cache: &ApiCache) {
+        if let Some(entries) = &api_cache.0 {
+            entries.lock().unwrap().invalidate(&self);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE, RETRY_AFTER};
+use hyper::Method;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use super::rate_limit::API_KEY_HEADER;
+use super::request_tracing::REQUEST_ID_HEADER;
+
+/// Which web pages may call the API from a browser, and with which requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorsConfig {
+    /// Origins such as `https://app.example.com`, or `*` for any origin.
+    pub allowed_origins: Vec<HeaderValue>,
+    pub allowed_methods: Vec<Method>,
+    /// Request headers to allow on top of the ones that the API reads itself.
+    pub allowed_headers: Vec<HeaderName>,
+    /// How long browsers may cache the answer to a preflight request.
+    pub max_age: Duration,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![HeaderValue::from_static("*")],
+            allowed_methods: vec![Method::GET, Method::POST],
+            allowed_headers: vec![],
+            max_age: Duration::from_secs(600),
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Builds the layer that answers preflight requests and adds the CORS headers to responses.
+    pub fn layer(&self) -> CorsLayer {
+        let allow_origin = match self.allowed_origins.iter().any(|origin| origin == "*") {
+            true => AllowOrigin::any(),
+            false => AllowOrigin::list(self.allowed_origins.iter().cloned()),
+        };
+        let mut allowed_headers = vec![
+            CONTENT_TYPE,
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            HeaderName::from_static(API_KEY_HEADER),
+        ];
+        allowed_headers.extend(self.allowed_headers.iter().cloned());
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(self.allowed_methods.clone())
+            .allow_headers(allowed_headers)
+            // Browsers only let scripts read a few response headers unless they are exposed.
+            .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER), RETRY_AFTER])
+            .max_age(self.max_age)
+    }
+}
@@ -2,8 +2,10 @@ use crate::common::typedefs::hash::ParseHashError;
 use crate::metric;
 use cadence_macros::statsd_count;
 use jsonrpsee::core::Error as RpcError;
-use jsonrpsee::types::error::CallError;
+use jsonrpsee::types::error::{CallError, ErrorObject, CALL_EXECUTION_FAILED_CODE};
 use log::error;
+use sea_orm::{DbErr, RuntimeErr};
+use serde::Serialize;
 use solana_sdk::pubkey::ParsePubkeyError;
 use thiserror::Error;
 
@@ -14,13 +16,116 @@ pub enum PhotonApiError {
     #[error("Invalid Public Key: field '{field}'")]
     InvalidPubkey { field: String },
     #[error("Database Error: {0}")]
-    DatabaseError(#[from] sea_orm::DbErr),
+    DatabaseError(sea_orm::DbErr),
+    #[error("Database is busy: no connection became available in time")]
+    DatabaseBusy,
+    #[error("Query was cancelled because it exceeded the statement timeout")]
+    QueryTimeout,
     #[error("Record Not Found: {0}")]
     RecordNotFound(String),
     #[error("Unexpected Error: {0}")]
     UnexpectedError(String),
     #[error("Node is behind {0} slots")]
     StaleSlot(u64),
+    #[error("Node is missing {0} slots")]
+    MissingSlots(u64),
+    #[error("Roots of {0} trees do not match the chain")]
+    RootMismatch(u64),
+    #[error("Node is warming up")]
+    WarmingUp,
+    #[error("Indexer is syncing: indexed slot {current_slot} of {target_slot}")]
+    IndexerSyncing { current_slot: u64, target_slot: u64 },
+    #[error("Rate limit exceeded")]
+    RateLimited,
+    #[error("Method is disabled on this node: {0}")]
+    MethodDisabled(String),
+    #[error("Slot {slot} is not indexed: indexing started at slot {start_slot}")]
+    SlotNotIndexed { slot: u64, start_slot: u64 },
+}
+
+/// Version of the `data` object that is attached to every API error. It has to be bumped whenever a
+/// field is removed or changes its meaning, so that clients can detect incompatible payloads.
+pub const API_ERROR_DATA_VERSION: u8 = 1;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiErrorData {
+    pub version: u8,
+    /// Machine-readable error kind, e.g. `stale_slot`.
+    pub kind: &'static str,
+    /// Whether the same request can succeed when it is retried later.
+    pub retryable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<SyncProgress>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncProgress {
+    pub current_slot: u64,
+    pub target_slot: u64,
+}
+
+impl PhotonApiError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PhotonApiError::ValidationError(_) => "validation_error",
+            PhotonApiError::InvalidPubkey { .. } => "invalid_pubkey",
+            PhotonApiError::DatabaseError(_) => "database_error",
+            PhotonApiError::DatabaseBusy => "database_busy",
+            PhotonApiError::QueryTimeout => "query_timeout",
+            PhotonApiError::RecordNotFound(_) => "record_not_found",
+            PhotonApiError::UnexpectedError(_) => "unexpected_error",
+            PhotonApiError::StaleSlot(_) => "stale_slot",
+            PhotonApiError::MissingSlots(_) => "missing_slots",
+            PhotonApiError::RootMismatch(_) => "root_mismatch",
+            PhotonApiError::WarmingUp => "warming_up",
+            PhotonApiError::IndexerSyncing { .. } => "indexer_syncing",
+            PhotonApiError::RateLimited => "rate_limited",
+            PhotonApiError::MethodDisabled(_) => "method_disabled",
+            PhotonApiError::SlotNotIndexed { .. } => "slot_not_indexed",
+        }
+    }
+
+    /// Errors are retryable if they are caused by the state of the node rather than by the
+    /// request, e.g. because the node is still catching up.
+    pub fn retryable(&self) -> bool {
+        match self {
+            PhotonApiError::DatabaseError(_)
+            | PhotonApiError::DatabaseBusy
+            | PhotonApiError::QueryTimeout
+            | PhotonApiError::StaleSlot(_)
+            | PhotonApiError::MissingSlots(_)
+            | PhotonApiError::WarmingUp
+            | PhotonApiError::IndexerSyncing { .. }
+            | PhotonApiError::RateLimited => true,
+            PhotonApiError::ValidationError(_)
+            | PhotonApiError::InvalidPubkey { .. }
+            | PhotonApiError::RecordNotFound(_)
+            | PhotonApiError::RootMismatch(_)
+            | PhotonApiError::MethodDisabled(_)
+            | PhotonApiError::SlotNotIndexed { .. }
+            | PhotonApiError::UnexpectedError(_) => false,
+        }
+    }
+
+    pub fn data(&self) -> ApiErrorData {
+        ApiErrorData {
+            version: API_ERROR_DATA_VERSION,
+            kind: self.kind(),
+            retryable: self.retryable(),
+            progress: match self {
+                PhotonApiError::IndexerSyncing {
+                    current_slot,
+                    target_slot,
+                } => Some(SyncProgress {
+                    current_slot: *current_slot,
+                    target_slot: *target_slot,
+                }),
+                _ => None,
+            },
+        }
+    }
 }
 
 // TODO: Simplify error conversions and ensure we adhere
@@ -52,24 +157,133 @@ impl From<PhotonApiError> for RpcError {
                 }
                 invalid_request(val)
             }
-            PhotonApiError::DatabaseError(e) => {
-                error!("Internal server database error: {}", e);
+            PhotonApiError::MissingSlots(_) => {
+                metric! {
+                    statsd_count!("missing_slots_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::RootMismatch(_) => {
+                metric! {
+                    statsd_count!("root_mismatch_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::WarmingUp => {
+                metric! {
+                    statsd_count!("warming_up_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::IndexerSyncing { .. } => {
+                metric! {
+                    statsd_count!("indexer_syncing_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::DatabaseBusy => {
+                metric! {
+                    statsd_count!("database_busy_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::QueryTimeout => {
+                metric! {
+                    statsd_count!("query_timeout_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::RateLimited => {
+                metric! {
+                    statsd_count!("rate_limited_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::MethodDisabled(_) => {
+                metric! {
+                    statsd_count!("method_disabled_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::SlotNotIndexed { .. } => {
+                metric! {
+                    statsd_count!("slot_not_indexed_api_error", 1);
+                }
+                invalid_request(val)
+            }
+            PhotonApiError::DatabaseError(ref e) => {
+                error!("Internal server database error [{}]: {}", val.kind(), e);
                 metric! {
                     statsd_count!("internal_database_api_error", 1);
                 }
-                internal_server_error()
+                internal_server_error(val)
             }
-            PhotonApiError::UnexpectedError(e) => {
-                error!("Internal server error: {}", e);
+            PhotonApiError::UnexpectedError(ref e) => {
+                error!("Internal server error [{}]: {}", val.kind(), e);
                 metric! {
                     statsd_count!("unexpected_api_error", 1);
                 }
-                internal_server_error()
+                internal_server_error(val)
             }
         }
     }
 }
 
+// Like for JSON-RPC, the details of internal errors are only logged.
+impl From<PhotonApiError> for tonic::Status {
+    fn from(val: PhotonApiError) -> Self {
+        match val {
+            PhotonApiError::ValidationError(_) | PhotonApiError::InvalidPubkey { .. } => {
+                tonic::Status::invalid_argument(val.to_string())
+            }
+            PhotonApiError::RecordNotFound(_) => tonic::Status::not_found(val.to_string()),
+            PhotonApiError::QueryTimeout => tonic::Status::deadline_exceeded(val.to_string()),
+            PhotonApiError::RateLimited => tonic::Status::resource_exhausted(val.to_string()),
+            PhotonApiError::MethodDisabled(_) => tonic::Status::unimplemented(val.to_string()),
+            PhotonApiError::SlotNotIndexed { .. } => tonic::Status::out_of_range(val.to_string()),
+            PhotonApiError::RootMismatch(_) => tonic::Status::failed_precondition(val.to_string()),
+            PhotonApiError::StaleSlot(_)
+            | PhotonApiError::MissingSlots(_)
+            | PhotonApiError::WarmingUp
+            | PhotonApiError::IndexerSyncing { .. }
+            | PhotonApiError::DatabaseBusy => tonic::Status::unavailable(val.to_string()),
+            PhotonApiError::DatabaseError(ref e) => {
+                error!("Internal server database error [{}]: {}", val.kind(), e);
+                tonic::Status::internal("Internal server error")
+            }
+            PhotonApiError::UnexpectedError(ref e) => {
+                error!("Internal server error [{}]: {}", val.kind(), e);
+                tonic::Status::internal("Internal server error")
+            }
+        }
+    }
+}
+
+// Exhausted pools and statement timeouts are reported as such, since unlike other database errors
+// they are caused by load and tell operators to tune the pool.
+impl From<DbErr> for PhotonApiError {
+    fn from(error: DbErr) -> Self {
+        match error {
+            DbErr::ConnectionAcquire => PhotonApiError::DatabaseBusy,
+            DbErr::Exec(RuntimeErr::SqlxError(ref e))
+            | DbErr::Query(RuntimeErr::SqlxError(ref e))
+                if is_statement_timeout(e) =>
+            {
+                PhotonApiError::QueryTimeout
+            }
+            error => PhotonApiError::DatabaseError(error),
+        }
+    }
+}
+
+// Postgres cancels statements that exceed `statement_timeout` with SQLSTATE 57014.
+fn is_statement_timeout(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("57014"),
+        _ => false,
+    }
+}
+
 // The API contract receives parsed input from the user, so if we get a ParseHashError it means
 // that the database itself returned an invalid hash.
 impl From<ParseHashError> for PhotonApiError {
@@ -85,9 +299,19 @@ impl From<ParsePubkeyError> for PhotonApiError {
 }
 
 fn invalid_request(e: PhotonApiError) -> RpcError {
-    RpcError::Call(CallError::from_std_error(e))
+    RpcError::Call(CallError::Custom(ErrorObject::owned(
+        CALL_EXECUTION_FAILED_CODE,
+        e.to_string(),
+        Some(e.data()),
+    )))
 }
 
-fn internal_server_error() -> RpcError {
-    RpcError::Call(CallError::Failed(anyhow::anyhow!("Internal server error")))
+// The details of internal errors are only logged, but the error data still tells clients whether
+// to retry.
+fn internal_server_error(e: PhotonApiError) -> RpcError {
+    RpcError::Call(CallError::Custom(ErrorObject::owned(
+        CALL_EXECUTION_FAILED_CODE,
+        "Internal server error",
+        Some(e.data()),
+    )))
 }
@@ -0,0 +1,528 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use hyper::body::Sender;
+use hyper::header::{ACCEPT, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use jsonrpsee::core::server::rpc_module::Methods;
+use log::error;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use solana_sdk::pubkey::Pubkey;
+use tower::{Layer, Service};
+
+use super::method::utils::parse_account_model;
+use super::read_replicas::ReadReplicas;
+use crate::common::account_data_deduplication::resolve_account_data;
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::hash::Hash;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::dao::generated::{accounts, state_trees};
+
+const TREE_LEAVES_PATH: &str = "/export/tree-leaves";
+const ACCOUNTS_PATH: &str = "/export/accounts";
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+// Paginated JSON-RPC methods that can be streamed with `format=ndjson`, with the field of the
+// response value that holds the rows of a page.
+const LIST_METHODS: &[(&str, &str)] = &[
+    ("getCompressedAccountsByOwner", "items"),
+    ("getCompressedTokenAccountsByOwner", "items"),
+    ("getCompressedTokenAccountsByDelegate", "items"),
+    ("getCompressedTokenAccountByOwnerAndMint", "items"),
+    ("getCompressedTokenBalancesByOwner", "tokenBalances"),
+    ("getCompressedTokenBalancesByOwnerV2", "items"),
+    ("getCompressedMintTokenHolders", "items"),
+    ("getTopCompressedSolOwners", "items"),
+    ("getLatestCompressionSignatures", "items"),
+    ("getCompressionSignaturesForAddress", "items"),
+    ("getCompressionSignaturesForOwner", "items"),
+    ("getCompressionSignaturesForTokenOwner", "items"),
+];
+
+// Number of rows that are loaded from the database at a time.
+const EXPORT_PAGE_SIZE: u64 = 1000;
+// Serialized rows are buffered until they reach this size and are then sent as one chunk.
+const EXPORT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// A result set that is too large to be built in memory, so it is streamed to the client page by
+/// page instead of being served through JSON-RPC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Export {
+    /// The leaves of a state tree, ordered by leaf index.
+    TreeLeaves { tree: SerializablePubkey },
+    /// The accounts created at or after a slot, ordered by the slot they were created in.
+    Accounts { from_slot: u64 },
+}
+
+impl Export {
+    fn parse(path: &str, query: Option<&str>) -> Option<Result<Self, String>> {
+        let param = |name: &str| {
+            query_param(query, name).ok_or(format!("Missing query parameter: {}", name))
+        };
+        let export = match path {
+            TREE_LEAVES_PATH => param("tree").and_then(|tree| {
+                Pubkey::from_str(tree)
+                    .map(|tree| Export::TreeLeaves { tree: tree.into() })
+                    .map_err(|_| format!("Invalid tree: {}", tree))
+            }),
+            ACCOUNTS_PATH => param("fromSlot").and_then(|from_slot| {
+                from_slot
+                    .parse()
+                    .map(|from_slot| Export::Accounts { from_slot })
+                    .map_err(|_| format!("Invalid fromSlot: {}", from_slot))
+            }),
+            _ => return None,
+        };
+        Some(export)
+    }
+}
+
+fn query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    /// A single JSON array.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn from_request(req: &Request<Body>) -> Self {
+        if query_param(req.uri().query(), "format") == Some("ndjson") {
+            return ExportFormat::Ndjson;
+        }
+        let accepts_ndjson = req
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.contains(NDJSON_CONTENT_TYPE));
+        if accepts_ndjson {
+            ExportFormat::Ndjson
+        } else {
+            ExportFormat::Json
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => JSON_CONTENT_TYPE,
+            ExportFormat::Ndjson => NDJSON_CONTENT_TYPE,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TreeLeaf {
+    leaf_index: u64,
+    hash: Hash,
+    seq: u64,
+}
+
+#[derive(Serialize)]
+struct AccountWithSpent {
+    #[serde(flatten)]
+    account: Account,
+    spent: bool,
+}
+
+/// Writes serialized rows to a streamed response body. Rows are sent in chunks of about
+/// `EXPORT_CHUNK_BYTES`, and sending waits until the client has consumed the previous chunk, so a
+/// slow client slows down the export instead of making it buffer.
+struct ChunkedWriter {
+    sender: Sender,
+    format: ExportFormat,
+    buffer: Vec<u8>,
+    rows: usize,
+}
+
+impl ChunkedWriter {
+    fn new(sender: Sender, format: ExportFormat) -> Self {
+        Self {
+            sender,
+            format,
+            buffer: Vec::with_capacity(EXPORT_CHUNK_BYTES),
+            rows: 0,
+        }
+    }
+
+    async fn write<T: Serialize>(&mut self, row: &T) -> anyhow::Result<()> {
+        if self.format == ExportFormat::Json {
+            self.buffer.push(if self.rows == 0 { b'[' } else { b',' });
+        }
+        serde_json::to_writer(&mut self.buffer, row)?;
+        if self.format == ExportFormat::Ndjson {
+            self.buffer.push(b'\n');
+        }
+        self.rows += 1;
+        if self.buffer.len() >= EXPORT_CHUNK_BYTES {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if !self.buffer.is_empty() {
+            let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(EXPORT_CHUNK_BYTES));
+            self.sender.send_data(Bytes::from(chunk)).await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> anyhow::Result<()> {
+        if self.format == ExportFormat::Json {
+            if self.rows == 0 {
+                self.buffer.push(b'[');
+            }
+            self.buffer.push(b']');
+        }
+        self.flush().await
+    }
+
+    /// Ends the response with an error, which the client sees as a truncated body. The status has
+    /// already been sent, so this is the only way to signal a failed export.
+    fn abort(self) {
+        self.sender.abort();
+    }
+}
+
+async fn export_tree_leaves(
+    db: &DatabaseConnection,
+    tree: SerializablePubkey,
+    writer: &mut ChunkedWriter,
+) -> anyhow::Result<()> {
+    let mut last_node_idx = None;
+    loop {
+        let mut query = state_trees::Entity::find()
+            .filter(state_trees::Column::Tree.eq(tree.to_bytes_vec()))
+            .filter(state_trees::Column::Level.eq(0));
+        if let Some(last_node_idx) = last_node_idx {
+            query = query.filter(state_trees::Column::NodeIdx.gt(last_node_idx));
+        }
+        let leaves = query
+            .order_by_asc(state_trees::Column::NodeIdx)
+            .limit(EXPORT_PAGE_SIZE)
+            .all(db)
+            .await?;
+        for leaf in leaves.iter() {
+            writer
+                .write(&TreeLeaf {
+                    leaf_index: leaf.leaf_idx.unwrap_or_default() as u64,
+                    hash: Hash::try_from(leaf.hash.clone())?,
+                    seq: leaf.seq as u64,
+                })
+                .await?;
+        }
+        match leaves.last() {
+            Some(leaf) if leaves.len() as u64 == EXPORT_PAGE_SIZE => {
+                last_node_idx = Some(leaf.node_idx)
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+async fn export_accounts(
+    db: &DatabaseConnection,
+    from_slot: u64,
+    writer: &mut ChunkedWriter,
+) -> anyhow::Result<()> {
+    let mut last_key: Option<(i64, Vec<u8>)> = None;
+    loop {
+        let mut query =
+            accounts::Entity::find().filter(accounts::Column::SlotCreated.gte(from_slot as i64));
+        if let Some((slot_created, hash)) = last_key {
+            query = query.filter(
+                Condition::any()
+                    .add(accounts::Column::SlotCreated.gt(slot_created))
+                    .add(
+                        Condition::all()
+                            .add(accounts::Column::SlotCreated.eq(slot_created))
+                            .add(accounts::Column::Hash.gt(hash)),
+                    ),
+            );
+        }
+        let mut models = query
+            .order_by_asc(accounts::Column::SlotCreated)
+            .order_by_asc(accounts::Column::Hash)
+            .limit(EXPORT_PAGE_SIZE)
+            .all(db)
+            .await?;
+        resolve_account_data(db, models.iter_mut()).await?;
+        let page_size = models.len() as u64;
+        last_key = models
+            .last()
+            .map(|model| (model.slot_created, model.hash.clone()));
+        for model in models {
+            let spent = model.spent;
+            let account = parse_account_model(model)?;
+            writer.write(&AccountWithSpent { account, spent }).await?;
+        }
+        if page_size < EXPORT_PAGE_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+async fn run_export(
+    db: &DatabaseConnection,
+    export: &Export,
+    writer: &mut ChunkedWriter,
+) -> anyhow::Result<()> {
+    match export {
+        Export::TreeLeaves { tree } => export_tree_leaves(db, *tree, writer).await?,
+        Export::Accounts { from_slot } => export_accounts(db, *from_slot, writer).await?,
+    }
+    writer.finish().await
+}
+
+#[derive(Deserialize)]
+struct ListRequest {
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+/// Pagination state of a list method that is streamed as NDJSON.
+struct ListQuery {
+    methods: Methods,
+    id: Value,
+    method: &'static str,
+    items_field: &'static str,
+    params: Map<String, Value>,
+}
+
+/// A page of a list method, or the JSON-RPC error response the method failed with.
+enum ListPage {
+    Rows { items: Vec<Value>, cursor: Value },
+    Error(String),
+}
+
+impl ListQuery {
+    fn parse(methods: Methods, body: &[u8]) -> Result<Self, String> {
+        let request: ListRequest =
+            serde_json::from_slice(body).map_err(|e| format!("Invalid request: {}", e))?;
+        let (method, items_field) = LIST_METHODS
+            .iter()
+            .find(|(method, _)| *method == request.method)
+            .ok_or(format!("{} does not support format=ndjson", request.method))?;
+        let params = match request.params {
+            None | Some(Value::Null) => Map::new(),
+            Some(Value::Object(params)) => params,
+            Some(_) => return Err("Params must be an object to use format=ndjson".to_string()),
+        };
+        Ok(Self {
+            methods,
+            id: request.id,
+            method,
+            items_field,
+            params,
+        })
+    }
+
+    async fn fetch_page(&self) -> anyhow::Result<ListPage> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.id,
+            "method": self.method,
+            "params": self.params,
+        });
+        let (response, _) = self.methods.raw_json_request(&request.to_string()).await?;
+        if !response.success {
+            return Ok(ListPage::Error(response.result));
+        }
+        let mut response: Value = serde_json::from_str(&response.result)?;
+        let value = response["result"]["value"].take();
+        let items = match &value[self.items_field] {
+            Value::Array(items) => items.clone(),
+            _ => anyhow::bail!("{} returned no {}", self.method, self.items_field),
+        };
+        Ok(ListPage::Rows {
+            items,
+            cursor: value["cursor"].clone(),
+        })
+    }
+
+    async fn stream_pages(
+        &mut self,
+        mut items: Vec<Value>,
+        mut cursor: Value,
+        writer: &mut ChunkedWriter,
+    ) -> anyhow::Result<()> {
+        loop {
+            for item in items.iter() {
+                writer.write(item).await?;
+            }
+            if cursor.is_null() {
+                return writer.finish().await;
+            }
+            self.params.insert("cursor".to_string(), cursor);
+            match self.fetch_page().await? {
+                ListPage::Rows {
+                    items: next_items,
+                    cursor: next_cursor,
+                } => {
+                    items = next_items;
+                    cursor = next_cursor;
+                }
+                ListPage::Error(error) => anyhow::bail!("{} failed: {}", self.method, error),
+            }
+        }
+    }
+}
+
+async fn stream_list_query(methods: Methods, body: Body) -> hyper::http::Result<Response<Body>> {
+    let bad_request = |message: String| {
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(message))
+    };
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => return bad_request(format!("Failed to read request: {}", e)),
+    };
+    let mut query = match ListQuery::parse(methods, &body) {
+        Ok(query) => query,
+        Err(message) => return bad_request(message),
+    };
+    let (items, cursor) = match query.fetch_page().await {
+        Ok(ListPage::Rows { items, cursor }) => (items, cursor),
+        // Errors of the first page are returned like any other JSON-RPC error.
+        Ok(ListPage::Error(error)) => {
+            return Response::builder()
+                .header(CONTENT_TYPE, JSON_CONTENT_TYPE)
+                .body(Body::from(error))
+        }
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(e.to_string()))
+        }
+    };
+    let (sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut writer = ChunkedWriter::new(sender, ExportFormat::Ndjson);
+        if let Err(e) = query.stream_pages(items, cursor, &mut writer).await {
+            error!("Failed to stream {}: {}", query.method, e);
+            writer.abort();
+        }
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, NDJSON_CONTENT_TYPE)
+        .body(body)
+}
+
+/// Streams large exports on `GET /export/tree-leaves?tree=<tree>` and
+/// `GET /export/accounts?fromSlot=<slot>` and passes all other requests through. The response is a
+/// JSON array, or newline-delimited JSON if the `Accept` header asks for `application/x-ndjson` or
+/// the query has `format=ndjson`.
+///
+/// JSON-RPC requests for the paginated methods in `LIST_METHODS` that are posted with
+/// `?format=ndjson` are answered with the rows of all pages as newline-delimited JSON, following
+/// the cursors on behalf of the client.
+#[derive(Clone)]
+pub struct ExportLayer {
+    db: Arc<ReadReplicas>,
+    methods: Methods,
+}
+
+impl ExportLayer {
+    pub fn new(db: Arc<ReadReplicas>, methods: impl Into<Methods>) -> Self {
+        Self {
+            db,
+            methods: methods.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for ExportLayer {
+    type Service = ExportService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExportService {
+            db: self.db.clone(),
+            methods: self.methods.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExportService<S> {
+    db: Arc<ReadReplicas>,
+    methods: Methods,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for ExportService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.method() == Method::POST
+            && query_param(req.uri().query(), "format") == Some("ndjson")
+        {
+            let response = stream_list_query(self.methods.clone(), req.into_body());
+            return Box::pin(async move { response.await.map_err(Into::into) });
+        }
+        let export = match req.method() {
+            &Method::GET => Export::parse(req.uri().path(), req.uri().query()),
+            _ => None,
+        };
+        let response = match export {
+            None => {
+                let future = self.inner.call(req);
+                return Box::pin(async move { future.await.map_err(Into::into) });
+            }
+            Some(Err(message)) => Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(message)),
+            Some(Ok(export)) => {
+                let format = ExportFormat::from_request(&req);
+                let (sender, body) = Body::channel();
+                let db = self.db.connection();
+                tokio::spawn(async move {
+                    let mut writer = ChunkedWriter::new(sender, format);
+                    if let Err(e) = run_export(db.as_ref(), &export, &mut writer).await {
+                        error!("Failed to stream {:?}: {}", export, e);
+                        writer.abort();
+                    }
+                });
+                Response::builder()
+                    .header(CONTENT_TYPE, format.content_type())
+                    .body(body)
+            }
+        }
+        .map_err(Into::into);
+        Box::pin(async move { response })
+    }
+}
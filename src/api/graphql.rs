@@ -0,0 +1,619 @@
+use std::error::Error as StdError;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Error, ErrorExtensions, Object, Result,
+    Schema, SimpleObject,
+};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::error;
+use tower::{Layer, Service};
+
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::cursor::Cursor;
+use crate::common::typedefs::hash::Hash;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::common::typedefs::token_data::{AccountState, TokenData};
+use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::ingester::persist::parse_token_data;
+
+use super::api::PhotonApi;
+use super::error::PhotonApiError;
+use super::method::get_compressed_accounts_by_owner::GetCompressedAccountsByOwnerRequest;
+use super::method::get_compression_signatures_for_address::GetCompressionSignaturesForAddressRequest;
+use super::method::get_compression_signatures_for_owner::GetCompressionSignaturesForOwnerRequest;
+use super::method::get_multiple_compressed_accounts::GetMultipleCompressedAccountsRequest;
+use super::method::utils::{
+    CompressedAccountRequest, GetCompressedTokenAccountsByDelegate,
+    GetCompressedTokenAccountsByOwner, HashRequest, Limit, PaginatedSignatureInfoList,
+    SignatureInfo, TokenAccountListResponse, PAGE_LIMIT,
+};
+
+pub const GRAPHQL_PATH: &str = "/graphql";
+
+// Fields that run a database query for every parent object, like the signatures of an account, are
+// weighted like this many plain fields, so that selecting them on a full page of accounts exceeds
+// the default complexity limit.
+const NESTED_QUERY_COMPLEXITY: usize = 100;
+
+/// Limits that queries are validated against before they are executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphQLLimits {
+    /// Maximum nesting of selection sets.
+    pub max_depth: usize,
+    /// Maximum complexity, where every field costs 1 and list fields cost their children times the
+    /// number of requested items.
+    pub max_complexity: usize,
+}
+
+impl Default for GraphQLLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_complexity: 100_000,
+        }
+    }
+}
+
+pub type PhotonSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn build_schema(api: PhotonApi, limits: GraphQLLimits) -> PhotonSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(api)
+        .limit_depth(limits.max_depth)
+        .limit_complexity(limits.max_complexity)
+        .finish()
+}
+
+// Like for JSON-RPC, the details of internal errors are only logged.
+fn api_error(error: PhotonApiError) -> Error {
+    let data = error.data();
+    let message = match error {
+        PhotonApiError::DatabaseError(ref e) => {
+            error!("Internal server database error [{}]: {}", error.kind(), e);
+            "Internal server error".to_string()
+        }
+        PhotonApiError::UnexpectedError(ref e) => {
+            error!("Internal server error [{}]: {}", error.kind(), e);
+            "Internal server error".to_string()
+        }
+        _ => error.to_string(),
+    };
+    Error::new(message).extend_with(|_, extensions| {
+        extensions.set("kind", data.kind);
+        extensions.set("retryable", data.retryable);
+    })
+}
+
+// Fields are answered by the handlers of JSON-RPC methods and are disabled with them.
+fn api<'a>(ctx: &Context<'a>, method: &str) -> Result<&'a PhotonApi> {
+    let api = ctx.data_unchecked::<PhotonApi>();
+    api.ensure_method_enabled(method).map_err(api_error)?;
+    Ok(api)
+}
+
+fn parse_pubkey(argument: &str, value: &str) -> Result<SerializablePubkey> {
+    SerializablePubkey::try_from(value)
+        .map_err(|_| Error::new(format!("Invalid public key: argument '{}'", argument)))
+}
+
+fn parse_hash(argument: &str, value: &str) -> Result<Hash> {
+    Hash::try_from(value).map_err(|_| Error::new(format!("Invalid hash: argument '{}'", argument)))
+}
+
+fn parse_cursor(cursor: Option<String>) -> Result<Option<Cursor>> {
+    cursor
+        .map(|cursor| Cursor::decode(&cursor))
+        .transpose()
+        .map_err(Error::new)
+}
+
+fn parse_limit(limit: Option<u64>) -> Result<Option<Limit>> {
+    limit.map(Limit::new).transpose().map_err(Error::new)
+}
+
+#[allow(deprecated)]
+fn encode_base64(bytes: &[u8]) -> String {
+    base64::encode(bytes)
+}
+
+/// Values are represented like in the JSON-RPC API: public keys, hashes and signatures are base58
+/// strings, and lamports and token amounts are strings since they can exceed the range of `Int`.
+pub struct AccountObject(Account);
+
+#[Object(name = "Account")]
+impl AccountObject {
+    async fn hash(&self) -> String {
+        self.0.hash.to_string()
+    }
+
+    async fn address(&self) -> Option<String> {
+        self.0.address.map(|address| address.to_string())
+    }
+
+    async fn data(&self) -> Option<AccountDataObject> {
+        self.0.data.as_ref().map(|data| AccountDataObject {
+            discriminator: data.discriminator.0,
+            data: encode_base64(&data.data.0),
+            data_hash: data.data_hash.to_string(),
+        })
+    }
+
+    async fn owner(&self) -> String {
+        self.0.owner.to_string()
+    }
+
+    async fn lamports(&self) -> String {
+        self.0.lamports.0.to_string()
+    }
+
+    async fn tree(&self) -> String {
+        self.0.tree.to_string()
+    }
+
+    async fn leaf_index(&self) -> u64 {
+        self.0.leaf_index.0
+    }
+
+    async fn seq(&self) -> u64 {
+        self.0.seq.0
+    }
+
+    async fn slot_created(&self) -> u64 {
+        self.0.slot_created.0
+    }
+
+    /// The token data of the account, if it is a compressed token account.
+    async fn token_data(&self) -> Result<Option<TokenDataObject>> {
+        let token_data = parse_token_data(&self.0).map_err(|e| {
+            api_error(PhotonApiError::UnexpectedError(format!(
+                "Failed to parse token data for account {}: {}",
+                self.0.hash, e
+            )))
+        })?;
+        Ok(token_data.map(TokenDataObject))
+    }
+
+    /// The signatures of the transactions that created or spent the account. Empty if the
+    /// transactions have not been indexed, e.g. because the account was loaded from a snapshot.
+    #[graphql(complexity = "NESTED_QUERY_COMPLEXITY + child_complexity")]
+    async fn signatures(&self, ctx: &Context<'_>) -> Result<Vec<SignatureInfoObject>> {
+        let response = api(ctx, "getCompressionSignaturesForAccount")?
+            .get_compression_signatures_for_account(HashRequest {
+                hash: self.0.hash.clone(),
+            })
+            .await;
+        match response {
+            Ok(response) => Ok(response
+                .value
+                .items
+                .into_iter()
+                .map(SignatureInfoObject::from)
+                .collect()),
+            Err(PhotonApiError::RecordNotFound(_)) => Ok(vec![]),
+            Err(e) => Err(api_error(e)),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "AccountData")]
+pub struct AccountDataObject {
+    discriminator: u64,
+    /// Base64 encoded data.
+    data: String,
+    data_hash: String,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "AccountState")]
+pub enum AccountStateObject {
+    Initialized,
+    Frozen,
+}
+
+pub struct TokenDataObject(TokenData);
+
+#[Object(name = "TokenData")]
+impl TokenDataObject {
+    async fn mint(&self) -> String {
+        self.0.mint.to_string()
+    }
+
+    async fn owner(&self) -> String {
+        self.0.owner.to_string()
+    }
+
+    async fn amount(&self) -> String {
+        self.0.amount.0.to_string()
+    }
+
+    async fn delegate(&self) -> Option<String> {
+        self.0.delegate.map(|delegate| delegate.to_string())
+    }
+
+    async fn state(&self) -> AccountStateObject {
+        match self.0.state {
+            AccountState::initialized => AccountStateObject::Initialized,
+            AccountState::frozen => AccountStateObject::Frozen,
+        }
+    }
+
+    /// Base64 encoded TLV data of the token extensions.
+    async fn tlv(&self) -> Option<String> {
+        self.0.tlv.as_ref().map(|tlv| encode_base64(&tlv.0))
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "TokenAccount")]
+pub struct TokenAccountObject {
+    account: AccountObject,
+    token_data: TokenDataObject,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "SignatureInfo")]
+pub struct SignatureInfoObject {
+    signature: String,
+    slot: u64,
+    block_time: u64,
+}
+
+impl From<SignatureInfo> for SignatureInfoObject {
+    fn from(signature_info: SignatureInfo) -> Self {
+        SignatureInfoObject {
+            signature: signature_info.signature.0.to_string(),
+            slot: signature_info.slot.0,
+            block_time: signature_info.block_time.0,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "AccountPage")]
+pub struct AccountPage {
+    items: Vec<AccountObject>,
+    cursor: Option<String>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "TokenAccountPage")]
+pub struct TokenAccountPage {
+    items: Vec<TokenAccountObject>,
+    cursor: Option<String>,
+}
+
+impl From<TokenAccountListResponse> for TokenAccountPage {
+    fn from(response: TokenAccountListResponse) -> Self {
+        TokenAccountPage {
+            items: response
+                .value
+                .items
+                .into_iter()
+                .map(|token_account| TokenAccountObject {
+                    account: AccountObject(token_account.account),
+                    token_data: TokenDataObject(token_account.token_data),
+                })
+                .collect(),
+            cursor: response.value.cursor.map(|cursor| cursor.encode()),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "SignaturePage")]
+pub struct SignaturePage {
+    items: Vec<SignatureInfoObject>,
+    cursor: Option<String>,
+}
+
+impl From<PaginatedSignatureInfoList> for SignaturePage {
+    fn from(list: PaginatedSignatureInfoList) -> Self {
+        SignaturePage {
+            items: list.items.into_iter().map(Into::into).collect(),
+            cursor: list.cursor.map(|cursor| cursor.encode()),
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+/// The read methods of `PhotonApi`, answered by the same handlers as the JSON-RPC API. Paginated
+/// fields take the same cursors and limits as the corresponding JSON-RPC methods.
+#[Object]
+impl QueryRoot {
+    /// The last slot that has been indexed.
+    async fn indexer_slot(&self, ctx: &Context<'_>) -> Result<u64> {
+        let slot = api(ctx, "getIndexerSlot")?
+            .get_indexer_slot()
+            .await
+            .map_err(api_error)?;
+        Ok(slot.0)
+    }
+
+    /// Looks up an account by its hash or by its address.
+    async fn account(
+        &self,
+        ctx: &Context<'_>,
+        hash: Option<String>,
+        address: Option<String>,
+    ) -> Result<Option<AccountObject>> {
+        let request = CompressedAccountRequest {
+            hash: hash.map(|hash| parse_hash("hash", &hash)).transpose()?,
+            address: address
+                .map(|address| parse_pubkey("address", &address))
+                .transpose()?,
+        };
+        let response = api(ctx, "getCompressedAccount")?
+            .get_compressed_account(request)
+            .await
+            .map_err(api_error)?;
+        Ok(response.value.map(AccountObject))
+    }
+
+    /// Looks up accounts by their hashes or by their addresses. Accounts that do not exist are
+    /// returned as `null`.
+    #[graphql(
+        complexity = "hashes.as_ref().or(addresses.as_ref()).map_or(0, Vec::len) * child_complexity"
+    )]
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        hashes: Option<Vec<String>>,
+        addresses: Option<Vec<String>>,
+    ) -> Result<Vec<Option<AccountObject>>> {
+        let request = GetMultipleCompressedAccountsRequest {
+            hashes: hashes
+                .map(|hashes| {
+                    hashes
+                        .iter()
+                        .map(|hash| parse_hash("hashes", hash))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .transpose()?,
+            addresses: addresses
+                .map(|addresses| {
+                    addresses
+                        .iter()
+                        .map(|address| parse_pubkey("addresses", address))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .transpose()?,
+        };
+        let response = api(ctx, "getMultipleCompressedAccounts")?
+            .get_multiple_compressed_accounts(request)
+            .await
+            .map_err(api_error)?;
+        Ok(response
+            .value
+            .items
+            .into_iter()
+            .map(|account| account.map(AccountObject))
+            .collect())
+    }
+
+    #[graphql(complexity = "limit.unwrap_or(PAGE_LIMIT) as usize * child_complexity")]
+    async fn accounts_by_owner(
+        &self,
+        ctx: &Context<'_>,
+        owner: String,
+        discriminator: Option<u64>,
+        cursor: Option<String>,
+        limit: Option<u64>,
+    ) -> Result<AccountPage> {
+        let request = GetCompressedAccountsByOwnerRequest {
+            owner: parse_pubkey("owner", &owner)?,
+            discriminator: discriminator.map(UnsignedInteger),
+            cursor: parse_cursor(cursor)?,
+            limit: parse_limit(limit)?,
+            ..Default::default()
+        };
+        let response = api(ctx, "getCompressedAccountsByOwner")?
+            .get_compressed_accounts_by_owner(request)
+            .await
+            .map_err(api_error)?;
+        Ok(AccountPage {
+            items: response
+                .value
+                .items
+                .into_iter()
+                .map(AccountObject)
+                .collect(),
+            cursor: response.value.cursor.map(|cursor| cursor.encode()),
+        })
+    }
+
+    #[graphql(complexity = "limit.unwrap_or(PAGE_LIMIT) as usize * child_complexity")]
+    async fn token_accounts_by_owner(
+        &self,
+        ctx: &Context<'_>,
+        owner: String,
+        mint: Option<String>,
+        cursor: Option<String>,
+        limit: Option<u64>,
+    ) -> Result<TokenAccountPage> {
+        let request = GetCompressedTokenAccountsByOwner {
+            owner: parse_pubkey("owner", &owner)?,
+            mint: mint.map(|mint| parse_pubkey("mint", &mint)).transpose()?,
+            cursor: parse_cursor(cursor)?,
+            limit: parse_limit(limit)?,
+            ..Default::default()
+        };
+        let response = api(ctx, "getCompressedTokenAccountsByOwner")?
+            .get_compressed_token_accounts_by_owner(request)
+            .await
+            .map_err(api_error)?;
+        Ok(response.into())
+    }
+
+    #[graphql(complexity = "limit.unwrap_or(PAGE_LIMIT) as usize * child_complexity")]
+    async fn token_accounts_by_delegate(
+        &self,
+        ctx: &Context<'_>,
+        delegate: String,
+        mint: Option<String>,
+        cursor: Option<String>,
+        limit: Option<u64>,
+    ) -> Result<TokenAccountPage> {
+        let request = GetCompressedTokenAccountsByDelegate {
+            delegate: parse_pubkey("delegate", &delegate)?,
+            mint: mint.map(|mint| parse_pubkey("mint", &mint)).transpose()?,
+            cursor: parse_cursor(cursor)?,
+            limit: parse_limit(limit)?,
+            ..Default::default()
+        };
+        let response = api(ctx, "getCompressedTokenAccountsByDelegate")?
+            .get_compressed_token_accounts_by_delegate(request)
+            .await
+            .map_err(api_error)?;
+        Ok(response.into())
+    }
+
+    #[graphql(complexity = "limit.unwrap_or(PAGE_LIMIT) as usize * child_complexity")]
+    async fn signatures_for_owner(
+        &self,
+        ctx: &Context<'_>,
+        owner: String,
+        cursor: Option<String>,
+        limit: Option<u64>,
+    ) -> Result<SignaturePage> {
+        let request = GetCompressionSignaturesForOwnerRequest {
+            owner: parse_pubkey("owner", &owner)?,
+            cursor: parse_cursor(cursor)?,
+            limit: parse_limit(limit)?,
+        };
+        let response = api(ctx, "getCompressionSignaturesForOwner")?
+            .get_compression_signatures_for_owner(request)
+            .await
+            .map_err(api_error)?;
+        Ok(response.value.into())
+    }
+
+    #[graphql(complexity = "limit.unwrap_or(PAGE_LIMIT) as usize * child_complexity")]
+    async fn signatures_for_address(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+        cursor: Option<String>,
+        limit: Option<u64>,
+    ) -> Result<SignaturePage> {
+        let request = GetCompressionSignaturesForAddressRequest {
+            address: parse_pubkey("address", &address)?,
+            cursor: parse_cursor(cursor)?,
+            limit: parse_limit(limit)?,
+        };
+        let response = api(ctx, "getCompressionSignaturesForAddress")?
+            .get_compression_signatures_for_address(request)
+            .await
+            .map_err(api_error)?;
+        Ok(response.value.into())
+    }
+}
+
+async fn execute(schema: PhotonSchema, body: Body) -> hyper::http::Result<Response<Body>> {
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Failed to read request: {}", e)))
+        }
+    };
+    let request: async_graphql::Request = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid GraphQL request: {}", e)))
+        }
+    };
+    let response = schema.execute(request).await;
+    match serde_json::to_vec(&response) {
+        Ok(body) => Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body)),
+        Err(e) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string())),
+    }
+}
+
+/// Answers GraphQL queries posted to `/graphql` and returns the schema in SDL on `GET /graphql`,
+/// so that clients can generate types without running an introspection query. All other requests
+/// are passed through, as are all requests if GraphQL is disabled.
+#[derive(Clone)]
+pub struct GraphQLLayer {
+    schema: Option<PhotonSchema>,
+}
+
+impl GraphQLLayer {
+    pub fn new(schema: Option<PhotonSchema>) -> Self {
+        Self { schema }
+    }
+}
+
+impl<S> Layer<S> for GraphQLLayer {
+    type Service = GraphQLService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GraphQLService {
+            schema: self.schema.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GraphQLService<S> {
+    schema: Option<PhotonSchema>,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for GraphQLService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn StdError + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let schema = match &self.schema {
+            Some(schema) if req.uri().path() == GRAPHQL_PATH => schema.clone(),
+            _ => {
+                let future = self.inner.call(req);
+                return Box::pin(async move { future.await.map_err(Into::into) });
+            }
+        };
+        match *req.method() {
+            Method::POST => {
+                Box::pin(async move { execute(schema, req.into_body()).await.map_err(Into::into) })
+            }
+            Method::GET => {
+                let response = Response::builder()
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body(Body::from(schema.sdl()))
+                    .map_err(Into::into);
+                Box::pin(async move { response })
+            }
+            _ => {
+                let response = Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .body(Body::empty())
+                    .map_err(Into::into);
+                Box::pin(async move { response })
+            }
+        }
+    }
+}
@@ -0,0 +1,462 @@
+// The handlers of the generated service return `tonic::Status`, so the helpers do as well.
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_stream::stream;
+use futures::Stream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::info_span;
+
+use crate::common::shutdown::shutdown_requested;
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::cursor::Cursor;
+use crate::common::typedefs::hash::Hash;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::common::typedefs::token_data::{AccountState, TokenData};
+use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::ingester::persist::persisted_state_tree::MerkleProofWithContext;
+
+use super::api::PhotonApi;
+use super::error::PhotonApiError;
+use super::method::get_compressed_accounts_by_owner::GetCompressedAccountsByOwnerRequest;
+use super::method::get_compression_signatures_for_address::GetCompressionSignaturesForAddressRequest;
+use super::method::get_compression_signatures_for_owner::GetCompressionSignaturesForOwnerRequest;
+use super::method::get_multiple_compressed_account_proofs::HashList;
+use super::method::get_multiple_compressed_accounts::GetMultipleCompressedAccountsRequest;
+use super::method::utils::{
+    CompressedAccountRequest, GetCompressedTokenAccountsByDelegate,
+    GetCompressedTokenAccountsByOwner, HashRequest, Limit, SignatureInfo, TokenAcccount,
+    TokenAccountListResponse,
+};
+use super::rate_limit::{client_key, API_KEY_HEADER};
+
+pub mod proto {
+    tonic::include_proto!("photon");
+}
+
+use proto::get_compressed_account_request::Identifier;
+use proto::photon_server::{Photon, PhotonServer};
+
+fn parse_pubkey(field: &str, bytes: Vec<u8>) -> Result<SerializablePubkey, Status> {
+    SerializablePubkey::try_from(bytes)
+        .map_err(|_| Status::invalid_argument(format!("Invalid public key: field '{}'", field)))
+}
+
+fn parse_hash(field: &str, bytes: Vec<u8>) -> Result<Hash, Status> {
+    Hash::try_from(bytes)
+        .map_err(|_| Status::invalid_argument(format!("Invalid hash: field '{}'", field)))
+}
+
+fn parse_cursor(cursor: Option<String>) -> Result<Option<Cursor>, Status> {
+    cursor
+        .map(|cursor| Cursor::decode(&cursor))
+        .transpose()
+        .map_err(Status::invalid_argument)
+}
+
+fn parse_limit(limit: Option<u64>) -> Result<Option<Limit>, Status> {
+    limit
+        .map(Limit::new)
+        .transpose()
+        .map_err(Status::invalid_argument)
+}
+
+impl From<Account> for proto::Account {
+    fn from(account: Account) -> Self {
+        proto::Account {
+            hash: account.hash.to_vec(),
+            address: account.address.map(Into::into),
+            data: account.data.map(|data| proto::AccountData {
+                discriminator: data.discriminator.0,
+                data: data.data.0,
+                data_hash: data.data_hash.to_vec(),
+            }),
+            owner: account.owner.into(),
+            lamports: account.lamports.0,
+            tree: account.tree.into(),
+            leaf_index: account.leaf_index.0,
+            seq: account.seq.0,
+            slot_created: account.slot_created.0,
+        }
+    }
+}
+
+impl From<TokenData> for proto::TokenData {
+    fn from(token_data: TokenData) -> Self {
+        let state = match token_data.state {
+            AccountState::initialized => proto::AccountState::Initialized,
+            AccountState::frozen => proto::AccountState::Frozen,
+        };
+        proto::TokenData {
+            mint: token_data.mint.into(),
+            owner: token_data.owner.into(),
+            amount: token_data.amount.0,
+            delegate: token_data.delegate.map(Into::into),
+            state: state.into(),
+            tlv: token_data.tlv.map(|tlv| tlv.0),
+        }
+    }
+}
+
+impl From<TokenAcccount> for proto::TokenAccount {
+    fn from(token_account: TokenAcccount) -> Self {
+        proto::TokenAccount {
+            account: Some(token_account.account.into()),
+            token_data: Some(token_account.token_data.into()),
+        }
+    }
+}
+
+impl From<TokenAccountListResponse> for proto::TokenAccountsResponse {
+    fn from(response: TokenAccountListResponse) -> Self {
+        proto::TokenAccountsResponse {
+            slot: response.context.slot,
+            token_accounts: response.value.items.into_iter().map(Into::into).collect(),
+            cursor: response.value.cursor.map(|cursor| cursor.encode()),
+        }
+    }
+}
+
+impl From<MerkleProofWithContext> for proto::MerkleProof {
+    fn from(proof: MerkleProofWithContext) -> Self {
+        proto::MerkleProof {
+            proof: proof.proof.iter().map(Hash::to_vec).collect(),
+            root: proof.root.to_vec(),
+            leaf_index: proof.leafIndex,
+            hash: proof.hash.to_vec(),
+            merkle_tree: proof.merkleTree.into(),
+            root_seq: proof.rootSeq,
+        }
+    }
+}
+
+impl From<SignatureInfo> for proto::SignatureInfo {
+    fn from(signature_info: SignatureInfo) -> Self {
+        proto::SignatureInfo {
+            signature: signature_info.signature.0.as_ref().to_vec(),
+            slot: signature_info.slot.0,
+            block_time: signature_info.block_time.0,
+        }
+    }
+}
+
+fn accounts_by_owner_request(
+    request: proto::GetCompressedAccountsByOwnerRequest,
+) -> Result<GetCompressedAccountsByOwnerRequest, Status> {
+    Ok(GetCompressedAccountsByOwnerRequest {
+        owner: parse_pubkey("owner", request.owner)?,
+        discriminator: request.discriminator.map(UnsignedInteger),
+        cursor: parse_cursor(request.cursor)?,
+        limit: parse_limit(request.limit)?,
+        ..Default::default()
+    })
+}
+
+/// Serves the core read methods of `PhotonApi` over gRPC. Requests are converted to the request
+/// types of the JSON-RPC API and answered by the same handlers.
+pub struct PhotonGrpcService {
+    api: Arc<PhotonApi>,
+}
+
+impl PhotonGrpcService {
+    pub fn new(api: PhotonApi) -> Self {
+        Self { api: Arc::new(api) }
+    }
+
+    // Methods are disabled and rate limited by the name of the JSON-RPC method that answers them,
+    // with the same buckets as the HTTP server.
+    fn ensure_allowed<T>(&self, request: &Request<T>, method: &str) -> Result<(), Status> {
+        self.api.ensure_method_enabled(method)?;
+        let Some(limiter) = self.api.rate_limiter() else {
+            return Ok(());
+        };
+        let api_key = request
+            .metadata()
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok());
+        let address = request.remote_addr().map(|address| address.ip());
+        let client = client_key(api_key, address, limiter.config());
+        if let Err(wait) = limiter.try_acquire_call(&client, method) {
+            let mut status = Status::from(PhotonApiError::RateLimited);
+            let retry_after = wait.as_secs_f64().ceil().max(1.0).to_string();
+            if let Ok(value) = retry_after.parse() {
+                status.metadata_mut().insert("retry-after", value);
+            }
+            return Err(status);
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl Photon for PhotonGrpcService {
+    async fn get_compressed_account(
+        &self,
+        request: Request<proto::GetCompressedAccountRequest>,
+    ) -> Result<Response<proto::GetCompressedAccountResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressedAccount")?;
+        let request = match request.into_inner().identifier {
+            Some(Identifier::Address(address)) => CompressedAccountRequest {
+                address: Some(parse_pubkey("address", address)?),
+                hash: None,
+            },
+            Some(Identifier::Hash(hash)) => CompressedAccountRequest {
+                address: None,
+                hash: Some(parse_hash("hash", hash)?),
+            },
+            None => {
+                return Err(Status::invalid_argument(
+                    "Either address or hash must be set",
+                ))
+            }
+        };
+        let response = self.api.get_compressed_account(request).await?;
+        Ok(Response::new(proto::GetCompressedAccountResponse {
+            slot: response.context.slot,
+            account: response.value.map(Into::into),
+        }))
+    }
+
+    async fn get_multiple_compressed_accounts(
+        &self,
+        request: Request<proto::GetMultipleCompressedAccountsRequest>,
+    ) -> Result<Response<proto::GetMultipleCompressedAccountsResponse>, Status> {
+        self.ensure_allowed(&request, "getMultipleCompressedAccounts")?;
+        let request = request.into_inner();
+        let hashes = request
+            .hashes
+            .into_iter()
+            .map(|hash| parse_hash("hashes", hash))
+            .collect::<Result<Vec<_>, _>>()?;
+        let addresses = request
+            .addresses
+            .into_iter()
+            .map(|address| parse_pubkey("addresses", address))
+            .collect::<Result<Vec<_>, _>>()?;
+        let request = GetMultipleCompressedAccountsRequest {
+            hashes: (!hashes.is_empty()).then_some(hashes),
+            addresses: (!addresses.is_empty()).then_some(addresses),
+        };
+        let response = self.api.get_multiple_compressed_accounts(request).await?;
+        Ok(Response::new(
+            proto::GetMultipleCompressedAccountsResponse {
+                slot: response.context.slot,
+                accounts: response
+                    .value
+                    .items
+                    .into_iter()
+                    .map(|account| proto::MaybeAccount {
+                        account: account.map(Into::into),
+                    })
+                    .collect(),
+            },
+        ))
+    }
+
+    async fn get_compressed_accounts_by_owner(
+        &self,
+        request: Request<proto::GetCompressedAccountsByOwnerRequest>,
+    ) -> Result<Response<proto::GetCompressedAccountsByOwnerResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressedAccountsByOwner")?;
+        let request = accounts_by_owner_request(request.into_inner())?;
+        let response = self.api.get_compressed_accounts_by_owner(request).await?;
+        Ok(Response::new(proto::GetCompressedAccountsByOwnerResponse {
+            slot: response.context.slot,
+            accounts: response.value.items.into_iter().map(Into::into).collect(),
+            cursor: response.value.cursor.map(|cursor| cursor.encode()),
+        }))
+    }
+
+    type StreamCompressedAccountsByOwnerStream =
+        Pin<Box<dyn Stream<Item = Result<proto::Account, Status>> + Send>>;
+
+    async fn stream_compressed_accounts_by_owner(
+        &self,
+        request: Request<proto::GetCompressedAccountsByOwnerRequest>,
+    ) -> Result<Response<Self::StreamCompressedAccountsByOwnerStream>, Status> {
+        self.ensure_allowed(&request, "getCompressedAccountsByOwner")?;
+        let mut request = accounts_by_owner_request(request.into_inner())?;
+        let api = self.api.clone();
+        let accounts = stream! {
+            loop {
+                let response = match api.get_compressed_accounts_by_owner(request.clone()).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        yield Err(e.into());
+                        break;
+                    }
+                };
+                for account in response.value.items {
+                    yield Ok(account.into());
+                }
+                // The cursor is only missing after the last page, which is empty.
+                match response.value.cursor {
+                    Some(cursor) => request.cursor = Some(cursor),
+                    None => break,
+                }
+            }
+        };
+        Ok(Response::new(Box::pin(accounts)))
+    }
+
+    async fn get_compressed_token_accounts_by_owner(
+        &self,
+        request: Request<proto::GetCompressedTokenAccountsByOwnerRequest>,
+    ) -> Result<Response<proto::TokenAccountsResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressedTokenAccountsByOwner")?;
+        let request = request.into_inner();
+        let request = GetCompressedTokenAccountsByOwner {
+            owner: parse_pubkey("owner", request.owner)?,
+            mint: request
+                .mint
+                .map(|mint| parse_pubkey("mint", mint))
+                .transpose()?,
+            cursor: parse_cursor(request.cursor)?,
+            limit: parse_limit(request.limit)?,
+            ..Default::default()
+        };
+        let response = self
+            .api
+            .get_compressed_token_accounts_by_owner(request)
+            .await?;
+        Ok(Response::new(response.into()))
+    }
+
+    async fn get_compressed_token_accounts_by_delegate(
+        &self,
+        request: Request<proto::GetCompressedTokenAccountsByDelegateRequest>,
+    ) -> Result<Response<proto::TokenAccountsResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressedTokenAccountsByDelegate")?;
+        let request = request.into_inner();
+        let request = GetCompressedTokenAccountsByDelegate {
+            delegate: parse_pubkey("delegate", request.delegate)?,
+            mint: request
+                .mint
+                .map(|mint| parse_pubkey("mint", mint))
+                .transpose()?,
+            cursor: parse_cursor(request.cursor)?,
+            limit: parse_limit(request.limit)?,
+            ..Default::default()
+        };
+        let response = self
+            .api
+            .get_compressed_token_accounts_by_delegate(request)
+            .await?;
+        Ok(Response::new(response.into()))
+    }
+
+    async fn get_compressed_account_proof(
+        &self,
+        request: Request<proto::GetCompressedAccountProofRequest>,
+    ) -> Result<Response<proto::GetCompressedAccountProofResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressedAccountProof")?;
+        let request = HashRequest {
+            hash: parse_hash("hash", request.into_inner().hash)?,
+        };
+        let response = self.api.get_compressed_account_proof(request).await?;
+        Ok(Response::new(proto::GetCompressedAccountProofResponse {
+            slot: response.context.slot,
+            proof: Some(response.value.into()),
+        }))
+    }
+
+    async fn get_multiple_compressed_account_proofs(
+        &self,
+        request: Request<proto::GetMultipleCompressedAccountProofsRequest>,
+    ) -> Result<Response<proto::GetMultipleCompressedAccountProofsResponse>, Status> {
+        self.ensure_allowed(&request, "getMultipleCompressedAccountProofs")?;
+        let hashes = request
+            .into_inner()
+            .hashes
+            .into_iter()
+            .map(|hash| parse_hash("hashes", hash))
+            .collect::<Result<Vec<_>, _>>()?;
+        let response = self
+            .api
+            .get_multiple_compressed_account_proofs(HashList(hashes))
+            .await?;
+        Ok(Response::new(
+            proto::GetMultipleCompressedAccountProofsResponse {
+                slot: response.context.slot,
+                proofs: response.value.into_iter().map(Into::into).collect(),
+            },
+        ))
+    }
+
+    async fn get_compression_signatures_for_account(
+        &self,
+        request: Request<proto::GetCompressionSignaturesForAccountRequest>,
+    ) -> Result<Response<proto::SignaturesResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressionSignaturesForAccount")?;
+        let request = HashRequest {
+            hash: parse_hash("hash", request.into_inner().hash)?,
+        };
+        let response = self
+            .api
+            .get_compression_signatures_for_account(request)
+            .await?;
+        Ok(Response::new(proto::SignaturesResponse {
+            slot: response.context.slot,
+            signatures: response.value.items.into_iter().map(Into::into).collect(),
+            cursor: None,
+        }))
+    }
+
+    async fn get_compression_signatures_for_address(
+        &self,
+        request: Request<proto::GetCompressionSignaturesForAddressRequest>,
+    ) -> Result<Response<proto::SignaturesResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressionSignaturesForAddress")?;
+        let request = request.into_inner();
+        let request = GetCompressionSignaturesForAddressRequest {
+            address: parse_pubkey("address", request.address)?,
+            cursor: parse_cursor(request.cursor)?,
+            limit: parse_limit(request.limit)?,
+        };
+        let response = self
+            .api
+            .get_compression_signatures_for_address(request)
+            .await?;
+        Ok(Response::new(proto::SignaturesResponse {
+            slot: response.context.slot,
+            signatures: response.value.items.into_iter().map(Into::into).collect(),
+            cursor: response.value.cursor.map(|cursor| cursor.encode()),
+        }))
+    }
+
+    async fn get_compression_signatures_for_owner(
+        &self,
+        request: Request<proto::GetCompressionSignaturesForOwnerRequest>,
+    ) -> Result<Response<proto::SignaturesResponse>, Status> {
+        self.ensure_allowed(&request, "getCompressionSignaturesForOwner")?;
+        let request = request.into_inner();
+        let request = GetCompressionSignaturesForOwnerRequest {
+            owner: parse_pubkey("owner", request.owner)?,
+            cursor: parse_cursor(request.cursor)?,
+            limit: parse_limit(request.limit)?,
+        };
+        let response = self
+            .api
+            .get_compression_signatures_for_owner(request)
+            .await?;
+        Ok(Response::new(proto::SignaturesResponse {
+            slot: response.context.slot,
+            signatures: response.value.items.into_iter().map(Into::into).collect(),
+            cursor: response.value.cursor.map(|cursor| cursor.encode()),
+        }))
+    }
+}
+
+/// Serves the gRPC API on `port` until a shutdown is requested.
+pub async fn run_grpc_server(api: PhotonApi, port: u16) -> Result<(), tonic::transport::Error> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    Server::builder()
+        .trace_fn(|request| info_span!("grpc_request", path = %request.uri().path()))
+        .add_service(PhotonServer::new(PhotonGrpcService::new(api)))
+        .serve_with_shutdown(addr, shutdown_requested())
+        .await
+}
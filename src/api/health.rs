@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+use tower::{Layer, Service};
+
+use super::api::PhotonApi;
+
+pub const LIVEZ_PATH: &str = "/livez";
+pub const READYZ_PATH: &str = "/readyz";
+
+async fn readyz(api: PhotonApi) -> hyper::http::Result<Response<Body>> {
+    let (status, body) = match api.check_ready().await {
+        Ok(slot) => (StatusCode::OK, json!({ "status": "ok", "slot": slot })),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            json!({ "status": "unavailable", "kind": e.kind(), "message": e.to_string() }),
+        ),
+    };
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+}
+
+/// Serves Kubernetes style probes. `GET /livez` only checks that the process serves requests,
+/// so that a node is not restarted while the database or the indexer are behind. `GET /readyz`
+/// fails with status 503 while the node cannot serve current data, see
+/// [`PhotonApi::check_ready`]. All other requests are passed through.
+#[derive(Clone)]
+pub struct HealthLayer {
+    api: PhotonApi,
+}
+
+impl HealthLayer {
+    pub fn new(api: PhotonApi) -> Self {
+        Self { api }
+    }
+}
+
+impl<S> Layer<S> for HealthLayer {
+    type Service = HealthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HealthService {
+            api: self.api.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HealthService<S> {
+    api: PhotonApi,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for HealthService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.method() == Method::GET {
+            match req.uri().path() {
+                LIVEZ_PATH => {
+                    let response = Response::builder()
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(Body::from(json!({ "status": "ok" }).to_string()))
+                        .map_err(Into::into);
+                    return Box::pin(async move { response });
+                }
+                READYZ_PATH => {
+                    let response = readyz(self.api.clone());
+                    return Box::pin(async move { response.await.map_err(Into::into) });
+                }
+                _ => {}
+            }
+        }
+        let future = self.inner.call(req);
+        Box::pin(async move { future.await.map_err(Into::into) })
+    }
+}
@@ -0,0 +1,89 @@
+use async_stream::stream;
+use cadence_macros::statsd_count;
+use futures::Stream;
+use log::warn;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::ToSchema;
+
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::unix_timestamp::UnixTimestamp;
+use crate::ingester::events::{subscribe, IngestionEvent};
+use crate::metric;
+
+use super::super::error::PhotonApiError;
+use super::utils::{AccountIdentifier, CompressedAccountRequest, Context};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum CompressedAccountEventType {
+    Created,
+    Spent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct CompressedAccountEvent {
+    pub event_type: CompressedAccountEventType,
+    pub account: Account,
+    /// Time of the block that created or spent the account, like `blockTime` of the Solana RPC.
+    pub block_time: UnixTimestamp,
+}
+
+// We do not use generics to simplify documentation generation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct CompressedAccountNotification {
+    pub context: Context,
+    pub value: CompressedAccountEvent,
+}
+
+fn matches(id: &AccountIdentifier, account: &Account) -> bool {
+    match id {
+        AccountIdentifier::Address(address) => account.address.as_ref() == Some(address),
+        AccountIdentifier::Hash(hash) => &account.hash == hash,
+    }
+}
+
+/// Streams a notification whenever the ingester commits the creation or the spending of the
+/// account identified by the request.
+pub fn compressed_account_subscribe(
+    request: CompressedAccountRequest,
+) -> Result<impl Stream<Item = CompressedAccountNotification>, PhotonApiError> {
+    let id = request.parse_id()?;
+    let mut events = subscribe();
+    Ok(stream! {
+        loop {
+            let (event_type, account, slot, block_time) = match events.recv().await {
+                Ok(IngestionEvent::AccountCreated {
+                    account,
+                    slot,
+                    block_time,
+                }) => (CompressedAccountEventType::Created, account, slot, block_time),
+                Ok(IngestionEvent::AccountSpent {
+                    account,
+                    slot,
+                    block_time,
+                }) => (CompressedAccountEventType::Spent, account, slot, block_time),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Account subscription lagged behind and skipped {} events", skipped);
+                    metric! {
+                        statsd_count!("account_subscription_lagged", 1);
+                    }
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if matches(&id, &account) {
+                yield CompressedAccountNotification {
+                    context: Context { slot },
+                    value: CompressedAccountEvent {
+                        event_type,
+                        account,
+                        block_time: UnixTimestamp(block_time as u64),
+                    },
+                };
+            }
+        }
+    })
+}
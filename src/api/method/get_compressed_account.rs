@@ -1,15 +1,20 @@
+use crate::common::account_data_deduplication::resolve_account_data;
 use crate::common::typedefs::account::Account;
+use crate::common::unspent_hash_filter::may_be_unspent;
 use crate::dao::generated::accounts;
 
 use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use super::super::cache::{ApiCache, ApiCacheEntries};
 use super::super::error::PhotonApiError;
-use super::utils::{parse_account_model, AccountDataTable, CompressedAccountRequest, Context};
+use super::utils::{
+    parse_account_model, AccountDataTable, AccountIdentifier, CompressedAccountRequest, Context,
+};
 
 // We do not use generics to simply documentation generation.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct AccountResponse {
     pub context: Context,
@@ -19,16 +24,29 @@ pub struct AccountResponse {
 pub async fn get_compressed_account(
     conn: &DatabaseConnection,
     request: CompressedAccountRequest,
+    api_cache: &ApiCache,
 ) -> Result<AccountResponse, PhotonApiError> {
     let context = Context::extract(conn).await?;
     let id = request.parse_id()?;
-    let account_model = accounts::Entity::find()
-        .filter(id.filter(AccountDataTable::Accounts))
-        .one(conn)
+    if let AccountIdentifier::Hash(hash) = &id {
+        if !may_be_unspent(hash) {
+            return Ok(AccountResponse {
+                value: None,
+                context,
+            });
+        }
+    }
+    let account = api_cache
+        .read_through("accounts", ApiCacheEntries::accounts, id.clone(), async {
+            let mut account_model = accounts::Entity::find()
+                .filter(id.filter(AccountDataTable::Accounts))
+                .one(conn)
+                .await?;
+            resolve_account_data(conn, account_model.iter_mut()).await?;
+            account_model.map(parse_account_model).transpose()
+        })
         .await?;
 
-    let account = account_model.map(parse_account_model).transpose()?;
-
     Ok(AccountResponse {
         value: { account },
         context,
@@ -1,4 +1,4 @@
-use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::common::typedefs::u64_string::U64String;
 use crate::dao::generated::accounts;
 use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QuerySelect};
 use sqlx::types::Decimal;
@@ -25,7 +25,7 @@ pub async fn get_compressed_account_balance(
         .unwrap_or(Decimal::from(0));
 
     Ok(AccountBalanceResponse {
-        value: UnsignedInteger(parse_decimal(balance)?),
+        value: U64String(parse_decimal(balance)?),
         context,
     })
 }
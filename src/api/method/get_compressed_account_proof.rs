@@ -2,6 +2,8 @@ use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, Statement, T
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::ingester::persist::batch_size::SqlBatchConfig;
+use crate::ingester::persist::in_memory_tree::InMemoryTrees;
 use crate::ingester::persist::persisted_state_tree::{
     get_multiple_compressed_leaf_proofs, MerkleProofWithContext,
 };
@@ -21,6 +23,8 @@ pub struct GetCompressedAccountProofResponse {
 pub async fn get_compressed_account_proof(
     conn: &DatabaseConnection,
     request: HashRequest,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
 ) -> Result<GetCompressedAccountProofResponse, PhotonApiError> {
     let context = Context::extract(conn).await?;
     let hash = request.hash;
@@ -32,7 +36,7 @@ pub async fn get_compressed_account_proof(
         ))
         .await?;
     }
-    let res = get_multiple_compressed_leaf_proofs(&tx, vec![hash])
+    let res = get_multiple_compressed_leaf_proofs(&tx, batch_config, in_memory_trees, vec![hash])
         .await?
         .into_iter()
         .next()
@@ -1,17 +1,26 @@
 use crate::{
-    common::typedefs::{account::Account, bs58_string::Base58String},
-    dao::generated::accounts,
+    common::account_data_deduplication::resolve_account_data,
+    common::typedefs::{
+        account::Account, bs58_string::Base58String, u64_string::U64String,
+        unsigned_integer::UnsignedInteger,
+    },
+    dao::generated::{accounts, owner_balances},
     ingester::persist::bytes_to_sql_format,
 };
-use sea_orm::{ConnectionTrait, DatabaseConnection, FromQueryResult, Statement};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, FromQueryResult, PaginatorTrait,
+    QueryFilter, Statement,
+};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use super::{
     super::error::PhotonApiError,
-    utils::{Context, Limit, PAGE_LIMIT},
+    utils::{parse_decimal, Context, Limit, PAGE_LIMIT},
+};
+use crate::common::typedefs::{
+    cursor::Cursor, hash::Hash, serializable_pubkey::SerializablePubkey,
 };
-use crate::common::typedefs::{hash::Hash, serializable_pubkey::SerializablePubkey};
 
 use super::utils::parse_account_model;
 
@@ -22,7 +31,7 @@ const MAX_CHILD_ACCOUNTS_WITH_FILTERS: usize = 1_000_000;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Options {
-    pub cursor: Option<Hash>,
+    pub cursor: Option<Cursor>,
     pub limit: Option<Limit>,
 }
 
@@ -69,29 +78,53 @@ pub struct GetCompressedAccountsByOwnerRequest {
     pub filters: Vec<FilterSelector>,
     #[serde(default)]
     pub dataSlice: Option<DataSlice>,
+    /// Only return accounts with this discriminator. Unlike a memcmp filter, this does not need to
+    /// read the account data.
     #[serde(default)]
-    pub cursor: Option<Hash>,
+    pub discriminator: Option<UnsignedInteger>,
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
+    /// Max number of accounts to return. Nodes can serve smaller pages, so clients should follow
+    /// the cursor until none is returned.
     #[serde(default)]
     pub limit: Option<Limit>,
+    /// Also return the number and total lamports of all accounts of the owner.
+    #[serde(default)]
+    pub with_summary: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, Default)]
+/// Totals over all unspent accounts of an owner, independent of filters and pagination.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnerAccountsSummary {
+    pub total_items: UnsignedInteger,
+    pub total_lamports: U64String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct PaginatedAccountList {
     pub items: Vec<Account>,
-    pub cursor: Option<Hash>,
+    pub cursor: Option<Cursor>,
+    /// Only returned if requested with `withSummary`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<OwnerAccountsSummary>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct GetCompressedAccountsByOwnerResponse {
     pub context: Context,
     pub value: PaginatedAccountList,
 }
 
+/// Lists the unspent accounts of an owner in the order of their hashes. Pages hold at most
+/// `max_page_size` accounts, also if the request asks for more, and come with a cursor to the next
+/// page unless they are the last one.
 pub async fn get_compressed_accounts_by_owner(
     conn: &DatabaseConnection,
     request: GetCompressedAccountsByOwnerRequest,
+    max_page_size: u64,
 ) -> Result<GetCompressedAccountsByOwnerResponse, PhotonApiError> {
     let context = Context::extract(conn).await?;
     let GetCompressedAccountsByOwnerRequest {
@@ -100,6 +133,8 @@ pub async fn get_compressed_accounts_by_owner(
         limit,
         filters,
         dataSlice,
+        discriminator,
+        with_summary,
     } = request;
 
     if filters.len() > MAX_FILTERS {
@@ -153,10 +188,15 @@ pub async fn get_compressed_accounts_by_owner(
     let mut filters_strings = vec![];
     filters_strings.push(format!("owner = {owner_string}"));
     filters_strings.push("spent = false".to_string());
+    if let Some(discriminator) = discriminator {
+        filters_strings.push(format!("discriminator = {}", discriminator.0));
+    }
 
+    let mut memcmps = vec![];
     for filter_selector in filters {
         match filter_selector.into_filter_instance()? {
             FilterInstance::Memcmp(memcmp) => {
+                memcmps.push(memcmp.clone());
                 let Memcmp { offset, bytes } = memcmp;
                 let one_based_offset = offset + 1;
                 let bytes = bytes.0;
@@ -175,36 +215,49 @@ pub async fn get_compressed_accounts_by_owner(
                         panic!("Unsupported database backend");
                     }
                 };
-                filters_strings.push(filter_string);
+                // Compressed and deduplicated data cannot be matched in SQL, so it is matched after
+                // it is loaded.
+                filters_strings.push(format!(
+                    "(data_compressed OR data_ref IS NOT NULL OR {filter_string})"
+                ));
             }
         }
     }
 
     if let Some(cursor) = cursor {
-        let cursor_string = bytes_to_sql_format(conn.get_database_backend(), cursor.into());
+        let hash = Hash::try_from(cursor.0).map_err(|_| {
+            PhotonApiError::ValidationError("Invalid cursor. Expected an account hash.".to_string())
+        })?;
+        let cursor_string = bytes_to_sql_format(conn.get_database_backend(), hash.into());
         filters_strings.push(format!("hash > {cursor_string}"));
     }
 
-    let mut query_limit = PAGE_LIMIT;
-    if let Some(limit) = limit {
-        query_limit = limit.value();
-    }
+    let query_limit = limit
+        .map(|limit| limit.value())
+        .unwrap_or(PAGE_LIMIT)
+        .min(max_page_size);
 
     let filters = &filters_strings.join(" AND ");
 
+    // Compressed data cannot be sliced in SQL, so it is sliced after decompression. Deduplicated
+    // data is NULL here and is sliced after it is loaded.
     let data_column = dataSlice
+        .as_ref()
         .map(|slice| {
             let DataSlice { offset, length } = slice;
             let one_based_offset = offset + 1;
             match conn.get_database_backend() {
                 sea_orm::DatabaseBackend::Postgres => {
                     format!(
-                        "SUBSTRING(data FROM {} FOR {}) AS data",
+                        "CASE WHEN data_compressed THEN data ELSE SUBSTRING(data FROM {} FOR {}) END AS data",
                         one_based_offset, length
                     )
                 }
                 sea_orm::DatabaseBackend::Sqlite => {
-                    format!("SUBSTR(data, {}, {}) AS data", one_based_offset, length)
+                    format!(
+                        "CASE WHEN data_compressed THEN data ELSE SUBSTR(data, {}, {}) END AS data",
+                        one_based_offset, length
+                    )
                 }
                 _ => {
                     panic!("Unsupported database backend");
@@ -228,7 +281,12 @@ pub async fn get_compressed_accounts_by_owner(
             spent,
             prev_spent,
             lamports,
-            discriminator
+            discriminator,
+            data_compressed,
+            data_ref,
+            tx_signature,
+            spent_tx_signature,
+            slot_spent
         FROM accounts
         WHERE {filters}
         ORDER BY accounts.hash ASC
@@ -236,25 +294,91 @@ pub async fn get_compressed_accounts_by_owner(
     "
     );
 
-    let result: Vec<accounts::Model> = accounts::Model::find_by_statement(Statement::from_string(
-        conn.get_database_backend(),
-        raw_sql,
-    ))
+    let mut result: Vec<accounts::Model> = accounts::Model::find_by_statement(
+        Statement::from_string(conn.get_database_backend(), raw_sql),
+    )
     .all(conn)
     .await?;
 
-    let items = result
-        .into_iter()
-        .map(parse_account_model)
-        .collect::<Result<Vec<Account>, PhotonApiError>>()?;
-
-    let mut cursor = items.last().map(|u| u.hash.clone());
-    if items.len() < query_limit as usize {
+    // The cursor is based on the rows returned by the database, since rows whose data is stored
+    // outside of SQL are filtered below, which can shorten a page that is not the last one.
+    let mut cursor = result.last().map(|model| Cursor(model.hash.clone()));
+    if result.len() < query_limit as usize {
         cursor = None;
     }
 
+    let stored_outside_sql = result
+        .iter()
+        .map(|model| model.data_compressed || model.data_ref.is_some())
+        .collect::<Vec<_>>();
+    resolve_account_data(conn, result.iter_mut()).await?;
+
+    let mut items = vec![];
+    for (model, stored_outside_sql) in result.into_iter().zip(stored_outside_sql) {
+        let mut account = parse_account_model(model)?;
+        if stored_outside_sql {
+            let data = account.data.as_mut().map(|data| &mut data.data.0);
+            if !memcmps
+                .iter()
+                .all(|memcmp| memcmp_matches(memcmp, data.as_deref()))
+            {
+                continue;
+            }
+            if let (Some(data), Some(slice)) = (data, dataSlice.as_ref()) {
+                *data = slice_data(data, slice);
+            }
+        }
+        items.push(account);
+    }
+
+    let summary = match with_summary {
+        true => Some(fetch_owner_accounts_summary(conn, owner).await?),
+        false => None,
+    };
+
     Ok(GetCompressedAccountsByOwnerResponse {
         context,
-        value: PaginatedAccountList { items, cursor },
+        value: PaginatedAccountList {
+            items,
+            cursor,
+            summary,
+        },
     })
 }
+
+async fn fetch_owner_accounts_summary(
+    conn: &DatabaseConnection,
+    owner: SerializablePubkey,
+) -> Result<OwnerAccountsSummary, PhotonApiError> {
+    // The balance is maintained during ingestion and the count is served from the
+    // (spent, owner, hash) index, so neither reads the accounts themselves.
+    let total_lamports = owner_balances::Entity::find_by_id(owner.to_bytes_vec())
+        .one(conn)
+        .await?
+        .map(|balance| parse_decimal(balance.lamports))
+        .transpose()?
+        .unwrap_or_default();
+    let total_items = accounts::Entity::find()
+        .filter(accounts::Column::Spent.eq(false))
+        .filter(accounts::Column::Owner.eq(owner.to_bytes_vec()))
+        .count(conn)
+        .await?;
+    Ok(OwnerAccountsSummary {
+        total_items: UnsignedInteger(total_items),
+        total_lamports: U64String(total_lamports),
+    })
+}
+
+fn memcmp_matches(memcmp: &Memcmp, data: Option<&Vec<u8>>) -> bool {
+    let bytes = &memcmp.bytes.0;
+    data.and_then(|data| data.get(memcmp.offset..memcmp.offset + bytes.len()))
+        .is_some_and(|window| window == bytes.as_slice())
+}
+
+// Mirrors the semantics of SUBSTRING, which returns the available bytes if the slice extends past
+// the end of the data.
+fn slice_data(data: &[u8], slice: &DataSlice) -> Vec<u8> {
+    let start = slice.offset.min(data.len());
+    let end = slice.offset.saturating_add(slice.length).min(data.len());
+    data[start..end].to_vec()
+}
@@ -0,0 +1,79 @@
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::account_data_deduplication::resolve_account_data;
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::dao::generated::{accounts, indexed_trees};
+
+use super::super::error::PhotonApiError;
+use super::utils::{parse_account_model, AccountDataTable, AccountIdentifier, Context};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetCompressedAddressInfoRequest {
+    pub address: SerializablePubkey,
+}
+
+/// Leaf of an address in its address tree, together with the account that holds the address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressedAddressInfo {
+    pub address: SerializablePubkey,
+    pub tree: SerializablePubkey,
+    pub leaf_index: UnsignedInteger,
+    /// Sequence number of the last update of the leaf.
+    pub seq: UnsignedInteger,
+    /// Unspent account at the address. Addresses stay in their tree after the account that
+    /// created them is closed, in which case this is `null`.
+    pub account: Option<Account>,
+}
+
+// We do not use generics to simplify documentation generation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetCompressedAddressInfoResponse {
+    pub context: Context,
+    /// `null` if the address has not been inserted into an address tree.
+    pub value: Option<CompressedAddressInfo>,
+}
+
+pub async fn get_compressed_address_info(
+    conn: &DatabaseConnection,
+    request: GetCompressedAddressInfoRequest,
+) -> Result<GetCompressedAddressInfoResponse, PhotonApiError> {
+    let context = Context::extract(conn).await?;
+    let GetCompressedAddressInfoRequest { address } = request;
+
+    // Values are unique across address trees, so the address identifies its leaf.
+    let Some(leaf) = indexed_trees::Entity::find()
+        .filter(indexed_trees::Column::Value.eq(address.to_bytes_vec()))
+        .one(conn)
+        .await?
+    else {
+        return Ok(GetCompressedAddressInfoResponse {
+            context,
+            value: None,
+        });
+    };
+
+    let mut account_model = accounts::Entity::find()
+        .filter(AccountIdentifier::Address(address).filter(AccountDataTable::Accounts))
+        .one(conn)
+        .await?;
+    resolve_account_data(conn, account_model.iter_mut()).await?;
+    let account = account_model.map(parse_account_model).transpose()?;
+
+    Ok(GetCompressedAddressInfoResponse {
+        context,
+        value: Some(CompressedAddressInfo {
+            address,
+            tree: SerializablePubkey::try_from(leaf.tree)?,
+            leaf_index: UnsignedInteger(leaf.leaf_index as u64),
+            seq: UnsignedInteger(leaf.seq as u64),
+            account,
+        }),
+    })
+}
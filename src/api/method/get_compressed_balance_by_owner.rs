@@ -1,5 +1,5 @@
 use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
-use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::common::typedefs::u64_string::U64String;
 use crate::dao::generated::owner_balances;
 use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect};
 use serde::{Deserialize, Serialize};
@@ -36,7 +36,7 @@ pub async fn get_compressed_balance_by_owner(
     let total_balance = balances.iter().sum::<u64>();
 
     Ok(AccountBalanceResponse {
-        value: UnsignedInteger(total_balance),
+        value: U64String(total_balance),
         context,
     })
 }
@@ -3,9 +3,9 @@ use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOr
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::common::typedefs::bs58_string::Base58String;
+use crate::common::typedefs::cursor::Cursor;
 use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
-use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::common::typedefs::u64_string::U64String;
 use crate::dao::generated::token_owner_balances;
 
 use super::super::error::PhotonApiError;
@@ -14,13 +14,13 @@ use super::utils::{parse_decimal, Context, Limit, PAGE_LIMIT};
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct OwnerBalance {
     pub owner: SerializablePubkey,
-    pub balance: UnsignedInteger,
+    pub balance: U64String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct OwnerBalanceList {
     pub items: Vec<OwnerBalance>,
-    pub cursor: Option<Base58String>,
+    pub cursor: Option<Cursor>,
 }
 
 // We do not use generics to simplify documentation generation.
@@ -35,7 +35,7 @@ pub struct OwnerBalancesResponse {
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct GetCompressedMintTokenHoldersRequest {
     pub mint: SerializablePubkey,
-    pub cursor: Option<Base58String>,
+    pub cursor: Option<Cursor>,
     pub limit: Option<Limit>,
 }
 
@@ -87,13 +87,13 @@ pub async fn get_compressed_mint_token_holders(
         .map(|token_owner_balance| {
             Ok(OwnerBalance {
                 owner: token_owner_balance.owner.try_into()?,
-                balance: UnsignedInteger(parse_decimal(token_owner_balance.amount)?),
+                balance: U64String(parse_decimal(token_owner_balance.amount)?),
             })
         })
         .collect::<Result<Vec<OwnerBalance>, PhotonApiError>>()?;
 
     let mut cursor = items.last().map(|item| {
-        Base58String({
+        Cursor({
             let item = item.clone();
             let mut bytes: Vec<u8> = Vec::new();
             bytes.extend_from_slice(&item.balance.0.to_le_bytes());
@@ -1,4 +1,4 @@
-use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::common::typedefs::u64_string::U64String;
 use crate::dao::generated::token_accounts;
 use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QuerySelect};
 use serde::{Deserialize, Serialize};
@@ -6,8 +6,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::types::Decimal;
 use utoipa::ToSchema;
 
+use super::super::cache::{ApiCache, ApiCacheEntries};
 use super::super::error::PhotonApiError;
-use super::utils::{parse_decimal, AccountDataTable};
+use super::utils::{parse_decimal, AccountDataTable, AccountIdentifier};
 use super::utils::{BalanceModel, CompressedAccountRequest, Context};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
@@ -15,7 +16,7 @@ use super::utils::{BalanceModel, CompressedAccountRequest, Context};
 // This is a struct because in the future we might add other fields here like decimals or uiAmount,
 // which is a string representation with decimals in the form of "10.00"
 pub struct TokenAccountBalance {
-    pub amount: UnsignedInteger,
+    pub amount: U64String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
@@ -29,23 +30,39 @@ pub struct GetCompressedTokenAccountBalanceResponse {
 pub async fn get_compressed_token_account_balance(
     conn: &DatabaseConnection,
     request: CompressedAccountRequest,
+    api_cache: &ApiCache,
 ) -> Result<GetCompressedTokenAccountBalanceResponse, PhotonApiError> {
     let context = Context::extract(conn).await?;
     let id = request.parse_id()?;
-    let balance = token_accounts::Entity::find()
-        .select_only()
-        .column(token_accounts::Column::Amount)
-        .filter(id.filter(AccountDataTable::TokenAccounts))
-        .into_model::<BalanceModel>()
-        .one(conn)
-        .await?
-        .map(|x| x.amount)
-        .unwrap_or(Decimal::from(0));
+    let fetch = async {
+        let balance = token_accounts::Entity::find()
+            .select_only()
+            .column(token_accounts::Column::Amount)
+            .filter(id.filter(AccountDataTable::TokenAccounts))
+            .into_model::<BalanceModel>()
+            .one(conn)
+            .await?
+            .map(|x| x.amount)
+            .unwrap_or(Decimal::from(0));
+        Ok(U64String(parse_decimal(balance)?))
+    };
+    let amount = match &id {
+        AccountIdentifier::Hash(hash) => {
+            api_cache
+                .read_through(
+                    "token_account_balances",
+                    ApiCacheEntries::token_account_balances,
+                    hash.clone(),
+                    fetch,
+                )
+                .await?
+        }
+        // Addresses resolve to the token accounts of an owner, which are not cached.
+        AccountIdentifier::Address(_) => fetch.await?,
+    };
 
     Ok(GetCompressedTokenAccountBalanceResponse {
-        value: TokenAccountBalance {
-            amount: UnsignedInteger(parse_decimal(balance)?),
-        },
+        value: TokenAccountBalance { amount },
         context,
     })
 }
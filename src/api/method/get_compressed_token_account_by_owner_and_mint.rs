@@ -0,0 +1,80 @@
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::typedefs::cursor::Cursor;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::common::typedefs::u64_string::U64String;
+use crate::dao::generated::token_owner_balances;
+
+use super::super::error::PhotonApiError;
+use super::utils::{
+    fetch_token_accounts, parse_decimal, Authority, Context,
+    GetCompressedTokenAccountsByAuthorityOptions, Limit, TokenAcccount,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetCompressedTokenAccountByOwnerAndMintRequest {
+    pub owner: SerializablePubkey,
+    pub mint: SerializablePubkey,
+    pub cursor: Option<Cursor>,
+    pub limit: Option<Limit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenAccountListWithTotal {
+    pub items: Vec<TokenAcccount>,
+    pub cursor: Option<Cursor>,
+    /// Total amount held by the owner across all of its token accounts of the mint.
+    pub total: U64String,
+}
+
+// We do not use generics to simplify documentation generation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct TokenAccountListWithTotalResponse {
+    pub context: Context,
+    pub value: TokenAccountListWithTotal,
+}
+
+pub async fn get_compressed_token_account_by_owner_and_mint(
+    conn: &DatabaseConnection,
+    request: GetCompressedTokenAccountByOwnerAndMintRequest,
+) -> Result<TokenAccountListWithTotalResponse, PhotonApiError> {
+    let GetCompressedTokenAccountByOwnerAndMintRequest {
+        owner,
+        mint,
+        cursor,
+        limit,
+    } = request;
+    let options = GetCompressedTokenAccountsByAuthorityOptions {
+        mint: Some(mint),
+        frozen: None,
+        delegated: None,
+        cursor,
+        limit,
+        sort_by: None,
+        sort_direction: None,
+    };
+    let accounts = fetch_token_accounts(conn, Authority::Owner(owner), options).await?;
+
+    // The total is maintained incrementally during ingestion, so we read it with a primary key
+    // lookup instead of summing over every token account of the owner.
+    let total = token_owner_balances::Entity::find_by_id((owner.into(), mint.into()))
+        .one(conn)
+        .await?
+        .map(|balance| parse_decimal(balance.amount))
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(TokenAccountListWithTotalResponse {
+        context: accounts.context,
+        value: TokenAccountListWithTotal {
+            items: accounts.value.items,
+            cursor: accounts.value.cursor,
+            total: U64String(total),
+        },
+    })
+}
@@ -15,13 +15,20 @@ pub async fn get_compressed_account_token_accounts_by_delegate(
     let GetCompressedTokenAccountsByDelegate {
         delegate,
         mint,
+        frozen,
         cursor,
         limit,
+        sort_by,
+        sort_direction,
     } = request;
     let options = GetCompressedTokenAccountsByAuthorityOptions {
         mint,
+        frozen,
+        delegated: None,
         cursor,
         limit,
+        sort_by,
+        sort_direction,
     };
     fetch_token_accounts(conn, Authority::Delegate(delegate), options).await
 }
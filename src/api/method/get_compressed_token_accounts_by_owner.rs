@@ -13,13 +13,21 @@ pub async fn get_compressed_token_accounts_by_owner(
     let GetCompressedTokenAccountsByOwner {
         owner,
         mint,
+        frozen,
+        delegated,
         cursor,
         limit,
+        sort_by,
+        sort_direction,
     } = request;
     let options = GetCompressedTokenAccountsByAuthorityOptions {
         mint,
+        frozen,
+        delegated,
         cursor,
         limit,
+        sort_by,
+        sort_direction,
     };
     fetch_token_accounts(conn, Authority::Owner(owner), options).await
 }
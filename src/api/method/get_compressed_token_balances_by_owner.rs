@@ -2,24 +2,25 @@ use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOr
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::common::typedefs::bs58_string::Base58String;
+use crate::common::typedefs::cursor::Cursor;
 use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
-use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::common::typedefs::u64_string::U64String;
 use crate::dao::generated::token_owner_balances;
 
+use super::super::cache::{ApiCache, ApiCacheEntries, TokenBalancesKey};
 use super::super::error::PhotonApiError;
 use super::utils::{parse_decimal, Context, Limit, PAGE_LIMIT};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct TokenBalance {
     pub mint: SerializablePubkey,
-    pub balance: UnsignedInteger,
+    pub balance: U64String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct TokenBalanceList {
     pub token_balances: Vec<TokenBalance>,
-    pub cursor: Option<Base58String>,
+    pub cursor: Option<Cursor>,
 }
 
 // We do not use generics to simplify documentation generation.
@@ -35,15 +36,37 @@ pub struct TokenBalancesResponse {
 pub struct GetCompressedTokenBalancesByOwnerRequest {
     pub owner: SerializablePubkey,
     pub mint: Option<SerializablePubkey>,
-    pub cursor: Option<Base58String>,
+    pub cursor: Option<Cursor>,
     pub limit: Option<Limit>,
 }
 
 pub async fn get_compressed_token_balances_by_owner(
     conn: &DatabaseConnection,
     request: GetCompressedTokenBalancesByOwnerRequest,
+    api_cache: &ApiCache,
 ) -> Result<TokenBalancesResponse, PhotonApiError> {
     let context = Context::extract(conn).await?;
+    let key = TokenBalancesKey {
+        owner: request.owner,
+        mint: request.mint,
+        cursor: request.cursor.as_ref().map(|cursor| cursor.0.clone()),
+        limit: request.limit.as_ref().map(|limit| limit.value()),
+    };
+    let value = api_cache
+        .read_through(
+            "token_balances",
+            ApiCacheEntries::token_balances,
+            key,
+            fetch_token_balances(conn, request),
+        )
+        .await?;
+    Ok(TokenBalancesResponse { value, context })
+}
+
+async fn fetch_token_balances(
+    conn: &DatabaseConnection,
+    request: GetCompressedTokenBalancesByOwnerRequest,
+) -> Result<TokenBalanceList, PhotonApiError> {
     let GetCompressedTokenBalancesByOwnerRequest {
         owner,
         mint,
@@ -80,13 +103,13 @@ pub async fn get_compressed_token_balances_by_owner(
         .map(|token_owner_balance| {
             Ok(TokenBalance {
                 mint: token_owner_balance.mint.try_into()?,
-                balance: UnsignedInteger(parse_decimal(token_owner_balance.amount)?),
+                balance: U64String(parse_decimal(token_owner_balance.amount)?),
             })
         })
         .collect::<Result<Vec<TokenBalance>, PhotonApiError>>()?;
 
     let mut cursor = items.last().map(|item| {
-        Base58String({
+        Cursor({
             let item = item.clone();
             let bytes: Vec<u8> = item.mint.into();
             bytes
@@ -96,12 +119,9 @@ pub async fn get_compressed_token_balances_by_owner(
         cursor = None;
     }
 
-    Ok(TokenBalancesResponse {
-        value: TokenBalanceList {
-            token_balances: items,
-            cursor,
-        },
-        context,
+    Ok(TokenBalanceList {
+        token_balances: items,
+        cursor,
     })
 }
 
@@ -116,14 +136,15 @@ pub struct TokenBalancesResponseV2 {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct TokenBalanceListV2 {
     pub items: Vec<TokenBalance>,
-    pub cursor: Option<Base58String>,
+    pub cursor: Option<Cursor>,
 }
 
 pub async fn get_compressed_token_balances_by_owner_v2(
     conn: &DatabaseConnection,
     request: GetCompressedTokenBalancesByOwnerRequest,
+    api_cache: &ApiCache,
 ) -> Result<TokenBalancesResponseV2, PhotonApiError> {
-    let response = get_compressed_token_balances_by_owner(conn, request).await?;
+    let response = get_compressed_token_balances_by_owner(conn, request, api_cache).await?;
     let context = response.context;
     let token_balance_list = response.value;
     let token_balances = token_balance_list.token_balances;
@@ -9,6 +9,7 @@ use super::{
         SignatureSearchType,
     },
 };
+use crate::common::typedefs::cursor::Cursor;
 use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
@@ -18,7 +19,7 @@ pub struct GetCompressionSignaturesForOwnerRequest {
     #[serde(default)]
     pub limit: Option<Limit>,
     #[serde(default)]
-    pub cursor: Option<String>,
+    pub cursor: Option<Cursor>,
 }
 
 pub async fn get_compression_signatures_for_owner(
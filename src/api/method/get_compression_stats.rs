@@ -0,0 +1,105 @@
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter,
+    QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Decimal;
+use utoipa::ToSchema;
+
+use crate::common::metadata::get_indexing_start_slot;
+use crate::common::typedefs::u64_string::U64String;
+use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::dao::generated::transactions;
+
+use super::super::error::PhotonApiError;
+use super::utils::{parse_decimal, Context};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetCompressionStatsRequest {
+    /// First slot to include. Defaults to the first indexed slot. Slots before it are rejected with
+    /// an error of kind `slot_not_indexed`.
+    #[serde(default)]
+    pub start_slot: Option<UnsignedInteger>,
+    /// Last slot to include. Defaults to the last indexed slot.
+    #[serde(default)]
+    pub end_slot: Option<UnsignedInteger>,
+}
+
+/// Lamports moved between uncompressed and compressed accounts. The difference of the two totals
+/// is the change in the lamports held in compressed accounts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionStats {
+    pub compressed_lamports: U64String,
+    pub decompressed_lamports: U64String,
+}
+
+// We do not use generics to simplify documentation generation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetCompressionStatsResponse {
+    pub context: Context,
+    pub value: CompressionStats,
+}
+
+#[derive(FromQueryResult)]
+struct LamportFlowsModel {
+    // Sums over no rows are NULL.
+    compressed_lamports: Option<Decimal>,
+    decompressed_lamports: Option<Decimal>,
+}
+
+pub async fn get_compression_stats(
+    conn: &DatabaseConnection,
+    request: GetCompressionStatsRequest,
+) -> Result<GetCompressionStatsResponse, PhotonApiError> {
+    let context = Context::extract(conn).await?;
+    let GetCompressionStatsRequest {
+        start_slot,
+        end_slot,
+    } = request;
+    if let Some(slot) = start_slot.or(end_slot) {
+        if let Some(start) = get_indexing_start_slot(conn).await? {
+            if slot.0 < start {
+                return Err(PhotonApiError::SlotNotIndexed {
+                    slot: slot.0,
+                    start_slot: start,
+                });
+            }
+        }
+    }
+
+    let mut query = transactions::Entity::find()
+        .select_only()
+        .column_as(
+            Expr::col(transactions::Column::CompressedLamports).sum(),
+            "compressed_lamports",
+        )
+        .column_as(
+            Expr::col(transactions::Column::DecompressedLamports).sum(),
+            "decompressed_lamports",
+        )
+        .filter(transactions::Column::UsesCompression.eq(true));
+    if let Some(start_slot) = start_slot {
+        query = query.filter(transactions::Column::Slot.gte(start_slot.0 as i64));
+    }
+    if let Some(end_slot) = end_slot {
+        query = query.filter(transactions::Column::Slot.lte(end_slot.0 as i64));
+    }
+    let flows = query
+        .into_model::<LamportFlowsModel>()
+        .one(conn)
+        .await?
+        .ok_or_else(|| PhotonApiError::UnexpectedError("Sum query returned no rows".to_string()))?;
+    let parse_sum =
+        |sum: Option<Decimal>| sum.map(parse_decimal).transpose().map(|x| x.unwrap_or(0));
+
+    Ok(GetCompressionStatsResponse {
+        context,
+        value: CompressionStats {
+            compressed_lamports: U64String(parse_sum(flows.compressed_lamports)?),
+            decompressed_lamports: U64String(parse_sum(flows.decompressed_lamports)?),
+        },
+    })
+}
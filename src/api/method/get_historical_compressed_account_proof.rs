@@ -0,0 +1,45 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, Statement, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::typedefs::hash::Hash;
+use crate::ingester::persist::batch_size::SqlBatchConfig;
+use crate::ingester::persist::node_history::get_historical_compressed_leaf_proof;
+
+use super::{
+    super::error::PhotonApiError, get_compressed_account_proof::GetCompressedAccountProofResponse,
+    utils::Context,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetHistoricalCompressedAccountProofRequest {
+    pub hash: Hash,
+    /// Sequence number of the root to prove against, e.g. of a root that is still in the root
+    /// history of the tree on chain.
+    pub root_seq: u64,
+}
+
+pub async fn get_historical_compressed_account_proof(
+    conn: &DatabaseConnection,
+    request: GetHistoricalCompressedAccountProofRequest,
+    batch_config: SqlBatchConfig,
+) -> Result<GetCompressedAccountProofResponse, PhotonApiError> {
+    let context = Context::extract(conn).await?;
+    let tx = conn.begin().await?;
+    if tx.get_database_backend() == DatabaseBackend::Postgres {
+        tx.execute(Statement::from_string(
+            tx.get_database_backend(),
+            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ;".to_string(),
+        ))
+        .await?;
+    }
+    let proof =
+        get_historical_compressed_leaf_proof(&tx, batch_config, request.hash, request.root_seq)
+            .await;
+    tx.commit().await?;
+    Ok(GetCompressedAccountProofResponse {
+        context,
+        value: proof?,
+    })
+}
@@ -2,6 +2,9 @@ use sea_orm::DatabaseConnection;
 
 use solana_client::nonblocking::rpc_client::RpcClient;
 
+use crate::ingester::gaps::missing_slot_count;
+use crate::monitor::root_consistency::root_mismatch_count;
+
 use super::super::error::PhotonApiError;
 use super::utils::Context;
 
@@ -23,5 +26,15 @@ pub async fn get_indexer_health(
     if slots_behind > HEALTH_CHECK_SLOT_DISTANCE {
         return Err(PhotonApiError::StaleSlot(slots_behind as u64));
     }
+    // Slots with a confirmed block that were not indexed and have not been refetched yet.
+    let missing_slots = missing_slot_count();
+    if missing_slots > 0 {
+        return Err(PhotonApiError::MissingSlots(missing_slots));
+    }
+    // Trees whose persisted state diverged from the chain in the last root consistency check.
+    let root_mismatches = root_mismatch_count();
+    if root_mismatches > 0 {
+        return Err(PhotonApiError::RootMismatch(root_mismatches));
+    }
     Ok("ok".to_string())
 }
@@ -0,0 +1,15 @@
+use std::collections::BTreeMap;
+
+use sea_orm::DatabaseConnection;
+
+use crate::common::metadata::fetch_all_metadata;
+
+use super::super::error::PhotonApiError;
+
+/// Returns the contents of the `metadata` table, so that operators can inspect the state of an
+/// instance without access to its database.
+pub async fn get_indexer_metadata(
+    conn: &DatabaseConnection,
+) -> Result<BTreeMap<String, String>, PhotonApiError> {
+    Ok(fetch_all_metadata(conn).await?.into_iter().collect())
+}
@@ -1,8 +1,10 @@
+use crate::ingester::persist::batch_size::SqlBatchConfig;
+use crate::ingester::persist::in_memory_tree::InMemoryTrees;
 use crate::ingester::persist::persisted_state_tree::{
-    get_multiple_compressed_leaf_proofs, MerkleProofWithContext,
+    get_multiple_compressed_leaf_proofs_by_tree, MerkleProofWithContext,
 };
 
-use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, Statement, TransactionTrait};
+use sea_orm::DatabaseConnection;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -26,6 +28,8 @@ pub struct HashList(pub Vec<Hash>);
 pub async fn get_multiple_compressed_account_proofs(
     conn: &DatabaseConnection,
     request: HashList,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
 ) -> Result<GetMultipleCompressedAccountProofsResponse, PhotonApiError> {
     let request = request.0;
     if request.len() > PAGE_LIMIT as usize {
@@ -36,16 +40,9 @@ pub async fn get_multiple_compressed_account_proofs(
         )));
     }
     let context = Context::extract(conn).await?;
-    let tx = conn.begin().await?;
-    if tx.get_database_backend() == DatabaseBackend::Postgres {
-        tx.execute(Statement::from_string(
-            tx.get_database_backend(),
-            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ;".to_string(),
-        ))
-        .await?;
-    }
-    let proofs = get_multiple_compressed_leaf_proofs(&tx, request).await?;
-    tx.commit().await?;
+    let proofs =
+        get_multiple_compressed_leaf_proofs_by_tree(conn, batch_config, in_memory_trees, request)
+            .await?;
     Ok(GetMultipleCompressedAccountProofsResponse {
         value: proofs,
         context,
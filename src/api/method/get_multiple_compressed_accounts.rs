@@ -14,6 +14,8 @@ use super::{
 };
 use crate::common::typedefs::hash::Hash;
 use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::common::account_data_deduplication::resolve_account_data;
+use crate::common::unspent_hash_filter::may_be_unspent;
 
 use super::utils::parse_account_model;
 
@@ -47,13 +49,13 @@ impl GetMultipleCompressedAccountsRequest {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct AccountList {
     pub items: Vec<Option<Account>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 // We do not use generics in order to simplify documentation generation
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct GetMultipleCompressedAccountsResponse {
@@ -66,15 +68,28 @@ pub async fn fetch_accounts_from_hashes(
     hashes: Vec<Hash>,
     spent: bool,
 ) -> Result<Vec<Option<accounts::Model>>, PhotonApiError> {
+    // The hash filter only tracks unspent accounts, so it can only rule out lookups of those.
+    let candidate_hashes: Vec<Vec<u8>> = hashes
+        .iter()
+        .filter(|hash| spent || may_be_unspent(hash))
+        .map(|hash| hash.to_vec())
+        .collect();
     let raw_hashes: Vec<Vec<u8>> = hashes.into_iter().map(|hash| hash.to_vec()).collect();
 
-    let accounts = accounts::Entity::find()
-        .filter(
-            accounts::Column::Hash
-                .is_in(raw_hashes.clone())
-                .and(accounts::Column::Spent.eq(spent)),
-        )
-        .all(conn)
+    let mut accounts = if candidate_hashes.is_empty() {
+        Vec::new()
+    } else {
+        accounts::Entity::find()
+            .filter(
+                accounts::Column::Hash
+                    .is_in(candidate_hashes)
+                    .and(accounts::Column::Spent.eq(spent)),
+            )
+            .all(conn)
+            .await
+            .map_err(|e| PhotonApiError::UnexpectedError(format!("DB error: {}", e)))?
+    };
+    resolve_account_data(conn, accounts.iter_mut())
         .await
         .map_err(|e| PhotonApiError::UnexpectedError(format!("DB error: {}", e)))?;
 
@@ -94,7 +109,7 @@ async fn fetch_account_from_addresses(
     addresses: Vec<SerializablePubkey>,
 ) -> Result<Vec<Option<accounts::Model>>, PhotonApiError> {
     let raw_addresses: Vec<Vec<u8>> = addresses.into_iter().map(|addr| addr.into()).collect();
-    let accounts = accounts::Entity::find()
+    let mut accounts = accounts::Entity::find()
         .filter(
             accounts::Column::Address
                 .is_in(raw_addresses.clone())
@@ -103,6 +118,9 @@ async fn fetch_account_from_addresses(
         .all(conn)
         .await
         .map_err(|e| PhotonApiError::UnexpectedError(format!("DB error: {}", e)))?;
+    resolve_account_data(conn, accounts.iter_mut())
+        .await
+        .map_err(|e| PhotonApiError::UnexpectedError(format!("DB error: {}", e)))?;
     let address_to_account: HashMap<Option<Vec<u8>>, accounts::Model> = accounts
         .into_iter()
         .map(|account| (account.address.clone(), account))
@@ -10,6 +10,7 @@ use utoipa::ToSchema;
 use crate::api::error::PhotonApiError;
 use crate::common::typedefs::hash::Hash;
 use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::ingester::persist::batch_size::SqlBatchConfig;
 use crate::ingester::persist::persisted_indexed_merkle_tree::get_exclusion_range_with_proof;
 
 pub const ADDRESS_TREE_HEIGHT: u32 = 27;
@@ -50,6 +51,7 @@ pub struct GetMultipleNewAddressProofsResponse {
 
 pub async fn get_multiple_new_address_proofs_helper(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     addresses: Vec<AddressWithTree>,
 ) -> Result<Vec<MerkleContextWithNewAddressProof>, PhotonApiError> {
     if addresses.is_empty() {
@@ -74,6 +76,7 @@ pub async fn get_multiple_new_address_proofs_helper(
     for AddressWithTree { address, tree } in addresses {
         let (model, proof) = get_exclusion_range_with_proof(
             txn,
+            batch_config,
             tree.to_bytes_vec(),
             ADDRESS_TREE_HEIGHT,
             address.to_bytes_vec(),
@@ -101,6 +104,7 @@ pub struct AddressList(pub Vec<SerializablePubkey>);
 pub async fn get_multiple_new_address_proofs(
     conn: &DatabaseConnection,
     addresses: AddressList,
+    batch_config: SqlBatchConfig,
 ) -> Result<GetMultipleNewAddressProofsResponse, PhotonApiError> {
     let addresses_with_trees = AddressListWithTrees(
         addresses
@@ -113,7 +117,7 @@ pub async fn get_multiple_new_address_proofs(
             .collect(),
     );
 
-    get_multiple_new_address_proofs_v2(conn, addresses_with_trees).await
+    get_multiple_new_address_proofs_v2(conn, addresses_with_trees, batch_config).await
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
@@ -123,6 +127,7 @@ pub struct AddressListWithTrees(pub Vec<AddressWithTree>);
 pub async fn get_multiple_new_address_proofs_v2(
     conn: &DatabaseConnection,
     addresses_with_trees: AddressListWithTrees,
+    batch_config: SqlBatchConfig,
 ) -> Result<GetMultipleNewAddressProofsResponse, PhotonApiError> {
     let context = Context::extract(conn).await?;
     let tx = conn.begin().await?;
@@ -135,7 +140,7 @@ pub async fn get_multiple_new_address_proofs_v2(
     }
 
     let new_address_proofs =
-        get_multiple_new_address_proofs_helper(&tx, addresses_with_trees.0).await?;
+        get_multiple_new_address_proofs_helper(&tx, batch_config, addresses_with_trees.0).await?;
     tx.commit().await?;
 
     Ok(GetMultipleNewAddressProofsResponse {
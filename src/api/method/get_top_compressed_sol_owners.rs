@@ -0,0 +1,87 @@
+use byteorder::{ByteOrder, LittleEndian};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::typedefs::cursor::Cursor;
+use crate::common::typedefs::u64_string::U64String;
+use crate::dao::generated::owner_balances;
+
+use super::super::error::PhotonApiError;
+use super::get_compressed_mint_token_holders::{
+    OwnerBalance, OwnerBalanceList, OwnerBalancesResponse,
+};
+use super::utils::{parse_decimal, Context, Limit, PAGE_LIMIT};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetTopCompressedSolOwnersRequest {
+    pub cursor: Option<Cursor>,
+    pub limit: Option<Limit>,
+}
+
+pub async fn get_top_compressed_sol_owners(
+    conn: &DatabaseConnection,
+    request: GetTopCompressedSolOwnersRequest,
+) -> Result<OwnerBalancesResponse, PhotonApiError> {
+    let context = Context::extract(conn).await?;
+    let GetTopCompressedSolOwnersRequest { cursor, limit } = request;
+    // Owner balances are maintained incrementally, so owners whose accounts have all been spent
+    // keep a row with a zero balance.
+    let mut filter = owner_balances::Column::Lamports.gt(0);
+
+    if let Some(cursor) = cursor {
+        let bytes = cursor.0;
+        let expected_cursor_length = 40;
+        let (lamports, owner) = if bytes.len() == expected_cursor_length {
+            bytes.split_at(8)
+        } else {
+            return Err(PhotonApiError::ValidationError(format!(
+                "Invalid cursor length. Expected {}. Received {}.",
+                expected_cursor_length,
+                bytes.len()
+            )));
+        };
+        let lamports = LittleEndian::read_u64(lamports);
+
+        filter = filter.and(
+            owner_balances::Column::Lamports
+                .lt(lamports)
+                .or(owner_balances::Column::Lamports
+                    .eq(lamports)
+                    .and(owner_balances::Column::Owner.lt::<Vec<u8>>(owner.into()))),
+        );
+    }
+    let limit = limit.map(|l| l.value()).unwrap_or(PAGE_LIMIT);
+
+    let items = owner_balances::Entity::find()
+        .filter(filter)
+        .order_by_desc(owner_balances::Column::Lamports)
+        .order_by_desc(owner_balances::Column::Owner)
+        .limit(limit)
+        .all(conn)
+        .await?
+        .drain(..)
+        .map(|owner_balance| {
+            Ok(OwnerBalance {
+                owner: owner_balance.owner.try_into()?,
+                balance: U64String(parse_decimal(owner_balance.lamports)?),
+            })
+        })
+        .collect::<Result<Vec<OwnerBalance>, PhotonApiError>>()?;
+
+    let mut cursor = items.last().map(|item| {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&item.balance.0.to_le_bytes());
+        bytes.extend_from_slice(&item.owner.0.to_bytes());
+        Cursor(bytes)
+    });
+    if items.len() < limit as usize {
+        cursor = None;
+    }
+
+    Ok(OwnerBalancesResponse {
+        value: OwnerBalanceList { items, cursor },
+        context,
+    })
+}
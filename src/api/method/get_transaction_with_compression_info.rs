@@ -36,7 +36,7 @@ pub struct GetTransactionRequest {
     pub signature: SerializableSignature,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 #[allow(non_snake_case)]
 pub struct CompressionInfo {
@@ -44,7 +44,7 @@ pub struct CompressionInfo {
     pub openedAccounts: Vec<AccountWithOptionalTokenData>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 #[allow(non_snake_case)]
 pub struct AccountWithOptionalTokenData {
@@ -52,7 +52,7 @@ pub struct AccountWithOptionalTokenData {
     pub optionalTokenData: Option<TokenData>,
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 #[allow(non_snake_case)]
 pub struct GetTransactionResponse {
@@ -1,8 +1,12 @@
 use crate::{
     api::error::PhotonApiError,
     common::typedefs::{hash::Hash, serializable_pubkey::SerializablePubkey},
-    ingester::persist::persisted_state_tree::{
-        get_multiple_compressed_leaf_proofs, MerkleProofWithContext,
+    ingester::persist::{
+        batch_size::SqlBatchConfig,
+        in_memory_tree::InMemoryTrees,
+        persisted_state_tree::{
+            get_multiple_compressed_leaf_proofs_by_tree, MerkleProofWithContext,
+        },
     },
 };
 use lazy_static::lazy_static;
@@ -268,6 +272,8 @@ pub async fn get_validity_proof(
     conn: &DatabaseConnection,
     prover_url: &str,
     mut request: GetValidityProofRequest,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
 ) -> Result<GetValidityProofResponse, PhotonApiError> {
     if request.hashes.is_empty()
         && request.newAddresses.is_empty()
@@ -295,6 +301,20 @@ pub async fn get_validity_proof(
 
     let context = Context::extract(conn).await?;
     let client = Client::new();
+    let account_proofs = match !request.hashes.is_empty() {
+        true => {
+            get_multiple_compressed_leaf_proofs_by_tree(
+                conn,
+                batch_config,
+                in_memory_trees,
+                request.hashes,
+            )
+            .await?
+        }
+        false => {
+            vec![]
+        }
+    };
     let tx = conn.begin().await?;
     if tx.get_database_backend() == DatabaseBackend::Postgres {
         tx.execute(Statement::from_string(
@@ -304,14 +324,11 @@ pub async fn get_validity_proof(
         .await?;
     }
 
-    let account_proofs = match !request.hashes.is_empty() {
-        true => get_multiple_compressed_leaf_proofs(&tx, request.hashes).await?,
-        false => {
-            vec![]
-        }
-    };
     let new_address_proofs = match !request.newAddressesWithTrees.is_empty() {
-        true => get_multiple_new_address_proofs_helper(&tx, request.newAddressesWithTrees).await?,
+        true => {
+            get_multiple_new_address_proofs_helper(&tx, batch_config, request.newAddressesWithTrees)
+                .await?
+        }
         false => {
             vec![]
         }
@@ -0,0 +1,30 @@
+use async_stream::stream;
+use cadence_macros::statsd_count;
+use futures::Stream;
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+use crate::ingester::events::subscribe_indexed_slots;
+use crate::metric;
+
+/// Streams the slot of every block that the ingester commits, also when it commits a batch of
+/// blocks at once.
+pub fn indexer_slot_subscribe() -> impl Stream<Item = UnsignedInteger> {
+    let mut slots = subscribe_indexed_slots();
+    stream! {
+        loop {
+            match slots.recv().await {
+                Ok(slot) => yield UnsignedInteger(slot),
+                // Skipped slots are harmless since clients only care about the latest one.
+                Err(RecvError::Lagged(_)) => {
+                    warn!("Slot subscription lagged behind");
+                    metric! {
+                        statsd_count!("slot_subscription_lagged", 1);
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
@@ -1,10 +1,13 @@
+pub mod compressed_account_subscribe;
 pub mod get_compressed_account;
 pub mod get_compressed_account_balance;
 pub mod get_compressed_account_proof;
 pub mod get_compressed_accounts_by_owner;
+pub mod get_compressed_address_info;
 pub mod get_compressed_balance_by_owner;
 pub mod get_compressed_mint_token_holders;
 pub mod get_compressed_token_account_balance;
+pub mod get_compressed_token_account_by_owner_and_mint;
 pub mod get_compressed_token_accounts_by_delegate;
 pub mod get_compressed_token_accounts_by_owner;
 pub mod get_compressed_token_balances_by_owner;
@@ -12,13 +15,18 @@ pub mod get_compression_signatures_for_account;
 pub mod get_compression_signatures_for_address;
 pub mod get_compression_signatures_for_owner;
 pub mod get_compression_signatures_for_token_owner;
+pub mod get_compression_stats;
+pub mod get_historical_compressed_account_proof;
 pub mod get_indexer_health;
+pub mod get_indexer_metadata;
 pub mod get_indexer_slot;
 pub mod get_latest_compression_signatures;
 pub mod get_latest_non_voting_signatures;
 pub mod get_multiple_compressed_account_proofs;
 pub mod get_multiple_compressed_accounts;
 pub mod get_multiple_new_address_proofs;
+pub mod get_top_compressed_sol_owners;
 pub mod get_transaction_with_compression_info;
 pub mod get_validity_proof;
+pub mod indexer_slot_subscribe;
 pub mod utils;
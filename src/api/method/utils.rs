@@ -1,11 +1,17 @@
+use crate::common::account_data_compression::decompress_account_data;
+use crate::common::account_data_deduplication::resolve_account_data;
 use crate::common::typedefs::account::{Account, AccountData};
-use crate::common::typedefs::bs58_string::Base58String;
 use crate::common::typedefs::bs64_string::Base64String;
+use crate::common::typedefs::cursor::Cursor;
 use crate::common::typedefs::serializable_signature::SerializableSignature;
-use crate::common::typedefs::token_data::{AccountState, TokenData};
+use crate::common::typedefs::token_data::{
+    AccountState, MetadataPointer, TokenData, TokenExtensions,
+};
+use crate::common::typedefs::u64_string::U64String;
 use crate::common::typedefs::unix_timestamp::UnixTimestamp;
 use crate::common::typedefs::unsigned_integer::UnsignedInteger;
 use crate::dao::generated::{accounts, blocks, token_accounts};
+use crate::ingester::indexer::OptionalContextModel;
 
 use byteorder::{ByteOrder, LittleEndian};
 use sea_orm::sea_query::SimpleExpr;
@@ -16,6 +22,7 @@ use sea_orm::{
 use serde::{de, Deserialize, Deserializer, Serialize};
 use solana_sdk::signature::Signature;
 
+use rust_decimal::prelude::ToPrimitive;
 use sqlx::types::Decimal;
 use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
 use utoipa::ToSchema;
@@ -29,10 +36,17 @@ use sea_orm_migration::sea_query::Expr;
 pub const PAGE_LIMIT: u64 = 1000;
 
 pub fn parse_decimal(value: Decimal) -> Result<u64, PhotonApiError> {
+    // Converted numerically instead of through its string form, which depends on the scale the
+    // database returned, e.g. `100.0` instead of `100`.
+    if !value.fract().is_zero() {
+        return Err(PhotonApiError::UnexpectedError(format!(
+            "Invalid decimal value {}",
+            value
+        )));
+    }
     value
-        .to_string()
-        .parse::<u64>()
-        .map_err(|_| PhotonApiError::UnexpectedError("Invalid decimal value".to_string()))
+        .to_u64()
+        .ok_or_else(|| PhotonApiError::UnexpectedError(format!("Invalid decimal value {}", value)))
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
@@ -106,21 +120,44 @@ pub struct ContextModel {
 
 impl Context {
     pub async fn extract(db: &DatabaseConnection) -> Result<Self, PhotonApiError> {
-        let context = blocks::Entity::find()
+        let slot = blocks::Entity::find()
             .select_only()
             .column_as(Expr::col(blocks::Column::Slot).max(), "slot")
-            .into_model::<ContextModel>()
+            .into_model::<OptionalContextModel>()
             .one(db)
             .await?
+            // The maximum of an empty table is null.
+            .and_then(|context| context.slot)
             .ok_or(PhotonApiError::RecordNotFound(
                 "No data has been indexed".to_string(),
             ))?;
-        Ok(Context {
-            slot: context.slot as u64,
-        })
+        Ok(Context { slot: slot as u64 })
     }
 }
 
+fn parse_token_extensions(
+    token_account: &token_accounts::Model,
+) -> Result<Option<TokenExtensions>, PhotonApiError> {
+    let parse_pubkey =
+        |bytes: &Option<Vec<u8>>| bytes.clone().map(SerializablePubkey::try_from).transpose();
+    let metadata_pointer = MetadataPointer {
+        authority: parse_pubkey(&token_account.metadata_pointer_authority)?,
+        metadata_address: parse_pubkey(&token_account.metadata_pointer_address)?,
+    };
+    let extensions = TokenExtensions {
+        withheld_transfer_fee: token_account
+            .withheld_transfer_fee
+            .map(parse_decimal)
+            .transpose()?
+            .map(U64String),
+        permanent_delegate: parse_pubkey(&token_account.permanent_delegate)?,
+        // Pointers without authority and address are not reported, see `TokenExtensions::parse`.
+        metadata_pointer: (metadata_pointer != MetadataPointer::default())
+            .then_some(metadata_pointer),
+    };
+    Ok((extensions != TokenExtensions::default()).then_some(extensions))
+}
+
 pub fn parse_discriminator(discriminator: Option<Vec<u8>>) -> Option<u64> {
     discriminator.map(|discriminator| LittleEndian::read_u64(&discriminator))
 }
@@ -134,7 +171,14 @@ fn parse_leaf_index(leaf_index: i64) -> Result<u32, PhotonApiError> {
 pub fn parse_account_model(account: accounts::Model) -> Result<Account, PhotonApiError> {
     let data = match (account.data, account.data_hash, account.discriminator) {
         (Some(data), Some(data_hash), Some(discriminator)) => Some(AccountData {
-            data: Base64String(data),
+            data: Base64String(
+                decompress_account_data(data, account.data_compressed).map_err(|e| {
+                    PhotonApiError::UnexpectedError(format!(
+                        "Failed to decompress account data: {}",
+                        e
+                    ))
+                })?,
+            ),
             data_hash: data_hash.try_into()?,
             discriminator: UnsignedInteger(parse_decimal(discriminator)?),
         }),
@@ -156,32 +200,32 @@ pub fn parse_account_model(account: accounts::Model) -> Result<Account, PhotonAp
         owner: account.owner.try_into()?,
         tree: account.tree.try_into()?,
         leaf_index: UnsignedInteger(parse_leaf_index(account.leaf_index)? as u64),
-        lamports: UnsignedInteger(parse_decimal(account.lamports)?),
+        lamports: U64String(parse_decimal(account.lamports)?),
         slot_created: UnsignedInteger(account.slot_created as u64),
         seq: UnsignedInteger(account.seq as u64),
     })
 }
 
 // We do not use generics to simplify documentation generation.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct TokenAccountListResponse {
     pub context: Context,
     pub value: TokenAccountList,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct TokenAcccount {
     pub account: Account,
     pub token_data: TokenData,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct TokenAccountList {
     pub items: Vec<TokenAcccount>,
-    pub cursor: Option<Base58String>,
+    pub cursor: Option<Cursor>,
 }
 
 pub enum Authority {
@@ -189,12 +233,36 @@ pub enum Authority {
     Delegate(SerializablePubkey),
 }
 
+/// `slotCreated` sorts by the slot in which the token account was created. `slotUpdated` is
+/// accepted as a deprecated alias of it, since token accounts are never updated in place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenAccountSortBy {
+    #[default]
+    Mint,
+    Amount,
+    #[serde(alias = "slotUpdated")]
+    SlotCreated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct GetCompressedTokenAccountsByAuthorityOptions {
     pub mint: Option<SerializablePubkey>,
-    pub cursor: Option<Base58String>,
+    pub frozen: Option<bool>,
+    pub delegated: Option<bool>,
+    pub cursor: Option<Cursor>,
     pub limit: Option<Limit>,
+    pub sort_by: Option<TokenAccountSortBy>,
+    pub sort_direction: Option<SortDirection>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
@@ -203,10 +271,20 @@ pub struct GetCompressedTokenAccountsByOwner {
     pub owner: SerializablePubkey,
     #[serde(default)]
     pub mint: Option<SerializablePubkey>,
+    /// Only return frozen accounts if true, or only accounts that are not frozen if false.
     #[serde(default)]
-    pub cursor: Option<Base58String>,
+    pub frozen: Option<bool>,
+    /// Only return accounts with a delegate if true, or only accounts without one if false.
+    #[serde(default)]
+    pub delegated: Option<bool>,
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
     #[serde(default)]
     pub limit: Option<Limit>,
+    #[serde(default)]
+    pub sort_by: Option<TokenAccountSortBy>,
+    #[serde(default)]
+    pub sort_direction: Option<SortDirection>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
@@ -215,10 +293,17 @@ pub struct GetCompressedTokenAccountsByDelegate {
     pub delegate: SerializablePubkey,
     #[serde(default)]
     pub mint: Option<SerializablePubkey>,
+    /// Only return frozen accounts if true, or only accounts that are not frozen if false.
+    #[serde(default)]
+    pub frozen: Option<bool>,
     #[serde(default)]
-    pub cursor: Option<Base58String>,
+    pub cursor: Option<Cursor>,
     #[serde(default)]
     pub limit: Option<Limit>,
+    #[serde(default)]
+    pub sort_by: Option<TokenAccountSortBy>,
+    #[serde(default)]
+    pub sort_direction: Option<SortDirection>,
 }
 
 #[derive(FromQueryResult)]
@@ -248,6 +333,7 @@ pub async fn fetch_token_accounts(
     options: GetCompressedTokenAccountsByAuthorityOptions,
 ) -> Result<TokenAccountListResponse, PhotonApiError> {
     let context = Context::extract(conn).await?;
+    let is_delegate_lookup = matches!(owner_or_delegate, Authority::Delegate(_));
     let mut filter = match owner_or_delegate {
         Authority::Owner(owner) => token_accounts::Column::Owner.eq::<Vec<u8>>(owner.into()),
         Authority::Delegate(delegate) => {
@@ -260,9 +346,35 @@ pub async fn fetch_token_accounts(
     if let Some(mint) = options.mint {
         filter = filter.and(token_accounts::Column::Mint.eq::<Vec<u8>>(mint.into()));
     }
+    if let Some(frozen) = options.frozen {
+        let frozen_state = AccountState::frozen as i32;
+        filter = filter.and(match frozen {
+            true => token_accounts::Column::State.eq(frozen_state),
+            false => token_accounts::Column::State.ne(frozen_state),
+        });
+    }
+    if let Some(delegated) = options.delegated {
+        filter = filter.and(match delegated {
+            true => token_accounts::Column::Delegate.is_not_null(),
+            false => token_accounts::Column::Delegate.is_null(),
+        });
+    }
+    let sort_by = options.sort_by.unwrap_or_default();
+    let sort_direction = options.sort_direction.unwrap_or_default();
+    let sort_column = match sort_by {
+        TokenAccountSortBy::Mint => token_accounts::Column::Mint,
+        TokenAccountSortBy::Amount => token_accounts::Column::Amount,
+        TokenAccountSortBy::SlotCreated => token_accounts::Column::SlotCreated,
+    };
     if let Some(cursor) = options.cursor {
         let bytes = cursor.0;
-        let expected_cursor_length = 64;
+        // Cursors consist of the sort key (32 bytes for mints, 8 bytes for amounts and slots)
+        // followed by the account hash, which breaks ties.
+        let sort_key_length = match sort_by {
+            TokenAccountSortBy::Mint => 32,
+            TokenAccountSortBy::Amount | TokenAccountSortBy::SlotCreated => 8,
+        };
+        let expected_cursor_length = sort_key_length + 32;
         if bytes.len() != expected_cursor_length {
             return Err(PhotonApiError::ValidationError(format!(
                 "Invalid cursor length. Expected {}. Received {}.",
@@ -270,41 +382,83 @@ pub async fn fetch_token_accounts(
                 bytes.len()
             )));
         }
-        let (mint, hash) = bytes.split_at(32);
+        let (sort_key, hash) = bytes.split_at(sort_key_length);
+        let sort_value: Value = match sort_by {
+            TokenAccountSortBy::Mint => sort_key.to_vec().into(),
+            TokenAccountSortBy::Amount => LittleEndian::read_u64(sort_key).into(),
+            TokenAccountSortBy::SlotCreated => (LittleEndian::read_u64(sort_key) as i64).into(),
+        };
 
-        filter = filter.and(
-            token_accounts::Column::Mint.gt::<Vec<u8>>(mint.into()).or(
-                token_accounts::Column::Mint
-                    .eq::<Vec<u8>>(mint.into())
-                    .and(token_accounts::Column::Hash.gt::<Vec<u8>>(hash.into())),
-            ),
-        );
+        filter = filter.and(match sort_direction {
+            SortDirection::Asc => sort_column.gt(sort_value.clone()).or(sort_column
+                .eq(sort_value)
+                .and(token_accounts::Column::Hash.gt::<Vec<u8>>(hash.into()))),
+            SortDirection::Desc => sort_column.lt(sort_value.clone()).or(sort_column
+                .eq(sort_value)
+                .and(token_accounts::Column::Hash.lt::<Vec<u8>>(hash.into()))),
+        });
     }
     if let Some(l) = options.limit {
         limit = l.value();
     }
+    let order = match sort_direction {
+        SortDirection::Asc => sea_orm::Order::Asc,
+        SortDirection::Desc => sea_orm::Order::Desc,
+    };
 
-    let items = token_accounts::Entity::find()
+    let query = token_accounts::Entity::find()
         .find_also_related(accounts::Entity)
-        .filter(filter)
-        .order_by(token_accounts::Column::Mint, sea_orm::Order::Asc)
-        .order_by(token_accounts::Column::Hash, sea_orm::Order::Asc)
-        .limit(limit)
-        .order_by(token_accounts::Column::Mint, sea_orm::Order::Asc)
-        .order_by(token_accounts::Column::Hash, sea_orm::Order::Asc)
-        .all(conn)
-        .await?
+        .order_by(sort_column, order.clone())
+        .order_by(token_accounts::Column::Hash, order.clone());
+    let query = if is_delegate_lookup {
+        // Delegates are spread over far more rows than owners, and planners tend to fall back to
+        // sequential scans when joining them against the accounts table. We therefore first page
+        // through the (delegate, spent, mint, hash) index alone and only join the selected page.
+        let hashes: Vec<Vec<u8>> = token_accounts::Entity::find()
+            .select_only()
+            .column(token_accounts::Column::Hash)
+            .filter(filter)
+            .order_by(sort_column, order.clone())
+            .order_by(token_accounts::Column::Hash, order)
+            .limit(limit)
+            .into_model::<HashModel>()
+            .all(conn)
+            .await?
+            .into_iter()
+            .map(|model| model.hash)
+            .collect();
+        if hashes.is_empty() {
+            return Ok(TokenAccountListResponse {
+                value: TokenAccountList::default(),
+                context,
+            });
+        }
+        query.filter(token_accounts::Column::Hash.is_in(hashes))
+    } else {
+        query.filter(filter).limit(limit)
+    };
+
+    let mut models = query.all(conn).await?;
+    resolve_account_data(
+        conn,
+        models
+            .iter_mut()
+            .filter_map(|(_, account)| account.as_mut()),
+    )
+    .await?;
+    let items = models
         .drain(..)
         .map(|(token_account, account)| {
             let account = account.ok_or(PhotonApiError::RecordNotFound(
                 "Base account not found for token account".to_string(),
             ))?;
+            let extensions = parse_token_extensions(&token_account)?;
             Ok(TokenAcccount {
                 account: parse_account_model(account)?,
                 token_data: TokenData {
                     mint: token_account.mint.try_into()?,
                     owner: token_account.owner.try_into()?,
-                    amount: UnsignedInteger(parse_decimal(token_account.amount)?),
+                    amount: U64String(parse_decimal(token_account.amount)?),
                     delegate: token_account
                         .delegate
                         .map(SerializablePubkey::try_from)
@@ -315,16 +469,33 @@ pub async fn fetch_token_accounts(
                             e
                         ))
                     })?,
-                    tlv: token_account.tlv.map(Base64String),
+                    tlv: token_account
+                        .tlv
+                        .map(|tlv| decompress_account_data(tlv, token_account.tlv_compressed))
+                        .transpose()
+                        .map_err(|e| {
+                            PhotonApiError::UnexpectedError(format!(
+                                "Failed to decompress token account TLV: {}",
+                                e
+                            ))
+                        })?
+                        .map(Base64String),
+                    extensions,
                 },
             })
         })
         .collect::<Result<Vec<TokenAcccount>, PhotonApiError>>()?;
 
     let mut cursor = items.last().map(|item| {
-        Base58String({
+        Cursor({
             let item = item.clone();
-            let mut bytes: Vec<u8> = item.token_data.mint.into();
+            let mut bytes: Vec<u8> = match sort_by {
+                TokenAccountSortBy::Mint => item.token_data.mint.into(),
+                TokenAccountSortBy::Amount => item.token_data.amount.0.to_le_bytes().to_vec(),
+                TokenAccountSortBy::SlotCreated => {
+                    item.account.slot_created.0.to_le_bytes().to_vec()
+                }
+            };
             let hash_bytes: Vec<u8> = item.account.hash.into();
             bytes.extend_from_slice(hash_bytes.as_slice());
             bytes
@@ -370,6 +541,7 @@ impl CompressedAccountRequest {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AccountIdentifier {
     Address(SerializablePubkey),
     Hash(Hash),
@@ -436,6 +608,11 @@ pub struct LamportModel {
     pub lamports: Decimal,
 }
 
+#[derive(FromQueryResult)]
+pub struct HashModel {
+    pub hash: Vec<u8>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct HashRequest {
@@ -483,14 +660,14 @@ pub struct SignatureInfoListWithError {
 #[serde(rename_all = "camelCase")]
 pub struct PaginatedSignatureInfoList {
     pub items: Vec<SignatureInfo>,
-    pub cursor: Option<String>,
+    pub cursor: Option<Cursor>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct PaginatedSignatureInfoListWithError {
     pub items: Vec<SignatureInfoWithError>,
-    pub cursor: Option<String>,
+    pub cursor: Option<Cursor>,
 }
 
 impl From<PaginatedSignatureInfoListWithError> for PaginatedSignatureInfoList {
@@ -556,14 +733,12 @@ fn compute_search_filter_and_arg(
 }
 
 fn compute_cursor_filter(
-    cursor: Option<String>,
+    cursor: Option<Cursor>,
     num_preceding_args: i64,
 ) -> Result<(String, Vec<Value>), PhotonApiError> {
     match cursor {
         Some(cursor) => {
-            let bytes = bs58::decode(cursor.clone()).into_vec().map_err(|_| {
-                PhotonApiError::ValidationError(format!("Invalid cursor {}", cursor))
-            })?;
+            let bytes = cursor.0;
             let slot_bytes = 8;
             let signature_bytes = 64;
             let expected_cursor_length = slot_bytes + signature_bytes;
@@ -600,7 +775,7 @@ fn compute_raw_sql_query_and_args(
     search_type: SignatureSearchType,
     signature_filter: Option<SignatureFilter>,
     only_compressed: bool,
-    cursor: Option<String>,
+    cursor: Option<Cursor>,
     limit: u64,
 ) -> Result<(String, Vec<Value>), PhotonApiError> {
     match signature_filter {
@@ -657,7 +832,7 @@ pub async fn search_for_signatures(
     search_type: SignatureSearchType,
     signature_filter: Option<SignatureFilter>,
     only_compressed: bool,
-    cursor: Option<String>,
+    cursor: Option<Cursor>,
     limit: Option<Limit>,
 ) -> Result<PaginatedSignatureInfoListWithError, PhotonApiError> {
     let limit = limit.unwrap_or_default().0;
@@ -680,12 +855,9 @@ pub async fn search_for_signatures(
     let cursor = match signatures.len() < limit as usize {
         true => None,
         false => signatures.last().map(|signature| {
-            bs58::encode::<Vec<u8>>({
-                let mut bytes = signature.slot.0.to_le_bytes().to_vec();
-                bytes.extend_from_slice(signature.signature.0.as_ref());
-                bytes
-            })
-            .into_string()
+            let mut bytes = signature.slot.0.to_le_bytes().to_vec();
+            bytes.extend_from_slice(signature.signature.0.as_ref());
+            Cursor(bytes)
         }),
     };
 
@@ -728,7 +900,7 @@ pub struct GetPaginatedSignaturesResponse {
 // We do not use generics to simplify documentation generation.
 pub struct AccountBalanceResponse {
     pub context: Context,
-    pub value: UnsignedInteger,
+    pub value: U64String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
@@ -737,7 +909,7 @@ pub struct GetLatestSignaturesRequest {
     #[serde(default)]
     pub limit: Option<Limit>,
     #[serde(default)]
-    pub cursor: Option<String>,
+    pub cursor: Option<Cursor>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
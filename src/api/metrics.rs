@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response};
+use jsonrpsee::server::logger::{HttpRequest, Logger, MethodKind, Params, TransportProtocol};
+use tower::{Layer, Service};
+
+use crate::common::prometheus::{render_metrics, API_REQUESTS, API_REQUEST_DURATION};
+
+const METRICS_PATH: &str = "/metrics";
+
+/// Records the number and duration of API requests per method.
+#[derive(Clone)]
+pub struct ApiMetricsLogger {
+    // Requests for other methods are not recorded, so that clients cannot create arbitrary labels.
+    method_names: Arc<HashSet<String>>,
+}
+
+impl ApiMetricsLogger {
+    pub fn new<'a>(method_names: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            method_names: Arc::new(method_names.into_iter().map(String::from).collect()),
+        }
+    }
+}
+
+impl Logger for ApiMetricsLogger {
+    type Instant = Instant;
+
+    fn on_connect(&self, _: SocketAddr, _: &HttpRequest, _: TransportProtocol) {}
+
+    fn on_request(&self, _: TransportProtocol) -> Self::Instant {
+        Instant::now()
+    }
+
+    fn on_call(&self, _: &str, _: Params, _: MethodKind, _: TransportProtocol) {}
+
+    fn on_result(
+        &self,
+        method_name: &str,
+        success: bool,
+        started_at: Self::Instant,
+        _: TransportProtocol,
+    ) {
+        if !self.method_names.contains(method_name) {
+            return;
+        }
+        let result = if success { "success" } else { "error" };
+        API_REQUESTS.inc_by(&[method_name, result], 1);
+        API_REQUEST_DURATION.observe_duration(&[method_name], started_at.elapsed());
+    }
+
+    fn on_response(&self, _: &str, _: Self::Instant, _: TransportProtocol) {}
+
+    fn on_disconnect(&self, _: SocketAddr, _: TransportProtocol) {}
+}
+
+/// Serves the Prometheus metrics on `GET /metrics` and passes all other requests through.
+#[derive(Clone, Default)]
+pub struct MetricsLayer;
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for MetricsService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.method() == Method::GET && req.uri().path() == METRICS_PATH {
+            let response = Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Body::from(render_metrics()))
+                .map_err(Into::into);
+            return Box::pin(async move { response });
+        }
+        let future = self.inner.call(req);
+        Box::pin(async move { future.await.map_err(Into::into) })
+    }
+}
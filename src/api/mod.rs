@@ -1,4 +1,18 @@
 pub mod api;
+pub mod cache;
+pub mod cors;
 pub mod error;
+pub mod export;
+pub mod graphql;
+pub mod grpc;
+pub mod health;
 pub mod method;
+pub mod metrics;
+pub mod proof_cache;
+pub mod rate_limit;
+pub mod read_replicas;
+pub mod request_limits;
+pub mod request_tracing;
+pub mod rest;
 pub mod rpc_server;
+pub mod warm_up;
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use cadence_macros::statsd_count;
+use lru::LruCache;
+use once_cell::sync::Lazy;
+
+use crate::common::typedefs::hash::Hash;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::ingester::persist::persisted_state_tree::MerkleProofWithContext;
+use crate::metric;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ProofKey {
+    tree: SerializablePubkey,
+    root_seq: u64,
+    hash: Hash,
+}
+
+/// Merkle proofs of leaves against the root of a tree with a given sequence number. The proof of a
+/// leaf against a root never changes, so entries do not depend on which connection they were read
+/// from and stay valid until the root of their tree advances.
+pub struct ProofCache {
+    proofs: LruCache<ProofKey, MerkleProofWithContext>,
+    // Latest root seq that proofs were cached for, per tree.
+    root_seqs: HashMap<SerializablePubkey, u64>,
+}
+
+impl ProofCache {
+    fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            proofs: LruCache::new(capacity),
+            root_seqs: HashMap::new(),
+        }
+    }
+
+    /// Drops the proofs against older roots of `tree` once a newer root is seen. Returns false if
+    /// `root_seq` is older than the cached roots, e.g. when read from a lagging replica.
+    fn advance_root(&mut self, tree: SerializablePubkey, root_seq: u64) -> bool {
+        let cached_root_seq = self.root_seqs.entry(tree).or_insert(root_seq);
+        if root_seq < *cached_root_seq {
+            return false;
+        }
+        if root_seq > *cached_root_seq {
+            *cached_root_seq = root_seq;
+            let stale_keys = self
+                .proofs
+                .iter()
+                .filter(|(key, _)| key.tree == tree)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in stale_keys {
+                self.proofs.pop(&key);
+            }
+        }
+        true
+    }
+}
+
+static PROOF_CACHE: Lazy<Mutex<Option<ProofCache>>> = Lazy::new(|| Mutex::new(None));
+
+/// Caches up to `capacity` Merkle proofs. 0 disables the cache.
+pub fn set_proof_cache_capacity(capacity: usize) {
+    *PROOF_CACHE.lock().unwrap() = NonZeroUsize::new(capacity).map(ProofCache::new);
+}
+
+pub fn proof_cache_enabled() -> bool {
+    PROOF_CACHE.lock().unwrap().is_some()
+}
+
+/// Drops all cached proofs. Rolling back an orphaned fork can reuse the sequence numbers of roots.
+pub fn clear_proof_cache() {
+    if let Some(cache) = PROOF_CACHE.lock().unwrap().as_mut() {
+        cache.proofs.clear();
+        cache.root_seqs.clear();
+    }
+}
+
+/// Returns the cached proof of `hash` against the root of `tree` with sequence number `root_seq`.
+pub fn get_cached_proof(
+    tree: SerializablePubkey,
+    root_seq: u64,
+    hash: &Hash,
+) -> Option<MerkleProofWithContext> {
+    let mut cache = PROOF_CACHE.lock().unwrap();
+    let cache = cache.as_mut()?;
+    let key = ProofKey {
+        tree,
+        root_seq,
+        hash: hash.clone(),
+    };
+    let proof = cache.proofs.get(&key).cloned();
+    let metric_name = match proof {
+        Some(_) => "api_cache_hit",
+        None => "api_cache_miss",
+    };
+    metric! {
+        statsd_count!(metric_name, 1, "cache" => "proofs");
+    }
+    proof
+}
+
+/// Caches `proofs` under the root that each of them was generated against.
+pub fn cache_proofs(proofs: &[MerkleProofWithContext]) {
+    if let Some(cache) = PROOF_CACHE.lock().unwrap().as_mut() {
+        for proof in proofs {
+            if cache.advance_root(proof.merkleTree, proof.rootSeq) {
+                let key = ProofKey {
+                    tree: proof.merkleTree,
+                    root_seq: proof.rootSeq,
+                    hash: proof.hash.clone(),
+                };
+                cache.proofs.put(key, proof.clone());
+            }
+        }
+    }
+}
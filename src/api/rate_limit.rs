@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::future::Future;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::header::{CONTENT_TYPE, RETRY_AFTER};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use jsonrpsee::types::error::CALL_EXECUTION_FAILED_CODE;
+use lru::LruCache;
+use serde_json::{json, Value};
+use tower::{Layer, Service};
+
+use super::error::PhotonApiError;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// Health checks come from load balancers, which should not be throttled with the clients.
+const EXEMPT_PATHS: &[&str] = &["/liveness", "/readiness"];
+
+const PROOF_METHODS: &[&str] = &[
+    "getCompressedAccountProof",
+    "getHistoricalCompressedAccountProof",
+    "getMultipleCompressedAccountProofs",
+    "getMultipleNewAddressProofs",
+    "getMultipleNewAddressProofsV2",
+    "getValidityProof",
+];
+
+// The least recently used buckets are dropped once this many are tracked, so that clients cannot
+// grow the map without bound.
+const MAX_BUCKETS: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Number of requests that a client can make per second.
+    pub requests_per_second: u32,
+    /// Number of requests that a client can make at once after being idle.
+    pub burst: u32,
+}
+
+/// Limits of the requests per client. Classes without a limit are not throttled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimitConfig {
+    pub reads: Option<RateLimit>,
+    /// Proof methods have their own limit, since they are far more expensive than other reads.
+    pub proofs: Option<RateLimit>,
+    /// API keys that identify a client in the `x-api-key` header. Other keys are ignored, so that
+    /// clients cannot escape their limit by sending a new key with every request.
+    pub api_keys: HashSet<String>,
+    /// Number of proxies in front of Photon that append to the `X-Forwarded-For` header. HTTP
+    /// clients without an API key are identified by the address that the outermost of them
+    /// appended, and gRPC clients by the address of their connection.
+    pub trusted_proxies: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum MethodClass {
+    Read,
+    Proof,
+}
+
+impl MethodClass {
+    fn of(method: &str) -> Self {
+        if PROOF_METHODS.contains(&method) {
+            MethodClass::Proof
+        } else {
+            MethodClass::Read
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * limit.requests_per_second as f64)
+            .min(limit.burst as f64);
+        self.refilled_at = now;
+    }
+}
+
+/// The token buckets of all clients. The HTTP and gRPC servers share one limiter, so that clients
+/// have the same limit on both.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<LruCache<(String, MethodClass), TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_BUCKETS).unwrap())),
+        }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Takes a token for a call of `method` from the bucket of the client, or returns how long the
+    /// client has to wait.
+    pub fn try_acquire_call(&self, client: &str, method: &str) -> Result<(), Duration> {
+        self.try_acquire(client, &[MethodClass::of(method)])
+    }
+
+    fn limit(&self, class: MethodClass) -> Option<RateLimit> {
+        match class {
+            MethodClass::Read => self.config.reads,
+            MethodClass::Proof => self.config.proofs,
+        }
+    }
+
+    /// Takes a token per call from the buckets of the client, or returns how long the client has
+    /// to wait if any of them has too few. Nothing is taken from a batch that is throttled.
+    fn try_acquire(&self, client: &str, calls: &[MethodClass]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut costs: HashMap<MethodClass, u32> = HashMap::new();
+        for class in calls {
+            *costs.entry(*class).or_default() += 1;
+        }
+        let mut wait = Duration::ZERO;
+        for (&class, &cost) in &costs {
+            let Some(limit) = self.limit(class) else {
+                continue;
+            };
+            let bucket = buckets.get_or_insert_mut((client.to_string(), class), || {
+                TokenBucket::full(limit, now)
+            });
+            bucket.refill(limit, now);
+            let missing = cost as f64 - bucket.tokens;
+            if missing > 0.0 {
+                wait = wait.max(Duration::from_secs_f64(
+                    missing / limit.requests_per_second as f64,
+                ));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for (class, cost) in costs {
+            if let Some(bucket) = buckets.get_mut(&(client.to_string(), class)) {
+                bucket.tokens -= cost as f64;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a client by its API key if it is configured, or else by its address. Requests
+/// without either, which did not pass the trusted proxies, share one limit.
+pub fn client_key(
+    api_key: Option<&str>,
+    address: Option<IpAddr>,
+    config: &RateLimitConfig,
+) -> String {
+    if let Some(key) = api_key
+        .map(str::trim)
+        .filter(|key| config.api_keys.contains(*key))
+    {
+        return format!("key:{}", key);
+    }
+    match address {
+        Some(address) => format!("ip:{}", address),
+        None => "direct".to_string(),
+    }
+}
+
+// The HTTP server does not expose the peer address of connections to middleware, so the address is
+// the `X-Forwarded-For` entry that the outermost trusted proxy appended. Entries to the left of it
+// are set by the client and cannot be trusted.
+fn http_client_key(req: &Request<Body>, config: &RateLimitConfig) -> String {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let forwarded = header("x-forwarded-for").and_then(|addresses| {
+        let addresses = addresses.split(',').collect::<Vec<_>>();
+        // Without trusted proxies, the index is past the last entry.
+        let address = addresses.get(addresses.len().checked_sub(config.trusted_proxies)?)?;
+        address.trim().parse::<IpAddr>().ok()
+    });
+    client_key(header(API_KEY_HEADER), forwarded, config)
+}
+
+/// Returns the id of a single JSON-RPC request and the class of each of its calls. Bodies that are
+/// not JSON-RPC requests, e.g. GraphQL queries, count as a single read.
+fn classify_body(body: &[u8]) -> (Value, Vec<MethodClass>) {
+    let method_class = |call: &Value| MethodClass::of(call["method"].as_str().unwrap_or_default());
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Array(calls)) if !calls.is_empty() => {
+            (Value::Null, calls.iter().map(method_class).collect())
+        }
+        Ok(call) => (call["id"].clone(), vec![method_class(&call)]),
+        Err(_) => (Value::Null, vec![MethodClass::Read]),
+    }
+}
+
+fn rate_limited_response(id: Value, wait: Duration) -> hyper::http::Result<Response<Body>> {
+    let error = PhotonApiError::RateLimited;
+    let body = json!({
+        "jsonrpc": "2.0",
+        "error": {
+            "code": CALL_EXECUTION_FAILED_CODE,
+            "message": error.to_string(),
+            "data": error.data(),
+        },
+        "id": id,
+    });
+    Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(CONTENT_TYPE, "application/json")
+        .header(RETRY_AFTER, wait.as_secs_f64().ceil().max(1.0).to_string())
+        .body(Body::from(body.to_string()))
+}
+
+/// Throttles the requests of each client with token buckets, see [`RateLimitConfig`]. Throttled
+/// requests get a JSON-RPC error of kind `rate_limited` with HTTP status 429 and a `Retry-After`
+/// header. Passes all requests through if no limiter is given.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: Option<Arc<RateLimiter>>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            limiter: self.limiter.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    limiter: Option<Arc<RateLimiter>>,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RateLimitService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limiter = match &self.limiter {
+            Some(limiter) if !EXEMPT_PATHS.contains(&req.uri().path()) => limiter.clone(),
+            _ => {
+                let future = self.inner.call(req);
+                return Box::pin(async move { future.await.map_err(Into::into) });
+            }
+        };
+        let client = http_client_key(&req, &limiter.config);
+
+        if req.method() != Method::POST {
+            // Of the GET routes, only the REST route of `getCompressedAccountProof` is a proof.
+            let class = match req.uri().path().trim_end_matches('/').ends_with("/proof") {
+                true => MethodClass::Proof,
+                false => MethodClass::Read,
+            };
+            if let Err(wait) = limiter.try_acquire(&client, &[class]) {
+                let response = rate_limited_response(Value::Null, wait).map_err(Into::into);
+                return Box::pin(async move { response });
+            }
+            let future = self.inner.call(req);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        }
+
+        // The body has to be read before the request is passed on, so the ready service is taken
+        // and a clone is left in its place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            // The size of the body was checked by the `RequestLimitsLayer` in front of this one.
+            let body = match hyper::body::to_bytes(body).await {
+                Ok(body) => body,
+                Err(e) => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from(format!("Failed to read request: {}", e)))
+                        .map_err(Into::into)
+                }
+            };
+            let (id, calls) = classify_body(&body);
+            if let Err(wait) = limiter.try_acquire(&client, &calls) {
+                return rate_limited_response(id, wait).map_err(Into::into);
+            }
+            inner
+                .call(Request::from_parts(parts, Body::from(body)))
+                .await
+                .map_err(Into::into)
+        })
+    }
+}
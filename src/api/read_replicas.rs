@@ -0,0 +1,133 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use cadence_macros::statsd_gauge;
+use log::{info, warn};
+use sea_orm::{sea_query::Expr, DatabaseConnection, DbErr, EntityTrait, QuerySelect};
+use tokio::task::JoinHandle;
+
+use crate::{dao::generated::blocks, ingester::indexer::OptionalContextModel, metric};
+
+use super::method::get_indexer_health::HEALTH_CHECK_SLOT_DISTANCE;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct Replica {
+    conn: Arc<DatabaseConnection>,
+    healthy: AtomicBool,
+}
+
+/// Routes the queries of the API to read replicas of the database that the indexer writes to.
+/// Requests are spread over the healthy replicas in turn and fall back to the primary while no
+/// replica is healthy. Without replicas, all queries go to the primary.
+pub struct ReadReplicas {
+    primary: Arc<DatabaseConnection>,
+    replicas: Vec<Replica>,
+    next: AtomicUsize,
+}
+
+impl ReadReplicas {
+    /// Replicas count as healthy until the first health check says otherwise.
+    pub fn new(primary: Arc<DatabaseConnection>, replicas: Vec<Arc<DatabaseConnection>>) -> Self {
+        Self {
+            primary,
+            replicas: replicas
+                .into_iter()
+                .map(|conn| Replica {
+                    conn,
+                    healthy: AtomicBool::new(true),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Connection to run the next read on.
+    pub fn connection(&self) -> Arc<DatabaseConnection> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.replicas.len())
+            .map(|offset| &self.replicas[(start + offset) % self.replicas.len()])
+            .find(|replica| replica.healthy.load(Ordering::Relaxed))
+            .map(|replica| replica.conn.clone())
+            .unwrap_or_else(|| self.primary.clone())
+    }
+
+    /// Connections that reads can be routed to.
+    pub fn connections(&self) -> Vec<Arc<DatabaseConnection>> {
+        match self.replicas.is_empty() {
+            true => vec![self.primary.clone()],
+            false => self
+                .replicas
+                .iter()
+                .map(|replica| replica.conn.clone())
+                .collect(),
+        }
+    }
+
+    /// Marks the replicas that cannot be queried, or whose last indexed slot is more than
+    /// `HEALTH_CHECK_SLOT_DISTANCE` slots behind the primary, as unhealthy, and the others as
+    /// healthy again.
+    pub async fn check_health(&self) {
+        let primary_slot = last_indexed_slot(&self.primary).await.ok().flatten();
+        for (index, replica) in self.replicas.iter().enumerate() {
+            let healthy = match last_indexed_slot(&replica.conn).await {
+                Ok(slot) => match (primary_slot, slot) {
+                    (Some(primary_slot), Some(slot)) => {
+                        primary_slot - slot <= HEALTH_CHECK_SLOT_DISTANCE
+                    }
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                },
+                Err(e) => {
+                    warn!("Health check of read replica {} failed: {}", index, e);
+                    false
+                }
+            };
+            if replica.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                match healthy {
+                    true => info!("Read replica {} is healthy again", index),
+                    false => warn!("Routing reads away from unhealthy read replica {}", index),
+                }
+            }
+        }
+        let healthy_replicas = self
+            .replicas
+            .iter()
+            .filter(|replica| replica.healthy.load(Ordering::Relaxed))
+            .count();
+        metric! {
+            statsd_gauge!("healthy_read_replicas", healthy_replicas as u64);
+        }
+    }
+
+    /// Checks the health of the replicas every `HEALTH_CHECK_INTERVAL`.
+    pub fn continously_check_health(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                self.check_health().await;
+                tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+            }
+        })
+    }
+}
+
+async fn last_indexed_slot(db: &DatabaseConnection) -> Result<Option<i64>, DbErr> {
+    let query = blocks::Entity::find()
+        .select_only()
+        .column_as(Expr::col(blocks::Column::Slot).max(), "slot")
+        .into_model::<OptionalContextModel>()
+        .one(db);
+    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, query).await {
+        Ok(context) => Ok(context?.and_then(|context| context.slot)),
+        Err(_) => Err(DbErr::Custom(format!(
+            "Query timed out after {:?}",
+            HEALTH_CHECK_TIMEOUT
+        ))),
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use jsonrpsee::types::error::{INVALID_REQUEST_CODE, OVERSIZED_REQUEST_CODE};
+use jsonrpsee_core::error::GenericTransportError;
+use jsonrpsee_core::http_helpers::read_body;
+use jsonrpsee_core::TEN_MB_SIZE_BYTES;
+use serde_json::{json, Value};
+use tower::{Layer, Service};
+
+// Health checks have to keep working whatever the operator disables.
+const ALWAYS_ENABLED_METHODS: &[&str] = &["liveness", "readiness"];
+
+/// Which methods the API serves, by their JSON-RPC names. Calls of other methods fail with an error
+/// of kind `method_disabled` before their handler runs. This includes the REST routes and exports
+/// of these methods as well as the gRPC methods and GraphQL fields that are answered by them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MethodFilter {
+    /// Only these methods are served if set.
+    pub enabled: Option<HashSet<String>>,
+    pub disabled: HashSet<String>,
+}
+
+impl MethodFilter {
+    pub fn is_enabled(&self, method: &str) -> bool {
+        if ALWAYS_ENABLED_METHODS.contains(&method) {
+            return true;
+        }
+        let enabled = match &self.enabled {
+            Some(enabled) => enabled.contains(method),
+            None => true,
+        };
+        enabled && !self.disabled.contains(method)
+    }
+
+    /// Returns the methods of the filter that are not in `method_names`, e.g. because of a typo.
+    pub fn unknown_methods<'a>(
+        &'a self,
+        method_names: &'a HashSet<&str>,
+    ) -> impl Iterator<Item = &'a String> + 'a {
+        self.enabled
+            .iter()
+            .flatten()
+            .chain(&self.disabled)
+            .filter(|method| !method_names.contains(method.as_str()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Max size of HTTP request bodies and WebSocket messages in bytes.
+    pub max_body_size: u32,
+    /// Max number of calls in a JSON-RPC batch. Unlimited if not set.
+    pub max_batch_len: Option<usize>,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_body_size: TEN_MB_SIZE_BYTES,
+            max_batch_len: None,
+        }
+    }
+}
+
+fn error_response(status: StatusCode, code: i32, message: String) -> Response<Body> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "error": { "code": code, "message": message },
+        "id": null,
+    });
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Rejects HTTP requests whose body is larger than [`RequestLimits::max_body_size`] or that batch
+/// more calls than [`RequestLimits::max_batch_len`] with a JSON-RPC error, before they reach the
+/// layers that read the body or run queries. Later layers can rely on bodies being within the
+/// limit.
+#[derive(Clone)]
+pub struct RequestLimitsLayer {
+    limits: RequestLimits,
+}
+
+impl RequestLimitsLayer {
+    pub fn new(limits: RequestLimits) -> Self {
+        Self { limits }
+    }
+}
+
+impl<S> Layer<S> for RequestLimitsLayer {
+    type Service = RequestLimitsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLimitsService {
+            limits: self.limits,
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestLimitsService<S> {
+    limits: RequestLimits,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestLimitsService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.method() != Method::POST {
+            let future = self.inner.call(req);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        }
+
+        let limits = self.limits;
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let (body, is_single) =
+                match read_body(&parts.headers, body, limits.max_body_size).await {
+                    Ok(body) => body,
+                    Err(GenericTransportError::TooLarge) => {
+                        return Ok(error_response(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            OVERSIZED_REQUEST_CODE,
+                            format!(
+                                "Request body exceeds the limit of {} bytes",
+                                limits.max_body_size
+                            ),
+                        ))
+                    }
+                    Err(e) => {
+                        return Ok(error_response(
+                            StatusCode::BAD_REQUEST,
+                            INVALID_REQUEST_CODE,
+                            format!("Failed to read request: {}", e),
+                        ))
+                    }
+                };
+            if let (false, Some(max_batch_len)) = (is_single, limits.max_batch_len) {
+                if let Ok(Value::Array(calls)) = serde_json::from_slice::<Value>(&body) {
+                    if calls.len() > max_batch_len {
+                        return Ok(error_response(
+                            StatusCode::BAD_REQUEST,
+                            INVALID_REQUEST_CODE,
+                            format!(
+                                "Batch of {} calls exceeds the limit of {} calls",
+                                calls.len(),
+                                max_batch_len
+                            ),
+                        ));
+                    }
+                }
+            }
+            inner
+                .call(Request::from_parts(parts, Body::from(body)))
+                .await
+                .map_err(Into::into)
+        })
+    }
+}
@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::HeaderValue;
+use hyper::{Body, Request, Response};
+use tower::{Layer, Service};
+use tracing::{info_span, Instrument};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Request IDs that clients pass are truncated, so that they cannot bloat the spans.
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+fn request_id(req: &Request<Body>) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.chars().take(MAX_REQUEST_ID_LENGTH).collect())
+        .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()))
+}
+
+/// Runs each HTTP request in a span that carries its request ID, so that the spans of the API
+/// methods that it calls and the log lines that they emit can be correlated with the request. The
+/// ID is taken from the `X-Request-Id` header, or generated if the client did not pass one, and
+/// returned in the `X-Request-Id` header of the response.
+#[derive(Clone, Default)]
+pub struct RequestTracingLayer;
+
+impl<S> Layer<S> for RequestTracingLayer {
+    type Service = RequestTracingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestTracingService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestTracingService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestTracingService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let request_id = request_id(&req);
+        let span = info_span!(
+            "http_request",
+            request_id = %request_id,
+            method = %req.method(),
+            path = %req.uri().path(),
+        );
+        let future = span.in_scope(|| self.inner.call(req));
+        Box::pin(
+            async move {
+                let mut response = future.await.map_err(Into::into)?;
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
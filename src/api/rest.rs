@@ -0,0 +1,280 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use jsonrpsee::core::server::rpc_module::Methods;
+use serde_json::{json, Map, Value};
+use tower::{Layer, Service};
+
+const PAGINATION_PARAMS: &[&str] = &["cursor", "limit"];
+const TOKEN_PAGINATION_PARAMS: &[&str] = &["mint", "cursor", "limit"];
+const TOKEN_ACCOUNT_PARAMS: &[&str] = &["mint", "frozen", "cursor", "limit"];
+const OWNER_TOKEN_ACCOUNT_PARAMS: &[&str] = &["mint", "frozen", "delegated", "cursor", "limit"];
+
+// Query parameters that the JSON-RPC methods take as numbers or booleans rather than strings.
+const NUMERIC_PARAMS: &[&str] = &["limit", "discriminator"];
+const BOOLEAN_PARAMS: &[&str] = &["frozen", "delegated"];
+
+/// A GET route and the JSON-RPC method that serves it. The `{name}` segments of the path and the
+/// allowed query parameters are passed to the method as the params of the same name.
+struct Route {
+    path: &'static str,
+    method: &'static str,
+    query_params: &'static [&'static str],
+}
+
+const ROUTES: &[Route] = &[
+    Route {
+        path: "/slot",
+        method: "getIndexerSlot",
+        query_params: &[],
+    },
+    Route {
+        path: "/accounts/{hash}",
+        method: "getCompressedAccount",
+        query_params: &[],
+    },
+    Route {
+        path: "/accounts/{hash}/balance",
+        method: "getCompressedAccountBalance",
+        query_params: &[],
+    },
+    Route {
+        path: "/accounts/{hash}/proof",
+        method: "getCompressedAccountProof",
+        query_params: &[],
+    },
+    Route {
+        path: "/accounts/{hash}/signatures",
+        method: "getCompressionSignaturesForAccount",
+        query_params: &[],
+    },
+    Route {
+        path: "/addresses/{address}/account",
+        method: "getCompressedAccount",
+        query_params: &[],
+    },
+    Route {
+        path: "/addresses/{address}/signatures",
+        method: "getCompressionSignaturesForAddress",
+        query_params: PAGINATION_PARAMS,
+    },
+    Route {
+        path: "/owners/{owner}/accounts",
+        method: "getCompressedAccountsByOwner",
+        query_params: &["discriminator", "cursor", "limit"],
+    },
+    Route {
+        path: "/owners/{owner}/balance",
+        method: "getCompressedBalanceByOwner",
+        query_params: &[],
+    },
+    Route {
+        path: "/owners/{owner}/token-accounts",
+        method: "getCompressedTokenAccountsByOwner",
+        query_params: OWNER_TOKEN_ACCOUNT_PARAMS,
+    },
+    Route {
+        path: "/owners/{owner}/token-balances",
+        method: "getCompressedTokenBalancesByOwnerV2",
+        query_params: TOKEN_PAGINATION_PARAMS,
+    },
+    Route {
+        path: "/owners/{owner}/signatures",
+        method: "getCompressionSignaturesForOwner",
+        query_params: PAGINATION_PARAMS,
+    },
+    Route {
+        path: "/delegates/{delegate}/token-accounts",
+        method: "getCompressedTokenAccountsByDelegate",
+        query_params: TOKEN_ACCOUNT_PARAMS,
+    },
+    Route {
+        path: "/mints/{mint}/holders",
+        method: "getCompressedMintTokenHolders",
+        query_params: PAGINATION_PARAMS,
+    },
+    Route {
+        path: "/transactions/{signature}",
+        method: "getTransactionWithCompressionInfo",
+        query_params: &[],
+    },
+];
+
+impl Route {
+    /// Returns the path parameters if `path` matches the route.
+    fn match_path(&self, path: &str) -> Option<Map<String, Value>> {
+        let mut params = Map::new();
+        let mut segments = path.trim_end_matches('/').split('/');
+        for pattern in self.path.split('/') {
+            let segment = segments.next()?;
+            match pattern
+                .strip_prefix('{')
+                .and_then(|name| name.strip_suffix('}'))
+            {
+                Some(_) if segment.is_empty() => return None,
+                Some(name) => {
+                    params.insert(name.to_string(), Value::String(segment.to_string()));
+                }
+                None if pattern == segment => {}
+                None => return None,
+            }
+        }
+        segments.next().is_none().then_some(params)
+    }
+
+    /// Builds the params of the JSON-RPC request from the path parameters and the query.
+    fn params(
+        &self,
+        mut params: Map<String, Value>,
+        query: Option<&str>,
+    ) -> Result<Map<String, Value>, String> {
+        let query = query.unwrap_or_default().as_bytes();
+        for (name, value) in form_urlencoded::parse(query) {
+            if !self.query_params.contains(&name.as_ref()) {
+                return Err(format!("Unsupported query parameter: {}", name));
+            }
+            let value = if NUMERIC_PARAMS.contains(&name.as_ref()) {
+                value
+                    .parse::<u64>()
+                    .map(Value::from)
+                    .map_err(|_| format!("Invalid {}: {}", name, value))?
+            } else if BOOLEAN_PARAMS.contains(&name.as_ref()) {
+                value
+                    .parse::<bool>()
+                    .map(Value::from)
+                    .map_err(|_| format!("Invalid {}: {}", name, value))?
+            } else {
+                Value::String(value.into_owned())
+            };
+            params.insert(name.into_owned(), value);
+        }
+        Ok(params)
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> hyper::http::Result<Response<Body>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+}
+
+/// Maps the errors of the API to HTTP statuses by their kind, see `PhotonApiError::kind`. Errors
+/// without data are raised before the handler runs, e.g. for params that cannot be deserialized.
+fn error_status(error: &Value) -> StatusCode {
+    let data = &error["data"];
+    match data["kind"].as_str() {
+        None => StatusCode::BAD_REQUEST,
+        Some("record_not_found") | Some("slot_not_indexed") => StatusCode::NOT_FOUND,
+        Some("validation_error") | Some("invalid_pubkey") => StatusCode::BAD_REQUEST,
+        Some("method_disabled") => StatusCode::FORBIDDEN,
+        Some(_) if data["retryable"] == Value::Bool(true) => StatusCode::SERVICE_UNAVAILABLE,
+        Some(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn call_method(
+    methods: Methods,
+    method: &'static str,
+    params: Map<String, Value>,
+) -> hyper::http::Result<Response<Body>> {
+    let mut request = json!({ "jsonrpc": "2.0", "id": 1, "method": method });
+    if !params.is_empty() {
+        request["params"] = Value::Object(params);
+    }
+    let response = match methods.raw_json_request(&request.to_string()).await {
+        Ok((response, _)) => response,
+        Err(e) => {
+            return json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &json!({ "message": e.to_string() }),
+            )
+        }
+    };
+    let mut response: Value = serde_json::from_str(&response.result).unwrap_or_default();
+    match response["error"].take() {
+        Value::Null => json_response(StatusCode::OK, &response["result"]),
+        error => json_response(error_status(&error), &error),
+    }
+}
+
+/// Serves the read methods in `ROUTES` as REST-style GET routes, e.g. `GET /accounts/{hash}` or
+/// `GET /owners/{owner}/token-accounts?mint=<mint>`, so that they can be queried without building
+/// a JSON-RPC request. The response body is the `result` of the method, or its `error` with a
+/// matching HTTP status. All other requests are passed through.
+#[derive(Clone)]
+pub struct RestLayer {
+    methods: Methods,
+}
+
+impl RestLayer {
+    pub fn new(methods: impl Into<Methods>) -> Self {
+        Self {
+            methods: methods.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for RestLayer {
+    type Service = RestService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RestService {
+            methods: self.methods.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RestService<S> {
+    methods: Methods,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RestService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let route = match req.method() {
+            &Method::GET => ROUTES.iter().find_map(|route| {
+                route
+                    .match_path(req.uri().path())
+                    .map(|params| (route, params))
+            }),
+            _ => None,
+        };
+        let Some((route, params)) = route else {
+            let future = self.inner.call(req);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        };
+        match route.params(params, req.uri().query()) {
+            Ok(params) => {
+                let response = call_method(self.methods.clone(), route.method, params);
+                Box::pin(async move { response.await.map_err(Into::into) })
+            }
+            Err(message) => {
+                let response =
+                    json_response(StatusCode::BAD_REQUEST, &json!({ "message": message }))
+                        .map_err(Into::into);
+                Box::pin(async move { response })
+            }
+        }
+    }
+}
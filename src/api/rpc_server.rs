@@ -1,49 +1,126 @@
+use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
-use hyper::Method;
 use jsonrpsee::{
+    core::Error as RpcError,
     server::{middleware::proxy_get_request::ProxyGetRequestLayer, ServerBuilder, ServerHandle},
+    types::Params,
     RpcModule,
 };
-use log::debug;
-use tower_http::cors::{Any, CorsLayer};
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::{info_span, Instrument};
 
 use super::api::PhotonApi;
+use super::export::ExportLayer;
+use super::graphql::{build_schema, GraphQLLayer};
+use super::health::HealthLayer;
+use super::method::compressed_account_subscribe::compressed_account_subscribe;
+use super::method::indexer_slot_subscribe::indexer_slot_subscribe;
+use super::metrics::{ApiMetricsLogger, MetricsLayer};
+use super::rate_limit::RateLimitLayer;
+use super::request_limits::RequestLimitsLayer;
+use super::request_tracing::RequestTracingLayer;
+use super::rest::RestLayer;
+use crate::openapi::build_api_spec;
 
 pub async fn run_server(api: PhotonApi, port: u16) -> Result<ServerHandle, anyhow::Error> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let cors = CorsLayer::new()
-        .allow_methods([Method::POST, Method::GET])
-        .allow_origin(Any)
-        .allow_headers([hyper::header::CONTENT_TYPE]);
+    let cors = api.cors().layer();
+    api.start_warm_up();
+    let read_replicas = api.read_replicas();
+    let rate_limiter = api.rate_limiter();
+    let request_limits = api.request_limits();
+    let method_filter = api.method_filter().clone();
+    let health = HealthLayer::new(api.clone());
+    let graphql_schema = api
+        .graphql_limits()
+        .map(|limits| build_schema(api.clone(), limits));
+    let rpc_module = build_rpc_module(api)?;
+    let method_names = rpc_module.method_names().collect();
+    for method in method_filter.unknown_methods(&method_names) {
+        warn!("Ignoring unknown method in the method filter: {}", method);
+    }
     let middleware = tower::ServiceBuilder::new()
         .layer(cors)
+        .layer(RequestTracingLayer)
+        .layer(MetricsLayer)
+        .layer(health)
+        .layer(RequestLimitsLayer::new(request_limits))
+        .layer(RateLimitLayer::new(rate_limiter))
+        .layer(ExportLayer::new(read_replicas, rpc_module.clone()))
+        .layer(GraphQLLayer::new(graphql_schema))
+        .layer(RestLayer::new(rpc_module.clone()))
         .layer(ProxyGetRequestLayer::new("/liveness", "liveness")?)
-        .layer(ProxyGetRequestLayer::new("/readiness", "readiness")?);
+        .layer(ProxyGetRequestLayer::new("/readiness", "readiness")?)
+        .layer(ProxyGetRequestLayer::new(
+            "/metadata",
+            "getIndexerMetadata",
+        )?)
+        .layer(ProxyGetRequestLayer::new("/api-spec", "rpc.discover")?);
     let server = ServerBuilder::default()
+        .set_logger(ApiMetricsLogger::new(rpc_module.method_names()))
+        .max_request_body_size(request_limits.max_body_size)
         .set_middleware(middleware)
         .build(addr)
         .await?;
-    let rpc_module = build_rpc_module(api)?;
     server.start(rpc_module).map_err(|e| anyhow::anyhow!(e))
 }
 
+/// Registers an API method that runs in a span named after the method, within the span of the
+/// HTTP request that called it. Calls of methods that the operator disabled fail without running
+/// `callback`.
+fn register_traced_method<R, Fut, F>(
+    module: &mut RpcModule<PhotonApi>,
+    method_name: &'static str,
+    callback: F,
+) -> Result<(), RpcError>
+where
+    R: Serialize + Send + Sync + 'static,
+    Fut: Future<Output = Result<R, RpcError>> + Send,
+    F: Fn(Params<'static>, Arc<PhotonApi>) -> Fut + Clone + Send + Sync + 'static,
+{
+    module.register_async_method(method_name, move |rpc_params, rpc_context| {
+        let callback = callback.clone();
+        async move {
+            rpc_context.ensure_method_enabled(method_name)?;
+            callback(rpc_params, rpc_context).await
+        }
+        .instrument(info_span!("rpc_method", method = method_name))
+    })?;
+    Ok(())
+}
+
+// The spec only depends on the types of the API, so it is assembled once.
+static API_SPEC: Lazy<serde_json::Value> = Lazy::new(build_api_spec);
+
 fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>, anyhow::Error> {
     let mut module = RpcModule::new(api_and_indexer);
 
-    module.register_async_method("liveness", |_rpc_params, rpc_context| async move {
-        debug!("Checking Liveness");
-        let api = rpc_context.as_ref();
-        api.liveness().await.map_err(Into::into)
-    })?;
+    register_traced_method(
+        &mut module,
+        "liveness",
+        |_rpc_params, rpc_context| async move {
+            debug!("Checking Liveness");
+            let api = rpc_context.as_ref();
+            api.liveness().await.map_err(Into::into)
+        },
+    )?;
 
-    module.register_async_method("readiness", |_rpc_params, rpc_context| async move {
-        debug!("Checking Readiness");
-        let api = rpc_context.as_ref();
-        api.readiness().await.map_err(Into::into)
-    })?;
+    register_traced_method(
+        &mut module,
+        "readiness",
+        |_rpc_params, rpc_context| async move {
+            debug!("Checking Readiness");
+            let api = rpc_context.as_ref();
+            api.readiness().await.map_err(Into::into)
+        },
+    )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedAccount",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -54,7 +131,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedAccountProof",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -65,7 +143,20 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
+        "getHistoricalCompressedAccountProof",
+        |rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            let payload = rpc_params.parse()?;
+            api.get_historical_compressed_account_proof(payload)
+                .await
+                .map_err(Into::into)
+        },
+    )?;
+
+    register_traced_method(
+        &mut module,
         "getMultipleCompressedAccountProofs",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -76,7 +167,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedTokenAccountsByOwner",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -87,7 +179,20 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
+        "getCompressedTokenAccountByOwnerAndMint",
+        |rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            let payload = rpc_params.parse()?;
+            api.get_compressed_token_account_by_owner_and_mint(payload)
+                .await
+                .map_err(Into::into)
+        },
+    )?;
+
+    register_traced_method(
+        &mut module,
         "getCompressedTokenAccountsByDelegate",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -98,7 +203,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedBalanceByOwner",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -109,7 +215,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedTokenBalancesByOwner",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -120,7 +227,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedTokenAccountBalance",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -131,7 +239,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedBalance",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -142,7 +251,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedAccountBalance",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -153,20 +263,45 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method("getIndexerHealth", |_rpc_params, rpc_context| async move {
-        rpc_context
-            .as_ref()
-            .get_indexer_health()
-            .await
-            .map_err(Into::into)
-    })?;
+    register_traced_method(
+        &mut module,
+        "getIndexerHealth",
+        |_rpc_params, rpc_context| async move {
+            rpc_context
+                .as_ref()
+                .get_indexer_health()
+                .await
+                .map_err(Into::into)
+        },
+    )?;
 
-    module.register_async_method("getIndexerSlot", |_rpc_params, rpc_context| async move {
-        let api = rpc_context.as_ref();
-        api.get_indexer_slot().await.map_err(Into::into)
-    })?;
+    register_traced_method(
+        &mut module,
+        "getIndexerMetadata",
+        |_rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            api.get_indexer_metadata().await.map_err(Into::into)
+        },
+    )?;
 
-    module.register_async_method(
+    // The name is the one that OpenRPC reserves for the document that describes the API.
+    register_traced_method(
+        &mut module,
+        "rpc.discover",
+        |_rpc_params, _rpc_context| async move { Ok(&*API_SPEC) },
+    )?;
+
+    register_traced_method(
+        &mut module,
+        "getIndexerSlot",
+        |_rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            api.get_indexer_slot().await.map_err(Into::into)
+        },
+    )?;
+
+    register_traced_method(
+        &mut module,
         "getCompressedAccountsByOwner",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -177,7 +312,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getMultipleCompressedAccounts",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -188,7 +324,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressionSignaturesForAccount",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -199,7 +336,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressionSignaturesForAddress",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -210,7 +348,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressionSignaturesForOwner",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -221,7 +360,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressionSignaturesForTokenOwner",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -232,7 +372,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getTransactionWithCompressionInfo",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -242,13 +383,18 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
                 .map_err(Into::into)
         },
     )?;
-    module.register_async_method("getValidityProof", |rpc_params, rpc_context| async move {
-        let api = rpc_context.as_ref();
-        let payload = rpc_params.parse()?;
-        api.get_validity_proof(payload).await.map_err(Into::into)
-    })?;
+    register_traced_method(
+        &mut module,
+        "getValidityProof",
+        |rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            let payload = rpc_params.parse()?;
+            api.get_validity_proof(payload).await.map_err(Into::into)
+        },
+    )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getLatestCompressionSignatures",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -259,7 +405,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getLatestNonVotingSignatures",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -270,7 +417,20 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
+        "getCompressedAddressInfo",
+        |rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            let payload = rpc_params.parse()?;
+            api.get_compressed_address_info(payload)
+                .await
+                .map_err(Into::into)
+        },
+    )?;
+
+    register_traced_method(
+        &mut module,
         "getMultipleNewAddressProofs",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -281,7 +441,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getMultipleNewAddressProofsV2",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -291,7 +452,8 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
                 .map_err(Into::into)
         },
     )?;
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
         "getCompressedMintTokenHolders",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -302,7 +464,30 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
-    module.register_async_method(
+    register_traced_method(
+        &mut module,
+        "getTopCompressedSolOwners",
+        |rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            let payload = rpc_params.parse()?;
+            api.get_top_compressed_sol_owners(payload)
+                .await
+                .map_err(Into::into)
+        },
+    )?;
+
+    register_traced_method(
+        &mut module,
+        "getCompressionStats",
+        |rpc_params, rpc_context| async move {
+            let api = rpc_context.as_ref();
+            let payload = rpc_params.parse()?;
+            api.get_compression_stats(payload).await.map_err(Into::into)
+        },
+    )?;
+
+    register_traced_method(
+        &mut module,
         "getCompressedTokenBalancesByOwnerV2",
         |rpc_params, rpc_context| async move {
             let api = rpc_context.as_ref();
@@ -313,5 +498,52 @@ fn build_rpc_module(api_and_indexer: PhotonApi) -> Result<RpcModule<PhotonApi>,
         },
     )?;
 
+    module.register_subscription(
+        "compressedAccountSubscribe",
+        "compressedAccountNotification",
+        "compressedAccountUnsubscribe",
+        |rpc_params, mut sink, rpc_context| {
+            if let Err(e) = rpc_context.ensure_method_enabled("compressedAccountSubscribe") {
+                sink.reject(RpcError::from(e))?;
+                return Ok(());
+            }
+            let payload = match rpc_params.parse() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    sink.reject(e)?;
+                    return Ok(());
+                }
+            };
+            let notifications = match compressed_account_subscribe(payload) {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    sink.reject(RpcError::from(e))?;
+                    return Ok(());
+                }
+            };
+            tokio::spawn(async move {
+                sink.pipe_from_stream(Box::pin(notifications)).await;
+            });
+            Ok(())
+        },
+    )?;
+
+    module.register_subscription(
+        "indexerSlotSubscribe",
+        "indexerSlotNotification",
+        "indexerSlotUnsubscribe",
+        |_rpc_params, mut sink, rpc_context| {
+            if let Err(e) = rpc_context.ensure_method_enabled("indexerSlotSubscribe") {
+                sink.reject(RpcError::from(e))?;
+                return Ok(());
+            }
+            tokio::spawn(async move {
+                sink.pipe_from_stream(Box::pin(indexer_slot_subscribe()))
+                    .await;
+            });
+            Ok(())
+        },
+    )?;
+
     Ok(module)
 }
@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+use futures::future::try_join_all;
+use log::{error, info};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, Statement, TransactionTrait,
+};
+
+use crate::common::typedefs::hash::Hash;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::dao::generated::{accounts, state_trees, token_accounts};
+use crate::ingester::persist::batch_size::SqlBatchConfig;
+use crate::ingester::persist::in_memory_tree::InMemoryTrees;
+use crate::ingester::persist::persisted_state_tree::get_multiple_compressed_leaf_proofs;
+
+use super::error::PhotonApiError;
+use super::method::utils::Context;
+
+// Queries are run on this many connections at once, so that most of the pool has the hot
+// statements prepared and cached before the first request arrives.
+const WARM_UP_CONNECTIONS: usize = 10;
+const WARM_UP_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// Runs the queries that nearly every request depends on. Besides preparing the statements, this
+// loads the indexes they use into the database's buffer cache.
+async fn run_warm_up_queries(db: &DatabaseConnection) -> Result<(), PhotonApiError> {
+    match Context::extract(db).await {
+        // A missing slot only means that nothing has been indexed yet.
+        Ok(_) | Err(PhotonApiError::RecordNotFound(_)) => {}
+        Err(e) => return Err(e),
+    }
+    state_trees::Entity::find()
+        .filter(state_trees::Column::NodeIdx.eq(1))
+        .all(db)
+        .await?;
+    accounts::Entity::find()
+        .filter(accounts::Column::Hash.eq(Hash::default().to_vec()))
+        .one(db)
+        .await?;
+    token_accounts::Entity::find()
+        .filter(token_accounts::Column::Owner.eq(SerializablePubkey::default().to_bytes_vec()))
+        .filter(token_accounts::Column::Spent.eq(false))
+        .limit(1)
+        .all(db)
+        .await?;
+    Ok(())
+}
+
+// Generates the proof of the latest leaf of every state tree, which loads the top levels of the
+// trees into memory and caches the proofs against their current roots.
+async fn warm_up_state_trees(
+    db: &DatabaseConnection,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
+) -> Result<(), PhotonApiError> {
+    let roots = state_trees::Entity::find()
+        .filter(state_trees::Column::NodeIdx.eq(1))
+        .all(db)
+        .await?;
+    let mut hashes = Vec::new();
+    for root in roots {
+        let latest_leaf = state_trees::Entity::find()
+            .filter(state_trees::Column::Tree.eq(root.tree))
+            .filter(state_trees::Column::Level.eq(0))
+            .order_by_desc(state_trees::Column::LeafIdx)
+            .one(db)
+            .await?;
+        hashes.extend(
+            latest_leaf
+                .map(|leaf| Hash::try_from(leaf.hash))
+                .transpose()?,
+        );
+    }
+    if hashes.is_empty() {
+        return Ok(());
+    }
+    let tx = db.begin().await?;
+    if tx.get_database_backend() == DatabaseBackend::Postgres {
+        tx.execute(Statement::from_string(
+            tx.get_database_backend(),
+            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ;".to_string(),
+        ))
+        .await?;
+    }
+    get_multiple_compressed_leaf_proofs(&tx, batch_config, in_memory_trees, hashes).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Warms up the database connections used by the API, together with the in-memory state trees and
+/// the proof cache. Retries until it succeeds, since the API should not report ready before the
+/// database is reachable.
+pub async fn warm_up_database(
+    db: &DatabaseConnection,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
+) {
+    let start = Instant::now();
+    loop {
+        // The trees are loaded first, so that the connections do not all load them at once.
+        let warm_up = async {
+            warm_up_state_trees(db, batch_config, in_memory_trees).await?;
+            try_join_all((0..WARM_UP_CONNECTIONS).map(|_| run_warm_up_queries(db))).await
+        };
+        match warm_up.await {
+            Ok(_) => break,
+            Err(e) => {
+                error!("Failed to warm up database connections: {}", e);
+                tokio::time::sleep(WARM_UP_RETRY_INTERVAL).await;
+            }
+        }
+    }
+    info!("Warmed up database connections in {:?}", start.elapsed());
+}
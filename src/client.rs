@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::api::method::get_compressed_account::AccountResponse;
+use crate::api::method::get_compressed_account_proof::GetCompressedAccountProofResponse;
+use crate::api::method::get_compressed_accounts_by_owner::{
+    GetCompressedAccountsByOwnerRequest, GetCompressedAccountsByOwnerResponse,
+};
+use crate::api::method::get_compressed_address_info::{
+    GetCompressedAddressInfoRequest, GetCompressedAddressInfoResponse,
+};
+use crate::api::method::get_compressed_balance_by_owner::GetCompressedBalanceByOwnerRequest;
+use crate::api::method::get_compressed_mint_token_holders::{
+    GetCompressedMintTokenHoldersRequest, OwnerBalancesResponse,
+};
+use crate::api::method::get_compressed_token_account_balance::GetCompressedTokenAccountBalanceResponse;
+use crate::api::method::get_compressed_token_account_by_owner_and_mint::{
+    GetCompressedTokenAccountByOwnerAndMintRequest, TokenAccountListWithTotalResponse,
+};
+use crate::api::method::get_compressed_token_balances_by_owner::{
+    GetCompressedTokenBalancesByOwnerRequest, TokenBalancesResponse, TokenBalancesResponseV2,
+};
+use crate::api::method::get_compression_signatures_for_address::GetCompressionSignaturesForAddressRequest;
+use crate::api::method::get_compression_signatures_for_owner::GetCompressionSignaturesForOwnerRequest;
+use crate::api::method::get_compression_signatures_for_token_owner::GetCompressionSignaturesForTokenOwnerRequest;
+use crate::api::method::get_compression_stats::{
+    GetCompressionStatsRequest, GetCompressionStatsResponse,
+};
+use crate::api::method::get_historical_compressed_account_proof::GetHistoricalCompressedAccountProofRequest;
+use crate::api::method::get_multiple_compressed_account_proofs::{
+    GetMultipleCompressedAccountProofsResponse, HashList,
+};
+use crate::api::method::get_multiple_compressed_accounts::{
+    GetMultipleCompressedAccountsRequest, GetMultipleCompressedAccountsResponse,
+};
+use crate::api::method::get_multiple_new_address_proofs::{
+    AddressList, AddressListWithTrees, GetMultipleNewAddressProofsResponse,
+};
+use crate::api::method::get_top_compressed_sol_owners::GetTopCompressedSolOwnersRequest;
+use crate::api::method::get_transaction_with_compression_info::{
+    GetTransactionRequest, GetTransactionResponse,
+};
+use crate::api::method::get_validity_proof::{GetValidityProofRequest, GetValidityProofResponse};
+use crate::api::method::utils::{
+    AccountBalanceResponse, CompressedAccountRequest, GetCompressedTokenAccountsByDelegate,
+    GetCompressedTokenAccountsByOwner, GetLatestSignaturesRequest,
+    GetNonPaginatedSignaturesResponse, GetNonPaginatedSignaturesResponseWithError,
+    GetPaginatedSignaturesResponse, HashRequest, TokenAccountListResponse,
+};
+use crate::common::typedefs::unsigned_integer::UnsignedInteger;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{message} (code {code})")]
+    Rpc {
+        code: i64,
+        message: String,
+        /// The `data` of the error, see `ApiErrorData`.
+        data: Option<Value>,
+    },
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+impl ClientError {
+    /// Whether the API reported that the same request can succeed when it is retried later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Rpc { data, .. } => data
+                .as_ref()
+                .and_then(|data| data["retryable"].as_bool())
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+/// An async client for the Photon JSON-RPC API. Requests and responses are the types that the API
+/// itself is implemented with, so the client cannot drift from the schema of the server it was
+/// built with.
+pub struct PhotonClient {
+    http: reqwest::Client,
+    url: String,
+    next_id: AtomicU64,
+}
+
+impl PhotonClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_http_client(url, reqwest::Client::new())
+    }
+
+    /// Sends the requests with `http`, e.g. to configure timeouts or default headers.
+    pub fn with_http_client(url: impl Into<String>, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            url: url.into(),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    async fn call<R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Option<impl Serialize>,
+    ) -> Result<R, ClientError> {
+        let mut request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+        });
+        if let Some(params) = params {
+            request["params"] = serde_json::to_value(params)
+                .map_err(|e| ClientError::InvalidResponse(e.to_string()))?;
+        }
+        let response = self.http.post(&self.url).json(&request).send().await?;
+        // Throttled requests are answered with a JSON-RPC error, which tells whether to retry.
+        let response: RpcResponse<R> = match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => response,
+            _ => response.error_for_status()?,
+        }
+        .json()
+        .await?;
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(ClientError::Rpc {
+                code: error.code,
+                message: error.message,
+                data: error.data,
+            }),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(ClientError::InvalidResponse(format!(
+                "{} returned neither a result nor an error",
+                method
+            ))),
+        }
+    }
+
+    pub async fn get_compressed_account(
+        &self,
+        request: CompressedAccountRequest,
+    ) -> Result<AccountResponse, ClientError> {
+        self.call("getCompressedAccount", Some(request)).await
+    }
+
+    pub async fn get_multiple_compressed_accounts(
+        &self,
+        request: GetMultipleCompressedAccountsRequest,
+    ) -> Result<GetMultipleCompressedAccountsResponse, ClientError> {
+        self.call("getMultipleCompressedAccounts", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_accounts_by_owner(
+        &self,
+        request: GetCompressedAccountsByOwnerRequest,
+    ) -> Result<GetCompressedAccountsByOwnerResponse, ClientError> {
+        self.call("getCompressedAccountsByOwner", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_account_balance(
+        &self,
+        request: CompressedAccountRequest,
+    ) -> Result<AccountBalanceResponse, ClientError> {
+        self.call("getCompressedAccountBalance", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_balance_by_owner(
+        &self,
+        request: GetCompressedBalanceByOwnerRequest,
+    ) -> Result<AccountBalanceResponse, ClientError> {
+        self.call("getCompressedBalanceByOwner", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_account_proof(
+        &self,
+        request: HashRequest,
+    ) -> Result<GetCompressedAccountProofResponse, ClientError> {
+        self.call("getCompressedAccountProof", Some(request)).await
+    }
+
+    pub async fn get_historical_compressed_account_proof(
+        &self,
+        request: GetHistoricalCompressedAccountProofRequest,
+    ) -> Result<GetCompressedAccountProofResponse, ClientError> {
+        self.call("getHistoricalCompressedAccountProof", Some(request))
+            .await
+    }
+
+    pub async fn get_multiple_compressed_account_proofs(
+        &self,
+        request: HashList,
+    ) -> Result<GetMultipleCompressedAccountProofsResponse, ClientError> {
+        self.call("getMultipleCompressedAccountProofs", Some(request))
+            .await
+    }
+
+    pub async fn get_multiple_new_address_proofs(
+        &self,
+        request: AddressList,
+    ) -> Result<GetMultipleNewAddressProofsResponse, ClientError> {
+        self.call("getMultipleNewAddressProofs", Some(request))
+            .await
+    }
+
+    pub async fn get_multiple_new_address_proofs_v2(
+        &self,
+        request: AddressListWithTrees,
+    ) -> Result<GetMultipleNewAddressProofsResponse, ClientError> {
+        self.call("getMultipleNewAddressProofsV2", Some(request))
+            .await
+    }
+
+    pub async fn get_validity_proof(
+        &self,
+        request: GetValidityProofRequest,
+    ) -> Result<GetValidityProofResponse, ClientError> {
+        self.call("getValidityProof", Some(request)).await
+    }
+
+    pub async fn get_compressed_address_info(
+        &self,
+        request: GetCompressedAddressInfoRequest,
+    ) -> Result<GetCompressedAddressInfoResponse, ClientError> {
+        self.call("getCompressedAddressInfo", Some(request)).await
+    }
+
+    pub async fn get_compressed_token_accounts_by_owner(
+        &self,
+        request: GetCompressedTokenAccountsByOwner,
+    ) -> Result<TokenAccountListResponse, ClientError> {
+        self.call("getCompressedTokenAccountsByOwner", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_token_accounts_by_delegate(
+        &self,
+        request: GetCompressedTokenAccountsByDelegate,
+    ) -> Result<TokenAccountListResponse, ClientError> {
+        self.call("getCompressedTokenAccountsByDelegate", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_token_account_by_owner_and_mint(
+        &self,
+        request: GetCompressedTokenAccountByOwnerAndMintRequest,
+    ) -> Result<TokenAccountListWithTotalResponse, ClientError> {
+        self.call("getCompressedTokenAccountByOwnerAndMint", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_token_account_balance(
+        &self,
+        request: CompressedAccountRequest,
+    ) -> Result<GetCompressedTokenAccountBalanceResponse, ClientError> {
+        self.call("getCompressedTokenAccountBalance", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_token_balances_by_owner(
+        &self,
+        request: GetCompressedTokenBalancesByOwnerRequest,
+    ) -> Result<TokenBalancesResponse, ClientError> {
+        self.call("getCompressedTokenBalancesByOwner", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_token_balances_by_owner_v2(
+        &self,
+        request: GetCompressedTokenBalancesByOwnerRequest,
+    ) -> Result<TokenBalancesResponseV2, ClientError> {
+        self.call("getCompressedTokenBalancesByOwnerV2", Some(request))
+            .await
+    }
+
+    pub async fn get_compressed_mint_token_holders(
+        &self,
+        request: GetCompressedMintTokenHoldersRequest,
+    ) -> Result<OwnerBalancesResponse, ClientError> {
+        self.call("getCompressedMintTokenHolders", Some(request))
+            .await
+    }
+
+    pub async fn get_top_compressed_sol_owners(
+        &self,
+        request: GetTopCompressedSolOwnersRequest,
+    ) -> Result<OwnerBalancesResponse, ClientError> {
+        self.call("getTopCompressedSolOwners", Some(request)).await
+    }
+
+    pub async fn get_compression_signatures_for_account(
+        &self,
+        request: HashRequest,
+    ) -> Result<GetNonPaginatedSignaturesResponse, ClientError> {
+        self.call("getCompressionSignaturesForAccount", Some(request))
+            .await
+    }
+
+    pub async fn get_compression_signatures_for_address(
+        &self,
+        request: GetCompressionSignaturesForAddressRequest,
+    ) -> Result<GetPaginatedSignaturesResponse, ClientError> {
+        self.call("getCompressionSignaturesForAddress", Some(request))
+            .await
+    }
+
+    pub async fn get_compression_signatures_for_owner(
+        &self,
+        request: GetCompressionSignaturesForOwnerRequest,
+    ) -> Result<GetPaginatedSignaturesResponse, ClientError> {
+        self.call("getCompressionSignaturesForOwner", Some(request))
+            .await
+    }
+
+    pub async fn get_compression_signatures_for_token_owner(
+        &self,
+        request: GetCompressionSignaturesForTokenOwnerRequest,
+    ) -> Result<GetPaginatedSignaturesResponse, ClientError> {
+        self.call("getCompressionSignaturesForTokenOwner", Some(request))
+            .await
+    }
+
+    pub async fn get_latest_compression_signatures(
+        &self,
+        request: GetLatestSignaturesRequest,
+    ) -> Result<GetPaginatedSignaturesResponse, ClientError> {
+        self.call("getLatestCompressionSignatures", Some(request))
+            .await
+    }
+
+    pub async fn get_latest_non_voting_signatures(
+        &self,
+        request: GetLatestSignaturesRequest,
+    ) -> Result<GetNonPaginatedSignaturesResponseWithError, ClientError> {
+        self.call("getLatestNonVotingSignatures", Some(request))
+            .await
+    }
+
+    pub async fn get_transaction_with_compression_info(
+        &self,
+        request: GetTransactionRequest,
+    ) -> Result<GetTransactionResponse, ClientError> {
+        self.call("getTransactionWithCompressionInfo", Some(request))
+            .await
+    }
+
+    pub async fn get_compression_stats(
+        &self,
+        request: GetCompressionStatsRequest,
+    ) -> Result<GetCompressionStatsResponse, ClientError> {
+        self.call("getCompressionStats", Some(request)).await
+    }
+
+    pub async fn get_indexer_slot(&self) -> Result<UnsignedInteger, ClientError> {
+        self.call("getIndexerSlot", None::<()>).await
+    }
+
+    pub async fn get_indexer_health(&self) -> Result<String, ClientError> {
+        self.call("getIndexerHealth", None::<()>).await
+    }
+
+    pub async fn get_indexer_metadata(&self) -> Result<BTreeMap<String, String>, ClientError> {
+        self.call("getIndexerMetadata", None::<()>).await
+    }
+}
@@ -0,0 +1,34 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Zstd's default level, which compresses well without slowing down ingestion noticeably.
+const COMPRESSION_LEVEL: i32 = 3;
+// Below this size the zstd frame overhead outweighs any savings.
+const MIN_COMPRESSION_SIZE: usize = 128;
+
+static COMPRESSION_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables compression of newly persisted account data. Already persisted data is
+/// read correctly either way, since every row records whether its data is compressed.
+pub fn set_account_data_compression(enabled: bool) {
+    COMPRESSION_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns the account data to store and whether it is compressed. Data is only compressed if
+/// compression is enabled and actually makes it smaller.
+pub fn compress_account_data(data: Vec<u8>) -> (Vec<u8>, bool) {
+    if !COMPRESSION_ENABLED.load(Ordering::SeqCst) || data.len() < MIN_COMPRESSION_SIZE {
+        return (data, false);
+    }
+    match zstd::bulk::compress(&data, COMPRESSION_LEVEL) {
+        Ok(compressed) if compressed.len() < data.len() => (compressed, true),
+        _ => (data, false),
+    }
+}
+
+pub fn decompress_account_data(data: Vec<u8>, compressed: bool) -> io::Result<Vec<u8>> {
+    if !compressed {
+        return Ok(data);
+    }
+    zstd::stream::decode_all(data.as_slice())
+}
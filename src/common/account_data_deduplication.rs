@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+use sha2::{Digest, Sha256};
+
+use crate::dao::generated::{account_data, accounts};
+
+// Smaller data is stored inline, since the reference would take up about as much space.
+const MIN_DEDUPLICATION_SIZE: usize = 64;
+
+static DEDUPLICATION_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables storing newly persisted account data in the content addressed
+/// `account_data` table. Accounts that were persisted with either setting can always be read.
+pub fn set_account_data_deduplication(enabled: bool) {
+    DEDUPLICATION_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns the key under which the data is stored in `account_data`, or `None` if the data is
+/// stored inline in `accounts`.
+pub fn account_data_ref(data: &[u8]) -> Option<Vec<u8>> {
+    if !DEDUPLICATION_ENABLED.load(Ordering::SeqCst) || data.len() < MIN_DEDUPLICATION_SIZE {
+        return None;
+    }
+    Some(Sha256::digest(data).to_vec())
+}
+
+/// Fills in the data of accounts that reference the `account_data` table, so that the models can
+/// be parsed like accounts with inline data.
+pub async fn resolve_account_data<'a>(
+    conn: &impl ConnectionTrait,
+    models: impl IntoIterator<Item = &'a mut accounts::Model>,
+) -> Result<(), DbErr> {
+    let mut unresolved: Vec<&mut accounts::Model> = models
+        .into_iter()
+        .filter(|model| model.data.is_none() && model.data_ref.is_some())
+        .collect();
+    if unresolved.is_empty() {
+        return Ok(());
+    }
+    let data_refs = unresolved
+        .iter()
+        .filter_map(|model| model.data_ref.clone())
+        .collect::<Vec<_>>();
+    let data_by_ref: HashMap<Vec<u8>, account_data::Model> = account_data::Entity::find()
+        .filter(account_data::Column::Hash.is_in(data_refs))
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|model| (model.hash.clone(), model))
+        .collect();
+    for model in unresolved.iter_mut() {
+        let data_ref = model.data_ref.as_ref().unwrap();
+        let data = data_by_ref.get(data_ref).ok_or_else(|| {
+            DbErr::RecordNotFound(format!("Account data {} not found", hex::encode(data_ref)))
+        })?;
+        model.data = Some(data.data.clone());
+        model.data_compressed = data.data_compressed;
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::{ffi::OsString, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command};
+use serde_json::{Map, Value};
+
+// Environment variables are named after the argument, e.g. `PHOTON_DB_URL` for `--db-url`.
+const ENV_PREFIX: &str = "PHOTON_";
+
+/// Reads a flat TOML or YAML config file, depending on its extension, whose keys are the long
+/// names of the command line arguments, e.g. `db-url` or `db_url`.
+pub fn load_config_file(path: &Path) -> Result<Map<String, Value>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {:?}", path))?;
+    let config = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str::<Value>(&contents)?,
+        Some("yaml") | Some("yml") => serde_yaml::from_str::<Value>(&contents)?,
+        _ => bail!(
+            "Config file {:?} has to be a .toml, .yaml or .yml file",
+            path
+        ),
+    };
+    match config {
+        Value::Object(config) => Ok(config
+            .into_iter()
+            .map(|(key, value)| (key.replace('_', "-"), value))
+            .collect()),
+        Value::Null => Ok(Map::new()),
+        _ => bail!("Config file {:?} has to be a table of arguments", path),
+    }
+}
+
+fn env_var_name(arg_id: &str) -> String {
+    format!("{}{}", ENV_PREFIX, arg_id.to_uppercase().replace('-', "_"))
+}
+
+/// Returns the command line arguments that set the arguments of `command` which were not passed
+/// on the command line, taken from `PHOTON_*` environment variables or else from `config`. Command
+/// line arguments thereby take precedence over environment variables, which take precedence over
+/// the config file. Fails on keys of `config` that are not arguments of `command`.
+pub fn layered_args(
+    command: &Command,
+    matches: &ArgMatches,
+    mut config: Map<String, Value>,
+) -> Result<Vec<OsString>> {
+    let mut args = Vec::new();
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long().filter(|_| is_configurable(arg)) else {
+            continue;
+        };
+        let config_value = config.remove(long);
+        let id = arg.get_id().as_str();
+        if matches!(matches.value_source(id), Some(ValueSource::CommandLine)) {
+            continue;
+        }
+        let takes_values = arg.get_action().takes_values();
+        if let Ok(value) = std::env::var(env_var_name(id)) {
+            match takes_values {
+                true => args.push(format!("--{}={}", long, value).into()),
+                false if value == "true" || value == "1" => args.push(format!("--{}", long).into()),
+                false => {}
+            }
+            continue;
+        }
+        let values = match config_value {
+            None => continue,
+            Some(Value::Array(values)) => values,
+            Some(value) => vec![value],
+        };
+        for value in values {
+            match (value, takes_values) {
+                (Value::Bool(true), false) => args.push(format!("--{}", long).into()),
+                (Value::Bool(false), false) => {}
+                (Value::String(value), true) => args.push(format!("--{}={}", long, value).into()),
+                (value @ (Value::Number(_) | Value::Bool(_)), true) => {
+                    args.push(format!("--{}={}", long, value).into())
+                }
+                (value, _) => bail!("Invalid value {} for {} in config file", value, long),
+            }
+        }
+    }
+    if let Some(key) = config.keys().next() {
+        return Err(anyhow!("Unknown argument {} in config file", key));
+    }
+    Ok(args)
+}
+
+/// Renders the values of the arguments of `command` in `matches` as a TOML config file, which can
+/// be loaded again with `load_config_file`.
+pub fn render_config(command: &Command, matches: &ArgMatches, skip: &[&str]) -> Result<String> {
+    let mut config = toml::value::Table::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(long) = arg.get_long().filter(|_| is_configurable(arg)) else {
+            continue;
+        };
+        if skip.contains(&id) {
+            continue;
+        }
+        let Some(raw_values) = matches.get_raw(id) else {
+            continue;
+        };
+        let mut values = raw_values
+            .map(|value| {
+                let value = value.to_string_lossy();
+                if let Ok(value) = value.parse::<i64>() {
+                    toml::Value::Integer(value)
+                } else if let Ok(value) = value.parse::<bool>() {
+                    toml::Value::Boolean(value)
+                } else {
+                    toml::Value::String(value.to_string())
+                }
+            })
+            .collect::<Vec<_>>();
+        let value = match is_repeatable(arg) || values.len() != 1 {
+            true => toml::Value::Array(values),
+            false => values.remove(0),
+        };
+        config.insert(long.to_string(), value);
+    }
+    Ok(toml::to_string(&config)?)
+}
+
+fn is_configurable(arg: &Arg) -> bool {
+    !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version)
+}
+
+fn is_repeatable(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Append) || arg.get_value_delimiter().is_some()
+}
+
+#[test]
+fn test_layered_args() {
+    let mut command = Command::new("photon")
+        .arg(Arg::new("port").long("port").default_value("8784"))
+        .arg(Arg::new("db_url").long("db-url"))
+        .arg(Arg::new("rpc_url").long("rpc-url"))
+        .arg(
+            Arg::new("disable_api")
+                .long("disable-api")
+                .action(ArgAction::SetTrue),
+        );
+    command.build();
+    let matches = command
+        .clone()
+        .get_matches_from(["photon", "--db-url", "sqlite://cli"]);
+    let path = std::env::temp_dir().join("photon-test-layered-args.toml");
+    std::fs::write(
+        &path,
+        "port = 1\ndb_url = \"sqlite://file\"\nrpc-url = \"http://file\"\ndisable-api = true\n",
+    )
+    .unwrap();
+    let config = load_config_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    std::env::set_var("PHOTON_RPC_URL", "http://env");
+    let args = layered_args(&command, &matches, config).unwrap();
+    std::env::remove_var("PHOTON_RPC_URL");
+    assert_eq!(
+        args,
+        vec![
+            OsString::from("--port=1"),
+            OsString::from("--rpc-url=http://env"),
+            OsString::from("--disable-api"),
+        ]
+    );
+
+    let mut config = Map::new();
+    config.insert("unknown".to_string(), Value::Bool(true));
+    assert!(layered_args(&command, &matches, config).is_err());
+}
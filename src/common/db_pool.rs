@@ -0,0 +1,95 @@
+use std::{sync::Mutex, time::Duration};
+
+use cadence_macros::statsd_gauge;
+use sqlx::{pool::PoolOptions, Database, PgPool, SqlitePool};
+use tokio::task::JoinHandle;
+
+use crate::metric;
+
+use super::prometheus::{DB_POOL_CONNECTIONS, DB_POOL_MAX_CONNECTIONS};
+
+// The default of sqlx.
+pub const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+const POOL_METRICS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Settings of a database connection pool.
+#[derive(Debug, Clone)]
+pub struct DatabasePoolConfig {
+    pub max_connections: u32,
+    /// How long a query waits for a connection of an exhausted pool before it fails.
+    pub acquire_timeout: Duration,
+    /// How long connections stay open while idle. Idle connections are kept by default.
+    pub idle_timeout: Option<Duration>,
+    /// Postgres cancels statements that run longer than this. SQLite has no statement timeout.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl DatabasePoolConfig {
+    pub fn new(max_connections: u32) -> Self {
+        Self {
+            max_connections,
+            acquire_timeout: DEFAULT_ACQUIRE_TIMEOUT,
+            idle_timeout: None,
+            statement_timeout: None,
+        }
+    }
+
+    pub fn pool_options<DB: Database>(&self) -> PoolOptions<DB> {
+        PoolOptions::new()
+            .max_connections(self.max_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+    }
+}
+
+enum Pool {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+static POOLS: Mutex<Vec<(String, u32, Pool)>> = Mutex::new(Vec::new());
+
+/// Reports the utilization of `pool` under the name `name` from now on.
+pub fn register_pg_pool(name: &str, config: &DatabasePoolConfig, pool: PgPool) {
+    register_pool(name, config, Pool::Postgres(pool));
+}
+
+/// Reports the utilization of `pool` under the name `name` from now on.
+pub fn register_sqlite_pool(name: &str, config: &DatabasePoolConfig, pool: SqlitePool) {
+    register_pool(name, config, Pool::Sqlite(pool));
+}
+
+fn register_pool(name: &str, config: &DatabasePoolConfig, pool: Pool) {
+    POOLS
+        .lock()
+        .unwrap()
+        .push((name.to_string(), config.max_connections, pool));
+}
+
+/// Updates the gauges of the open, idle and in use connections of every registered pool.
+pub fn report_pool_metrics() {
+    for (name, max_connections, pool) in POOLS.lock().unwrap().iter() {
+        let (size, idle) = match pool {
+            Pool::Postgres(pool) => (pool.size(), pool.num_idle() as u32),
+            Pool::Sqlite(pool) => (pool.size(), pool.num_idle() as u32),
+        };
+        let in_use = size.saturating_sub(idle);
+        DB_POOL_CONNECTIONS.set(&[name, "idle"], idle as i64);
+        DB_POOL_CONNECTIONS.set(&[name, "in_use"], in_use as i64);
+        DB_POOL_MAX_CONNECTIONS.set(&[name], *max_connections as i64);
+        metric! {
+            statsd_gauge!("db_pool_idle_connections", idle as u64, "pool" => name);
+            statsd_gauge!("db_pool_in_use_connections", in_use as u64, "pool" => name);
+            statsd_gauge!("db_pool_max_connections", *max_connections as u64, "pool" => name);
+        }
+    }
+}
+
+pub fn continously_report_pool_metrics() -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            report_pool_metrics();
+            tokio::time::sleep(POOL_METRICS_INTERVAL).await;
+        }
+    })
+}
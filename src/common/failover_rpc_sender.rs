@@ -0,0 +1,201 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use cadence_macros::statsd_count;
+use log::warn;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result},
+    rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    rpc_request::{RpcError, RpcRequest},
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+
+use crate::{common::prometheus::RPC_ENDPOINT_HEALTHY, metric};
+
+// Weight of the latest request in the moving average of the latency of an endpoint.
+const LATENCY_SMOOTHING: f64 = 0.2;
+// Latencies that are older than this are not trusted anymore, so that an endpoint that was slow
+// is tried again eventually.
+const LATENCY_SAMPLE_TTL: Duration = Duration::from_secs(60);
+const INITIAL_FAILURE_COOLDOWN: Duration = Duration::from_secs(1);
+const MAX_FAILURE_COOLDOWN: Duration = Duration::from_secs(60);
+
+struct LatencySample {
+    average: Duration,
+    sampled_at: Instant,
+}
+
+#[derive(Default)]
+struct EndpointHealth {
+    // Methods differ widely in latency, e.g. getSlot and getBlock, so they are scored separately.
+    latencies: HashMap<String, LatencySample>,
+    consecutive_failures: u32,
+    cooldown_until: Option<Instant>,
+}
+
+impl EndpointHealth {
+    /// Endpoints that failed recently sort last, and the others by their latency for `method`.
+    /// Endpoints without a recent latency sort first, so that they are sampled.
+    fn score(&self, method: &str, now: Instant) -> (bool, Duration) {
+        let cooling_down = self.cooldown_until.is_some_and(|until| until > now);
+        let latency = match self.latencies.get(method) {
+            Some(sample) if now - sample.sampled_at < LATENCY_SAMPLE_TTL => sample.average,
+            _ => Duration::ZERO,
+        };
+        (cooling_down, latency)
+    }
+
+    fn record_success(&mut self, method: String, latency: Duration, now: Instant) {
+        self.consecutive_failures = 0;
+        self.cooldown_until = None;
+        let average = match self.latencies.get(&method) {
+            Some(sample) => {
+                sample.average.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING)
+            }
+            None => latency,
+        };
+        self.latencies.insert(
+            method,
+            LatencySample {
+                average,
+                sampled_at: now,
+            },
+        );
+    }
+
+    fn record_failure(&mut self, now: Instant) {
+        let cooldown = INITIAL_FAILURE_COOLDOWN
+            .saturating_mul(2u32.saturating_pow(self.consecutive_failures))
+            .min(MAX_FAILURE_COOLDOWN);
+        self.consecutive_failures += 1;
+        self.cooldown_until = Some(now + cooldown);
+    }
+}
+
+struct Endpoint<S> {
+    sender: S,
+    health: Mutex<EndpointHealth>,
+}
+
+/// Transport for `RpcClient` that spreads requests over several RPC endpoints. Each request goes to
+/// the endpoint with the lowest recent latency for its method, and fails over to the next endpoint
+/// if the endpoint cannot be reached, fails with a server error or reports itself as unhealthy.
+/// Endpoints that failed are avoided for an exponentially growing cooldown, so that a single flaky
+/// provider does not stall indexing. Errors that every endpoint would return, e.g. for skipped
+/// slots, are returned right away.
+pub struct FailoverRpcSender<S> {
+    endpoints: Vec<Endpoint<S>>,
+}
+
+impl<S> FailoverRpcSender<S> {
+    pub fn new(senders: Vec<S>) -> Self {
+        assert!(!senders.is_empty(), "At least one RPC endpoint is required");
+        let endpoints = senders
+            .into_iter()
+            .map(|sender| Endpoint {
+                sender,
+                health: Mutex::new(EndpointHealth::default()),
+            })
+            .collect();
+        Self { endpoints }
+    }
+
+    /// Indices of the endpoints in the order in which a request for `method` tries them.
+    fn endpoint_order(&self, method: &str) -> Vec<usize> {
+        let now = Instant::now();
+        let mut order: Vec<usize> = (0..self.endpoints.len()).collect();
+        // Stable, so that ties keep the configured order.
+        order.sort_by_cached_key(|&index| {
+            self.endpoints[index]
+                .health
+                .lock()
+                .unwrap()
+                .score(method, now)
+        });
+        order
+    }
+}
+
+#[async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for FailoverRpcSender<S> {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let method = request.to_string();
+        let order = self.endpoint_order(&method);
+        let mut last_error = None;
+        for (attempt, index) in order.into_iter().enumerate() {
+            let endpoint = &self.endpoints[index];
+            if attempt > 0 {
+                metric! {
+                    statsd_count!("rpc_endpoint_failover", 1, "method" => &method);
+                }
+            }
+            let started_at = Instant::now();
+            let result = endpoint.sender.send(request, params.clone()).await;
+            let now = Instant::now();
+            match result {
+                Err(e) if is_endpoint_failure(&e) => {
+                    warn!(
+                        "RPC endpoint {} failed {} request: {}",
+                        endpoint.sender.url(),
+                        method,
+                        e
+                    );
+                    endpoint.health.lock().unwrap().record_failure(now);
+                    RPC_ENDPOINT_HEALTHY.set(&[&index.to_string()], 0);
+                    last_error = Some(e);
+                }
+                result => {
+                    endpoint
+                        .health
+                        .lock()
+                        .unwrap()
+                        .record_success(method, now - started_at, now);
+                    RPC_ENDPOINT_HEALTHY.set(&[&index.to_string()], 1);
+                    return result;
+                }
+            }
+        }
+        Err(last_error.expect("At least one RPC endpoint is required"))
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.sender.get_transport_stats())
+            .fold(RpcTransportStats::default(), |total, stats| {
+                RpcTransportStats {
+                    request_count: total.request_count + stats.request_count,
+                    elapsed_time: total.elapsed_time + stats.elapsed_time,
+                    rate_limited_time: total.rate_limited_time + stats.rate_limited_time,
+                }
+            })
+    }
+
+    fn url(&self) -> String {
+        let order = self.endpoint_order(&RpcRequest::GetSlot.to_string());
+        self.endpoints[order[0]].sender.url()
+    }
+}
+
+/// Whether `error` is specific to the endpoint, so that another endpoint may succeed.
+fn is_endpoint_failure(error: &ClientError) -> bool {
+    match &error.kind {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(e) => e
+            .status()
+            .map(|status| status.as_u16() == 429 || status.is_server_error())
+            .unwrap_or(true),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QueryTrait, Set,
+};
+
+use crate::dao::generated::metadata;
+
+/// Keys of the `metadata` table. Subsystems that need to persist a few values should add a key
+/// here instead of creating a table of their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataKey {
+    /// Slot of the last state snapshot that was imported into the database.
+    LastStateSnapshotSlot,
+    /// First slot that the ingester indexed. Slots before it are not indexed.
+    IndexingStartSlot,
+    /// Fingerprint of the migrations of the last binary that ran against the database.
+    SchemaFingerprint,
+    FeatureFlag(String),
+    /// Position up to which an external sink has consumed the indexed data.
+    SinkCursor(String),
+    /// Secret that pagination cursors are signed with when no secret is configured. It is not
+    /// returned by `getIndexerMetadata`.
+    CursorSecret,
+}
+
+impl fmt::Display for MetadataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataKey::LastStateSnapshotSlot => write!(f, "last_state_snapshot_slot"),
+            MetadataKey::IndexingStartSlot => write!(f, "indexing_start_slot"),
+            MetadataKey::SchemaFingerprint => write!(f, "schema_fingerprint"),
+            MetadataKey::FeatureFlag(name) => write!(f, "feature_flag:{}", name),
+            MetadataKey::SinkCursor(name) => write!(f, "sink_cursor:{}", name),
+            MetadataKey::CursorSecret => write!(f, "cursor_secret"),
+        }
+    }
+}
+
+pub async fn get_metadata(
+    conn: &impl ConnectionTrait,
+    key: &MetadataKey,
+) -> Result<Option<String>, DbErr> {
+    Ok(metadata::Entity::find_by_id(key.to_string())
+        .one(conn)
+        .await?
+        .map(|model| model.value))
+}
+
+pub async fn set_metadata(
+    conn: &impl ConnectionTrait,
+    key: &MetadataKey,
+    value: String,
+) -> Result<(), DbErr> {
+    let query = metadata::Entity::insert(metadata::ActiveModel {
+        key: Set(key.to_string()),
+        value: Set(value),
+    })
+    .on_conflict(
+        OnConflict::column(metadata::Column::Key)
+            .update_column(metadata::Column::Value)
+            .to_owned(),
+    )
+    .build(conn.get_database_backend());
+    conn.execute(query).await?;
+    Ok(())
+}
+
+/// Returns all entries ordered by key, except for secrets.
+pub async fn fetch_all_metadata(
+    conn: &impl ConnectionTrait,
+) -> Result<Vec<(String, String)>, DbErr> {
+    Ok(metadata::Entity::find()
+        .filter(metadata::Column::Key.ne(MetadataKey::CursorSecret.to_string()))
+        .order_by_asc(metadata::Column::Key)
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|model| (model.key, model.value))
+        .collect())
+}
+
+async fn get_parsed_metadata<T: std::str::FromStr>(
+    conn: &impl ConnectionTrait,
+    key: &MetadataKey,
+) -> Result<Option<T>, DbErr> {
+    get_metadata(conn, key)
+        .await?
+        .map(|value| {
+            value.parse().map_err(|_| {
+                DbErr::Custom(format!("Invalid metadata value for {}: {}", key, value))
+            })
+        })
+        .transpose()
+}
+
+pub async fn get_last_state_snapshot_slot(
+    conn: &impl ConnectionTrait,
+) -> Result<Option<u64>, DbErr> {
+    get_parsed_metadata(conn, &MetadataKey::LastStateSnapshotSlot).await
+}
+
+pub async fn set_last_state_snapshot_slot(
+    conn: &impl ConnectionTrait,
+    slot: u64,
+) -> Result<(), DbErr> {
+    set_metadata(conn, &MetadataKey::LastStateSnapshotSlot, slot.to_string()).await
+}
+
+pub async fn get_indexing_start_slot(conn: &impl ConnectionTrait) -> Result<Option<u64>, DbErr> {
+    get_parsed_metadata(conn, &MetadataKey::IndexingStartSlot).await
+}
+
+/// Records that indexing starts at `slot`, unless an earlier start was already recorded, e.g.
+/// when the ingester is restarted from a later slot.
+pub async fn record_indexing_start_slot(
+    conn: &impl ConnectionTrait,
+    slot: u64,
+) -> Result<(), DbErr> {
+    match get_indexing_start_slot(conn).await? {
+        Some(start_slot) if start_slot <= slot => Ok(()),
+        _ => set_metadata(conn, &MetadataKey::IndexingStartSlot, slot.to_string()).await,
+    }
+}
+
+pub async fn get_schema_fingerprint(conn: &impl ConnectionTrait) -> Result<Option<String>, DbErr> {
+    get_metadata(conn, &MetadataKey::SchemaFingerprint).await
+}
+
+pub async fn set_schema_fingerprint(
+    conn: &impl ConnectionTrait,
+    fingerprint: String,
+) -> Result<(), DbErr> {
+    set_metadata(conn, &MetadataKey::SchemaFingerprint, fingerprint).await
+}
+
+pub async fn get_feature_flag(
+    conn: &impl ConnectionTrait,
+    name: &str,
+) -> Result<Option<bool>, DbErr> {
+    get_parsed_metadata(conn, &MetadataKey::FeatureFlag(name.to_string())).await
+}
+
+pub async fn set_feature_flag(
+    conn: &impl ConnectionTrait,
+    name: &str,
+    enabled: bool,
+) -> Result<(), DbErr> {
+    set_metadata(
+        conn,
+        &MetadataKey::FeatureFlag(name.to_string()),
+        enabled.to_string(),
+    )
+    .await
+}
+
+pub async fn get_sink_cursor(
+    conn: &impl ConnectionTrait,
+    sink: &str,
+) -> Result<Option<u64>, DbErr> {
+    get_parsed_metadata(conn, &MetadataKey::SinkCursor(sink.to_string())).await
+}
+
+pub async fn set_sink_cursor(
+    conn: &impl ConnectionTrait,
+    sink: &str,
+    cursor: u64,
+) -> Result<(), DbErr> {
+    set_metadata(
+        conn,
+        &MetadataKey::SinkCursor(sink.to_string()),
+        cursor.to_string(),
+    )
+    .await
+}
+
+/// Returns the secret that pagination cursors are signed with and generates it if there is none
+/// yet. Storing it in the database keeps cursors valid across restarts and on all instances that
+/// serve the same database.
+pub async fn get_or_create_cursor_secret(conn: &impl ConnectionTrait) -> Result<String, DbErr> {
+    if let Some(secret) = get_metadata(conn, &MetadataKey::CursorSecret).await? {
+        return Ok(secret);
+    }
+    // Instances that start at the same time all end up with the secret that was stored first.
+    let query = metadata::Entity::insert(metadata::ActiveModel {
+        key: Set(MetadataKey::CursorSecret.to_string()),
+        value: Set(hex::encode(rand::random::<[u8; 32]>())),
+    })
+    .on_conflict(
+        OnConflict::column(metadata::Column::Key)
+            .do_nothing()
+            .to_owned(),
+    )
+    .build(conn.get_database_backend());
+    conn.execute(query).await?;
+    get_metadata(conn, &MetadataKey::CursorSecret)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(MetadataKey::CursorSecret.to_string()))
+}
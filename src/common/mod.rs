@@ -5,14 +5,31 @@ use cadence::{BufferedUdpMetricSink, QueuingMetricSink, StatsdClient};
 use cadence_macros::set_global_default;
 use clap::{Parser, ValueEnum};
 use sea_orm::{DatabaseConnection, SqlxPostgresConnector};
-use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcBlockConfig};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::RpcClientConfig, rpc_config::RpcBlockConfig,
+};
+use solana_rpc_client::http_sender::HttpSender;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
-use sqlx::{
-    postgres::{PgConnectOptions, PgPoolOptions},
-    PgPool,
-};
+use sqlx::{postgres::PgConnectOptions, PgPool, Postgres};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use self::db_pool::DatabasePoolConfig;
+use self::failover_rpc_sender::FailoverRpcSender;
+use self::rate_limited_rpc_sender::{RateLimitedRpcSender, RpcRateLimitConfig};
+pub mod account_data_compression;
+pub mod account_data_deduplication;
+pub mod config_file;
+pub mod db_pool;
+pub mod failover_rpc_sender;
+pub mod metadata;
+pub mod prometheus;
+pub mod rate_limited_rpc_sender;
+pub mod shutdown;
+pub mod supervisor;
+pub mod telemetry;
 pub mod typedefs;
+pub mod unspent_hash_filter;
 
 pub fn relative_project_path(path: &str) -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path)
@@ -104,19 +121,38 @@ impl fmt::Display for LoggingFormat {
 }
 
 pub fn setup_logging(logging_format: LoggingFormat) {
+    setup_logging_with_otlp(logging_format, None);
+}
+
+/// Like `setup_logging`, and additionally exports spans to the OTLP collector at `otlp_endpoint`.
+pub fn setup_logging_with_otlp(logging_format: LoggingFormat, otlp_endpoint: Option<&str>) {
     let env_filter = env::var("RUST_LOG")
         .unwrap_or("info,sqlx=error,sea_orm_migration=error,jsonrpsee_server=warn".to_string());
-    let subscriber = tracing_subscriber::fmt().with_env_filter(env_filter);
+    let otlp_layer = otlp_endpoint.map(|endpoint| {
+        telemetry::otlp_layer(endpoint).expect("Failed to set up the OTLP exporter")
+    });
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new(env_filter))
+        .with(otlp_layer);
     match logging_format {
-        LoggingFormat::Standard => subscriber.init(),
-        LoggingFormat::Json => subscriber.json().init(),
+        LoggingFormat::Standard => subscriber.with(tracing_subscriber::fmt::layer()).init(),
+        LoggingFormat::Json => subscriber
+            .with(tracing_subscriber::fmt::layer().json())
+            .init(),
     }
 }
 
 pub async fn setup_pg_pool(database_url: &str, max_connections: u32) -> PgPool {
-    let options: PgConnectOptions = database_url.parse().unwrap();
-    PgPoolOptions::new()
-        .max_connections(max_connections)
+    setup_pg_pool_with_config(database_url, &DatabasePoolConfig::new(max_connections)).await
+}
+
+pub async fn setup_pg_pool_with_config(database_url: &str, config: &DatabasePoolConfig) -> PgPool {
+    let mut options: PgConnectOptions = database_url.parse().unwrap();
+    if let Some(statement_timeout) = config.statement_timeout {
+        options = options.options([("statement_timeout", statement_timeout.as_millis())]);
+    }
+    config
+        .pool_options::<Postgres>()
         .connect_with(options)
         .await
         .unwrap()
@@ -143,9 +179,33 @@ pub async fn fetch_current_slot_with_infinite_retry(client: &RpcClient) -> u64 {
 }
 
 pub fn get_rpc_client(rpc_url: &str) -> Arc<RpcClient> {
-    Arc::new(RpcClient::new_with_timeout_and_commitment(
-        rpc_url.to_string(),
-        Duration::from_secs(90),
-        CommitmentConfig::confirmed(),
+    get_rate_limited_rpc_client(rpc_url, RpcRateLimitConfig::default())
+}
+
+pub fn get_rate_limited_rpc_client(rpc_url: &str, config: RpcRateLimitConfig) -> Arc<RpcClient> {
+    let sender = RateLimitedRpcSender::new(
+        HttpSender::new_with_timeout(rpc_url.to_string(), Duration::from_secs(90)),
+        config,
+    );
+    Arc::new(RpcClient::new_sender(
+        sender,
+        RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+    ))
+}
+
+/// Like `get_rate_limited_rpc_client`, but fails over between the RPC endpoints at `rpc_urls`. The
+/// rate limit applies to the requests to all endpoints together.
+pub fn get_failover_rpc_client(rpc_urls: &[String], config: RpcRateLimitConfig) -> Arc<RpcClient> {
+    if let [rpc_url] = rpc_urls {
+        return get_rate_limited_rpc_client(rpc_url, config);
+    }
+    let senders = rpc_urls
+        .iter()
+        .map(|rpc_url| HttpSender::new_with_timeout(rpc_url.to_string(), Duration::from_secs(90)))
+        .collect();
+    let sender = RateLimitedRpcSender::new(FailoverRpcSender::new(senders), config);
+    Arc::new(RpcClient::new_sender(
+        sender,
+        RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
     ))
 }
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Buckets for durations in seconds, from 1ms to 10s.
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub static BLOCKS_INDEXED: Counter = Counter::new(
+    "photon_blocks_indexed_total",
+    "Number of blocks that were indexed",
+    &[],
+);
+pub static ACCOUNTS_INDEXED: Counter = Counter::new(
+    "photon_accounts_indexed_total",
+    "Number of compressed accounts that were created",
+    &[],
+);
+pub static INDEXER_LAG: Gauge = Gauge::new(
+    "photon_indexer_lag_slots",
+    "Number of slots that the indexer is behind the RPC node",
+    &[],
+);
+pub static PIPELINE_QUEUED_BLOCKS: Gauge = Gauge::new(
+    "photon_pipeline_queued_blocks",
+    "Number of fetched blocks that wait to be parsed or persisted",
+    &[],
+);
+pub static ROOT_MISMATCHES: Gauge = Gauge::new(
+    "photon_root_mismatches",
+    "Number of trees whose root did not match in the last root consistency check",
+    &[],
+);
+pub static DB_WRITE_DURATION: Histogram = Histogram::new(
+    "photon_db_write_duration_seconds",
+    "Time spent writing state updates to the database",
+    &[],
+    DURATION_BUCKETS,
+);
+pub static DB_POOL_CONNECTIONS: Gauge = Gauge::new(
+    "photon_db_pool_connections",
+    "Number of open database connections by pool and whether they are idle or in use",
+    &["pool", "state"],
+);
+pub static DB_POOL_MAX_CONNECTIONS: Gauge = Gauge::new(
+    "photon_db_pool_max_connections",
+    "Maximum number of connections of each database pool",
+    &["pool"],
+);
+pub static RPC_ENDPOINT_HEALTHY: Gauge = Gauge::new(
+    "photon_rpc_endpoint_healthy",
+    "Whether the last request to each RPC endpoint, by its position in the configured list, succeeded",
+    &["endpoint"],
+);
+pub static API_REQUESTS: Counter = Counter::new(
+    "photon_api_requests_total",
+    "Number of API requests by method and result",
+    &["method", "result"],
+);
+pub static API_REQUEST_DURATION: Histogram = Histogram::new(
+    "photon_api_request_duration_seconds",
+    "Time spent handling API requests by method",
+    &["method"],
+    DURATION_BUCKETS,
+);
+
+/// Renders all metrics in the Prometheus text exposition format.
+pub fn render_metrics() -> String {
+    let mut output = String::new();
+    BLOCKS_INDEXED.render(&mut output);
+    ACCOUNTS_INDEXED.render(&mut output);
+    INDEXER_LAG.render(&mut output);
+    PIPELINE_QUEUED_BLOCKS.render(&mut output);
+    ROOT_MISMATCHES.render(&mut output);
+    DB_WRITE_DURATION.render(&mut output);
+    DB_POOL_CONNECTIONS.render(&mut output);
+    DB_POOL_MAX_CONNECTIONS.render(&mut output);
+    RPC_ENDPOINT_HEALTHY.render(&mut output);
+    API_REQUESTS.render(&mut output);
+    API_REQUEST_DURATION.render(&mut output);
+    output
+}
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    label_names: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl Counter {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        label_names: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            label_names,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc_by(&self, label_values: &[&str], value: u64) {
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .entry(to_owned(label_values))
+            .or_default() += value;
+    }
+
+    fn render(&self, output: &mut String) {
+        write_header(output, self.name, self.help, "counter");
+        for (label_values, value) in self.values.lock().unwrap().iter() {
+            let labels = format_labels(self.label_names, label_values, None);
+            writeln!(output, "{}{} {}", self.name, labels, value).unwrap();
+        }
+    }
+}
+
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    label_names: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, i64>>,
+}
+
+impl Gauge {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        label_names: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            label_names,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn set(&self, label_values: &[&str], value: i64) {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(to_owned(label_values), value);
+    }
+
+    fn render(&self, output: &mut String) {
+        write_header(output, self.name, self.help, "gauge");
+        for (label_values, value) in self.values.lock().unwrap().iter() {
+            let labels = format_labels(self.label_names, label_values, None);
+            writeln!(output, "{}{} {}", self.name, labels, value).unwrap();
+        }
+    }
+}
+
+#[derive(Default)]
+struct HistogramValues {
+    // Non-cumulative counts per bucket. The last entry counts the observations above all bounds.
+    bucket_counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    label_names: &'static [&'static str],
+    buckets: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, HistogramValues>>,
+}
+
+impl Histogram {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        label_names: &'static [&'static str],
+        buckets: &'static [f64],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            label_names,
+            buckets,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, label_values: &[&str], value: f64) {
+        let mut values = self.values.lock().unwrap();
+        let values = values
+            .entry(to_owned(label_values))
+            .or_insert_with(|| HistogramValues {
+                bucket_counts: vec![0; self.buckets.len() + 1],
+                ..Default::default()
+            });
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.buckets.len());
+        values.bucket_counts[bucket] += 1;
+        values.sum += value;
+        values.count += 1;
+    }
+
+    pub fn observe_duration(&self, label_values: &[&str], duration: Duration) {
+        self.observe(label_values, duration.as_secs_f64());
+    }
+
+    fn render(&self, output: &mut String) {
+        write_header(output, self.name, self.help, "histogram");
+        for (label_values, values) in self.values.lock().unwrap().iter() {
+            let mut cumulative_count = 0;
+            let bounds = self
+                .buckets
+                .iter()
+                .map(|bound| bound.to_string())
+                .chain(std::iter::once("+Inf".to_string()));
+            for (bound, bucket_count) in bounds.zip(&values.bucket_counts) {
+                cumulative_count += bucket_count;
+                let labels = format_labels(self.label_names, label_values, Some(&bound));
+                writeln!(
+                    output,
+                    "{}_bucket{} {}",
+                    self.name, labels, cumulative_count
+                )
+                .unwrap();
+            }
+            let labels = format_labels(self.label_names, label_values, None);
+            writeln!(output, "{}_sum{} {}", self.name, labels, values.sum).unwrap();
+            writeln!(output, "{}_count{} {}", self.name, labels, values.count).unwrap();
+        }
+    }
+}
+
+fn to_owned(label_values: &[&str]) -> Vec<String> {
+    label_values.iter().map(|value| value.to_string()).collect()
+}
+
+fn write_header(output: &mut String, name: &str, help: &str, metric_type: &str) {
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} {}", name, metric_type).unwrap();
+}
+
+fn format_labels(label_names: &[&str], label_values: &[String], le: Option<&str>) -> String {
+    let labels = label_names
+        .iter()
+        .zip(label_values)
+        .map(|(name, value)| (*name, value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
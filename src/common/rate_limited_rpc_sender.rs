@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cadence_macros::statsd_count;
+use log::debug;
+use rand::Rng;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result},
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client::http_sender::HttpSender;
+use tokio::{
+    sync::Mutex,
+    time::{sleep, sleep_until, Instant},
+};
+
+use crate::metric;
+
+/// Limits for the requests that Photon sends to an RPC node.
+#[derive(Debug, Clone)]
+pub struct RpcRateLimitConfig {
+    /// Requests are spread out so that at most this many are sent per second. Unlimited if `None`.
+    pub requests_per_second: Option<u32>,
+    /// How often a request that was throttled or failed with a server error is retried before the
+    /// error is returned to the caller.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RpcRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: None,
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// HTTP transport for `RpcClient` that limits the request rate and retries requests that the node
+/// throttled (429) or failed (5xx) with jittered exponential backoff.
+pub struct RateLimitedRpcSender<S = HttpSender> {
+    inner: S,
+    config: RpcRateLimitConfig,
+    next_request_at: Mutex<Instant>,
+}
+
+impl<S> RateLimitedRpcSender<S> {
+    pub fn new(inner: S, config: RpcRateLimitConfig) -> Self {
+        Self {
+            inner,
+            config,
+            next_request_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait_for_rate_limit(&self) {
+        let Some(requests_per_second) = self.config.requests_per_second else {
+            return;
+        };
+        let request_at = {
+            let mut next_request_at = self.next_request_at.lock().await;
+            let request_at = (*next_request_at).max(Instant::now());
+            *next_request_at = request_at + Duration::from_secs(1) / requests_per_second.max(1);
+            request_at
+        };
+        sleep_until(request_at).await;
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .config
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.config.max_backoff);
+        // Full jitter in the upper half, so that concurrent requests that were throttled together
+        // do not retry together.
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+#[async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for RateLimitedRpcSender<S> {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let mut retry = 0;
+        loop {
+            self.wait_for_rate_limit().await;
+            match self.inner.send(request, params.clone()).await {
+                Err(e) if retry < self.config.max_retries && is_retryable(&e) => {
+                    let backoff = self.backoff(retry);
+                    debug!(
+                        "RPC request {} failed: {}. Retrying in {:?}",
+                        request, e, backoff
+                    );
+                    let method = request.to_string();
+                    metric! {
+                        statsd_count!("rpc_request_retried", 1, "method" => &method);
+                    }
+                    sleep(backoff).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+fn is_retryable(error: &ClientError) -> bool {
+    match &error.kind {
+        ClientErrorKind::Reqwest(e) => e
+            .status()
+            .map(|status| status.as_u16() == 429 || status.is_server_error())
+            .unwrap_or_default(),
+        _ => false,
+    }
+}
@@ -0,0 +1,37 @@
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+
+static SHUTDOWN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+/// Asks the components to stop at the next point where they can do so without losing work. The
+/// indexer finishes persisting the batch it is writing, and the API server stops accepting
+/// connections.
+pub fn request_shutdown() {
+    SHUTDOWN.send_replace(true);
+}
+
+pub fn is_shutdown_requested() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// Resolves once a shutdown has been requested.
+pub async fn shutdown_requested() {
+    let mut receiver = SHUTDOWN.subscribe();
+    // The sender is static, so the channel is never closed.
+    let _ = receiver.wait_for(|shutdown| *shutdown).await;
+}
+
+/// Resolves on SIGINT, or on SIGTERM on Unix, which is how container orchestrators stop processes.
+pub async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = sigterm.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
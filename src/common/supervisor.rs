@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+
+use cadence_macros::statsd_count;
+use log::{error, info, warn};
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::metric;
+
+use super::shutdown::is_shutdown_requested;
+
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+// A component that ran for this long before failing is considered to have recovered, so that a
+// failure days later is not delayed by the backoff of earlier failures.
+const HEALTHY_RUN_DURATION: Duration = Duration::from_secs(300);
+
+// Aborts the running component when the supervisor is aborted, e.g. on shutdown.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn panic_message(error: JoinError) -> String {
+    match error.try_into_panic() {
+        Ok(panic) => match panic.downcast::<String>() {
+            Ok(message) => *message,
+            Err(panic) => match panic.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "unknown panic payload".to_string(),
+            },
+        },
+        Err(error) => error.to_string(),
+    }
+}
+
+/// Runs a long-lived component of the binary and restarts it with exponential backoff whenever it
+/// panics or exits, so that a failure of one component does not leave the process running without
+/// it. `start` is called for every (re)start and has to spawn the component.
+pub fn supervise<F>(component: &'static str, mut start: F) -> JoinHandle<()>
+where
+    F: FnMut() -> JoinHandle<()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut backoff = INITIAL_RESTART_BACKOFF;
+        loop {
+            let started_at = Instant::now();
+            let mut task = AbortOnDrop(start());
+            let result = (&mut task.0).await;
+            // Components that stop on shutdown are not restarted.
+            if result.is_ok() && is_shutdown_requested() {
+                info!("{} stopped", component);
+                return;
+            }
+            match result {
+                Ok(()) => warn!("{} exited unexpectedly", component),
+                Err(e) => {
+                    error!("{} panicked: {}", component, panic_message(e));
+                    metric! {
+                        statsd_count!("component_panic", 1, "component" => component);
+                    }
+                }
+            }
+            if started_at.elapsed() >= HEALTHY_RUN_DURATION {
+                backoff = INITIAL_RESTART_BACKOFF;
+            }
+            warn!("Restarting {} in {:?}", component, backoff);
+            metric! {
+                statsd_count!("component_restart", 1, "component" => component);
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+        }
+    })
+}
@@ -0,0 +1,35 @@
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+const SERVICE_NAME: &str = "photon";
+
+/// Returns a layer that exports the spans of the indexing pipeline and of the API methods to the
+/// OTLP gRPC collector at `endpoint`, e.g. `http://localhost:4317`. Spans are exported in batches
+/// from a background task, so this has to be called from within the Tokio runtime.
+pub fn otlp_layer<S>(endpoint: &str) -> anyhow::Result<OpenTelemetryLayer<S, trace::Tracer>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)])),
+        )
+        .install_batch(runtime::Tokio)?;
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Exports the spans that are still buffered. Spans that end afterwards are dropped.
+pub fn shutdown_telemetry() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -1,27 +1,27 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use utoipa::ToSchema;
 
 use super::{
     bs64_string::Base64String, hash::Hash, serializable_pubkey::SerializablePubkey,
-    unsigned_integer::UnsignedInteger,
+    u64_string::U64String, unsigned_integer::UnsignedInteger,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Account {
     pub hash: Hash,
     pub address: Option<SerializablePubkey>,
     pub data: Option<AccountData>,
     pub owner: SerializablePubkey,
-    pub lamports: UnsignedInteger,
+    pub lamports: U64String,
     pub tree: SerializablePubkey,
     pub leaf_index: UnsignedInteger,
     pub seq: UnsignedInteger,
     pub slot_created: UnsignedInteger,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct AccountData {
     pub discriminator: UnsignedInteger,
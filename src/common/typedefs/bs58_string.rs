@@ -1,10 +1,24 @@
-use bs58;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use utoipa::{
     openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
     ToSchema,
 };
 
+/// Encodes bytes with the Bitcoin alphabet, which Solana uses for keys and signatures. All base58
+/// values that Photon serves go through here, so that responses are byte-for-byte identical across
+/// builds instead of depending on the default alphabet of the linked bs58 version.
+pub fn encode_base58(bytes: impl AsRef<[u8]>) -> String {
+    bs58::encode(bytes)
+        .with_alphabet(bs58::Alphabet::BITCOIN)
+        .into_string()
+}
+
+pub fn decode_base58(value: impl AsRef<[u8]>) -> Result<Vec<u8>, bs58::decode::Error> {
+    bs58::decode(value)
+        .with_alphabet(bs58::Alphabet::BITCOIN)
+        .into_vec()
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Base58String(pub Vec<u8>);
 
@@ -13,8 +27,7 @@ impl Serialize for Base58String {
     where
         S: Serializer,
     {
-        let base58_str = bs58::encode(&self.0).into_string();
-        serializer.serialize_str(&base58_str)
+        serializer.serialize_str(&encode_base58(&self.0))
     }
 }
 
@@ -24,9 +37,7 @@ impl<'de> Deserialize<'de> for Base58String {
         D: Deserializer<'de>,
     {
         let s: String = Deserialize::deserialize(deserializer)?;
-        let bytes = bs58::decode(&s)
-            .into_vec()
-            .map_err(serde::de::Error::custom)?;
+        let bytes = decode_base58(&s).map_err(serde::de::Error::custom)?;
         Ok(Base58String(bytes))
     }
 }
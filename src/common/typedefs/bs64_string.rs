@@ -1,5 +1,5 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-use serde::Serialize;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use utoipa::{
     openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
     ToSchema,
@@ -21,6 +21,18 @@ impl Serialize for Base64String {
     }
 }
 
+impl<'de> Deserialize<'de> for Base64String {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+        #[allow(deprecated)]
+        let bytes = base64::decode(&s).map_err(de::Error::custom)?;
+        Ok(Base64String(bytes))
+    }
+}
+
 impl<'__s> ToSchema<'__s> for Base64String {
     fn schema() -> (&'__s str, RefOr<Schema>) {
         let example = Some(serde_json::Value::String(
@@ -0,0 +1,135 @@
+use std::sync::Mutex;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+use utoipa::{
+    openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
+    ToSchema,
+};
+
+// Length of the truncated HMAC that is appended to the sort key.
+const TAG_LENGTH: usize = 8;
+
+// Until a secret is set, cursors are signed with a random secret, so that they cannot be forged
+// but are only valid for the process that issued them. The API server sets a secret that is
+// stable across restarts and instances.
+static CURSOR_SECRET: Lazy<Mutex<Vec<u8>>> =
+    Lazy::new(|| Mutex::new(rand::random::<[u8; 32]>().to_vec()));
+
+/// Sets the secret that cursors are signed with. Instances that serve the same clients need the
+/// same secret.
+pub fn set_cursor_secret(secret: &str) {
+    *CURSOR_SECRET.lock().unwrap() = secret.as_bytes().to_vec();
+}
+
+fn mac(key: &[u8]) -> Hmac<Sha256> {
+    let secret = CURSOR_SECRET.lock().unwrap();
+    let mut mac = Hmac::<Sha256>::new_from_slice(&secret).expect("HMAC accepts keys of any length");
+    mac.update(key);
+    mac
+}
+
+fn compute_tag(key: &[u8]) -> [u8; TAG_LENGTH] {
+    let mut tag = [0u8; TAG_LENGTH];
+    tag.copy_from_slice(&mac(key).finalize().into_bytes()[..TAG_LENGTH]);
+    tag
+}
+
+/// Opaque pagination cursor shared by all paginated endpoints. It holds the sort key of the last
+/// item of a page, e.g. the slot and signature of a transaction, so that the next page starts
+/// after that item even if rows were inserted in between. Clients receive the sort key followed
+/// by a truncated HMAC of it, base64 encoded, and cursors whose HMAC does not match are rejected.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cursor(pub Vec<u8>);
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let mut bytes = self.0.clone();
+        bytes.extend_from_slice(&compute_tag(&self.0));
+        STANDARD.encode(bytes)
+    }
+
+    pub fn decode(value: &str) -> Result<Self, String> {
+        let bytes = STANDARD
+            .decode(value)
+            .map_err(|_| format!("Invalid cursor {}", value))?;
+        if bytes.len() < TAG_LENGTH {
+            return Err(format!("Invalid cursor {}", value));
+        }
+        let (key, tag) = bytes.split_at(bytes.len() - TAG_LENGTH);
+        // Compares in constant time, so that the tag cannot be guessed byte by byte.
+        mac(key)
+            .verify_truncated_left(tag)
+            .map_err(|_| format!("Invalid cursor {}", value))?;
+        Ok(Cursor(key.to_vec()))
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+        Cursor::decode(&s).map_err(de::Error::custom)
+    }
+}
+
+impl<'__s> ToSchema<'__s> for Cursor {
+    fn schema() -> (&'__s str, RefOr<Schema>) {
+        let example = Some(serde_json::Value::String(
+            "AAAAAAAAAAAJIm3tBJ3TYw==".to_string(),
+        ));
+        let schema = Schema::Object(
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(
+                    "An opaque pagination cursor. Pass the cursor of a page to get the next page.",
+                ))
+                .example(example)
+                .build(),
+        );
+
+        ("Cursor", RefOr::T(schema))
+    }
+
+    fn aliases() -> Vec<(&'static str, utoipa::openapi::schema::Schema)> {
+        Vec::new()
+    }
+}
+
+#[test]
+fn test_cursor_round_trip() {
+    let cursor = Cursor(vec![1, 2, 3, 4]);
+    let serialized = serde_json::to_string(&cursor).unwrap();
+    assert_eq!(serde_json::from_str::<Cursor>(&serialized).unwrap(), cursor);
+
+    let mut bytes = STANDARD.decode(cursor.encode()).unwrap();
+    bytes[0] ^= 1;
+    assert!(Cursor::decode(&STANDARD.encode(&bytes)).is_err());
+    assert!(Cursor::decode("AQID").is_err());
+    assert!(Cursor::decode("not a cursor").is_err());
+
+    // Cursors cannot be forged without knowing the secret, e.g. by signing them with an empty one.
+    let mut forged = cursor.0.clone();
+    forged.extend_from_slice(
+        &Hmac::<Sha256>::new_from_slice(&[])
+            .unwrap()
+            .chain_update(&cursor.0)
+            .finalize()
+            .into_bytes()[..TAG_LENGTH],
+    );
+    assert!(Cursor::decode(&STANDARD.encode(forged)).is_err());
+}
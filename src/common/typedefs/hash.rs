@@ -1,5 +1,8 @@
 use core::fmt;
 use std::mem;
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 
 use serde::{Deserialize, Serialize};
 use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
@@ -12,6 +15,8 @@ use serde::Deserializer;
 use solana_sdk::pubkey::Pubkey;
 use thiserror::Error;
 
+use super::bs58_string::{decode_base58, encode_base58};
+
 // Maximum length of a 32 byte base58 encoded hash
 const MAX_BASE58_LEN: usize = 44;
 
@@ -31,7 +36,7 @@ impl Hash {
     }
 
     pub fn to_base58(&self) -> String {
-        bs58::encode(self.0).into_string()
+        encode_base58(self.0)
     }
 
     pub fn new_unique() -> Self {
@@ -45,7 +50,7 @@ impl<'__s> ToSchema<'__s> for Hash {
         let schema = Schema::Object(
             ObjectBuilder::new()
                 .schema_type(SchemaType::String)
-                .description(Some("A 32-byte hash represented as a base58 string."))
+                .description(Some("A 32-byte hash represented as a base58 string. Base64 strings are accepted as input as well."))
                 .example(Some(serde_json::Value::String(
                     Hash::try_from("11111112cMQwSC9qirWGjZM6gLGwW69X22mqwLLGP")
                         .unwrap()
@@ -77,14 +82,20 @@ impl TryFrom<&str> for Hash {
         if s.len() > MAX_BASE58_LEN {
             return Err(ParseHashError::WrongSize);
         }
-        let bytes = bs58::decode(s)
-            .into_vec()
-            .map_err(|_| ParseHashError::Invalid)?;
+        let bytes = decode_base58(s).map_err(|_| ParseHashError::Invalid)?;
 
         bytes.try_into()
     }
 }
 
+impl FromStr for Hash {
+    type Err = ParseHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hash::try_from(s)
+    }
+}
+
 impl From<Hash> for Vec<u8> {
     fn from(val: Hash) -> Self {
         val.0.to_vec()
@@ -140,11 +151,18 @@ impl<'de> Visitor<'de> for Base58VisitorHash {
     type Value = Hash;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("a base58 encoded string")
+        formatter.write_str("a base58 or base64 encoded string")
     }
 
     fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
-        Hash::try_from(value).map_err(|e| E::custom(e.to_string()))
+        // Hashes used to be base64 encoded, which is still accepted. The padding of a base64
+        // encoded hash is not part of the base58 alphabet, so the two encodings do not overlap.
+        Hash::try_from(value)
+            .or_else(|e| match STANDARD.decode(value) {
+                Ok(bytes) => Hash::try_from(bytes),
+                Err(_) => Err(e),
+            })
+            .map_err(|e| E::custom(e.to_string()))
     }
 }
 
@@ -156,8 +174,7 @@ impl<'de> Deserialize<'de> for Hash {
 
 impl Serialize for Hash {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let base58_string = bs58::encode(self.0).into_string();
-        serializer.serialize_str(&base58_string)
+        serializer.serialize_str(&encode_base58(self.0))
     }
 }
 
@@ -169,3 +186,13 @@ fn test_serialization() {
     let deserialized: Hash = serde_json::from_str(&serialized).unwrap();
     assert_eq!(hash, deserialized);
 }
+
+#[test]
+fn test_base64_deserialization() {
+    let hash = Hash::new_unique();
+    let base64_encoded = serde_json::to_string(&STANDARD.encode(hash.0)).unwrap();
+    let deserialized: Hash = serde_json::from_str(&base64_encoded).unwrap();
+    assert_eq!(hash, deserialized);
+    assert_eq!(Hash::from_str(&hash.to_string()).unwrap(), hash);
+    assert!(serde_json::from_str::<Hash>("\"not a hash\"").is_err());
+}
@@ -1,9 +1,11 @@
 pub mod account;
 pub mod bs58_string;
 pub mod bs64_string;
+pub mod cursor;
 pub mod hash;
 pub mod serializable_pubkey;
 pub mod serializable_signature;
 pub mod token_data;
+pub mod u64_string;
 pub mod unix_timestamp;
 pub mod unsigned_integer;
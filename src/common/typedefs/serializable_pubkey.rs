@@ -17,6 +17,8 @@ use utoipa::ToSchema;
 
 use std::convert::TryFrom;
 
+use super::bs58_string::encode_base58;
+
 #[derive(Default, Clone, PartialEq, Eq, Hash, Copy)]
 /// A Solana public key.
 pub struct SerializablePubkey(pub SolanaPubkey);
@@ -51,8 +53,9 @@ impl anchor_lang::AnchorSerialize for SerializablePubkey {
 
 impl<'__s> ToSchema<'__s> for SerializablePubkey {
     fn schema() -> (&'__s str, RefOr<Schema>) {
+        // A fixed key, so that the generated specs do not change between runs.
         let example = Some(serde_json::Value::String(
-            SerializablePubkey(SolanaPubkey::new_unique()).to_string(),
+            "11111116EPqoQskEM2Pddp8KTL9JdYEBZMGF3aq7V".to_string(),
         ));
         let schema = Schema::Object(
             ObjectBuilder::new()
@@ -147,8 +150,7 @@ impl<'de> Deserialize<'de> for SerializablePubkey {
 
 impl Serialize for SerializablePubkey {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let base58_string = bs58::encode(self.0).into_string();
-        serializer.serialize_str(&base58_string)
+        serializer.serialize_str(&encode_base58(self.0))
     }
 }
 
@@ -10,6 +10,8 @@ use utoipa::{
     ToSchema,
 };
 
+use super::bs58_string::encode_base58;
+
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct SerializableSignature(pub Signature);
 
@@ -37,8 +39,7 @@ impl<'de> Deserialize<'de> for SerializableSignature {
 
 impl Serialize for SerializableSignature {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let base58_string = bs58::encode(self.0).into_string();
-        serializer.serialize_str(&base58_string)
+        serializer.serialize_str(&encode_base58(self.0))
     }
 }
 
@@ -1,11 +1,10 @@
 use anchor_lang::{AnchorDeserialize, AnchorSerialize};
 use num_enum::TryFromPrimitive;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use super::{
-    bs64_string::Base64String, serializable_pubkey::SerializablePubkey,
-    unsigned_integer::UnsignedInteger,
+    bs64_string::Base64String, serializable_pubkey::SerializablePubkey, u64_string::U64String,
 };
 
 #[derive(
@@ -19,6 +18,7 @@ use super::{
     TryFromPrimitive,
     ToSchema,
     Serialize,
+    Deserialize,
 )]
 #[repr(u8)]
 #[derive(Default)]
@@ -31,7 +31,16 @@ pub enum AccountState {
 }
 
 #[derive(
-    Debug, PartialEq, Eq, AnchorDeserialize, AnchorSerialize, Clone, ToSchema, Serialize, Default,
+    Debug,
+    PartialEq,
+    Eq,
+    AnchorDeserialize,
+    AnchorSerialize,
+    Clone,
+    ToSchema,
+    Serialize,
+    Deserialize,
+    Default,
 )]
 #[serde(rename_all = "camelCase")]
 pub struct TokenData {
@@ -40,7 +49,7 @@ pub struct TokenData {
     /// The owner of this account.
     pub owner: SerializablePubkey,
     /// The amount of tokens this account holds.
-    pub amount: UnsignedInteger,
+    pub amount: U64String,
     /// If `delegate` is `Some` then `delegated_amount` represents
     /// the amount authorized by the delegate
     pub delegate: Option<SerializablePubkey>,
@@ -48,4 +57,88 @@ pub struct TokenData {
     pub state: AccountState,
     /// Placeholder for TokenExtension tlv data (unimplemented)
     pub tlv: Option<Base64String>,
+    /// Extensions parsed from `tlv`. Not part of the on-chain layout.
+    #[borsh_skip]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<TokenExtensions>,
+}
+
+// Token-2022 extension types, see `ExtensionType` in spl-token-2022.
+const TRANSFER_FEE_AMOUNT_EXTENSION: u16 = 2;
+const PERMANENT_DELEGATE_EXTENSION: u16 = 12;
+const METADATA_POINTER_EXTENSION: u16 = 18;
+
+/// Token-2022 extension fields of a token account. Extensions that Photon does not parse are
+/// only available in the raw `tlv` data.
+#[derive(Debug, PartialEq, Eq, Clone, ToSchema, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenExtensions {
+    /// Transfer fees withheld in the account.
+    pub withheld_transfer_fee: Option<U64String>,
+    /// Delegate that can transfer and burn tokens of any account of the mint.
+    pub permanent_delegate: Option<SerializablePubkey>,
+    pub metadata_pointer: Option<MetadataPointer>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, ToSchema, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataPointer {
+    /// Authority that can set the metadata address.
+    pub authority: Option<SerializablePubkey>,
+    /// Account that holds the token metadata.
+    pub metadata_address: Option<SerializablePubkey>,
+}
+
+impl TokenExtensions {
+    /// Parses the extensions from TLV data in the Token-2022 layout, i.e. entries of a little
+    /// endian u16 type, a little endian u16 length and the value. Returns `None` if no field is
+    /// set, e.g. because none of the entries is an extension that Photon parses.
+    pub fn parse(tlv: &[u8]) -> Result<Option<Self>, String> {
+        let mut extensions = TokenExtensions::default();
+        let mut rest = tlv;
+        while !rest.is_empty() {
+            if rest.len() < 4 {
+                return Err(format!(
+                    "Truncated TLV entry header of {} bytes",
+                    rest.len()
+                ));
+            }
+            let extension_type = u16::from_le_bytes([rest[0], rest[1]]);
+            let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+            let value = rest
+                .get(4..4 + len)
+                .ok_or_else(|| format!("Truncated value of TLV extension {}", extension_type))?;
+            rest = &rest[4 + len..];
+            let invalid_len =
+                || format!("Invalid length {} of TLV extension {}", len, extension_type);
+            match extension_type {
+                TRANSFER_FEE_AMOUNT_EXTENSION => {
+                    let amount: [u8; 8] = value.try_into().map_err(|_| invalid_len())?;
+                    extensions.withheld_transfer_fee = Some(U64String(u64::from_le_bytes(amount)));
+                }
+                PERMANENT_DELEGATE_EXTENSION => {
+                    let delegate: [u8; 32] = value.try_into().map_err(|_| invalid_len())?;
+                    extensions.permanent_delegate = optional_non_zero_pubkey(delegate);
+                }
+                METADATA_POINTER_EXTENSION => {
+                    if value.len() != 64 {
+                        return Err(invalid_len());
+                    }
+                    let metadata_pointer = MetadataPointer {
+                        authority: optional_non_zero_pubkey(value[..32].try_into().unwrap()),
+                        metadata_address: optional_non_zero_pubkey(value[32..].try_into().unwrap()),
+                    };
+                    extensions.metadata_pointer = (metadata_pointer != MetadataPointer::default())
+                        .then_some(metadata_pointer);
+                }
+                _ => {}
+            }
+        }
+        Ok((extensions != TokenExtensions::default()).then_some(extensions))
+    }
+}
+
+// Token-2022 stores optional keys as the zero key when they are unset.
+fn optional_non_zero_pubkey(bytes: [u8; 32]) -> Option<SerializablePubkey> {
+    (bytes != [0; 32]).then(|| SerializablePubkey::from(bytes))
 }
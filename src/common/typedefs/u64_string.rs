@@ -0,0 +1,113 @@
+use std::fmt;
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use utoipa::{
+    openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
+    ToSchema,
+};
+
+/// A u64 that is encoded as a decimal string, since JSON numbers above 2^53 lose precision in
+/// JavaScript clients. Used for lamports and token amounts.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Copy, PartialOrd, Ord, Hash)]
+pub struct U64String(pub u64);
+
+impl<'__s> ToSchema<'__s> for U64String {
+    fn schema() -> (&'__s str, RefOr<Schema>) {
+        let example = Some(serde_json::Value::String("100".to_string()));
+        let schema = Schema::Object(
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(
+                    "An unsigned 64-bit integer encoded as a decimal string.",
+                ))
+                .default(example.clone())
+                .example(example)
+                .build(),
+        );
+        ("U64String", RefOr::T(schema))
+    }
+
+    fn aliases() -> Vec<(&'static str, utoipa::openapi::schema::Schema)> {
+        Vec::new()
+    }
+}
+
+impl fmt::Display for U64String {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for U64String {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+struct U64StringVisitor;
+
+impl<'de> Visitor<'de> for U64StringVisitor {
+    type Value = U64String;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an unsigned 64-bit integer or a decimal string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        value
+            .parse()
+            .map(U64String)
+            .map_err(|_| E::custom(format!("Invalid unsigned 64-bit integer: {}", value)))
+    }
+
+    // Numbers are accepted from clients that predate the string encoding.
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(U64String(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        u64::try_from(value)
+            .map(U64String)
+            .map_err(|_| E::custom(format!("Invalid unsigned 64-bit integer: {}", value)))
+    }
+}
+
+impl<'de> Deserialize<'de> for U64String {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(U64StringVisitor)
+    }
+}
+
+impl anchor_lang::AnchorDeserialize for U64String {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> Result<Self, std::io::Error> {
+        let mut buffer = [0u8; 8];
+        reader.read_exact(&mut buffer)?;
+        Ok(U64String(u64::from_le_bytes(buffer)))
+    }
+}
+
+impl anchor_lang::AnchorSerialize for U64String {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        writer.write_all(&self.0.to_le_bytes())
+    }
+}
+
+#[test]
+fn test_serialization() {
+    let amount = U64String(u64::MAX);
+    let serialized = serde_json::to_string(&amount).unwrap();
+    assert_eq!(serialized, "\"18446744073709551615\"");
+    assert_eq!(
+        serde_json::from_str::<U64String>(&serialized).unwrap(),
+        amount
+    );
+    assert_eq!(
+        serde_json::from_str::<U64String>("100").unwrap(),
+        U64String(100)
+    );
+    assert!(serde_json::from_str::<U64String>("-1").is_err());
+    assert!(serde_json::from_str::<U64String>("\"1.5\"").is_err());
+}
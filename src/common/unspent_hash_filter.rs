@@ -0,0 +1,132 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash as _, Hasher};
+use std::sync::RwLock;
+
+use log::info;
+use once_cell::sync::Lazy;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use crate::api::method::utils::HashModel;
+use crate::common::typedefs::hash::Hash;
+use crate::dao::generated::accounts;
+
+// Bits per expected item and number of hash functions for a false positive rate of about 1%.
+const BITS_PER_ITEM: usize = 10;
+const NUM_HASHES: u64 = 7;
+// Leaves room for the accounts created while the indexer runs before the false positive rate
+// starts to degrade.
+const CAPACITY_HEADROOM: usize = 2;
+const MIN_CAPACITY: usize = 1_000_000;
+const LOAD_PAGE_SIZE: u64 = 100_000;
+
+/// A bloom filter over account hashes. A negative answer is definite, while a positive answer only
+/// means that the hash is likely present and has to be confirmed against the database.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+}
+
+impl BloomFilter {
+    pub fn with_capacity(expected_items: usize) -> Self {
+        let num_bits = (expected_items.max(1) * BITS_PER_ITEM) as u64;
+        Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+        }
+    }
+
+    fn bit_indices(&self, hash: &Hash) -> impl Iterator<Item = u64> {
+        // Double hashing lets us derive all bit indices from two base hashes.
+        let base_hash = |seed: u8| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            hash.0.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (h1, h2) = (base_hash(0), base_hash(1));
+        let num_bits = self.num_bits;
+        (0..NUM_HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    pub fn insert(&mut self, hash: &Hash) {
+        for index in self.bit_indices(hash).collect::<Vec<_>>() {
+            self.bits[(index / 64) as usize] |= 1 << (index % 64);
+        }
+    }
+
+    pub fn may_contain(&self, hash: &Hash) -> bool {
+        self.bit_indices(hash)
+            .all(|index| self.bits[(index / 64) as usize] & (1 << (index % 64)) != 0)
+    }
+
+    /// Builds a filter containing the hashes of all unspent accounts in the database.
+    pub async fn load_unspent_hashes(db: &DatabaseConnection) -> Result<Self, DbErr> {
+        let unspent_filter = accounts::Column::Spent.eq(false);
+        let count = accounts::Entity::find()
+            .filter(unspent_filter.clone())
+            .count(db)
+            .await? as usize;
+        let mut filter = Self::with_capacity((count * CAPACITY_HEADROOM).max(MIN_CAPACITY));
+
+        let mut last_hash: Option<Vec<u8>> = None;
+        loop {
+            let mut page_filter = unspent_filter.clone();
+            if let Some(last_hash) = last_hash {
+                page_filter = page_filter.and(accounts::Column::Hash.gt(last_hash));
+            }
+            let hashes = accounts::Entity::find()
+                .select_only()
+                .column(accounts::Column::Hash)
+                .filter(page_filter)
+                .order_by_asc(accounts::Column::Hash)
+                .limit(LOAD_PAGE_SIZE)
+                .into_model::<HashModel>()
+                .all(db)
+                .await?;
+            for model in hashes.iter() {
+                let hash = Hash::try_from(model.hash.clone())
+                    .map_err(|e| DbErr::Custom(format!("Invalid account hash: {}", e)))?;
+                filter.insert(&hash);
+            }
+            last_hash = match hashes.last() {
+                Some(model) if hashes.len() == LOAD_PAGE_SIZE as usize => Some(model.hash.clone()),
+                _ => break,
+            };
+        }
+        info!(
+            "Loaded {} unspent account hashes into the hash filter",
+            count
+        );
+        Ok(filter)
+    }
+}
+
+// Only enabled with `--unspent-hash-filter` in processes that also run the ingester, since the
+// filter has to see every account that is written to the database to not report false negatives.
+// Accounts written by other processes, e.g. backfills, are missing from it.
+static UNSPENT_HASHES: Lazy<RwLock<Option<BloomFilter>>> = Lazy::new(|| RwLock::new(None));
+
+pub fn enable_unspent_hash_filter(filter: BloomFilter) {
+    *UNSPENT_HASHES.write().unwrap() = Some(filter);
+}
+
+pub fn insert_unspent_hashes<'a>(hashes: impl IntoIterator<Item = &'a Hash>) {
+    if let Some(filter) = UNSPENT_HASHES.write().unwrap().as_mut() {
+        for hash in hashes {
+            filter.insert(hash);
+        }
+    }
+}
+
+/// Returns `false` only if the account is definitely not an unspent account in the database.
+/// Always returns `true` when the filter is not enabled.
+pub fn may_be_unspent(hash: &Hash) -> bool {
+    match UNSPENT_HASHES.read().unwrap().as_ref() {
+        Some(filter) => filter.may_contain(hash),
+        None => true,
+    }
+}
@@ -0,0 +1,93 @@
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use light_sdk::{
+    address::NewAddressParamsPacked,
+    compressed_account::{
+        OutputCompressedAccountWithPackedContext, PackedCompressedAccountWithMerkleContext,
+    },
+    proof::CompressedProof,
+    CPI_AUTHORITY_PDA_SEED, PROGRAM_ID_ACCOUNT_COMPRESSION, PROGRAM_ID_LIGHT_SYSTEM,
+    PROGRAM_ID_NOOP,
+};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+
+const SOL_POOL_PDA_SEED: &[u8] = b"sol_pool_pda";
+
+/// Arguments of the Light system program `invoke` instruction. Copied from the Light repo since
+/// the system program crate is not published.
+#[derive(Debug, PartialEq, Default, Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct InstructionDataInvoke {
+    pub proof: Option<CompressedProof>,
+    pub input_compressed_accounts_with_merkle_context:
+        Vec<PackedCompressedAccountWithMerkleContext>,
+    pub output_compressed_accounts: Vec<OutputCompressedAccountWithPackedContext>,
+    pub relay_fee: Option<u64>,
+    pub new_address_params: Vec<NewAddressParamsPacked>,
+    pub compress_or_decompress_lamports: Option<u64>,
+    pub is_compress: bool,
+}
+
+pub fn invoke_discriminator() -> [u8; 8] {
+    let hash = Sha256::digest(b"global:invoke");
+    hash[..8].try_into().unwrap()
+}
+
+pub fn sol_pool_pda() -> Pubkey {
+    Pubkey::find_program_address(&[SOL_POOL_PDA_SEED], &PROGRAM_ID_LIGHT_SYSTEM).0
+}
+
+/// Builds an `invoke` instruction of the Light system program. `remaining_accounts` are the trees
+/// and queues that the packed indices in `data` refer to. The sol pool is only passed if lamports
+/// are compressed or decompressed.
+pub fn invoke_instruction(
+    fee_payer: Pubkey,
+    authority: Pubkey,
+    data: &InstructionDataInvoke,
+    decompression_recipient: Option<Pubkey>,
+    remaining_accounts: &[Pubkey],
+) -> Instruction {
+    let registered_program_pda = Pubkey::find_program_address(
+        &[PROGRAM_ID_LIGHT_SYSTEM.as_ref()],
+        &PROGRAM_ID_ACCOUNT_COMPRESSION,
+    )
+    .0;
+    let account_compression_authority =
+        Pubkey::find_program_address(&[CPI_AUTHORITY_PDA_SEED], &PROGRAM_ID_LIGHT_SYSTEM).0;
+    // Anchor expects the program id in place of optional accounts that are not passed.
+    let optional_account = |pubkey: Option<Pubkey>| match pubkey {
+        Some(pubkey) => AccountMeta::new(pubkey, false),
+        None => AccountMeta::new_readonly(PROGRAM_ID_LIGHT_SYSTEM, false),
+    };
+    let sol_pool = data.compress_or_decompress_lamports.map(|_| sol_pool_pda());
+
+    let mut accounts = vec![
+        AccountMeta::new(fee_payer, true),
+        AccountMeta::new_readonly(authority, true),
+        AccountMeta::new_readonly(registered_program_pda, false),
+        AccountMeta::new_readonly(PROGRAM_ID_NOOP, false),
+        AccountMeta::new_readonly(account_compression_authority, false),
+        AccountMeta::new_readonly(PROGRAM_ID_ACCOUNT_COMPRESSION, false),
+        optional_account(sol_pool),
+        optional_account(decompression_recipient),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+    accounts.extend(
+        remaining_accounts
+            .iter()
+            .map(|pubkey| AccountMeta::new(*pubkey, false)),
+    );
+
+    // The program takes the serialized arguments as a single `Vec<u8>` argument.
+    let mut instruction_data = invoke_discriminator().to_vec();
+    instruction_data.extend(data.try_to_vec().unwrap().try_to_vec().unwrap());
+
+    Instruction {
+        program_id: PROGRAM_ID_LIGHT_SYSTEM,
+        accounts,
+        data: instruction_data,
+    }
+}
@@ -0,0 +1,532 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use light_sdk::{
+    compressed_account::{
+        CompressedAccount, OutputCompressedAccountWithPackedContext,
+        PackedCompressedAccountWithMerkleContext,
+    },
+    merkle_context::PackedMerkleContext,
+    proof::CompressedProof,
+};
+use log::info;
+use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    pubkey,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+
+use self::instructions::{invoke_instruction, InstructionDataInvoke};
+
+pub mod instructions;
+
+pub const DEVNET_RPC_URL: &str = "https://api.devnet.solana.com";
+pub const DEVNET_STATE_TREE: Pubkey = pubkey!("smt1NamzXdq4AMqS2fS2F1i5KTYPZRhoHgWx38d8WsT");
+pub const DEVNET_NULLIFIER_QUEUE: Pubkey = pubkey!("nfq1NvQDJ2GEgnS8zt9prAe8rjjpAW1zFkrvZoBR148");
+
+const COMPUTE_UNIT_LIMIT: u32 = 1_000_000;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct ConformanceConfig {
+    pub photon_url: String,
+    pub rpc_url: String,
+    pub payer: Keypair,
+    pub lamports: u64,
+    pub state_tree: Pubkey,
+    pub nullifier_queue: Pubkey,
+    pub indexing_timeout: Duration,
+}
+
+struct PhotonClient {
+    http_client: reqwest::Client,
+    url: String,
+}
+
+impl PhotonClient {
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let mut response: Value = self
+            .http_client
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("{} failed: {}", method, error));
+        }
+        Ok(response["result"].take())
+    }
+
+    async fn compressed_accounts_by_owner(&self, owner: &Pubkey) -> Result<Vec<Value>> {
+        let result = self
+            .call(
+                "getCompressedAccountsByOwner",
+                json!({ "owner": owner.to_string() }),
+            )
+            .await?;
+        Ok(result["value"]["items"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn compressed_balance_by_owner(&self, owner: &Pubkey) -> Result<u64> {
+        let result = self
+            .call(
+                "getCompressedBalanceByOwner",
+                json!({ "owner": owner.to_string() }),
+            )
+            .await?;
+        result["value"]
+            .as_u64()
+            .ok_or(anyhow!("Invalid balance: {}", result))
+    }
+
+    async fn signatures_for_owner(&self, owner: &Pubkey) -> Result<Vec<String>> {
+        let result = self
+            .call(
+                "getCompressionSignaturesForOwner",
+                json!({ "owner": owner.to_string() }),
+            )
+            .await?;
+        Ok(string_fields(&result["value"]["items"], "signature"))
+    }
+
+    async fn signatures_for_account(&self, hash: &str) -> Result<Vec<String>> {
+        let result = self
+            .call(
+                "getCompressionSignaturesForAccount",
+                json!({ "hash": hash }),
+            )
+            .await?;
+        Ok(string_fields(&result["value"]["items"], "signature"))
+    }
+
+    async fn compressed_account(&self, hash: &str) -> Result<Option<Value>> {
+        let result = self
+            .call("getCompressedAccount", json!({ "hash": hash }))
+            .await?;
+        Ok(Some(result["value"].clone()).filter(|value| !value.is_null()))
+    }
+
+    // Returns the hashes of the closed and opened accounts of the transaction.
+    async fn transaction_compression_info(
+        &self,
+        signature: &Signature,
+    ) -> Result<(Vec<String>, Vec<String>)> {
+        let result = self
+            .call(
+                "getTransactionWithCompressionInfo",
+                json!({ "signature": signature.to_string() }),
+            )
+            .await?;
+        let hashes = |accounts: &Value| -> Vec<String> {
+            accounts
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|account| account["account"]["hash"].as_str())
+                .map(str::to_string)
+                .collect()
+        };
+        Ok((
+            hashes(&result["compressionInfo"]["closedAccounts"]),
+            hashes(&result["compressionInfo"]["openedAccounts"]),
+        ))
+    }
+
+    async fn validity_proof(&self, hash: &str) -> Result<(CompressedProof, u16)> {
+        let result = self
+            .call("getValidityProof", json!({ "hashes": [hash] }))
+            .await?;
+        let value = &result["value"];
+        let bytes = |field: &str| -> Result<Vec<u8>> {
+            serde_json::from_value(value["compressedProof"][field].clone())
+                .with_context(|| format!("Invalid proof field {}", field))
+        };
+        let proof = CompressedProof {
+            a: bytes("a")?
+                .try_into()
+                .map_err(|_| anyhow!("Invalid proof length"))?,
+            b: bytes("b")?
+                .try_into()
+                .map_err(|_| anyhow!("Invalid proof length"))?,
+            c: bytes("c")?
+                .try_into()
+                .map_err(|_| anyhow!("Invalid proof length"))?,
+        };
+        let root_index = value["rootIndices"][0]
+            .as_u64()
+            .ok_or(anyhow!("Missing root index: {}", result))?;
+        Ok((proof, root_index.try_into()?))
+    }
+
+    // Waits until the transaction shows up in the signatures of `owner`, which means that the
+    // block that contains it has been indexed.
+    async fn wait_for_signature(
+        &self,
+        owner: &Pubkey,
+        signature: &Signature,
+        timeout: Duration,
+    ) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self
+                .signatures_for_owner(owner)
+                .await?
+                .contains(&signature.to_string())
+            {
+                return Ok(());
+            }
+            ensure!(
+                Instant::now() < deadline,
+                "Transaction {} was not indexed within {:?}",
+                signature,
+                timeout
+            );
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+fn string_fields(items: &Value, field: &str) -> Vec<String> {
+    items
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item[field].as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+struct Conformance {
+    config: ConformanceConfig,
+    rpc_client: RpcClient,
+    photon: PhotonClient,
+}
+
+impl Conformance {
+    async fn send(
+        &self,
+        data: InstructionDataInvoke,
+        authority: &Keypair,
+        decompression_recipient: Option<Pubkey>,
+    ) -> Result<Signature> {
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(COMPUTE_UNIT_LIMIT),
+            invoke_instruction(
+                self.config.payer.pubkey(),
+                authority.pubkey(),
+                &data,
+                decompression_recipient,
+                &[self.config.state_tree, self.config.nullifier_queue],
+            ),
+        ];
+        let blockhash = self.rpc_client.get_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.config.payer.pubkey()),
+            &[&self.config.payer, authority],
+            blockhash,
+        );
+        Ok(self
+            .rpc_client
+            .send_and_confirm_transaction(&transaction)
+            .await?)
+    }
+
+    // Fetches the single account of `owner` and checks that it holds the transferred lamports.
+    async fn single_account(&self, owner: &Pubkey) -> Result<Value> {
+        let accounts = self.photon.compressed_accounts_by_owner(owner).await?;
+        ensure!(
+            accounts.len() == 1,
+            "Expected one account for {}, got {}",
+            owner,
+            accounts.len()
+        );
+        let account = accounts[0].clone();
+        ensure!(
+            account["lamports"].as_u64() == Some(self.config.lamports),
+            "Unexpected account: {}",
+            account
+        );
+        ensure!(
+            account["tree"].as_str() == Some(self.config.state_tree.to_string().as_str()),
+            "Account is not in the state tree: {}",
+            account
+        );
+        ensure!(
+            self.photon.compressed_balance_by_owner(owner).await? == self.config.lamports,
+            "Unexpected compressed balance for {}",
+            owner
+        );
+        Ok(account)
+    }
+
+    async fn check_transaction(
+        &self,
+        signature: &Signature,
+        closed: Option<&str>,
+        opened: Option<&str>,
+    ) -> Result<()> {
+        let (closed_accounts, opened_accounts) =
+            self.photon.transaction_compression_info(signature).await?;
+        ensure!(
+            closed_accounts == closed.into_iter().map(str::to_string).collect::<Vec<_>>(),
+            "Unexpected closed accounts for {}: {:?}",
+            signature,
+            closed_accounts
+        );
+        ensure!(
+            opened_accounts == opened.into_iter().map(str::to_string).collect::<Vec<_>>(),
+            "Unexpected opened accounts for {}: {:?}",
+            signature,
+            opened_accounts
+        );
+        for hash in closed.into_iter().chain(opened) {
+            ensure!(
+                self.photon
+                    .signatures_for_account(hash)
+                    .await?
+                    .contains(&signature.to_string()),
+                "Signature {} is missing for account {}",
+                signature,
+                hash
+            );
+        }
+        if let Some(closed) = closed {
+            ensure!(
+                self.photon.compressed_account(closed).await?.is_none(),
+                "Spent account {} is still returned",
+                closed
+            );
+        }
+        Ok(())
+    }
+
+    // Builds the input for spending `account`, which is owned by `owner`.
+    async fn input_account(
+        &self,
+        owner: &Pubkey,
+        account: &Value,
+    ) -> Result<(CompressedProof, PackedCompressedAccountWithMerkleContext)> {
+        let hash = account["hash"]
+            .as_str()
+            .ok_or(anyhow!("Missing hash: {}", account))?;
+        let leaf_index = account["leafIndex"]
+            .as_u64()
+            .ok_or(anyhow!("Missing leaf index: {}", account))?;
+        let (proof, root_index) = self.photon.validity_proof(hash).await?;
+        let input = PackedCompressedAccountWithMerkleContext {
+            compressed_account: CompressedAccount {
+                owner: *owner,
+                lamports: self.config.lamports,
+                address: None,
+                data: None,
+            },
+            merkle_context: PackedMerkleContext {
+                merkle_tree_pubkey_index: 0,
+                nullifier_queue_pubkey_index: 1,
+                leaf_index: leaf_index.try_into()?,
+                queue_index: None,
+            },
+            root_index,
+            read_only: false,
+        };
+        Ok((proof, input))
+    }
+
+    fn output_account(&self, owner: &Pubkey) -> OutputCompressedAccountWithPackedContext {
+        OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner: *owner,
+                lamports: self.config.lamports,
+                address: None,
+                data: None,
+            },
+            merkle_tree_index: 0,
+        }
+    }
+
+    async fn compress(&self) -> Result<String> {
+        let payer = &self.config.payer;
+        let signature = self
+            .send(
+                InstructionDataInvoke {
+                    output_compressed_accounts: vec![self.output_account(&payer.pubkey())],
+                    compress_or_decompress_lamports: Some(self.config.lamports),
+                    is_compress: true,
+                    ..Default::default()
+                },
+                payer,
+                None,
+            )
+            .await?;
+        info!(
+            "Compressed {} lamports: {}",
+            self.config.lamports, signature
+        );
+        self.photon
+            .wait_for_signature(&payer.pubkey(), &signature, self.config.indexing_timeout)
+            .await?;
+
+        let accounts = self
+            .photon
+            .compressed_accounts_by_owner(&payer.pubkey())
+            .await?;
+        let (_, opened) = self.photon.transaction_compression_info(&signature).await?;
+        let account = accounts
+            .into_iter()
+            .find(|account| {
+                opened
+                    .iter()
+                    .any(|hash| account["hash"].as_str() == Some(hash))
+            })
+            .ok_or(anyhow!("Compressed account is missing for {}", signature))?;
+        ensure!(
+            account["lamports"].as_u64() == Some(self.config.lamports),
+            "Unexpected account: {}",
+            account
+        );
+        let hash = account["hash"].as_str().unwrap().to_string();
+        self.check_transaction(&signature, None, Some(&hash))
+            .await?;
+        Ok(hash)
+    }
+
+    async fn transfer(&self, hash: &str, recipient: &Keypair) -> Result<String> {
+        let payer = &self.config.payer;
+        let account = self
+            .photon
+            .compressed_account(hash)
+            .await?
+            .ok_or(anyhow!("Account {} is missing", hash))?;
+        let (proof, input) = self.input_account(&payer.pubkey(), &account).await?;
+        let signature = self
+            .send(
+                InstructionDataInvoke {
+                    proof: Some(proof),
+                    input_compressed_accounts_with_merkle_context: vec![input],
+                    output_compressed_accounts: vec![self.output_account(&recipient.pubkey())],
+                    ..Default::default()
+                },
+                payer,
+                None,
+            )
+            .await?;
+        info!(
+            "Transferred {} lamports: {}",
+            self.config.lamports, signature
+        );
+        self.photon
+            .wait_for_signature(
+                &recipient.pubkey(),
+                &signature,
+                self.config.indexing_timeout,
+            )
+            .await?;
+
+        let account = self.single_account(&recipient.pubkey()).await?;
+        let recipient_hash = account["hash"].as_str().unwrap().to_string();
+        self.check_transaction(&signature, Some(hash), Some(&recipient_hash))
+            .await?;
+        ensure!(
+            self.photon
+                .signatures_for_owner(&payer.pubkey())
+                .await?
+                .contains(&signature.to_string()),
+            "Signature {} is missing for the sender",
+            signature
+        );
+        Ok(recipient_hash)
+    }
+
+    async fn decompress(&self, hash: &str, owner: &Keypair) -> Result<()> {
+        let payer = &self.config.payer;
+        let account = self.single_account(&owner.pubkey()).await?;
+        let (proof, input) = self.input_account(&owner.pubkey(), &account).await?;
+        let signature = self
+            .send(
+                InstructionDataInvoke {
+                    proof: Some(proof),
+                    input_compressed_accounts_with_merkle_context: vec![input],
+                    compress_or_decompress_lamports: Some(self.config.lamports),
+                    is_compress: false,
+                    ..Default::default()
+                },
+                owner,
+                Some(payer.pubkey()),
+            )
+            .await?;
+        info!(
+            "Decompressed {} lamports: {}",
+            self.config.lamports, signature
+        );
+        self.photon
+            .wait_for_signature(&owner.pubkey(), &signature, self.config.indexing_timeout)
+            .await?;
+
+        ensure!(
+            self.photon
+                .compressed_accounts_by_owner(&owner.pubkey())
+                .await?
+                .is_empty(),
+            "Decompressed account is still returned for {}",
+            owner.pubkey()
+        );
+        ensure!(
+            self.photon
+                .compressed_balance_by_owner(&owner.pubkey())
+                .await?
+                == 0,
+            "Compressed balance of {} is not zero",
+            owner.pubkey()
+        );
+        self.check_transaction(&signature, Some(hash), None).await
+    }
+}
+
+/// Runs a compress, transfer and decompress of `lamports` against a live cluster and checks that
+/// Photon returns the expected state after each transaction. The payer funds all transactions and
+/// gets the lamports back at the end.
+pub async fn run_conformance(config: ConformanceConfig) -> Result<()> {
+    let conformance = Conformance {
+        rpc_client: RpcClient::new_with_commitment(
+            config.rpc_url.clone(),
+            CommitmentConfig::confirmed(),
+        ),
+        photon: PhotonClient {
+            http_client: reqwest::Client::new(),
+            url: config.photon_url.clone(),
+        },
+        config,
+    };
+    let recipient = Keypair::new();
+
+    let hash = conformance.compress().await.context("Compress failed")?;
+    info!("Compress passed");
+    let hash = conformance
+        .transfer(&hash, &recipient)
+        .await
+        .context("Transfer failed")?;
+    info!("Transfer passed");
+    conformance
+        .decompress(&hash, &recipient)
+        .await
+        .context("Decompress failed")?;
+    info!("Decompress passed");
+    Ok(())
+}
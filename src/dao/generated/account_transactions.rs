@@ -9,6 +9,8 @@ pub struct Model {
     pub hash: Vec<u8>,
     #[sea_orm(primary_key, auto_increment = false)]
     pub signature: Vec<u8>,
+    pub slot: i64,
+    pub spent: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
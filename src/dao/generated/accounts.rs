@@ -1,8 +1,9 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "accounts")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -18,9 +19,16 @@ pub struct Model {
     pub spent: bool,
     pub prev_spent: Option<bool>,
     #[sea_orm(column_type = "Decimal(Some((20, 0)))")]
+    #[serde(with = "rust_decimal::serde::str")]
     pub lamports: Decimal,
     #[sea_orm(column_type = "Decimal(Some((20, 0)))", nullable)]
+    #[serde(with = "rust_decimal::serde::str_option")]
     pub discriminator: Option<Decimal>,
+    pub data_compressed: bool,
+    pub data_ref: Option<Vec<u8>>,
+    pub tx_signature: Option<Vec<u8>>,
+    pub spent_tx_signature: Option<Vec<u8>>,
+    pub slot_spent: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
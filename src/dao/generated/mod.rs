@@ -2,13 +2,21 @@
 
 pub mod prelude;
 
+pub mod account_data;
 pub mod account_transactions;
 pub mod accounts;
 pub mod blocks;
+pub mod checkpoints;
+pub mod failed_blocks;
 pub mod indexed_trees;
+pub mod metadata;
+pub mod nullifier_queue_elements;
 pub mod owner_balances;
+pub mod pruned_accounts;
 pub mod state_tree_histories;
+pub mod state_tree_node_histories;
 pub mod state_trees;
 pub mod token_accounts;
 pub mod token_owner_balances;
 pub mod transactions;
+pub mod trees;
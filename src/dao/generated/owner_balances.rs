@@ -1,13 +1,15 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "owner_balances")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub owner: Vec<u8>,
     #[sea_orm(column_type = "Decimal(Some((20, 0)))")]
+    #[serde(with = "rust_decimal::serde::str")]
     pub lamports: Decimal,
 }
 
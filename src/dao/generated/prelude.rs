@@ -1,12 +1,20 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
+pub use super::account_data::Entity as AccountData;
 pub use super::account_transactions::Entity as AccountTransactions;
 pub use super::accounts::Entity as Accounts;
 pub use super::blocks::Entity as Blocks;
+pub use super::checkpoints::Entity as Checkpoints;
+pub use super::failed_blocks::Entity as FailedBlocks;
 pub use super::indexed_trees::Entity as IndexedTrees;
+pub use super::metadata::Entity as Metadata;
+pub use super::nullifier_queue_elements::Entity as NullifierQueueElements;
 pub use super::owner_balances::Entity as OwnerBalances;
+pub use super::pruned_accounts::Entity as PrunedAccounts;
 pub use super::state_tree_histories::Entity as StateTreeHistories;
+pub use super::state_tree_node_histories::Entity as StateTreeNodeHistories;
 pub use super::state_trees::Entity as StateTrees;
 pub use super::token_accounts::Entity as TokenAccounts;
 pub use super::token_owner_balances::Entity as TokenOwnerBalances;
 pub use super::transactions::Entity as Transactions;
+pub use super::trees::Entity as Trees;
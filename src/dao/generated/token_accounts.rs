@@ -1,8 +1,9 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.6
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "token_accounts")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -14,8 +15,20 @@ pub struct Model {
     pub spent: bool,
     pub prev_spent: Option<bool>,
     #[sea_orm(column_type = "Decimal(Some((20, 0)))")]
+    #[serde(with = "rust_decimal::serde::str")]
     pub amount: Decimal,
     pub tlv: Option<Vec<u8>>,
+    pub slot_created: i64,
+    #[sea_orm(column_type = "Decimal(Some((20, 0)))", nullable)]
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub withheld_transfer_fee: Option<Decimal>,
+    pub permanent_delegate: Option<Vec<u8>>,
+    pub metadata_pointer_authority: Option<Vec<u8>>,
+    pub metadata_pointer_address: Option<Vec<u8>>,
+    pub tlv_compressed: bool,
+    pub tx_signature: Option<Vec<u8>>,
+    pub spent_tx_signature: Option<Vec<u8>>,
+    pub slot_spent: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -11,6 +11,10 @@ pub struct Model {
     pub uses_compression: bool,
     #[sea_orm(column_type = "Text", nullable)]
     pub error: Option<String>,
+    #[sea_orm(column_type = "Decimal(Some((20, 0)))")]
+    pub compressed_lamports: Decimal,
+    #[sea_orm(column_type = "Decimal(Some((20, 0)))")]
+    pub decompressed_lamports: Decimal,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,158 @@
+use std::{collections::HashSet, sync::Arc};
+
+use futures::{stream, StreamExt};
+use log::{debug, info};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, FromQueryResult, QueryFilter, QuerySelect,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+
+use crate::dao::generated::blocks;
+
+use super::{
+    error::IngesterError,
+    fetchers::{
+        bigtable::BigtableBlockFetcher, poller::fetch_unpruned_block_with_infinite_retries,
+    },
+    index_block_batch_with_infinite_retries,
+    typedefs::block_info::BlockInfo,
+    IndexerContext,
+};
+
+// Number of slots whose indexing status is looked up at once, which bounds memory usage for
+// large ranges.
+const BACKFILL_CHUNK_SIZE: u64 = 10_000;
+
+/// Where the blocks of a backfill are fetched from. Bigtable serves the complete ledger history,
+/// so it is useful for ranges that the RPC node has already pruned or rate limits.
+#[derive(Clone)]
+pub enum BackfillBlockSource {
+    Rpc {
+        rpc_client: Arc<RpcClient>,
+        /// RPC node with the full ledger history that pruned slots are fetched from instead.
+        archival_rpc_client: Option<Arc<RpcClient>>,
+    },
+    Bigtable(Arc<BigtableBlockFetcher>),
+}
+
+impl BackfillBlockSource {
+    /// Returns `None` for skipped slots and fails if the slot has been pruned by the RPC node and
+    /// there is no archival node to fall back to.
+    async fn fetch_block_with_infinite_retries(
+        &self,
+        slot: u64,
+    ) -> Result<Option<BlockInfo>, IngesterError> {
+        match self {
+            BackfillBlockSource::Rpc {
+                rpc_client,
+                archival_rpc_client,
+            } => {
+                let result =
+                    fetch_unpruned_block_with_infinite_retries(rpc_client.clone(), slot).await;
+                match (result, archival_rpc_client) {
+                    (Err(IngesterError::SlotUnavailable { .. }), Some(archival_rpc_client)) => {
+                        debug!("Fetching pruned slot {} from the archival RPC node", slot);
+                        fetch_unpruned_block_with_infinite_retries(
+                            archival_rpc_client.clone(),
+                            slot,
+                        )
+                        .await
+                    }
+                    (result, _) => result,
+                }
+            }
+            BackfillBlockSource::Bigtable(fetcher) => {
+                Ok(fetcher.fetch_block_with_infinite_retries(slot).await)
+            }
+        }
+    }
+}
+
+#[derive(FromQueryResult)]
+struct SlotModel {
+    slot: i64,
+}
+
+/// Returns the slots in the inclusive range that do not have a block in the database yet.
+pub async fn fetch_slots_to_backfill(
+    db: &DatabaseConnection,
+    start_slot: u64,
+    end_slot: u64,
+) -> Result<Vec<u64>, DbErr> {
+    let indexed_slots = blocks::Entity::find()
+        .select_only()
+        .column(blocks::Column::Slot)
+        .filter(blocks::Column::Slot.between(start_slot as i64, end_slot as i64))
+        .into_model::<SlotModel>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|model| model.slot as u64)
+        .collect::<HashSet<_>>();
+    Ok((start_slot..=end_slot)
+        .filter(|slot| !indexed_slots.contains(slot))
+        .collect())
+}
+
+/// Indexes all blocks in the inclusive slot range that are not indexed yet. Blocks are fetched
+/// concurrently but persisted in slot order, since an account has to be created before it can be
+/// marked as spent. Persisting a block is idempotent, so the backfill can be safely restarted.
+/// The backfill stops at the first slot that the block source cannot provide anymore, after
+/// indexing the slots before it.
+pub async fn backfill_slot_range(
+    indexer: &IndexerContext,
+    block_source: BackfillBlockSource,
+    start_slot: u64,
+    end_slot: u64,
+    max_concurrent_block_fetches: usize,
+) -> Result<(), IngesterError> {
+    let total_slots = end_slot.saturating_sub(start_slot) + 1;
+    let mut chunk_start = start_slot;
+    while chunk_start <= end_slot {
+        let chunk_end = chunk_start
+            .saturating_add(BACKFILL_CHUNK_SIZE - 1)
+            .min(end_slot);
+        let slots = fetch_slots_to_backfill(indexer.db.as_ref(), chunk_start, chunk_end).await?;
+        let already_indexed = (chunk_end - chunk_start + 1) as usize - slots.len();
+
+        let block_batches = stream::iter(slots)
+            .map(|slot| {
+                let block_source = block_source.clone();
+                async move { block_source.fetch_block_with_infinite_retries(slot).await }
+            })
+            .buffered(max_concurrent_block_fetches)
+            .ready_chunks(max_concurrent_block_fetches);
+        futures::pin_mut!(block_batches);
+        while let Some(results) = block_batches.next().await {
+            let mut blocks = Vec::new();
+            let mut error = None;
+            for result in results {
+                match result {
+                    Ok(block) => blocks.extend(block),
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+            index_block_batch_with_infinite_retries(indexer, blocks).await;
+            if let Some(e) = error {
+                return Err(e);
+            }
+        }
+
+        info!(
+            "Backfilled {} / {} slots. Skipped {} already indexed slots in {}-{}",
+            chunk_end - start_slot + 1,
+            total_slots,
+            already_indexed,
+            chunk_start,
+            chunk_end
+        );
+        match chunk_end.checked_add(1) {
+            Some(next_chunk_start) => chunk_start = next_chunk_start,
+            None => break,
+        }
+    }
+    Ok(())
+}
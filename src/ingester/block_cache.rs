@@ -0,0 +1,121 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::metric;
+use anyhow::{anyhow, Context, Result};
+use cadence_macros::statsd_count;
+use log::{error, info};
+use once_cell::sync::Lazy;
+
+use super::{
+    index_block_batch_with_infinite_retries, typedefs::block_info::BlockInfo, IndexerContext,
+};
+
+const CACHED_BLOCK_EXTENSION: &str = ".bin.zst";
+// Zstd's default level, which compresses well without slowing down ingestion noticeably.
+const COMPRESSION_LEVEL: i32 = 3;
+// Number of cached blocks that are loaded and indexed together during a replay.
+const REPLAY_BATCH_SIZE: usize = 100;
+
+static BLOCK_CACHE_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Keeps a copy of every block that is indexed in `dir`, so that the blocks can be indexed again
+/// with `replay_cached_blocks` without fetching them again. `None` disables the cache.
+pub fn set_block_cache_dir(dir: Option<PathBuf>) {
+    *BLOCK_CACHE_DIR.lock().unwrap() = dir;
+}
+
+fn cached_block_path(dir: &Path, slot: u64) -> PathBuf {
+    dir.join(format!("{}{}", slot, CACHED_BLOCK_EXTENSION))
+}
+
+/// Writes `block` to `dir` as a zstd compressed, bincode encoded `BlockInfo` named after its slot.
+/// The file is written under a temporary name first, so that a crash cannot leave a truncated
+/// block behind.
+pub fn store_cached_block(dir: &Path, block: &BlockInfo) -> Result<()> {
+    let bytes = bincode::serialize(block).context("Failed to serialize block")?;
+    let compressed =
+        zstd::bulk::compress(&bytes, COMPRESSION_LEVEL).context("Failed to compress block")?;
+    let path = cached_block_path(dir, block.metadata.slot);
+    let temporary_path = path.with_extension("tmp");
+    std::fs::write(&temporary_path, compressed)
+        .with_context(|| format!("Failed to write {:?}", temporary_path))?;
+    std::fs::rename(&temporary_path, &path)
+        .with_context(|| format!("Failed to rename {:?}", temporary_path))
+}
+
+pub fn load_cached_block(dir: &Path, slot: u64) -> Result<BlockInfo> {
+    let path = cached_block_path(dir, slot);
+    let compressed = std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    let bytes = zstd::stream::decode_all(compressed.as_slice())
+        .with_context(|| format!("Failed to decompress {:?}", path))?;
+    bincode::deserialize(&bytes).with_context(|| format!("Failed to deserialize {:?}", path))
+}
+
+/// Returns the slots of the blocks in `dir` within the inclusive range, in ascending order.
+pub fn cached_slots(dir: &Path, start_slot: u64, end_slot: u64) -> Result<Vec<u64>> {
+    let mut slots = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let file_name = entry?.file_name();
+        let slot = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(CACHED_BLOCK_EXTENSION))
+            .and_then(|slot| slot.parse::<u64>().ok());
+        if let Some(slot) = slot.filter(|slot| (start_slot..=end_slot).contains(slot)) {
+            slots.push(slot);
+        }
+    }
+    slots.sort_unstable();
+    Ok(slots)
+}
+
+/// Stores the blocks in the block cache if it is enabled. Failing to cache a block does not fail
+/// indexing, since the cache is only a convenience for replays.
+pub async fn cache_blocks(blocks: impl IntoIterator<Item = &BlockInfo>) {
+    let Some(dir) = BLOCK_CACHE_DIR.lock().unwrap().clone() else {
+        return;
+    };
+    let blocks = blocks.into_iter().cloned().collect::<Vec<_>>();
+    let result = tokio::task::spawn_blocking(move || {
+        blocks
+            .iter()
+            .try_for_each(|block| store_cached_block(&dir, block))
+    })
+    .await
+    .map_err(|e| anyhow!(e))
+    .and_then(|result| result);
+    if let Err(e) = result {
+        error!("Failed to cache blocks: {:#}", e);
+        metric! {
+            statsd_count!("block_cache_write_failed", 1);
+        }
+    }
+}
+
+/// Indexes the cached blocks in `dir` within the inclusive slot range again, in slot order.
+/// Transactions that are already indexed are skipped, so replays are meant for a fresh database,
+/// e.g. after a schema change or a parser fix. Returns the number of replayed blocks.
+pub async fn replay_cached_blocks(
+    indexer: &IndexerContext,
+    dir: &Path,
+    start_slot: u64,
+    end_slot: u64,
+) -> Result<usize> {
+    let slots = cached_slots(dir, start_slot, end_slot)?;
+    info!("Replaying {} cached blocks", slots.len());
+    for (index, slot_chunk) in slots.chunks(REPLAY_BATCH_SIZE).enumerate() {
+        let blocks = slot_chunk
+            .iter()
+            .map(|slot| load_cached_block(dir, *slot))
+            .collect::<Result<Vec<_>>>()?;
+        index_block_batch_with_infinite_retries(indexer, blocks).await;
+        info!(
+            "Replayed {} / {} cached blocks",
+            index * REPLAY_BATCH_SIZE + slot_chunk.len(),
+            slots.len()
+        );
+    }
+    Ok(slots.len())
+}
@@ -0,0 +1,66 @@
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryTrait, Set,
+};
+
+use crate::dao::generated::checkpoints;
+
+use super::error::IngesterError;
+
+/// Name of the checkpoint of the slot up to which the ingester has indexed blocks.
+pub const INGESTER_CHECKPOINT: &str = "ingester";
+
+/// Moves the ingester checkpoint forward to `slot`. It has to be called in the transaction that
+/// persists the blocks up to `slot`, so that the checkpoint never gets ahead of the indexed state
+/// after a crash. Older slots, e.g. of filled gaps, leave the checkpoint as it is.
+pub async fn advance_checkpoint(
+    conn: &impl ConnectionTrait,
+    slot: u64,
+) -> Result<(), IngesterError> {
+    let slot = slot as i64;
+    let query = checkpoints::Entity::update_many()
+        .col_expr(checkpoints::Column::Slot, Expr::value(slot))
+        .filter(checkpoints::Column::Name.eq(INGESTER_CHECKPOINT))
+        .filter(checkpoints::Column::Slot.lt(slot))
+        .build(conn.get_database_backend());
+    if conn.execute(query).await?.rows_affected() == 0 {
+        let query = checkpoints::Entity::insert(checkpoints::ActiveModel {
+            name: Set(INGESTER_CHECKPOINT.to_string()),
+            slot: Set(slot),
+        })
+        .on_conflict(
+            OnConflict::column(checkpoints::Column::Name)
+                .do_nothing()
+                .to_owned(),
+        )
+        .build(conn.get_database_backend());
+        conn.execute(query).await?;
+    }
+    Ok(())
+}
+
+/// Moves the ingester checkpoint back to `slot` when the blocks above it are rolled back.
+pub async fn rewind_checkpoint(
+    conn: &impl ConnectionTrait,
+    slot: u64,
+) -> Result<(), IngesterError> {
+    let slot = slot as i64;
+    let query = checkpoints::Entity::update_many()
+        .col_expr(checkpoints::Column::Slot, Expr::value(slot))
+        .filter(checkpoints::Column::Name.eq(INGESTER_CHECKPOINT))
+        .filter(checkpoints::Column::Slot.gt(slot))
+        .build(conn.get_database_backend());
+    conn.execute(query).await?;
+    Ok(())
+}
+
+/// Returns the slot up to which the ingester has indexed blocks, or `None` if it has not indexed
+/// any block yet.
+pub async fn fetch_checkpoint(conn: &impl ConnectionTrait) -> Result<Option<u64>, IngesterError> {
+    Ok(
+        checkpoints::Entity::find_by_id(INGESTER_CHECKPOINT.to_string())
+            .one(conn)
+            .await?
+            .map(|checkpoint| checkpoint.slot as u64),
+    )
+}
@@ -0,0 +1,129 @@
+use cadence_macros::statsd_count;
+use log::{error, info};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QueryTrait, Set, TransactionTrait,
+};
+
+use crate::{dao::generated::failed_blocks, metric};
+
+use super::{
+    error::IngesterError, index_block_metadatas, persist_parsed_block_batch,
+    typedefs::block_info::BlockInfo, IndexerContext, ParsedBlock,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedBlock {
+    pub slot: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetrySummary {
+    pub indexed: usize,
+    pub failed: usize,
+}
+
+/// Moves a block that could not be indexed to the dead-letter queue. Its metadata is still
+/// recorded, so that gap detection does not refetch it and indexing continues after it.
+pub async fn dead_letter_block(
+    indexer: &IndexerContext,
+    block: &BlockInfo,
+    error: &IngesterError,
+) -> Result<(), IngesterError> {
+    let block_bytes = bincode::serialize(block)
+        .map_err(|e| IngesterError::ParserError(format!("Failed to serialize block: {}", e)))?;
+    let txn = indexer.db.begin().await?;
+    index_block_metadatas(&txn, indexer.sql_batch_config, vec![&block.metadata]).await?;
+    let query = failed_blocks::Entity::insert(failed_blocks::ActiveModel {
+        slot: Set(block.metadata.slot as i64),
+        block: Set(block_bytes),
+        error: Set(error.to_string()),
+    })
+    .on_conflict(
+        OnConflict::column(failed_blocks::Column::Slot)
+            .update_columns([failed_blocks::Column::Block, failed_blocks::Column::Error])
+            .to_owned(),
+    )
+    .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    txn.commit().await?;
+    metric! {
+        statsd_count!("block_dead_lettered", 1, "kind" => error.kind());
+    }
+    Ok(())
+}
+
+pub async fn fetch_failed_blocks(
+    db: &DatabaseConnection,
+) -> Result<Vec<FailedBlock>, IngesterError> {
+    Ok(failed_blocks::Entity::find()
+        .order_by_asc(failed_blocks::Column::Slot)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|failed_block| FailedBlock {
+            slot: failed_block.slot as u64,
+            error: failed_block.error,
+        })
+        .collect())
+}
+
+/// Indexes the blocks in the dead-letter queue in slot order, or only the block at `slot` if it is
+/// given. Blocks that are indexed are removed from the queue, and the error of blocks that fail
+/// again is updated. Accounts that were spent by blocks indexed while their own block was in the
+/// queue are inserted as spent. Indexed blocks are published to subscribers and webhooks like
+/// blocks that are indexed live.
+pub async fn retry_failed_blocks(
+    indexer: &IndexerContext,
+    slot: Option<u64>,
+) -> Result<RetrySummary, IngesterError> {
+    let db = indexer.db.as_ref();
+    let mut query = failed_blocks::Entity::find().order_by_asc(failed_blocks::Column::Slot);
+    if let Some(slot) = slot {
+        query = query.filter(failed_blocks::Column::Slot.eq(slot as i64));
+    }
+    let mut summary = RetrySummary::default();
+    for failed_block in query.all(db).await? {
+        let slot = failed_block.slot as u64;
+        match retry_failed_block(indexer, failed_block).await {
+            Ok(()) => {
+                info!("Indexed failed block {}", slot);
+                summary.indexed += 1;
+            }
+            Err(e) => {
+                error!("Failed to index failed block {}: {}", slot, e);
+                failed_blocks::Entity::update_many()
+                    .col_expr(failed_blocks::Column::Error, Expr::value(e.to_string()))
+                    .filter(failed_blocks::Column::Slot.eq(slot as i64))
+                    .exec(db)
+                    .await?;
+                summary.failed += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+async fn retry_failed_block(
+    indexer: &IndexerContext,
+    failed_block: failed_blocks::Model,
+) -> Result<(), IngesterError> {
+    let slot = failed_block.slot as u64;
+    let block: BlockInfo =
+        bincode::deserialize(&failed_block.block).map_err(|e| IngesterError::MalformedBlock {
+            slot,
+            msg: format!("Failed to deserialize dead-lettered block: {}", e),
+        })?;
+    let parsed_block = [ParsedBlock::parse(block)];
+    indexer.fetch_tree_infos(&parsed_block).await;
+    let txn = indexer.db.begin().await?;
+    let committed_batch = persist_parsed_block_batch(indexer, &txn, &parsed_block).await?;
+    failed_blocks::Entity::delete_by_id(slot as i64)
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    committed_batch.apply(indexer).await;
+    Ok(())
+}
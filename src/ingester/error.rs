@@ -1,6 +1,9 @@
+use std::fmt::Display;
+
+use sea_orm::{DbErr, RuntimeErr};
 use thiserror::Error;
 
-#[derive(Error, Debug, PartialEq, Eq)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum IngesterError {
     #[error("Persist logic for {event_type} has not yet been implemented")]
     EventNotImplemented { event_type: String },
@@ -8,12 +11,78 @@ pub enum IngesterError {
     MalformedEvent { msg: String },
     #[error("Database error: {0}")]
     DatabaseError(String),
+    #[error("Database is unavailable: {0}")]
+    DatabaseUnavailable(String),
     #[error("Parser error: {0}")]
     ParserError(String),
+    #[error("Malformed block {slot}: {msg}")]
+    MalformedBlock { slot: u64, msg: String },
+    #[error("Malformed transaction {signature}: {msg}")]
+    MalformedTransaction { signature: String, msg: String },
+    #[error("Slot {slot} is no longer available on the RPC node: {msg}")]
+    SlotUnavailable { slot: u64, msg: String },
+}
+
+impl IngesterError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IngesterError::EventNotImplemented { .. } => "event_not_implemented",
+            IngesterError::MalformedEvent { .. } => "malformed_event",
+            IngesterError::DatabaseError(_) => "database_error",
+            IngesterError::DatabaseUnavailable(_) => "database_unavailable",
+            IngesterError::ParserError(_) => "parser_error",
+            IngesterError::MalformedBlock { .. } => "malformed_block",
+            IngesterError::MalformedTransaction { .. } => "malformed_transaction",
+            IngesterError::SlotUnavailable { .. } => "slot_unavailable",
+        }
+    }
+
+    /// Whether the failed operation can succeed when it is retried with the same input. Parsing
+    /// errors are deterministic, so retrying them only stalls indexing.
+    pub fn retryable(&self) -> bool {
+        match self {
+            IngesterError::DatabaseError(_) | IngesterError::DatabaseUnavailable(_) => true,
+            IngesterError::EventNotImplemented { .. }
+            | IngesterError::MalformedEvent { .. }
+            | IngesterError::ParserError(_)
+            | IngesterError::MalformedBlock { .. }
+            | IngesterError::MalformedTransaction { .. }
+            | IngesterError::SlotUnavailable { .. } => false,
+        }
+    }
+
+    /// Wraps an error of a database operation together with the context it occurred in. Errors
+    /// that mean that the database cannot be reached are kept apart from the ones caused by the
+    /// operation itself, e.g. constraint violations, which can be specific to the indexed block.
+    pub fn database(context: impl Display, err: DbErr) -> Self {
+        let msg = format!("{}: {}", context, err);
+        if is_connection_error(&err) {
+            IngesterError::DatabaseUnavailable(msg)
+        } else {
+            IngesterError::DatabaseError(msg)
+        }
+    }
+}
+
+fn is_connection_error(err: &DbErr) -> bool {
+    match err {
+        DbErr::ConnectionAcquire | DbErr::Conn(_) => true,
+        DbErr::Exec(RuntimeErr::SqlxError(e)) | DbErr::Query(RuntimeErr::SqlxError(e)) => {
+            matches!(
+                e,
+                sqlx::Error::Io(_)
+                    | sqlx::Error::Tls(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::PoolClosed
+                    | sqlx::Error::WorkerCrashed
+            )
+        }
+        _ => false,
+    }
 }
 
-impl From<sea_orm::error::DbErr> for IngesterError {
-    fn from(err: sea_orm::error::DbErr) -> Self {
-        IngesterError::DatabaseError(format!("DatabaseError: {}", err))
+impl From<DbErr> for IngesterError {
+    fn from(err: DbErr) -> Self {
+        IngesterError::database("DatabaseError", err)
     }
 }
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use cadence_macros::statsd_count;
+use log::error;
+use once_cell::sync::Lazy;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use tokio::sync::broadcast;
+
+use crate::api::method::utils::parse_account_model;
+use crate::common::account_data_deduplication::resolve_account_data;
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::hash::Hash;
+use crate::dao::generated::accounts;
+use crate::metric;
+
+use super::parser::state_update::StateUpdate;
+use super::persist::batch_size::SqlBatchConfig;
+use super::typedefs::block_info::BlockMetadata;
+
+// Subscribers that fall more than this many events behind skip the missed events.
+const EVENT_CHANNEL_CAPACITY: usize = 10_000;
+// Slot subscribers only care about the latest slot, so they can skip missed slots early.
+const SLOT_CHANNEL_CAPACITY: usize = 1_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IngestionEvent {
+    AccountCreated {
+        account: Account,
+        slot: u64,
+        block_time: i64,
+    },
+    AccountSpent {
+        account: Account,
+        slot: u64,
+        block_time: i64,
+    },
+}
+
+// Slots are published on their own channel, so that slot subscribers neither make the ingester
+// collect account events nor have to skip them.
+static EVENTS: Lazy<broadcast::Sender<IngestionEvent>> =
+    Lazy::new(|| broadcast::channel(EVENT_CHANNEL_CAPACITY).0);
+static INDEXED_SLOTS: Lazy<broadcast::Sender<u64>> =
+    Lazy::new(|| broadcast::channel(SLOT_CHANNEL_CAPACITY).0);
+
+pub fn subscribe() -> broadcast::Receiver<IngestionEvent> {
+    EVENTS.subscribe()
+}
+
+pub fn subscribe_indexed_slots() -> broadcast::Receiver<u64> {
+    INDEXED_SLOTS.subscribe()
+}
+
+fn has_subscribers() -> bool {
+    EVENTS.receiver_count() > 0
+}
+
+fn publish(event: IngestionEvent) {
+    // Sending only fails if every subscriber has disconnected in the meantime.
+    let _ = EVENTS.send(event);
+}
+
+/// Publishes the slot of a block once it is committed.
+pub fn publish_indexed_slot(slot: u64) {
+    let _ = INDEXED_SLOTS.send(slot);
+}
+
+/// Account changes of a state update that are published once the state update is committed. We
+/// must not publish before committing since the transaction might still be rolled back and retried.
+pub struct PendingAccountEvents {
+    created: Vec<Account>,
+    /// Spent accounts with the slots of the transactions that spent them.
+    spent: HashMap<Hash, u64>,
+    block_times: HashMap<u64, i64>,
+}
+
+impl PendingAccountEvents {
+    /// Returns `None` if nobody is subscribed so that we do not pay for publishing when the
+    /// subscription API is unused.
+    pub fn collect(state_update: &StateUpdate, blocks: &[&BlockMetadata]) -> Option<Self> {
+        if !has_subscribers() {
+            return None;
+        }
+        Some(Self {
+            created: state_update.out_accounts.clone(),
+            // Every spent account comes with the transaction that spent it, which belongs to the
+            // block of the spend also when a batch of blocks is committed at once.
+            spent: state_update
+                .account_transactions
+                .iter()
+                .filter(|account_transaction| account_transaction.spent)
+                .map(|account_transaction| {
+                    (account_transaction.hash.clone(), account_transaction.slot)
+                })
+                .collect(),
+            block_times: blocks
+                .iter()
+                .map(|block| (block.slot, block.block_time))
+                .collect(),
+        })
+    }
+
+    pub async fn publish(self, db: &DatabaseConnection, batch_config: SqlBatchConfig) {
+        let PendingAccountEvents {
+            created,
+            spent,
+            block_times,
+        } = self;
+        let block_time = |slot| block_times.get(&slot).copied().unwrap_or_default();
+        for account in created {
+            let slot = account.slot_created.0;
+            publish(IngestionEvent::AccountCreated {
+                account,
+                slot,
+                block_time: block_time(slot),
+            });
+        }
+        // Input accounts are only referenced by hash, so we load them to be able to notify
+        // subscribers of their address.
+        let spent_hashes = spent.keys().cloned().collect::<Vec<_>>();
+        for chunk in spent_hashes.chunks(batch_config.batch_size(1)) {
+            match load_accounts(db, chunk).await {
+                Ok(spent_accounts) => {
+                    for account in spent_accounts {
+                        let slot = spent[&account.hash];
+                        publish(IngestionEvent::AccountSpent {
+                            account,
+                            slot,
+                            block_time: block_time(slot),
+                        });
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to load spent accounts for notifications: {}", e);
+                    metric! {
+                        statsd_count!("account_event_publish_error", 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn load_accounts(db: &DatabaseConnection, hashes: &[Hash]) -> Result<Vec<Account>, String> {
+    let mut models = accounts::Entity::find()
+        .filter(accounts::Column::Hash.is_in(hashes.iter().map(|hash| hash.to_vec())))
+        .all(db)
+        .await
+        .map_err(|e| e.to_string())?;
+    resolve_account_data(db, models.iter_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+    models
+        .into_iter()
+        .map(|model| parse_account_model(model).map_err(|e| e.to_string()))
+        .collect()
+}
@@ -0,0 +1,262 @@
+use std::{
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cadence_macros::statsd_count;
+use prost::Message;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+use yellowstone_grpc_proto::solana::storage::confirmed_block::ConfirmedBlock;
+
+use crate::{
+    common::typedefs::hash::Hash,
+    ingester::{
+        error::IngesterError,
+        typedefs::block_info::{drop_malformed_transactions, BlockInfo, BlockMetadata},
+    },
+    metric,
+};
+
+use super::{
+    grpc::parse_confirmed_transaction,
+    poller::{INITIAL_FETCH_RETRY_BACKOFF, MAX_FETCH_RETRY_BACKOFF},
+};
+
+pub const BIGTABLE_API_URL: &str = "https://bigtable.googleapis.com/v2";
+const BLOCKS_TABLE: &str = "blocks";
+// Blocks are stored protobuf encoded in this cell. Rows that were written before the protobuf
+// format was introduced only have a bincode `bin` cell, but they predate ZK compression.
+const BLOCK_CELL: &str = "proto";
+const METADATA_SERVER_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+// Access tokens from the metadata server are refreshed this long before they expire.
+const ACCESS_TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadRowsResponse {
+    #[serde(default)]
+    chunks: Vec<CellChunk>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CellChunk {
+    row_key: Option<String>,
+    qualifier: Option<String>,
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    reset_row: bool,
+    #[serde(default)]
+    commit_row: bool,
+}
+
+#[derive(Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Reads historical blocks from the Bigtable instance that Solana validators upload the ledger to,
+/// e.g. for backfills of ranges that the RPC node no longer serves. Requests are authenticated with
+/// a static access token if one is provided, e.g. from `gcloud auth print-access-token`, and with
+/// the service account of the GCE instance otherwise.
+pub struct BigtableBlockFetcher {
+    http_client: reqwest::Client,
+    table_url: String,
+    static_access_token: Option<String>,
+    cached_access_token: Mutex<Option<(String, Instant)>>,
+}
+
+impl BigtableBlockFetcher {
+    pub fn new(
+        api_url: &str,
+        project: &str,
+        instance: &str,
+        static_access_token: Option<String>,
+    ) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            table_url: format!(
+                "{}/projects/{}/instances/{}/tables/{}",
+                api_url.trim_end_matches('/'),
+                project,
+                instance,
+                BLOCKS_TABLE
+            ),
+            static_access_token,
+            cached_access_token: Mutex::new(None),
+        }
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        if let Some(access_token) = &self.static_access_token {
+            return Ok(access_token.clone());
+        }
+        let mut cached_access_token = self.cached_access_token.lock().await;
+        if let Some((access_token, expires_at)) = cached_access_token.as_ref() {
+            if Instant::now() + ACCESS_TOKEN_REFRESH_MARGIN < *expires_at {
+                return Ok(access_token.clone());
+            }
+        }
+        let response: AccessTokenResponse = self
+            .http_client
+            .get(METADATA_SERVER_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await?
+            .error_for_status()
+            .context("Failed to fetch access token from the metadata server")?
+            .json()
+            .await?;
+        let expires_at = Instant::now() + Duration::from_secs(response.expires_in);
+        *cached_access_token = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+
+    /// Returns the block at `slot` or `None` if the slot was skipped.
+    pub async fn fetch_block(&self, slot: u64) -> Result<Option<BlockInfo>> {
+        let row_key = format!("{:016x}", slot);
+        let request = json!({
+            "rows": { "rowKeys": [STANDARD.encode(&row_key)] },
+            "filter": {
+                "chain": {
+                    "filters": [
+                        { "columnQualifierRegexFilter": STANDARD.encode(BLOCK_CELL) },
+                        { "cellsPerColumnLimitFilter": 1 },
+                    ]
+                }
+            },
+        });
+        let responses: Vec<ReadRowsResponse> = self
+            .http_client
+            .post(format!("{}:readRows", self.table_url))
+            .bearer_auth(self.access_token().await?)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to read row {} from Bigtable", row_key))?
+            .json()
+            .await?;
+        let cell = match read_committed_cell(responses)? {
+            Some(cell) => cell,
+            None => return Ok(None),
+        };
+        let block = ConfirmedBlock::decode(decompress(&cell)?.as_slice())
+            .with_context(|| format!("Failed to decode block {}", slot))?;
+        Ok(Some(parse_confirmed_block(block, slot)?))
+    }
+
+    pub async fn fetch_block_with_infinite_retries(&self, slot: u64) -> Option<BlockInfo> {
+        let mut backoff = INITIAL_FETCH_RETRY_BACKOFF;
+        loop {
+            match self.fetch_block(slot).await {
+                Ok(Some(mut block)) => {
+                    metric! {
+                        statsd_count!("bigtable_block_fetched", 1);
+                    }
+                    block.metadata.received_at = Some(Instant::now());
+                    return Some(block);
+                }
+                Ok(None) => {
+                    metric! {
+                        statsd_count!("bigtable_skipped_block", 1);
+                    }
+                    log::info!("Skipped block: {}", slot);
+                    return None;
+                }
+                Err(e) => {
+                    log::warn!("Failed to fetch block {} from Bigtable: {:#}", slot, e);
+                    metric! {
+                        statsd_count!("bigtable_block_fetch_failed", 1);
+                    }
+                }
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_FETCH_RETRY_BACKOFF);
+        }
+    }
+}
+
+// Reassembles the value of the single requested cell from the chunks of a `readRows` response. A
+// value can be split across several chunks, and a row is only valid once it is committed.
+fn read_committed_cell(responses: Vec<ReadRowsResponse>) -> Result<Option<Vec<u8>>> {
+    let mut value = Vec::new();
+    for chunk in responses.into_iter().flat_map(|response| response.chunks) {
+        if chunk.row_key.is_some() || chunk.qualifier.is_some() {
+            value.clear();
+        }
+        value.extend(STANDARD.decode(&chunk.value)?);
+        if chunk.reset_row {
+            value.clear();
+        } else if chunk.commit_row {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+// Cells are prefixed with the bincode encoded compression method, i.e. a little endian u32.
+fn decompress(cell: &[u8]) -> Result<Vec<u8>> {
+    if cell.len() < 4 {
+        return Err(anyhow!("Cell is too short: {} bytes", cell.len()));
+    }
+    let (method, data) = cell.split_at(4);
+    let mut decompressed = Vec::new();
+    match u32::from_le_bytes(method.try_into()?) {
+        0 => decompressed.extend_from_slice(data),
+        1 => {
+            bzip2::read::BzDecoder::new(data).read_to_end(&mut decompressed)?;
+        }
+        2 => {
+            flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed)?;
+        }
+        3 => {
+            zstd::Decoder::new(data)?.read_to_end(&mut decompressed)?;
+        }
+        method => return Err(anyhow!("Unknown compression method: {}", method)),
+    }
+    Ok(decompressed)
+}
+
+fn parse_confirmed_block(block: ConfirmedBlock, slot: u64) -> Result<BlockInfo, IngesterError> {
+    let malformed_block = |msg: String| IngesterError::MalformedBlock { slot, msg };
+    let metadata = BlockMetadata {
+        slot,
+        parent_slot: block.parent_slot,
+        block_time: block
+            .block_time
+            .ok_or(malformed_block("Missing block_time".to_string()))?
+            .timestamp,
+        blockhash: Hash::try_from(block.blockhash.as_str())
+            .map_err(|e| malformed_block(format!("Failed to parse blockhash: {}", e)))?,
+        parent_blockhash: Hash::try_from(block.previous_blockhash.as_str())
+            .map_err(|e| malformed_block(format!("Failed to parse previous_blockhash: {}", e)))?,
+        block_height: block
+            .block_height
+            .ok_or(malformed_block("Missing block_height".to_string()))?
+            .block_height,
+        received_at: None,
+    };
+    let transactions = drop_malformed_transactions(
+        slot,
+        block.transactions.into_iter().map(|transaction| {
+            let signature = transaction
+                .transaction
+                .as_ref()
+                .and_then(|transaction| transaction.signatures.first().cloned())
+                .unwrap_or_default();
+            parse_confirmed_transaction(signature, transaction.transaction, transaction.meta)
+        }),
+    );
+    Ok(BlockInfo {
+        metadata,
+        transactions,
+    })
+}
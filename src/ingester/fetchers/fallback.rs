@@ -0,0 +1,142 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_stream::stream;
+use cadence_macros::statsd_count;
+use futures::future::{select, Either};
+use futures::{pin_mut, Stream, StreamExt};
+use log::info;
+use solana_client::nonblocking::rpc_client::RpcClient;
+
+use crate::api::method::get_indexer_health::HEALTH_CHECK_SLOT_DISTANCE;
+use crate::ingester::fetchers::poller::get_block_poller_stream;
+use crate::ingester::typedefs::block_info::BlockInfo;
+use crate::metric;
+use crate::monitor::{start_latest_slot_updater, LATEST_SLOT};
+
+/// Streams the blocks of a push-based `block_stream`, e.g. gRPC or the Geyser plugin socket, and
+/// polls the RPC node instead while the stream is behind, stalls or skips a block. `source` names
+/// the stream in logs.
+pub fn get_stream_with_rpc_fallback(
+    block_stream: impl Stream<Item = BlockInfo>,
+    source: &'static str,
+    rpc_client: Arc<RpcClient>,
+    mut last_indexed_slot: u64,
+    max_concurrent_block_fetches: usize,
+) -> impl Stream<Item = Vec<BlockInfo>> {
+    stream! {
+        start_latest_slot_updater(rpc_client.clone()).await;
+        pin_mut!(block_stream);
+        let mut rpc_poll_stream:  Option<Pin<Box<dyn Stream<Item = Vec<BlockInfo>> + Send>>> = Some(
+            Box::pin(get_block_poller_stream(
+                rpc_client.clone(),
+                last_indexed_slot,
+                max_concurrent_block_fetches,
+            ))
+        );
+
+        // Await either the pushed blocks or the RPC block fetching
+        loop {
+            match rpc_poll_stream.as_mut() {
+                Some(rpc_poll_stream_value) => {
+                    match select(block_stream.next(), rpc_poll_stream_value.next()).await {
+                        Either::Left((Some(pushed_block), _)) => {
+                            let slot = pushed_block.metadata.slot;
+                            if pushed_block.metadata.parent_slot == last_indexed_slot {
+                                last_indexed_slot = pushed_block.metadata.slot;
+                                yield vec![pushed_block];
+                                metric! {
+                                    statsd_count!("pushed_block_indexed", 1);
+                                }
+                                if is_healthy(slot) {
+                                    info!("Switching to {} block fetching since Photon is up-to-date", source);
+                                    rpc_poll_stream = None;
+                                }
+                            }
+                        }
+                        Either::Left((None, _)) => {
+                            panic!("{} stream ended unexpectedly", source);
+                        }
+                        Either::Right((Some(rpc_blocks), _)) => {
+                            let rpc_blocks: Vec<BlockInfo> = rpc_blocks
+                                .into_iter()
+                                .filter(|b| b.metadata.slot > last_indexed_slot)
+                                .collect();
+                            if rpc_blocks.is_empty() {
+                                continue;
+                            }
+                            let blocks_len = rpc_blocks.len();
+                            let parent_slot = rpc_blocks.first().unwrap().metadata.parent_slot;
+                            let last_slot = rpc_blocks.last().unwrap().metadata.slot;
+                            // The poller also emits blocks whose parent is below the last
+                            // indexed slot, so that orphaned blocks can be rolled back.
+                            if parent_slot <= last_indexed_slot {
+                                last_indexed_slot = last_slot;
+                                yield rpc_blocks;
+                                metric! {
+                                    statsd_count!("rpc_block_indexed", blocks_len as i64);
+                                }
+                            }
+                        }
+                        Either::Right((None, _)) => {
+                            panic!("RPC stream ended unexpectedly");
+                        }
+                    }
+                }
+                None => {
+                    let block = match tokio::time::timeout(Duration::from_secs(5), block_stream.next()).await {
+                        Ok(Some(block)) => block,
+                        Ok(None) => panic!("{} stream ended unexpectedly", source),
+                        Err(_) => {
+                            metric! {
+                                statsd_count!("grpc_timeout", 1);
+                            }
+                            info!("{} stream timed out, enabling RPC block fetching", source);
+                            rpc_poll_stream = Some(Box::pin(get_block_poller_stream(
+                                rpc_client.clone(),
+                                last_indexed_slot,
+                                max_concurrent_block_fetches,
+                            )));
+                            continue;
+                        }
+                    };
+                    let slot = block.metadata.slot;
+                    if block.metadata.parent_slot == last_indexed_slot {
+                        last_indexed_slot = block.metadata.slot;
+                        yield vec![block];
+                    } else {
+                        metric! {
+                            statsd_count!("grpc_out_of_order", 1);
+                        }
+                        info!("Switching to RPC block fetching");
+                        rpc_poll_stream = Some(Box::pin(get_block_poller_stream(
+                            rpc_client.clone(),
+                            last_indexed_slot,
+                            max_concurrent_block_fetches,
+                        )));
+                        continue;
+                    }
+                    if !is_healthy(slot) && rpc_poll_stream.is_none() {
+                        info!("{} is unhealthy. Enabling RPC block fetching", source);
+                        metric! {
+                            statsd_count!("grpc_stale", 1);
+                        }
+                        rpc_poll_stream = Some(Box::pin(get_block_poller_stream(
+                            rpc_client.clone(),
+                            last_indexed_slot,
+                            max_concurrent_block_fetches,
+                        )));
+                    }
+                }
+            }
+
+
+        }
+    }
+}
+
+fn is_healthy(slot: u64) -> bool {
+    (LATEST_SLOT.load(Ordering::SeqCst) as i64 - slot as i64) <= HEALTH_CHECK_SLOT_DISTANCE
+}
@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use async_stream::stream;
+use futures::Stream;
+use solana_transaction_status::UiConfirmedBlock;
+
+use crate::ingester::typedefs::block_info::{parse_ui_confirmed_blocked, BlockInfo};
+
+/// Loads the blocks stored in `dir`, one file per block named after its slot. A file holds either
+/// the result of a `getBlock` request with JSON encoding, like the blocks that the tests record in
+/// `tests/data/blocks`, or a serialized `BlockInfo`, e.g. of transactions built for a test. The
+/// blocks are returned in the order of their slots.
+pub fn load_fixture_blocks(dir: &Path) -> Result<Vec<BlockInfo>> {
+    let mut blocks = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let path = entry?.path();
+        let slot = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+            .ok_or_else(|| anyhow!("Fixture {:?} is not named after a slot", path))?;
+        let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        let block = match serde_json::from_slice::<UiConfirmedBlock>(&bytes) {
+            Ok(block) => parse_ui_confirmed_blocked(block, slot)?,
+            Err(_) => serde_json::from_slice::<BlockInfo>(&bytes)
+                .with_context(|| format!("Fixture {:?} is not a block", path))?,
+        };
+        blocks.push(block);
+    }
+    blocks.sort_by_key(|block| block.metadata.slot);
+    Ok(blocks)
+}
+
+/// Streams canned blocks in place of a network source, so that the whole ingester can be tested
+/// deterministically. Like the other block sources, it starts after `last_indexed_slot` and yields
+/// the blocks one at a time. Unlike them, it ends once all blocks have been yielded.
+pub fn get_fixture_block_stream(
+    blocks: Vec<BlockInfo>,
+    last_indexed_slot: u64,
+) -> impl Stream<Item = Vec<BlockInfo>> {
+    stream! {
+        for block in blocks {
+            if last_indexed_slot == 0 || block.metadata.slot > last_indexed_slot {
+                yield vec![block];
+            }
+        }
+    }
+}
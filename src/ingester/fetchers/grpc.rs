@@ -1,14 +1,11 @@
-use std::pin::Pin;
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Instant;
 use std::{collections::HashMap, time::Duration};
 
 use async_stream::stream;
 use cadence_macros::statsd_count;
-use futures::future::{select, Either};
 use futures::sink::SinkExt;
-use futures::{pin_mut, Stream, StreamExt};
-use log::info;
+use futures::{Stream, StreamExt};
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use solana_client::nonblocking::rpc_client::RpcClient;
@@ -24,140 +21,43 @@ use yellowstone_grpc_proto::geyser::{
 use yellowstone_grpc_proto::geyser::{
     SubscribeRequestFilterBlocks, SubscribeUpdateBlock, SubscribeUpdateTransactionInfo,
 };
-use yellowstone_grpc_proto::solana::storage::confirmed_block::InnerInstructions;
+use yellowstone_grpc_proto::solana::storage::confirmed_block::{
+    InnerInstructions, Transaction, TransactionStatusMeta,
+};
 
-use crate::api::method::get_indexer_health::HEALTH_CHECK_SLOT_DISTANCE;
 use crate::common::typedefs::hash::Hash;
-use crate::ingester::fetchers::poller::get_block_poller_stream;
+use crate::ingester::error::IngesterError;
+use crate::ingester::fetchers::fallback::get_stream_with_rpc_fallback;
+use crate::ingester::parser::ACCOUNT_COMPRESSION_PROGRAM_ID;
 use crate::ingester::typedefs::block_info::{
-    BlockInfo, BlockMetadata, Instruction, InstructionGroup, TransactionInfo,
+    drop_malformed_transactions, outer_program_groups, BlockInfo, BlockMetadata, Instruction,
+    InstructionGroup, TransactionInfo,
 };
 
 use crate::metric;
-use crate::monitor::{start_latest_slot_updater, LATEST_SLOT};
 
 pub fn get_grpc_stream_with_rpc_fallback(
     endpoint: String,
     auth_header: String,
     rpc_client: Arc<RpcClient>,
-    mut last_indexed_slot: u64,
+    last_indexed_slot: u64,
     max_concurrent_block_fetches: usize,
+    compression_transactions_only: bool,
 ) -> impl Stream<Item = Vec<BlockInfo>> {
-    stream! {
-        start_latest_slot_updater(rpc_client.clone()).await;
-        let grpc_stream = get_grpc_block_stream(endpoint, auth_header);
-        pin_mut!(grpc_stream);
-        let mut rpc_poll_stream:  Option<Pin<Box<dyn Stream<Item = Vec<BlockInfo>> + Send>>> = Some(
-            Box::pin(get_block_poller_stream(
-                rpc_client.clone(),
-                last_indexed_slot,
-                max_concurrent_block_fetches,
-            ))
-        );
-
-        // Await either the gRPC stream or the RPC block fetching
-        loop {
-            match rpc_poll_stream.as_mut() {
-                Some(rpc_poll_stream_value) => {
-                    match select(grpc_stream.next(), rpc_poll_stream_value.next()).await {
-                        Either::Left((Some(grpc_block), _)) => {
-                            let slot = grpc_block.metadata.slot;
-                            if grpc_block.metadata.parent_slot == last_indexed_slot {
-                                last_indexed_slot = grpc_block.metadata.slot;
-                                yield vec![grpc_block];
-                                metric! {
-                                    statsd_count!("grpc_block_indexed", 1);
-                                }
-                                if is_healthy(slot) {
-                                    info!("Switching to gRPC block fetching since Photon is up-to-date");
-                                    rpc_poll_stream = None;
-                                }
-                            }
-                        }
-                        Either::Left((None, _)) => {
-                            panic!("gRPC stream ended unexpectedly");
-                        }
-                        Either::Right((Some(rpc_blocks), _)) => {
-                            let rpc_blocks: Vec<BlockInfo> = rpc_blocks
-                                .into_iter()
-                                .filter(|b| b.metadata.slot > last_indexed_slot)
-                                .collect();
-                            if rpc_blocks.is_empty() {
-                                continue;
-                            }
-                            let blocks_len = rpc_blocks.len();
-                            let parent_slot = rpc_blocks.first().unwrap().metadata.parent_slot;
-                            let last_slot = rpc_blocks.last().unwrap().metadata.slot;
-                            if parent_slot == last_indexed_slot {
-                                last_indexed_slot = last_slot;
-                                yield rpc_blocks;
-                                metric! {
-                                    statsd_count!("rpc_block_indexed", blocks_len as i64);
-                                }
-                            }
-                        }
-                        Either::Right((None, _)) => {
-                            panic!("RPC stream ended unexpectedly");
-                        }
-                    }
-                }
-                None => {
-                    let block = match tokio::time::timeout(Duration::from_secs(5), grpc_stream.next()).await {
-                        Ok(Some(block)) => block,
-                        Ok(None) => panic!("gRPC stream ended unexpectedly"),
-                        Err(_) => {
-                            metric! {
-                                statsd_count!("grpc_timeout", 1);
-                            }
-                            info!("gRPC stream timed out, enabling RPC block fetching");
-                            rpc_poll_stream = Some(Box::pin(get_block_poller_stream(
-                                rpc_client.clone(),
-                                last_indexed_slot,
-                                max_concurrent_block_fetches,
-                            )));
-                            continue;
-                        }
-                    };
-                    let slot = block.metadata.slot;
-                    if block.metadata.parent_slot == last_indexed_slot {
-                        last_indexed_slot = block.metadata.slot;
-                        yield vec![block];
-                    } else {
-                        metric! {
-                            statsd_count!("grpc_out_of_order", 1);
-                        }
-                        info!("Switching to RPC block fetching");
-                        rpc_poll_stream = Some(Box::pin(get_block_poller_stream(
-                            rpc_client.clone(),
-                            last_indexed_slot,
-                            max_concurrent_block_fetches,
-                        )));
-                        continue;
-                    }
-                    if !is_healthy(slot) && rpc_poll_stream.is_none() {
-                        info!("gRPC is unhealthy. Enabling RPC block fetching");
-                        metric! {
-                            statsd_count!("grpc_stale", 1);
-                        }
-                        rpc_poll_stream = Some(Box::pin(get_block_poller_stream(
-                            rpc_client.clone(),
-                            last_indexed_slot,
-                            max_concurrent_block_fetches,
-                        )));
-                    }
-                }
-            }
-
-
-        }
-    }
-}
-
-fn is_healthy(slot: u64) -> bool {
-    (LATEST_SLOT.load(Ordering::SeqCst) as i64 - slot as i64) <= HEALTH_CHECK_SLOT_DISTANCE
+    get_stream_with_rpc_fallback(
+        get_grpc_block_stream(endpoint, auth_header, compression_transactions_only),
+        "gRPC",
+        rpc_client,
+        last_indexed_slot,
+        max_concurrent_block_fetches,
+    )
 }
 
-fn get_grpc_block_stream(endpoint: String, auth_header: String) -> impl Stream<Item = BlockInfo> {
+fn get_grpc_block_stream(
+    endpoint: String,
+    auth_header: String,
+    compression_transactions_only: bool,
+) -> impl Stream<Item = BlockInfo> {
     stream! {
         loop {
             let mut grpc_tx;
@@ -175,7 +75,9 @@ fn get_grpc_block_stream(endpoint: String, auth_header: String) -> impl Stream<I
                 }
                 let subscription = grpc_client
                     .unwrap()
-                    .subscribe_with_request(Some(get_block_subscribe_request()))
+                    .subscribe_with_request(Some(get_block_subscribe_request(
+                        compression_transactions_only,
+                    )))
                     .await;
                 if let Err(e) = subscription {
                     error!("Error subscribing to gRPC stream, waiting one second then retrying connect: {}", e);
@@ -190,13 +92,22 @@ fn get_grpc_block_stream(endpoint: String, auth_header: String) -> impl Stream<I
             while let Some(message) = grpc_rx.next().await {
                 match message {
                     Ok(message) => match message.update_oneof {
-                        Some(UpdateOneof::Block(block)) => {
-                            let block = parse_block(block);
-                            metric! {
-                                statsd_count!("grpc_block_emitted", 1);
+                        Some(UpdateOneof::Block(block)) => match parse_block(block) {
+                            Ok(block) => {
+                                metric! {
+                                    statsd_count!("grpc_block_emitted", 1);
+                                }
+                                yield block;
                             }
-                            yield block;
-                        }
+                            // The RPC fallback fetches the block once the next block arrives out
+                            // of order.
+                            Err(e) => {
+                                error!("Failed to parse gRPC block: {}", e);
+                                metric! {
+                                    statsd_count!("grpc_block_parse_failed", 1);
+                                }
+                            }
+                        },
                         Some(UpdateOneof::Ping(_)) => {
                             // This is necessary to keep load balancers that expect client pings alive. If your load balancer doesn't
                             // require periodic client pings then this is unnecessary
@@ -251,12 +162,20 @@ fn generate_random_string(len: usize) -> String {
         .collect()
 }
 
-fn get_block_subscribe_request() -> SubscribeRequest {
+fn get_block_subscribe_request(compression_transactions_only: bool) -> SubscribeRequest {
+    // Every compression transaction invokes the account compression program, so filtering on it
+    // drops all other transactions from the streamed blocks. Blocks are still streamed for every
+    // slot, which keeps the parent slot checks above working.
+    let account_include = if compression_transactions_only {
+        vec![ACCOUNT_COMPRESSION_PROGRAM_ID.to_string()]
+    } else {
+        vec![]
+    };
     SubscribeRequest {
         blocks: HashMap::from_iter(vec![(
             generate_random_string(20),
             SubscribeRequestFilterBlocks {
-                account_include: vec![],
+                account_include,
                 include_transactions: Some(true),
                 include_accounts: Some(false),
                 include_entries: Some(false),
@@ -274,35 +193,94 @@ fn ping() -> SubscribeRequest {
     }
 }
 
-fn parse_block(block: SubscribeUpdateBlock) -> BlockInfo {
+fn parse_block(block: SubscribeUpdateBlock) -> Result<BlockInfo, IngesterError> {
+    let slot = block.slot;
+    let malformed_block = |msg: String| IngesterError::MalformedBlock { slot, msg };
     let metadata = BlockMetadata {
-        slot: block.slot,
+        slot,
         parent_slot: block.parent_slot,
-        block_time: block.block_time.unwrap().timestamp,
-        blockhash: Hash::try_from(block.blockhash.as_str()).unwrap(),
-        parent_blockhash: Hash::try_from(block.parent_blockhash.as_str()).unwrap(),
-        block_height: block.block_height.unwrap().block_height,
+        block_time: block
+            .block_time
+            .ok_or(malformed_block("Missing block_time".to_string()))?
+            .timestamp,
+        blockhash: Hash::try_from(block.blockhash.as_str())
+            .map_err(|e| malformed_block(format!("Failed to parse blockhash: {}", e)))?,
+        parent_blockhash: Hash::try_from(block.parent_blockhash.as_str())
+            .map_err(|e| malformed_block(format!("Failed to parse parent_blockhash: {}", e)))?,
+        block_height: block
+            .block_height
+            .ok_or(malformed_block("Missing block_height".to_string()))?
+            .block_height,
+        received_at: Some(Instant::now()),
     };
-    let transactions = block
-        .transactions
-        .into_iter()
-        .map(parse_transaction)
-        .collect();
+    let transactions =
+        drop_malformed_transactions(slot, block.transactions.into_iter().map(parse_transaction));
 
-    BlockInfo {
+    Ok(BlockInfo {
         metadata,
         transactions,
-    }
+    })
 }
 
-fn parse_transaction(transaction: SubscribeUpdateTransactionInfo) -> TransactionInfo {
-    let meta = transaction.meta.unwrap();
-    let error = create_tx_error(meta.err.as_ref()).unwrap();
+fn parse_transaction(
+    transaction: SubscribeUpdateTransactionInfo,
+) -> Result<TransactionInfo, IngesterError> {
+    parse_confirmed_transaction(
+        transaction.signature,
+        transaction.transaction,
+        transaction.meta,
+    )
+}
+
+/// Parses a transaction in the protobuf format that is shared by the gRPC stream and the Bigtable
+/// ledger storage.
+pub(crate) fn parse_confirmed_transaction(
+    signature: Vec<u8>,
+    transaction: Option<Transaction>,
+    meta: Option<TransactionStatusMeta>,
+) -> Result<TransactionInfo, IngesterError> {
+    let signature = Signature::try_from(signature)
+        .map_err(|_| IngesterError::ParserError("Failed to parse signature".to_string()))?;
+    let malformed_transaction = |msg: String| IngesterError::MalformedTransaction {
+        signature: signature.to_string(),
+        msg,
+    };
+    let meta = meta.ok_or(malformed_transaction("Missing metadata".to_string()))?;
+    let error = create_tx_error(meta.err.as_ref())
+        .map_err(|e| malformed_transaction(format!("Failed to parse error: {}", e)))?;
     let error = error.map(|e| e.to_string());
 
-    let signature = Signature::try_from(transaction.signature).unwrap();
-    let message = transaction.transaction.unwrap().message.unwrap();
+    let message = transaction
+        .and_then(|transaction| transaction.message)
+        .ok_or(malformed_transaction("Missing message".to_string()))?;
     let outer_intructions = message.instructions;
+    // Like for RPC blocks, transactions that cannot emit compression events are not parsed in full.
+    let is_compression_program =
+        |key: &Vec<u8>| key.as_slice() == ACCOUNT_COMPRESSION_PROGRAM_ID.as_ref();
+    if !message.account_keys.iter().any(is_compression_program)
+        && !meta
+            .loaded_writable_addresses
+            .iter()
+            .chain(meta.loaded_readonly_addresses.iter())
+            .any(is_compression_program)
+    {
+        let account_keys = message.account_keys;
+        return Ok(TransactionInfo {
+            instruction_groups: outer_program_groups(
+                outer_intructions
+                    .iter()
+                    .map(|ix| ix.program_id_index as usize),
+                |index| {
+                    account_keys
+                        .get(index)
+                        .and_then(|key| Pubkey::try_from(key.as_slice()).ok())
+                },
+            )
+            .map_err(malformed_transaction)?,
+            signature,
+            error,
+        });
+    }
     let mut accounts = message.account_keys;
     for account in meta.loaded_writable_addresses {
         accounts.push(account);
@@ -310,31 +288,33 @@ fn parse_transaction(transaction: SubscribeUpdateTransactionInfo) -> Transaction
     for account in meta.loaded_readonly_addresses {
         accounts.push(account);
     }
+    let account = |index: u32| {
+        let key = accounts.get(index as usize).ok_or_else(|| {
+            malformed_transaction(format!("Account index {} is out of bounds", index))
+        })?;
+        Pubkey::try_from(key.as_slice())
+            .map_err(|_| malformed_transaction(format!("Invalid account key at index {}", index)))
+    };
+    let instruction_accounts = |account_indices: &[u8]| {
+        account_indices
+            .iter()
+            .map(|account_index| account(*account_index as u32))
+            .collect::<Result<Vec<Pubkey>, IngesterError>>()
+    };
 
     let mut instruction_groups: Vec<InstructionGroup> = outer_intructions
         .iter()
         .map(|ix| {
-            let program_id =
-                Pubkey::try_from(accounts[ix.program_id_index as usize].clone()).unwrap();
-            let data = ix.data.clone();
-            let accounts: Vec<Pubkey> = ix
-                .accounts
-                .iter()
-                .map(|account_index| {
-                    Pubkey::try_from(accounts[*account_index as usize].clone()).unwrap()
-                })
-                .collect();
-
-            InstructionGroup {
+            Ok(InstructionGroup {
                 outer_instruction: Instruction {
-                    program_id,
-                    data,
-                    accounts,
+                    program_id: account(ix.program_id_index)?,
+                    data: ix.data.clone(),
+                    accounts: instruction_accounts(&ix.accounts)?,
                 },
                 inner_instructions: Vec::new(),
-            }
+            })
         })
-        .collect();
+        .collect::<Result<_, IngesterError>>()?;
 
     for inner_instruction_group in meta.inner_instructions {
         let InnerInstructions {
@@ -342,28 +322,26 @@ fn parse_transaction(transaction: SubscribeUpdateTransactionInfo) -> Transaction
             instructions,
         } = inner_instruction_group;
         for instruction in instructions {
-            let instruction_group = &mut instruction_groups[index as usize];
-            let program_id =
-                Pubkey::try_from(accounts[instruction.program_id_index as usize].clone()).unwrap();
-            let data = instruction.data.clone();
-            let accounts: Vec<Pubkey> = instruction
-                .accounts
-                .iter()
-                .map(|account_index| {
-                    Pubkey::try_from(accounts[*account_index as usize].clone()).unwrap()
-                })
-                .collect();
+            let program_id = account(instruction.program_id_index)?;
+            let accounts = instruction_accounts(&instruction.accounts)?;
+            let instruction_group =
+                instruction_groups.get_mut(index as usize).ok_or_else(|| {
+                    malformed_transaction(format!(
+                        "Inner instructions reference missing instruction {}",
+                        index
+                    ))
+                })?;
             instruction_group.inner_instructions.push(Instruction {
                 program_id,
-                data,
+                data: instruction.data,
                 accounts,
             });
         }
     }
 
-    TransactionInfo {
+    Ok(TransactionInfo {
         instruction_groups,
         signature,
         error,
-    }
+    })
 }
@@ -0,0 +1,268 @@
+use std::{collections::BTreeMap, pin::Pin, str::FromStr, sync::Arc, time::Duration};
+
+use async_stream::stream;
+use cadence_macros::statsd_count;
+use futures::{pin_mut, Stream, StreamExt};
+use log::{error, info, warn};
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use tokio::time::{sleep, timeout};
+
+use crate::{
+    ingester::{parser::ACCOUNT_COMPRESSION_PROGRAM_ID, typedefs::block_info::BlockInfo},
+    metric,
+};
+
+use super::{
+    poller::get_block_poller_stream,
+    signatures::{fetch_block, fetch_blocks, with_infinite_retries},
+};
+
+// Notifications are sent once a slot is confirmed, so the notified slots are complete once the
+// subscription has been quiet for this long.
+const LOGS_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+// While there is no compression activity, the indexed slot is advanced to this many slots before
+// the latest confirmed slot, so that notifications that are still on their way are not skipped.
+const HEARTBEAT_SLOT_MARGIN: u64 = 8;
+
+enum LogsNotification {
+    /// A new subscription has been made. Transactions may have been missed since the last one.
+    Subscribed,
+    Transaction {
+        slot: u64,
+        signature: Signature,
+    },
+}
+
+enum LogsStreamEvent {
+    Notification(Option<LogsNotification>),
+    RpcBlocks(Option<Vec<BlockInfo>>),
+    Idle,
+}
+
+/// Subscribes to the logs of the transactions that mention the account compression program over
+/// the Solana WebSocket API at `ws_url`, and resubscribes whenever the connection is lost. Failed
+/// transactions are left out since they do not change any state.
+fn get_logs_notification_stream(ws_url: String) -> impl Stream<Item = LogsNotification> {
+    stream! {
+        loop {
+            let client = match PubsubClient::new(&ws_url).await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!(
+                        "Error connecting to WebSocket {}, waiting one second then retrying connect: {}",
+                        ws_url, e
+                    );
+                    metric! {
+                        statsd_count!("logs_subscription_connect_error", 1);
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let subscription = client
+                .logs_subscribe(
+                    RpcTransactionLogsFilter::Mentions(vec![
+                        ACCOUNT_COMPRESSION_PROGRAM_ID.to_string(),
+                    ]),
+                    RpcTransactionLogsConfig {
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+                .await;
+            let (mut notifications, _unsubscribe) = match subscription {
+                Ok(subscription) => subscription,
+                Err(e) => {
+                    error!(
+                        "Error subscribing to logs at {}, waiting one second then retrying: {}",
+                        ws_url, e
+                    );
+                    metric! {
+                        statsd_count!("logs_subscription_connect_error", 1);
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            info!("Subscribed to compression program logs at {}", ws_url);
+            yield LogsNotification::Subscribed;
+            while let Some(response) = notifications.next().await {
+                if response.value.err.is_some() {
+                    continue;
+                }
+                match Signature::from_str(&response.value.signature) {
+                    Ok(signature) => yield LogsNotification::Transaction {
+                        slot: response.context.slot,
+                        signature,
+                    },
+                    Err(e) => error!("Invalid signature {}: {}", response.value.signature, e),
+                }
+            }
+            error!("Logs subscription at {} was closed, resubscribing", ws_url);
+            metric! {
+                statsd_count!("logs_subscription_closed", 1);
+            }
+        }
+    }
+}
+
+/// Streams the blocks that contain compression transactions as they are announced by a
+/// `logsSubscribe` subscription at `ws_url`. Only the announced transactions and the headers of
+/// their blocks are fetched, like with `get_signature_block_stream`.
+///
+/// Transactions that are sent while the subscription is down are lost, so the RPC node is polled
+/// for whole blocks until the blocks up to the first slot that the current subscription fully
+/// covers have been indexed, i.e. after starting and after every reconnect.
+pub fn get_logs_block_stream_with_rpc_fallback(
+    ws_url: String,
+    rpc_client: Arc<RpcClient>,
+    mut last_indexed_slot: u64,
+    max_concurrent_block_fetches: usize,
+) -> impl Stream<Item = Vec<BlockInfo>> {
+    stream! {
+        let notifications = get_logs_notification_stream(ws_url);
+        pin_mut!(notifications);
+        let mut rpc_poll_stream: Option<Pin<Box<dyn Stream<Item = Vec<BlockInfo>> + Send>>> = Some(
+            Box::pin(get_block_poller_stream(
+                rpc_client.clone(),
+                last_indexed_slot,
+                max_concurrent_block_fetches,
+            ))
+        );
+        // The first notification of a subscription may arrive after other transactions of its
+        // slot were sent, so the subscription only covers the slots after it.
+        let mut covered_from_slot: Option<u64> = None;
+        let mut pending_signatures: BTreeMap<u64, Vec<Signature>> = BTreeMap::new();
+
+        loop {
+            if rpc_poll_stream.is_some()
+                && covered_from_slot.is_some_and(|slot| last_indexed_slot + 1 >= slot)
+            {
+                info!("Switching to logs subscription since it covers every slot that is not indexed");
+                rpc_poll_stream = None;
+                pending_signatures.retain(|slot, _| *slot > last_indexed_slot);
+            }
+            let event = match rpc_poll_stream.as_mut() {
+                Some(rpc_poll_stream_value) => tokio::select! {
+                    notification = notifications.next() => LogsStreamEvent::Notification(notification),
+                    rpc_blocks = rpc_poll_stream_value.next() => LogsStreamEvent::RpcBlocks(rpc_blocks),
+                },
+                None => match timeout(LOGS_IDLE_TIMEOUT, notifications.next()).await {
+                    Ok(notification) => LogsStreamEvent::Notification(notification),
+                    Err(_) => LogsStreamEvent::Idle,
+                },
+            };
+            match event {
+                LogsStreamEvent::Notification(None) => {
+                    panic!("Logs subscription stream ended unexpectedly");
+                }
+                LogsStreamEvent::Notification(Some(LogsNotification::Subscribed)) => {
+                    covered_from_slot = None;
+                    pending_signatures.clear();
+                    if rpc_poll_stream.is_none() {
+                        info!("Switching to RPC block fetching until the logs subscription has caught up");
+                        rpc_poll_stream = Some(Box::pin(get_block_poller_stream(
+                            rpc_client.clone(),
+                            last_indexed_slot,
+                            max_concurrent_block_fetches,
+                        )));
+                    }
+                }
+                LogsStreamEvent::Notification(Some(LogsNotification::Transaction {
+                    slot,
+                    signature,
+                })) => {
+                    match covered_from_slot {
+                        None => {
+                            covered_from_slot = Some(slot + 1);
+                            continue;
+                        }
+                        Some(covered_from_slot) if slot < covered_from_slot => continue,
+                        Some(_) if slot > last_indexed_slot => {
+                            pending_signatures.entry(slot).or_default().push(signature);
+                        }
+                        // Slots up to the last indexed slot are left to the RPC fallback while it
+                        // runs.
+                        Some(_) if rpc_poll_stream.is_some() => continue,
+                        Some(_) => {
+                            warn!(
+                                "Skipping transaction {} of slot {} that was announced after the slot was indexed",
+                                signature, slot
+                            );
+                            metric! {
+                                statsd_count!("logs_notification_too_late", 1);
+                            }
+                            continue;
+                        }
+                    }
+                    if rpc_poll_stream.is_none() {
+                        // A notification of a later slot completes the earlier slots.
+                        let incomplete_signatures = pending_signatures.split_off(&slot);
+                        let complete_signatures =
+                            std::mem::replace(&mut pending_signatures, incomplete_signatures);
+                        let blocks = fetch_blocks(
+                            &rpc_client,
+                            complete_signatures,
+                            max_concurrent_block_fetches,
+                        )
+                        .await;
+                        if let Some(block) = blocks.last() {
+                            last_indexed_slot = block.metadata.slot;
+                            metric! {
+                                statsd_count!("logs_block_indexed", blocks.len() as i64);
+                            }
+                            yield blocks;
+                        }
+                    }
+                }
+                LogsStreamEvent::RpcBlocks(None) => {
+                    panic!("RPC stream ended unexpectedly");
+                }
+                LogsStreamEvent::RpcBlocks(Some(rpc_blocks)) => {
+                    let rpc_blocks: Vec<BlockInfo> = rpc_blocks
+                        .into_iter()
+                        .filter(|b| b.metadata.slot > last_indexed_slot)
+                        .collect();
+                    if let (Some(first_block), Some(last_block)) =
+                        (rpc_blocks.first(), rpc_blocks.last())
+                    {
+                        if first_block.metadata.parent_slot <= last_indexed_slot {
+                            last_indexed_slot = last_block.metadata.slot;
+                            metric! {
+                                statsd_count!("rpc_block_indexed", rpc_blocks.len() as i64);
+                            }
+                            yield rpc_blocks;
+                        }
+                    }
+                }
+                LogsStreamEvent::Idle => {
+                    let mut blocks = fetch_blocks(
+                        &rpc_client,
+                        std::mem::take(&mut pending_signatures),
+                        max_concurrent_block_fetches,
+                    )
+                    .await;
+                    if blocks.is_empty() {
+                        let latest_slot = with_infinite_retries("latest confirmed slot", || {
+                            rpc_client.get_slot_with_commitment(CommitmentConfig::confirmed())
+                        })
+                        .await;
+                        let heartbeat_slot = latest_slot.saturating_sub(HEARTBEAT_SLOT_MARGIN);
+                        if heartbeat_slot > last_indexed_slot {
+                            blocks.extend(
+                                fetch_block(rpc_client.clone(), heartbeat_slot, Vec::new()).await,
+                            );
+                        }
+                    }
+                    if let Some(block) = blocks.last() {
+                        last_indexed_slot = block.metadata.slot;
+                        yield blocks;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -7,15 +7,37 @@ use solana_client::nonblocking::rpc_client::RpcClient;
 
 use super::typedefs::block_info::BlockInfo;
 
+pub mod bigtable;
+pub mod fallback;
+pub mod fixtures;
 pub mod grpc;
+pub mod logs;
 pub mod poller;
+pub mod signatures;
+pub mod socket;
 
+use fallback::get_stream_with_rpc_fallback;
 use grpc::get_grpc_stream_with_rpc_fallback;
+use logs::get_logs_block_stream_with_rpc_fallback;
 use poller::get_block_poller_stream;
+use signatures::get_signature_block_stream;
+use socket::get_socket_block_stream;
 
+#[derive(Clone)]
 pub struct BlockStreamConfig {
     pub rpc_client: Arc<RpcClient>,
     pub geyser_url: Option<String>,
+    /// Only request transactions that touch the compression programs from the gRPC stream. Blocks
+    /// fetched through the RPC fallback still contain every transaction.
+    pub grpc_compression_transactions_only: bool,
+    /// Unix socket of the Photon Geyser plugin of a local validator. Ignored if `geyser_url` is set.
+    pub geyser_plugin_socket: Option<String>,
+    /// Poll the signatures of the compression programs and fetch only their transactions instead
+    /// of whole blocks. Ignored if `geyser_url` or `geyser_plugin_socket` is set.
+    pub poll_compression_signatures: bool,
+    /// WebSocket endpoint whose logsSubscribe notifications announce the compression transactions
+    /// to fetch. Ignored if `geyser_url` or `geyser_plugin_socket` is set.
+    pub logs_websocket_url: Option<String>,
     pub max_concurrent_block_fetches: usize,
     pub last_indexed_slot: u64,
 }
@@ -30,10 +52,41 @@ impl BlockStreamConfig {
                 self.rpc_client.clone(),
                 self.last_indexed_slot,
                 self.max_concurrent_block_fetches,
+                self.grpc_compression_transactions_only,
             )
         });
 
-        let poller_stream = if self.geyser_url.is_none() {
+        let socket_stream = match (&self.geyser_url, &self.geyser_plugin_socket) {
+            (None, Some(socket_path)) => Some(get_stream_with_rpc_fallback(
+                get_socket_block_stream(socket_path.clone()),
+                "Geyser plugin socket",
+                self.rpc_client.clone(),
+                self.last_indexed_slot,
+                self.max_concurrent_block_fetches,
+            )),
+            _ => None,
+        };
+
+        let polls_blocks = self.geyser_url.is_none() && self.geyser_plugin_socket.is_none();
+        let logs_stream = match (polls_blocks, &self.logs_websocket_url) {
+            (true, Some(ws_url)) => Some(get_logs_block_stream_with_rpc_fallback(
+                ws_url.clone(),
+                self.rpc_client.clone(),
+                self.last_indexed_slot,
+                self.max_concurrent_block_fetches,
+            )),
+            _ => None,
+        };
+        let polls_blocks = polls_blocks && self.logs_websocket_url.is_none();
+        let signature_stream = (polls_blocks && self.poll_compression_signatures).then(|| {
+            get_signature_block_stream(
+                self.rpc_client.clone(),
+                self.last_indexed_slot,
+                self.max_concurrent_block_fetches,
+            )
+        });
+
+        let poller_stream = if polls_blocks && !self.poll_compression_signatures {
             Some(get_block_poller_stream(
                 self.rpc_client.clone(),
                 self.last_indexed_slot,
@@ -54,6 +107,27 @@ impl BlockStreamConfig {
                 }
             }
 
+            if let Some(socket_stream) = socket_stream {
+                pin_mut!(socket_stream);
+                while let Some(blocks) = socket_stream.next().await {
+                    yield blocks;
+                }
+            }
+
+            if let Some(logs_stream) = logs_stream {
+                pin_mut!(logs_stream);
+                while let Some(blocks) = logs_stream.next().await {
+                    yield blocks;
+                }
+            }
+
+            if let Some(signature_stream) = signature_stream {
+                pin_mut!(signature_stream);
+                while let Some(blocks) = signature_stream.next().await {
+                    yield blocks;
+                }
+            }
+
             if let Some(poller_stream) = poller_stream {
                 pin_mut!(poller_stream);
                 loop {
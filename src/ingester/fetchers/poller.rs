@@ -1,6 +1,7 @@
 use std::{
     collections::BTreeMap,
     sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
 };
 
 use async_stream::stream;
@@ -12,14 +13,23 @@ use solana_client::{
 
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
+use tracing::instrument;
 
 use crate::{
-    ingester::typedefs::block_info::{parse_ui_confirmed_blocked, BlockInfo},
+    ingester::{
+        error::IngesterError,
+        typedefs::block_info::{parse_ui_confirmed_blocked, BlockInfo},
+    },
     metric,
     monitor::{start_latest_slot_updater, LATEST_SLOT},
 };
 
-const SKIPPED_BLOCK_ERRORS: [i64; 2] = [-32007, -32009];
+pub(crate) const SKIPPED_BLOCK_ERRORS: [i64; 2] = [-32007, -32009];
+// Returned for slots that the node has removed from its ledger and cannot serve from long-term
+// storage either.
+const BLOCK_CLEANED_UP_ERROR: i64 = -32001;
+pub(crate) const INITIAL_FETCH_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+pub(crate) const MAX_FETCH_RETRY_BACKOFF: Duration = Duration::from_secs(5);
 
 fn get_slot_stream(rpc_client: Arc<RpcClient>, start_slot: u64) -> impl Stream<Item = u64> {
     stream! {
@@ -48,12 +58,15 @@ pub fn get_block_poller_stream(
         };
         let slot_stream = get_slot_stream(rpc_client.clone(), start_slot);
         pin_mut!(slot_stream);
+        // Keeps up to `max_concurrent_block_fetches` requests in flight but yields the blocks in
+        // slot order, so that a slow or failing slot holds back at most that many fetched blocks
+        // instead of letting the block cache grow unbounded.
         let block_stream = slot_stream
             .map(|slot| {
                 let rpc_client = rpc_client.clone();
                 async move { fetch_block_with_infinite_retries(rpc_client.clone(), slot).await }
             })
-            .buffer_unordered(max_concurrent_block_fetches);
+            .buffered(max_concurrent_block_fetches);
         pin_mut!(block_stream);
         let mut block_cache: BTreeMap<u64, BlockInfo> = BTreeMap::new();
         while let Some(block) = block_stream.next().await {
@@ -78,12 +91,16 @@ fn pop_cached_blocks_to_index(
 ) -> (Vec<BlockInfo>, u64) {
     let mut blocks = Vec::new();
     loop {
-        let min_slot = match block_cache.keys().min() {
-            Some(&slot) => slot,
+        let min_slot = match block_cache.first_key_value() {
+            Some((&slot, _)) => slot,
             None => break,
         };
         let block: &BlockInfo = block_cache.get(&min_slot).unwrap();
-        if block.metadata.parent_slot == last_indexed_slot {
+        // A parent below the last indexed slot means that the last indexed block was orphaned.
+        // The block is emitted anyway so that the indexer can roll back to its parent.
+        let orphaned_last_indexed_block =
+            block.metadata.parent_slot < last_indexed_slot && min_slot > last_indexed_slot;
+        if block.metadata.parent_slot == last_indexed_slot || orphaned_last_indexed_block {
             last_indexed_slot = block.metadata.slot;
             blocks.push(block.clone());
             block_cache.remove(&min_slot);
@@ -100,6 +117,27 @@ pub async fn fetch_block_with_infinite_retries(
     rpc_client: Arc<RpcClient>,
     slot: u64,
 ) -> Option<BlockInfo> {
+    fetch_block_with_retries(rpc_client, slot, true)
+        .await
+        .unwrap_or_default()
+}
+
+/// Like `fetch_block_with_infinite_retries`, but fails instead of retrying if the RPC node has
+/// pruned the slot, since no amount of retries makes the block available again.
+pub async fn fetch_unpruned_block_with_infinite_retries(
+    rpc_client: Arc<RpcClient>,
+    slot: u64,
+) -> Result<Option<BlockInfo>, IngesterError> {
+    fetch_block_with_retries(rpc_client, slot, false).await
+}
+
+#[instrument(name = "fetch_block", skip(rpc_client, retry_pruned_slots))]
+async fn fetch_block_with_retries(
+    rpc_client: Arc<RpcClient>,
+    slot: u64,
+    retry_pruned_slots: bool,
+) -> Result<Option<BlockInfo>, IngesterError> {
+    let mut backoff = INITIAL_FETCH_RETRY_BACKOFF;
     loop {
         match rpc_client
             .get_block_with_config(
@@ -118,25 +156,56 @@ pub async fn fetch_block_with_infinite_retries(
                 metric! {
                     statsd_count!("rpc_block_fetched", 1);
                 }
-                return Some(parse_ui_confirmed_blocked(block, slot).unwrap());
+                match parse_ui_confirmed_blocked(block, slot) {
+                    Ok(mut block) => {
+                        block.metadata.received_at = Some(Instant::now());
+                        return Ok(Some(block));
+                    }
+                    Err(e) => {
+                        log::error!("Failed to parse block {}: {}", slot, e);
+                        metric! {
+                            statsd_count!("rpc_block_parse_failed", 1);
+                        }
+                    }
+                }
             }
             Err(e) => {
                 if let solana_client::client_error::ClientErrorKind::RpcError(
-                    RpcError::RpcResponseError { code, .. },
-                ) = e.kind
+                    RpcError::RpcResponseError { code, message, .. },
+                ) = &e.kind
                 {
-                    if SKIPPED_BLOCK_ERRORS.contains(&code) {
+                    if SKIPPED_BLOCK_ERRORS.contains(code) {
                         metric! {
                             statsd_count!("rpc_skipped_block", 1);
                         }
                         log::info!("Skipped block: {}", slot);
-                        return None;
+                        return Ok(None);
+                    }
+                    if *code == BLOCK_CLEANED_UP_ERROR {
+                        metric! {
+                            statsd_count!("rpc_pruned_block", 1);
+                        }
+                        if !retry_pruned_slots {
+                            return Err(IngesterError::SlotUnavailable {
+                                slot,
+                                msg: message.clone(),
+                            });
+                        }
+                        log::error!(
+                            "Block {} has been pruned by the RPC node, which needs to keep the \
+                            full ledger history or be backed by long-term storage: {}",
+                            slot,
+                            message
+                        );
                     }
                 }
+                log::warn!("Failed to fetch block {}: {}", slot, e);
                 metric! {
                     statsd_count!("rpc_block_fetch_failed", 1);
                 }
             }
         }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_FETCH_RETRY_BACKOFF);
     }
 }
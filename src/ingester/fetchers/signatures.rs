@@ -0,0 +1,220 @@
+use std::{collections::BTreeMap, future::Future, str::FromStr, sync::Arc, time::Duration};
+
+use async_stream::stream;
+use cadence_macros::statsd_count;
+use futures::{stream, Stream, StreamExt};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::{RpcBlockConfig, RpcTransactionConfig},
+    rpc_request::RpcError,
+    rpc_response::RpcConfirmedTransactionStatusWithSignature,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
+use tokio::time::sleep;
+
+use crate::{
+    ingester::{
+        parser::ACCOUNT_COMPRESSION_PROGRAM_ID,
+        typedefs::block_info::{
+            drop_malformed_transactions, parse_ui_confirmed_blocked, BlockInfo, TransactionInfo,
+        },
+    },
+    metric,
+};
+
+use super::poller::{INITIAL_FETCH_RETRY_BACKOFF, MAX_FETCH_RETRY_BACKOFF, SKIPPED_BLOCK_ERRORS};
+
+const SIGNATURE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Maximum number of signatures that getSignaturesForAddress returns at once.
+const SIGNATURE_PAGE_SIZE: usize = 1000;
+
+/// Streams the blocks that contain compression transactions without fetching every block. The
+/// signatures of the account compression program, which every compression transaction invokes,
+/// are polled with `getSignaturesForAddress`, and only their transactions and the headers of their
+/// blocks are fetched. Each poll also yields the header of the latest block, so that the indexed
+/// slot keeps up with the chain while there is no compression activity.
+///
+/// Like the block poller, it streams confirmed blocks. The blocks do not contain the transactions
+/// that do not use compression, and since the blocks in between are not fetched, forks are only
+/// detected if the parent of a streamed block was streamed as well.
+pub fn get_signature_block_stream(
+    rpc_client: Arc<RpcClient>,
+    mut last_indexed_slot: u64,
+    max_concurrent_transaction_fetches: usize,
+) -> impl Stream<Item = Vec<BlockInfo>> {
+    stream! {
+        let mut newest_signature = None;
+        loop {
+            // Every signature up to the latest slot is listed once the slot is fetched first.
+            let latest_slot = with_infinite_retries("latest confirmed slot", || {
+                rpc_client.get_slot_with_commitment(CommitmentConfig::confirmed())
+            })
+            .await;
+            let signatures =
+                fetch_new_signatures(&rpc_client, newest_signature, last_indexed_slot).await;
+            if let Some((_, signature)) = signatures.last() {
+                newest_signature = Some(*signature);
+            }
+            metric! {
+                statsd_count!("rpc_signature_fetched", signatures.len() as i64);
+            }
+
+            let mut signatures_by_slot: BTreeMap<u64, Vec<Signature>> = BTreeMap::new();
+            for (slot, signature) in signatures {
+                signatures_by_slot.entry(slot).or_default().push(signature);
+            }
+            if latest_slot > last_indexed_slot {
+                signatures_by_slot.entry(latest_slot).or_default();
+            }
+            let blocks =
+                fetch_blocks(&rpc_client, signatures_by_slot, max_concurrent_transaction_fetches)
+                    .await;
+            if let Some(block) = blocks.last() {
+                last_indexed_slot = block.metadata.slot;
+                yield blocks;
+            }
+            sleep(SIGNATURE_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Returns the signatures of the account compression program after `last_indexed_slot` that are
+/// newer than `until`, oldest first.
+async fn fetch_new_signatures(
+    rpc_client: &RpcClient,
+    until: Option<Signature>,
+    last_indexed_slot: u64,
+) -> Vec<(u64, Signature)> {
+    let mut signatures = Vec::new();
+    let mut before = None;
+    loop {
+        let page: Vec<RpcConfirmedTransactionStatusWithSignature> =
+            with_infinite_retries("compression signatures", || {
+                rpc_client.get_signatures_for_address_with_config(
+                    &ACCOUNT_COMPRESSION_PROGRAM_ID,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: Some(SIGNATURE_PAGE_SIZE),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+            })
+            .await;
+        let is_last_page = page.len() < SIGNATURE_PAGE_SIZE
+            || page.iter().any(|status| status.slot <= last_indexed_slot);
+        for status in page {
+            match Signature::from_str(&status.signature) {
+                Ok(signature) => {
+                    before = Some(signature);
+                    if status.slot > last_indexed_slot {
+                        signatures.push((status.slot, signature));
+                    }
+                }
+                Err(e) => log::error!("Invalid signature {}: {}", status.signature, e),
+            }
+        }
+        if is_last_page {
+            break;
+        }
+    }
+    // Signatures are listed newest first.
+    signatures.reverse();
+    signatures
+}
+
+/// Fetches the headers of the blocks in `signatures_by_slot` together with the transactions with
+/// the given signatures, in the order of their slots. Skipped slots are left out.
+pub(crate) async fn fetch_blocks(
+    rpc_client: &Arc<RpcClient>,
+    signatures_by_slot: BTreeMap<u64, Vec<Signature>>,
+    max_concurrent_transaction_fetches: usize,
+) -> Vec<BlockInfo> {
+    stream::iter(signatures_by_slot)
+        .map(|(slot, signatures)| fetch_block(rpc_client.clone(), slot, signatures))
+        .buffered(max_concurrent_transaction_fetches)
+        .filter_map(|block| async { block })
+        .collect()
+        .await
+}
+
+/// Fetches the header of the block at `slot` and the transactions with `signatures` in it.
+/// Returns `None` if the slot was skipped.
+pub(crate) async fn fetch_block(
+    rpc_client: Arc<RpcClient>,
+    slot: u64,
+    signatures: Vec<Signature>,
+) -> Option<BlockInfo> {
+    let header = with_infinite_retries(&format!("header of block {}", slot), || async {
+        match rpc_client
+            .get_block_with_config(
+                slot,
+                RpcBlockConfig {
+                    encoding: None,
+                    transaction_details: Some(TransactionDetails::None),
+                    rewards: Some(false),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+        {
+            Ok(block) => Ok(Some(block)),
+            Err(ClientError {
+                kind: ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }),
+                ..
+            }) if SKIPPED_BLOCK_ERRORS.contains(&code) => Ok(None),
+            Err(e) => Err(e),
+        }
+    })
+    .await?;
+    let mut block = match parse_ui_confirmed_blocked(header, slot) {
+        Ok(block) => block,
+        Err(e) => {
+            log::error!("Failed to parse header of block {}: {}", slot, e);
+            return None;
+        }
+    };
+
+    let mut transactions = Vec::with_capacity(signatures.len());
+    for signature in signatures {
+        let transaction = with_infinite_retries(&format!("transaction {}", signature), || {
+            rpc_client.get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+        })
+        .await;
+        transactions.push(TransactionInfo::try_from(transaction));
+    }
+    block.transactions = drop_malformed_transactions(slot, transactions);
+    Some(block)
+}
+
+pub(crate) async fn with_infinite_retries<T, F, Fut>(name: &str, mut request: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ClientError>>,
+{
+    let mut backoff = INITIAL_FETCH_RETRY_BACKOFF;
+    loop {
+        match request().await {
+            Ok(result) => return result,
+            Err(e) => {
+                log::warn!("Failed to fetch {}: {}", name, e);
+                metric! {
+                    statsd_count!("rpc_signature_mode_fetch_failed", 1);
+                }
+            }
+        }
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_FETCH_RETRY_BACKOFF);
+    }
+}
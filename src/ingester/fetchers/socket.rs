@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use async_stream::stream;
+use cadence_macros::statsd_count;
+use futures::Stream;
+use log::{error, info};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::UnixStream;
+use tokio::time::sleep;
+
+use crate::ingester::typedefs::block_info::BlockInfo;
+use crate::metric;
+
+// Upper bound for a single frame, so that a corrupted length prefix cannot make Photon allocate
+// arbitrary amounts of memory.
+const MAX_FRAME_BYTES: usize = 256 * 1024 * 1024;
+
+/// Encodes a block the way the Photon Geyser plugin sends it over its socket: a little endian u32
+/// length prefix followed by the bincode encoded block.
+pub fn encode_block_frame(block: &BlockInfo) -> Result<Vec<u8>> {
+    let block_bytes = bincode::serialize(block).context("Failed to serialize block")?;
+    let len = u32::try_from(block_bytes.len()).context("Block is too large to be framed")?;
+    Ok([len.to_le_bytes().as_slice(), &block_bytes].concat())
+}
+
+pub async fn read_block_frame(reader: &mut (impl AsyncRead + Unpin)) -> Result<BlockInfo> {
+    let len = reader.read_u32_le().await? as usize;
+    if len > MAX_FRAME_BYTES {
+        return Err(anyhow!("Frame of {} bytes exceeds the maximum size", len));
+    }
+    let mut block_bytes = vec![0; len];
+    reader.read_exact(&mut block_bytes).await?;
+    bincode::deserialize(&block_bytes).context("Failed to deserialize block")
+}
+
+/// Streams the blocks that the Photon Geyser plugin of a local validator pushes over the Unix
+/// socket at `socket_path`. Reconnects whenever the socket is closed, e.g. because the validator
+/// restarted. Blocks that are sent while Photon is disconnected are lost, so the stream has to be
+/// combined with an RPC fallback.
+pub fn get_socket_block_stream(socket_path: String) -> impl Stream<Item = BlockInfo> {
+    stream! {
+        loop {
+            let mut socket = match UnixStream::connect(&socket_path).await {
+                Ok(socket) => socket,
+                Err(e) => {
+                    error!(
+                        "Error connecting to Geyser plugin socket {}, waiting one second then retrying connect: {}",
+                        socket_path, e
+                    );
+                    metric! {
+                        statsd_count!("geyser_socket_connect_error", 1);
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            info!("Connected to Geyser plugin socket {}", socket_path);
+            loop {
+                match read_block_frame(&mut socket).await {
+                    Ok(mut block) => {
+                        block.metadata.received_at = Some(Instant::now());
+                        yield block;
+                    }
+                    Err(e) => {
+                        error!("Error reading from Geyser plugin socket, reconnecting: {}", e);
+                        metric! {
+                            statsd_count!("geyser_socket_read_error", 1);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
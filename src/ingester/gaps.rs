@@ -0,0 +1,135 @@
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use cadence_macros::{statsd_count, statsd_gauge};
+use log::{error, info, warn};
+use sea_orm::DatabaseConnection;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use tokio::task::JoinHandle;
+
+use crate::metric;
+
+use super::{
+    backfill::fetch_slots_to_backfill, fetchers::poller::fetch_block_with_infinite_retries,
+    index_block, indexer::fetch_last_indexed_slot_with_infinite_retry, IndexerContext,
+};
+
+const GAP_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Slots close to the tip may still be in flight, so they are only checked once the indexer is
+// this far past them.
+const GAP_CHECK_SLOT_MARGIN: u64 = 100;
+// Kept well below the 500,000 slot limit of getBlocks to bound the size of each response.
+const GAP_CHECK_CHUNK_SIZE: u64 = 10_000;
+
+static MISSING_SLOTS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of slots that have a confirmed block but are not indexed yet.
+pub fn missing_slot_count() -> u64 {
+    MISSING_SLOTS.load(Ordering::SeqCst)
+}
+
+/// Returns the slots in the inclusive range that have a confirmed block according to the RPC
+/// node but are missing from the database. Slots without a block were skipped by their leader
+/// and are not gaps.
+pub async fn find_missing_slots(
+    db: &DatabaseConnection,
+    rpc_client: &RpcClient,
+    start_slot: u64,
+    end_slot: u64,
+) -> anyhow::Result<Vec<u64>> {
+    let confirmed_slots = rpc_client
+        .get_blocks_with_commitment(start_slot, Some(end_slot), CommitmentConfig::confirmed())
+        .await?;
+    let unindexed_slots = fetch_slots_to_backfill(db, start_slot, end_slot)
+        .await?
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    Ok(confirmed_slots
+        .into_iter()
+        .filter(|slot| unindexed_slots.contains(slot))
+        .collect())
+}
+
+/// Periodically compares the slots indexed after `start_slot` with the blocks known to the RPC
+/// node and refetches the blocks that are missing, e.g. because an RPC node wrongly reported them
+/// as skipped.
+pub fn continously_refetch_slot_gaps(
+    indexer: IndexerContext,
+    rpc_client: Arc<RpcClient>,
+    start_slot: u64,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let db = indexer.db.clone();
+        let mut checked_up_to_slot = start_slot;
+        let mut missing_slots = BTreeSet::new();
+        loop {
+            tokio::time::sleep(GAP_CHECK_INTERVAL).await;
+            let last_indexed_slot = fetch_last_indexed_slot_with_infinite_retry(db.as_ref())
+                .await
+                .unwrap_or_default() as u64;
+            let check_up_to_slot = last_indexed_slot.saturating_sub(GAP_CHECK_SLOT_MARGIN);
+            while checked_up_to_slot < check_up_to_slot {
+                let chunk_start = checked_up_to_slot + 1;
+                let chunk_end = (checked_up_to_slot + GAP_CHECK_CHUNK_SIZE).min(check_up_to_slot);
+                match find_missing_slots(db.as_ref(), rpc_client.as_ref(), chunk_start, chunk_end)
+                    .await
+                {
+                    Ok(slots) => {
+                        if !slots.is_empty() {
+                            warn!(
+                                "Detected {} missing slots in {}-{}: {:?}",
+                                slots.len(),
+                                chunk_start,
+                                chunk_end,
+                                slots
+                            );
+                            metric! {
+                                statsd_count!("slot_gaps_detected", slots.len() as i64);
+                            }
+                        }
+                        missing_slots.extend(slots);
+                        checked_up_to_slot = chunk_end;
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to check slots {}-{} for gaps: {}",
+                            chunk_start, chunk_end, e
+                        );
+                        break;
+                    }
+                }
+            }
+
+            for slot in missing_slots.clone() {
+                let block = fetch_block_with_infinite_retries(rpc_client.clone(), slot).await;
+                let Some(block) = block else {
+                    warn!("Missing slot {} is still reported as skipped", slot);
+                    continue;
+                };
+                // Indexing is idempotent, so it does not matter if the block has been indexed by
+                // other means in the meantime.
+                match index_block(&indexer, &block).await {
+                    Ok(()) => {
+                        info!("Indexed missing slot {}", slot);
+                        metric! {
+                            statsd_count!("slot_gaps_refetched", 1);
+                        }
+                        missing_slots.remove(&slot);
+                    }
+                    Err(e) => error!("Failed to index missing slot {}: {}", slot, e),
+                }
+            }
+            MISSING_SLOTS.store(missing_slots.len() as u64, Ordering::SeqCst);
+            metric! {
+                statsd_gauge!("slot_gaps", missing_slots.len() as u64);
+            }
+        }
+    })
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cadence_macros::{statsd_count, statsd_gauge};
+use log::info;
+use tokio::sync::Notify;
+
+use crate::{common::prometheus::PIPELINE_QUEUED_BLOCKS, metric};
+
+static HIGH_WATERMARK_BLOCKS: AtomicUsize = AtomicUsize::new(200);
+static LOW_WATERMARK_BLOCKS: AtomicUsize = AtomicUsize::new(100);
+
+/// Sets the number of fetched blocks waiting to be persisted at which fetching pauses, and the
+/// number that persistence has to drain the queue to before fetching resumes. Takes effect for
+/// pipelines started afterwards.
+pub fn set_pipeline_watermarks(high_watermark_blocks: usize, low_watermark_blocks: usize) {
+    HIGH_WATERMARK_BLOCKS.store(high_watermark_blocks, Ordering::SeqCst);
+    LOW_WATERMARK_BLOCKS.store(low_watermark_blocks, Ordering::SeqCst);
+}
+
+/// Counts the blocks of an indexing pipeline that have been fetched but not persisted yet, so
+/// that a slow database pauses fetching instead of letting blocks pile up in memory. Resuming only
+/// at the low watermark keeps fetching from toggling with every persisted batch.
+pub struct Backpressure {
+    high_watermark: usize,
+    low_watermark: usize,
+    queued_blocks: AtomicUsize,
+    drained: Notify,
+}
+
+impl Backpressure {
+    pub fn new(high_watermark: usize, low_watermark: usize) -> Self {
+        Self {
+            high_watermark,
+            low_watermark: low_watermark.min(high_watermark),
+            queued_blocks: AtomicUsize::new(0),
+            drained: Notify::new(),
+        }
+    }
+
+    pub fn with_configured_watermarks() -> Self {
+        Self::new(
+            HIGH_WATERMARK_BLOCKS.load(Ordering::SeqCst),
+            LOW_WATERMARK_BLOCKS.load(Ordering::SeqCst),
+        )
+    }
+
+    pub fn queued_blocks(&self) -> usize {
+        self.queued_blocks.load(Ordering::SeqCst)
+    }
+
+    /// Returns once more blocks may be fetched, i.e. right away below the high watermark, and
+    /// otherwise once the queue has been drained to the low watermark.
+    pub async fn wait_for_capacity(&self) {
+        if self.queued_blocks() < self.high_watermark {
+            return;
+        }
+        info!(
+            "Pausing block fetching while {} blocks wait to be persisted",
+            self.queued_blocks()
+        );
+        metric! {
+            statsd_count!("pipeline_fetch_paused", 1);
+        }
+        loop {
+            // Registered before checking, so that a drain in between is not missed.
+            let drained = self.drained.notified();
+            if self.queued_blocks() <= self.low_watermark {
+                break;
+            }
+            drained.await;
+        }
+        info!("Resuming block fetching");
+    }
+
+    /// Records that `blocks` blocks have been fetched.
+    pub fn push(&self, blocks: usize) {
+        let queued_blocks = self.queued_blocks.fetch_add(blocks, Ordering::SeqCst) + blocks;
+        record_queued_blocks(queued_blocks);
+    }
+
+    /// Records that `blocks` blocks have been persisted.
+    pub fn pop(&self, blocks: usize) {
+        let queued_blocks = self.queued_blocks.fetch_sub(blocks, Ordering::SeqCst) - blocks;
+        record_queued_blocks(queued_blocks);
+        if queued_blocks <= self.low_watermark {
+            self.drained.notify_waiters();
+        }
+    }
+}
+
+fn record_queued_blocks(queued_blocks: usize) {
+    PIPELINE_QUEUED_BLOCKS.set(&[], queued_blocks as i64);
+    metric! {
+        statsd_gauge!("pipeline_queued_blocks", queued_blocks as u64);
+    }
+}
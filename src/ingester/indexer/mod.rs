@@ -1,19 +1,91 @@
-use std::{sync::Arc, thread::sleep, time::Duration};
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use async_std::stream::StreamExt;
-use futures::{pin_mut, Stream};
-use log::info;
-use sea_orm::{sea_query::Expr, DatabaseConnection, EntityTrait, FromQueryResult, QuerySelect};
+use async_stream::stream;
+use futures::{pin_mut, Stream, StreamExt};
+use log::{error, info};
+use sea_orm::{DatabaseConnection, FromQueryResult};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::sync::mpsc;
+use tracing::instrument;
 
 use crate::{
-    common::fetch_current_slot_with_infinite_retry, dao::generated::blocks,
-    ingester::index_block_batch_with_infinite_retries,
+    common::{
+        fetch_current_slot_with_infinite_retry,
+        shutdown::{is_shutdown_requested, shutdown_requested},
+    },
+    ingester::{
+        checkpoint::fetch_checkpoint, error::IngesterError,
+        index_parsed_block_batch_with_infinite_retries, parse_block_transactions,
+        parser::state_update::StateUpdate, reorg::resolve_forks, IndexerContext, ParsedBlock,
+    },
+    monitor::set_last_indexed_slot,
 };
 
 use super::typedefs::block_info::BlockInfo;
+use backpressure::Backpressure;
+
+pub mod backpressure;
+
 const POST_BACKFILL_FREQUENCY: u64 = 10;
 const PRE_BACKFILL_FREQUENCY: u64 = 10;
+// Number of block batches that fetching and parsing can each run ahead of persistence. Bounds the
+// memory held by blocks that are waiting for the database.
+const PIPELINE_QUEUE_SIZE: usize = 4;
+
+// Zero uses one parse worker per CPU.
+static PARSE_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+// The target is zero while no backfill is running.
+static BACKFILL_TARGET_SLOT: AtomicU64 = AtomicU64::new(0);
+static BACKFILLED_SLOT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackfillProgress {
+    /// Last slot that has been indexed.
+    pub current_slot: u64,
+    /// Slot that was current when the indexer started. The backfill ends once it is indexed.
+    pub target_slot: u64,
+}
+
+/// Progress of the backfill that runs when the indexer starts behind the chain, or `None` once
+/// the indexer has caught up.
+pub fn backfill_progress() -> Option<BackfillProgress> {
+    match BACKFILL_TARGET_SLOT.load(Ordering::SeqCst) {
+        0 => None,
+        target_slot => Some(BackfillProgress {
+            current_slot: BACKFILLED_SLOT.load(Ordering::SeqCst),
+            target_slot,
+        }),
+    }
+}
+
+pub fn update_backfill_progress(progress: Option<BackfillProgress>) {
+    match progress {
+        Some(progress) => {
+            BACKFILLED_SLOT.store(progress.current_slot, Ordering::SeqCst);
+            BACKFILL_TARGET_SLOT.store(progress.target_slot, Ordering::SeqCst);
+        }
+        None => BACKFILL_TARGET_SLOT.store(0, Ordering::SeqCst),
+    }
+}
+
+/// Sets the number of blocks that are parsed concurrently. 0 uses one worker per CPU.
+pub fn set_parse_workers(workers: usize) {
+    PARSE_WORKERS.store(workers, Ordering::SeqCst);
+}
+
+fn parse_workers() -> usize {
+    match PARSE_WORKERS.load(Ordering::SeqCst) {
+        0 => std::thread::available_parallelism().map_or(1, |workers| workers.get()),
+        workers => workers,
+    }
+}
 
 #[derive(FromQueryResult)]
 pub struct OptionalContextModel {
@@ -21,41 +93,36 @@ pub struct OptionalContextModel {
     pub slot: Option<i64>,
 }
 
+/// Returns the slot that the ingester resumes after, as recorded by its checkpoint.
 pub async fn fetch_last_indexed_slot_with_infinite_retry(
     db_conn: &DatabaseConnection,
 ) -> Option<i64> {
     loop {
-        let context = blocks::Entity::find()
-            .select_only()
-            .column_as(Expr::col(blocks::Column::Slot).max(), "slot")
-            .into_model::<OptionalContextModel>()
-            .one(db_conn)
-            .await;
-
-        match context {
-            Ok(context) => {
-                return context
-                    .expect("Always expected maximum query to return a result")
-                    .slot
-            }
+        match fetch_checkpoint(db_conn).await {
+            Ok(slot) => return slot.map(|slot| slot as i64),
             Err(e) => {
                 log::error!("Failed to fetch current slot from database: {}", e);
-                sleep(Duration::from_secs(5));
+                tokio::time::sleep(Duration::from_secs(5)).await;
             }
         }
     }
 }
 
+/// Indexes the blocks of `block_stream` in a pipeline of three stages connected by bounded queues.
+/// Blocks are fetched and parsed while earlier batches are being persisted, and fetching pauses
+/// once persistence falls `PIPELINE_QUEUE_SIZE` batches or the high watermark of blocks behind.
 pub async fn index_block_stream(
     block_stream: impl Stream<Item = Vec<BlockInfo>>,
-    db: Arc<DatabaseConnection>,
+    indexer: IndexerContext,
     rpc_client: Arc<RpcClient>,
     last_indexed_slot_at_start: u64,
     end_slot: Option<u64>,
 ) {
     pin_mut!(block_stream);
-    let current_slot =
-        end_slot.unwrap_or(fetch_current_slot_with_infinite_retry(&rpc_client).await);
+    let current_slot = match end_slot {
+        Some(end_slot) => end_slot,
+        None => fetch_current_slot_with_infinite_retry(&rpc_client).await,
+    };
     let number_of_blocks_to_backfill = if current_slot > last_indexed_slot_at_start {
         current_slot - last_indexed_slot_at_start
     } else {
@@ -65,34 +132,161 @@ pub async fn index_block_stream(
         "Backfilling historical blocks. Current number of blocks to backfill: {}",
         number_of_blocks_to_backfill
     );
+    if number_of_blocks_to_backfill > 0 {
+        update_backfill_progress(Some(BackfillProgress {
+            current_slot: last_indexed_slot_at_start,
+            target_slot: current_slot,
+        }));
+    }
     let mut last_indexed_slot = last_indexed_slot_at_start;
+    set_last_indexed_slot(last_indexed_slot);
 
     let mut finished_backfill_slot = None;
 
-    while let Some(blocks) = block_stream.next().await {
-        let last_slot_in_block = blocks.last().unwrap().metadata.slot;
-        index_block_batch_with_infinite_retries(db.as_ref(), blocks).await;
-
-        for slot in (last_indexed_slot + 1)..(last_slot_in_block + 1) {
-            let blocks_indexed = slot - last_indexed_slot_at_start;
-            if blocks_indexed < number_of_blocks_to_backfill {
-                if blocks_indexed % PRE_BACKFILL_FREQUENCY == 0 {
-                    info!(
-                        "Backfilled {} / {} blocks",
-                        blocks_indexed, number_of_blocks_to_backfill
-                    );
-                }
-            } else {
-                if finished_backfill_slot.is_none() {
-                    info!("Finished backfilling historical blocks!");
-                    info!("Starting to index new blocks...");
-                    finished_backfill_slot = Some(slot);
-                }
-                if slot % POST_BACKFILL_FREQUENCY == 0 {
-                    info!("Indexed slot {}", slot);
+    let (fetched_sender, mut fetched_receiver) = mpsc::channel(PIPELINE_QUEUE_SIZE);
+    let (parsed_sender, mut parsed_receiver) = mpsc::channel(PIPELINE_QUEUE_SIZE);
+    let backpressure = &Backpressure::with_configured_watermarks();
+    let fetch = async move {
+        loop {
+            let blocks = tokio::select! {
+                biased;
+                _ = shutdown_requested() => None,
+                blocks = async {
+                    backpressure.wait_for_capacity().await;
+                    block_stream.next().await
+                } => blocks,
+            };
+            let Some(blocks) = blocks else {
+                break;
+            };
+            backpressure.push(blocks.len());
+            if fetched_sender.send(blocks).await.is_err() {
+                break;
+            }
+        }
+    };
+    let parse = async move {
+        let fetched_blocks = stream! {
+            while let Some(blocks) = fetched_receiver.recv().await {
+                yield blocks;
+            }
+        };
+        let parsed_blocks = parse_block_stream(fetched_blocks, parse_workers());
+        pin_mut!(parsed_blocks);
+        while let Some(parsed_blocks) = parsed_blocks.next().await {
+            if parsed_sender.send(parsed_blocks).await.is_err() {
+                break;
+            }
+        }
+    };
+    let persist = async {
+        // On shutdown, the batch that is being persisted is committed and the batches that are
+        // still queued are dropped. They are fetched again after a restart.
+        loop {
+            let blocks = tokio::select! {
+                biased;
+                _ = shutdown_requested() => None,
+                blocks = parsed_receiver.recv() => blocks,
+            };
+            let Some(blocks) = blocks else {
+                break;
+            };
+            let blocks_len = blocks.len();
+            // Forks are resolved here, since detecting them requires the previous batches to be
+            // persisted.
+            let blocks = resolve_forks(&indexer, rpc_client.clone(), blocks).await;
+            let last_slot_in_block = blocks.last().unwrap().block.metadata.slot;
+            index_parsed_block_batch_with_infinite_retries(&indexer, blocks).await;
+            backpressure.pop(blocks_len);
+
+            for slot in (last_indexed_slot + 1)..(last_slot_in_block + 1) {
+                let blocks_indexed = slot - last_indexed_slot_at_start;
+                if blocks_indexed < number_of_blocks_to_backfill {
+                    update_backfill_progress(Some(BackfillProgress {
+                        current_slot: slot,
+                        target_slot: current_slot,
+                    }));
+                    if blocks_indexed % PRE_BACKFILL_FREQUENCY == 0 {
+                        info!(
+                            "Backfilled {} / {} blocks",
+                            blocks_indexed, number_of_blocks_to_backfill
+                        );
+                    }
+                } else {
+                    if finished_backfill_slot.is_none() {
+                        info!("Finished backfilling historical blocks!");
+                        info!("Starting to index new blocks...");
+                        update_backfill_progress(None);
+                        finished_backfill_slot = Some(slot);
+                    }
+                    if slot % POST_BACKFILL_FREQUENCY == 0 {
+                        info!("Indexed slot {}", slot);
+                    }
                 }
+                last_indexed_slot = slot;
+            }
+            set_last_indexed_slot(last_indexed_slot);
+        }
+    };
+    tokio::join!(fetch, parse, persist);
+    if is_shutdown_requested() {
+        info!("Stopped indexing at slot {}", last_indexed_slot);
+    }
+    // Otherwise the stream only ends when indexing up to a fixed end slot.
+    update_backfill_progress(None);
+}
+
+/// Parses the blocks of `block_stream` on up to `workers` blocking threads at a time and yields
+/// them in the batches and order in which they were streamed, so that they are persisted in slot
+/// order.
+pub fn parse_block_stream(
+    block_stream: impl Stream<Item = Vec<BlockInfo>>,
+    workers: usize,
+) -> impl Stream<Item = Vec<ParsedBlock>> {
+    let blocks = stream! {
+        for await batch in block_stream {
+            let batch_len = batch.len();
+            for (index, block) in batch.into_iter().enumerate() {
+                yield (block, index + 1 == batch_len);
+            }
+        }
+    };
+    let parsed_blocks = blocks
+        .map(|(block, ends_batch)| async move {
+            // Parsing is CPU bound, so it runs off the async workers. The block is shared with the
+            // worker, so that a block whose parser panicked can still go to the dead-letter queue.
+            let block = Arc::new(block);
+            let worker_block = block.clone();
+            let transaction_state_updates =
+                tokio::task::spawn_blocking(move || parse_block(&worker_block))
+                    .await
+                    .unwrap_or_else(|e| {
+                        error!("Parsing block {} panicked: {}", block.metadata.slot, e);
+                        Err(IngesterError::ParserError(format!(
+                            "Parsing block panicked: {}",
+                            e
+                        )))
+                    });
+            let parsed_block = ParsedBlock {
+                block: Arc::try_unwrap(block).unwrap_or_else(|block| (*block).clone()),
+                transaction_state_updates,
+            };
+            (parsed_block, ends_batch)
+        })
+        .buffered(workers.max(1));
+    stream! {
+        pin_mut!(parsed_blocks);
+        let mut batch = Vec::new();
+        while let Some((parsed_block, ends_batch)) = parsed_blocks.next().await {
+            batch.push(parsed_block);
+            if ends_batch {
+                yield std::mem::take(&mut batch);
             }
-            last_indexed_slot = slot;
         }
     }
 }
+
+#[instrument(skip_all, fields(slot = block.metadata.slot))]
+fn parse_block(block: &BlockInfo) -> Result<Vec<StateUpdate>, IngesterError> {
+    parse_block_transactions(block)
+}
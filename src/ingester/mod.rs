@@ -1,9 +1,10 @@
-use std::thread::sleep;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use cadence_macros::statsd_count;
+use cadence_macros::{statsd_count, statsd_histogram};
 use error::IngesterError;
 
+use itertools::Itertools;
 use parser::parse_transaction;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::ConnectionTrait;
@@ -14,42 +15,303 @@ use sea_orm::EntityTrait;
 use sea_orm::QueryTrait;
 use sea_orm::Set;
 use sea_orm::TransactionTrait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tracing::instrument;
 
+use self::events::{publish_indexed_slot, PendingAccountEvents};
 use self::parser::state_update::StateUpdate;
+use self::persist::batch_size::SqlBatchConfig;
+use self::persist::fetch_persisted_signatures;
+use self::persist::in_memory_tree::{InMemoryTrees, PendingTreeUpdates};
 use self::persist::persist_state_update;
-use self::persist::MAX_SQL_INSERTS;
+use self::persist::record_block_state_update_composition;
+use self::tree_info::fetch_tree_infos;
 use self::typedefs::block_info::BlockInfo;
 use self::typedefs::block_info::BlockMetadata;
+use crate::api::cache::{ApiCache, PendingCacheInvalidations};
+use crate::common::prometheus::BLOCKS_INDEXED;
 use crate::dao::generated::blocks;
 use crate::metric;
+pub mod backfill;
+pub mod block_cache;
+pub mod checkpoint;
+pub mod dead_letter;
 pub mod error;
+pub mod events;
 pub mod fetchers;
+pub mod gaps;
 pub mod indexer;
 pub mod parser;
 pub mod persist;
+pub mod pruner;
+pub mod reorg;
+pub mod tree_info;
 pub mod typedefs;
+pub mod webhooks;
 
-fn derive_block_state_update(block: &BlockInfo) -> Result<StateUpdate, IngesterError> {
-    let mut state_updates: Vec<StateUpdate> = Vec::new();
-    for transaction in &block.transactions {
-        state_updates.push(parse_transaction(transaction, block.metadata.slot)?);
+// Errors that are classified as retryable can still be caused by the block itself, e.g. constraint
+// violations, so failing blocks are given up on after this many attempts. Outages of the database
+// say nothing about the block and are retried until the database is back.
+const MAX_INDEX_ATTEMPTS: usize = 5;
+const MAX_INDEX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+// Events are emitted by the programs, so failing to parse them is not a transient error. Skipping
+// the transaction would diverge the indexed state from the chain, so the block fails instead and is
+// moved to the dead-letter queue.
+fn parse_block_transactions(block: &BlockInfo) -> Result<Vec<StateUpdate>, IngesterError> {
+    block
+        .transactions
+        .iter()
+        .map(|transaction| {
+            parse_transaction(transaction, block.metadata.slot).inspect_err(|e| {
+                log::error!(
+                    "Failed to parse transaction {} in block {}: {}",
+                    transaction.signature,
+                    block.metadata.slot,
+                    e
+                );
+            })
+        })
+        .collect()
+}
+
+/// A block together with the state updates of its transactions. Blocks are parsed before they are
+/// handed to the database so that parsing can run ahead of persistence.
+#[derive(Debug, Clone)]
+pub struct ParsedBlock {
+    pub block: BlockInfo,
+    /// The error of the first transaction that failed to parse, which fails indexing the block.
+    transaction_state_updates: Result<Vec<StateUpdate>, IngesterError>,
+}
+
+impl ParsedBlock {
+    pub fn parse(block: BlockInfo) -> Self {
+        let transaction_state_updates = parse_block_transactions(&block);
+        Self {
+            block,
+            transaction_state_updates,
+        }
+    }
+
+    /// State trees whose leaves the transactions of the block set. Empty if the block failed to
+    /// parse.
+    pub fn state_trees(&self) -> impl Iterator<Item = Pubkey> + '_ {
+        self.transaction_state_updates
+            .iter()
+            .flatten()
+            .flat_map(|state_update| {
+                let appended = state_update
+                    .out_accounts
+                    .iter()
+                    .map(|account| account.tree.0);
+                let nullified = state_update
+                    .leaf_nullifications
+                    .iter()
+                    .map(|leaf_nullification| leaf_nullification.tree);
+                let updated = state_update
+                    .path_updates
+                    .iter()
+                    .map(|path_update| Pubkey::new_from_array(path_update.tree));
+                appended.chain(nullified).chain(updated)
+            })
+    }
+}
+
+/// What the paths that index blocks share: the live indexer, backfills, replays, gap refetches and
+/// retries of the dead-letter queue.
+#[derive(Clone)]
+pub struct IndexerContext {
+    pub db: Arc<DatabaseConnection>,
+    /// Node that the parameters of new trees are fetched from. Without it, trees whose parameters
+    /// are not stored yet are assumed to have the height of the default state trees.
+    pub rpc_client: Option<Arc<RpcClient>>,
+    pub sql_batch_config: SqlBatchConfig,
+    /// Cache of the API that is served from the same process. Its entries are invalidated once
+    /// the blocks that write them are committed.
+    pub api_cache: ApiCache,
+    /// In-memory state trees of the API that is served from the same process. Their nodes are
+    /// updated once the blocks that write them are committed.
+    pub in_memory_trees: InMemoryTrees,
+}
+
+impl IndexerContext {
+    pub fn new(db: Arc<DatabaseConnection>) -> Self {
+        let sql_batch_config = SqlBatchConfig::new(db.get_database_backend());
+        Self {
+            db,
+            rpc_client: None,
+            sql_batch_config,
+            api_cache: ApiCache::default(),
+            in_memory_trees: InMemoryTrees::default(),
+        }
+    }
+
+    pub fn with_rpc_client(mut self, rpc_client: Arc<RpcClient>) -> Self {
+        self.rpc_client = Some(rpc_client);
+        self
+    }
+
+    /// Replaces the limits that hold for every version of the database, see
+    /// [`SqlBatchConfig::detect`].
+    pub fn with_sql_batch_config(mut self, sql_batch_config: SqlBatchConfig) -> Self {
+        self.sql_batch_config = sql_batch_config;
+        self
+    }
+
+    pub fn with_api_cache(mut self, api_cache: ApiCache) -> Self {
+        self.api_cache = api_cache;
+        self
+    }
+
+    pub fn with_in_memory_trees(mut self, in_memory_trees: InMemoryTrees) -> Self {
+        self.in_memory_trees = in_memory_trees;
+        self
+    }
+
+    // The heights of new trees are needed to persist their leaves, so they are fetched before the
+    // blocks are. Indexing goes on with the default height if the node cannot be reached.
+    async fn fetch_tree_infos(&self, blocks: &[ParsedBlock]) {
+        let Some(rpc_client) = &self.rpc_client else {
+            return;
+        };
+        let trees = blocks.iter().flat_map(ParsedBlock::state_trees);
+        if let Err(e) = fetch_tree_infos(self.db.as_ref(), rpc_client.as_ref(), trees).await {
+            log::error!("Failed to store tree parameters: {}", e);
+        }
+    }
+}
+
+fn compression_signature(transaction_state_update: &StateUpdate) -> Option<Signature> {
+    transaction_state_update
+        .transactions
+        .iter()
+        .find(|transaction| transaction.uses_compression)
+        .map(|transaction| transaction.signature)
+}
+
+// Derives the state update of each block. Compression transactions that are already persisted, or
+// that appear twice in the batch, are left out so that replaying blocks after a crash or in
+// overlapping backfills does not apply their state twice.
+async fn derive_block_state_updates(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    blocks: &[ParsedBlock],
+) -> Result<Vec<StateUpdate>, IngesterError> {
+    // The parsed blocks are kept intact, since a failed batch is indexed again.
+    let transaction_state_updates = blocks
+        .iter()
+        .map(|block| block.transaction_state_updates.clone())
+        .collect::<Result<Vec<_>, _>>()?;
+    let signatures = transaction_state_updates
+        .iter()
+        .flatten()
+        .filter_map(compression_signature)
+        .collect_vec();
+    let mut seen_signatures = fetch_persisted_signatures(txn, batch_config, &signatures).await?;
+
+    let mut skipped_transactions = 0;
+    let mut block_state_updates = Vec::new();
+    for state_updates in transaction_state_updates {
+        let mut unseen_state_updates = Vec::new();
+        for state_update in state_updates {
+            match compression_signature(&state_update) {
+                Some(signature) if !seen_signatures.insert(signature) => {
+                    log::debug!("Skipping already indexed transaction {}", signature);
+                    skipped_transactions += 1;
+                }
+                _ => unseen_state_updates.push(state_update),
+            }
+        }
+        let state_update = StateUpdate::merge_updates(unseen_state_updates);
+        record_block_state_update_composition(&state_update);
+        block_state_updates.push(state_update);
+    }
+    if skipped_transactions > 0 {
+        metric! {
+            statsd_count!("already_indexed_transaction_skipped", skipped_transactions);
+        }
     }
-    Ok(StateUpdate::merge_updates(state_updates))
+    Ok(block_state_updates)
 }
 
-pub async fn index_block(db: &DatabaseConnection, block: &BlockInfo) -> Result<(), IngesterError> {
-    let txn = db.begin().await?;
-    index_block_metadatas(&txn, vec![&block.metadata]).await?;
-    persist_state_update(&txn, derive_block_state_update(block)?).await?;
+pub async fn index_block(indexer: &IndexerContext, block: &BlockInfo) -> Result<(), IngesterError> {
+    block_cache::cache_blocks([block]).await;
+    let parsed_block = [ParsedBlock::parse(block.clone())];
+    indexer.fetch_tree_infos(&parsed_block).await;
+    let txn = indexer.db.begin().await?;
+    let committed_batch = persist_parsed_block_batch(indexer, &txn, &parsed_block).await?;
     txn.commit().await?;
+    BLOCKS_INDEXED.inc_by(&[], 1);
+    committed_batch.apply(indexer).await;
     Ok(())
 }
 
+/// What is applied once the transaction that persisted a batch of blocks is committed, since the
+/// transaction might still be rolled back and retried before.
+struct CommittedBlockBatch {
+    slots: Vec<u64>,
+    account_events: Option<PendingAccountEvents>,
+    cache_invalidations: Option<PendingCacheInvalidations>,
+    tree_updates: Option<PendingTreeUpdates>,
+}
+
+impl CommittedBlockBatch {
+    async fn apply(self, indexer: &IndexerContext) {
+        let db = indexer.db.as_ref();
+        if let Some(tree_updates) = self.tree_updates {
+            tree_updates
+                .apply(db, indexer.sql_batch_config, &indexer.in_memory_trees)
+                .await;
+        }
+        if let Some(cache_invalidations) = self.cache_invalidations {
+            cache_invalidations.apply(&indexer.api_cache);
+        }
+        if let Some(account_events) = self.account_events {
+            account_events.publish(db, indexer.sql_batch_config).await;
+        }
+        for slot in self.slots {
+            publish_indexed_slot(slot);
+        }
+    }
+}
+
+// Persists the blocks within `txn`. The tree parameters of the blocks have to be fetched before.
+async fn persist_parsed_block_batch(
+    indexer: &IndexerContext,
+    txn: &DatabaseTransaction,
+    block_batch: &[ParsedBlock],
+) -> Result<CommittedBlockBatch, IngesterError> {
+    let batch_config = indexer.sql_batch_config;
+    let block_metadatas: Vec<&BlockMetadata> =
+        block_batch.iter().map(|b| &b.block.metadata).collect();
+    index_block_metadatas(txn, batch_config, block_metadatas.clone()).await?;
+    let state_update = StateUpdate::merge_updates(
+        derive_block_state_updates(txn, batch_config, block_batch).await?,
+    );
+    let committed_batch = CommittedBlockBatch {
+        slots: block_metadatas.iter().map(|block| block.slot).collect(),
+        account_events: PendingAccountEvents::collect(&state_update, &block_metadatas),
+        cache_invalidations: PendingCacheInvalidations::collect(
+            txn,
+            batch_config,
+            &indexer.api_cache,
+            &state_update,
+        )
+        .await?,
+        tree_updates: PendingTreeUpdates::collect(&indexer.in_memory_trees, &state_update),
+    };
+    persist_state_update(txn, batch_config, state_update).await?;
+    Ok(committed_batch)
+}
+
 async fn index_block_metadatas(
     tx: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     blocks: Vec<&BlockMetadata>,
 ) -> Result<(), IngesterError> {
-    for block_chunk in blocks.chunks(MAX_SQL_INSERTS) {
+    for block_chunk in blocks.chunks(batch_config.insert_batch_size::<blocks::Entity>()) {
         let block_models: Vec<blocks::ActiveModel> = block_chunk
             .iter()
             .map(|block| {
@@ -75,47 +337,160 @@ async fn index_block_metadatas(
             .build(tx.get_database_backend());
         tx.execute(query).await?;
     }
+    if let Some(slot) = blocks.iter().map(|block| block.slot).max() {
+        checkpoint::advance_checkpoint(tx, slot).await?;
+    }
 
     Ok(())
 }
 
 pub async fn index_block_batch(
-    db: &DatabaseConnection,
-    block_batch: &Vec<BlockInfo>,
+    indexer: &IndexerContext,
+    block_batch: &[BlockInfo],
+) -> Result<(), IngesterError> {
+    let parsed_blocks = block_batch
+        .iter()
+        .cloned()
+        .map(ParsedBlock::parse)
+        .collect_vec();
+    index_parsed_block_batch(indexer, &parsed_blocks).await
+}
+
+#[instrument(
+    name = "persist_block_batch",
+    skip_all,
+    fields(
+        start_slot = block_batch.first().map(|b| b.block.metadata.slot),
+        end_slot = block_batch.last().map(|b| b.block.metadata.slot),
+    )
+)]
+async fn index_parsed_block_batch(
+    indexer: &IndexerContext,
+    block_batch: &[ParsedBlock],
 ) -> Result<(), IngesterError> {
     let blocks_len = block_batch.len();
-    let tx = db.begin().await?;
-    let block_metadatas: Vec<&BlockMetadata> = block_batch.iter().map(|b| &b.metadata).collect();
-    index_block_metadatas(&tx, block_metadatas).await?;
-    let mut state_updates = Vec::new();
-    for block in block_batch {
-        state_updates.push(derive_block_state_update(block)?);
-    }
-    persist::persist_state_update(&tx, StateUpdate::merge_updates(state_updates)).await?;
+    indexer.fetch_tree_infos(block_batch).await;
+    let tx = indexer.db.begin().await?;
+    let committed_batch = persist_parsed_block_batch(indexer, &tx, block_batch).await?;
     metric! {
         statsd_count!("blocks_indexed", blocks_len as i64);
     }
     tx.commit().await?;
+    BLOCKS_INDEXED.inc_by(&[], blocks_len as u64);
+    record_indexing_latency(block_batch);
+    committed_batch.apply(indexer).await;
     Ok(())
 }
 
+// Reports how long it took for each block to be committed to the DB, measured both from the
+// block's on-chain timestamp and from when the block source handed it to us.
+fn record_indexing_latency(block_batch: &[ParsedBlock]) {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    for ParsedBlock { block, .. } in block_batch {
+        let block_time_latency_ms = (now_ms - block.metadata.block_time * 1000).max(0) as u64;
+        metric! {
+            statsd_histogram!("block_time_to_commit_ms", block_time_latency_ms);
+        }
+        if let Some(received_at) = block.metadata.received_at {
+            metric! {
+                statsd_histogram!(
+                    "block_receipt_to_commit_ms",
+                    received_at.elapsed().as_millis() as u64
+                );
+            }
+        }
+    }
+}
+
 pub async fn index_block_batch_with_infinite_retries(
-    db: &DatabaseConnection,
+    indexer: &IndexerContext,
     block_batch: Vec<BlockInfo>,
 ) {
+    let parsed_blocks = block_batch.into_iter().map(ParsedBlock::parse).collect();
+    index_parsed_block_batch_with_infinite_retries(indexer, parsed_blocks).await
+}
+
+/// Like [`index_block_batch_with_infinite_retries`] for blocks that have already been parsed.
+pub async fn index_parsed_block_batch_with_infinite_retries(
+    indexer: &IndexerContext,
+    block_batch: Vec<ParsedBlock>,
+) {
+    block_cache::cache_blocks(block_batch.iter().map(|parsed_block| &parsed_block.block)).await;
+    if block_batch.len() > 1
+        && index_block_batch_with_retries(indexer, &block_batch)
+            .await
+            .is_ok()
+    {
+        return;
+    }
+    // Retrying the batch would fail again. Indexing the blocks one by one commits the blocks
+    // before the failing one, and the failing block is moved to the dead-letter queue so that it
+    // does not stall indexing.
+    for block in block_batch {
+        loop {
+            let e =
+                match index_block_batch_with_retries(indexer, std::slice::from_ref(&block)).await {
+                    Ok(()) => break,
+                    Err(e) => e,
+                };
+            log::error!(
+                "Moving block {} to the dead-letter queue after {} error: {}",
+                block.block.metadata.slot,
+                e.kind(),
+                e
+            );
+            match dead_letter::dead_letter_block(indexer, &block.block, &e).await {
+                Ok(()) => break,
+                Err(e) => {
+                    log::error!(
+                        "Failed to move block {} to the dead-letter queue: {}",
+                        block.block.metadata.slot,
+                        e
+                    );
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+}
+
+// Retries with exponential backoff until the batch is indexed, fails with a non-retryable error or
+// has failed `MAX_INDEX_ATTEMPTS` times, and returns the last error in the latter cases. Errors
+// while the database is unavailable do not count as attempts.
+async fn index_block_batch_with_retries(
+    indexer: &IndexerContext,
+    block_batch: &[ParsedBlock],
+) -> Result<(), IngesterError> {
+    let mut attempts = 0;
+    let mut backoff = Duration::from_secs(1);
     loop {
-        match index_block_batch(db, &block_batch).await {
-            Ok(()) => return,
+        match index_parsed_block_batch(indexer, block_batch).await {
+            Ok(()) => return Ok(()),
             Err(e) => {
-                let start_block = block_batch.first().unwrap().metadata.slot;
-                let end_block = block_batch.last().unwrap().metadata.slot;
+                if !matches!(e, IngesterError::DatabaseUnavailable(_)) {
+                    attempts += 1;
+                }
+                let start_block = block_batch.first().unwrap().block.metadata.slot;
+                let end_block = block_batch.last().unwrap().block.metadata.slot;
                 log::error!(
-                    "Failed to index block batch {}-{}. Got error {}",
+                    "Failed to index block batch {}-{}. Got {} error (retryable: {}): {}",
                     start_block,
                     end_block,
+                    e.kind(),
+                    e.retryable(),
                     e
                 );
-                sleep(Duration::from_secs(1));
+                metric! {
+                    statsd_count!("index_block_batch_error", 1, "kind" => e.kind());
+                }
+                if !e.retryable() || attempts >= MAX_INDEX_ATTEMPTS {
+                    return Err(e);
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_INDEX_RETRY_BACKOFF);
             }
         }
     }
@@ -54,6 +54,7 @@ pub enum MerkleTreeEvent {
     V1(ChangelogEvent),
     V2(NullifierEvent),
     V3(IndexedMerkleTreeEvent),
+    V4(BatchedMerkleTreeEvent),
 }
 
 /// Node of the Merkle path with an index representing the position in a
@@ -119,3 +120,13 @@ pub struct IndexedMerkleTreeEvent {
     /// seq + 1 corresponds to leaves[1].
     pub seq: u64,
 }
+
+/// Changes to several state trees that are emitted as a single event, e.g. by instructions that
+/// append to and nullify leaves in multiple trees at once.
+#[derive(AnchorDeserialize, AnchorSerialize, Debug)]
+pub struct BatchedMerkleTreeEvent {
+    /// Merkle paths of the changed leaves, one event per tree.
+    pub changelogs: Vec<ChangelogEvent>,
+    /// Nullified leaves, one event per tree.
+    pub nullifications: Vec<NullifierEvent>,
+}
@@ -1,15 +1,18 @@
 use borsh::BorshDeserialize;
 use byteorder::{ByteOrder, LittleEndian};
-use indexer_events::{IndexedMerkleTreeEvent, MerkleTreeEvent, NullifierEvent};
+use indexer_events::{
+    BatchedMerkleTreeEvent, ChangelogEvent, IndexedMerkleTreeEvent, MerkleTreeEvent, NullifierEvent,
+};
 use log::debug;
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
-use state_update::{IndexedTreeLeafUpdate, LeafNullification};
+use state_update::{IndexedTreeLeafUpdate, LeafNullification, PathNode, PathUpdate};
 
 use crate::common::typedefs::{
     account::{Account, AccountData},
     bs64_string::Base64String,
     hash::Hash,
     serializable_pubkey::SerializablePubkey,
+    u64_string::U64String,
     unsigned_integer::UnsignedInteger,
 };
 
@@ -27,13 +30,15 @@ use solana_program::pubkey;
 
 pub const ACCOUNT_COMPRESSION_PROGRAM_ID: Pubkey =
     pubkey!("compr6CUsB5m2jS4Y3831ztGSTnDpnKJTKS95d64XVq");
-const SYSTEM_PROGRAM: Pubkey = pubkey!("11111111111111111111111111111111");
-const NOOP_PROGRAM_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+pub(crate) const SYSTEM_PROGRAM: Pubkey = pubkey!("11111111111111111111111111111111");
+pub(crate) const NOOP_PROGRAM_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
 const VOTE_PROGRAM_ID: Pubkey = pubkey!("Vote111111111111111111111111111111111111111");
 
 pub fn parse_transaction(tx: &TransactionInfo, slot: u64) -> Result<StateUpdate, IngesterError> {
     let mut state_updates = Vec::new();
     let mut is_compression_transaction = false;
+    let mut compressed_lamports = 0;
+    let mut decompressed_lamports = 0;
 
     let mut logged_transaction = false;
 
@@ -72,6 +77,15 @@ pub fn parse_transaction(tx: &TransactionInfo, slot: u64) -> Result<StateUpdate,
                                 e
                             ))
                         })?;
+                        // Compressing SOL moves lamports from the fee payer into the output
+                        // accounts and decompressing moves them out to a recipient.
+                        if let Some(lamports) = public_transaction_event.compression_lamports {
+                            if public_transaction_event.is_compress {
+                                compressed_lamports += lamports;
+                            } else {
+                                decompressed_lamports += lamports;
+                            }
+                        }
                         let state_update = parse_public_transaction_event(
                             tx.signature,
                             slot,
@@ -92,22 +106,28 @@ pub fn parse_transaction(tx: &TransactionInfo, slot: u64) -> Result<StateUpdate,
                             MerkleTreeEvent::deserialize(&mut next_instruction.data.as_slice())
                                 .map_err(|e| {
                                     IngesterError::ParserError(format!(
-                                        "Failed to deserialize NullifierEvent: {}",
+                                        "Failed to deserialize MerkleTreeEvent: {}",
                                         e
                                     ))
                                 })?;
 
+                        // Older event versions keep being parsed, so that transactions emitted
+                        // before and after an upgrade of the program can be indexed alike.
                         let state_update = match merkle_tree_event {
+                            MerkleTreeEvent::V1(changelog_event) => {
+                                parse_changelog_event(tx.signature, changelog_event)
+                            }
                             MerkleTreeEvent::V2(nullifier_event) => {
                                 parse_nullifier_event(tx.signature, nullifier_event)?
                             }
                             MerkleTreeEvent::V3(indexed_merkle_tree_event) => {
                                 parse_indexed_merkle_tree_update(indexed_merkle_tree_event)?
                             }
-                            _ => {
-                                return Err(IngesterError::ParserError(
-                                    "Expected nullifier event or merkle tree update".to_string(),
-                                ))
+                            MerkleTreeEvent::V4(batched_merkle_tree_event) => {
+                                parse_batched_merkle_tree_event(
+                                    tx.signature,
+                                    batched_merkle_tree_event,
+                                )?
                             }
                         };
                         state_updates.push(state_update);
@@ -124,6 +144,8 @@ pub fn parse_transaction(tx: &TransactionInfo, slot: u64) -> Result<StateUpdate,
             slot,
             uses_compression: is_compression_transaction,
             error: tx.error.clone(),
+            compressed_lamports,
+            decompressed_lamports,
         });
     }
 
@@ -159,7 +181,7 @@ fn parse_account_data(
 
     Account {
         owner: owner.into(),
-        lamports: UnsignedInteger(lamports),
+        lamports: U64String(lamports),
         address: address.map(SerializablePubkey::from),
         data,
         hash: hash.into(),
@@ -206,6 +228,48 @@ fn parse_indexed_merkle_tree_update(
     Ok(state_update)
 }
 
+fn parse_changelog_event(tx: Signature, changelog_event: ChangelogEvent) -> StateUpdate {
+    let ChangelogEvent { id, paths, seq, .. } = changelog_event;
+    let mut state_update = StateUpdate::new();
+
+    for (i, path) in paths.into_iter().enumerate() {
+        state_update.path_updates.push(PathUpdate {
+            tree: id,
+            path: path
+                .into_iter()
+                .map(|node| PathNode {
+                    node: node.node,
+                    index: node.index,
+                })
+                .collect(),
+            seq: seq + i as u64,
+            signature: tx,
+        });
+    }
+
+    state_update
+}
+
+fn parse_batched_merkle_tree_event(
+    tx: Signature,
+    batched_merkle_tree_event: BatchedMerkleTreeEvent,
+) -> Result<StateUpdate, IngesterError> {
+    let BatchedMerkleTreeEvent {
+        changelogs,
+        nullifications,
+    } = batched_merkle_tree_event;
+
+    let mut state_updates = changelogs
+        .into_iter()
+        .map(|changelog_event| parse_changelog_event(tx, changelog_event))
+        .collect::<Vec<_>>();
+    for nullifier_event in nullifications {
+        state_updates.push(parse_nullifier_event(tx, nullifier_event)?);
+    }
+
+    Ok(StateUpdate::merge_updates(state_updates))
+}
+
 fn parse_nullifier_event(
     tx: Signature,
     nullifier_event: NullifierEvent,
@@ -265,7 +329,15 @@ fn parse_public_transaction_event(
         .zip(output_compressed_account_hashes)
         .zip(transaction_event.output_leaf_indices.iter())
     {
-        let tree = pubkey_array[out_account.merkle_tree_index as usize];
+        let tree = *pubkey_array
+            .get(out_account.merkle_tree_index as usize)
+            .ok_or_else(|| IngesterError::MalformedTransaction {
+                signature: tx.to_string(),
+                msg: format!(
+                    "Merkle tree index {} is out of bounds",
+                    out_account.merkle_tree_index
+                ),
+            })?;
         let seq = tree_to_seq_number
             .get_mut(&tree)
             .ok_or_else(|| IngesterError::ParserError("Missing sequence number".to_string()))?;
@@ -291,6 +363,8 @@ fn parse_public_transaction_event(
                 .map(|hash| AccountTransaction {
                     hash: hash.clone(),
                     signature: tx,
+                    slot,
+                    spent: true,
                 }),
         );
 
@@ -303,6 +377,8 @@ fn parse_public_transaction_event(
                 .map(|a| AccountTransaction {
                     hash: a.hash.clone(),
                     signature: tx,
+                    slot,
+                    spent: false,
                 }),
         );
 
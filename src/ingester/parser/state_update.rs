@@ -23,14 +23,16 @@ pub struct EnrichedPathNode {
     pub tree: [u8; 32],
     pub seq: u64,
     pub level: usize,
-    pub tree_depth: usize,
     pub leaf_index: Option<u32>,
 }
 
+/// Merkle path of a leaf that was set by a changelog event, ordered from the leaf to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PathUpdate {
     pub tree: [u8; 32],
     pub path: Vec<PathNode>,
     pub seq: u64,
+    pub signature: Signature,
 }
 
 #[derive(Hash, Eq, Clone, PartialEq, Debug)]
@@ -39,12 +41,19 @@ pub struct Transaction {
     pub slot: u64,
     pub uses_compression: bool,
     pub error: Option<String>,
+    /// Lamports moved from uncompressed accounts into compressed accounts.
+    pub compressed_lamports: u64,
+    /// Lamports moved from compressed accounts into uncompressed accounts.
+    pub decompressed_lamports: u64,
 }
 
 #[derive(Hash, PartialEq, Eq, Debug, Clone)]
 pub struct AccountTransaction {
     pub hash: Hash,
     pub signature: Signature,
+    pub slot: u64,
+    /// Whether the transaction spent the account rather than created it.
+    pub spent: bool,
 }
 
 #[derive(Hash, PartialEq, Eq, Debug, Clone)]
@@ -71,6 +80,9 @@ pub struct StateUpdate {
     pub account_transactions: HashSet<AccountTransaction>,
     pub transactions: HashSet<Transaction>,
     pub leaf_nullifications: HashSet<LeafNullification>,
+    /// Leaves that changelog events set. Transaction events and changelog events can describe the
+    /// same leaves, in which case they are persisted once.
+    pub path_updates: Vec<PathUpdate>,
     pub indexed_merkle_tree_updates: HashMap<(Pubkey, u64), IndexedTreeLeafUpdate>,
 }
 
@@ -91,6 +103,7 @@ impl StateUpdate {
             merged
                 .leaf_nullifications
                 .extend(update.leaf_nullifications);
+            merged.path_updates.extend(update.path_updates);
 
             for (key, value) in update.indexed_merkle_tree_updates {
                 // Insert only if the seq is higher.
@@ -0,0 +1,103 @@
+use log::info;
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, EntityTrait, Iterable, Statement,
+};
+
+// Postgres encodes the number of parameters of a statement in 16 bits.
+const POSTGRES_MAX_PARAMETERS: usize = 65_535;
+// SQLITE_MAX_VARIABLE_NUMBER defaults to 999 before SQLite 3.32.0 and to 32,766 since.
+const LEGACY_SQLITE_MAX_PARAMETERS: usize = 999;
+const SQLITE_MAX_PARAMETERS: usize = 32_766;
+// Parameters left for the rest of a statement, e.g. the slot that an `IN` list is combined with.
+const RESERVED_PARAMETERS: usize = 16;
+
+/// Sizes the chunks that rows are split into so that every statement stays within the parameter
+/// limit of the database, unless a fixed number of rows per statement is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SqlBatchConfig {
+    backend: DatabaseBackend,
+    parameter_limit: usize,
+    max_batch_size: Option<usize>,
+}
+
+impl SqlBatchConfig {
+    /// Limits of `backend` that hold for every version of it. SQLite gets the lower limit of the
+    /// versions before 3.32.0, see [`SqlBatchConfig::detect`].
+    pub fn new(backend: DatabaseBackend) -> Self {
+        let parameter_limit = match backend {
+            DatabaseBackend::Postgres => POSTGRES_MAX_PARAMETERS,
+            _ => LEGACY_SQLITE_MAX_PARAMETERS,
+        };
+        Self {
+            backend,
+            parameter_limit,
+            max_batch_size: None,
+        }
+    }
+
+    /// Looks up the parameter limit of the database behind `conn`. Only the SQLite limit depends
+    /// on the version of the library that Photon is linked against.
+    pub async fn detect(conn: &DatabaseConnection) -> Result<Self, DbErr> {
+        let mut config = Self::new(conn.get_database_backend());
+        if config.backend != DatabaseBackend::Sqlite {
+            return Ok(config);
+        }
+        let row = conn
+            .query_one(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT sqlite_version() AS version".to_string(),
+            ))
+            .await?
+            .ok_or_else(|| DbErr::Custom("Failed to query the SQLite version".to_string()))?;
+        let version: String = row.try_get("", "version")?;
+        config.parameter_limit = sqlite_parameter_limit(&version);
+        info!(
+            "SQLite {} allows {} parameters per statement",
+            version, config.parameter_limit
+        );
+        Ok(config)
+    }
+
+    /// Overrides the number of rows per statement that [`SqlBatchConfig::batch_size`] computes
+    /// from the parameter limit. Statements fail if the override exceeds the parameter limit.
+    pub fn with_max_batch_size(mut self, max_batch_size: Option<usize>) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    pub fn backend(&self) -> DatabaseBackend {
+        self.backend
+    }
+
+    /// Maximum number of parameters of a single statement.
+    pub fn parameter_limit(&self) -> usize {
+        self.parameter_limit
+    }
+
+    /// Number of rows per statement set with [`SqlBatchConfig::with_max_batch_size`], if any.
+    pub fn max_batch_size(&self) -> Option<usize> {
+        self.max_batch_size
+    }
+
+    /// Number of rows that fit into a single statement that binds `parameters_per_row`
+    /// parameters for every row, e.g. one for every hash in an `IN` list.
+    pub fn batch_size(&self, parameters_per_row: usize) -> usize {
+        self.max_batch_size.unwrap_or_else(|| {
+            let available_parameters = self.parameter_limit - RESERVED_PARAMETERS;
+            (available_parameters / parameters_per_row.max(1)).max(1)
+        })
+    }
+
+    /// Number of rows of `E` that fit into a single `insert_many`.
+    pub fn insert_batch_size<E: EntityTrait>(&self) -> usize {
+        self.batch_size(E::Column::iter().count())
+    }
+}
+
+fn sqlite_parameter_limit(version: &str) -> usize {
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(major), Some(minor)) if (major, minor) >= (3, 32) => SQLITE_MAX_PARAMETERS,
+        _ => LEGACY_SQLITE_MAX_PARAMETERS,
+    }
+}
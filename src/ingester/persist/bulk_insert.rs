@@ -0,0 +1,87 @@
+use sea_orm::{
+    sea_query::{Alias, Expr, OnConflict, Query, Value},
+    ActiveModelTrait, DatabaseBackend, EntityName, EntityTrait, IdenStatic, Iterable, QueryTrait,
+    Statement,
+};
+use serde_json::{Map, Value as Json};
+
+use super::batch_size::SqlBatchConfig;
+
+/// Number of rows per insert statement on Postgres, where the rows of a statement are bound as a
+/// single parameter. Bounds the size of the statements rather than a protocol limit.
+pub const MAX_BULK_INSERTS: usize = 10_000;
+
+/// Number of rows of `E` to insert per statement built by [`build_bulk_insert`].
+pub fn bulk_insert_chunk_size<E: EntityTrait>(batch_config: SqlBatchConfig) -> usize {
+    match batch_config.backend() {
+        DatabaseBackend::Postgres => batch_config.max_batch_size().unwrap_or(MAX_BULK_INSERTS),
+        _ => batch_config.insert_batch_size::<E>(),
+    }
+}
+
+/// Builds an insert of `models` with the given conflict clause. `insert_many` binds every value as
+/// a separate parameter, which caps a statement at a few hundred rows. On Postgres the rows are
+/// instead bound as one JSON array and expanded with `jsonb_populate_recordset`, so a statement
+/// can carry any number of rows. SQLite falls back to `insert_many`.
+pub fn build_bulk_insert<A>(
+    backend: DatabaseBackend,
+    models: Vec<A>,
+    on_conflict: OnConflict,
+) -> Statement
+where
+    A: ActiveModelTrait,
+{
+    if backend != DatabaseBackend::Postgres {
+        return A::Entity::insert_many(models)
+            .on_conflict(on_conflict)
+            .build(backend);
+    }
+
+    // Like `insert_many`, only columns that are set in some model are inserted.
+    let columns = <A::Entity as EntityTrait>::Column::iter()
+        .filter(|column| models.iter().any(|model| model.get(*column).is_set()))
+        .collect::<Vec<_>>();
+    let rows = models
+        .iter()
+        .map(|model| {
+            let row = columns
+                .iter()
+                .filter_map(|column| {
+                    let value = model.get(*column).into_value()?;
+                    Some((column.as_str().to_string(), value_to_json(value)))
+                })
+                .collect::<Map<_, _>>();
+            Json::Object(row)
+        })
+        .collect::<Vec<_>>();
+
+    let table_name = A::Entity::default().table_name().to_string();
+    let records = Query::select()
+        .expr(Expr::cust_with_values(
+            &format!("(jsonb_populate_recordset(NULL::\"{}\", $1)).*", table_name),
+            [Value::from(Json::Array(rows))],
+        ))
+        .to_owned();
+    let select = Query::select()
+        .columns(columns.clone())
+        .from_subquery(records, Alias::new("records"))
+        .to_owned();
+    let insert = Query::insert()
+        .into_table(A::Entity::default())
+        .columns(columns)
+        .select_from(select)
+        .expect("Selected columns match the inserted columns")
+        .on_conflict(on_conflict)
+        .to_owned();
+    backend.build(&insert)
+}
+
+// Values are converted to the JSON that Postgres parses into the column type, which for byte
+// arrays is the hex format and for decimals a string, since JSON numbers would lose precision.
+fn value_to_json(value: Value) -> Json {
+    match value {
+        Value::Bytes(Some(bytes)) => Json::String(format!("\\x{}", hex::encode(*bytes))),
+        Value::Decimal(Some(decimal)) => Json::String(decimal.to_string()),
+        value => sea_orm::sea_query::value::sea_value_to_json_value(&value),
+    }
+}
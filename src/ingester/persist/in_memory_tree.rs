@@ -0,0 +1,345 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use cadence_macros::statsd_count;
+use log::warn;
+use lru::LruCache;
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    dao::generated::state_trees,
+    ingester::{parser::state_update::StateUpdate, tree_info::tree_height},
+    metric,
+};
+
+use super::{
+    batch_size::SqlBatchConfig,
+    get_node_direct_ancestors,
+    persisted_state_tree::{find_nodes, leaf_index_to_node_index},
+};
+
+// Nodes whose index is below this bound, i.e. the top 12 levels of a tree, stay in memory once
+// their tree has been loaded. These are the nodes that the proofs of all leaves share.
+const RESIDENT_NODE_INDEX_BOUND: i64 = 1 << 12;
+// Lookups retry this often when the indexer updates a tree while its nodes are loaded.
+const MAX_LOAD_ATTEMPTS: usize = 2;
+
+type NodeLocation = (Vec<u8>, i64);
+
+#[derive(Default)]
+struct TreeNodes {
+    // Bumped whenever the indexer updates the tree, so that loads which started before an update
+    // do not store what they read.
+    generation: u64,
+    // Nodes of the top levels, loaded all at once. Nodes that are missing are empty.
+    resident_nodes: Option<HashMap<i64, state_trees::Model>>,
+}
+
+struct InMemoryTreeNodes {
+    trees: HashMap<Vec<u8>, TreeNodes>,
+    // `None` for nodes that are known to be empty.
+    lower_nodes: LruCache<NodeLocation, Option<state_trees::Model>>,
+}
+
+impl InMemoryTreeNodes {
+    fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            trees: HashMap::new(),
+            lower_nodes: LruCache::new(capacity),
+        }
+    }
+
+    /// Returns `None` if the node has not been loaded yet.
+    fn get(&mut self, (tree, node_idx): &NodeLocation) -> Option<Option<state_trees::Model>> {
+        if *node_idx < RESIDENT_NODE_INDEX_BOUND {
+            let resident_nodes = self.trees.get(tree)?.resident_nodes.as_ref()?;
+            Some(resident_nodes.get(node_idx).cloned())
+        } else {
+            self.lower_nodes.get(&(tree.clone(), *node_idx)).cloned()
+        }
+    }
+
+    fn generation(&self, tree: &[u8]) -> u64 {
+        self.trees.get(tree).map_or(0, |tree| tree.generation)
+    }
+
+    /// Stores the node at `location`, unless the stored node has a higher seq. Resident nodes are
+    /// only stored once the top levels of their tree are loaded.
+    fn update(&mut self, location: NodeLocation, node: Option<state_trees::Model>) {
+        let (tree, node_idx) = &location;
+        let stored_node = match *node_idx < RESIDENT_NODE_INDEX_BOUND {
+            true => match self
+                .trees
+                .get_mut(tree)
+                .and_then(|tree| tree.resident_nodes.as_mut())
+            {
+                Some(resident_nodes) => resident_nodes.get(node_idx),
+                None => return,
+            },
+            false => self.lower_nodes.peek(&location).and_then(Option::as_ref),
+        };
+        if let (Some(stored_node), Some(node)) = (stored_node, &node) {
+            if stored_node.seq > node.seq {
+                return;
+            }
+        }
+        if *node_idx < RESIDENT_NODE_INDEX_BOUND {
+            let resident_nodes = self
+                .trees
+                .get_mut(tree)
+                .and_then(|tree| tree.resident_nodes.as_mut())
+                .unwrap();
+            match node {
+                Some(node) => resident_nodes.insert(*node_idx, node),
+                None => resident_nodes.remove(node_idx),
+            };
+        } else {
+            self.lower_nodes.put(location, node);
+        }
+    }
+
+    fn clear(&mut self) {
+        for tree_nodes in self.trees.values_mut() {
+            tree_nodes.generation += 1;
+            tree_nodes.resident_nodes = None;
+        }
+        self.lower_nodes.clear();
+    }
+
+    fn evict_tree(&mut self, tree: &[u8]) {
+        let tree_nodes = self.trees.entry(tree.to_vec()).or_default();
+        tree_nodes.generation += 1;
+        tree_nodes.resident_nodes = None;
+        let stale_locations = self
+            .lower_nodes
+            .iter()
+            .filter(|((node_tree, _), _)| node_tree == tree)
+            .map(|(location, _)| location.clone())
+            .collect::<Vec<_>>();
+        for location in stale_locations {
+            self.lower_nodes.pop(&location);
+        }
+    }
+}
+
+/// In-memory copy of the nodes of the state trees that proofs are read from, shared by the API and
+/// the indexer that updates it. The top levels of each tree are kept in full, the lower levels are
+/// loaded on demand and evicted when the number of loaded lower nodes exceeds the capacity. The
+/// indexer updates the nodes after it commits new leaves.
+#[derive(Clone, Default)]
+pub struct InMemoryTrees(Option<Arc<Mutex<InMemoryTreeNodes>>>);
+
+impl InMemoryTrees {
+    /// Keeps up to `capacity` nodes below the resident top levels in memory. 0 disables the
+    /// in-memory trees.
+    pub fn new(capacity: usize) -> Self {
+        Self(
+            NonZeroUsize::new(capacity)
+                .map(|capacity| Arc::new(Mutex::new(InMemoryTreeNodes::new(capacity)))),
+        )
+    }
+
+    /// Drops all nodes, e.g. after blocks of an orphaned fork were rolled back.
+    pub fn clear(&self) {
+        if let Some(nodes) = &self.0 {
+            nodes.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns the nodes at `node_locations` from memory, loading the ones that are not in memory
+    /// yet. The nodes are read at once, so they belong to the same state of each tree. Returns
+    /// `None` if the in-memory trees are disabled or kept being updated while their nodes were
+    /// loaded.
+    pub async fn get_nodes(
+        &self,
+        conn: &impl ConnectionTrait,
+        batch_config: SqlBatchConfig,
+        node_locations: &[NodeLocation],
+    ) -> Result<Option<HashMap<NodeLocation, state_trees::Model>>, DbErr> {
+        let Some(in_memory_nodes) = &self.0 else {
+            return Ok(None);
+        };
+        for _ in 0..MAX_LOAD_ATTEMPTS {
+            let (missing_trees, missing_lower_nodes) = {
+                let mut trees = in_memory_nodes.lock().unwrap();
+                let mut nodes = HashMap::new();
+                let mut missing_trees = HashMap::new();
+                let mut missing_lower_nodes = Vec::new();
+                for location in node_locations {
+                    match trees.get(location) {
+                        Some(node) => {
+                            nodes.extend(node.map(|node| (location.clone(), node)));
+                        }
+                        None if location.1 < RESIDENT_NODE_INDEX_BOUND => {
+                            missing_trees.insert(location.0.clone(), trees.generation(&location.0));
+                        }
+                        None => missing_lower_nodes.push(location.clone()),
+                    }
+                }
+                if missing_trees.is_empty() && missing_lower_nodes.is_empty() {
+                    metric! {
+                        statsd_count!("in_memory_tree_hit", 1);
+                    }
+                    return Ok(Some(nodes));
+                }
+                let missing_lower_nodes = missing_lower_nodes
+                    .into_iter()
+                    .map(|location| {
+                        let generation = trees.generation(&location.0);
+                        (location, generation)
+                    })
+                    .collect::<Vec<_>>();
+                (missing_trees, missing_lower_nodes)
+            };
+            metric! {
+                statsd_count!("in_memory_tree_miss", 1);
+            }
+
+            let mut resident_nodes = Vec::new();
+            for (tree, generation) in missing_trees {
+                let nodes = state_trees::Entity::find()
+                    .filter(
+                        state_trees::Column::Tree
+                            .eq(tree.clone())
+                            .and(state_trees::Column::NodeIdx.lt(RESIDENT_NODE_INDEX_BOUND)),
+                    )
+                    .all(conn)
+                    .await?
+                    .into_iter()
+                    .map(|node| (node.node_idx, node))
+                    .collect::<HashMap<_, _>>();
+                resident_nodes.push((tree, generation, nodes));
+            }
+            let lower_nodes = find_nodes(
+                conn,
+                batch_config,
+                &missing_lower_nodes
+                    .iter()
+                    .map(|(location, _)| location.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .await?
+            .into_iter()
+            .map(|node| ((node.tree.clone(), node.node_idx), node))
+            .collect::<HashMap<_, _>>();
+
+            let mut trees = in_memory_nodes.lock().unwrap();
+            for (tree, generation, nodes) in resident_nodes {
+                if trees.generation(&tree) == generation {
+                    trees.trees.entry(tree).or_default().resident_nodes = Some(nodes);
+                }
+            }
+            for (location, generation) in missing_lower_nodes {
+                if trees.generation(&location.0) == generation {
+                    let node = lower_nodes.get(&location).cloned();
+                    trees.lower_nodes.put(location, node);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Leaves of the state trees that a state update writes. The in-memory trees are updated once the
+/// state update is committed, since updating them earlier lets concurrent reads see the state
+/// update before it is committed.
+pub struct PendingTreeUpdates {
+    leaves: HashSet<NodeLocation>,
+    // Address trees are evicted instead of updated, since their leaves are written in several
+    // places.
+    address_trees: HashSet<Vec<u8>>,
+}
+
+impl PendingTreeUpdates {
+    /// Returns `None` if the in-memory trees are disabled.
+    pub fn collect(in_memory_trees: &InMemoryTrees, state_update: &StateUpdate) -> Option<Self> {
+        in_memory_trees.0.as_ref()?;
+        let leaf_node_index = |tree: &Pubkey, leaf_index: u64| {
+            leaf_index_to_node_index(leaf_index as u32, tree_height(tree))
+        };
+        let mut leaves = HashSet::new();
+        for account in &state_update.out_accounts {
+            leaves.insert((
+                account.tree.to_bytes_vec(),
+                leaf_node_index(&account.tree.0, account.leaf_index.0),
+            ));
+        }
+        for leaf_nullification in &state_update.leaf_nullifications {
+            leaves.insert((
+                leaf_nullification.tree.to_bytes().to_vec(),
+                leaf_node_index(&leaf_nullification.tree, leaf_nullification.leaf_index),
+            ));
+        }
+        for path_update in &state_update.path_updates {
+            if let Some(leaf) = path_update.path.first() {
+                leaves.insert((path_update.tree.to_vec(), leaf.index as i64));
+            }
+        }
+        let address_trees = state_update
+            .indexed_merkle_tree_updates
+            .keys()
+            .map(|(tree, _)| tree.to_bytes().to_vec())
+            .collect();
+        Some(Self {
+            leaves,
+            address_trees,
+        })
+    }
+
+    /// Reloads the leaves and the nodes above them from `conn`. Evicts their trees if that fails.
+    pub async fn apply(
+        self,
+        conn: &impl ConnectionTrait,
+        batch_config: SqlBatchConfig,
+        in_memory_trees: &InMemoryTrees,
+    ) {
+        let Some(in_memory_nodes) = &in_memory_trees.0 else {
+            return;
+        };
+        let node_locations = self
+            .leaves
+            .iter()
+            .flat_map(|(tree, node_idx)| {
+                std::iter::once(*node_idx)
+                    .chain(get_node_direct_ancestors(*node_idx))
+                    .map(move |idx| (tree.clone(), idx))
+            })
+            .collect::<HashSet<NodeLocation>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let nodes = find_nodes(conn, batch_config, &node_locations).await;
+
+        let mut trees = in_memory_nodes.lock().unwrap();
+        for tree in &self.address_trees {
+            trees.evict_tree(tree);
+        }
+        let updated_trees = self
+            .leaves
+            .iter()
+            .map(|(tree, _)| tree.clone())
+            .collect::<HashSet<_>>();
+        let nodes = match nodes {
+            Ok(nodes) => nodes,
+            Err(e) => {
+                warn!("Failed to reload updated state tree nodes: {}", e);
+                for tree in &updated_trees {
+                    trees.evict_tree(tree);
+                }
+                return;
+            }
+        };
+        for tree in &updated_trees {
+            trees.trees.entry(tree.clone()).or_default().generation += 1;
+        }
+        let mut nodes = nodes
+            .into_iter()
+            .map(|node| ((node.tree.clone(), node.node_idx), node))
+            .collect::<HashMap<_, _>>();
+        for location in node_locations {
+            let node = nodes.remove(&location);
+            trees.update(location, node);
+        }
+    }
+}
@@ -1,62 +1,92 @@
 use super::{error, parser::state_update::AccountTransaction};
 use crate::{
     api::method::{get_multiple_new_address_proofs::ADDRESS_TREE_HEIGHT, utils::PAGE_LIMIT},
-    common::typedefs::{account::Account, hash::Hash, token_data::TokenData},
+    common::typedefs::{
+        account::Account,
+        hash::Hash,
+        token_data::{TokenData, TokenExtensions},
+    },
     dao::generated::{account_transactions, state_tree_histories, state_trees, transactions},
     ingester::parser::state_update::Transaction,
     metric,
 };
 use crate::{
-    dao::generated::{accounts, token_accounts},
-    ingester::parser::state_update::StateUpdate,
+    common::account_data_compression::compress_account_data,
+    common::account_data_deduplication::account_data_ref,
+    common::prometheus::{ACCOUNTS_INDEXED, DB_WRITE_DURATION},
+    common::unspent_hash_filter::insert_unspent_hashes,
+    dao::generated::{account_data, accounts, pruned_accounts, token_accounts},
+    ingester::{parser::state_update::StateUpdate, tree_info::tree_height},
 };
-use itertools::Itertools;
+use itertools::{Either, Itertools};
 use light_poseidon::{Poseidon, PoseidonBytesHasher};
 
 use ark_bn254::Fr;
+use batch_size::SqlBatchConfig;
 use borsh::BorshDeserialize;
-use cadence_macros::statsd_count;
+use bulk_insert::{build_bulk_insert, bulk_insert_chunk_size};
+use cadence_macros::{statsd_count, statsd_histogram};
+use futures::future::try_join_all;
 use log::debug;
+use node_history::persist_node_histories;
+use nullifier_queue::{dequeue_nullified_leaves, enqueue_spent_accounts};
 use persisted_indexed_merkle_tree::update_indexed_tree_leaves;
-use persisted_state_tree::{persist_leaf_nodes, LeafNode};
+use persisted_state_tree::{count_path_nodes, persist_leaf_nodes, LeafNode};
 use sea_orm::{
-    sea_query::{Expr, OnConflict},
+    sea_query::{CaseStatement, Expr, Nullable, OnConflict, SimpleExpr, Value},
     ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseTransaction, EntityTrait, Order,
     QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set, Statement,
 };
-use std::{cmp::max, collections::HashMap};
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
 
 use error::IngesterError;
 use solana_program::pubkey;
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
 use sqlx::types::Decimal;
+use tracing::instrument;
+pub mod batch_size;
+pub mod bulk_insert;
+pub mod in_memory_tree;
+pub mod node_history;
+pub mod nullifier_queue;
 pub mod persisted_indexed_merkle_tree;
 pub mod persisted_state_tree;
+pub mod rollback;
+pub mod token_data_monitor;
 
-const COMPRESSED_TOKEN_PROGRAM: Pubkey = pubkey!("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m");
-const TREE_HEIGHT: u32 = 27;
-// To avoid exceeding the 64k total parameter limit
-pub const MAX_SQL_INSERTS: usize = 500;
+pub const COMPRESSED_TOKEN_PROGRAM: Pubkey = pubkey!("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m");
+pub const TREE_HEIGHT: u32 = 27;
+// Number of groups of state trees whose nodes are written concurrently within a batch transaction.
+const MAX_CONCURRENT_TREE_WRITES: usize = 4;
 
+#[instrument(name = "persist_state_update", skip_all)]
 pub async fn persist_state_update(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     state_update: StateUpdate,
 ) -> Result<(), IngesterError> {
     if state_update == StateUpdate::default() {
         return Ok(());
     }
+    let started_at = Instant::now();
     let StateUpdate {
         in_accounts,
         out_accounts,
         account_transactions,
         transactions,
         leaf_nullifications,
+        path_updates,
         indexed_merkle_tree_updates,
     } = state_update;
 
     let input_accounts_len = in_accounts.len();
     let output_accounts_len = out_accounts.len();
     let leaf_nullifications_len = leaf_nullifications.len();
+    let path_updates_len = path_updates.len();
     let indexed_merkle_tree_updates_len = indexed_merkle_tree_updates.len();
 
     debug!(
@@ -64,36 +94,41 @@ pub async fn persist_state_update(
         in_accounts.len(),
         out_accounts.len()
     );
+    let (creation_signatures, spends): (HashMap<_, _>, HashMap<_, _>) = account_transactions
+        .iter()
+        .partition_map(|account_transaction| {
+            let hash = account_transaction.hash.clone();
+            if account_transaction.spent {
+                Either::Right((hash, account_transaction))
+            } else {
+                Either::Left((hash, account_transaction.signature))
+            }
+        });
+
     debug!("Persisting output accounts...");
-    for chunk in out_accounts.chunks(MAX_SQL_INSERTS) {
-        append_output_accounts(txn, chunk).await?;
+    // Token accounts and shared account data are inserted in the same chunks and have fewer
+    // columns than accounts.
+    for chunk in out_accounts.chunks(bulk_insert_chunk_size::<accounts::Entity>(batch_config)) {
+        append_output_accounts(txn, chunk, &creation_signatures).await?;
     }
 
     debug!("Persisting spent accounts...");
-    for chunk in in_accounts
-        .into_iter()
-        .collect::<Vec<_>>()
-        .chunks(MAX_SQL_INSERTS)
-    {
-        spend_input_accounts(txn, chunk).await?;
+    // Spends are always written, since the hash filter only knows about the accounts that this
+    // process has seen. Besides the hash in the filter, the spend signature and the spend slot
+    // each bind the hash and their value.
+    let in_accounts = in_accounts.into_iter().collect::<Vec<_>>();
+    for chunk in in_accounts.chunks(batch_config.batch_size(5)) {
+        spend_input_accounts(txn, chunk, &spends).await?;
+        let hashes = chunk.iter().map(|hash| hash.to_vec()).collect_vec();
+        enqueue_spent_accounts(txn, &hashes).await?;
     }
 
-    let account_to_transaction = account_transactions
-        .iter()
-        .map(|account_transaction| {
-            (
-                account_transaction.hash.clone(),
-                account_transaction.signature,
-            )
-        })
-        .collect::<HashMap<_, _>>();
-
     let mut leaf_nodes_with_signatures: Vec<(LeafNode, Signature)> = out_accounts
         .iter()
         .map(|account| {
             (
                 LeafNode::from(account.clone()),
-                account_to_transaction
+                creation_signatures
                     .get(&account.hash)
                     .copied()
                     // HACK: We should always have a signature for account transactions, but sometimes
@@ -108,19 +143,21 @@ pub async fn persist_state_update(
             )
         }))
         .collect();
+    for path_update in path_updates {
+        let signature = path_update.signature;
+        leaf_nodes_with_signatures.push((LeafNode::try_from(path_update)?, signature));
+    }
 
     leaf_nodes_with_signatures.sort_by_key(|x| x.0.seq);
 
     debug!("Persisting state nodes...");
-    for chunk in leaf_nodes_with_signatures.chunks(MAX_SQL_INSERTS) {
-        let chunk_vec = chunk.iter().cloned().collect_vec();
-        persist_state_tree_history(txn, chunk_vec.clone()).await?;
-        let leaf_nodes_chunk = chunk_vec
-            .iter()
-            .map(|(leaf_node, _)| leaf_node.clone())
-            .collect_vec();
-
-        persist_leaf_nodes(txn, leaf_nodes_chunk, TREE_HEIGHT).await?;
+    persist_state_trees_concurrently(txn, batch_config, &leaf_nodes_with_signatures).await?;
+    let leaf_nodes = leaf_nodes_with_signatures
+        .into_iter()
+        .map(|(leaf_node, _)| leaf_node)
+        .collect_vec();
+    for chunk in leaf_nodes.chunks(batch_config.batch_size(2)) {
+        dequeue_nullified_leaves(txn, chunk).await?;
     }
 
     let transactions_vec = transactions.into_iter().collect::<Vec<_>>();
@@ -142,26 +179,93 @@ pub async fn persist_state_update(
                 .take(non_compression_transactions_to_keep as usize),
         )
         .collect_vec();
-    for chunk in transactions_to_persist.chunks(MAX_SQL_INSERTS) {
+    for chunk in
+        transactions_to_persist.chunks(bulk_insert_chunk_size::<transactions::Entity>(batch_config))
+    {
         persist_transactions(txn, chunk).await?;
     }
 
     debug!("Persisting account transactions...");
     let account_transactions = account_transactions.into_iter().collect::<Vec<_>>();
-    for chunk in account_transactions.chunks(MAX_SQL_INSERTS) {
+    for chunk in account_transactions.chunks(
+        bulk_insert_chunk_size::<account_transactions::Entity>(batch_config),
+    ) {
         persist_account_transactions(txn, chunk).await?;
     }
 
     debug!("Persisting index tree updates...");
-    update_indexed_tree_leaves(txn, indexed_merkle_tree_updates, ADDRESS_TREE_HEIGHT).await?;
+    update_indexed_tree_leaves(
+        txn,
+        batch_config,
+        indexed_merkle_tree_updates,
+        ADDRESS_TREE_HEIGHT,
+    )
+    .await?;
 
     metric! {
         statsd_count!("state_update.input_accounts", input_accounts_len as u64);
         statsd_count!("state_update.output_accounts", output_accounts_len as u64);
         statsd_count!("state_update.leaf_nullifications", leaf_nullifications_len as u64);
+        statsd_count!("state_update.path_updates", path_updates_len as u64);
         statsd_count!("state_update.indexed_merkle_tree_updates", indexed_merkle_tree_updates_len as u64);
     }
+    ACCOUNTS_INDEXED.inc_by(&[], output_accounts_len as u64);
+    DB_WRITE_DURATION.observe_duration(&[], started_at.elapsed());
+
+    Ok(())
+}
+
+// Distributes the trees over up to `MAX_CONCURRENT_TREE_WRITES` groups and writes the groups
+// concurrently. Trees share no rows, and all writes go to the batch transaction, so the batch stays
+// atomic while the node hashes of one group are computed as the statements of another run.
+async fn persist_state_trees_concurrently(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    leaf_nodes_with_signatures: &[(LeafNode, Signature)],
+) -> Result<(), IngesterError> {
+    let leaf_nodes_by_tree = leaf_nodes_with_signatures
+        .iter()
+        .cloned()
+        .into_group_map_by(|(leaf_node, _)| leaf_node.tree);
+    if leaf_nodes_by_tree.len() < 2 {
+        return persist_state_trees(txn, batch_config, leaf_nodes_with_signatures).await;
+    }
+    let partition_count = leaf_nodes_by_tree.len().min(MAX_CONCURRENT_TREE_WRITES);
+    let mut partitions = vec![Vec::new(); partition_count];
+    for (i, tree_leaf_nodes) in leaf_nodes_by_tree.into_values().enumerate() {
+        partitions[i % partition_count].extend(tree_leaf_nodes);
+    }
+    try_join_all(partitions.into_iter().map(|mut partition| async move {
+        partition.sort_by_key(|(leaf_node, _)| leaf_node.seq);
+        persist_state_trees(txn, batch_config, &partition).await
+    }))
+    .await?;
+    Ok(())
+}
 
+// Writes the history and the nodes of the state trees that the leaves belong to.
+async fn persist_state_trees(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    leaf_nodes_with_signatures: &[(LeafNode, Signature)],
+) -> Result<(), IngesterError> {
+    // The path nodes of the leaves are inserted in chunks of their own in `persist_leaf_nodes`.
+    for chunk in leaf_nodes_with_signatures
+        .chunks(batch_config.insert_batch_size::<state_tree_histories::Entity>())
+    {
+        persist_state_tree_history(txn, chunk.to_vec()).await?;
+        let leaf_nodes_chunk = chunk
+            .iter()
+            .map(|(leaf_node, _)| leaf_node.clone())
+            .collect_vec();
+        persist_node_histories(txn, batch_config, &leaf_nodes_chunk).await?;
+        let leaf_nodes_by_height = leaf_nodes_chunk
+            .into_iter()
+            .into_group_map_by(|leaf_node| tree_height(&leaf_node.tree.0));
+        for (height, leaf_nodes) in leaf_nodes_by_height {
+            persist_leaf_nodes(txn, batch_config, leaf_nodes, height).await?;
+        }
+    }
     Ok(())
 }
 
@@ -181,24 +285,68 @@ async fn persist_state_tree_history(
     // We first build the query and then execute it because SeaORM has a bug where it always throws
     // an error if we do not insert a record in an insert statement. However, in this case, it's
     // expected not to insert anything if the key already exists.
-    let query = state_tree_histories::Entity::insert_many(state_tree_history)
-        .on_conflict(
-            OnConflict::columns([state_trees::Column::Tree, state_trees::Column::Seq])
-                .do_nothing()
-                .to_owned(),
-        )
-        .build(txn.get_database_backend());
+    let query = build_bulk_insert(
+        txn.get_database_backend(),
+        state_tree_history,
+        OnConflict::columns([state_trees::Column::Tree, state_trees::Column::Seq])
+            .do_nothing()
+            .to_owned(),
+    );
     txn.execute(query).await?;
     Ok(())
 }
 
+/// Records the composition of the state update of a single block. The ingester persists blocks in
+/// batches, so the totals recorded while persisting do not show how traffic is distributed.
+pub fn record_block_state_update_composition(state_update: &StateUpdate) {
+    let token_accounts = state_update
+        .out_accounts
+        .iter()
+        .filter(|account| account.owner.0 == COMPRESSED_TOKEN_PROGRAM && account.data.is_some())
+        .count();
+    let leaves = state_update
+        .out_accounts
+        .iter()
+        .map(|account| (account.tree, account.leaf_index.0 as u32))
+        .chain(
+            state_update
+                .leaf_nullifications
+                .iter()
+                .map(|nullification| (nullification.tree.into(), nullification.leaf_index as u32)),
+        )
+        .chain(
+            state_update
+                .path_updates
+                .iter()
+                .filter_map(|path_update| LeafNode::try_from(path_update.clone()).ok())
+                .map(|leaf_node| (leaf_node.tree, leaf_node.leaf_index)),
+        );
+    // Leaves of different trees never share nodes, so the counts of each height add up.
+    let path_nodes = leaves
+        .into_group_map_by(|(tree, _)| tree_height(&tree.0))
+        .into_iter()
+        .map(|(height, leaves)| count_path_nodes(leaves, height))
+        .sum::<usize>();
+    metric! {
+        statsd_histogram!("block_state_update.input_accounts", state_update.in_accounts.len() as u64);
+        statsd_histogram!("block_state_update.output_accounts", state_update.out_accounts.len() as u64);
+        statsd_histogram!("block_state_update.token_accounts", token_accounts as u64);
+        statsd_histogram!("block_state_update.path_nodes", path_nodes as u64);
+    }
+}
+
 pub fn parse_token_data(account: &Account) -> Result<Option<TokenData>, IngesterError> {
     match account.data.clone() {
         Some(data) if account.owner.0 == COMPRESSED_TOKEN_PROGRAM => {
             let data_slice = data.data.0.as_slice();
-            let token_data = TokenData::try_from_slice(data_slice).map_err(|e| {
+            let mut token_data = TokenData::try_from_slice(data_slice).map_err(|e| {
                 IngesterError::ParserError(format!("Failed to parse token data: {:?}", e))
             })?;
+            if let Some(tlv) = &token_data.tlv {
+                token_data.extensions = TokenExtensions::parse(&tlv.0).map_err(|e| {
+                    IngesterError::ParserError(format!("Failed to parse token extensions: {}", e))
+                })?;
+            }
             Ok(Some(token_data))
         }
         _ => Ok(None),
@@ -208,14 +356,26 @@ pub fn parse_token_data(account: &Account) -> Result<Option<TokenData>, Ingester
 async fn spend_input_accounts(
     txn: &DatabaseTransaction,
     in_accounts: &[Hash],
+    spends: &HashMap<Hash, &AccountTransaction>,
 ) -> Result<(), IngesterError> {
-    // Perform the update operation on the identified records
+    let spend_signature = |spend: &AccountTransaction| spend.signature.as_ref().to_vec();
+    let spend_slot = |spend: &AccountTransaction| spend.slot as i64;
+    // Accounts that are spent already are left alone, so that spending them again, e.g. when a
+    // backfill replays blocks that live ingestion has indexed, cannot overwrite `prev_spent`.
     let query = accounts::Entity::update_many()
         .col_expr(accounts::Column::Spent, Expr::value(true))
         .col_expr(
             accounts::Column::PrevSpent,
             Expr::col(accounts::Column::Spent).into(),
         )
+        .col_expr(
+            accounts::Column::SpentTxSignature,
+            spend_expr(accounts::Column::Hash, in_accounts, spends, spend_signature),
+        )
+        .col_expr(
+            accounts::Column::SlotSpent,
+            spend_expr(accounts::Column::Hash, in_accounts, spends, spend_slot),
+        )
         .filter(
             accounts::Column::Hash.is_in(
                 in_accounts
@@ -224,6 +384,7 @@ async fn spend_input_accounts(
                     .collect::<Vec<Vec<u8>>>(),
             ),
         )
+        .filter(accounts::Column::Spent.eq(false))
         .build(txn.get_database_backend());
 
     execute_account_update_query_and_update_balances(
@@ -241,6 +402,24 @@ async fn spend_input_accounts(
             token_accounts::Column::PrevSpent,
             Expr::col(token_accounts::Column::Spent).into(),
         )
+        .col_expr(
+            token_accounts::Column::SpentTxSignature,
+            spend_expr(
+                token_accounts::Column::Hash,
+                in_accounts,
+                spends,
+                spend_signature,
+            ),
+        )
+        .col_expr(
+            token_accounts::Column::SlotSpent,
+            spend_expr(
+                token_accounts::Column::Hash,
+                in_accounts,
+                spends,
+                spend_slot,
+            ),
+        )
         .filter(
             token_accounts::Column::Hash.is_in(
                 in_accounts
@@ -249,6 +428,7 @@ async fn spend_input_accounts(
                     .collect::<Vec<Vec<u8>>>(),
             ),
         )
+        .filter(token_accounts::Column::Spent.eq(false))
         .build(txn.get_database_backend());
 
     execute_account_update_query_and_update_balances(
@@ -262,9 +442,45 @@ async fn spend_input_accounts(
     Ok(())
 }
 
+// Maps the hash of every spent account to a value of the transaction that spent it, e.g. its
+// signature or slot. Spends without a known transaction, e.g. in state snapshots, leave the value
+// NULL.
+fn spend_expr<V: Into<Value> + Nullable>(
+    hash_column: impl ColumnTrait,
+    in_accounts: &[Hash],
+    spends: &HashMap<Hash, &AccountTransaction>,
+    value: impl Fn(&AccountTransaction) -> V,
+) -> SimpleExpr {
+    let unknown = Expr::value(Option::<V>::None);
+    let known_spends = in_accounts
+        .iter()
+        .filter_map(|hash| Some((hash, spends.get(hash)?)))
+        .collect_vec();
+    // A CASE needs at least one WHEN.
+    if known_spends.is_empty() {
+        return unknown;
+    }
+    known_spends
+        .into_iter()
+        .fold(CaseStatement::new(), |case, (hash, spend)| {
+            case.case(hash_column.eq(hash.to_vec()), Expr::val(value(spend)))
+        })
+        .finally(unknown)
+        .into()
+}
+
+// The spend of an account that was indexed before the account itself, because the block that
+// created the account was indexed out of order or because the account was pruned.
+struct RecordedSpend {
+    slot: Option<i64>,
+    tx_signature: Option<Vec<u8>>,
+}
+
 pub struct EnrichedTokenAccount {
     pub token_data: TokenData,
     pub hash: Hash,
+    pub slot_created: u64,
+    pub tx_signature: Option<Signature>,
 }
 
 #[derive(Debug)]
@@ -309,20 +525,23 @@ async fn execute_account_update_query_and_update_balances(
     account_type: AccountType,
     modification_type: ModificationType,
 ) -> Result<(), IngesterError> {
-    let (owner_table_name, balance_column, additional_columns) = match account_type {
-        AccountType::Account => ("owner_balances", "lamports", ""),
-        AccountType::TokenAccount => ("token_owner_balances", "amount", ", mint"),
+    let (balance_column, additional_columns) = match account_type {
+        AccountType::Account => ("lamports", ""),
+        AccountType::TokenAccount => ("amount", ", mint"),
     };
 
     query.sql = format!(
-        "{} RETURNING owner,prev_spent,{}{}",
+        "{} RETURNING owner,spent,prev_spent,{}{}",
         query.sql, balance_column, additional_columns
     );
     let result = txn.query_all(query.clone()).await.map_err(|e| {
-        IngesterError::DatabaseError(format!(
-            "Got error appending {:?} accounts {}. Query {}",
-            account_type, e, query.sql
-        ))
+        IngesterError::database(
+            format!(
+                "Got error appending {:?} accounts. Query {}",
+                account_type, query.sql
+            ),
+            e,
+        )
     })?;
     let multiplier = Decimal::from(match &modification_type {
         ModificationType::Append => 1,
@@ -331,9 +550,10 @@ async fn execute_account_update_query_and_update_balances(
     let mut balance_modifications = HashMap::new();
     let db_backend = txn.get_database_backend();
     for row in result {
+        let spent: bool = row.try_get("", "spent")?;
         let prev_spent: Option<bool> = row.try_get("", "prev_spent")?;
-        match (prev_spent, &modification_type) {
-            (_, ModificationType::Append) | (Some(false), ModificationType::Spend) => {
+        match (spent, prev_spent, &modification_type) {
+            (false, _, ModificationType::Append) | (_, Some(false), ModificationType::Spend) => {
                 let mut amount_of_interest = match db_backend {
                     DatabaseBackend::Postgres => row.try_get("", balance_column)?,
                     DatabaseBackend::Sqlite => {
@@ -362,6 +582,21 @@ async fn execute_account_update_query_and_update_balances(
             _ => {}
         }
     }
+    update_balances(txn, &account_type, balance_modifications).await
+}
+
+/// Adds the given amounts to the owner balances. The keys are the SQL literals of the balance
+/// table's primary key, i.e. the owner for accounts and the owner and mint for token accounts.
+async fn update_balances(
+    txn: &DatabaseTransaction,
+    account_type: &AccountType,
+    balance_modifications: HashMap<String, Decimal>,
+) -> Result<(), IngesterError> {
+    let (owner_table_name, balance_column, additional_columns) = match account_type {
+        AccountType::Account => ("owner_balances", "lamports", ""),
+        AccountType::TokenAccount => ("token_owner_balances", "amount", ", mint"),
+    };
+    let db_backend = txn.get_database_backend();
     let values = balance_modifications
         .into_iter()
         .filter(|(_, value)| *value != Decimal::from(0))
@@ -385,11 +620,43 @@ async fn execute_account_update_query_and_update_balances(
 async fn append_output_accounts(
     txn: &DatabaseTransaction,
     out_accounts: &[Account],
+    creation_signatures: &HashMap<Hash, Signature>,
 ) -> Result<(), IngesterError> {
     let mut account_models = Vec::new();
+    let mut shared_data_models = HashMap::new();
     let mut token_accounts = Vec::new();
+    insert_unspent_hashes(out_accounts.iter().map(|account| &account.hash));
+    let recorded_spends = fetch_recorded_spends(
+        txn,
+        out_accounts
+            .iter()
+            .map(|account| account.hash.to_vec())
+            .collect(),
+    )
+    .await?;
 
     for account in out_accounts {
+        let tx_signature = creation_signatures.get(&account.hash).copied();
+        let spend = recorded_spends.get(&account.hash.to_vec());
+        let (data, data_compressed, data_ref) = match account.data.as_ref() {
+            Some(data) => {
+                let (stored_data, data_compressed) = compress_account_data(data.data.0.clone());
+                match account_data_ref(&data.data.0) {
+                    Some(data_ref) => {
+                        shared_data_models
+                            .entry(data_ref.clone())
+                            .or_insert_with(|| account_data::ActiveModel {
+                                hash: Set(data_ref.clone()),
+                                data: Set(stored_data),
+                                data_compressed: Set(data_compressed),
+                            });
+                        (None, false, Some(data_ref))
+                    }
+                    None => (Some(stored_data), data_compressed, None),
+                }
+            }
+            None => (None, false, None),
+        };
         account_models.push(accounts::ActiveModel {
             hash: Set(account.hash.to_vec()),
             address: Set(account.address.map(|x| x.to_bytes_vec())),
@@ -397,34 +664,56 @@ async fn append_output_accounts(
                 .data
                 .as_ref()
                 .map(|x| Decimal::from(x.discriminator.0))),
-            data: Set(account.data.as_ref().map(|x| x.data.clone().0)),
+            data: Set(data),
+            data_compressed: Set(data_compressed),
+            data_ref: Set(data_ref),
             data_hash: Set(account.data.as_ref().map(|x| x.data_hash.to_vec())),
             tree: Set(account.tree.to_bytes_vec()),
             leaf_index: Set(account.leaf_index.0 as i64),
             owner: Set(account.owner.to_bytes_vec()),
             lamports: Set(Decimal::from(account.lamports.0)),
-            spent: Set(false),
+            spent: Set(spend.is_some()),
             slot_created: Set(account.slot_created.0 as i64),
             seq: Set(account.seq.0 as i64),
-            prev_spent: Set(None),
+            prev_spent: Set(spend.map(|_| false)),
+            tx_signature: Set(
+                tx_signature.map(|signature| Into::<[u8; 64]>::into(signature).to_vec())
+            ),
+            spent_tx_signature: Set(spend.and_then(|spend| spend.tx_signature.clone())),
+            slot_spent: Set(spend.and_then(|spend| spend.slot)),
         });
 
-        if let Some(token_data) = parse_token_data(account)? {
+        let token_data = parse_token_data(account);
+        token_data_monitor::record_token_data_parse(&account.owner.0, &token_data);
+        if let Some(token_data) = token_data? {
             token_accounts.push(EnrichedTokenAccount {
                 token_data,
                 hash: account.hash.clone(),
+                slot_created: account.slot_created.0,
+                tx_signature,
             });
         }
     }
 
+    if !shared_data_models.is_empty() {
+        let query = build_bulk_insert(
+            txn.get_database_backend(),
+            shared_data_models.into_values().collect(),
+            OnConflict::column(account_data::Column::Hash)
+                .do_nothing()
+                .to_owned(),
+        );
+        txn.execute(query).await?;
+    }
+
     if !out_accounts.is_empty() {
-        let query = accounts::Entity::insert_many(account_models)
-            .on_conflict(
-                OnConflict::column(accounts::Column::Hash)
-                    .do_nothing()
-                    .to_owned(),
-            )
-            .build(txn.get_database_backend());
+        let query = build_bulk_insert(
+            txn.get_database_backend(),
+            account_models,
+            OnConflict::column(accounts::Column::Hash)
+                .do_nothing()
+                .to_owned(),
+        );
         execute_account_update_query_and_update_balances(
             txn,
             query,
@@ -435,7 +724,7 @@ async fn append_output_accounts(
 
         if !token_accounts.is_empty() {
             debug!("Persisting {} token accounts...", token_accounts.len());
-            persist_token_accounts(txn, token_accounts).await?;
+            insert_token_accounts(txn, token_accounts, &recorded_spends).await?;
         }
     }
 
@@ -445,31 +734,83 @@ async fn append_output_accounts(
 pub async fn persist_token_accounts(
     txn: &DatabaseTransaction,
     token_accounts: Vec<EnrichedTokenAccount>,
+) -> Result<(), IngesterError> {
+    let recorded_spends = fetch_recorded_spends(
+        txn,
+        token_accounts
+            .iter()
+            .map(|token_account| token_account.hash.to_vec())
+            .collect(),
+    )
+    .await?;
+    insert_token_accounts(txn, token_accounts, &recorded_spends).await
+}
+
+async fn insert_token_accounts(
+    txn: &DatabaseTransaction,
+    token_accounts: Vec<EnrichedTokenAccount>,
+    recorded_spends: &HashMap<Vec<u8>, RecordedSpend>,
 ) -> Result<(), IngesterError> {
     let token_models = token_accounts
         .into_iter()
         .map(
-            |EnrichedTokenAccount { token_data, hash }| token_accounts::ActiveModel {
-                hash: Set(hash.into()),
-                mint: Set(token_data.mint.to_bytes_vec()),
-                owner: Set(token_data.owner.to_bytes_vec()),
-                amount: Set(Decimal::from(token_data.amount.0)),
-                delegate: Set(token_data.delegate.map(|d| d.to_bytes_vec())),
-                state: Set(token_data.state as i32),
-                spent: Set(false),
-                prev_spent: Set(None),
-                tlv: Set(token_data.tlv.map(|t| t.0)),
+            |EnrichedTokenAccount {
+                 token_data,
+                 hash,
+                 slot_created,
+                 tx_signature,
+             }| {
+                let extensions = token_data.extensions.unwrap_or_default();
+                let metadata_pointer = extensions.metadata_pointer.unwrap_or_default();
+                let (tlv, tlv_compressed) = match token_data.tlv {
+                    Some(tlv) => {
+                        let (tlv, tlv_compressed) = compress_account_data(tlv.0);
+                        (Some(tlv), tlv_compressed)
+                    }
+                    None => (None, false),
+                };
+                let spend = recorded_spends.get(&hash.to_vec());
+                token_accounts::ActiveModel {
+                    hash: Set(hash.into()),
+                    mint: Set(token_data.mint.to_bytes_vec()),
+                    owner: Set(token_data.owner.to_bytes_vec()),
+                    amount: Set(Decimal::from(token_data.amount.0)),
+                    delegate: Set(token_data.delegate.map(|d| d.to_bytes_vec())),
+                    state: Set(token_data.state as i32),
+                    spent: Set(spend.is_some()),
+                    prev_spent: Set(spend.map(|_| false)),
+                    tlv: Set(tlv),
+                    tlv_compressed: Set(tlv_compressed),
+                    withheld_transfer_fee: Set(extensions
+                        .withheld_transfer_fee
+                        .map(|amount| Decimal::from(amount.0))),
+                    permanent_delegate: Set(extensions
+                        .permanent_delegate
+                        .map(|d| d.to_bytes_vec())),
+                    metadata_pointer_authority: Set(metadata_pointer
+                        .authority
+                        .map(|a| a.to_bytes_vec())),
+                    metadata_pointer_address: Set(metadata_pointer
+                        .metadata_address
+                        .map(|a| a.to_bytes_vec())),
+                    slot_created: Set(slot_created as i64),
+                    tx_signature: Set(
+                        tx_signature.map(|signature| Into::<[u8; 64]>::into(signature).to_vec())
+                    ),
+                    spent_tx_signature: Set(spend.and_then(|spend| spend.tx_signature.clone())),
+                    slot_spent: Set(spend.and_then(|spend| spend.slot)),
+                }
             },
         )
         .collect::<Vec<_>>();
 
-    let query = token_accounts::Entity::insert_many(token_models)
-        .on_conflict(
-            OnConflict::column(token_accounts::Column::Hash)
-                .do_nothing()
-                .to_owned(),
-        )
-        .build(txn.get_database_backend());
+    let query = build_bulk_insert(
+        txn.get_database_backend(),
+        token_models,
+        OnConflict::column(token_accounts::Column::Hash)
+            .do_nothing()
+            .to_owned(),
+    );
 
     execute_account_update_query_and_update_balances(
         txn,
@@ -482,6 +823,46 @@ pub async fn persist_token_accounts(
     Ok(())
 }
 
+/// Looks up the spends of the given accounts that are already recorded, either in the account
+/// transactions of blocks that were indexed before the blocks creating the accounts, or as
+/// tombstones of pruned accounts. Accounts with a recorded spend are inserted as spent, so that
+/// indexing blocks out of order cannot resurrect them.
+async fn fetch_recorded_spends(
+    txn: &DatabaseTransaction,
+    hashes: Vec<Vec<u8>>,
+) -> Result<HashMap<Vec<u8>, RecordedSpend>, IngesterError> {
+    let mut recorded_spends = HashMap::new();
+    let pruned_accounts = pruned_accounts::Entity::find()
+        .filter(pruned_accounts::Column::Hash.is_in(hashes.clone()))
+        .all(txn)
+        .await?;
+    for pruned_account in pruned_accounts {
+        recorded_spends.insert(
+            pruned_account.hash,
+            RecordedSpend {
+                slot: pruned_account.slot_spent,
+                tx_signature: pruned_account.spent_tx_signature,
+            },
+        );
+    }
+    let spends = account_transactions::Entity::find()
+        .filter(account_transactions::Column::Hash.is_in(hashes))
+        .filter(account_transactions::Column::Spent.eq(true))
+        .order_by_asc(account_transactions::Column::Slot)
+        .all(txn)
+        .await?;
+    for spend in spends {
+        recorded_spends.insert(
+            spend.hash,
+            RecordedSpend {
+                slot: Some(spend.slot),
+                tx_signature: Some(spend.signature),
+            },
+        );
+    }
+    Ok(recorded_spends)
+}
+
 fn get_node_direct_ancestors(leaf_index: i64) -> Vec<i64> {
     let mut path: Vec<i64> = Vec::new();
     let mut current_index = leaf_index;
@@ -511,6 +892,8 @@ async fn persist_transactions(
             slot: Set(transaction.slot as i64),
             uses_compression: Set(transaction.uses_compression),
             error: Set(transaction.error.clone()),
+            compressed_lamports: Set(Decimal::from(transaction.compressed_lamports)),
+            decompressed_lamports: Set(Decimal::from(transaction.decompressed_lamports)),
         })
         .collect::<Vec<_>>();
 
@@ -518,13 +901,13 @@ async fn persist_transactions(
         // We first build the query and then execute it because SeaORM has a bug where it always throws
         // an error if we do not insert a record in an insert statement. However, in this case, it's
         // expected not to insert anything if the key already exists.
-        let query = transactions::Entity::insert_many(transaction_models)
-            .on_conflict(
-                OnConflict::columns([transactions::Column::Signature])
-                    .do_nothing()
-                    .to_owned(),
-            )
-            .build(txn.get_database_backend());
+        let query = build_bulk_insert(
+            txn.get_database_backend(),
+            transaction_models,
+            OnConflict::columns([transactions::Column::Signature])
+                .do_nothing()
+                .to_owned(),
+        );
         txn.execute(query).await?;
     }
 
@@ -549,6 +932,37 @@ async fn persist_transactions(
     Ok(())
 }
 
+/// Returns the given signatures that are already in the transactions table. Every compression
+/// transaction is persisted together with its state, so this tells which transactions must not be
+/// applied again.
+pub async fn fetch_persisted_signatures(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    signatures: &[Signature],
+) -> Result<HashSet<Signature>, IngesterError> {
+    let mut persisted_signatures = HashSet::new();
+    for chunk in signatures.chunks(batch_config.batch_size(1)) {
+        let transaction_models = transactions::Entity::find()
+            .filter(
+                transactions::Column::Signature.is_in(
+                    chunk
+                        .iter()
+                        .map(|signature| signature.as_ref().to_vec())
+                        .collect::<Vec<_>>(),
+                ),
+            )
+            .all(txn)
+            .await?;
+        for transaction in transaction_models {
+            let signature = Signature::try_from(transaction.signature.as_slice()).map_err(|e| {
+                IngesterError::DatabaseError(format!("Invalid persisted signature: {}", e))
+            })?;
+            persisted_signatures.insert(signature);
+        }
+    }
+    Ok(persisted_signatures)
+}
+
 async fn persist_account_transactions(
     txn: &DatabaseTransaction,
     account_transactions: &[AccountTransaction],
@@ -558,6 +972,8 @@ async fn persist_account_transactions(
         .map(|transaction| account_transactions::ActiveModel {
             hash: Set(transaction.hash.to_vec()),
             signature: Set(Into::<[u8; 64]>::into(transaction.signature).to_vec()),
+            slot: Set(transaction.slot as i64),
+            spent: Set(transaction.spent),
         })
         .collect::<Vec<_>>();
 
@@ -565,21 +981,24 @@ async fn persist_account_transactions(
         // We first build the query and then execute it because SeaORM has a bug where it always throws
         // an error if we do not insert a record in an insert statement. However, in this case, it's
         // expected not to insert anything if the key already exists.
-        let query = account_transactions::Entity::insert_many(account_transaction_models)
-            .on_conflict(
-                OnConflict::columns([
-                    account_transactions::Column::Hash,
-                    account_transactions::Column::Signature,
-                ])
-                .do_nothing()
-                .to_owned(),
-            )
-            .build(txn.get_database_backend());
+        let query = build_bulk_insert(
+            txn.get_database_backend(),
+            account_transaction_models,
+            OnConflict::columns([
+                account_transactions::Column::Hash,
+                account_transactions::Column::Signature,
+            ])
+            .do_nothing()
+            .to_owned(),
+        );
         txn.execute(query).await.map_err(|e| {
-            IngesterError::DatabaseError(format!(
-                "Failed to persist account transactions: {:?}. Error {}",
-                account_transactions, e
-            ))
+            IngesterError::database(
+                format!(
+                    "Failed to persist account transactions: {:?}",
+                    account_transactions
+                ),
+                e,
+            )
         })?;
     }
 
@@ -0,0 +1,286 @@
+use std::{
+    cmp::max,
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use itertools::Itertools;
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait,
+    QueryFilter, QueryOrder, QueryTrait, Set,
+};
+
+use crate::{
+    api::error::PhotonApiError,
+    common::typedefs::{hash::Hash, serializable_pubkey::SerializablePubkey},
+    dao::generated::{accounts, state_tree_node_histories, state_trees},
+    ingester::tree_info::tree_height,
+};
+
+use super::{
+    batch_size::SqlBatchConfig,
+    compute_parent_hash,
+    error::IngesterError,
+    get_node_direct_ancestors,
+    persisted_state_tree::{
+        find_nodes, get_proof_path, leaf_index_to_node_index, validate_proof, LeafNode,
+        MerkleProofWithContext, ZERO_BYTES,
+    },
+};
+
+type NodeLocation = (Vec<u8>, i64);
+
+static PROOF_HISTORY_WINDOW: AtomicU64 = AtomicU64::new(0);
+
+/// Keeps the versions of state tree nodes that were replaced within the last `window` sequence
+/// numbers of their tree, so that proofs against these older roots can still be assembled. 0
+/// disables the history.
+pub fn set_proof_history_window(window: u64) {
+    PROOF_HISTORY_WINDOW.store(window, Ordering::SeqCst);
+}
+
+pub fn proof_history_window() -> u64 {
+    PROOF_HISTORY_WINDOW.load(Ordering::SeqCst)
+}
+
+/// Records the versions of the nodes that persisting `leaf_nodes` replaces, and prunes the
+/// versions that fell out of the window. Has to run before the leaves are persisted. The leaves
+/// are applied one after the other, so that the versions which a batch writes and replaces itself
+/// are recorded as well.
+pub async fn persist_node_histories(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    leaf_nodes: &[LeafNode],
+) -> Result<(), IngesterError> {
+    let window = proof_history_window();
+    if window == 0 || leaf_nodes.is_empty() {
+        return Ok(());
+    }
+    let leaf_nodes = leaf_nodes
+        .iter()
+        .sorted_by_key(|leaf_node| leaf_node.seq)
+        .collect::<Vec<_>>();
+    let node_locations = leaf_nodes
+        .iter()
+        .flat_map(|leaf_node| {
+            let tree = leaf_node.tree.to_bytes_vec();
+            let node_index = leaf_node.node_index(tree_height(&leaf_node.tree.0));
+            get_proof_path(node_index, true)
+                .into_iter()
+                .chain(get_node_direct_ancestors(node_index))
+                .map(move |node_idx| (tree.clone(), node_idx))
+        })
+        .unique()
+        .collect::<Vec<NodeLocation>>();
+    let mut nodes = find_nodes(txn, batch_config, &node_locations)
+        .await?
+        .into_iter()
+        .map(|node| ((node.tree, node.node_idx), (node.hash, node.seq)))
+        .collect::<HashMap<NodeLocation, (Vec<u8>, i64)>>();
+
+    let mut histories = Vec::new();
+    let mut last_seqs = HashMap::new();
+    for leaf_node in leaf_nodes {
+        let tree = leaf_node.tree.to_bytes_vec();
+        let height = tree_height(&leaf_node.tree.0);
+        let mut node_idx = leaf_node.node_index(height);
+        let mut hash = leaf_node.hash.to_vec();
+        let mut seq = leaf_node.seq as i64;
+        // Same as in `persist_leaf_nodes`, leaves are not overwritten by older versions.
+        if nodes
+            .get(&(tree.clone(), node_idx))
+            .is_some_and(|(_, leaf_seq)| *leaf_seq > seq)
+        {
+            continue;
+        }
+        last_seqs
+            .entry(tree.clone())
+            .and_modify(|last_seq| *last_seq = max(*last_seq, seq))
+            .or_insert(seq);
+        for zero_bytes in ZERO_BYTES.iter().take(height as usize) {
+            let replaced = nodes.insert((tree.clone(), node_idx), (hash.clone(), seq));
+            match replaced {
+                Some((_, replaced_seq)) if replaced_seq >= seq => {}
+                _ => histories.push(state_tree_node_histories::ActiveModel {
+                    tree: Set(tree.clone()),
+                    node_idx: Set(node_idx),
+                    seq: Set(seq),
+                    hash: Set(replaced.as_ref().map(|(hash, _)| hash.clone())),
+                    prev_seq: Set(replaced.map(|(_, seq)| seq)),
+                }),
+            }
+            if node_idx == 1 {
+                break;
+            }
+            let (sibling_hash, sibling_seq) = nodes
+                .get(&(tree.clone(), node_idx ^ 1))
+                .cloned()
+                .unwrap_or((zero_bytes.to_vec(), 0));
+            hash = match node_idx % 2 {
+                0 => compute_parent_hash(hash, sibling_hash)?,
+                _ => compute_parent_hash(sibling_hash, hash)?,
+            };
+            seq = max(seq, sibling_seq);
+            node_idx /= 2;
+        }
+    }
+
+    let batch_size = batch_config.insert_batch_size::<state_tree_node_histories::Entity>();
+    for chunk in histories.chunks(batch_size) {
+        // Blocks that are indexed again already have their versions recorded.
+        let query = state_tree_node_histories::Entity::insert_many(chunk.iter().cloned())
+            .on_conflict(
+                OnConflict::columns([
+                    state_tree_node_histories::Column::Tree,
+                    state_tree_node_histories::Column::NodeIdx,
+                    state_tree_node_histories::Column::Seq,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .build(txn.get_database_backend());
+        txn.execute(query)
+            .await
+            .map_err(|e| IngesterError::database("Failed to persist node histories", e))?;
+    }
+    for (tree, last_seq) in last_seqs {
+        let query = state_tree_node_histories::Entity::delete_many()
+            .filter(state_tree_node_histories::Column::Tree.eq(tree))
+            .filter(state_tree_node_histories::Column::Seq.lte(last_seq - window as i64))
+            .build(txn.get_database_backend());
+        txn.execute(query).await?;
+    }
+    Ok(())
+}
+
+/// Returns the proof of the account with hash `hash` against the root of its tree with sequence
+/// number `root_seq`, also if the account has been spent since. Fails if the root is older than
+/// the proof history window.
+pub async fn get_historical_compressed_leaf_proof(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    hash: Hash,
+    root_seq: u64,
+) -> Result<MerkleProofWithContext, PhotonApiError> {
+    if proof_history_window() == 0 {
+        return Err(PhotonApiError::ValidationError(
+            "Proof history is disabled".to_string(),
+        ));
+    }
+    let account = accounts::Entity::find()
+        .filter(accounts::Column::Hash.eq(hash.to_vec()))
+        .one(txn)
+        .await?
+        .ok_or(PhotonApiError::RecordNotFound(format!(
+            "Account not found for hash: {}",
+            hash
+        )))?;
+    let tree = SerializablePubkey::try_from(account.tree.clone())?;
+    let leaf_index = account.leaf_index as u32;
+    let node_index = leaf_index_to_node_index(leaf_index, tree_height(&tree.0));
+    let root_seq = root_seq as i64;
+
+    let mut node_indices = get_proof_path(node_index, true);
+    let current_nodes = find_nodes(
+        txn,
+        batch_config,
+        &node_indices
+            .iter()
+            .map(|node_idx| (account.tree.clone(), *node_idx))
+            .collect::<Vec<_>>(),
+    )
+    .await?
+    .into_iter()
+    .map(|node| (node.node_idx, node))
+    .collect::<HashMap<i64, state_trees::Model>>();
+    let current_root_seq = current_nodes.get(&1).map_or(0, |root| root.seq);
+    if root_seq > current_root_seq {
+        return Err(PhotonApiError::ValidationError(format!(
+            "Root seq {} of tree {} has not been indexed yet",
+            root_seq, tree
+        )));
+    }
+
+    let leaf_node_index = node_indices.remove(0);
+    let leaf_hash = find_node_hash_at(
+        txn,
+        &account.tree,
+        leaf_node_index,
+        0,
+        current_nodes.get(&leaf_node_index),
+        root_seq,
+    )
+    .await?;
+    if leaf_hash != hash.to_vec() {
+        return Err(PhotonApiError::RecordNotFound(format!(
+            "Account {} is not in tree {} at root seq {}",
+            hash, tree, root_seq
+        )));
+    }
+    let mut proof = Vec::with_capacity(node_indices.len());
+    for (level, node_idx) in node_indices.into_iter().enumerate() {
+        let node_hash = find_node_hash_at(
+            txn,
+            &account.tree,
+            node_idx,
+            level,
+            current_nodes.get(&node_idx),
+            root_seq,
+        )
+        .await?;
+        proof.push(Hash::try_from(node_hash)?);
+    }
+    let root = proof.pop().ok_or(PhotonApiError::UnexpectedError(
+        "Root node not found in proof".to_string(),
+    ))?;
+
+    let proof = MerkleProofWithContext {
+        proof,
+        root,
+        leafIndex: leaf_index,
+        hash,
+        merkleTree: tree,
+        rootSeq: root_seq as u64,
+    };
+    validate_proof(&proof)?;
+    Ok(proof)
+}
+
+/// Returns the hash that the node had at `root_seq`, given its current version `current_node`.
+async fn find_node_hash_at(
+    txn: &DatabaseTransaction,
+    tree: &[u8],
+    node_idx: i64,
+    level: usize,
+    current_node: Option<&state_trees::Model>,
+    root_seq: i64,
+) -> Result<Vec<u8>, PhotonApiError> {
+    if let Some(node) = current_node.filter(|node| node.seq <= root_seq) {
+        return Ok(node.hash.clone());
+    }
+    // The first version that was replaced after `root_seq` is the version at `root_seq`.
+    let history = state_tree_node_histories::Entity::find()
+        .filter(state_tree_node_histories::Column::Tree.eq(tree.to_vec()))
+        .filter(state_tree_node_histories::Column::NodeIdx.eq(node_idx))
+        .filter(state_tree_node_histories::Column::Seq.gt(root_seq))
+        .order_by_asc(state_tree_node_histories::Column::Seq)
+        .one(txn)
+        .await?;
+    match (current_node, history) {
+        (None, None) => Ok(ZERO_BYTES[level].to_vec()),
+        (_, Some(history)) => match (history.hash, history.prev_seq) {
+            (None, _) => Ok(ZERO_BYTES[level].to_vec()),
+            (Some(hash), Some(prev_seq)) if prev_seq <= root_seq => Ok(hash),
+            _ => Err(history_unavailable(root_seq)),
+        },
+        // The node was replaced after `root_seq`, but the replaced version was not recorded.
+        (Some(_), None) => Err(history_unavailable(root_seq)),
+    }
+}
+
+fn history_unavailable(root_seq: i64) -> PhotonApiError {
+    PhotonApiError::ValidationError(format!(
+        "Root seq {} is outside of the proof history window",
+        root_seq
+    ))
+}
@@ -0,0 +1,91 @@
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, Condition, ConnectionTrait, DatabaseTransaction,
+    EntityTrait, QueryFilter, QuerySelect, QueryTrait, Set,
+};
+
+use crate::{
+    common::typedefs::hash::Hash,
+    dao::generated::{accounts, nullifier_queue_elements},
+    ingester::error::IngesterError,
+};
+
+use super::persisted_state_tree::{LeafNode, ZERO_BYTES};
+
+/// Queues the spent accounts among `hashes`. Spent accounts stay in the nullifier queue of their
+/// tree until a forester nullifies their leaf, i.e. overwrites it with the zero value. Accounts
+/// are looked up in the accounts table, since the queue is keyed by the leaf they occupy.
+pub async fn enqueue_spent_accounts(
+    txn: &DatabaseTransaction,
+    hashes: &[Vec<u8>],
+) -> Result<(), IngesterError> {
+    let elements = accounts::Entity::find()
+        .select_only()
+        .column(accounts::Column::Hash)
+        .column(accounts::Column::Tree)
+        .column(accounts::Column::LeafIndex)
+        .filter(accounts::Column::Hash.is_in(hashes.to_vec()))
+        .filter(accounts::Column::Spent.eq(true))
+        .into_model::<nullifier_queue_elements::Model>()
+        .all(txn)
+        .await?;
+    if elements.is_empty() {
+        return Ok(());
+    }
+    let query =
+        nullifier_queue_elements::Entity::insert_many(elements.into_iter().map(|element| {
+            nullifier_queue_elements::ActiveModel {
+                hash: Set(element.hash),
+                tree: Set(element.tree),
+                leaf_index: Set(element.leaf_index),
+            }
+        }))
+        .on_conflict(
+            OnConflict::column(nullifier_queue_elements::Column::Hash)
+                .do_nothing()
+                .to_owned(),
+        )
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    Ok(())
+}
+
+/// Removes the given accounts from the nullifier queues, e.g. because their spend was rolled back.
+pub async fn dequeue_accounts(
+    txn: &DatabaseTransaction,
+    hashes: &[Vec<u8>],
+) -> Result<(), IngesterError> {
+    let query = nullifier_queue_elements::Entity::delete_many()
+        .filter(nullifier_queue_elements::Column::Hash.is_in(hashes.to_vec()))
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    Ok(())
+}
+
+/// Removes the accounts whose leaves are overwritten with the zero value by `leaf_nodes`. Leaves
+/// are nullified by V1 changelog events as well as by the nullifier events of batched trees.
+pub async fn dequeue_nullified_leaves(
+    txn: &DatabaseTransaction,
+    leaf_nodes: &[LeafNode],
+) -> Result<(), IngesterError> {
+    let zero_hash = Hash::from(ZERO_BYTES[0]);
+    let condition = leaf_nodes
+        .iter()
+        .filter(|leaf_node| leaf_node.hash == zero_hash)
+        .fold(Condition::any(), |condition, leaf_node| {
+            condition.add(
+                Condition::all()
+                    .add(nullifier_queue_elements::Column::Tree.eq(leaf_node.tree.to_bytes_vec()))
+                    .add(
+                        nullifier_queue_elements::Column::LeafIndex.eq(leaf_node.leaf_index as i64),
+                    ),
+            )
+        });
+    if condition.is_empty() {
+        return Ok(());
+    }
+    let query = nullifier_queue_elements::Entity::delete_many()
+        .filter(condition)
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    Ok(())
+}
@@ -27,12 +27,12 @@ use lazy_static::lazy_static;
 use light_poseidon::PoseidonBytesHasher;
 
 use super::{
+    batch_size::SqlBatchConfig,
     compute_parent_hash,
     persisted_state_tree::{
         get_multiple_compressed_leaf_proofs_from_full_leaf_info, persist_leaf_nodes,
-        validate_proof, LeafNode, MerkleProofWithContext, ZERO_BYTES,
+        validate_proof, zero_hashes, LeafNode, MerkleProofWithContext, ZERO_BYTES,
     },
-    MAX_SQL_INSERTS,
 };
 
 lazy_static! {
@@ -84,6 +84,7 @@ fn get_top_element(tree: Vec<u8>) -> indexed_trees::Model {
 
 pub async fn get_exclusion_range_with_proof(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     tree: Vec<u8>,
     tree_height: u32,
     value: Vec<u8>,
@@ -106,12 +107,14 @@ pub async fn get_exclusion_range_with_proof(
             PhotonApiError::UnexpectedError(format!("Failed to compute hash: {}", e))
         })?;
         let mut proof: Vec<Hash> = vec![top_element_hash.clone()];
-        for i in 1..(tree_height - 1) {
-            let hash = Hash::try_from(ZERO_BYTES[i as usize]).map_err(|e| {
-                PhotonApiError::UnexpectedError(format!("Failed to convert hash: {}", e))
-            })?;
-            proof.push(hash);
-        }
+        // All other leaves are empty, so the rest of the proof are hashes of empty subtrees.
+        let zero_hashes = zero_hashes(tree_height)?;
+        proof.extend(
+            zero_hashes[1..zero_hashes.len() - 1]
+                .iter()
+                .copied()
+                .map(Hash::from),
+        );
         let mut root = zeroeth_element_hash.clone().to_vec();
 
         for elem in proof.iter() {
@@ -153,31 +156,35 @@ pub async fn get_exclusion_range_with_proof(
     let node_index = leaf_node.node_index(tree_height);
 
     let leaf_proofs: Vec<MerkleProofWithContext> =
-        get_multiple_compressed_leaf_proofs_from_full_leaf_info(txn, vec![(leaf_node, node_index)])
-            .await
-            .map_err(|proof_error| {
-                let tree_pubkey = match SerializablePubkey::try_from(range_node.tree.clone()) {
-                    Ok(pubkey) => pubkey,
-                    Err(e) => {
-                        log::error!("Failed to serialize tree pubkey: {}", e);
-                        return proof_error;
-                    }
-                };
-                let value_pubkey = match SerializablePubkey::try_from(range_node.value.clone()) {
-                    Ok(pubkey) => pubkey,
-                    Err(e) => {
-                        log::error!("Failed to serialize value pubkey: {}", e);
-                        return proof_error;
-                    }
-                };
-                log::error!(
-                    "Failed to get multiple compressed leaf proofs for {:?} for value {:?}: {}",
-                    tree_pubkey,
-                    value_pubkey,
-                    proof_error
-                );
+        get_multiple_compressed_leaf_proofs_from_full_leaf_info(
+            txn,
+            batch_config,
+            vec![(leaf_node, node_index)],
+        )
+        .await
+        .map_err(|proof_error| {
+            let tree_pubkey = match SerializablePubkey::try_from(range_node.tree.clone()) {
+                Ok(pubkey) => pubkey,
+                Err(e) => {
+                    log::error!("Failed to serialize tree pubkey: {}", e);
+                    return proof_error;
+                }
+            };
+            let value_pubkey = match SerializablePubkey::try_from(range_node.value.clone()) {
+                Ok(pubkey) => pubkey,
+                Err(e) => {
+                    log::error!("Failed to serialize value pubkey: {}", e);
+                    return proof_error;
+                }
+            };
+            log::error!(
+                "Failed to get multiple compressed leaf proofs for {:?} for value {:?}: {}",
+                tree_pubkey,
+                value_pubkey,
                 proof_error
-            })?;
+            );
+            proof_error
+        })?;
 
     let leaf_proof = leaf_proofs
         .into_iter()
@@ -191,6 +198,7 @@ pub async fn get_exclusion_range_with_proof(
 
 pub async fn update_indexed_tree_leaves(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     mut indexed_leaf_updates: HashMap<(Pubkey, u64), IndexedTreeLeafUpdate>,
     tree_height: u32,
 ) -> Result<(), IngesterError> {
@@ -232,7 +240,7 @@ pub async fn update_indexed_tree_leaves(
     }
     let chunks = indexed_leaf_updates
         .values()
-        .chunks(MAX_SQL_INSERTS)
+        .chunks(batch_config.insert_batch_size::<indexed_trees::Entity>())
         .into_iter()
         .map(|x| x.collect_vec())
         .collect_vec();
@@ -265,9 +273,9 @@ pub async fn update_indexed_tree_leaves(
 
         query.sql = format!("{} WHERE excluded.seq >= indexed_trees.seq", query.sql);
 
-        txn.execute(query).await.map_err(|e| {
-            IngesterError::DatabaseError(format!("Failed to insert indexed tree elements: {}", e))
-        })?;
+        txn.execute(query)
+            .await
+            .map_err(|e| IngesterError::database("Failed to insert indexed tree elements", e))?;
 
         let state_tree_leaf_nodes = chunk
             .iter()
@@ -285,7 +293,7 @@ pub async fn update_indexed_tree_leaves(
             })
             .collect::<Result<Vec<LeafNode>, IngesterError>>()?;
 
-        persist_leaf_nodes(txn, state_tree_leaf_nodes, tree_height).await?;
+        persist_leaf_nodes(txn, batch_config, state_tree_leaf_nodes, tree_height).await?;
     }
 
     Ok(())
@@ -293,6 +301,7 @@ pub async fn update_indexed_tree_leaves(
 
 pub async fn multi_append(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     values: Vec<Vec<u8>>,
     tree: Vec<u8>,
     tree_height: u32,
@@ -303,9 +312,7 @@ pub async fn multi_append(
             "LOCK TABLE indexed_trees IN EXCLUSIVE MODE;".to_string(),
         ))
         .await
-        .map_err(|e| {
-            IngesterError::DatabaseError(format!("Failed to lock state_trees table: {}", e))
-        })?;
+        .map_err(|e| IngesterError::database("Failed to lock state_trees table", e))?;
     }
 
     let index_stmt = Statement::from_string(
@@ -316,9 +323,10 @@ pub async fn multi_append(
             format_bytes(tree.clone(), txn.get_database_backend())
         ),
     );
-    let max_index = txn.query_one(index_stmt).await.map_err(|e| {
-        IngesterError::DatabaseError(format!("Failed to execute max index query: {}", e))
-    })?;
+    let max_index = txn
+        .query_one(index_stmt)
+        .await
+        .map_err(|e| IngesterError::database("Failed to execute max index query", e))?;
 
     let mut current_index = match max_index {
         Some(row) => row.try_get("", "leaf_index").unwrap_or(0),
@@ -392,9 +400,7 @@ pub async fn multi_append(
         )
         .exec(txn)
         .await
-        .map_err(|e| {
-            IngesterError::DatabaseError(format!("Failed to insert indexed tree elements: {}", e))
-        })?;
+        .map_err(|e| IngesterError::database("Failed to insert indexed tree elements", e))?;
 
     let leaf_nodes = elements_to_update
         .values()
@@ -410,7 +416,7 @@ pub async fn multi_append(
         })
         .collect::<Result<Vec<LeafNode>, IngesterError>>()?;
 
-    persist_leaf_nodes(txn, leaf_nodes, tree_height).await?;
+    persist_leaf_nodes(txn, batch_config, leaf_nodes, tree_height).await?;
 
     Ok(())
 }
@@ -441,9 +447,7 @@ where
                     full_query,
                 ))
                 .await
-                .map_err(|e| {
-                    IngesterError::DatabaseError(format!("Failed to execute indexed query: {}", e))
-                })?
+                .map_err(|e| IngesterError::database("Failed to execute indexed query", e))?
         }
         DatabaseBackend::Sqlite => {
             let mut response = vec![];
@@ -460,12 +464,7 @@ where
                         full_query,
                     ))
                     .await
-                    .map_err(|e| {
-                        IngesterError::DatabaseError(format!(
-                            "Failed to execute indexed query: {}",
-                            e
-                        ))
-                    })?;
+                    .map_err(|e| IngesterError::database("Failed to execute indexed query", e))?;
                 response.extend(result);
             }
             response
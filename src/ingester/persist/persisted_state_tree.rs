@@ -1,23 +1,39 @@
-use std::{cmp::max, collections::HashMap};
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet},
+};
 
 use cadence_macros::statsd_count;
+use futures::future::try_join_all;
 use itertools::Itertools;
 use sea_orm::{
-    sea_query::OnConflict, ColumnTrait, ConnectionTrait, DatabaseTransaction, DbErr, EntityTrait,
-    QueryFilter, QueryTrait, Set, Statement, TransactionTrait, Value,
+    sea_query::OnConflict, ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection,
+    DatabaseTransaction, DbErr, EntityTrait, QueryFilter, QueryTrait, Set, Statement,
+    TransactionTrait, Value,
 };
 use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
 use utoipa::ToSchema;
 
 use crate::{
-    api::error::PhotonApiError,
+    api::{
+        error::PhotonApiError,
+        proof_cache::{cache_proofs, get_cached_proof, proof_cache_enabled},
+    },
     common::typedefs::{account::Account, hash::Hash, serializable_pubkey::SerializablePubkey},
     dao::generated::state_trees,
-    ingester::{error::IngesterError, parser::state_update::LeafNullification},
+    ingester::{
+        error::IngesterError,
+        parser::state_update::{LeafNullification, PathUpdate},
+        tree_info::tree_height,
+    },
     metric,
 };
 
-use super::{compute_parent_hash, get_node_direct_ancestors};
+use super::{
+    batch_size::SqlBatchConfig, compute_parent_hash, get_node_direct_ancestors,
+    in_memory_tree::InMemoryTrees,
+};
 
 #[derive(Clone, Debug)]
 pub struct LeafNode {
@@ -33,10 +49,39 @@ impl LeafNode {
     }
 }
 
-fn leaf_index_to_node_index(leaf_index: u32, tree_height: u32) -> i64 {
+pub fn leaf_index_to_node_index(leaf_index: u32, tree_height: u32) -> i64 {
     2_i64.pow(tree_height - 1) + leaf_index as i64
 }
 
+/// Returns the leaf index of the node with index `node_idx`, or `None` if the node is not a leaf
+/// of a tree with `tree_height` levels.
+pub fn node_idx_to_leaf_idx(node_idx: i64, tree_height: u32) -> Option<u32> {
+    let first_leaf_node_idx = 2_i64.pow(tree_height - 1);
+    match node_idx >= first_leaf_node_idx && node_idx < 2 * first_leaf_node_idx {
+        true => Some((node_idx - first_leaf_node_idx) as u32),
+        false => None,
+    }
+}
+
+/// Returns the number of distinct nodes, leaves included, on the paths from the given leaves to
+/// the roots of their trees. This is the number of nodes that persisting the leaves writes.
+pub fn count_path_nodes(
+    leaves: impl IntoIterator<Item = (SerializablePubkey, u32)>,
+    tree_height: u32,
+) -> usize {
+    let mut nodes = HashSet::new();
+    for (tree, leaf_index) in leaves {
+        let node_index = leaf_index_to_node_index(leaf_index, tree_height);
+        nodes.insert((tree, node_index));
+        nodes.extend(
+            get_node_direct_ancestors(node_index)
+                .into_iter()
+                .map(|ancestor| (tree, ancestor)),
+        );
+    }
+    nodes.len()
+}
+
 impl From<Account> for LeafNode {
     fn from(account: Account) -> Self {
         Self {
@@ -59,8 +104,35 @@ impl From<LeafNullification> for LeafNode {
     }
 }
 
+impl TryFrom<PathUpdate> for LeafNode {
+    type Error = IngesterError;
+
+    fn try_from(path_update: PathUpdate) -> Result<Self, Self::Error> {
+        // The path runs from the leaf to the root, so its length is the height of the tree.
+        let tree_height = tree_height(&Pubkey::new_from_array(path_update.tree));
+        if path_update.path.len() != tree_height as usize {
+            return Err(IngesterError::ParserError(format!(
+                "Expected a Merkle path of length {}, got {}",
+                tree_height,
+                path_update.path.len()
+            )));
+        }
+        let leaf = &path_update.path[0];
+        let leaf_index = node_idx_to_leaf_idx(leaf.index as i64, tree_height).ok_or_else(|| {
+            IngesterError::ParserError(format!("Invalid leaf node index {}", leaf.index))
+        })?;
+        Ok(Self {
+            tree: SerializablePubkey::from(path_update.tree),
+            leaf_index,
+            hash: Hash::from(leaf.node),
+            seq: path_update.seq as u32,
+        })
+    }
+}
+
 pub async fn persist_leaf_nodes(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     mut leaf_nodes: Vec<LeafNode>,
     tree_height: u32,
 ) -> Result<(), IngesterError> {
@@ -75,7 +147,7 @@ pub async fn persist_leaf_nodes(
         .map(|node| (node.tree.to_bytes_vec(), node.node_index(tree_height)))
         .collect::<Vec<_>>();
 
-    let node_locations_to_models = get_proof_nodes(txn, leaf_locations, true).await?;
+    let node_locations_to_models = get_proof_nodes(txn, batch_config, leaf_locations, true).await?;
     let mut node_locations_to_hashes_and_seq = node_locations_to_models
         .iter()
         .map(|(key, value)| (key.clone(), (value.hash.clone(), value.seq)))
@@ -156,20 +228,25 @@ pub async fn persist_leaf_nodes(
         node_locations_to_hashes_and_seq.insert(key, (hash, seq));
     }
 
-    // We first build the query and then execute it because SeaORM has a bug where it always throws
-    // an error if we do not insert a record in an insert statement. However, in this case, it's
-    // expected not to insert anything if the key already exists.
-    let mut query = state_trees::Entity::insert_many(models_to_updates.into_values())
-        .on_conflict(
-            OnConflict::columns([state_trees::Column::Tree, state_trees::Column::NodeIdx])
-                .update_columns([state_trees::Column::Hash, state_trees::Column::Seq])
-                .to_owned(),
-        )
-        .build(txn.get_database_backend());
-    query.sql = format!("{} WHERE excluded.seq >= state_trees.seq", query.sql);
-    txn.execute(query).await.map_err(|e| {
-        IngesterError::DatabaseError(format!("Failed to persist path nodes: {}", e))
-    })?;
+    // Every leaf can update a node on each level, so the nodes are chunked separately from the
+    // leaves.
+    let models = models_to_updates.into_values().collect_vec();
+    for chunk in models.chunks(batch_config.insert_batch_size::<state_trees::Entity>()) {
+        // We first build the query and then execute it because SeaORM has a bug where it always
+        // throws an error if we do not insert a record in an insert statement. However, in this
+        // case, it's expected not to insert anything if the key already exists.
+        let mut query = state_trees::Entity::insert_many(chunk.iter().cloned())
+            .on_conflict(
+                OnConflict::columns([state_trees::Column::Tree, state_trees::Column::NodeIdx])
+                    .update_columns([state_trees::Column::Hash, state_trees::Column::Seq])
+                    .to_owned(),
+            )
+            .build(txn.get_database_backend());
+        query.sql = format!("{} WHERE excluded.seq >= state_trees.seq", query.sql);
+        txn.execute(query)
+            .await
+            .map_err(|e| IngesterError::database("Failed to persist path nodes", e))?;
+    }
     Ok(())
 }
 
@@ -187,6 +264,8 @@ pub struct MerkleProofWithContext {
 
 pub async fn get_multiple_compressed_leaf_proofs(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
     hashes: Vec<Hash>,
 ) -> Result<Vec<MerkleProofWithContext>, PhotonApiError> {
     if hashes.is_empty() {
@@ -194,13 +273,185 @@ pub async fn get_multiple_compressed_leaf_proofs(
             "No hashes provided".to_string(),
         ));
     }
+    let leaf_nodes_with_node_index = find_leaf_nodes(txn, &hashes).await?;
+    let hash_to_leaf_node_with_node_index = leaf_nodes_with_node_index
+        .iter()
+        .map(|(leaf_node, node_index)| (leaf_node.hash.clone(), (leaf_node.clone(), *node_index)))
+        .collect::<HashMap<Hash, (LeafNode, i64)>>();
+
+    let leaf_nodes_with_node_index = hashes
+        .into_iter()
+        .map(|hash| {
+            hash_to_leaf_node_with_node_index
+                .get(&hash)
+                .ok_or(PhotonApiError::RecordNotFound(format!(
+                    "Leaf node not found for hash: {}",
+                    hash
+                )))
+                .cloned()
+        })
+        .collect::<Result<Vec<(LeafNode, i64)>, PhotonApiError>>()?;
+
+    if !proof_cache_enabled() {
+        return get_multiple_compressed_leaf_proofs_from_memory(
+            txn,
+            batch_config,
+            in_memory_trees,
+            leaf_nodes_with_node_index,
+        )
+        .await;
+    }
+    let roots = find_roots(
+        txn,
+        leaf_nodes_with_node_index
+            .iter()
+            .map(|(leaf_node, _)| leaf_node.tree)
+            .unique()
+            .collect(),
+    )
+    .await?;
+    let mut proofs = leaf_nodes_with_node_index
+        .iter()
+        .map(|(leaf_node, _)| {
+            let (root_seq, root) = roots.get(&leaf_node.tree)?;
+            // A rolled back fork can leave a different root with the same seq.
+            get_cached_proof(leaf_node.tree, *root_seq, &leaf_node.hash)
+                .filter(|proof| proof.root == *root)
+        })
+        .collect::<Vec<Option<MerkleProofWithContext>>>();
+    let uncached_leaf_nodes = leaf_nodes_with_node_index
+        .into_iter()
+        .zip(proofs.iter())
+        .filter(|(_, proof)| proof.is_none())
+        .map(|(leaf_node, _)| leaf_node)
+        .collect::<Vec<(LeafNode, i64)>>();
+    if !uncached_leaf_nodes.is_empty() {
+        let generated_proofs = get_multiple_compressed_leaf_proofs_from_memory(
+            txn,
+            batch_config,
+            in_memory_trees,
+            uncached_leaf_nodes,
+        )
+        .await?;
+        cache_proofs(&generated_proofs);
+        let mut generated_proofs = generated_proofs.into_iter();
+        for proof in proofs.iter_mut().filter(|proof| proof.is_none()) {
+            *proof = generated_proofs.next();
+        }
+    }
+    proofs
+        .into_iter()
+        .map(|proof| {
+            proof.ok_or(PhotonApiError::UnexpectedError(
+                "Proof not generated".to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Sequence numbers and hashes of the current roots of `trees`.
+async fn find_roots(
+    conn: &impl ConnectionTrait,
+    trees: Vec<SerializablePubkey>,
+) -> Result<HashMap<SerializablePubkey, (u64, Hash)>, PhotonApiError> {
+    state_trees::Entity::find()
+        .filter(
+            state_trees::Column::Tree
+                .is_in(trees.iter().map(|tree| tree.to_bytes_vec()))
+                .and(state_trees::Column::NodeIdx.eq(1)),
+        )
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|root| {
+            Ok((
+                SerializablePubkey::try_from(root.tree)?,
+                (root.seq as u64, Hash::try_from(root.hash)?),
+            ))
+        })
+        .collect()
+}
+
+/// Same as `get_multiple_compressed_leaf_proofs`, but the proofs of each tree are assembled
+/// concurrently, each tree in its own transaction on the connection pool. The proofs are returned
+/// in the order of `hashes`.
+pub async fn get_multiple_compressed_leaf_proofs_by_tree(
+    conn: &DatabaseConnection,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
+    hashes: Vec<Hash>,
+) -> Result<Vec<MerkleProofWithContext>, PhotonApiError> {
+    if hashes.is_empty() {
+        return Err(PhotonApiError::ValidationError(
+            "No hashes provided".to_string(),
+        ));
+    }
+    let hash_to_tree = find_leaf_nodes(conn, &hashes)
+        .await?
+        .into_iter()
+        .map(|(leaf_node, _)| (leaf_node.hash, leaf_node.tree))
+        .collect::<HashMap<Hash, SerializablePubkey>>();
+    let hashes_by_tree = hashes
+        .iter()
+        .map(|hash| {
+            hash_to_tree
+                .get(hash)
+                .map(|tree| (*tree, hash.clone()))
+                .ok_or(PhotonApiError::RecordNotFound(format!(
+                    "Leaf node not found for hash: {}",
+                    hash
+                )))
+        })
+        .collect::<Result<Vec<_>, PhotonApiError>>()?
+        .into_iter()
+        .into_group_map();
+
+    let proofs_by_tree = try_join_all(hashes_by_tree.into_values().map(|tree_hashes| async move {
+        let txn = conn.begin().await?;
+        if txn.get_database_backend() == DatabaseBackend::Postgres {
+            txn.execute(Statement::from_string(
+                txn.get_database_backend(),
+                "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ;".to_string(),
+            ))
+            .await?;
+        }
+        let proofs =
+            get_multiple_compressed_leaf_proofs(&txn, batch_config, in_memory_trees, tree_hashes)
+                .await?;
+        txn.commit().await?;
+        Ok::<_, PhotonApiError>(proofs)
+    }))
+    .await?;
+
+    let mut hash_to_proof = proofs_by_tree
+        .into_iter()
+        .flatten()
+        .map(|proof| (proof.hash.clone(), proof))
+        .collect::<HashMap<Hash, MerkleProofWithContext>>();
+    hashes
+        .iter()
+        .map(|hash| {
+            hash_to_proof
+                .remove(hash)
+                .ok_or(PhotonApiError::UnexpectedError(format!(
+                    "Proof not assembled for hash: {}",
+                    hash
+                )))
+        })
+        .collect()
+}
+
+async fn find_leaf_nodes(
+    conn: &impl ConnectionTrait,
+    hashes: &[Hash],
+) -> Result<Vec<(LeafNode, i64)>, PhotonApiError> {
     let leaf_nodes_with_node_index = state_trees::Entity::find()
         .filter(
             state_trees::Column::Hash
                 .is_in(hashes.iter().map(|x| x.to_vec()).collect::<Vec<Vec<u8>>>())
                 .and(state_trees::Column::Level.eq(0)),
         )
-        .all(txn)
+        .all(conn)
         .await?
         .into_iter()
         .map(|x| {
@@ -225,30 +476,74 @@ pub async fn get_multiple_compressed_leaf_proofs(
             hashes.len()
         )));
     }
-    let hash_to_leaf_node_with_node_index = leaf_nodes_with_node_index
-        .iter()
-        .map(|(leaf_node, node_index)| (leaf_node.hash.clone(), (leaf_node.clone(), *node_index)))
-        .collect::<HashMap<Hash, (LeafNode, i64)>>();
-
-    let leaf_nodes_with_node_index = hashes
-        .into_iter()
-        .map(|hash| {
-            hash_to_leaf_node_with_node_index
-                .get(&hash)
-                .ok_or(PhotonApiError::RecordNotFound(format!(
-                    "Leaf node not found for hash: {}",
-                    hash
-                )))
-                .cloned()
-        })
-        .collect::<Result<Vec<(LeafNode, i64)>, PhotonApiError>>()?;
-
-    get_multiple_compressed_leaf_proofs_from_full_leaf_info(txn, leaf_nodes_with_node_index).await
+    Ok(leaf_nodes_with_node_index)
 }
 
 pub async fn get_multiple_compressed_leaf_proofs_from_full_leaf_info(
     txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
     leaf_nodes_with_node_index: Vec<(LeafNode, i64)>,
+) -> Result<Vec<MerkleProofWithContext>, PhotonApiError> {
+    let node_to_model = get_proof_nodes(
+        txn,
+        batch_config,
+        leaf_nodes_with_node_index
+            .iter()
+            .map(|(node, node_index)| (node.tree.to_bytes_vec(), *node_index))
+            .collect::<Vec<(Vec<u8>, i64)>>(),
+        false,
+    )
+    .await?;
+    build_proofs(&leaf_nodes_with_node_index, &node_to_model)
+}
+
+/// Same as `get_multiple_compressed_leaf_proofs_from_full_leaf_info`, but reads the proof nodes
+/// from the in-memory trees if they are enabled. Only for reads of committed state, since the
+/// in-memory trees do not see the writes of open transactions.
+async fn get_multiple_compressed_leaf_proofs_from_memory(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    in_memory_trees: &InMemoryTrees,
+    leaf_nodes_with_node_index: Vec<(LeafNode, i64)>,
+) -> Result<Vec<MerkleProofWithContext>, PhotonApiError> {
+    let required_nodes = leaf_nodes_with_node_index
+        .iter()
+        .flat_map(|(leaf_node, node_index)| {
+            get_proof_path(*node_index, false)
+                .into_iter()
+                .map(|idx| (leaf_node.tree.to_bytes_vec(), idx))
+        })
+        .unique()
+        .collect::<Vec<(Vec<u8>, i64)>>();
+    let Some(node_to_model) = in_memory_trees
+        .get_nodes(txn, batch_config, &required_nodes)
+        .await?
+    else {
+        return get_multiple_compressed_leaf_proofs_from_full_leaf_info(
+            txn,
+            batch_config,
+            leaf_nodes_with_node_index,
+        )
+        .await;
+    };
+    // The in-memory trees can be ahead of the transaction, e.g. if a leaf has been nullified
+    // since the transaction started.
+    match build_proofs(&leaf_nodes_with_node_index, &node_to_model) {
+        Ok(proofs) => Ok(proofs),
+        Err(_) => {
+            get_multiple_compressed_leaf_proofs_from_full_leaf_info(
+                txn,
+                batch_config,
+                leaf_nodes_with_node_index,
+            )
+            .await
+        }
+    }
+}
+
+fn build_proofs(
+    leaf_nodes_with_node_index: &[(LeafNode, i64)],
+    node_to_model: &HashMap<(Vec<u8>, i64), state_trees::Model>,
 ) -> Result<Vec<MerkleProofWithContext>, PhotonApiError> {
     let include_leafs = false;
     let leaf_locations_to_required_nodes = leaf_nodes_with_node_index
@@ -262,16 +557,6 @@ pub async fn get_multiple_compressed_leaf_proofs_from_full_leaf_info(
         })
         .collect::<HashMap<(Vec<u8>, i64), Vec<i64>>>();
 
-    let node_to_model = get_proof_nodes(
-        txn,
-        leaf_nodes_with_node_index
-            .iter()
-            .map(|(node, node_index)| (node.tree.to_bytes_vec(), *node_index))
-            .collect::<Vec<(Vec<u8>, i64)>>(),
-        include_leafs,
-    )
-    .await?;
-
     let proofs: Result<Vec<MerkleProofWithContext>, PhotonApiError> = leaf_nodes_with_node_index
         .iter()
         .map(|(leaf_node, node_index)| {
@@ -282,6 +567,8 @@ pub async fn get_multiple_compressed_leaf_proofs_from_full_leaf_info(
                     leaf_node.tree, node_index
                 )))?;
 
+            // The path runs from the sibling of the leaf up to the root, one node per level.
+            let zero_hashes = zero_hashes(required_node_indices.len() as u32)?;
             let mut proof = required_node_indices
                 .iter()
                 .enumerate()
@@ -295,7 +582,8 @@ pub async fn get_multiple_compressed_leaf_proofs_from_full_leaf_info(
                                 )
                             })
                         })
-                        .unwrap_or(Ok(Hash::from(ZERO_BYTES[level])))
+                        // Nodes of empty subtrees are never persisted.
+                        .unwrap_or(Ok(Hash::from(zero_hashes[level])))
                 })
                 .collect::<Result<Vec<Hash>, PhotonApiError>>()?;
 
@@ -393,6 +681,7 @@ pub fn get_proof_path(index: i64, include_leaf: bool) -> Vec<i64> {
 
 pub async fn get_proof_nodes<T>(
     txn_or_conn: &T,
+    batch_config: SqlBatchConfig,
     leaf_nodes_locations: Vec<(Vec<u8>, i64)>,
     include_leafs: bool,
 ) -> Result<HashMap<(Vec<u8>, i64), state_trees::Model>, DbErr>
@@ -415,34 +704,63 @@ where
         .dedup()
         .collect::<Vec<(Vec<u8>, i64)>>();
 
-    let mut params = Vec::new();
-    let mut placeholders = Vec::new();
+    Ok(
+        find_nodes(txn_or_conn, batch_config, &all_required_node_indices)
+            .await?
+            .into_iter()
+            .map(|node| ((node.tree.clone(), node.node_idx), node))
+            .collect::<HashMap<(Vec<u8>, i64), state_trees::Model>>(),
+    )
+}
 
-    for (index, (tree, node_idx)) in all_required_node_indices.into_iter().enumerate() {
-        let param_index = index * 2; // each pair contributes two parameters
-        params.push(Value::from(tree));
-        params.push(Value::from(node_idx));
-        placeholders.push(format!("(${}, ${})", param_index + 1, param_index + 2));
-    }
-    let placeholder_str = placeholders.join(", ");
-    let sql = format!(
+/// Looks up the nodes at the given `(tree, node index)` locations. Empty nodes are not stored, so
+/// they are missing from the result.
+pub async fn find_nodes(
+    conn: &impl ConnectionTrait,
+    batch_config: SqlBatchConfig,
+    node_locations: &[(Vec<u8>, i64)],
+) -> Result<Vec<state_trees::Model>, DbErr> {
+    let mut nodes = Vec::new();
+    // Each node contributes two parameters.
+    for chunk in node_locations.chunks(batch_config.batch_size(2)) {
+        let mut params = Vec::new();
+        let mut placeholders = Vec::new();
+
+        for (index, (tree, node_idx)) in chunk.iter().enumerate() {
+            let param_index = index * 2;
+            params.push(Value::from(tree.clone()));
+            params.push(Value::from(*node_idx));
+            placeholders.push(format!("(${}, ${})", param_index + 1, param_index + 2));
+        }
+        let placeholder_str = placeholders.join(", ");
+        let sql = format!(
             "WITH vals(tree, node_idx) AS (VALUES {}) SELECT st.* FROM state_trees st JOIN vals v ON st.tree = v.tree AND st.node_idx = v.node_idx",
             placeholder_str
         );
 
-    let proof_nodes = state_trees::Entity::find()
-        .from_raw_sql(Statement::from_sql_and_values(
-            txn_or_conn.get_database_backend(),
-            &sql,
-            params,
-        ))
-        .all(txn_or_conn)
-        .await?;
+        nodes.extend(
+            state_trees::Entity::find()
+                .from_raw_sql(Statement::from_sql_and_values(
+                    conn.get_database_backend(),
+                    &sql,
+                    params,
+                ))
+                .all(conn)
+                .await?,
+        );
+    }
+    Ok(nodes)
+}
 
-    Ok(proof_nodes
-        .iter()
-        .map(|node| ((node.tree.clone(), node.node_idx), node.clone()))
-        .collect::<HashMap<(Vec<u8>, i64), state_trees::Model>>())
+/// Hashes of the empty subtrees on each level of a tree of height `tree_height`, from an empty
+/// leaf up to the root of the empty tree.
+pub fn zero_hashes(tree_height: u32) -> Result<&'static [[u8; 32]], PhotonApiError> {
+    ZERO_BYTES
+        .get(..tree_height as usize)
+        .ok_or(PhotonApiError::UnexpectedError(format!(
+            "Unsupported tree height {}",
+            tree_height
+        )))
 }
 
 pub const MAX_HEIGHT: usize = 32;
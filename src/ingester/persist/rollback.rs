@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use log::info;
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait,
+    FromQueryResult, QueryFilter, QuerySelect, QueryTrait,
+};
+use sqlx::types::Decimal;
+
+use crate::{
+    common::typedefs::{hash::Hash, serializable_pubkey::SerializablePubkey},
+    dao::generated::{
+        account_transactions, accounts, blocks, failed_blocks, state_tree_histories,
+        state_tree_node_histories, state_trees, token_accounts, transactions,
+    },
+    ingester::{checkpoint::rewind_checkpoint, error::IngesterError, tree_info::tree_height},
+};
+
+use super::{
+    batch_size::SqlBatchConfig,
+    bytes_to_sql_format,
+    nullifier_queue::{dequeue_accounts, enqueue_spent_accounts},
+    persisted_state_tree::{persist_leaf_nodes, LeafNode, ZERO_BYTES},
+    update_balances, AccountType,
+};
+
+#[derive(FromQueryResult)]
+struct SignatureModel {
+    signature: Vec<u8>,
+}
+
+/// Reverts everything that was indexed from blocks above `slot`, so that the blocks of another
+/// fork can be indexed on top of it. Accounts created above `slot` are deleted, accounts spent
+/// above `slot` become unspent again, and the state tree leaves written above `slot` are restored
+/// to their previous values. Address trees are not rolled back.
+pub async fn rollback_to_slot(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    slot: u64,
+) -> Result<(), IngesterError> {
+    let slot = slot as i64;
+    let signatures = transactions::Entity::find()
+        .select_only()
+        .column(transactions::Column::Signature)
+        .filter(transactions::Column::Slot.gt(slot))
+        .into_model::<SignatureModel>()
+        .all(txn)
+        .await?
+        .into_iter()
+        .map(|model| model.signature)
+        .collect::<Vec<_>>();
+
+    // State trees have to be reverted first, since the previous leaf values are looked up in the
+    // accounts table.
+    rollback_state_trees(txn, batch_config, &signatures).await?;
+
+    let spent_accounts = account_transactions::Entity::find()
+        .filter(account_transactions::Column::Slot.gt(slot))
+        .filter(account_transactions::Column::Spent.eq(true))
+        .all(txn)
+        .await?
+        .into_iter()
+        .map(|model| model.hash)
+        .unique()
+        .collect::<Vec<_>>();
+    let query = account_transactions::Entity::delete_many()
+        .filter(account_transactions::Column::Slot.gt(slot))
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    for chunk in spent_accounts.chunks(batch_config.batch_size(1)) {
+        unspend_accounts(txn, chunk, slot).await?;
+    }
+    delete_created_accounts(txn, batch_config, slot).await?;
+
+    let query = transactions::Entity::delete_many()
+        .filter(transactions::Column::Slot.gt(slot))
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    // Dead-lettered blocks of the orphaned fork must not be retried.
+    let query = failed_blocks::Entity::delete_many()
+        .filter(failed_blocks::Column::Slot.gt(slot))
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    let query = blocks::Entity::delete_many()
+        .filter(blocks::Column::Slot.gt(slot))
+        .build(txn.get_database_backend());
+    let result = txn.execute(query).await?;
+    rewind_checkpoint(txn, slot as u64).await?;
+    info!(
+        "Rolled back {} blocks and {} transactions above slot {}",
+        result.rows_affected(),
+        signatures.len(),
+        slot
+    );
+    Ok(())
+}
+
+/// Marks the accounts that were created at or below `slot` but spent by a rolled back transaction
+/// as unspent and adds them back to the owner balances.
+async fn unspend_accounts(
+    txn: &DatabaseTransaction,
+    hashes: &[Vec<u8>],
+    slot: i64,
+) -> Result<(), IngesterError> {
+    let db_backend = txn.get_database_backend();
+    let spent_accounts = accounts::Entity::find()
+        .filter(accounts::Column::Hash.is_in(hashes.to_vec()))
+        .filter(accounts::Column::Spent.eq(true))
+        .filter(accounts::Column::SlotCreated.lte(slot))
+        .all(txn)
+        .await?;
+    let mut balance_modifications = HashMap::new();
+    for account in spent_accounts.iter() {
+        *balance_modifications
+            .entry(bytes_to_sql_format(db_backend, account.owner.clone()))
+            .or_insert(Decimal::from(0)) += account.lamports;
+    }
+    update_balances(txn, &AccountType::Account, balance_modifications).await?;
+    let unspent_hashes = spent_accounts
+        .into_iter()
+        .map(|account| account.hash)
+        .collect::<Vec<_>>();
+    let query = accounts::Entity::update_many()
+        .col_expr(accounts::Column::Spent, Expr::value(false))
+        .col_expr(
+            accounts::Column::PrevSpent,
+            Expr::value(Option::<bool>::None),
+        )
+        .col_expr(
+            accounts::Column::SpentTxSignature,
+            Expr::value(Option::<Vec<u8>>::None),
+        )
+        .col_expr(accounts::Column::SlotSpent, Expr::value(Option::<i64>::None))
+        .filter(accounts::Column::Hash.is_in(unspent_hashes.clone()))
+        .build(db_backend);
+    txn.execute(query).await?;
+    dequeue_accounts(txn, &unspent_hashes).await?;
+
+    let spent_token_accounts = token_accounts::Entity::find()
+        .filter(token_accounts::Column::Hash.is_in(hashes.to_vec()))
+        .filter(token_accounts::Column::Spent.eq(true))
+        .filter(token_accounts::Column::SlotCreated.lte(slot))
+        .all(txn)
+        .await?;
+    let mut balance_modifications = HashMap::new();
+    for token_account in spent_token_accounts.iter() {
+        *balance_modifications
+            .entry(token_balance_key(db_backend, token_account))
+            .or_insert(Decimal::from(0)) += token_account.amount;
+    }
+    update_balances(txn, &AccountType::TokenAccount, balance_modifications).await?;
+    let query = token_accounts::Entity::update_many()
+        .col_expr(token_accounts::Column::Spent, Expr::value(false))
+        .col_expr(
+            token_accounts::Column::PrevSpent,
+            Expr::value(Option::<bool>::None),
+        )
+        .col_expr(
+            token_accounts::Column::SpentTxSignature,
+            Expr::value(Option::<Vec<u8>>::None),
+        )
+        .col_expr(token_accounts::Column::SlotSpent, Expr::value(Option::<i64>::None))
+        .filter(
+            token_accounts::Column::Hash.is_in(
+                spent_token_accounts
+                    .into_iter()
+                    .map(|token_account| token_account.hash),
+            ),
+        )
+        .build(db_backend);
+    txn.execute(query).await?;
+    Ok(())
+}
+
+/// Deletes the accounts created above `slot` and removes the unspent ones from the owner balances.
+async fn delete_created_accounts(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    slot: i64,
+) -> Result<(), IngesterError> {
+    let db_backend = txn.get_database_backend();
+    let created_token_accounts = token_accounts::Entity::find()
+        .filter(token_accounts::Column::SlotCreated.gt(slot))
+        .all(txn)
+        .await?;
+    let mut balance_modifications = HashMap::new();
+    for token_account in created_token_accounts.iter().filter(|x| !x.spent) {
+        *balance_modifications
+            .entry(token_balance_key(db_backend, token_account))
+            .or_insert(Decimal::from(0)) -= token_account.amount;
+    }
+    update_balances(txn, &AccountType::TokenAccount, balance_modifications).await?;
+    let query = token_accounts::Entity::delete_many()
+        .filter(token_accounts::Column::SlotCreated.gt(slot))
+        .build(db_backend);
+    txn.execute(query).await?;
+
+    let created_accounts = accounts::Entity::find()
+        .filter(accounts::Column::SlotCreated.gt(slot))
+        .all(txn)
+        .await?;
+    let mut balance_modifications = HashMap::new();
+    for account in created_accounts.iter().filter(|x| !x.spent) {
+        *balance_modifications
+            .entry(bytes_to_sql_format(db_backend, account.owner.clone()))
+            .or_insert(Decimal::from(0)) -= account.lamports;
+    }
+    update_balances(txn, &AccountType::Account, balance_modifications).await?;
+    for chunk in created_accounts.chunks(batch_config.batch_size(1)) {
+        let hashes = chunk
+            .iter()
+            .map(|account| account.hash.clone())
+            .collect::<Vec<_>>();
+        let query = account_transactions::Entity::delete_many()
+            .filter(account_transactions::Column::Hash.is_in(hashes.clone()))
+            .build(db_backend);
+        txn.execute(query).await?;
+        dequeue_accounts(txn, &hashes).await?;
+        let query = accounts::Entity::delete_many()
+            .filter(accounts::Column::Hash.is_in(hashes))
+            .build(db_backend);
+        txn.execute(query).await?;
+    }
+    Ok(())
+}
+
+fn token_balance_key(
+    db_backend: sea_orm::DatabaseBackend,
+    token_account: &token_accounts::Model,
+) -> String {
+    format!(
+        "{},{}",
+        bytes_to_sql_format(db_backend, token_account.owner.clone()),
+        bytes_to_sql_format(db_backend, token_account.mint.clone())
+    )
+}
+
+/// Restores every state tree written by the given transactions to its state before the first of
+/// them. Sequence numbers increase monotonically within a tree, so all nodes with a sequence
+/// number at or above that of the first rolled back change are rewritten.
+async fn rollback_state_trees(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    signatures: &[Vec<u8>],
+) -> Result<(), IngesterError> {
+    let mut first_rolled_back_seqs: HashMap<Vec<u8>, i64> = HashMap::new();
+    for chunk in signatures.chunks(batch_config.batch_size(1)) {
+        let histories = state_tree_histories::Entity::find()
+            .filter(state_tree_histories::Column::TransactionSignature.is_in(chunk.to_vec()))
+            .all(txn)
+            .await?;
+        for history in histories {
+            first_rolled_back_seqs
+                .entry(history.tree)
+                .and_modify(|seq| *seq = (*seq).min(history.seq))
+                .or_insert(history.seq);
+        }
+    }
+    for (tree, first_rolled_back_seq) in first_rolled_back_seqs {
+        rollback_state_tree(txn, batch_config, tree, first_rolled_back_seq).await?;
+    }
+    Ok(())
+}
+
+async fn rollback_state_tree(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    tree: Vec<u8>,
+    first_rolled_back_seq: i64,
+) -> Result<(), IngesterError> {
+    let leaf_indices = state_trees::Entity::find()
+        .filter(state_trees::Column::Tree.eq(tree.clone()))
+        .filter(state_trees::Column::Level.eq(0))
+        .filter(state_trees::Column::Seq.gte(first_rolled_back_seq))
+        .all(txn)
+        .await?
+        .into_iter()
+        .filter_map(|node| node.leaf_idx)
+        .collect::<Vec<_>>();
+
+    let serializable_tree = SerializablePubkey::try_from(tree.clone())
+        .map_err(|e| IngesterError::DatabaseError(format!("Invalid tree pubkey: {}", e)))?;
+    let mut restored_leaves = Vec::new();
+    for chunk in leaf_indices.chunks(batch_config.batch_size(1)) {
+        // State trees are append-only, so each leaf holds at most one account, which is replaced
+        // by the zero value once the leaf is nullified.
+        let appended_accounts = accounts::Entity::find()
+            .filter(accounts::Column::Tree.eq(tree.clone()))
+            .filter(accounts::Column::LeafIndex.is_in(chunk.to_vec()))
+            .filter(accounts::Column::Seq.lt(first_rolled_back_seq))
+            .all(txn)
+            .await?
+            .into_iter()
+            .map(|account| (account.leaf_index, (account.hash, account.seq)))
+            .collect::<HashMap<_, _>>();
+        let mut last_seqs: HashMap<i64, i64> = HashMap::new();
+        for history in state_tree_histories::Entity::find()
+            .filter(state_tree_histories::Column::Tree.eq(tree.clone()))
+            .filter(state_tree_histories::Column::LeafIdx.is_in(chunk.to_vec()))
+            .filter(state_tree_histories::Column::Seq.lt(first_rolled_back_seq))
+            .all(txn)
+            .await?
+        {
+            last_seqs
+                .entry(history.leaf_idx)
+                .and_modify(|seq| *seq = (*seq).max(history.seq))
+                .or_insert(history.seq);
+        }
+
+        for leaf_index in chunk {
+            let (hash, seq) = match (appended_accounts.get(leaf_index), last_seqs.get(leaf_index)) {
+                (Some((_, account_seq)), Some(&last_seq)) if last_seq > *account_seq => {
+                    (Hash::from(ZERO_BYTES[0]), last_seq)
+                }
+                (Some((hash, account_seq)), _) => (
+                    Hash::try_from(hash.clone()).map_err(|e| {
+                        IngesterError::DatabaseError(format!("Invalid account hash: {}", e))
+                    })?,
+                    *account_seq,
+                ),
+                (None, last_seq) => (Hash::from(ZERO_BYTES[0]), last_seq.copied().unwrap_or(0)),
+            };
+            restored_leaves.push(LeafNode {
+                tree: serializable_tree,
+                leaf_index: *leaf_index as u32,
+                hash,
+                seq: seq as u32,
+            });
+        }
+    }
+
+    // Every node with a sequence number at or above the first rolled back one lies on the path of
+    // a restored leaf, so the deleted nodes are recomputed when the restored leaves are persisted.
+    let query = state_trees::Entity::delete_many()
+        .filter(state_trees::Column::Tree.eq(tree.clone()))
+        .filter(state_trees::Column::Seq.gte(first_rolled_back_seq))
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    for chunk in restored_leaves.chunks(batch_config.batch_size(1)) {
+        // Spent accounts whose nullification is rolled back are pending again.
+        let hashes = chunk
+            .iter()
+            .filter(|leaf| leaf.hash != Hash::from(ZERO_BYTES[0]))
+            .map(|leaf| leaf.hash.to_vec())
+            .collect::<Vec<_>>();
+        enqueue_spent_accounts(txn, &hashes).await?;
+        persist_leaf_nodes(
+            txn,
+            batch_config,
+            chunk.to_vec(),
+            tree_height(&serializable_tree.0),
+        )
+        .await?;
+    }
+
+    let query = state_tree_node_histories::Entity::delete_many()
+        .filter(state_tree_node_histories::Column::Tree.eq(tree.clone()))
+        .filter(state_tree_node_histories::Column::Seq.gte(first_rolled_back_seq))
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    let query = state_tree_histories::Entity::delete_many()
+        .filter(state_tree_histories::Column::Tree.eq(tree))
+        .filter(state_tree_histories::Column::Seq.gte(first_rolled_back_seq))
+        .build(txn.get_database_backend());
+    txn.execute(query).await?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use cadence_macros::statsd_count;
+use log::error;
+use once_cell::sync::Lazy;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::common::typedefs::token_data::TokenData;
+use crate::ingester::error::IngesterError;
+use crate::metric;
+
+// Number of most recent token accounts per program that the failure rate is computed over.
+const WINDOW_SIZE: usize = 1000;
+// Avoids alerting on a handful of malformed accounts right after startup.
+const MIN_SAMPLES: usize = 100;
+const ALERT_FAILURE_RATE: f64 = 0.05;
+// Once raised, the alert is only cleared when the failure rate drops well below the threshold so
+// that a rate hovering around the threshold does not flap.
+const CLEAR_FAILURE_RATE: f64 = ALERT_FAILURE_RATE / 2.0;
+
+/// Tracks the token data parse failure rate of a single program over a sliding window.
+///
+/// Token data is deserialized with a fixed layout, so a sudden spike in parse failures almost
+/// always means that a program upgrade changed the layout rather than that individual accounts
+/// are malformed.
+#[derive(Debug, Default)]
+pub struct TokenDataParseMonitor {
+    outcomes: VecDeque<bool>,
+    failures: usize,
+    alerting: bool,
+}
+
+impl TokenDataParseMonitor {
+    /// Records a parse outcome and returns `true` if the failure rate just crossed the alert
+    /// threshold.
+    pub fn record(&mut self, success: bool) -> bool {
+        self.outcomes.push_back(success);
+        if !success {
+            self.failures += 1;
+        }
+        if self.outcomes.len() > WINDOW_SIZE {
+            if let Some(false) = self.outcomes.pop_front() {
+                self.failures -= 1;
+            }
+        }
+        if self.outcomes.len() < MIN_SAMPLES {
+            return false;
+        }
+        let failure_rate = self.failure_rate();
+        if !self.alerting && failure_rate >= ALERT_FAILURE_RATE {
+            self.alerting = true;
+            return true;
+        }
+        if self.alerting && failure_rate < CLEAR_FAILURE_RATE {
+            self.alerting = false;
+        }
+        false
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+        self.failures as f64 / self.outcomes.len() as f64
+    }
+
+    pub fn is_alerting(&self) -> bool {
+        self.alerting
+    }
+}
+
+static MONITORS: Lazy<Mutex<HashMap<Pubkey, TokenDataParseMonitor>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Records the outcome of parsing the token data of an account owned by `program` and raises a
+/// layout change alert if the failure rate of the program spikes. Accounts that are not token
+/// accounts are ignored.
+pub fn record_token_data_parse(
+    program: &Pubkey,
+    result: &Result<Option<TokenData>, IngesterError>,
+) {
+    let success = match result {
+        Ok(Some(_)) => true,
+        Ok(None) => return,
+        Err(_) => false,
+    };
+    let program_tag = program.to_string();
+    if !success {
+        metric! {
+            statsd_count!("token_data_parse_failure", 1, "program" => &program_tag);
+        }
+    }
+    let mut monitors = MONITORS.lock().unwrap();
+    let monitor = monitors.entry(*program).or_default();
+    if monitor.record(success) {
+        error!(
+            "Token data parse failure rate for program {} reached {:.1}%. The program was likely \
+             upgraded and changed its account layout.",
+            program,
+            monitor.failure_rate() * 100.0
+        );
+        metric! {
+            statsd_count!("token_data_layout_change_suspected", 1, "program" => &program_tag);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::{sync::Arc, time::Duration};
+
+use cadence_macros::statsd_count;
+use log::{error, info};
+use sea_orm::{
+    sea_query::{OnConflict, Query},
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter,
+    QueryTrait, Statement, TransactionTrait,
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    dao::generated::{account_transactions, accounts, pruned_accounts},
+    metric,
+};
+
+use super::{
+    error::IngesterError, indexer::fetch_last_indexed_slot_with_infinite_retry,
+    persist::batch_size::SqlBatchConfig,
+};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(600);
+// Accounts are deleted in separate transactions of at most this many accounts so that the
+// pruner does not hold locks that stall the indexer for long.
+const PRUNE_BATCH_SIZE: usize = 1000;
+/// Lower bound for the retention of spent accounts. Rolling back an orphaned fork unspends the
+/// accounts spent on it, so they must not have been pruned yet.
+pub const MIN_PRUNE_RETENTION_SLOTS: u64 = 1000;
+
+#[derive(FromQueryResult)]
+struct HashModel {
+    hash: Vec<u8>,
+}
+
+/// Deletes the accounts that were spent more than `retention_slots` slots before the last indexed
+/// slot, together with their token accounts and their links to transactions. A tombstone with
+/// the spend of each pruned account is kept in `pruned_accounts`. Accounts whose spending
+/// transaction is not indexed have no spend slot, so the slot of the last transaction that
+/// touched them is used instead. Accounts without any indexed transaction are kept.
+///
+/// Returns the number of deleted accounts.
+pub async fn prune_spent_accounts(
+    db: &DatabaseConnection,
+    batch_config: SqlBatchConfig,
+    retention_slots: u64,
+) -> Result<u64, IngesterError> {
+    let Some(last_indexed_slot) = fetch_last_indexed_slot_with_infinite_retry(db).await else {
+        return Ok(0);
+    };
+    let Some(cutoff_slot) = (last_indexed_slot as u64).checked_sub(retention_slots) else {
+        return Ok(0);
+    };
+    let db_backend = db.get_database_backend();
+    let batch_size = PRUNE_BATCH_SIZE.min(batch_config.batch_size(1));
+
+    let mut pruned_accounts = 0;
+    loop {
+        let txn = db.begin().await?;
+        let hashes = HashModel::find_by_statement(Statement::from_sql_and_values(
+            db_backend,
+            "SELECT accounts.hash FROM accounts \
+             WHERE accounts.spent = true AND (accounts.slot_spent <= $1 OR ( \
+                 accounts.slot_spent IS NULL AND ( \
+                     SELECT MAX(account_transactions.slot) FROM account_transactions \
+                     WHERE account_transactions.hash = accounts.hash \
+                 ) <= $1 \
+             )) \
+             LIMIT $2",
+            [(cutoff_slot as i64).into(), (batch_size as i64).into()],
+        ))
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|model| model.hash)
+        .collect::<Vec<_>>();
+        if hashes.is_empty() {
+            break;
+        }
+        // Tombstones keep the accounts spent if the blocks that created them are indexed again.
+        let query = Query::insert()
+            .into_table(pruned_accounts::Entity)
+            .columns([
+                pruned_accounts::Column::Hash,
+                pruned_accounts::Column::SlotSpent,
+                pruned_accounts::Column::SpentTxSignature,
+            ])
+            .select_from(
+                Query::select()
+                    .columns([
+                        accounts::Column::Hash,
+                        accounts::Column::SlotSpent,
+                        accounts::Column::SpentTxSignature,
+                    ])
+                    .from(accounts::Entity)
+                    .and_where(accounts::Column::Hash.is_in(hashes.clone()))
+                    .to_owned(),
+            )
+            .map_err(|e| IngesterError::DatabaseError(e.to_string()))?
+            .on_conflict(
+                OnConflict::column(pruned_accounts::Column::Hash)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .to_owned();
+        txn.execute(db_backend.build(&query)).await?;
+        let query = account_transactions::Entity::delete_many()
+            .filter(account_transactions::Column::Hash.is_in(hashes.clone()))
+            .build(db_backend);
+        txn.execute(query).await?;
+        // Token accounts are deleted by their foreign key.
+        let query = accounts::Entity::delete_many()
+            .filter(accounts::Column::Hash.is_in(hashes.clone()))
+            .build(db_backend);
+        txn.execute(query).await?;
+        txn.commit().await?;
+        pruned_accounts += hashes.len() as u64;
+    }
+
+    metric! {
+        statsd_count!("pruned_spent_accounts", pruned_accounts);
+    }
+    Ok(pruned_accounts)
+}
+
+/// Periodically prunes the accounts that were spent more than `retention_slots` slots ago.
+pub fn continously_prune_spent_accounts(
+    db: Arc<DatabaseConnection>,
+    batch_config: SqlBatchConfig,
+    retention_slots: u64,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match prune_spent_accounts(db.as_ref(), batch_config, retention_slots).await {
+                Ok(0) => {}
+                Ok(pruned_accounts) => info!("Pruned {} spent accounts", pruned_accounts),
+                Err(e) => error!("Failed to prune spent accounts: {}", e),
+            }
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    })
+}
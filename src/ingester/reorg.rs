@@ -0,0 +1,118 @@
+use std::{sync::Arc, time::Duration};
+
+use cadence_macros::{statsd_count, statsd_histogram};
+use log::{error, warn};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+
+use crate::{api::proof_cache::clear_proof_cache, dao::generated::blocks, metric};
+
+use super::{
+    error::IngesterError, fetchers::poller::fetch_block_with_infinite_retries,
+    persist::rollback::rollback_to_slot, typedefs::block_info::BlockMetadata, IndexerContext,
+    ParsedBlock,
+};
+
+/// Checks whether `block` extends the chain of indexed blocks. Returns the slot that the database
+/// has to be rolled back to if it does not, i.e. if the indexed parent or the indexed block at its
+/// slot has a different hash, or if a block was indexed in a slot between them, which the fork of
+/// `block` skipped. Blocks indexed above the slot of `block` are not compared, so that indexing
+/// older slots again, e.g. after restarting below the indexed tip, keeps them.
+pub async fn detect_fork(
+    db: &DatabaseConnection,
+    block: &BlockMetadata,
+) -> Result<Option<u64>, IngesterError> {
+    let indexed_blocks = blocks::Entity::find()
+        .filter(blocks::Column::Slot.gte(block.parent_slot as i64))
+        .filter(blocks::Column::Slot.lte(block.slot as i64))
+        .order_by_asc(blocks::Column::Slot)
+        .all(db)
+        .await?;
+    let blockhash = block.blockhash.to_vec();
+    if indexed_blocks
+        .iter()
+        .any(|indexed| indexed.slot == block.slot as i64 && indexed.blockhash == blockhash)
+    {
+        return Ok(None);
+    }
+    match indexed_blocks.first() {
+        Some(parent)
+            if parent.slot == block.parent_slot as i64
+                && parent.blockhash != block.parent_blockhash.to_vec() =>
+        {
+            // The indexed parent is orphaned as well, so we roll back past it and check the
+            // canonical parent against its own parent once it has been refetched.
+            Ok(Some(block.parent_slot.saturating_sub(1)))
+        }
+        _ if indexed_blocks
+            .iter()
+            .any(|indexed| indexed.slot > block.parent_slot as i64) =>
+        {
+            Ok(Some(block.parent_slot))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Reverts all blocks above `slot` in a single transaction.
+pub async fn rollback(indexer: &IndexerContext, slot: u64) -> Result<(), IngesterError> {
+    let txn = indexer.db.begin().await?;
+    rollback_to_slot(&txn, indexer.sql_batch_config, slot).await?;
+    txn.commit().await?;
+    // Rollbacks are rare, so the cache is cleared instead of tracking what was rolled back.
+    indexer.api_cache.clear();
+    clear_proof_cache();
+    indexer.in_memory_trees.clear();
+    Ok(())
+}
+
+/// Makes sure that the batch of blocks can be indexed on top of the indexed chain. If the batch
+/// belongs to another fork, the orphaned blocks are rolled back and the canonical blocks between
+/// the fork point and the batch are refetched and prepended to it.
+pub async fn resolve_forks(
+    indexer: &IndexerContext,
+    rpc_client: Arc<RpcClient>,
+    mut blocks: Vec<ParsedBlock>,
+) -> Vec<ParsedBlock> {
+    loop {
+        let first_block = match blocks.first() {
+            Some(parsed_block) => parsed_block.block.metadata.clone(),
+            None => return blocks,
+        };
+        let rollback_slot = match detect_fork(indexer.db.as_ref(), &first_block).await {
+            Ok(None) => return blocks,
+            Ok(Some(rollback_slot)) => rollback_slot,
+            Err(e) => {
+                error!(
+                    "Failed to check block {} for forks: {}",
+                    first_block.slot, e
+                );
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        warn!(
+            "Block {} does not extend the indexed chain. Rolling back to slot {}",
+            first_block.slot, rollback_slot
+        );
+        metric! {
+            statsd_count!("reorg_detected", 1);
+            statsd_histogram!("reorg_rollback_depth", first_block.slot - rollback_slot);
+        }
+        while let Err(e) = rollback(indexer, rollback_slot).await {
+            error!("Failed to roll back to slot {}: {}", rollback_slot, e);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        let mut canonical_blocks = Vec::new();
+        for slot in (rollback_slot + 1)..first_block.slot {
+            if let Some(block) = fetch_block_with_infinite_retries(rpc_client.clone(), slot).await {
+                canonical_blocks.push(ParsedBlock::parse(block));
+            }
+        }
+        canonical_blocks.extend(blocks);
+        blocks = canonical_blocks;
+    }
+}
@@ -0,0 +1,165 @@
+use std::{collections::HashMap, mem, sync::Mutex};
+
+use itertools::Itertools;
+use light_concurrent_merkle_tree::{copy::ConcurrentMerkleTreeCopy, light_hasher::Poseidon};
+use light_sdk::state::MerkleTreeMetadata;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use sea_orm::{sea_query::OnConflict, ConnectionTrait, EntityTrait, QueryTrait, Set};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{account::Account as SolanaAccount, pubkey::Pubkey};
+
+use crate::{common::typedefs::hash::Hash, dao::generated::trees};
+
+use super::{error::IngesterError, persist::TREE_HEIGHT};
+
+// Number of accounts that are fetched per `getMultipleAccounts` request.
+const ACCOUNTS_PER_REQUEST: usize = 100;
+
+/// Parameters of a Merkle tree account on chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeInfo {
+    /// Number of levels above the leaves.
+    pub height: u32,
+    pub canopy_depth: u32,
+    pub changelog_size: u64,
+}
+
+// `None` for accounts that are not Merkle trees of a supported height, so that they are not
+// fetched again.
+static TREE_INFOS: Lazy<Mutex<HashMap<Pubkey, Option<TreeInfo>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the parameters of `tree`, if they have been fetched.
+pub fn get_tree_info(tree: &Pubkey) -> Option<TreeInfo> {
+    TREE_INFOS.lock().unwrap().get(tree).copied().flatten()
+}
+
+/// Number of node levels of `tree` including the leaves, which determines the node indices of its
+/// leaves. Trees whose parameters are unknown are assumed to have the height of the state trees
+/// that the indexer was written for.
+pub fn tree_height(tree: &Pubkey) -> u32 {
+    get_tree_info(tree).map_or(TREE_HEIGHT, |info| info.height + 1)
+}
+
+fn parse_tree<const HEIGHT: usize>(bytes: &[u8]) -> Option<(TreeInfo, Vec<Hash>)> {
+    let tree = ConcurrentMerkleTreeCopy::<Poseidon, HEIGHT>::from_bytes_copy(bytes).ok()?;
+    let info = TreeInfo {
+        height: tree.height as u32,
+        canopy_depth: tree.canopy_depth as u32,
+        changelog_size: tree.changelog.capacity() as u64,
+    };
+    let roots = tree.roots.iter().map(|root| Hash::from(*root)).collect();
+    Some((info, roots))
+}
+
+// The layout of a tree depends on its height, which is a const generic of the tree types.
+macro_rules! parse_tree_of_height {
+    ($bytes:expr, $height:expr, [$($supported_height:literal),*]) => {
+        match $height {
+            $($supported_height => parse_tree::<$supported_height>($bytes),)*
+            _ => None,
+        }
+    };
+}
+
+/// Parses the parameters and the recent roots of a state Merkle tree account.
+pub fn parse_merkle_tree_account(account: &SolanaAccount) -> Option<(TreeInfo, Vec<Hash>)> {
+    let bytes = account
+        .data
+        .get(8 + mem::size_of::<MerkleTreeMetadata>()..)?;
+    // The height is the first field of the tree.
+    let height = usize::from_le_bytes(bytes.get(..mem::size_of::<usize>())?.try_into().ok()?);
+    parse_tree_of_height!(
+        bytes,
+        height,
+        [16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32]
+    )
+}
+
+/// Loads the tree parameters that were stored by earlier runs.
+pub async fn load_tree_infos(conn: &impl ConnectionTrait) -> Result<(), IngesterError> {
+    let models = trees::Entity::find().all(conn).await?;
+    let mut tree_infos = TREE_INFOS.lock().unwrap();
+    for model in models {
+        let tree = Pubkey::try_from(model.tree)
+            .map_err(|_| IngesterError::DatabaseError("Invalid tree pubkey".to_string()))?;
+        let info = TreeInfo {
+            height: model.height as u32,
+            canopy_depth: model.canopy_depth as u32,
+            changelog_size: model.changelog_size as u64,
+        };
+        tree_infos.insert(tree, Some(info));
+    }
+    Ok(())
+}
+
+/// Stores the parameters of `tree`, so that they are known from then on and after restarts.
+pub async fn store_tree_info(
+    conn: &impl ConnectionTrait,
+    tree: Pubkey,
+    info: TreeInfo,
+) -> Result<(), IngesterError> {
+    let model = trees::ActiveModel {
+        tree: Set(tree.to_bytes().to_vec()),
+        height: Set(info.height as i64),
+        canopy_depth: Set(info.canopy_depth as i64),
+        changelog_size: Set(info.changelog_size as i64),
+    };
+    let query = trees::Entity::insert(model)
+        .on_conflict(
+            OnConflict::column(trees::Column::Tree)
+                .update_columns([
+                    trees::Column::Height,
+                    trees::Column::CanopyDepth,
+                    trees::Column::ChangelogSize,
+                ])
+                .to_owned(),
+        )
+        .build(conn.get_database_backend());
+    conn.execute(query).await?;
+    TREE_INFOS.lock().unwrap().insert(tree, Some(info));
+    Ok(())
+}
+
+/// Fetches the parameters of the trees among `trees` that are not known yet from chain and stores
+/// them. Trees whose accounts cannot be fetched are tried again on the next call.
+pub async fn fetch_tree_infos(
+    conn: &impl ConnectionTrait,
+    rpc_client: &RpcClient,
+    trees: impl IntoIterator<Item = Pubkey>,
+) -> Result<(), IngesterError> {
+    let unknown_trees = {
+        let tree_infos = TREE_INFOS.lock().unwrap();
+        trees
+            .into_iter()
+            .filter(|tree| !tree_infos.contains_key(tree))
+            .unique()
+            .collect::<Vec<_>>()
+    };
+    for chunk in unknown_trees.chunks(ACCOUNTS_PER_REQUEST) {
+        let accounts = match rpc_client.get_multiple_accounts(chunk).await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                warn!("Failed to fetch tree accounts: {}", e);
+                return Ok(());
+            }
+        };
+        for (tree, account) in chunk.iter().zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            match parse_merkle_tree_account(&account) {
+                Some((info, _)) => {
+                    info!("Tree {} has parameters {:?}", tree, info);
+                    store_tree_info(conn, *tree, info).await?;
+                }
+                None => {
+                    warn!("Account {} is not a supported Merkle tree", tree);
+                    TREE_INFOS.lock().unwrap().insert(*tree, None);
+                }
+            }
+        }
+    }
+    Ok(())
+}
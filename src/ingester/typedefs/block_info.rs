@@ -1,19 +1,24 @@
+use cadence_macros::statsd_count;
+use log::error;
 use serde::{Deserialize, Serialize};
 use solana_sdk::{
     clock::{Slot, UnixTimestamp},
     pubkey::Pubkey,
     signature::Signature,
-    transaction::VersionedTransaction,
+    transaction::{SanitizedTransaction, VersionedTransaction},
 };
 use solana_transaction_status::{
     option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
-    EncodedTransactionWithStatusMeta, UiConfirmedBlock, UiInstruction, UiTransactionStatusMeta,
+    EncodedTransactionWithStatusMeta, TransactionStatusMeta, UiConfirmedBlock, UiInstruction,
+    UiTransactionStatusMeta,
 };
-use std::{fmt, str::FromStr};
+use std::{fmt, str::FromStr, time::Instant};
 
 use std::convert::TryFrom;
 
 use crate::common::typedefs::hash::Hash;
+use crate::ingester::parser::ACCOUNT_COMPRESSION_PROGRAM_ID;
+use crate::metric;
 
 use super::super::error::IngesterError;
 
@@ -50,6 +55,10 @@ pub struct BlockMetadata {
     pub blockhash: Hash,
     pub parent_blockhash: Hash,
     pub block_height: u64,
+    // When the block was received from the block source. Only used for latency metrics, so it is
+    // not persisted in snapshots.
+    #[serde(skip)]
+    pub received_at: Option<Instant>,
 }
 
 pub fn parse_ui_confirmed_blocked(
@@ -65,33 +74,55 @@ pub fn parse_ui_confirmed_blocked(
         block_height,
         ..
     } = block;
+    let malformed_block = |msg: String| IngesterError::MalformedBlock { slot, msg };
 
-    let transactions: Result<Vec<_>, _> = transactions
-        .unwrap_or(Vec::new())
-        .into_iter()
-        .map(_parse_transaction)
-        .collect();
+    let transactions = drop_malformed_transactions(
+        slot,
+        transactions
+            .unwrap_or(Vec::new())
+            .into_iter()
+            .map(_parse_transaction),
+    );
 
     Ok(BlockInfo {
-        transactions: transactions?,
+        transactions,
         metadata: BlockMetadata {
             parent_slot,
-            block_time: block_time
-                .ok_or(IngesterError::ParserError("Missing block_time".to_string()))?,
+            block_time: block_time.ok_or(malformed_block("Missing block_time".to_string()))?,
             slot,
-            blockhash: Hash::try_from(blockhash.as_str()).map_err(|e| {
-                IngesterError::ParserError(format!("Failed to parse blockhash: {}", e))
-            })?,
+            blockhash: Hash::try_from(blockhash.as_str())
+                .map_err(|e| malformed_block(format!("Failed to parse blockhash: {}", e)))?,
             parent_blockhash: Hash::try_from(previous_blockhash.as_str()).map_err(|e| {
-                IngesterError::ParserError(format!("Failed to parse previous_blockhash: {}", e))
+                malformed_block(format!("Failed to parse previous_blockhash: {}", e))
             })?,
-            block_height: block_height.ok_or(IngesterError::ParserError(
-                "Missing block_height".to_string(),
-            ))?,
+            block_height: block_height
+                .ok_or(malformed_block("Missing block_height".to_string()))?,
+            received_at: None,
         },
     })
 }
 
+/// Drops the transactions of a block that cannot be parsed instead of failing the whole block, so
+/// that a single malformed transaction cannot stall indexing.
+pub fn drop_malformed_transactions(
+    slot: Slot,
+    transactions: impl IntoIterator<Item = Result<TransactionInfo, IngesterError>>,
+) -> Vec<TransactionInfo> {
+    transactions
+        .into_iter()
+        .filter_map(|transaction| match transaction {
+            Ok(transaction) => Some(transaction),
+            Err(e) => {
+                error!("Dropping malformed transaction in block {}: {}", slot, e);
+                metric! {
+                    statsd_count!("malformed_transaction_dropped", 1);
+                }
+                None
+            }
+        })
+        .collect()
+}
+
 fn _parse_transaction(
     transaction: EncodedTransactionWithStatusMeta,
 ) -> Result<TransactionInfo, IngesterError> {
@@ -104,7 +135,7 @@ fn _parse_transaction(
     )?;
     let meta = meta.ok_or(IngesterError::ParserError("Missing metadata".to_string()))?;
 
-    let signature = versioned_transaction.signatures[0];
+    let signature = first_signature(&versioned_transaction)?;
     let error = meta.clone().err.map(|e| e.to_string());
     let instruction_groups = parse_instruction_groups(versioned_transaction, meta)?;
     Ok(TransactionInfo {
@@ -114,6 +145,18 @@ fn _parse_transaction(
     })
 }
 
+fn first_signature(
+    versioned_transaction: &VersionedTransaction,
+) -> Result<Signature, IngesterError> {
+    versioned_transaction
+        .signatures
+        .first()
+        .copied()
+        .ok_or(IngesterError::ParserError(
+            "Transaction has no signatures".to_string(),
+        ))
+}
+
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Instruction {{ program_id: {}}}", self.program_id,)
@@ -162,7 +205,7 @@ impl TryFrom<EncodedConfirmedTransactionWithStatusMeta> for TransactionInfo {
         let versioned_transaction: VersionedTransaction = transaction.decode().ok_or(
             IngesterError::ParserError("Transaction cannot be decoded".to_string()),
         )?;
-        let signature = versioned_transaction.signatures[0];
+        let signature = first_signature(&versioned_transaction)?;
         let meta = meta.ok_or(IngesterError::ParserError("Missing metadata".to_string()))?;
         let error = meta.clone().err.map(|e| e.to_string());
         Ok(TransactionInfo {
@@ -173,28 +216,79 @@ impl TryFrom<EncodedConfirmedTransactionWithStatusMeta> for TransactionInfo {
     }
 }
 
+/// Parses a transaction in the form in which a validator passes it to Geyser plugins.
+pub fn parse_sanitized_transaction(
+    transaction: &SanitizedTransaction,
+    meta: &TransactionStatusMeta,
+) -> Result<TransactionInfo, IngesterError> {
+    Ok(TransactionInfo {
+        instruction_groups: parse_instruction_groups(
+            transaction.to_versioned_transaction(),
+            meta.clone().into(),
+        )?,
+        signature: *transaction.signature(),
+        error: meta.status.clone().err().map(|e| e.to_string()),
+    })
+}
+
 pub fn parse_instruction_groups(
     versioned_transaction: VersionedTransaction,
     meta: UiTransactionStatusMeta,
 ) -> Result<Vec<InstructionGroup>, IngesterError> {
-    let mut accounts = Vec::from(versioned_transaction.message.static_account_keys());
-    if versioned_transaction
+    let signature = first_signature(&versioned_transaction)?;
+    let malformed_transaction = |msg: String| IngesterError::MalformedTransaction {
+        signature: signature.to_string(),
+        msg,
+    };
+    let static_account_keys = versioned_transaction.message.static_account_keys();
+    let uses_lookup_tables = versioned_transaction
         .message
         .address_table_lookups()
-        .is_some()
+        .is_some();
+    // Most transactions do not invoke the compression programs, so their inner instructions are
+    // neither decoded nor parsed, and the addresses loaded from lookup tables are only compared.
+    let loads_compression_program = || match &meta.loaded_addresses {
+        OptionSerializer::Some(loaded_addresses) if uses_lookup_tables => loaded_addresses
+            .writable
+            .iter()
+            .chain(loaded_addresses.readonly.iter())
+            .any(|address| Pubkey::from_str(address).ok() == Some(ACCOUNT_COMPRESSION_PROGRAM_ID)),
+        _ => false,
+    };
+    if !static_account_keys.contains(&ACCOUNT_COMPRESSION_PROGRAM_ID)
+        && !loads_compression_program()
     {
+        return outer_program_groups(
+            versioned_transaction
+                .message
+                .instructions()
+                .iter()
+                .map(|ix| ix.program_id_index as usize),
+            |index| static_account_keys.get(index).copied(),
+        )
+        .map_err(malformed_transaction);
+    }
+
+    let mut accounts = Vec::from(static_account_keys);
+    if uses_lookup_tables {
         if let OptionSerializer::Some(loaded_addresses) = meta.loaded_addresses.clone() {
             for address in loaded_addresses
                 .writable
                 .iter()
                 .chain(loaded_addresses.readonly.iter())
             {
-                let pubkey = Pubkey::from_str(address)
-                    .map_err(|e| IngesterError::ParserError(e.to_string()))?;
+                let pubkey = Pubkey::from_str(address).map_err(|e| {
+                    malformed_transaction(format!("Failed to parse loaded address: {}", e))
+                })?;
                 accounts.push(pubkey);
             }
         }
     }
+    let account = |index: u8| {
+        accounts.get(index as usize).copied().ok_or_else(|| {
+            malformed_transaction(format!("Account index {} is out of bounds", index))
+        })
+    };
 
     // Parse outer instructions and bucket them into groups
     let mut instruction_groups: Vec<InstructionGroup> = versioned_transaction
@@ -202,24 +296,20 @@ pub fn parse_instruction_groups(
         .instructions()
         .iter()
         .map(|ix| {
-            let program_id = accounts[ix.program_id_index as usize];
-            let data = ix.data.clone();
-            let accounts: Vec<Pubkey> = ix
-                .accounts
-                .iter()
-                .map(|account_index| accounts[*account_index as usize])
-                .collect();
-
-            InstructionGroup {
+            Ok(InstructionGroup {
                 outer_instruction: Instruction {
-                    program_id,
-                    data,
-                    accounts,
+                    program_id: account(ix.program_id_index)?,
+                    data: ix.data.clone(),
+                    accounts: ix
+                        .accounts
+                        .iter()
+                        .map(|account_index| account(*account_index))
+                        .collect::<Result<_, _>>()?,
                 },
                 inner_instructions: Vec::new(),
-            }
+            })
         })
-        .collect();
+        .collect::<Result<_, IngesterError>>()?;
 
     // Parse inner instructions and place them into the correct instruction group
     if let OptionSerializer::Some(inner_instructions_vec) = meta.inner_instructions.as_ref() {
@@ -228,17 +318,28 @@ pub fn parse_instruction_groups(
             for ui_instruction in inner_instructions.instructions.iter() {
                 match ui_instruction {
                     UiInstruction::Compiled(ui_compiled_instruction) => {
-                        let program_id =
-                            accounts[ui_compiled_instruction.program_id_index as usize];
+                        let program_id = account(ui_compiled_instruction.program_id_index)?;
                         let data = bs58::decode(&ui_compiled_instruction.data)
                             .into_vec()
-                            .map_err(|e| IngesterError::ParserError(e.to_string()))?;
+                            .map_err(|e| {
+                                malformed_transaction(format!(
+                                    "Failed to decode inner instruction data: {}",
+                                    e
+                                ))
+                            })?;
                         let accounts = ui_compiled_instruction
                             .accounts
                             .iter()
-                            .map(|account_index| accounts[*account_index as usize])
-                            .collect();
-                        instruction_groups[index as usize]
+                            .map(|account_index| account(*account_index))
+                            .collect::<Result<_, _>>()?;
+                        instruction_groups
+                            .get_mut(index as usize)
+                            .ok_or_else(|| {
+                                malformed_transaction(format!(
+                                    "Inner instructions reference missing instruction {}",
+                                    index
+                                ))
+                            })?
                             .inner_instructions
                             .push(Instruction {
                                 program_id,
@@ -258,3 +359,25 @@ pub fn parse_instruction_groups(
 
     Ok(instruction_groups)
 }
+
+/// Instruction groups of a transaction that does not reference the account compression program
+/// and thus cannot emit compression events. Only the programs of the outer instructions are kept,
+/// since that is all the parser looks at in such transactions, e.g. to tell votes apart.
+pub(crate) fn outer_program_groups(
+    program_id_indices: impl Iterator<Item = usize>,
+    account: impl Fn(usize) -> Option<Pubkey>,
+) -> Result<Vec<InstructionGroup>, String> {
+    program_id_indices
+        .map(|index| {
+            Ok(InstructionGroup {
+                outer_instruction: Instruction {
+                    program_id: account(index)
+                        .ok_or_else(|| format!("Account index {} is out of bounds", index))?,
+                    data: Vec::new(),
+                    accounts: Vec::new(),
+                },
+                inner_instructions: Vec::new(),
+            })
+        })
+        .collect()
+}
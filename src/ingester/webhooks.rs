@@ -0,0 +1,224 @@
+use std::path::Path;
+use std::time::Duration;
+
+use cadence_macros::statsd_count;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::common::typedefs::account::Account;
+use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
+use crate::common::typedefs::token_data::TokenData;
+use crate::metric;
+
+use super::events::{subscribe, IngestionEvent};
+use super::persist::parse_token_data;
+
+pub const SIGNATURE_HEADER: &str = "X-Photon-Signature";
+// Payloads queued for a webhook that is slower than the ingester. Once the queue is full, new
+// payloads for that webhook are dropped so that a single unresponsive endpoint cannot build up
+// unbounded memory.
+const WEBHOOK_QUEUE_SIZE: usize = 10_000;
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+const INITIAL_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A webhook registered by the operator. Each filter matches if it is empty or if the account
+/// matches any of its entries, and a state change is only posted if all filters match.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Secret used to sign payloads. The hex encoded HMAC-SHA256 of the request body is sent in
+    /// the `X-Photon-Signature` header.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Matches both the owner of the compressed account and the owner of its token data.
+    #[serde(default)]
+    pub owners: Vec<SerializablePubkey>,
+    #[serde(default)]
+    pub mints: Vec<SerializablePubkey>,
+    #[serde(default)]
+    pub trees: Vec<SerializablePubkey>,
+}
+
+impl WebhookConfig {
+    fn matches(&self, account: &Account, token_data: Option<&TokenData>) -> bool {
+        let owner_matches = self.owners.is_empty()
+            || self.owners.contains(&account.owner)
+            || token_data.is_some_and(|token_data| self.owners.contains(&token_data.owner));
+        let mint_matches = self.mints.is_empty()
+            || token_data.is_some_and(|token_data| self.mints.contains(&token_data.mint));
+        let tree_matches = self.trees.is_empty() || self.trees.contains(&account.tree);
+        owner_matches && mint_matches && tree_matches
+    }
+}
+
+pub fn load_webhook_configs(path: &Path) -> anyhow::Result<Vec<WebhookConfig>> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WebhookEventType {
+    AccountCreated,
+    AccountSpent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    pub event_type: WebhookEventType,
+    pub slot: u64,
+    /// Unix timestamp of the block, like `blockTime` of the Solana RPC.
+    pub block_time: i64,
+    pub account: Account,
+    pub token_data: Option<TokenData>,
+}
+
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Posts the account changes committed by the ingester to the configured webhooks. Each webhook
+/// is served by its own worker so that a slow endpoint does not delay the others.
+pub fn start_webhook_dispatcher(webhooks: Vec<WebhookConfig>) -> JoinHandle<()> {
+    let mut events = subscribe();
+    tokio::spawn(async move {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap();
+        let queues: Vec<(WebhookConfig, mpsc::Sender<Vec<u8>>)> = webhooks
+            .into_iter()
+            .map(|webhook| {
+                let (sender, receiver) = mpsc::channel(WEBHOOK_QUEUE_SIZE);
+                tokio::spawn(run_webhook_worker(
+                    client.clone(),
+                    webhook.clone(),
+                    receiver,
+                ));
+                (webhook, sender)
+            })
+            .collect();
+        info!("Dispatching state changes to {} webhooks", queues.len());
+
+        loop {
+            let (event_type, account, slot, block_time) = match events.recv().await {
+                Ok(IngestionEvent::AccountCreated {
+                    account,
+                    slot,
+                    block_time,
+                }) => (WebhookEventType::AccountCreated, account, slot, block_time),
+                Ok(IngestionEvent::AccountSpent {
+                    account,
+                    slot,
+                    block_time,
+                }) => (WebhookEventType::AccountSpent, account, slot, block_time),
+                Err(RecvError::Lagged(skipped)) => {
+                    error!(
+                        "Webhook dispatcher lagged behind and dropped {} events",
+                        skipped
+                    );
+                    metric! {
+                        statsd_count!("webhook_events_dropped", skipped);
+                    }
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let token_data = parse_token_data(&account).ok().flatten();
+            let mut body = None;
+            for (webhook, queue) in queues.iter() {
+                if !webhook.matches(&account, token_data.as_ref()) {
+                    continue;
+                }
+                let body = body.get_or_insert_with(|| {
+                    serde_json::to_vec(&WebhookPayload {
+                        event_type,
+                        slot,
+                        block_time,
+                        account: account.clone(),
+                        token_data: token_data.clone(),
+                    })
+                    .unwrap()
+                });
+                if queue.try_send(body.clone()).is_err() {
+                    warn!(
+                        "Webhook queue for {} is full. Dropping payload.",
+                        webhook.url
+                    );
+                    metric! {
+                        statsd_count!("webhook_payload_dropped", 1);
+                    }
+                }
+            }
+        }
+    })
+}
+
+async fn run_webhook_worker(
+    client: reqwest::Client,
+    webhook: WebhookConfig,
+    mut payloads: mpsc::Receiver<Vec<u8>>,
+) {
+    while let Some(body) = payloads.recv().await {
+        if deliver(&client, &webhook, body).await {
+            metric! {
+                statsd_count!("webhook_delivered", 1);
+            }
+        } else {
+            error!(
+                "Giving up on webhook delivery to {} after {} attempts",
+                webhook.url, MAX_DELIVERY_ATTEMPTS
+            );
+            metric! {
+                statsd_count!("webhook_delivery_failed", 1);
+            }
+        }
+    }
+}
+
+async fn deliver(client: &reqwest::Client, webhook: &WebhookConfig, body: Vec<u8>) -> bool {
+    let signature = webhook
+        .secret
+        .as_ref()
+        .map(|secret| sign_payload(secret, &body));
+    let mut backoff = INITIAL_RETRY_BACKOFF;
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        let mut request = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return true,
+            Ok(response) => warn!(
+                "Webhook {} responded with status {} (attempt {})",
+                webhook.url,
+                response.status(),
+                attempt
+            ),
+            Err(e) => warn!(
+                "Failed to post to webhook {}: {} (attempt {})",
+                webhook.url, e, attempt
+            ),
+        }
+        if attempt < MAX_DELIVERY_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+        }
+    }
+    false
+}
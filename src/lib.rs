@@ -1,9 +1,13 @@
 // Required for capturing backtraces
 pub mod api;
+pub mod client;
 pub mod common;
+pub mod conformance;
 pub mod dao;
 pub mod ingester;
 pub mod migration;
 pub mod openapi;
 pub mod snapshot;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 pub mod monitor;
\ No newline at end of file
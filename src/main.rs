@@ -1,58 +1,271 @@
 use std::fs::File;
+use std::io::BufReader;
 
 use async_std::stream::StreamExt;
 use async_stream::stream;
-use clap::Parser;
+use clap::{
+    builder::RangedU64ValueParser, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand,
+    ValueEnum,
+};
 use futures::pin_mut;
-use jsonrpsee::server::ServerHandle;
-use log::{error, info};
-use photon_indexer::api::{self, api::PhotonApi};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Method;
+use log::{error, info, warn};
+use photon_indexer::api::{
+    self,
+    api::{ApiConfig, PhotonApi},
+    cache::ApiCache,
+    cors::CorsConfig,
+    graphql::GraphQLLimits,
+    grpc::run_grpc_server,
+    method::{get_indexer_health::HEALTH_CHECK_SLOT_DISTANCE, utils::PAGE_LIMIT},
+    proof_cache::set_proof_cache_capacity,
+    rate_limit::{RateLimit, RateLimitConfig},
+    read_replicas::ReadReplicas,
+    request_limits::{MethodFilter, RequestLimits},
+};
 
+use photon_indexer::common::account_data_compression::set_account_data_compression;
+use photon_indexer::common::account_data_deduplication::set_account_data_deduplication;
+use photon_indexer::common::config_file::{layered_args, load_config_file, render_config};
+use photon_indexer::common::db_pool::{
+    continously_report_pool_metrics, register_pg_pool, register_sqlite_pool, DatabasePoolConfig,
+    DEFAULT_ACQUIRE_TIMEOUT,
+};
+use photon_indexer::common::metadata::{
+    get_or_create_cursor_secret, get_schema_fingerprint, record_indexing_start_slot,
+    set_feature_flag, set_schema_fingerprint,
+};
+use photon_indexer::common::rate_limited_rpc_sender::RpcRateLimitConfig;
+use photon_indexer::common::shutdown::{request_shutdown, shutdown_requested, shutdown_signal};
+use photon_indexer::common::supervisor::supervise;
+use photon_indexer::common::telemetry::shutdown_telemetry;
+use photon_indexer::common::typedefs::cursor::set_cursor_secret;
+use photon_indexer::common::unspent_hash_filter::{enable_unspent_hash_filter, BloomFilter};
 use photon_indexer::common::{
-    fetch_block_parent_slot, fetch_current_slot_with_infinite_retry, get_network_start_slot,
-    get_rpc_client, setup_logging, setup_metrics, setup_pg_pool, LoggingFormat,
+    fetch_block_parent_slot, fetch_current_slot_with_infinite_retry, get_failover_rpc_client,
+    get_network_start_slot, get_rate_limited_rpc_client, get_rpc_client, setup_logging_with_otlp,
+    setup_metrics, setup_pg_pool_with_config, LoggingFormat,
+};
+use photon_indexer::conformance::{
+    run_conformance, ConformanceConfig, DEVNET_NULLIFIER_QUEUE, DEVNET_RPC_URL, DEVNET_STATE_TREE,
 };
 
+use photon_indexer::ingester::backfill::{backfill_slot_range, BackfillBlockSource};
+use photon_indexer::ingester::block_cache::{replay_cached_blocks, set_block_cache_dir};
+use photon_indexer::ingester::dead_letter::{fetch_failed_blocks, retry_failed_blocks};
+use photon_indexer::ingester::error::IngesterError;
+use photon_indexer::ingester::fetchers::bigtable::{BigtableBlockFetcher, BIGTABLE_API_URL};
 use photon_indexer::ingester::fetchers::BlockStreamConfig;
+use photon_indexer::ingester::gaps::continously_refetch_slot_gaps;
+use photon_indexer::ingester::indexer::backpressure::set_pipeline_watermarks;
 use photon_indexer::ingester::indexer::{
-    fetch_last_indexed_slot_with_infinite_retry, index_block_stream,
+    fetch_last_indexed_slot_with_infinite_retry, index_block_stream, set_parse_workers,
 };
+use photon_indexer::ingester::persist::batch_size::SqlBatchConfig;
+use photon_indexer::ingester::persist::in_memory_tree::InMemoryTrees;
+use photon_indexer::ingester::persist::node_history::set_proof_history_window;
+use photon_indexer::ingester::pruner::{
+    continously_prune_spent_accounts, prune_spent_accounts, MIN_PRUNE_RETENTION_SLOTS,
+};
+use photon_indexer::ingester::tree_info::load_tree_infos;
+use photon_indexer::ingester::webhooks::{load_webhook_configs, start_webhook_dispatcher};
+use photon_indexer::ingester::IndexerContext;
 use photon_indexer::migration::{
+    schema_fingerprint,
     sea_orm::{DatabaseBackend, DatabaseConnection, SqlxPostgresConnector, SqlxSqliteConnector},
     Migrator, MigratorTrait,
 };
 
-use photon_indexer::monitor::continously_monitor_photon;
+use photon_indexer::monitor::leaf_audit::{audit_tree_leaves, LeafSelection};
+use photon_indexer::monitor::root_consistency::{
+    check_root_consistency, continously_check_root_consistency,
+};
+use photon_indexer::monitor::{continously_monitor_photon, set_slot_poll_intervals};
+use photon_indexer::snapshot::owner_snapshot::owner_snapshot_at_slot;
+use photon_indexer::snapshot::state_snapshot::{
+    bootstrap_from_state_snapshot_url, export_state_snapshot_to_file, import_state_snapshot,
+};
 use photon_indexer::snapshot::{
     get_snapshot_files_with_metadata, load_block_stream_from_directory_adapter, DirectoryAdapter,
 };
 use solana_client::nonblocking::rpc_client::RpcClient;
-use sqlx::{
-    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
-    SqlitePool,
-};
+use solana_sdk::{pubkey::Pubkey, signature::read_keypair_file};
+use sqlx::{sqlite::SqliteConnectOptions, Sqlite, SqlitePool};
 use std::env::temp_dir;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Photon: a compressed transaction Solana indexer
 #[derive(Parser, Debug)]
 #[command(version, about)]
 struct Args {
+    /// Logging format
+    #[arg(short, long, global = true, default_value_t = LoggingFormat::Standard)]
+    logging_format: LoggingFormat,
+
+    /// OTLP gRPC endpoint, e.g. http://localhost:4317, to export the spans of the indexing
+    /// pipeline and of the API methods to
+    #[arg(long, global = true, default_value = None)]
+    otlp_endpoint: Option<String>,
+
+    // Without a subcommand, Photon serves with these arguments, as it did before the subcommands
+    // were added.
+    #[command(flatten)]
+    serve: ServeArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// TOML or YAML file with the arguments to serve with, keyed by their long names, e.g.
+    /// `db-url = "postgres://..."`. Arguments passed on the command line take precedence over
+    /// `PHOTON_*` environment variables, e.g. `PHOTON_DB_URL`, which take precedence over the file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Print the arguments that Photon would serve with as a TOML config file and exit
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    print_config: bool,
+
     /// Port to expose the local Photon API
     // We use a random default port to avoid conflicts with other services
     #[arg(short, long, default_value_t = 8784)]
     port: u16,
 
+    /// Port to expose the gRPC API on, which serves the core read methods. Disabled by default
+    #[arg(long, default_value = None)]
+    grpc_port: Option<u16>,
+
+    /// Serve GraphQL queries at `/graphql` on the API port
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    enable_graphql: bool,
+
+    /// Maximum nesting depth of GraphQL queries
+    #[arg(long, default_value_t = GraphQLLimits::default().max_depth)]
+    graphql_max_depth: usize,
+
+    /// Maximum complexity of GraphQL queries. Fields cost 1 and paginated fields cost their
+    /// selection times the requested limit
+    #[arg(long, default_value_t = GraphQLLimits::default().max_complexity)]
+    graphql_max_complexity: usize,
+
+    /// Max number of API requests per second per client, except for proof requests. Clients are
+    /// identified by their `x-api-key` header if the key is in `--rate-limit-api-keys`, or else by
+    /// the `X-Forwarded-For` address set by the `--trusted-proxies` over HTTP and by the address of
+    /// their connection over gRPC. Unlimited by default
+    #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    read_requests_per_second: Option<u32>,
+
+    /// Max number of requests that a client can make at once on top of the read rate. Defaults to
+    /// the read rate
+    #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    read_requests_burst: Option<u32>,
+
+    /// Max number of proof requests per second per client, e.g. for `getValidityProof`. Unlimited
+    /// by default
+    #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    proof_requests_per_second: Option<u32>,
+
+    /// Max number of proof requests that a client can make at once on top of the proof rate.
+    /// Defaults to the proof rate
+    #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    proof_requests_burst: Option<u32>,
+
+    /// API keys that clients are rate limited by, separated by commas. Requests with other keys
+    /// are limited by their address
+    #[arg(long, value_delimiter = ',')]
+    rate_limit_api_keys: Vec<String>,
+
+    /// Number of proxies in front of Photon that append the address they received a request from
+    /// to `X-Forwarded-For`. Required with rate limits, since the HTTP server cannot tell clients
+    /// apart by their connection
+    #[arg(long, default_value_t = 0)]
+    trusted_proxies: usize,
+
+    /// Origins of the web pages that may call the API from a browser, separated by commas, e.g.
+    /// `https://app.example.com`. `*` allows any origin
+    #[arg(long, value_delimiter = ',', default_value = "*", value_parser = HeaderValue::from_str)]
+    cors_allowed_origins: Vec<HeaderValue>,
+
+    /// HTTP methods that browsers may call the API with, separated by commas
+    #[arg(long, value_delimiter = ',', default_value = "GET,POST", value_parser = Method::from_str)]
+    cors_allowed_methods: Vec<Method>,
+
+    /// Request headers that browsers may send in addition to `Content-Type`, `X-Request-Id` and
+    /// `x-api-key`, separated by commas
+    #[arg(long, value_delimiter = ',', value_parser = HeaderName::from_str)]
+    cors_allowed_headers: Vec<HeaderName>,
+
+    /// Number of seconds that browsers may cache the answer to a preflight request
+    #[arg(long, default_value_t = CorsConfig::default().max_age.as_secs())]
+    cors_max_age: u64,
+
+    /// Only serve these JSON-RPC methods, separated by commas. `liveness` and `readiness` are
+    /// always served. Serves all methods by default
+    #[arg(long, value_delimiter = ',')]
+    enabled_methods: Option<Vec<String>>,
+
+    /// JSON-RPC methods not to serve, separated by commas, e.g. expensive analytics methods such as
+    /// `getCompressionStats`
+    #[arg(long, value_delimiter = ',')]
+    disabled_methods: Vec<String>,
+
+    /// Max size of API request bodies in bytes
+    #[arg(long, default_value_t = RequestLimits::default().max_body_size)]
+    max_request_body_size: u32,
+
+    /// Max number of calls in a JSON-RPC batch. Unlimited by default
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    max_batch_len: Option<usize>,
+
+    /// Number of slots that the indexed slot may lag behind the RPC node before `/readyz` fails,
+    /// so that load balancers stop routing requests to the node
+    #[arg(long, default_value_t = HEALTH_CHECK_SLOT_DISTANCE as u64)]
+    readiness_max_slots_behind: u64,
+
+    /// Max number of accounts per page of `getCompressedAccountsByOwner`. Requests for larger pages
+    /// get pages of this size, with a cursor to the next page
+    #[arg(long, default_value_t = PAGE_LIMIT, value_parser = RangedU64ValueParser::<u64>::new().range(1..=PAGE_LIMIT))]
+    max_accounts_by_owner_page_size: u64,
+
     /// URL of the RPC server
     #[arg(short, long, default_value = "http://127.0.0.1:8899")]
     rpc_url: String,
 
+    /// URLs of further RPC servers, separated by commas. Requests then go to the server with the
+    /// lowest recent latency, and fail over to the next one when a server cannot be reached, fails
+    /// with a server error or reports itself as unhealthy. Servers that failed are avoided for a
+    /// while.
+    #[arg(long, value_delimiter = ',')]
+    fallback_rpc_url: Vec<String>,
+
+    /// Max number of requests per second to send to the RPC server. Unlimited by default
+    #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    rpc_requests_per_second: Option<u32>,
+
+    /// How often requests that the RPC server throttled or failed with a server error are retried
+    /// with exponential backoff before they fail
+    #[arg(long, default_value_t = 5)]
+    rpc_max_retries: u32,
+
     /// DB URL to store indexing data. By default we use an in-memory SQLite database.
-    #[arg(short, long)]
+    #[arg(short, long, value_parser = parse_db_url)]
     db_url: Option<String>,
 
-    /// The start slot to begin indexing from. Defaults to the last indexed slot in the database plus
-    /// one.  
+    /// DB URLs of read replicas of the database at `--db-url`, separated by commas. API queries
+    /// are spread over the replicas that are reachable and at most 20 slots behind, and go to the
+    /// primary database while none is.
+    #[arg(long, value_delimiter = ',', value_parser = parse_db_url)]
+    read_replica_db_url: Vec<String>,
+
+    /// The start slot to begin indexing from. Defaults to the slot after the ingestion checkpoint,
+    /// which is committed together with the state of the last indexed block.
+    /// Queries of slots before the first indexed slot, which is recorded in the database, fail.
     #[arg(short, long)]
     start_slot: Option<String>,
 
@@ -60,15 +273,62 @@ struct Args {
     #[arg(long, default_value_t = 10)]
     max_db_conn: u32,
 
-    /// Logging format
-    #[arg(short, long, default_value_t = LoggingFormat::Standard)]
-    logging_format: LoggingFormat,
+    /// Seconds that a query waits for a connection while all connections of the pool are in use.
+    /// API requests that time out fail with the retryable `database_busy` error.
+    #[arg(long, default_value_t = DEFAULT_ACQUIRE_TIMEOUT.as_secs())]
+    db_acquire_timeout: u64,
+
+    /// Close database connections that have been idle for this many seconds. By default idle
+    /// connections are kept open.
+    #[arg(long)]
+    db_idle_timeout: Option<u64>,
+
+    /// Cancel Postgres queries of the API that run for longer than this many seconds, which then
+    /// fail with the retryable `query_timeout` error. Applies to the read replicas, and to the
+    /// primary database only with `--disable-indexing`, so that large writes are not cancelled.
+    #[arg(long)]
+    db_statement_timeout: Option<u64>,
+
+    /// Max number of rows per SQL statement. By default it is derived from the parameter limit of
+    /// the database and the number of columns of the table.
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    max_sql_batch_size: Option<usize>,
 
     /// Max number of blocks to fetch concurrently. Generally, this should be set to be as high
     /// as possible without reaching RPC rate limits.
-    #[arg(short, long)]
+    #[arg(short, long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
     max_concurrent_block_fetches: Option<usize>,
 
+    /// Number of blocks to parse concurrently. Defaults to the number of CPUs.
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    parse_workers: Option<usize>,
+
+    /// Number of fetched blocks waiting to be persisted at which block fetching pauses, so that a
+    /// slow database does not make blocks pile up in memory.
+    #[arg(long, default_value_t = 200, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pipeline_high_watermark_blocks: usize,
+
+    /// Number of fetched blocks waiting to be persisted at which paused block fetching resumes.
+    /// Capped at --pipeline-high-watermark-blocks.
+    #[arg(long, default_value_t = 100)]
+    pipeline_low_watermark_blocks: usize,
+
+    /// Directory to keep a zstd compressed copy of every indexed block in, so that the blocks can
+    /// be indexed again with `photon replay`, e.g. after a schema change or a parser fix
+    #[arg(long)]
+    block_cache_dir: Option<PathBuf>,
+
+    /// Shortest interval in milliseconds at which the latest slot is polled from the RPC node. The
+    /// interval is shortened towards it while the indexer is behind the latest slot.
+    #[arg(long, default_value_t = 100, value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    min_slot_poll_interval_ms: u64,
+
+    /// Longest interval in milliseconds at which the latest slot is polled from the RPC node. The
+    /// interval is lengthened towards it while the indexer is caught up, which saves RPC requests
+    /// without delaying blocks by much more than a slot.
+    #[arg(long, default_value_t = 400, value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    max_slot_poll_interval_ms: u64,
+
     /// Light Prover url to use for verifying proofs
     #[arg(long, default_value = "http://127.0.0.1:3001")]
     prover_url: String,
@@ -77,11 +337,54 @@ struct Args {
     #[arg(long, default_value = None)]
     snapshot_dir: Option<String>,
 
+    /// URL of a state snapshot created with `photon snapshot export`. If the database is
+    /// empty, the snapshot is downloaded, verified against the checksum at `<url>.sha256` and
+    /// loaded before indexing starts.
+    #[arg(long, default_value = None, conflicts_with = "snapshot_dir")]
+    snapshot_url: Option<String>,
+
     #[arg(short, long, default_value = None)]
     /// Yellowstone gRPC URL. If it's inputed, then the indexer will use gRPC to fetch new blocks
     /// instead of polling. It will still use RPC to fetch blocks if
     grpc_url: Option<String>,
 
+    /// Only stream transactions that use compression over gRPC. This reduces bandwidth and
+    /// latency, but non-compression transactions are then missing from
+    /// getLatestNonVotingSignatures.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    grpc_compression_transactions_only: bool,
+
+    /// Unix socket of the Photon Geyser plugin (see `geyser-plugin/`) of a validator on the same
+    /// machine. Blocks are then pushed by the validator instead of being polled. RPC is still used
+    /// to fetch blocks while the socket is disconnected or behind.
+    #[arg(long, default_value = None, conflicts_with = "grpc_url")]
+    geyser_plugin_socket: Option<String>,
+
+    /// Poll the signatures of the account compression program with getSignaturesForAddress and
+    /// fetch only their transactions and block headers instead of whole blocks. This saves most of
+    /// the bandwidth and RPC load while there is little compression activity, but non-compression
+    /// transactions are then missing from getLatestNonVotingSignatures, and slot gaps are not
+    /// refetched.
+    #[arg(
+        long,
+        action = clap::ArgAction::SetTrue,
+        conflicts_with_all = ["grpc_url", "geyser_plugin_socket"]
+    )]
+    poll_compression_signatures: bool,
+
+    /// WebSocket URL of an RPC node, e.g. wss://api.devnet.solana.com. Compression transactions
+    /// are then fetched as soon as a logsSubscribe subscription announces them instead of polling
+    /// blocks. Whole blocks are still polled until the subscription covers every slot that is not
+    /// indexed, i.e. at startup and after reconnects. Like with --poll-compression-signatures,
+    /// non-compression transactions are missing from getLatestNonVotingSignatures, and slot gaps
+    /// are not refetched.
+    #[arg(
+        long,
+        default_value = None,
+        conflicts_with_all = ["grpc_url", "geyser_plugin_socket", "poll_compression_signatures"]
+    )]
+    logs_websocket_url: Option<String>,
+
     /// Disable indexing
     #[arg(long, action = clap::ArgAction::SetTrue)]
     disable_indexing: bool,
@@ -94,19 +397,736 @@ struct Args {
     /// If provided, metrics will be sent to the specified statsd server.
     #[arg(long, default_value = None)]
     metrics_endpoint: Option<String>,
+
+    /// Path to a JSON file with the webhooks to post state changes to
+    #[arg(long, default_value = None)]
+    webhook_config: Option<String>,
+
+    /// Store account data and token account TLV data zstd compressed. Reads are transparent, so
+    /// this can be toggled at any time without migrating existing data.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    compress_account_data: bool,
+
+    /// Store account data that is shared by many accounts only once. Like compression, this can
+    /// be toggled at any time.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    deduplicate_account_data: bool,
+
+    /// Number of results of getCompressedAccount, getCompressedTokenAccountBalance and
+    /// getCompressedTokenBalancesByOwner to keep in memory each. The cache is only used by
+    /// instances that index and have no read replicas, since the indexer keeps it up to date.
+    /// Set to 0 to disable it.
+    #[arg(long, default_value_t = 10_000)]
+    api_cache_capacity: usize,
+
+    /// Number of Merkle proofs to keep in memory. Proofs are cached per tree until its root
+    /// advances, so they are also cached with read replicas. Set to 0 to disable the cache.
+    #[arg(long, default_value_t = 10_000)]
+    proof_cache_capacity: usize,
+
+    /// Number of state tree nodes below the top 12 levels to keep in memory for proofs. The top
+    /// levels of the trees are kept in full. Like the API cache, the in-memory trees are only used
+    /// by instances that index and have no read replicas. Set to 0 to disable them.
+    #[arg(long, default_value_t = 100_000)]
+    in_memory_tree_capacity: usize,
+
+    /// Answer getCompressedAccount and getMultipleCompressedAccounts lookups of hashes that are
+    /// missing from an in-memory filter of the unspent accounts without querying the database.
+    /// Only enable it if this instance is the only process writing to the database: accounts
+    /// written by `photon backfill`, `photon failed-blocks retry` or another indexer are reported
+    /// as missing until it restarts. Has no effect without indexing.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    unspent_hash_filter: bool,
+
+    /// Number of sequence numbers per state tree for which replaced tree nodes are kept, so that
+    /// getHistoricalCompressedAccountProof can serve proofs against older roots. The trees on
+    /// chain keep their last 2400 roots. Every indexed leaf then writes a row per tree level, so
+    /// the history is disabled by default. Instances that serve the method need the same window.
+    #[arg(long, default_value_t = 0)]
+    proof_history_window: u64,
+
+    /// Delete accounts and token accounts once they have been spent for this many slots. Spent
+    /// accounts are kept by default.
+    #[arg(
+        long,
+        value_parser = RangedU64ValueParser::<u64>::new().range(MIN_PRUNE_RETENTION_SLOTS..)
+    )]
+    prune_spent_accounts_after_slots: Option<u64>,
+
+    /// Interval in seconds at which the root of every tree is recomputed from its persisted leaves
+    /// and compared with the persisted root and the roots of the tree account on chain. Disabled
+    /// by default, since every check reads all leaves. Set e.g. to 3600 to check hourly.
+    #[arg(long, default_value_t = 0)]
+    root_consistency_check_interval: u64,
+
+    /// Secret that pagination cursors are signed with, so that clients cannot forge cursors.
+    /// Instances that serve the same clients need the same secret. By default, a secret is
+    /// generated once and stored in the database, which API only instances that run against a
+    /// read replica can read but not create.
+    #[arg(long, default_value = None)]
+    cursor_secret: Option<String>,
+
+    /// Seconds to wait on SIGINT or SIGTERM for the indexer to commit the blocks it is persisting
+    /// and for the API server to answer the requests in flight, before they are aborted
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout: u64,
 }
 
-async fn start_api_server(
-    db: Arc<DatabaseConnection>,
-    rpc_client: Arc<RpcClient>,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Index new blocks and serve the API. This is the default if no subcommand is given.
+    Serve(Box<ServeArgs>),
+    /// Apply the pending migrations to the database
+    Migrate(MigrateArgs),
+    /// Index the blocks of a slot range that are not indexed yet, e.g. to fill in history from
+    /// before the indexer was started
+    Backfill(BackfillArgs),
+    /// Export the indexed state to a state snapshot file or restore it from one
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Recompute the root of every tree from its persisted leaves and compare it with the
+    /// persisted root and the roots of the tree account on chain, and check a sample of leaves
+    /// against the transactions that created them. Exits with 1 if anything does not match, and
+    /// with 2 if the checks could not be completed.
+    Verify(VerifyArgs),
+    /// Send compress, transfer and decompress transactions to devnet and check that a running
+    /// Photon instance indexes them correctly
+    Conformance(ConformanceArgs),
+    /// Print the accounts and token accounts that an owner held at a past slot, as reconstructed
+    /// from the indexed transaction history
+    DumpOwner(DumpOwnerArgs),
+    /// Delete the accounts and token accounts that have been spent for more than the given number
+    /// of slots
+    PruneSpentAccounts(PruneSpentAccountsArgs),
+    /// Index the blocks of a block cache directory again, without fetching them from RPC. Meant
+    /// for a fresh database, since transactions that are already indexed are skipped.
+    Replay(ReplayArgs),
+    /// Inspect the blocks that the indexer could not index and retry them once the cause is fixed
+    FailedBlocks(FailedBlocksArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct MigrateArgs {
+    /// DB URL of the indexer database
+    #[arg(short, long, value_parser = parse_db_url)]
+    db_url: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct BackfillArgs {
+    /// DB URL of the indexer database
+    #[arg(short, long, value_parser = parse_db_url)]
+    db_url: String,
+
+    /// URL of the RPC server
+    #[arg(short, long, default_value = "http://127.0.0.1:8899")]
+    rpc_url: String,
+
+    /// RPC server with the full ledger history that slots are fetched from if the RPC server
+    /// has pruned them
+    #[arg(long)]
+    archival_rpc_url: Option<String>,
+
+    /// Max number of requests per second to send to the RPC servers. Unlimited by default
+    #[arg(long, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    rpc_requests_per_second: Option<u32>,
+
+    /// How often requests that the RPC servers throttled or failed with a server error are
+    /// retried with exponential backoff before they fail
+    #[arg(long, default_value_t = 5)]
+    rpc_max_retries: u32,
+
+    /// Where to fetch the blocks from. Bigtable requires an access token in the
+    /// `BIGTABLE_ACCESS_TOKEN` environment variable unless Photon runs on GCE with a service
+    /// account that can read the instance.
+    #[arg(long, value_enum, default_value_t = BackfillSource::Rpc)]
+    block_source: BackfillSource,
+
+    /// GCP project of the Bigtable instance that stores the ledger
+    #[arg(long, required_if_eq("block_source", "bigtable"))]
+    bigtable_project: Option<String>,
+
+    /// Bigtable instance that stores the ledger
+    #[arg(long, default_value = "solana-ledger")]
+    bigtable_instance: String,
+
+    /// First slot to index
+    #[arg(long)]
+    start_slot: u64,
+
+    /// Last slot to index (inclusive)
+    #[arg(long)]
+    end_slot: u64,
+
+    /// Max number of blocks to fetch concurrently
+    #[arg(short, long, value_parser = RangedU64ValueParser::<usize>::new().range(1..), default_value_t = 20)]
+    max_concurrent_block_fetches: usize,
+
+    /// Max database connections to use in database pool
+    #[arg(long, default_value_t = 10)]
+    max_db_conn: u32,
+
+    /// Directory to keep a copy of the backfilled blocks in, like `photon --block-cache-dir`
+    #[arg(long)]
+    block_cache_dir: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum BackfillSource {
+    Rpc,
+    Bigtable,
+}
+
+#[derive(clap::Args, Debug)]
+struct FailedBlocksArgs {
+    #[command(subcommand)]
+    command: FailedBlocksCommand,
+
+    /// DB URL of the indexer database
+    #[arg(short, long, value_parser = parse_db_url)]
+    db_url: String,
+
+    /// Max database connections to use in database pool
+    #[arg(long, default_value_t = 10)]
+    max_db_conn: u32,
+}
+
+#[derive(Subcommand, Debug)]
+enum FailedBlocksCommand {
+    /// List the blocks in the dead-letter queue with the error they failed with
+    List,
+    /// Index the blocks in the dead-letter queue in slot order. Blocks that are indexed are
+    /// removed from the queue.
+    Retry {
+        /// Only retry the block at this slot
+        #[arg(long)]
+        slot: Option<u64>,
+
+        /// URL of the RPC server that the parameters of new trees are fetched from
+        #[arg(short, long, default_value = "http://127.0.0.1:8899")]
+        rpc_url: String,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// DB URL of the indexer database
+    #[arg(short, long, value_parser = parse_db_url)]
+    db_url: String,
+
+    /// Block cache directory that `photon --block-cache-dir` wrote the blocks to
+    #[arg(long)]
+    block_cache_dir: PathBuf,
+
+    /// URL of the RPC server that the parameters of new trees are fetched from
+    #[arg(short, long, default_value = "http://127.0.0.1:8899")]
+    rpc_url: String,
+
+    /// First slot to replay
+    #[arg(long, default_value_t = 0)]
+    start_slot: u64,
+
+    /// Last slot to replay (inclusive). Defaults to the last cached slot
+    #[arg(long, default_value_t = u64::MAX)]
+    end_slot: u64,
+
+    /// Max database connections to use in database pool
+    #[arg(long, default_value_t = 10)]
+    max_db_conn: u32,
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Export the indexed state to a snapshot file. The checksum is written to `<output>.sha256`,
+    /// so the file can be served for `photon --snapshot-url`.
+    Export {
+        /// DB URL of the indexer database
+        #[arg(short, long, value_parser = parse_db_url)]
+        db_url: String,
+
+        /// Path of the snapshot file to write
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Restore the indexed state from a snapshot file into an empty, migrated database
+    Import {
+        /// DB URL of the indexer database
+        #[arg(short, long, value_parser = parse_db_url)]
+        db_url: String,
+
+        /// Path of the snapshot file to read
+        #[arg(long)]
+        input: PathBuf,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    /// DB URL of the indexer database
+    #[arg(short, long, value_parser = parse_db_url)]
+    db_url: String,
+
+    /// URL of the RPC server that the tree accounts and transactions are fetched from
+    #[arg(short, long, default_value = "http://127.0.0.1:8899")]
+    rpc_url: String,
+
+    /// Number of random leaves per tree whose account is checked against the transaction that
+    /// created it. 0 skips the leaf audit
+    #[arg(long, default_value_t = 100)]
+    leaf_sample_size: u64,
+
+    /// Check every leaf instead of a sample
+    #[arg(long, conflicts_with = "leaf_sample_size")]
+    all_leaves: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct PruneSpentAccountsArgs {
+    /// DB URL of the indexer database
+    #[arg(short, long, value_parser = parse_db_url)]
+    db_url: String,
+
+    /// Number of slots that spent accounts are kept for
+    #[arg(
+        long,
+        value_parser = RangedU64ValueParser::<u64>::new().range(MIN_PRUNE_RETENTION_SLOTS..)
+    )]
+    retention_slots: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct DumpOwnerArgs {
+    /// DB URL of the indexer database
+    #[arg(short, long, value_parser = parse_db_url)]
+    db_url: String,
+
+    /// Owner of the accounts
+    #[arg(long)]
+    owner: Pubkey,
+
+    /// Slot to reconstruct the accounts at
+    #[arg(long)]
+    at_slot: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConformanceArgs {
+    /// URL of the Photon API to check
+    #[arg(long, default_value = "http://127.0.0.1:8784")]
+    photon_url: String,
+
+    /// URL of the RPC server to send the transactions to
+    #[arg(long, default_value = DEVNET_RPC_URL)]
+    rpc_url: String,
+
+    /// Keypair that pays for the transactions. Defaults to the Solana CLI keypair.
+    #[arg(long, default_value = None)]
+    keypair: Option<String>,
+
+    /// Lamports to compress, transfer and decompress
+    #[arg(long, default_value_t = 1_000_000)]
+    lamports: u64,
+
+    /// State tree to write compressed accounts to
+    #[arg(long, default_value_t = DEVNET_STATE_TREE)]
+    state_tree: Pubkey,
+
+    /// Nullifier queue of the state tree
+    #[arg(long, default_value_t = DEVNET_NULLIFIER_QUEUE)]
+    nullifier_queue: Pubkey,
+
+    /// Seconds to wait for each transaction to be indexed
+    #[arg(long, default_value_t = 120)]
+    indexing_timeout: u64,
+}
+
+async fn record_instance_metadata(
+    db: &DatabaseConnection,
+    compress_account_data: bool,
+    deduplicate_account_data: bool,
+) {
+    let fingerprint = schema_fingerprint();
+    if let Some(previous) = get_schema_fingerprint(db).await.unwrap() {
+        if previous != fingerprint {
+            info!(
+                "Schema fingerprint changed from {} to {}",
+                previous, fingerprint
+            );
+        }
+    }
+    set_schema_fingerprint(db, fingerprint).await.unwrap();
+    set_feature_flag(db, "compress_account_data", compress_account_data)
+        .await
+        .unwrap();
+    set_feature_flag(db, "deduplicate_account_data", deduplicate_account_data)
+        .await
+        .unwrap();
+}
+
+async fn run_dump_owner_command(args: DumpOwnerArgs) {
+    let db = connect_to_database(args.db_url, 1).await;
+    let sql_batch_config = SqlBatchConfig::detect(db.as_ref()).await.unwrap();
+    match owner_snapshot_at_slot(
+        db.as_ref(),
+        sql_batch_config,
+        args.owner.into(),
+        args.at_slot,
+    )
+    .await
+    {
+        Ok(snapshot) => println!("{}", serde_json::to_string_pretty(&snapshot).unwrap()),
+        Err(e) => {
+            error!(
+                "Failed to reconstruct the accounts of {}: {:#}",
+                args.owner, e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_prune_spent_accounts_command(args: PruneSpentAccountsArgs) {
+    let db = connect_to_database(args.db_url, 1).await;
+    let sql_batch_config = SqlBatchConfig::detect(db.as_ref()).await.unwrap();
+    match prune_spent_accounts(db.as_ref(), sql_batch_config, args.retention_slots).await {
+        Ok(pruned_accounts) => info!("Pruned {} spent accounts", pruned_accounts),
+        Err(e) => {
+            error!("Failed to prune spent accounts: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_replay_command(args: ReplayArgs) {
+    let db = connect_to_database(args.db_url, args.max_db_conn).await;
+    let sql_batch_config = SqlBatchConfig::detect(db.as_ref()).await.unwrap();
+    load_tree_infos(db.as_ref()).await.unwrap();
+    let indexer = IndexerContext::new(db)
+        .with_rpc_client(get_rpc_client(&args.rpc_url))
+        .with_sql_batch_config(sql_batch_config);
+    match replay_cached_blocks(
+        &indexer,
+        &args.block_cache_dir,
+        args.start_slot,
+        args.end_slot,
+    )
+    .await
+    {
+        Ok(replayed_blocks) => info!("Replayed {} cached blocks", replayed_blocks),
+        Err(e) => {
+            error!("Failed to replay cached blocks: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_migrate_command(args: MigrateArgs) {
+    let db = connect_to_database(args.db_url, 1).await;
+    info!("Running migrations...");
+    if let Err(e) = Migrator::up(db.as_ref(), None).await {
+        error!("Failed to run migrations: {}", e);
+        std::process::exit(1);
+    }
+    info!("Database is up to date");
+}
+
+async fn run_backfill_command(args: BackfillArgs) {
+    if args.start_slot > args.end_slot {
+        error!(
+            "Start slot {} is after end slot {}",
+            args.start_slot, args.end_slot
+        );
+        std::process::exit(1);
+    }
+    let db = connect_to_database(args.db_url, args.max_db_conn).await;
+    let sql_batch_config = SqlBatchConfig::detect(db.as_ref()).await.unwrap();
+    load_tree_infos(db.as_ref()).await.unwrap();
+    set_block_cache_dir(args.block_cache_dir);
+    let rate_limit_config = RpcRateLimitConfig {
+        requests_per_second: args.rpc_requests_per_second,
+        max_retries: args.rpc_max_retries,
+        ..Default::default()
+    };
+    let rpc_client = get_rate_limited_rpc_client(&args.rpc_url, rate_limit_config.clone());
+    let block_source = match args.block_source {
+        BackfillSource::Rpc => BackfillBlockSource::Rpc {
+            rpc_client: rpc_client.clone(),
+            archival_rpc_client: args
+                .archival_rpc_url
+                .as_deref()
+                .map(|url| get_rate_limited_rpc_client(url, rate_limit_config.clone())),
+        },
+        BackfillSource::Bigtable => {
+            BackfillBlockSource::Bigtable(Arc::new(BigtableBlockFetcher::new(
+                BIGTABLE_API_URL,
+                &args.bigtable_project.unwrap(),
+                &args.bigtable_instance,
+                std::env::var("BIGTABLE_ACCESS_TOKEN").ok(),
+            )))
+        }
+    };
+    info!(
+        "Backfilling slots {} to {}...",
+        args.start_slot, args.end_slot
+    );
+    let indexer = IndexerContext::new(db)
+        .with_rpc_client(rpc_client)
+        .with_sql_batch_config(sql_batch_config);
+    match backfill_slot_range(
+        &indexer,
+        block_source,
+        args.start_slot,
+        args.end_slot,
+        args.max_concurrent_block_fetches,
+    )
+    .await
+    {
+        Ok(()) => info!(
+            "Finished backfilling slots {} to {}",
+            args.start_slot, args.end_slot
+        ),
+        Err(e @ IngesterError::SlotUnavailable { .. }) => {
+            error!(
+                "{}. Rerun the backfill against an RPC node with the full ledger history, pass \
+                --archival-rpc-url to fetch pruned slots from one, or use --block-source=bigtable",
+                e
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            error!("Failed to backfill slots: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_failed_blocks_command(args: FailedBlocksArgs) {
+    let db = connect_to_database(args.db_url, args.max_db_conn).await;
+    match args.command {
+        FailedBlocksCommand::List => match fetch_failed_blocks(db.as_ref()).await {
+            Ok(failed_blocks) => {
+                for failed_block in &failed_blocks {
+                    println!("{}\t{}", failed_block.slot, failed_block.error);
+                }
+                info!("{} blocks in the dead-letter queue", failed_blocks.len());
+            }
+            Err(e) => {
+                error!("Failed to list failed blocks: {}", e);
+                std::process::exit(1);
+            }
+        },
+        FailedBlocksCommand::Retry { slot, rpc_url } => {
+            let sql_batch_config = SqlBatchConfig::detect(db.as_ref()).await.unwrap();
+            load_tree_infos(db.as_ref()).await.unwrap();
+            let indexer = IndexerContext::new(db)
+                .with_rpc_client(get_rpc_client(&rpc_url))
+                .with_sql_batch_config(sql_batch_config);
+            match retry_failed_blocks(&indexer, slot).await {
+                Ok(summary) => {
+                    info!(
+                        "Indexed {} blocks. {} blocks failed again",
+                        summary.indexed, summary.failed
+                    );
+                    if summary.failed > 0 {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to retry failed blocks: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+async fn run_snapshot_command(command: SnapshotCommand) {
+    let result = match command {
+        SnapshotCommand::Export { db_url, output } => {
+            let db = connect_to_database(db_url, 1).await;
+            info!("Exporting state snapshot to {:?}...", output);
+            export_state_snapshot_to_file(db.as_ref(), &output)
+                .await
+                .map(|summary| {
+                    info!(
+                        "Exported {} rows at slot {} to {:?}",
+                        summary.rows, summary.slot, output
+                    )
+                })
+        }
+        SnapshotCommand::Import { db_url, input } => {
+            let db = connect_to_database(db_url, 1).await;
+            let sql_batch_config = SqlBatchConfig::detect(db.as_ref()).await.unwrap();
+            info!("Importing state snapshot from {:?}...", input);
+            match File::open(&input) {
+                Ok(file) => {
+                    import_state_snapshot(db.as_ref(), sql_batch_config, BufReader::new(file))
+                        .await
+                        .map(|summary| {
+                            info!(
+                                "Imported {} rows. Indexing will continue from slot {}",
+                                summary.rows, summary.slot
+                            )
+                        })
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+    };
+    if let Err(e) = result {
+        error!("State snapshot failed: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run_verify_command(args: VerifyArgs) {
+    // Exit codes for cron jobs: 1 if the index is inconsistent, 2 if it could not be checked.
+    const INCONSISTENT_EXIT_CODE: i32 = 1;
+    const FAILED_EXIT_CODE: i32 = 2;
+
+    let db = connect_to_database(args.db_url, 1).await;
+    let rpc_client = RpcClient::new(args.rpc_url);
+    let root_mismatches = match check_root_consistency(db.as_ref(), &rpc_client).await {
+        Ok(0) => {
+            info!("Roots of all trees are consistent");
+            0
+        }
+        Ok(mismatches) => {
+            error!("Roots of {} trees do not match", mismatches);
+            mismatches
+        }
+        Err(e) => {
+            error!("Failed to check the consistency of the tree roots: {}", e);
+            std::process::exit(FAILED_EXIT_CODE);
+        }
+    };
+
+    let leaf_selection = match (args.all_leaves, args.leaf_sample_size) {
+        (true, _) => Some(LeafSelection::All),
+        (false, 0) => None,
+        (false, leaves) => Some(LeafSelection::Sample(leaves)),
+    };
+    let mut leaf_discrepancies = 0;
+    if let Some(leaf_selection) = leaf_selection {
+        match audit_tree_leaves(db.as_ref(), &rpc_client, leaf_selection).await {
+            Ok(report) => {
+                info!(
+                    "Verified {} leaves against their transactions, {} leaves could not be verified",
+                    report.verified_leaves, report.unverified_leaves
+                );
+                if report.discrepancies > 0 {
+                    error!(
+                        "{} leaves do not match their transactions",
+                        report.discrepancies
+                    );
+                }
+                leaf_discrepancies = report.discrepancies;
+            }
+            Err(e) => {
+                error!("Failed to audit the tree leaves: {:#}", e);
+                std::process::exit(FAILED_EXIT_CODE);
+            }
+        }
+    }
+
+    if root_mismatches > 0 || leaf_discrepancies > 0 {
+        std::process::exit(INCONSISTENT_EXIT_CODE);
+    }
+}
+
+async fn run_conformance_command(args: ConformanceArgs) {
+    let keypair_path = match args.keypair {
+        Some(keypair) => PathBuf::from(keypair),
+        None => dirs::home_dir()
+            .unwrap()
+            .join(".config")
+            .join("solana")
+            .join("id.json"),
+    };
+    let payer = read_keypair_file(&keypair_path).unwrap_or_else(|e| {
+        error!("Failed to read keypair {:?}: {}", keypair_path, e);
+        std::process::exit(1);
+    });
+    let config = ConformanceConfig {
+        photon_url: args.photon_url,
+        rpc_url: args.rpc_url,
+        payer,
+        lamports: args.lamports,
+        state_tree: args.state_tree,
+        nullifier_queue: args.nullifier_queue,
+        indexing_timeout: Duration::from_secs(args.indexing_timeout),
+    };
+    match run_conformance(config).await {
+        Ok(()) => info!("Conformance checks passed"),
+        Err(e) => {
+            error!("Conformance checks failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Settings of the API server, taken from the arguments of `serve`.
+#[derive(Clone)]
+struct ApiServerConfig {
+    port: u16,
     prover_url: String,
-    api_port: u16,
-) -> ServerHandle {
-    let api = PhotonApi::new(db, rpc_client, prover_url);
-    api::rpc_server::run_server(api, api_port).await.unwrap()
+    api: ApiConfig,
+}
+
+impl ApiServerConfig {
+    fn from_args(args: &ServeArgs) -> Self {
+        let graphql_limits = args.enable_graphql.then_some(GraphQLLimits {
+            max_depth: args.graphql_max_depth,
+            max_complexity: args.graphql_max_complexity,
+        });
+        let rate_limit = |requests_per_second: Option<u32>, burst: Option<u32>| {
+            requests_per_second.map(|requests_per_second| RateLimit {
+                requests_per_second,
+                burst: burst.unwrap_or(requests_per_second),
+            })
+        };
+        let rate_limits = RateLimitConfig {
+            reads: rate_limit(args.read_requests_per_second, args.read_requests_burst),
+            proofs: rate_limit(args.proof_requests_per_second, args.proof_requests_burst),
+            api_keys: args.rate_limit_api_keys.iter().cloned().collect(),
+            trusted_proxies: args.trusted_proxies,
+        };
+        let rate_limits =
+            (rate_limits.reads.is_some() || rate_limits.proofs.is_some()).then_some(rate_limits);
+        let cors = CorsConfig {
+            allowed_origins: args.cors_allowed_origins.clone(),
+            allowed_methods: args.cors_allowed_methods.clone(),
+            allowed_headers: args.cors_allowed_headers.clone(),
+            max_age: Duration::from_secs(args.cors_max_age),
+        };
+        let method_filter = MethodFilter {
+            enabled: args
+                .enabled_methods
+                .clone()
+                .map(|methods| methods.into_iter().collect()),
+            disabled: args.disabled_methods.iter().cloned().collect(),
+        };
+        let request_limits = RequestLimits {
+            max_body_size: args.max_request_body_size,
+            max_batch_len: args.max_batch_len,
+        };
+        Self {
+            port: args.port,
+            prover_url: args.prover_url.clone(),
+            api: ApiConfig {
+                graphql_limits,
+                rate_limits,
+                cors,
+                method_filter,
+                request_limits,
+                max_slots_behind: args.readiness_max_slots_behind,
+                max_accounts_by_owner_page_size: args.max_accounts_by_owner_page_size,
+            },
+        }
+    }
 }
 
-async fn setup_temporary_sqlite_database_pool(max_connections: u32) -> SqlitePool {
+async fn setup_temporary_sqlite_database_pool(config: &DatabasePoolConfig) -> SqlitePool {
     let dir = temp_dir();
     if !dir.exists() {
         std::fs::create_dir_all(&dir).unwrap();
@@ -119,63 +1139,92 @@ async fn setup_temporary_sqlite_database_pool(max_connections: u32) -> SqlitePoo
     info!("Creating temporary SQLite database at: {:?}", path);
     File::create(&path).unwrap();
     let db_path = format!("sqlite:////{}", path.to_str().unwrap());
-    setup_sqlite_pool(&db_path, max_connections).await
+    setup_sqlite_pool(&db_path, config).await
 }
 
-async fn setup_sqlite_pool(db_url: &str, max_connections: u32) -> SqlitePool {
+async fn setup_sqlite_pool(db_url: &str, config: &DatabasePoolConfig) -> SqlitePool {
     let options: SqliteConnectOptions = db_url.parse().unwrap();
-    SqlitePoolOptions::new()
-        .max_connections(max_connections)
+    config
+        .pool_options::<Sqlite>()
         .min_connections(1)
         .connect_with(options)
         .await
         .unwrap()
 }
 
-pub fn parse_db_type(db_url: &str) -> DatabaseBackend {
+/// Returns the backend of the database at `db_url`, or an error if Photon cannot connect to it.
+pub fn parse_db_type(db_url: &str) -> Result<DatabaseBackend, String> {
     if db_url.starts_with("postgres://") {
-        DatabaseBackend::Postgres
+        Ok(DatabaseBackend::Postgres)
     } else if db_url.starts_with("sqlite://") {
-        DatabaseBackend::Sqlite
+        Ok(DatabaseBackend::Sqlite)
     } else {
-        unimplemented!("Unsupported database type: {}", db_url)
+        Err("Expected a postgres:// or sqlite:// URL".to_string())
     }
 }
 
+/// Rejects the URLs of databases that Photon cannot connect to while the arguments are parsed.
+fn parse_db_url(db_url: &str) -> Result<String, String> {
+    parse_db_type(db_url).map(|_| db_url.to_string())
+}
+
+/// Connects to the database at `db_url` and reports the utilization of its pool as `pool_name`.
+/// Without a URL, a temporary SQLite database is created.
 async fn setup_database_connection(
     db_url: Option<String>,
-    max_connections: u32,
-) -> Arc<DatabaseConnection> {
-    Arc::new(match db_url {
-        Some(db_url) => {
-            let db_type = parse_db_type(&db_url);
-            match db_type {
-                DatabaseBackend::Postgres => SqlxPostgresConnector::from_sqlx_postgres_pool(
-                    setup_pg_pool(&db_url, max_connections).await,
-                ),
-                DatabaseBackend::Sqlite => SqlxSqliteConnector::from_sqlx_sqlite_pool(
-                    setup_sqlite_pool(&db_url, max_connections).await,
-                ),
-                _ => unimplemented!("Unsupported database type: {}", db_url),
-            }
+    pool_name: &str,
+    config: &DatabasePoolConfig,
+) -> Result<Arc<DatabaseConnection>, String> {
+    let db_type = db_url
+        .as_deref()
+        .map_or(Ok(DatabaseBackend::Sqlite), parse_db_type)?;
+    Ok(Arc::new(if db_type == DatabaseBackend::Postgres {
+        let pool = setup_pg_pool_with_config(db_url.as_deref().unwrap(), config).await;
+        register_pg_pool(pool_name, config, pool.clone());
+        SqlxPostgresConnector::from_sqlx_postgres_pool(pool)
+    } else {
+        let pool = match &db_url {
+            Some(db_url) => setup_sqlite_pool(db_url, config).await,
+            None => setup_temporary_sqlite_database_pool(config).await,
+        };
+        register_sqlite_pool(pool_name, config, pool.clone());
+        SqlxSqliteConnector::from_sqlx_sqlite_pool(pool)
+    }))
+}
+
+// Subcommands work on a single database and have nothing to do without it.
+async fn connect_to_database(db_url: String, max_connections: u32) -> Arc<DatabaseConnection> {
+    let config = DatabasePoolConfig::new(max_connections);
+    match setup_database_connection(Some(db_url), "primary", &config).await {
+        Ok(db) => db,
+        Err(e) => {
+            error!("Failed to connect to the database: {}", e);
+            std::process::exit(1);
         }
-        None => SqlxSqliteConnector::from_sqlx_sqlite_pool(
-            setup_temporary_sqlite_database_pool(max_connections).await,
-        ),
-    })
+    }
 }
 
 fn continously_index_new_blocks(
-    block_stream_config: BlockStreamConfig,
-    db: Arc<DatabaseConnection>,
+    mut block_stream_config: BlockStreamConfig,
+    indexer: IndexerContext,
     rpc_client: Arc<RpcClient>,
-    last_indexed_slot: u64,
+    resume_from_last_indexed_slot: bool,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
+        // After a restart, we continue from the last block that made it into the database instead
+        // of the slot that the indexer was originally started from.
+        if resume_from_last_indexed_slot {
+            if let Some(last_indexed_slot) =
+                fetch_last_indexed_slot_with_infinite_retry(indexer.db.as_ref()).await
+            {
+                block_stream_config.last_indexed_slot = last_indexed_slot as u64;
+            }
+        }
+        let last_indexed_slot = block_stream_config.last_indexed_slot;
         let block_stream = block_stream_config.load_block_stream();
         index_block_stream(
             block_stream,
-            db,
+            indexer,
             rpc_client.clone(),
             last_indexed_slot,
             None,
@@ -184,19 +1233,228 @@ fn continously_index_new_blocks(
     })
 }
 
+// Arguments of the command that serves, i.e. the top-level command or `serve`.
+fn serve_matches<'a>(
+    command: &'a clap::Command,
+    matches: &'a ArgMatches,
+) -> Option<(&'a clap::Command, &'a ArgMatches)> {
+    match matches.subcommand() {
+        None => Some((command, matches)),
+        Some(("serve", serve_matches)) => Some((command.find_subcommand("serve")?, serve_matches)),
+        Some(_) => None,
+    }
+}
+
+/// Parses the command line arguments, filling in the arguments to serve with that were not passed
+/// from environment variables and the config file.
+fn parse_args() -> Args {
+    const NON_CONFIG_ARGS: [&str; 2] = ["config", "print_config"];
+    let argv = std::env::args_os().collect::<Vec<_>>();
+    let mut command = Args::command();
+    command.build();
+    let matches = command.clone().get_matches_from(&argv);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let serve_args = match &args.command {
+        None => &args.serve,
+        Some(Command::Serve(serve_args)) => serve_args.as_ref(),
+        Some(_) => return args,
+    };
+    let (serve_command, matches) = serve_matches(&command, &matches).unwrap();
+    let layered_args = match &serve_args.config {
+        Some(path) => load_config_file(path),
+        None => Ok(Default::default()),
+    }
+    .and_then(|mut config| {
+        for arg in NON_CONFIG_ARGS {
+            config.remove(&arg.replace('_', "-"));
+        }
+        layered_args(serve_command, matches, config)
+    })
+    .unwrap_or_else(|e| {
+        eprintln!("error: {:#}", e);
+        std::process::exit(2);
+    });
+    let print_config = serve_args.print_config;
+
+    let matches = command
+        .clone()
+        .get_matches_from(argv.into_iter().chain(layered_args));
+    if print_config {
+        let (serve_command, matches) = serve_matches(&command, &matches).unwrap();
+        print!(
+            "{}",
+            render_config(serve_command, matches, &NON_CONFIG_ARGS).unwrap()
+        );
+        std::process::exit(0);
+    }
+    Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
-    setup_logging(args.logging_format);
+    let args = parse_args();
+    setup_logging_with_otlp(args.logging_format, args.otlp_endpoint.as_deref());
+    match args.command.unwrap_or(Command::Serve(Box::new(args.serve))) {
+        Command::Serve(serve_args) => run_serve_command(*serve_args).await,
+        Command::Migrate(migrate_args) => run_migrate_command(migrate_args).await,
+        Command::Backfill(backfill_args) => run_backfill_command(backfill_args).await,
+        Command::Snapshot(snapshot_command) => run_snapshot_command(snapshot_command).await,
+        Command::Verify(verify_args) => run_verify_command(verify_args).await,
+        Command::Conformance(conformance_args) => run_conformance_command(conformance_args).await,
+        Command::DumpOwner(dump_owner_args) => run_dump_owner_command(dump_owner_args).await,
+        Command::PruneSpentAccounts(prune_args) => {
+            run_prune_spent_accounts_command(prune_args).await
+        }
+        Command::Replay(replay_args) => run_replay_command(replay_args).await,
+        Command::FailedBlocks(failed_blocks_args) => {
+            run_failed_blocks_command(failed_blocks_args).await
+        }
+    }
+    shutdown_telemetry();
+}
+
+/// Waits up to `timeout` for a component to stop after a shutdown was requested. Returns its handle
+/// if it is still running.
+async fn wait_for_component(
+    component: &str,
+    handle: Option<tokio::task::JoinHandle<()>>,
+    timeout: Duration,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let mut handle = handle?;
+    info!("Waiting for {} to stop...", component);
+    match tokio::time::timeout(timeout, &mut handle).await {
+        Ok(_) => None,
+        Err(_) => {
+            warn!("{} did not stop within {:?}", component, timeout);
+            Some(handle)
+        }
+    }
+}
+
+async fn run_serve_command(args: ServeArgs) {
+    let api_server_config = ApiServerConfig::from_args(&args);
+    if api_server_config.api.rate_limits.is_some() && args.trusted_proxies == 0 {
+        error!("Rate limits require --trusted-proxies to identify HTTP clients by their address");
+        std::process::exit(1);
+    }
     setup_metrics(args.metrics_endpoint);
+    set_account_data_compression(args.compress_account_data);
+    set_account_data_deduplication(args.deduplicate_account_data);
+    set_proof_history_window(args.proof_history_window);
+    set_parse_workers(args.parse_workers.unwrap_or_default());
+    set_block_cache_dir(args.block_cache_dir.clone());
+    set_pipeline_watermarks(
+        args.pipeline_high_watermark_blocks,
+        args.pipeline_low_watermark_blocks,
+    );
+    if args.min_slot_poll_interval_ms > args.max_slot_poll_interval_ms {
+        error!("--min-slot-poll-interval-ms must not exceed --max-slot-poll-interval-ms");
+        std::process::exit(1);
+    }
+    set_slot_poll_intervals(
+        Duration::from_millis(args.min_slot_poll_interval_ms),
+        Duration::from_millis(args.max_slot_poll_interval_ms),
+    );
 
-    let db_conn = setup_database_connection(args.db_url.clone(), args.max_db_conn).await;
+    let pool_config = DatabasePoolConfig {
+        max_connections: args.max_db_conn,
+        acquire_timeout: Duration::from_secs(args.db_acquire_timeout),
+        idle_timeout: args.db_idle_timeout.map(Duration::from_secs),
+        statement_timeout: None,
+    };
+    let api_pool_config = DatabasePoolConfig {
+        statement_timeout: args.db_statement_timeout.map(Duration::from_secs),
+        ..pool_config.clone()
+    };
+    let db_conn = match setup_database_connection(
+        args.db_url.clone(),
+        "primary",
+        match args.disable_indexing {
+            true => &api_pool_config,
+            false => &pool_config,
+        },
+    )
+    .await
+    {
+        Ok(db_conn) => db_conn,
+        Err(e) => {
+            error!("Failed to connect to the database: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let pool_metrics_handle = supervise("pool metrics", continously_report_pool_metrics);
+    let sql_batch_config = SqlBatchConfig::detect(db_conn.as_ref())
+        .await
+        .unwrap()
+        .with_max_batch_size(args.max_sql_batch_size);
     if args.db_url.is_none() {
         info!("Running migrations...");
         Migrator::up(db_conn.as_ref(), None).await.unwrap();
     }
+    load_tree_infos(db_conn.as_ref()).await.unwrap();
+    let cursor_secret = match &args.cursor_secret {
+        Some(cursor_secret) => cursor_secret.clone(),
+        None => match get_or_create_cursor_secret(db_conn.as_ref()).await {
+            Ok(cursor_secret) => cursor_secret,
+            Err(e) => {
+                error!(
+                    "Failed to load the cursor secret from the database, pass --cursor-secret \
+                     instead: {}",
+                    e
+                );
+                std::process::exit(1);
+            }
+        },
+    };
+    set_cursor_secret(&cursor_secret);
+    // API only instances can run against a read replica.
+    if !args.disable_indexing {
+        record_instance_metadata(
+            db_conn.as_ref(),
+            args.compress_account_data,
+            args.deduplicate_account_data,
+        )
+        .await;
+    }
     let is_rpc_node_local = args.rpc_url.contains("127.0.0.1");
-    let rpc_client = get_rpc_client(&args.rpc_url);
+    let rpc_urls = [vec![args.rpc_url.clone()], args.fallback_rpc_url.clone()].concat();
+    let rpc_client = get_failover_rpc_client(
+        &rpc_urls,
+        RpcRateLimitConfig {
+            requests_per_second: args.rpc_requests_per_second,
+            max_retries: args.rpc_max_retries,
+            ..Default::default()
+        },
+    );
+    // The cache and the in-memory trees are only kept up to date by the indexer of this process,
+    // and replicas can still serve what it has already invalidated.
+    let serves_indexed_state =
+        !args.disable_api && !args.disable_indexing && args.read_replica_db_url.is_empty();
+    let (api_cache, in_memory_trees) = match serves_indexed_state {
+        true => (
+            ApiCache::new(args.api_cache_capacity),
+            InMemoryTrees::new(args.in_memory_tree_capacity),
+        ),
+        false => (ApiCache::default(), InMemoryTrees::default()),
+    };
+    let indexer = IndexerContext::new(db_conn.clone())
+        .with_rpc_client(rpc_client.clone())
+        .with_sql_batch_config(sql_batch_config)
+        .with_api_cache(api_cache.clone())
+        .with_in_memory_trees(in_memory_trees.clone());
+
+    if let Some(snapshot_url) = &args.snapshot_url {
+        match bootstrap_from_state_snapshot_url(db_conn.as_ref(), sql_batch_config, snapshot_url)
+            .await
+            .unwrap()
+        {
+            Some(summary) => info!(
+                "Loaded {} rows from state snapshot at slot {}",
+                summary.rows, summary.slot
+            ),
+            None => info!("Database is not empty. Skipping state snapshot bootstrap"),
+        }
+    }
 
     if let Some(snapshot_dir) = args.snapshot_dir {
         let directory_adapter = Arc::new(DirectoryAdapter::from_local_directory(snapshot_dir));
@@ -219,7 +1477,7 @@ async fn main() {
             };
             index_block_stream(
                 block_stream,
-                db_conn.clone(),
+                indexer.clone(),
                 rpc_client.clone(),
                 last_indexed_slot,
                 Some(last_slot),
@@ -228,10 +1486,12 @@ async fn main() {
         }
     }
 
-    let (indexer_handle, monitor_handle) = match args.disable_indexing {
+    let (indexer_handle, monitor_handle, gap_handle, prune_handle, root_check_handle) = match args
+        .disable_indexing
+    {
         true => {
             info!("Indexing is disabled");
-            (None, None)
+            (None, None, None, None, None)
         }
         false => {
             info!("Starting indexer...");
@@ -246,7 +1506,9 @@ async fn main() {
                     }
                 }
             };
-            let last_indexed_slot = match args.start_slot {
+            let last_checkpointed_slot =
+                fetch_last_indexed_slot_with_infinite_retry(db_conn.as_ref()).await;
+            let last_indexed_slot = match &args.start_slot {
                 Some(start_slot) => match start_slot.as_str() {
                     "latest" => fetch_current_slot_with_infinite_retry(&rpc_client).await,
                     _ => {
@@ -254,83 +1516,205 @@ async fn main() {
                             .await
                     }
                 },
-                None => fetch_last_indexed_slot_with_infinite_retry(db_conn.as_ref())
-                    .await
-                    .unwrap_or(
-                        get_network_start_slot(&rpc_client)
-                            .await
-                            .try_into()
-                            .unwrap(),
-                    )
-                    .try_into()
-                    .unwrap(),
+                None => match last_checkpointed_slot {
+                    Some(slot) => slot as u64,
+                    None => get_network_start_slot(&rpc_client).await,
+                },
             };
+            // Queries of slots before the first indexed slot fail instead of returning empty
+            // results. Indexing from genesis starts at slot 0, which is never indexed otherwise.
+            if args.start_slot.is_some() || last_checkpointed_slot.is_none() {
+                let start_slot = match last_indexed_slot {
+                    0 => 0,
+                    slot => slot + 1,
+                };
+                record_indexing_start_slot(db_conn.as_ref(), start_slot)
+                    .await
+                    .unwrap();
+            }
+
+            // The filter has to be loaded before the indexer starts writing so that it does not
+            // miss any accounts. Since it only sees the accounts that this process writes, lookups
+            // are answered from the database unless the operator vouches that there are no
+            // other writers.
+            if !args.disable_api && args.unspent_hash_filter {
+                let filter = BloomFilter::load_unspent_hashes(db_conn.as_ref())
+                    .await
+                    .unwrap();
+                enable_unspent_hash_filter(filter);
+            }
+            // The dispatcher is started after loading snapshots so that historical state changes
+            // are not posted to webhooks.
+            if let Some(webhook_config) = &args.webhook_config {
+                let webhooks = load_webhook_configs(Path::new(webhook_config)).unwrap();
+                start_webhook_dispatcher(webhooks);
+            }
 
             let block_stream_config = BlockStreamConfig {
                 rpc_client: rpc_client.clone(),
                 max_concurrent_block_fetches,
                 last_indexed_slot,
                 geyser_url: args.grpc_url,
+                grpc_compression_transactions_only: args.grpc_compression_transactions_only,
+                geyser_plugin_socket: args.geyser_plugin_socket,
+                poll_compression_signatures: args.poll_compression_signatures,
+                logs_websocket_url: args.logs_websocket_url.clone(),
             };
 
+            // Without whole blocks, every slot without compression activity would look like a
+            // gap.
+            let refetches_slot_gaps =
+                !args.poll_compression_signatures && args.logs_websocket_url.is_none();
+            let mut indexer_restarted = false;
+            let (indexer_context, indexer_rpc_client) = (indexer.clone(), rpc_client.clone());
+            let (monitor_db_conn, monitor_rpc_client) = (db_conn.clone(), rpc_client.clone());
+            let (gap_indexer, gap_rpc_client) = (indexer.clone(), rpc_client.clone());
+            let prune_db_conn = db_conn.clone();
+            let (root_check_db_conn, root_check_rpc_client) = (db_conn.clone(), rpc_client.clone());
             (
-                Some(continously_index_new_blocks(
-                    block_stream_config,
-                    db_conn.clone(),
-                    rpc_client.clone(),
-                    last_indexed_slot,
-                )),
-                Some(continously_monitor_photon(
-                    db_conn.clone(),
-                    rpc_client.clone(),
-                )),
+                Some(supervise("indexer", move || {
+                    let handle = continously_index_new_blocks(
+                        block_stream_config.clone(),
+                        indexer_context.clone(),
+                        indexer_rpc_client.clone(),
+                        indexer_restarted,
+                    );
+                    indexer_restarted = true;
+                    handle
+                })),
+                Some(supervise("monitor", move || {
+                    continously_monitor_photon(monitor_db_conn.clone(), monitor_rpc_client.clone())
+                })),
+                refetches_slot_gaps.then(|| {
+                    supervise("slot gap detection", move || {
+                        continously_refetch_slot_gaps(
+                            gap_indexer.clone(),
+                            gap_rpc_client.clone(),
+                            last_indexed_slot,
+                        )
+                    })
+                }),
+                args.prune_spent_accounts_after_slots
+                    .map(|retention_slots| {
+                        supervise("pruner", move || {
+                            continously_prune_spent_accounts(
+                                prune_db_conn.clone(),
+                                sql_batch_config,
+                                retention_slots,
+                            )
+                        })
+                    }),
+                (args.root_consistency_check_interval > 0).then(|| {
+                    let interval = Duration::from_secs(args.root_consistency_check_interval);
+                    supervise("root consistency check", move || {
+                        continously_check_root_consistency(
+                            root_check_db_conn.clone(),
+                            root_check_rpc_client.clone(),
+                            interval,
+                        )
+                    })
+                }),
             )
         }
     };
 
     info!("Starting API server with port {}...", args.port);
-    let api_handler = if args.disable_api {
-        None
+    let (api_handle, grpc_handle, replica_health_handle) = if args.disable_api {
+        (None, None, None)
     } else {
-        Some(
-            start_api_server(
-                db_conn.clone(),
-                rpc_client.clone(),
-                args.prover_url,
-                args.port,
-            )
-            .await,
+        set_proof_cache_capacity(args.proof_cache_capacity);
+        let mut replica_db_conns = Vec::new();
+        for (index, db_url) in args.read_replica_db_url.into_iter().enumerate() {
+            let pool_name = format!("replica_{}", index);
+            match setup_database_connection(Some(db_url), &pool_name, &api_pool_config).await {
+                Ok(db_conn) => replica_db_conns.push(db_conn),
+                Err(e) => {
+                    error!("Failed to connect to read replica {}: {}", index, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        let has_read_replicas = !replica_db_conns.is_empty();
+        let read_replicas = Arc::new(ReadReplicas::new(db_conn.clone(), replica_db_conns));
+        let health_read_replicas = read_replicas.clone();
+        // Both servers run the same configured API, so that the method filter and the other
+        // settings apply to gRPC as well, and so that clients share one rate limit bucket.
+        let api = PhotonApi::new(
+            db_conn.clone(),
+            rpc_client.clone(),
+            api_server_config.prover_url.clone(),
+        )
+        .with_read_replicas(read_replicas)
+        .with_config(api_server_config.api.clone())
+        .with_sql_batch_config(sql_batch_config)
+        .with_api_cache(api_cache)
+        .with_in_memory_trees(in_memory_trees);
+        let grpc_handle = args.grpc_port.map(|grpc_port| {
+            info!("Starting gRPC server with port {}...", grpc_port);
+            let api = api.clone();
+            supervise("gRPC server", move || {
+                let api = api.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = run_grpc_server(api, grpc_port).await {
+                        error!("gRPC server failed: {}", e);
+                    }
+                })
+            })
+        });
+        let port = api_server_config.port;
+        (
+            Some(supervise("API server", move || {
+                let api = api.clone();
+                tokio::spawn(async move {
+                    let handle = api::rpc_server::run_server(api, port).await.unwrap();
+                    tokio::select! {
+                        _ = handle.clone().stopped() => {}
+                        _ = shutdown_requested() => {
+                            // Stops accepting connections and waits for the calls in flight.
+                            let _ = handle.stop();
+                            handle.stopped().await;
+                        }
+                    }
+                })
+            })),
+            grpc_handle,
+            has_read_replicas.then(|| {
+                supervise("read replica health checks", move || {
+                    health_read_replicas.clone().continously_check_health()
+                })
+            }),
         )
     };
 
-    match tokio::signal::ctrl_c().await {
-        Ok(()) => {
-            if let Some(indexer_handle) = indexer_handle {
-                info!("Shutting down indexer...");
-                indexer_handle.abort();
-                indexer_handle
-                    .await
-                    .expect_err("Indexer should have been aborted");
-            }
-            if let Some(api_handler) = &api_handler {
-                info!("Shutting down API server...");
-                api_handler.stop().unwrap();
-            }
-
-            if let Some(monitor_handle) = monitor_handle {
-                info!("Shutting down monitor...");
-                monitor_handle.abort();
-                monitor_handle
-                    .await
-                    .expect_err("Monitor should have been aborted");
-            }
-        }
-        Err(err) => {
-            error!("Unable to listen for shutdown signal: {}", err);
-        }
+    if let Err(err) = shutdown_signal().await {
+        error!("Unable to listen for shutdown signal: {}", err);
+        return;
     }
-    // We need to wait for the API server to stop to ensure that all clean up is done
-    if let Some(api_handler) = api_handler {
-        tokio::spawn(api_handler.stopped());
+    info!("Shutting down...");
+    request_shutdown();
+    // The indexer commits the batch it is persisting and the API server finishes the calls in
+    // flight. Whatever is still running after the timeout is aborted.
+    let drain_timeout = Duration::from_secs(args.shutdown_timeout);
+    let indexer_handle = wait_for_component("indexer", indexer_handle, drain_timeout).await;
+    let api_handle = wait_for_component("API server", api_handle, drain_timeout).await;
+    let grpc_handle = wait_for_component("gRPC server", grpc_handle, drain_timeout).await;
+    // Aborting a supervised component also drops its server handle, which stops the API server.
+    for (component, handle) in [
+        ("indexer", indexer_handle),
+        ("API server", api_handle),
+        ("gRPC server", grpc_handle),
+        ("monitor", monitor_handle),
+        ("slot gap detection", gap_handle),
+        ("pruner", prune_handle),
+        ("root consistency check", root_check_handle),
+        ("read replica health checks", replica_health_handle),
+        ("pool metrics", Some(pool_metrics_handle)),
+    ] {
+        if let Some(handle) = handle.filter(|handle| !handle.is_finished()) {
+            info!("Shutting down {}...", component);
+            handle.abort();
+            let _ = handle.await;
+        }
     }
+    info!("Shutdown complete");
 }
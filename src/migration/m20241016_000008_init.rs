@@ -0,0 +1,109 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DatabaseBackend, Statement};
+
+use crate::migration::model::table::TokenAccounts;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+// Indexes backing the sort options of getCompressedTokenAccountsByOwner/ByDelegate. The
+// (spent, owner|delegate, mint, hash) indexes from the initial migration cover sorting by mint.
+const SORT_INDEXES: [(&str, &str); 4] = [
+    (
+        "token_accounts_owner_amount_hash_idx",
+        "token_accounts (spent, owner, amount, hash)",
+    ),
+    (
+        "token_accounts_owner_slot_created_hash_idx",
+        "token_accounts (spent, owner, slot_created, hash)",
+    ),
+    (
+        "token_accounts_delegate_amount_hash_idx",
+        "token_accounts (spent, delegate, amount, hash)",
+    ),
+    (
+        "token_accounts_delegate_slot_created_hash_idx",
+        "token_accounts (spent, delegate, slot_created, hash)",
+    ),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(TokenAccounts::Table)
+                    .add_column(
+                        ColumnDef::new(TokenAccounts::SlotCreated)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Backfill from the base accounts so that existing token accounts sort correctly.
+        execute_sql(
+            manager,
+            "UPDATE token_accounts SET slot_created = (SELECT accounts.slot_created FROM accounts WHERE accounts.hash = token_accounts.hash);",
+        )
+        .await?;
+
+        for (name, columns) in SORT_INDEXES {
+            if manager.get_database_backend() == DatabaseBackend::Postgres {
+                // Create index concurrently for Postgres
+                execute_sql(
+                    manager,
+                    &format!("CREATE INDEX CONCURRENTLY IF NOT EXISTS {name} ON {columns};"),
+                )
+                .await?;
+            } else {
+                // For other databases, create index normally
+                execute_sql(
+                    manager,
+                    &format!("CREATE INDEX IF NOT EXISTS {name} ON {columns};"),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (name, _) in SORT_INDEXES {
+            manager
+                .drop_index(
+                    Index::drop()
+                        .name(name)
+                        .table(TokenAccounts::Table)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(TokenAccounts::Table)
+                    .drop_column(TokenAccounts::SlotCreated)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
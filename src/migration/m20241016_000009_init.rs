@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DatabaseBackend, Statement};
+
+use crate::migration::model::table::TokenAccounts;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Backs getCompressedTokenAccountByOwnerAndMint. Only unspent token accounts are ever
+        // queried by owner and mint, so a partial index stays a fraction of the size of the table.
+        if manager.get_database_backend() == DatabaseBackend::Postgres {
+            // Create index concurrently for Postgres
+            execute_sql(
+                manager,
+                "CREATE INDEX CONCURRENTLY IF NOT EXISTS token_accounts_unspent_owner_mint_hash_idx ON token_accounts (owner, mint, hash) WHERE spent = false;",
+            )
+            .await?;
+        } else {
+            // For other databases, create index normally
+            execute_sql(
+                manager,
+                "CREATE INDEX IF NOT EXISTS token_accounts_unspent_owner_mint_hash_idx ON token_accounts (owner, mint, hash) WHERE spent = false;",
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("token_accounts_unspent_owner_mint_hash_idx")
+                    .table(TokenAccounts::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
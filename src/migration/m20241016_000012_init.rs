@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+use crate::migration::model::table::{AccountData, Accounts};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Content addressed account data that is shared between all accounts with the same data.
+        manager
+            .create_table(
+                Table::create()
+                    .table(AccountData::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(AccountData::Hash).binary().not_null())
+                    .col(ColumnDef::new(AccountData::Data).binary().not_null())
+                    .col(
+                        ColumnDef::new(AccountData::DataCompressed)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk_account_data")
+                            .col(AccountData::Hash),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Set instead of the data column for accounts whose data is stored in account_data.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .add_column(ColumnDef::new(Accounts::DataRef).binary())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .drop_column(Accounts::DataRef)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AccountData::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
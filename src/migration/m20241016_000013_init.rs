@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use crate::migration::model::table::FailedBlocks;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Dead-letter queue of blocks that could not be indexed, stored with the error so that they
+        // can be retried once the cause is fixed.
+        manager
+            .create_table(
+                Table::create()
+                    .table(FailedBlocks::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(FailedBlocks::Slot).big_integer().not_null())
+                    .col(ColumnDef::new(FailedBlocks::Block).binary().not_null())
+                    .col(ColumnDef::new(FailedBlocks::Error).text().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk_failed_blocks")
+                            .col(FailedBlocks::Slot),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FailedBlocks::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
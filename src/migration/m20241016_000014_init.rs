@@ -0,0 +1,34 @@
+use sea_orm_migration::prelude::*;
+
+use crate::migration::model::table::Metadata;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Key-value store for instance state that does not warrant a table of its own.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Metadata::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Metadata::Key).text().not_null())
+                    .col(ColumnDef::new(Metadata::Value).text().not_null())
+                    .primary_key(Index::create().name("pk_metadata").col(Metadata::Key))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Metadata::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
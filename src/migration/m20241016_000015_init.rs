@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+use crate::migration::model::table::NullifierQueueElements;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Spent accounts whose leaves have not been nullified in their state tree yet.
+        manager
+            .create_table(
+                Table::create()
+                    .table(NullifierQueueElements::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(NullifierQueueElements::Hash)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NullifierQueueElements::Tree)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NullifierQueueElements::LeafIndex)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk_nullifier_queue_elements")
+                            .col(NullifierQueueElements::Hash),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("nullifier_queue_elements_tree_leaf_index_idx")
+                    .table(NullifierQueueElements::Table)
+                    .col(NullifierQueueElements::Tree)
+                    .col(NullifierQueueElements::LeafIndex)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(NullifierQueueElements::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
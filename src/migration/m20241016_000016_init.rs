@@ -0,0 +1,86 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DatabaseBackend, Statement};
+
+use crate::migration::model::table::TokenAccounts;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Token-2022 extension fields parsed from the TLV data of token accounts. Amounts use the
+        // same column types as the other token amounts.
+        match manager.get_database_backend() {
+            DatabaseBackend::Postgres => {
+                execute_sql(
+                    manager,
+                    "ALTER TABLE token_accounts ADD COLUMN withheld_transfer_fee bigint2;",
+                )
+                .await?;
+            }
+            DatabaseBackend::Sqlite => {
+                execute_sql(
+                    manager,
+                    "ALTER TABLE token_accounts ADD COLUMN withheld_transfer_fee REAL;",
+                )
+                .await?;
+            }
+            _ => {
+                unimplemented!("Unsupported database type")
+            }
+        }
+
+        for column in [
+            TokenAccounts::PermanentDelegate,
+            TokenAccounts::MetadataPointerAuthority,
+            TokenAccounts::MetadataPointerAddress,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(TokenAccounts::Table)
+                        .add_column(ColumnDef::new(column).binary())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            TokenAccounts::PermanentDelegate,
+            TokenAccounts::MetadataPointerAuthority,
+            TokenAccounts::MetadataPointerAddress,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(TokenAccounts::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        execute_sql(
+            manager,
+            "ALTER TABLE token_accounts DROP COLUMN withheld_transfer_fee;",
+        )
+        .await?;
+
+        Ok(())
+    }
+}
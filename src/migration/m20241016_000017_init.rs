@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DatabaseBackend, Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Lamports that each transaction moved between compressed and uncompressed accounts.
+        // Transactions indexed before this migration count as not having moved any.
+        let column_type = match manager.get_database_backend() {
+            DatabaseBackend::Postgres => "bigint2",
+            DatabaseBackend::Sqlite => "REAL",
+            _ => unimplemented!("Unsupported database type"),
+        };
+        for column in ["compressed_lamports", "decompressed_lamports"] {
+            execute_sql(
+                manager,
+                &format!(
+                    "ALTER TABLE transactions ADD COLUMN {} {} NOT NULL DEFAULT 0;",
+                    column, column_type
+                ),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in ["compressed_lamports", "decompressed_lamports"] {
+            execute_sql(
+                manager,
+                &format!("ALTER TABLE transactions DROP COLUMN {};", column),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+use crate::migration::model::table::{Accounts, TokenAccounts};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Signatures of the transactions that created and spent an account. Accounts indexed
+        // before these columns existed have neither.
+        for column in [Accounts::TxSignature, Accounts::SpentTxSignature] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Accounts::Table)
+                        .add_column(ColumnDef::new(column).binary())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for column in [TokenAccounts::TxSignature, TokenAccounts::SpentTxSignature] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(TokenAccounts::Table)
+                        .add_column(ColumnDef::new(column).binary())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [TokenAccounts::TxSignature, TokenAccounts::SpentTxSignature] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(TokenAccounts::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for column in [Accounts::TxSignature, Accounts::SpentTxSignature] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Accounts::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+use crate::migration::model::table::Accounts;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Lists the accounts of an owner by type without reading their data.
+        manager
+            .create_index(
+                Index::create()
+                    .name("accounts_owner_discriminator_hash_idx")
+                    .table(Accounts::Table)
+                    .col(Accounts::Spent)
+                    .col(Accounts::Owner)
+                    .col(Accounts::Discriminator)
+                    .col(Accounts::Hash) // For pagination
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("accounts_owner_discriminator_hash_idx")
+                    .table(Accounts::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use sea_orm_migration::prelude::*;
+
+use super::model::table::StateTreeNodeHistories;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Versions of state tree nodes that were replaced at `seq`. `hash` and `prev_seq` are null
+        // if the node was empty before.
+        manager
+            .create_table(
+                Table::create()
+                    .table(StateTreeNodeHistories::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(StateTreeNodeHistories::Tree)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(StateTreeNodeHistories::NodeIdx)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(StateTreeNodeHistories::Seq)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(StateTreeNodeHistories::Hash).binary())
+                    .col(ColumnDef::new(StateTreeNodeHistories::PrevSeq).big_integer())
+                    .primary_key(
+                        Index::create()
+                            .name("pk_state_tree_node_histories")
+                            .col(StateTreeNodeHistories::Tree)
+                            .col(StateTreeNodeHistories::NodeIdx)
+                            .col(StateTreeNodeHistories::Seq),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Old versions are pruned by seq.
+        manager
+            .create_index(
+                Index::create()
+                    .name("state_tree_node_histories_tree_seq_idx")
+                    .table(StateTreeNodeHistories::Table)
+                    .col(StateTreeNodeHistories::Tree)
+                    .col(StateTreeNodeHistories::Seq)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(StateTreeNodeHistories::Table)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+use super::model::table::Trees;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Parameters of the Merkle tree accounts on chain, fetched when a tree is first indexed.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Trees::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Trees::Tree).binary().not_null())
+                    .col(ColumnDef::new(Trees::Height).big_integer().not_null())
+                    .col(ColumnDef::new(Trees::CanopyDepth).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Trees::ChangelogSize)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(Index::create().name("pk_trees").col(Trees::Tree))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Trees::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DatabaseBackend, Statement};
+
+use crate::migration::model::table::TokenAccounts;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Serves the delegated token accounts of an owner without scanning all of them. Accounts
+        // without a delegate are served from token_accounts_unspent_owner_mint_hash_idx.
+        if manager.get_database_backend() == DatabaseBackend::Postgres {
+            execute_sql(
+                manager,
+                "CREATE INDEX CONCURRENTLY IF NOT EXISTS token_accounts_unspent_delegated_owner_mint_hash_idx ON token_accounts (owner, mint, hash) WHERE spent = false AND delegate IS NOT NULL;",
+            )
+            .await?;
+        } else {
+            execute_sql(
+                manager,
+                "CREATE INDEX IF NOT EXISTS token_accounts_unspent_delegated_owner_mint_hash_idx ON token_accounts (owner, mint, hash) WHERE spent = false AND delegate IS NOT NULL;",
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("token_accounts_unspent_delegated_owner_mint_hash_idx")
+                    .table(TokenAccounts::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
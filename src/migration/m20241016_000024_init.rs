@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+use super::model::table::Checkpoints;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Slots up to which each checkpointed process has committed its work, e.g. the ingester.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Checkpoints::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Checkpoints::Name).text().not_null())
+                    .col(ColumnDef::new(Checkpoints::Slot).big_integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk_checkpoints")
+                            .col(Checkpoints::Name),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Databases that were indexed before the checkpoint existed resume from their last block.
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                "INSERT INTO checkpoints (name, slot) SELECT 'ingester', slot FROM blocks ORDER BY slot DESC LIMIT 1;"
+                    .to_string(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Checkpoints::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
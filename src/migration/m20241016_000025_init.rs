@@ -0,0 +1,111 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+use super::model::table::AccountTransactions;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Slot of the transaction and whether it spent the account rather than created it, so
+        // that rollbacks and histories do not have to join the transactions and guess.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AccountTransactions::Table)
+                    .add_column(
+                        ColumnDef::new(AccountTransactions::Slot)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AccountTransactions::Table)
+                    .add_column(
+                        ColumnDef::new(AccountTransactions::Spent)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Transactions can be missing, e.g. in databases loaded from a state snapshot, and their
+        // rows keep slot 0.
+        execute_sql(
+            manager,
+            "UPDATE account_transactions SET slot = COALESCE(( \
+                 SELECT transactions.slot FROM transactions \
+                 WHERE transactions.signature = account_transactions.signature \
+             ), 0);",
+        )
+        .await?;
+        // Rows written before the spending transaction was recorded on the account are spends if
+        // their slot is after the slot the account was created in.
+        execute_sql(
+            manager,
+            "UPDATE account_transactions SET spent = true WHERE EXISTS ( \
+                 SELECT 1 FROM accounts WHERE accounts.hash = account_transactions.hash AND ( \
+                     accounts.spent_tx_signature = account_transactions.signature \
+                     OR (accounts.spent_tx_signature IS NULL AND accounts.spent \
+                         AND account_transactions.slot > accounts.slot_created) \
+                 ) \
+             );",
+        )
+        .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("account_transactions_slot_idx")
+                    .table(AccountTransactions::Table)
+                    .col(AccountTransactions::Slot)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("account_transactions_slot_idx")
+                    .table(AccountTransactions::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [AccountTransactions::Spent, AccountTransactions::Slot] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(AccountTransactions::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
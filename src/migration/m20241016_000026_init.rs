@@ -0,0 +1,102 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+use super::model::table::{Accounts, TokenAccounts};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Slot of the transaction that spent the account, next to `slot_created`, so that
+        // histories and pruning can tell how long an account lived.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .add_column(ColumnDef::new(Accounts::SlotSpent).big_integer().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(TokenAccounts::Table)
+                    .add_column(
+                        ColumnDef::new(TokenAccounts::SlotSpent)
+                            .big_integer()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Accounts whose spending transaction is not indexed keep a NULL slot.
+        for table in ["accounts", "token_accounts"] {
+            execute_sql(
+                manager,
+                &format!(
+                    "UPDATE {table} SET slot_spent = ( \
+                         SELECT MAX(account_transactions.slot) FROM account_transactions \
+                         WHERE account_transactions.hash = {table}.hash \
+                         AND account_transactions.spent \
+                     ) WHERE {table}.spent;"
+                ),
+            )
+            .await?;
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("accounts_slot_spent_idx")
+                    .table(Accounts::Table)
+                    .col(Accounts::SlotSpent)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("accounts_slot_spent_idx")
+                    .table(Accounts::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .drop_column(Accounts::SlotSpent)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(TokenAccounts::Table)
+                    .drop_column(TokenAccounts::SlotSpent)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
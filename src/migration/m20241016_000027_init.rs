@@ -0,0 +1,142 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DatabaseBackend, Statement};
+
+use super::model::table::PrunedAccounts;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+async fn execute_sql(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await?;
+    Ok(())
+}
+
+// SQLite cannot add or drop foreign keys, so the table is copied into a new one with the
+// requested constraints.
+async fn rebuild_sqlite_account_transactions(
+    manager: &SchemaManager<'_>,
+    hash_foreign_key: bool,
+) -> Result<(), DbErr> {
+    let hash_foreign_key = if hash_foreign_key {
+        "FOREIGN KEY (\"hash\") REFERENCES \"accounts\" (\"hash\") ON DELETE CASCADE, "
+    } else {
+        ""
+    };
+    execute_sql(
+        manager,
+        &format!(
+            "CREATE TABLE \"account_transactions_new\" ( \
+                 \"hash\" blob NOT NULL, \
+                 \"signature\" blob NOT NULL, \
+                 \"slot\" integer NOT NULL DEFAULT 0, \
+                 \"spent\" boolean NOT NULL DEFAULT FALSE, \
+                 CONSTRAINT \"pk_account_transaction_history\" PRIMARY KEY (\"hash\", \"signature\"), \
+                 {hash_foreign_key}\
+                 FOREIGN KEY (\"signature\") REFERENCES \"transactions\" (\"signature\") ON DELETE CASCADE \
+             );"
+        ),
+    )
+    .await?;
+    execute_sql(
+        manager,
+        "INSERT INTO account_transactions_new (hash, signature, slot, spent) \
+         SELECT hash, signature, slot, spent FROM account_transactions;",
+    )
+    .await?;
+    execute_sql(manager, "DROP TABLE account_transactions;").await?;
+    execute_sql(
+        manager,
+        "ALTER TABLE account_transactions_new RENAME TO account_transactions;",
+    )
+    .await?;
+    execute_sql(
+        manager,
+        "CREATE INDEX account_transactions_signature_idx ON account_transactions (signature);",
+    )
+    .await?;
+    execute_sql(
+        manager,
+        "CREATE INDEX account_transactions_slot_idx ON account_transactions (slot);",
+    )
+    .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Spends of accounts whose creating block is not indexed yet, e.g. because it is
+        // backfilled later, are recorded before the account exists, so that the account can be
+        // inserted as spent once its block is indexed.
+        match manager.get_database_backend() {
+            DatabaseBackend::Sqlite => rebuild_sqlite_account_transactions(manager, false).await?,
+            _ => execute_sql(
+                manager,
+                "ALTER TABLE account_transactions DROP CONSTRAINT account_transactions_hash_fk;",
+            )
+            .await?,
+        }
+
+        // Spent accounts that were pruned, so that indexing their creating block again does not
+        // bring them back as unspent.
+        manager
+            .create_table(
+                Table::create()
+                    .table(PrunedAccounts::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PrunedAccounts::Hash)
+                            .binary()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PrunedAccounts::SlotSpent)
+                            .big_integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(PrunedAccounts::SpentTxSignature)
+                            .binary()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PrunedAccounts::Table).to_owned())
+            .await?;
+
+        execute_sql(
+            manager,
+            "DELETE FROM account_transactions WHERE NOT EXISTS ( \
+                 SELECT 1 FROM accounts WHERE accounts.hash = account_transactions.hash \
+             );",
+        )
+        .await?;
+        match manager.get_database_backend() {
+            DatabaseBackend::Sqlite => rebuild_sqlite_account_transactions(manager, true).await?,
+            _ => {
+                execute_sql(
+                    manager,
+                    "ALTER TABLE account_transactions ADD CONSTRAINT account_transactions_hash_fk \
+                     FOREIGN KEY (hash) REFERENCES accounts (hash) ON DELETE CASCADE;",
+                )
+                .await?
+            }
+        }
+
+        Ok(())
+    }
+}
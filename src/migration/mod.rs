@@ -1,4 +1,5 @@
 pub use sea_orm_migration::prelude::*;
+use sha2::{Digest, Sha256};
 
 mod m20220101_000001_init;
 mod m20240623_000002_init;
@@ -6,6 +7,27 @@ mod m20240624_000003_init;
 mod m20240807_000004_init;
 mod m20240914_000005_init;
 mod m20241008_000006_init;
+mod m20241016_000007_init;
+mod m20241016_000008_init;
+mod m20241016_000009_init;
+mod m20241016_000010_init;
+mod m20241016_000011_init;
+mod m20241016_000012_init;
+mod m20241016_000013_init;
+mod m20241016_000014_init;
+mod m20241016_000015_init;
+mod m20241016_000016_init;
+mod m20241016_000017_init;
+mod m20241016_000018_init;
+mod m20241016_000019_init;
+mod m20241016_000020_init;
+mod m20241016_000021_init;
+mod m20241016_000022_init;
+mod m20241016_000023_init;
+mod m20241016_000024_init;
+mod m20241016_000025_init;
+mod m20241016_000026_init;
+mod m20241016_000027_init;
 mod model;
 
 pub struct Migrator;
@@ -20,6 +42,37 @@ impl MigratorTrait for Migrator {
             Box::new(m20240807_000004_init::Migration),
             Box::new(m20240914_000005_init::Migration),
             Box::new(m20241008_000006_init::Migration),
+            Box::new(m20241016_000007_init::Migration),
+            Box::new(m20241016_000008_init::Migration),
+            Box::new(m20241016_000009_init::Migration),
+            Box::new(m20241016_000010_init::Migration),
+            Box::new(m20241016_000011_init::Migration),
+            Box::new(m20241016_000012_init::Migration),
+            Box::new(m20241016_000013_init::Migration),
+            Box::new(m20241016_000014_init::Migration),
+            Box::new(m20241016_000015_init::Migration),
+            Box::new(m20241016_000016_init::Migration),
+            Box::new(m20241016_000017_init::Migration),
+            Box::new(m20241016_000018_init::Migration),
+            Box::new(m20241016_000019_init::Migration),
+            Box::new(m20241016_000020_init::Migration),
+            Box::new(m20241016_000021_init::Migration),
+            Box::new(m20241016_000022_init::Migration),
+            Box::new(m20241016_000023_init::Migration),
+            Box::new(m20241016_000024_init::Migration),
+            Box::new(m20241016_000025_init::Migration),
+            Box::new(m20241016_000026_init::Migration),
+            Box::new(m20241016_000027_init::Migration),
         ]
     }
 }
+
+/// Hex encoded hash of the names of all migrations, which changes whenever a migration is added.
+pub fn schema_fingerprint() -> String {
+    let mut hasher = Sha256::new();
+    for migration in Migrator::migrations() {
+        hasher.update(migration.name());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
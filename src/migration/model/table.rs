@@ -25,6 +25,12 @@ pub enum Accounts {
     PrevSpent,
     Seq,
     SlotCreated,
+    DataCompressed,
+    DataRef,
+    Discriminator,
+    TxSignature,
+    SpentTxSignature,
+    SlotSpent,
 }
 
 #[derive(Copy, Clone, Iden)]
@@ -38,6 +44,14 @@ pub enum TokenAccounts {
     Tlv,
     Spent,
     PrevSpent,
+    SlotCreated,
+    PermanentDelegate,
+    MetadataPointerAuthority,
+    MetadataPointerAddress,
+    TlvCompressed,
+    TxSignature,
+    SpentTxSignature,
+    SlotSpent,
 }
 
 #[derive(Copy, Clone, Iden)]
@@ -65,6 +79,8 @@ pub enum AccountTransactions {
     Table,
     Hash,
     Signature,
+    Slot,
+    Spent,
 }
 
 #[derive(Copy, Clone, Iden)]
@@ -99,3 +115,68 @@ pub enum StateTreeHistories {
     TransactionSignature,
     LeafIdx,
 }
+
+#[derive(Copy, Clone, Iden)]
+pub enum StateTreeNodeHistories {
+    Table,
+    Tree,
+    NodeIdx,
+    Seq,
+    Hash,
+    PrevSeq,
+}
+
+#[derive(Copy, Clone, Iden)]
+pub enum Trees {
+    Table,
+    Tree,
+    Height,
+    CanopyDepth,
+    ChangelogSize,
+}
+
+#[derive(Copy, Clone, Iden)]
+pub enum AccountData {
+    Table,
+    Hash,
+    Data,
+    DataCompressed,
+}
+
+#[derive(Copy, Clone, Iden)]
+pub enum Checkpoints {
+    Table,
+    Name,
+    Slot,
+}
+
+#[derive(Copy, Clone, Iden)]
+pub enum FailedBlocks {
+    Table,
+    Slot,
+    Block,
+    Error,
+}
+
+#[derive(Copy, Clone, Iden)]
+pub enum Metadata {
+    Table,
+    Key,
+    Value,
+}
+
+#[derive(Copy, Clone, Iden)]
+pub enum NullifierQueueElements {
+    Table,
+    Hash,
+    Tree,
+    LeafIndex,
+}
+
+#[derive(Copy, Clone, Iden)]
+pub enum PrunedAccounts {
+    Table,
+    Hash,
+    SlotSpent,
+    SpentTxSignature,
+}
@@ -0,0 +1,228 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, Order, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::UiTransactionEncoding;
+
+use crate::{
+    dao::generated::{accounts, state_trees},
+    ingester::{
+        parser::parse_transaction, persist::persisted_state_tree::ZERO_BYTES,
+        typedefs::block_info::TransactionInfo,
+    },
+};
+
+// Small enough that the hashes of a page fit into a single query within SQLite's parameter limit.
+const LEAF_PAGE_SIZE: u64 = 500;
+
+/// Leaves of each tree that `audit_tree_leaves` checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeafSelection {
+    /// Up to this many leaves per tree, picked at random.
+    Sample(u64),
+    All,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LeafAuditReport {
+    /// Leaves whose account was found in the transaction that created it.
+    pub verified_leaves: u64,
+    /// Leaves that could not be checked, since their account has been pruned, was indexed
+    /// without its transaction, or the RPC node no longer has the transaction.
+    pub unverified_leaves: u64,
+    /// Leaves that do not match their account or the transaction that created it.
+    pub discrepancies: u64,
+}
+
+enum CreatingTransaction {
+    Unavailable,
+    Unparsable(String),
+    /// Hash, tree and leaf index of the accounts that the transaction outputs.
+    Parsed(HashSet<(Vec<u8>, Pubkey, u64)>),
+}
+
+/// Cross-checks leaves of the indexed state trees against the transactions that created them.
+/// Each leaf has to belong to an account with the same tree and leaf index, and parsing the
+/// account's transaction, fetched again from the RPC node, has to output the account at that
+/// leaf. Nullified leaves are skipped.
+pub async fn audit_tree_leaves(
+    db: &DatabaseConnection,
+    rpc_client: &RpcClient,
+    selection: LeafSelection,
+) -> Result<LeafAuditReport> {
+    let trees = state_trees::Entity::find()
+        .filter(state_trees::Column::NodeIdx.eq(1))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|root| root.tree)
+        .collect::<Vec<_>>();
+    let mut report = LeafAuditReport::default();
+    for tree in trees {
+        let mut last_node_idx = 0;
+        loop {
+            let mut query = state_trees::Entity::find().filter(
+                state_trees::Column::Tree
+                    .eq(tree.clone())
+                    .and(state_trees::Column::Level.eq(0))
+                    .and(state_trees::Column::Hash.ne(ZERO_BYTES[0].to_vec())),
+            );
+            query = match selection {
+                LeafSelection::Sample(leaves) => query
+                    .order_by(Expr::cust("RANDOM()"), Order::Asc)
+                    .limit(leaves),
+                LeafSelection::All => query
+                    .filter(state_trees::Column::NodeIdx.gt(last_node_idx))
+                    .order_by_asc(state_trees::Column::NodeIdx)
+                    .limit(LEAF_PAGE_SIZE),
+            };
+            let leaves = query.all(db).await?;
+            let page_len = leaves.len() as u64;
+            if let Some(leaf) = leaves.last() {
+                last_node_idx = leaf.node_idx;
+            }
+            for chunk in leaves.chunks(LEAF_PAGE_SIZE as usize) {
+                audit_leaves(db, rpc_client, chunk, &mut report).await?;
+            }
+            if selection != LeafSelection::All || page_len < LEAF_PAGE_SIZE {
+                break;
+            }
+        }
+        info!(
+            "Audited the leaves of tree {}",
+            bs58::encode(&tree).into_string()
+        );
+    }
+    Ok(report)
+}
+
+async fn audit_leaves(
+    db: &DatabaseConnection,
+    rpc_client: &RpcClient,
+    leaves: &[state_trees::Model],
+    report: &mut LeafAuditReport,
+) -> Result<()> {
+    let accounts = accounts::Entity::find()
+        .filter(accounts::Column::Hash.is_in(leaves.iter().map(|leaf| leaf.hash.clone())))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|account| (account.hash.clone(), account))
+        .collect::<HashMap<_, _>>();
+    // Transactions often create several of the audited leaves, so each is only fetched once.
+    let mut transactions: HashMap<Signature, CreatingTransaction> = HashMap::new();
+    for leaf in leaves {
+        let tree = Pubkey::try_from(leaf.tree.as_slice()).context("Invalid tree pubkey")?;
+        let leaf_index = leaf.leaf_idx.context("Leaf without leaf index")? as u64;
+        let hash = bs58::encode(&leaf.hash).into_string();
+        let Some(account) = accounts.get(&leaf.hash) else {
+            warn!(
+                "Leaf {} of tree {} has no account, e.g. since it was pruned",
+                leaf_index, tree
+            );
+            report.unverified_leaves += 1;
+            continue;
+        };
+        if account.tree != leaf.tree || account.leaf_index as u64 != leaf_index {
+            error!(
+                "Leaf {} of tree {} holds account {}, which is indexed at leaf {} of tree {}",
+                leaf_index,
+                tree,
+                hash,
+                account.leaf_index,
+                bs58::encode(&account.tree).into_string()
+            );
+            report.discrepancies += 1;
+            continue;
+        }
+        let Some(signature) = account
+            .tx_signature
+            .as_deref()
+            .and_then(|signature| Signature::try_from(signature).ok())
+        else {
+            report.unverified_leaves += 1;
+            continue;
+        };
+        if let Entry::Vacant(entry) = transactions.entry(signature) {
+            entry.insert(fetch_creating_transaction(rpc_client, &signature).await?);
+        }
+        match &transactions[&signature] {
+            CreatingTransaction::Unavailable => {
+                warn!(
+                    "Transaction {} that created account {} is not available on the RPC node",
+                    signature, hash
+                );
+                report.unverified_leaves += 1;
+            }
+            CreatingTransaction::Unparsable(e) => {
+                error!(
+                    "Transaction {} that created account {} cannot be parsed: {}",
+                    signature, hash, e
+                );
+                report.discrepancies += 1;
+            }
+            CreatingTransaction::Parsed(out_accounts) => {
+                if out_accounts.contains(&(leaf.hash.clone(), tree, leaf_index)) {
+                    report.verified_leaves += 1;
+                } else {
+                    error!(
+                        "Transaction {} does not create account {} at leaf {} of tree {}",
+                        signature, hash, leaf_index, tree
+                    );
+                    report.discrepancies += 1;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn fetch_creating_transaction(
+    rpc_client: &RpcClient,
+    signature: &Signature,
+) -> Result<CreatingTransaction> {
+    let transaction = rpc_client
+        .get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )
+        .await;
+    let transaction = match transaction {
+        Ok(transaction) => transaction,
+        // The RPC node returns null for transactions it does not have, which fails to deserialize.
+        Err(ClientError {
+            kind: ClientErrorKind::SerdeJson(_),
+            ..
+        }) => return Ok(CreatingTransaction::Unavailable),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to fetch transaction {}", signature))
+        }
+    };
+    let slot = transaction.slot;
+    Ok(
+        match TransactionInfo::try_from(transaction).and_then(|tx| parse_transaction(&tx, slot)) {
+            Ok(state_update) => CreatingTransaction::Parsed(
+                state_update
+                    .out_accounts
+                    .into_iter()
+                    .map(|account| (account.hash.to_vec(), account.tree.0, account.leaf_index.0))
+                    .collect(),
+            ),
+            Err(e) => CreatingTransaction::Unparsable(e.to_string()),
+        },
+    )
+}
@@ -11,31 +11,84 @@ use log::{error, info};
 use once_cell::sync::Lazy;
 use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use tokio::{
-    task::JoinHandle,
-    time::{interval, sleep},
-};
+use tokio::{task::JoinHandle, time::sleep};
 
 use crate::{
     api::method::{get_indexer_health::HEALTH_CHECK_SLOT_DISTANCE, utils::Context},
-    common::fetch_current_slot_with_infinite_retry,
+    common::{fetch_current_slot_with_infinite_retry, prometheus::INDEXER_LAG},
     dao::generated::state_trees,
+    ingester::tree_info::parse_merkle_tree_account,
     metric,
 };
-use light_concurrent_merkle_tree::copy::ConcurrentMerkleTreeCopy;
-use light_concurrent_merkle_tree::light_hasher::Poseidon;
-use light_sdk::state::MerkleTreeMetadata;
 
 use crate::common::typedefs::hash::Hash;
 
 use solana_sdk::account::Account as SolanaAccount;
 
 use solana_sdk::pubkey::Pubkey;
-use std::mem;
+
+pub mod leaf_audit;
+pub mod root_consistency;
+
 const CHUNK_SIZE: usize = 100;
 
 pub static LATEST_SLOT: Lazy<Arc<AtomicU64>> = Lazy::new(|| Arc::new(AtomicU64::new(0)));
 
+// Last slot that the indexer of this process has persisted, or `NOT_INDEXING` if it does not run
+// an indexer.
+static LAST_INDEXED_SLOT: AtomicU64 = AtomicU64::new(NOT_INDEXING);
+const NOT_INDEXING: u64 = u64::MAX;
+
+// The block of the latest slot is usually still being fetched or persisted when the latest slot is
+// polled, so the indexer counts as caught up while it lags behind by at most this many slots.
+const CAUGHT_UP_LAG: u64 = 2;
+
+static MIN_SLOT_POLL_INTERVAL_MS: AtomicU64 = AtomicU64::new(100);
+static MAX_SLOT_POLL_INTERVAL_MS: AtomicU64 = AtomicU64::new(400);
+
+/// Bounds the interval at which the latest slot is polled. Takes effect for updaters started
+/// afterwards.
+pub fn set_slot_poll_intervals(min_interval: Duration, max_interval: Duration) {
+    MIN_SLOT_POLL_INTERVAL_MS.store(min_interval.as_millis() as u64, Ordering::SeqCst);
+    MAX_SLOT_POLL_INTERVAL_MS.store(max_interval.as_millis() as u64, Ordering::SeqCst);
+}
+
+/// Records the last slot that the indexer has persisted, which the interval at which the latest
+/// slot is polled follows.
+pub fn set_last_indexed_slot(slot: u64) {
+    LAST_INDEXED_SLOT.store(slot, Ordering::SeqCst);
+}
+
+/// Number of slots that the indexer of this process lags behind the latest slot, or `None` if it
+/// does not run an indexer.
+fn indexer_lag() -> Option<u64> {
+    match LAST_INDEXED_SLOT.load(Ordering::SeqCst) {
+        NOT_INDEXING => None,
+        last_indexed_slot => {
+            Some(LATEST_SLOT.load(Ordering::SeqCst).saturating_sub(last_indexed_slot))
+        }
+    }
+}
+
+/// Interval until the next poll of the latest slot, given the current interval and how many slots
+/// the indexer lags behind. While the indexer is behind, it has to learn about new slots as soon as
+/// possible, so the interval is halved. Once it has caught up, polls rarely find a slot that it
+/// can fetch right away, so the interval is doubled to save RPC requests. Both stay within
+/// `min_interval` and `max_interval`. Without an indexer, the latest slot is only needed for
+/// health checks and is polled every `max_interval`.
+pub fn next_slot_poll_interval(
+    interval: Duration,
+    indexer_lag: Option<u64>,
+    min_interval: Duration,
+    max_interval: Duration,
+) -> Duration {
+    let interval = match indexer_lag {
+        Some(lag) if lag > CAUGHT_UP_LAG => interval / 2,
+        _ => interval * 2,
+    };
+    interval.clamp(min_interval, max_interval)
+}
+
 async fn fetch_last_indexed_slot_with_infinite_retry(db: &DatabaseConnection) -> u64 {
     loop {
         if let Ok(context) = Context::extract(db).await {
@@ -65,6 +118,7 @@ pub fn continously_monitor_photon(
             metric! {
                 statsd_gauge!("indexing_lag", lag);
             }
+            INDEXER_LAG.set(&[], lag as i64);
             if lag < HEALTH_CHECK_SLOT_DISTANCE as u64 {
                 has_been_healthy = true;
             }
@@ -92,26 +146,24 @@ pub async fn start_latest_slot_updater(rpc_client: Arc<RpcClient>) {
         return;
     }
     update_latest_slot(&rpc_client).await;
+    let min_interval = Duration::from_millis(MIN_SLOT_POLL_INTERVAL_MS.load(Ordering::SeqCst));
+    let max_interval = Duration::from_millis(MAX_SLOT_POLL_INTERVAL_MS.load(Ordering::SeqCst));
     tokio::spawn(async move {
-        let mut interval = interval(Duration::from_millis(100));
+        let mut poll_interval = min_interval;
         loop {
-            interval.tick().await;
+            sleep(poll_interval).await;
             update_latest_slot(&rpc_client).await;
+            poll_interval =
+                next_slot_poll_interval(poll_interval, indexer_lag(), min_interval, max_interval);
+            metric! {
+                statsd_gauge!("slot_poll_interval_ms", poll_interval.as_millis() as u64);
+            }
         }
     });
 }
 
-fn parse_historical_roots(account: SolanaAccount) -> Vec<Hash> {
-    let roots = ConcurrentMerkleTreeCopy::<Poseidon, 26>::from_bytes_copy(
-        &account.data[8 + mem::size_of::<MerkleTreeMetadata>()..],
-    )
-    .unwrap()
-    .roots
-    .iter()
-    .map(|root| Hash::from(*root))
-    .collect();
-
-    roots
+fn parse_historical_roots(account: SolanaAccount) -> Option<Vec<Hash>> {
+    parse_merkle_tree_account(&account).map(|(_, roots)| roots)
 }
 
 async fn load_db_tree_roots_with_infinite_retry(db: &DatabaseConnection) -> Vec<(Pubkey, Hash)> {
@@ -178,7 +230,10 @@ async fn validate_tree_roots(rpc_client: &RpcClient, db_roots: Vec<(Pubkey, Hash
         let pubkeys = chunk.iter().map(|(pubkey, _)| pubkey.clone()).collect();
         let accounts = load_accounts_with_infinite_retry(rpc_client, pubkeys).await;
         for ((pubkey, db_hash), account) in chunk.iter().zip(accounts) {
-            let account_roots = parse_historical_roots(account);
+            let Some(account_roots) = parse_historical_roots(account) else {
+                log::error!("Failed to parse historical roots of tree {:?}", pubkey);
+                return;
+            };
             if !account_roots.contains(db_hash) {
                 log::error!(
                     "Root mismatch for pubkey {:?}. db_hash: {}, account_roots: {:?}",
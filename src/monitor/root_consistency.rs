@@ -0,0 +1,227 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use cadence_macros::{statsd_count, statsd_gauge};
+use log::{error, info};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, DatabaseTransaction,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect, Statement, TransactionTrait,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tokio::task::JoinHandle;
+
+use crate::{
+    api::method::get_indexer_health::HEALTH_CHECK_SLOT_DISTANCE,
+    common::{
+        fetch_current_slot_with_infinite_retry, prometheus::ROOT_MISMATCHES, typedefs::hash::Hash,
+    },
+    dao::generated::state_trees,
+    ingester::{
+        error::IngesterError,
+        persist::{compute_parent_hash, persisted_state_tree::ZERO_BYTES},
+    },
+    metric,
+};
+
+use super::{
+    fetch_last_indexed_slot_with_infinite_retry, load_accounts_with_infinite_retry,
+    parse_historical_roots, CHUNK_SIZE,
+};
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+// Leaves are loaded in pages of this size, since a tree can have millions of leaves.
+const LEAF_PAGE_SIZE: u64 = 10_000;
+
+static ROOT_MISMATCH_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Number of trees whose root did not match in the last root consistency check.
+pub fn root_mismatch_count() -> u64 {
+    ROOT_MISMATCH_COUNT.load(Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRoots {
+    pub tree: Pubkey,
+    /// Root node stored in `state_trees`.
+    pub persisted_root: Hash,
+    /// Root recomputed from the persisted leaves of the tree.
+    pub recomputed_root: Hash,
+}
+
+/// Recomputes the root of every tree from its persisted leaves. Each tree is read in a
+/// transaction of its own, so that the leaves and the root node belong to the same state.
+pub async fn recompute_tree_roots(
+    db: &DatabaseConnection,
+) -> Result<Vec<TreeRoots>, IngesterError> {
+    let trees = state_trees::Entity::find()
+        .filter(state_trees::Column::NodeIdx.eq(1))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|root| root.tree)
+        .collect::<Vec<_>>();
+    let mut tree_roots = Vec::new();
+    for tree in trees {
+        let txn = db.begin().await?;
+        if txn.get_database_backend() == DatabaseBackend::Postgres {
+            txn.execute(Statement::from_string(
+                txn.get_database_backend(),
+                "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ;".to_string(),
+            ))
+            .await?;
+        }
+        let roots = recompute_tree_root(&txn, tree).await?;
+        txn.commit().await?;
+        tree_roots.extend(roots);
+    }
+    Ok(tree_roots)
+}
+
+async fn recompute_tree_root(
+    txn: &DatabaseTransaction,
+    tree: Vec<u8>,
+) -> Result<Option<TreeRoots>, IngesterError> {
+    let Some(root) = state_trees::Entity::find()
+        .filter(
+            state_trees::Column::Tree
+                .eq(tree.clone())
+                .and(state_trees::Column::NodeIdx.eq(1)),
+        )
+        .one(txn)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let mut nodes = Vec::new();
+    loop {
+        let last_node_idx = nodes.last().map_or(0, |(node_idx, _)| *node_idx);
+        let page = state_trees::Entity::find()
+            .filter(
+                state_trees::Column::Tree
+                    .eq(tree.clone())
+                    .and(state_trees::Column::Level.eq(0))
+                    .and(state_trees::Column::NodeIdx.gt(last_node_idx)),
+            )
+            .order_by_asc(state_trees::Column::NodeIdx)
+            .limit(LEAF_PAGE_SIZE)
+            .all(txn)
+            .await?;
+        let page_len = page.len() as u64;
+        nodes.extend(page.into_iter().map(|leaf| (leaf.node_idx, leaf.hash)));
+        if page_len < LEAF_PAGE_SIZE {
+            break;
+        }
+    }
+    if nodes.is_empty() {
+        return Ok(None);
+    }
+
+    // Hashes the tree level by level. Only the nodes above persisted leaves are computed, all
+    // other nodes are empty.
+    let mut level = 0;
+    while nodes[0].0 > 1 {
+        let mut parents = Vec::with_capacity(nodes.len() / 2 + 1);
+        let mut children = nodes.into_iter().peekable();
+        while let Some((node_idx, hash)) = children.next() {
+            let (left, right) = match node_idx % 2 {
+                0 => match children.next_if(|(sibling_idx, _)| *sibling_idx == node_idx + 1) {
+                    Some((_, sibling_hash)) => (hash, sibling_hash),
+                    None => (hash, ZERO_BYTES[level].to_vec()),
+                },
+                _ => (ZERO_BYTES[level].to_vec(), hash),
+            };
+            parents.push((node_idx / 2, compute_parent_hash(left, right)?));
+        }
+        nodes = parents;
+        level += 1;
+    }
+    let parse_hash = |hash: Vec<u8>| {
+        Hash::try_from(hash)
+            .map_err(|e| IngesterError::DatabaseError(format!("Invalid node hash: {}", e)))
+    };
+    Ok(Some(TreeRoots {
+        tree: Pubkey::try_from(tree)
+            .map_err(|_| IngesterError::DatabaseError("Invalid tree pubkey".to_string()))?,
+        persisted_root: parse_hash(root.hash)?,
+        recomputed_root: parse_hash(nodes.remove(0).1)?,
+    }))
+}
+
+/// Compares the root of every tree, recomputed from the persisted leaves, with the persisted root
+/// and with the recent roots of the Merkle tree account on chain. Returns the number of trees
+/// whose roots do not match.
+pub async fn check_root_consistency(
+    db: &DatabaseConnection,
+    rpc_client: &RpcClient,
+) -> Result<u64, IngesterError> {
+    let tree_roots = recompute_tree_roots(db).await?;
+    let mut mismatches = 0;
+    for chunk in tree_roots.chunks(CHUNK_SIZE) {
+        let pubkeys = chunk.iter().map(|roots| roots.tree).collect();
+        let accounts = load_accounts_with_infinite_retry(rpc_client, pubkeys).await;
+        for (roots, account) in chunk.iter().zip(accounts) {
+            if roots.recomputed_root != roots.persisted_root {
+                error!(
+                    "Persisted root {} of tree {} does not match the root {} recomputed from its leaves",
+                    roots.persisted_root, roots.tree, roots.recomputed_root
+                );
+                mismatches += 1;
+                continue;
+            }
+            match parse_historical_roots(account) {
+                Some(onchain_roots) if onchain_roots.contains(&roots.recomputed_root) => {}
+                Some(_) => {
+                    error!(
+                        "Root {} of tree {} is not among the recent roots on chain",
+                        roots.recomputed_root, roots.tree
+                    );
+                    mismatches += 1;
+                }
+                None => error!("Failed to parse the Merkle tree account {}", roots.tree),
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Checks the consistency of the tree roots every `interval` and reports the number of trees
+/// whose roots do not match to the health check and as a metric. The check is skipped while the
+/// indexer is behind, since the persisted roots can then be older than the roots kept on chain.
+pub fn continously_check_root_consistency(
+    db: Arc<DatabaseConnection>,
+    rpc_client: Arc<RpcClient>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let current_slot = fetch_current_slot_with_infinite_retry(rpc_client.as_ref()).await;
+            let last_indexed_slot = fetch_last_indexed_slot_with_infinite_retry(db.as_ref()).await;
+            if current_slot.saturating_sub(last_indexed_slot) > HEALTH_CHECK_SLOT_DISTANCE as u64 {
+                tokio::time::sleep(RETRY_INTERVAL).await;
+                continue;
+            }
+            match check_root_consistency(db.as_ref(), rpc_client.as_ref()).await {
+                Ok(mismatches) => {
+                    if mismatches == 0 {
+                        info!("Roots of all trees are consistent");
+                    }
+                    ROOT_MISMATCH_COUNT.store(mismatches, Ordering::SeqCst);
+                    ROOT_MISMATCHES.set(&[], mismatches as i64);
+                    metric! {
+                        statsd_gauge!("root_mismatches", mismatches);
+                        statsd_count!("root_consistency_check", 1);
+                    }
+                }
+                Err(e) => error!("Failed to check the consistency of the tree roots: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    })
+}
@@ -1,5 +1,6 @@
-use photon_indexer::openapi::update_docs;
+use photon_indexer::openapi::{update_api_spec, update_docs};
 
 fn main() {
     update_docs(false);
+    update_api_spec(false);
 }
@@ -4,14 +4,18 @@ use crate::api::api::PhotonApi;
 use crate::api::method::get_compressed_accounts_by_owner::DataSlice;
 use crate::api::method::get_compressed_accounts_by_owner::FilterSelector;
 use crate::api::method::get_compressed_accounts_by_owner::Memcmp;
+use crate::api::method::get_compressed_accounts_by_owner::OwnerAccountsSummary;
 use crate::api::method::get_compressed_accounts_by_owner::PaginatedAccountList;
+use crate::api::method::get_compressed_address_info::CompressedAddressInfo;
 use crate::api::method::get_compressed_mint_token_holders::OwnerBalance;
 use crate::api::method::get_compressed_mint_token_holders::OwnerBalanceList;
 use crate::api::method::get_compressed_mint_token_holders::OwnerBalancesResponse;
 use crate::api::method::get_compressed_token_account_balance::TokenAccountBalance;
+use crate::api::method::get_compressed_token_account_by_owner_and_mint::TokenAccountListWithTotal;
 use crate::api::method::get_compressed_token_balances_by_owner::TokenBalance;
 use crate::api::method::get_compressed_token_balances_by_owner::TokenBalanceList;
 use crate::api::method::get_compressed_token_balances_by_owner::TokenBalanceListV2;
+use crate::api::method::get_compression_stats::CompressionStats;
 use crate::api::method::get_multiple_compressed_accounts::AccountList;
 
 use crate::api::method::get_multiple_new_address_proofs::AddressListWithTrees;
@@ -27,21 +31,28 @@ use crate::api::method::utils::SignatureInfo;
 use crate::api::method::utils::SignatureInfoList;
 use crate::api::method::utils::SignatureInfoListWithError;
 use crate::api::method::utils::SignatureInfoWithError;
+use crate::api::method::utils::SortDirection;
 use crate::api::method::utils::TokenAcccount;
 use crate::api::method::utils::TokenAccountList;
+use crate::api::method::utils::TokenAccountSortBy;
 use crate::common::typedefs::account::Account;
 use crate::common::typedefs::account::AccountData;
 use crate::common::typedefs::bs58_string::Base58String;
 use crate::common::typedefs::bs64_string::Base64String;
+use crate::common::typedefs::cursor::Cursor;
 use crate::common::typedefs::hash::Hash;
 use crate::common::typedefs::serializable_pubkey::SerializablePubkey;
 use crate::common::typedefs::serializable_signature::SerializableSignature;
 use crate::common::typedefs::token_data::AccountState;
+use crate::common::typedefs::token_data::MetadataPointer;
 use crate::common::typedefs::token_data::TokenData;
+use crate::common::typedefs::token_data::TokenExtensions;
+use crate::common::typedefs::u64_string::U64String;
 use crate::common::typedefs::unix_timestamp::UnixTimestamp;
 use crate::common::typedefs::unsigned_integer::UnsignedInteger;
 use crate::ingester::persist::persisted_state_tree::MerkleProofWithContext;
 use dirs;
+use serde_json::{json, Value};
 use utoipa::openapi::Components;
 use utoipa::openapi::Response;
 
@@ -68,6 +79,8 @@ use utoipa::openapi::ServerBuilder;
 use utoipa::OpenApi;
 
 const JSON_CONTENT_TYPE: &str = "application/json";
+const OPENRPC_VERSION: &str = "1.2.6";
+const API_SPEC_PATH: &str = "src/openapi/specs/openrpc.json";
 
 #[derive(OpenApi)]
 #[openapi(components(schemas(
@@ -75,6 +88,7 @@ const JSON_CONTENT_TYPE: &str = "application/json";
     Context,
     Hash,
     PaginatedAccountList,
+    OwnerAccountsSummary,
     Account,
     MerkleProofWithContext,
     TokenAccountList,
@@ -82,6 +96,7 @@ const JSON_CONTENT_TYPE: &str = "application/json";
     TokenAccountBalance,
     AccountList,
     Limit,
+    Cursor,
     Base58String,
     Base64String,
     SignatureInfoList,
@@ -91,11 +106,14 @@ const JSON_CONTENT_TYPE: &str = "application/json";
     TokenBalanceList,
     TokenBalance,
     TokenData,
+    TokenExtensions,
+    MetadataPointer,
     AccountData,
     AccountState,
     AccountWithOptionalTokenData,
     UnixTimestamp,
     UnsignedInteger,
+    U64String,
     CompressedProof,
     CompressedProofWithContext,
     MerkleContextWithNewAddressProof,
@@ -110,6 +128,11 @@ const JSON_CONTENT_TYPE: &str = "application/json";
     OwnerBalanceList,
     OwnerBalancesResponse,
     TokenBalanceListV2,
+    TokenAccountSortBy,
+    SortDirection,
+    TokenAccountListWithTotal,
+    CompressedAddressInfo,
+    CompressionStats,
 )))]
 struct ApiDoc;
 
@@ -368,3 +391,90 @@ pub fn update_docs(is_test: bool) {
         }
     }
 }
+
+// Lists the properties of the params object as by-name params. Params that are not an object, e.g.
+// the list of hashes of `getMultipleCompressedAccountProofs`, are described as a single param named
+// `params` whose schema is that of all params.
+fn method_params(request: Option<RefOr<Schema>>, components: &Components) -> Vec<Value> {
+    let Some(request) = request else {
+        return Vec::new();
+    };
+    let request = match request {
+        RefOr::Ref(reference) => {
+            let name = reference.ref_location.rsplit('/').next().unwrap();
+            components.schemas.get(name).unwrap().clone()
+        }
+        request => request,
+    };
+    match request {
+        RefOr::T(Schema::Object(object)) if !object.properties.is_empty() => object
+            .properties
+            .into_iter()
+            .map(|(name, schema)| {
+                json!({
+                    "name": name,
+                    "required": object.required.contains(&name),
+                    "schema": fix_examples_for_allOf_references(schema),
+                })
+            })
+            .collect(),
+        request => vec![json!({"name": "params", "required": true, "schema": request})],
+    }
+}
+
+/// Assembles an OpenRPC document that describes all methods of the API, for client SDK
+/// generators. It is served by the `rpc.discover` method and on `GET /api-spec`.
+pub fn build_api_spec() -> Value {
+    let mut components = ApiDoc::openapi().components.unwrap();
+    components.schemas = components
+        .schemas
+        .into_iter()
+        .map(|(k, v)| (k, fix_examples_for_allOf_references(v)))
+        .collect();
+    let methods = PhotonApi::method_api_specs()
+        .into_iter()
+        .map(|spec| {
+            json!({
+                "name": spec.name,
+                "paramStructure": "by-name",
+                "params": method_params(spec.request, &components),
+                "result": {
+                    "name": "result",
+                    "schema": fix_examples_for_allOf_references(spec.response),
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "Photon",
+            "description": "Indexer for ZK Compression on Solana",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{
+            "name": "mainnet",
+            "url": "https://mainnet.helius-rpc.com?api-key=<api_key>",
+        }],
+        "methods": methods,
+        "components": {"schemas": components.schemas},
+    })
+}
+
+/// Writes the document of `build_api_spec` to `src/openapi/specs/openrpc.json`. In tests, fails if
+/// the committed document is out of date instead.
+pub fn update_api_spec(is_test: bool) {
+    let spec = serde_json::to_string_pretty(&build_api_spec()).unwrap() + "\n";
+    let path = relative_project_path(API_SPEC_PATH);
+    match is_test {
+        true => {
+            let committed_spec = std::fs::read_to_string(&path).unwrap_or_default();
+            assert!(
+                committed_spec == spec,
+                "{} is out of date. Run photon-openapi to update it.",
+                API_SPEC_PATH
+            );
+        }
+        false => std::fs::write(path, spec).unwrap(),
+    }
+}
@@ -28,6 +28,8 @@ use s3::{bucket::Bucket, BucketConfiguration};
 use s3_utils::multipart_upload::put_object_stream_custom;
 use tokio::io::{AsyncRead, ReadBuf};
 pub mod s3_utils;
+pub mod owner_snapshot;
+pub mod state_snapshot;
 
 pub const MEGABYTE: usize = 1024 * 1024;
 pub const CHUNK_SIZE: usize = 100 * 1024 * 1024;
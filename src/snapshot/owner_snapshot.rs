@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect};
+use serde::Serialize;
+
+use crate::{
+    api::{
+        error::PhotonApiError,
+        method::utils::{parse_account_model, Context, HashModel, TokenAcccount},
+    },
+    common::{
+        account_data_deduplication::resolve_account_data,
+        metadata::get_indexing_start_slot,
+        typedefs::{account::Account, hash::Hash, serializable_pubkey::SerializablePubkey},
+    },
+    dao::generated::{accounts, token_accounts},
+    ingester::persist::{batch_size::SqlBatchConfig, parse_token_data},
+};
+
+/// The accounts that an owner held at a past slot, as far as it can be reconstructed from the
+/// indexed transaction history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnerSnapshot {
+    pub owner: SerializablePubkey,
+    pub slot: u64,
+    pub lamports: u64,
+    pub accounts: Vec<Account>,
+    pub token_accounts: Vec<TokenAcccount>,
+    /// Accounts that were created at or before the slot and are spent now, but whose spending
+    /// transaction is not indexed, e.g. because the database was restored from a state snapshot.
+    /// Whether they were still unspent at the slot is unknown.
+    pub unknown_spend_slot: Vec<Hash>,
+}
+
+/// Reconstructs the unspent accounts and token accounts of `owner` as of `slot`. Accounts are
+/// included if they were created at or before `slot` and either are unspent or were spent by a
+/// transaction after `slot`. Fails for slots that are not indexed, i.e. before indexing started or
+/// after the last indexed slot.
+pub async fn owner_snapshot_at_slot(
+    db: &DatabaseConnection,
+    batch_config: SqlBatchConfig,
+    owner: SerializablePubkey,
+    slot: u64,
+) -> Result<OwnerSnapshot> {
+    let indexed_slot = Context::extract(db).await?.slot;
+    if slot > indexed_slot {
+        return Err(anyhow!(
+            "Slot {} is not indexed yet. The last indexed slot is {}",
+            slot,
+            indexed_slot
+        ));
+    }
+    // Accounts spent before indexing started are missing, so the snapshot would be incomplete.
+    if let Some(start_slot) = get_indexing_start_slot(db).await? {
+        if slot < start_slot {
+            return Err(PhotonApiError::SlotNotIndexed { slot, start_slot }.into());
+        }
+    }
+
+    let owner_bytes: Vec<u8> = owner.into();
+    let account_hashes = accounts::Entity::find()
+        .select_only()
+        .column(accounts::Column::Hash)
+        .filter(accounts::Column::Owner.eq(owner_bytes.clone()))
+        .filter(accounts::Column::SlotCreated.lte(slot as i64))
+        .into_model::<HashModel>()
+        .all(db)
+        .await?;
+    let token_account_hashes = token_accounts::Entity::find()
+        .select_only()
+        .column(token_accounts::Column::Hash)
+        .filter(token_accounts::Column::Owner.eq(owner_bytes))
+        .filter(token_accounts::Column::SlotCreated.lte(slot as i64))
+        .into_model::<HashModel>()
+        .all(db)
+        .await?;
+    let hashes = account_hashes
+        .into_iter()
+        .chain(token_account_hashes)
+        .map(|model| model.hash)
+        .unique()
+        .collect_vec();
+
+    let mut snapshot = OwnerSnapshot {
+        owner,
+        slot,
+        lamports: 0,
+        accounts: Vec::new(),
+        token_accounts: Vec::new(),
+        unknown_spend_slot: Vec::new(),
+    };
+    for chunk in hashes.chunks(batch_config.batch_size(1)) {
+        let mut models = accounts::Entity::find()
+            .filter(accounts::Column::Hash.is_in(chunk.to_vec()))
+            .all(db)
+            .await?;
+        resolve_account_data(db, models.iter_mut()).await?;
+
+        for model in models {
+            if model.spent {
+                match model.slot_spent {
+                    Some(slot_spent) if slot_spent as u64 <= slot => continue,
+                    Some(_) => {}
+                    None => {
+                        snapshot
+                            .unknown_spend_slot
+                            .push(Hash::try_from(model.hash)?);
+                        continue;
+                    }
+                }
+            }
+            let account = parse_account_model(model)?;
+            match parse_token_data(&account)? {
+                Some(token_data) if token_data.owner == owner => {
+                    snapshot.token_accounts.push(TokenAcccount {
+                        account,
+                        token_data,
+                    })
+                }
+                _ => {
+                    snapshot.lamports += account.lamports.0;
+                    snapshot.accounts.push(account);
+                }
+            }
+        }
+    }
+    snapshot
+        .accounts
+        .sort_by_key(|account| (account.slot_created, account.hash.to_vec()));
+    snapshot.token_accounts.sort_by_key(|token_account| {
+        (
+            token_account.account.slot_created,
+            token_account.account.hash.to_vec(),
+        )
+    });
+    Ok(snapshot)
+}
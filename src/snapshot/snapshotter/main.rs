@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{builder::RangedU64ValueParser, Parser};
 use futures::StreamExt;
 use log::{error, info};
 use photon_indexer::common::{
@@ -40,7 +40,7 @@ struct Args {
     logging_format: LoggingFormat,
 
     /// Max number of blocks to fetch concurrently
-    #[arg(short, long)]
+    #[arg(short, long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
     max_concurrent_block_fetches: Option<usize>,
 
     /// Snapshot directory
@@ -256,6 +256,12 @@ async fn main() {
                     max_concurrent_block_fetches: args.max_concurrent_block_fetches.unwrap_or(20),
                     last_indexed_slot,
                     geyser_url: args.grpc_url.clone(),
+                    // Snapshots are replayed by indexers that may serve non-compression
+                    // signatures, so they keep every transaction.
+                    grpc_compression_transactions_only: false,
+                    geyser_plugin_socket: None,
+                    poll_compression_signatures: false,
+                    logs_websocket_url: None,
                 },
                 args.incremental_snapshot_interval_slots,
                 args.snapshot_interval_slots,
@@ -0,0 +1,386 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::StreamExt;
+use itertools::Itertools;
+use log::{info, warn};
+use sea_orm::{
+    sea_query::IntoValueTuple, AccessMode, ActiveModelTrait, ConnectionTrait, CursorTrait,
+    DatabaseBackend, DatabaseConnection, DatabaseTransaction, EntityTrait, FromQueryResult,
+    IntoActiveModel, IntoIdentity, IsolationLevel, PaginatorTrait, QueryOrder, QueryTrait,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    common::metadata::set_last_state_snapshot_slot,
+    dao::generated::{
+        account_data, accounts, blocks, failed_blocks, indexed_trees, owner_balances, state_trees,
+        token_accounts, token_owner_balances,
+    },
+    ingester::persist::batch_size::SqlBatchConfig,
+};
+
+const STATE_SNAPSHOT_MAGIC: &[u8; 8] = b"PHOTONSS";
+// Has to be bumped whenever the layout of `StateSnapshotPage` or of the exported models changes.
+const STATE_SNAPSHOT_VERSION: u8 = 2;
+const EXPORT_PAGE_SIZE: u64 = 10_000;
+const ZSTD_COMPRESSION_LEVEL: i32 = 3;
+
+/// A page of rows of one table. A snapshot is a sequence of pages terminated by `End`.
+#[derive(Serialize, Deserialize)]
+enum StateSnapshotPage {
+    Blocks(Vec<blocks::Model>),
+    Accounts(Vec<accounts::Model>),
+    AccountData(Vec<account_data::Model>),
+    TokenAccounts(Vec<token_accounts::Model>),
+    OwnerBalances(Vec<owner_balances::Model>),
+    TokenOwnerBalances(Vec<token_owner_balances::Model>),
+    StateTrees(Vec<state_trees::Model>),
+    IndexedTrees(Vec<indexed_trees::Model>),
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSnapshotSummary {
+    /// The last indexed slot when the snapshot was taken.
+    pub slot: u64,
+    pub rows: usize,
+}
+
+/// Writes the indexed state, i.e. the accounts, token accounts, balances and tree nodes, together
+/// with the last indexed block as a zstd compressed snapshot. Transaction history is not included.
+/// The state is read in a single transaction, so the indexer can keep running during the export.
+pub async fn export_state_snapshot(
+    db: &DatabaseConnection,
+    mut writer: impl Write,
+) -> Result<StateSnapshotSummary> {
+    let txn = match db.get_database_backend() {
+        DatabaseBackend::Postgres => {
+            db.begin_with_config(
+                Some(IsolationLevel::RepeatableRead),
+                Some(AccessMode::ReadOnly),
+            )
+            .await?
+        }
+        _ => db.begin().await?,
+    };
+    let last_block = blocks::Entity::find()
+        .order_by_desc(blocks::Column::Slot)
+        .one(&txn)
+        .await?
+        .ok_or_else(|| anyhow!("Cannot export a snapshot of an empty database"))?;
+    let slot = last_block.slot as u64;
+    let failed_blocks = failed_blocks::Entity::find().count(&txn).await?;
+    if failed_blocks > 0 {
+        warn!(
+            "The state of {} blocks in the dead-letter queue is not included in the snapshot",
+            failed_blocks
+        );
+    }
+
+    writer.write_all(STATE_SNAPSHOT_MAGIC)?;
+    writer.write_all(&[STATE_SNAPSHOT_VERSION])?;
+    writer.write_all(&slot.to_le_bytes())?;
+    let mut encoder = zstd::Encoder::new(writer, ZSTD_COMPRESSION_LEVEL)?;
+
+    let mut rows = 1;
+    write_page(&mut encoder, &StateSnapshotPage::Blocks(vec![last_block]))?;
+    rows += export_table::<accounts::Entity, _, _>(
+        &txn,
+        &mut encoder,
+        accounts::Column::Hash,
+        |model| model.hash.clone(),
+        StateSnapshotPage::Accounts,
+    )
+    .await?;
+    rows += export_table::<account_data::Entity, _, _>(
+        &txn,
+        &mut encoder,
+        account_data::Column::Hash,
+        |model| model.hash.clone(),
+        StateSnapshotPage::AccountData,
+    )
+    .await?;
+    rows += export_table::<token_accounts::Entity, _, _>(
+        &txn,
+        &mut encoder,
+        token_accounts::Column::Hash,
+        |model| model.hash.clone(),
+        StateSnapshotPage::TokenAccounts,
+    )
+    .await?;
+    rows += export_table::<owner_balances::Entity, _, _>(
+        &txn,
+        &mut encoder,
+        owner_balances::Column::Owner,
+        |model| model.owner.clone(),
+        StateSnapshotPage::OwnerBalances,
+    )
+    .await?;
+    rows += export_table::<token_owner_balances::Entity, _, _>(
+        &txn,
+        &mut encoder,
+        (
+            token_owner_balances::Column::Owner,
+            token_owner_balances::Column::Mint,
+        ),
+        |model| (model.owner.clone(), model.mint.clone()),
+        StateSnapshotPage::TokenOwnerBalances,
+    )
+    .await?;
+    rows += export_table::<state_trees::Entity, _, _>(
+        &txn,
+        &mut encoder,
+        (state_trees::Column::Tree, state_trees::Column::NodeIdx),
+        |model| (model.tree.clone(), model.node_idx),
+        StateSnapshotPage::StateTrees,
+    )
+    .await?;
+    rows += export_table::<indexed_trees::Entity, _, _>(
+        &txn,
+        &mut encoder,
+        (
+            indexed_trees::Column::Tree,
+            indexed_trees::Column::LeafIndex,
+        ),
+        |model| (model.tree.clone(), model.leaf_index),
+        StateSnapshotPage::IndexedTrees,
+    )
+    .await?;
+    write_page(&mut encoder, &StateSnapshotPage::End)?;
+    encoder.finish()?.flush()?;
+    txn.commit().await?;
+
+    Ok(StateSnapshotSummary { slot, rows })
+}
+
+fn write_page(writer: &mut impl Write, page: &StateSnapshotPage) -> Result<()> {
+    bincode::serialize_into(writer, page).context("Failed to write snapshot page")
+}
+
+async fn export_table<E, C, V>(
+    txn: &DatabaseTransaction,
+    writer: &mut impl Write,
+    cursor_columns: C,
+    cursor_values: fn(&E::Model) -> V,
+    page: fn(Vec<E::Model>) -> StateSnapshotPage,
+) -> Result<usize>
+where
+    E: EntityTrait,
+    E::Model: FromQueryResult + Send + Sync,
+    C: IntoIdentity + Clone,
+    V: IntoValueTuple,
+{
+    let mut rows = 0;
+    let mut last_values = None;
+    loop {
+        let mut cursor = E::find().cursor_by(cursor_columns.clone());
+        if let Some(last_values) = last_values.take() {
+            cursor.after(last_values);
+        }
+        let models = cursor.first(EXPORT_PAGE_SIZE).all(txn).await?;
+        let page_len = models.len();
+        last_values = models.last().map(cursor_values);
+        rows += page_len;
+        if page_len > 0 {
+            write_page(writer, &page(models))?;
+        }
+        if page_len < EXPORT_PAGE_SIZE as usize {
+            break;
+        }
+    }
+    info!("Exported {} rows of {}", rows, E::default().table_name());
+    Ok(rows)
+}
+
+/// Reads the last indexed slot of a snapshot without decompressing it.
+pub fn read_state_snapshot_slot(reader: &mut impl Read) -> Result<u64> {
+    let mut magic = [0; 8];
+    reader
+        .read_exact(&mut magic)
+        .context("Failed to read snapshot header")?;
+    if &magic != STATE_SNAPSHOT_MAGIC {
+        return Err(anyhow!("Not a Photon state snapshot"));
+    }
+    let mut version = [0; 1];
+    reader.read_exact(&mut version)?;
+    if version[0] != STATE_SNAPSHOT_VERSION {
+        return Err(anyhow!(
+            "Unsupported state snapshot version: {}. Expected version {}",
+            version[0],
+            STATE_SNAPSHOT_VERSION
+        ));
+    }
+    let mut slot = [0; 8];
+    reader.read_exact(&mut slot)?;
+    Ok(u64::from_le_bytes(slot))
+}
+
+/// Restores a snapshot written by `export_state_snapshot` into an empty, migrated database. The
+/// indexer then continues from the slot the snapshot was taken at.
+pub async fn import_state_snapshot(
+    db: &DatabaseConnection,
+    batch_config: SqlBatchConfig,
+    mut reader: impl Read,
+) -> Result<StateSnapshotSummary> {
+    let slot = read_state_snapshot_slot(&mut reader)?;
+    if blocks::Entity::find().count(db).await? > 0 {
+        return Err(anyhow!(
+            "Snapshots can only be imported into an empty database"
+        ));
+    }
+
+    let txn = db.begin().await?;
+    let mut decoder = zstd::Decoder::new(reader)?;
+    let mut rows = 0;
+    loop {
+        let page: StateSnapshotPage =
+            bincode::deserialize_from(&mut decoder).context("Failed to read snapshot page")?;
+        rows += match page {
+            StateSnapshotPage::Blocks(models) => insert_models(&txn, batch_config, models).await?,
+            StateSnapshotPage::Accounts(models) => {
+                insert_models(&txn, batch_config, models).await?
+            }
+            StateSnapshotPage::AccountData(models) => {
+                insert_models(&txn, batch_config, models).await?
+            }
+            StateSnapshotPage::TokenAccounts(models) => {
+                insert_models(&txn, batch_config, models).await?
+            }
+            StateSnapshotPage::OwnerBalances(models) => {
+                insert_models(&txn, batch_config, models).await?
+            }
+            StateSnapshotPage::TokenOwnerBalances(models) => {
+                insert_models(&txn, batch_config, models).await?
+            }
+            StateSnapshotPage::StateTrees(models) => {
+                insert_models(&txn, batch_config, models).await?
+            }
+            StateSnapshotPage::IndexedTrees(models) => {
+                insert_models(&txn, batch_config, models).await?
+            }
+            StateSnapshotPage::End => break,
+        };
+    }
+    set_last_state_snapshot_slot(&txn, slot).await?;
+    txn.commit().await?;
+
+    Ok(StateSnapshotSummary { slot, rows })
+}
+
+async fn insert_models<M, A>(
+    txn: &DatabaseTransaction,
+    batch_config: SqlBatchConfig,
+    models: Vec<M>,
+) -> Result<usize>
+where
+    M: IntoActiveModel<A>,
+    A: ActiveModelTrait,
+{
+    let rows = models.len();
+    let batch_size = batch_config.insert_batch_size::<A::Entity>();
+    for chunk in &models.into_iter().chunks(batch_size) {
+        let query = A::Entity::insert_many(chunk.map(IntoActiveModel::into_active_model))
+            .build(txn.get_database_backend());
+        txn.execute(query).await?;
+    }
+    Ok(rows)
+}
+
+/// Returns the hex encoded SHA-256 checksum of a snapshot. It is published next to the snapshot as
+/// `<snapshot>.sha256`, in the format of `sha256sum`.
+pub fn state_snapshot_checksum(mut reader: impl Read) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Exports a snapshot to the file at `path` and writes its checksum to `<path>.sha256`, so that
+/// the file can be served for `photon --snapshot-url`.
+pub async fn export_state_snapshot_to_file(
+    db: &DatabaseConnection,
+    path: &Path,
+) -> Result<StateSnapshotSummary> {
+    let writer = BufWriter::new(File::create(path)?);
+    let summary = export_state_snapshot(db, writer).await?;
+    let checksum = state_snapshot_checksum(BufReader::new(File::open(path)?))?;
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut checksum_path = path.as_os_str().to_owned();
+    checksum_path.push(".sha256");
+    std::fs::write(checksum_path, format!("{}  {}\n", checksum, file_name))?;
+    Ok(summary)
+}
+
+/// Downloads the snapshot at `url`, verifies it against the checksum at `<url>.sha256` and imports
+/// it. Returns `None` without downloading anything if the database already contains indexed blocks.
+pub async fn bootstrap_from_state_snapshot_url(
+    db: &DatabaseConnection,
+    batch_config: SqlBatchConfig,
+    url: &str,
+) -> Result<Option<StateSnapshotSummary>> {
+    if blocks::Entity::find().count(db).await? > 0 {
+        return Ok(None);
+    }
+    let http_client = reqwest::Client::new();
+    let checksum_url = format!("{}.sha256", url);
+    let expected_checksum = http_client
+        .get(&checksum_url)
+        .send()
+        .await?
+        .error_for_status()
+        .with_context(|| format!("Failed to download snapshot checksum from {}", checksum_url))?
+        .text()
+        .await?
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("Snapshot checksum at {} is empty", checksum_url))?
+        .to_lowercase();
+
+    info!("Downloading state snapshot from {}...", url);
+    let path = std::env::temp_dir().join(format!("photon-state-snapshot-{}", std::process::id()));
+    let result = async {
+        let response = http_client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to download snapshot from {}", url))?;
+        let mut file = tokio::fs::File::create(&path).await?;
+        let mut hasher = Sha256::new();
+        let mut byte_stream = response.bytes_stream();
+        while let Some(bytes) = byte_stream.next().await {
+            let bytes = bytes.context("Failed to read snapshot from response body")?;
+            hasher.update(&bytes);
+            file.write_all(&bytes).await?;
+        }
+        file.flush().await?;
+
+        let checksum = hex::encode(hasher.finalize());
+        if checksum != expected_checksum {
+            return Err(anyhow!(
+                "Snapshot checksum mismatch. Expected {}, got {}",
+                expected_checksum,
+                checksum
+            ));
+        }
+        info!("Verified snapshot checksum. Importing snapshot...");
+        import_state_snapshot(db, batch_config, BufReader::new(File::open(&path)?)).await
+    }
+    .await;
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove downloaded snapshot {:?}: {}", path, e);
+        }
+    }
+    result.map(Some)
+}
@@ -0,0 +1,292 @@
+//! Builders for the transactions that Photon indexes, so that programs built on the compression
+//! programs can test against Photon without a validator. Build a transaction with
+//! [`EventBundleBuilder`], index it in a block with `index_block` and query it with `PhotonApi`.
+
+use anchor_lang::AnchorSerialize;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::common::typedefs::bs64_string::Base64String;
+use crate::common::typedefs::hash::Hash;
+use crate::common::typedefs::token_data::TokenData;
+use crate::ingester::parser::indexer_events::{
+    ChangelogEvent, CompressedAccount, CompressedAccountData, MerkleTreeEvent,
+    MerkleTreeSequenceNumber, OutputCompressedAccountWithPackedContext, PathNode,
+    PublicTransactionEvent,
+};
+use crate::ingester::parser::{ACCOUNT_COMPRESSION_PROGRAM_ID, NOOP_PROGRAM_ID, SYSTEM_PROGRAM};
+use crate::ingester::persist::COMPRESSED_TOKEN_PROGRAM;
+use crate::ingester::typedefs::block_info::{Instruction, InstructionGroup, TransactionInfo};
+
+/// Discriminator of the data of compressed token accounts.
+pub const TOKEN_ACCOUNT_DISCRIMINATOR: [u8; 8] = [2, 0, 0, 0, 0, 0, 0, 0];
+
+/// Builds a transaction that emits a `PublicTransactionEvent` and the Merkle tree events of the
+/// same transaction, in the instruction layout that the parser expects.
+#[derive(Debug, Clone)]
+pub struct EventBundleBuilder {
+    event: PublicTransactionEvent,
+    // `MerkleTreeEvent` is not `Clone`, so the events are kept serialized.
+    merkle_tree_events: Vec<Vec<u8>>,
+    signature: Signature,
+    error: Option<String>,
+}
+
+impl Default for EventBundleBuilder {
+    fn default() -> Self {
+        Self {
+            event: PublicTransactionEvent::default(),
+            merkle_tree_events: vec![],
+            signature: Signature::new_unique(),
+            error: None,
+        }
+    }
+}
+
+impl EventBundleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spends the account with `hash`.
+    pub fn input(mut self, hash: Hash) -> Self {
+        self.event.input_compressed_account_hashes.push(hash.0);
+        self
+    }
+
+    /// Creates `account` with `hash` at `leaf_index` of `tree`. Unless set with
+    /// [`Self::sequence_number`], the sequence number of the first output of a tree is its leaf
+    /// index, as for appends to a tree without nullifications.
+    pub fn output(
+        mut self,
+        hash: Hash,
+        tree: Pubkey,
+        leaf_index: u32,
+        account: CompressedAccount,
+    ) -> Self {
+        let merkle_tree_index = match self.event.pubkey_array.iter().position(|key| *key == tree) {
+            Some(index) => index,
+            None => {
+                self.event.pubkey_array.push(tree);
+                self.event.pubkey_array.len() - 1
+            }
+        };
+        if !self
+            .event
+            .sequence_numbers
+            .iter()
+            .any(|seq| seq.pubkey == tree)
+        {
+            self.event.sequence_numbers.push(MerkleTreeSequenceNumber {
+                pubkey: tree,
+                seq: leaf_index as u64,
+            });
+        }
+        self.event
+            .output_compressed_accounts
+            .push(OutputCompressedAccountWithPackedContext {
+                compressed_account: account,
+                merkle_tree_index: merkle_tree_index as u8,
+            });
+        self.event.output_compressed_account_hashes.push(hash.0);
+        self.event.output_leaf_indices.push(leaf_index);
+        self
+    }
+
+    /// Sets the sequence number of the first output of `tree`. Later outputs of the tree get the
+    /// following numbers.
+    pub fn sequence_number(mut self, tree: Pubkey, seq: u64) -> Self {
+        self.event.sequence_numbers.retain(|seq| seq.pubkey != tree);
+        self.event
+            .sequence_numbers
+            .push(MerkleTreeSequenceNumber { pubkey: tree, seq });
+        self
+    }
+
+    /// Moves `lamports` into compressed accounts, or out of them if `is_compress` is false.
+    pub fn compression(mut self, is_compress: bool, lamports: u64) -> Self {
+        self.event.is_compress = is_compress;
+        self.event.compression_lamports = Some(lamports);
+        self
+    }
+
+    pub fn changelog(self, changelog: ChangelogEvent) -> Self {
+        self.merkle_tree_event(MerkleTreeEvent::V1(changelog))
+    }
+
+    pub fn merkle_tree_event(mut self, event: MerkleTreeEvent) -> Self {
+        self.merkle_tree_events.push(event.try_to_vec().unwrap());
+        self
+    }
+
+    pub fn signature(mut self, signature: Signature) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// Marks the transaction as failed, so that none of its events are indexed.
+    pub fn error(mut self, error: impl Into<String>) -> Self {
+        self.error = Some(error.into());
+        self
+    }
+
+    pub fn event(&self) -> &PublicTransactionEvent {
+        &self.event
+    }
+
+    pub fn build(self) -> TransactionInfo {
+        let instruction = |program_id: Pubkey, data: Vec<u8>| Instruction {
+            program_id,
+            data,
+            accounts: vec![],
+        };
+        let mut instruction_groups = vec![InstructionGroup {
+            outer_instruction: instruction(ACCOUNT_COMPRESSION_PROGRAM_ID, vec![]),
+            inner_instructions: vec![
+                instruction(SYSTEM_PROGRAM, vec![]),
+                instruction(NOOP_PROGRAM_ID, self.event.try_to_vec().unwrap()),
+            ],
+        }];
+        instruction_groups.extend(self.merkle_tree_events.into_iter().map(|event| {
+            InstructionGroup {
+                outer_instruction: instruction(ACCOUNT_COMPRESSION_PROGRAM_ID, vec![]),
+                inner_instructions: vec![instruction(NOOP_PROGRAM_ID, event)],
+            }
+        }));
+        TransactionInfo {
+            instruction_groups,
+            signature: self.signature,
+            error: self.error,
+        }
+    }
+}
+
+/// Changelog event of appending `leaves`, pairs of a leaf index and a hash, to `tree`. `height` is
+/// the number of nodes of a path, e.g. `TREE_HEIGHT`. Only the leaves of the paths are set, since
+/// Photon computes the other nodes itself.
+pub fn changelog_event(
+    tree: Pubkey,
+    height: u32,
+    seq: u64,
+    leaves: &[(u32, Hash)],
+) -> ChangelogEvent {
+    let paths = leaves
+        .iter()
+        .map(|(leaf_index, hash)| {
+            (0..height)
+                .map(|level| PathNode {
+                    node: if level == 0 { hash.0 } else { [0; 32] },
+                    index: (2_u32.pow(height - 1) + leaf_index) >> level,
+                })
+                .collect()
+        })
+        .collect();
+    ChangelogEvent {
+        id: tree.to_bytes(),
+        paths,
+        seq,
+        index: 0,
+    }
+}
+
+/// Compressed token account that holds `token_data`. The data hash is not checked by Photon and
+/// left zeroed.
+pub fn token_account(token_data: &TokenData) -> CompressedAccount {
+    CompressedAccount {
+        owner: COMPRESSED_TOKEN_PROGRAM,
+        lamports: 0,
+        address: None,
+        data: Some(CompressedAccountData {
+            discriminator: TOKEN_ACCOUNT_DISCRIMINATOR,
+            data: token_data.try_to_vec().unwrap(),
+            data_hash: [0; 32],
+        }),
+    }
+}
+
+/// Builds the `tlv` of a [`TokenData`] in the Token-2022 layout.
+#[derive(Debug, Clone, Default)]
+pub struct TlvBuilder {
+    data: Vec<u8>,
+}
+
+impl TlvBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an extension with any type and value, e.g. one that Photon does not parse.
+    pub fn extension(mut self, extension_type: u16, value: &[u8]) -> Self {
+        self.data.extend_from_slice(&extension_type.to_le_bytes());
+        self.data
+            .extend_from_slice(&(value.len() as u16).to_le_bytes());
+        self.data.extend_from_slice(value);
+        self
+    }
+
+    pub fn transfer_fee_amount(self, withheld_amount: u64) -> Self {
+        self.extension(2, &withheld_amount.to_le_bytes())
+    }
+
+    pub fn permanent_delegate(self, delegate: Pubkey) -> Self {
+        self.extension(12, &delegate.to_bytes())
+    }
+
+    pub fn metadata_pointer(
+        self,
+        authority: Option<Pubkey>,
+        metadata_address: Option<Pubkey>,
+    ) -> Self {
+        let key = |key: Option<Pubkey>| key.unwrap_or_default().to_bytes();
+        self.extension(18, &[key(authority), key(metadata_address)].concat())
+    }
+
+    pub fn build(self) -> Base64String {
+        Base64String(self.data)
+    }
+}
+
+#[test]
+fn test_event_bundle_builder() {
+    use crate::common::typedefs::token_data::TokenExtensions;
+    use crate::common::typedefs::u64_string::U64String;
+    use crate::ingester::parser::parse_transaction;
+    use crate::ingester::persist::{parse_token_data, TREE_HEIGHT};
+
+    let tree = Pubkey::new_unique();
+    let (spent, created) = (Hash::new_unique(), Hash::new_unique());
+    let token_data = TokenData {
+        mint: Pubkey::new_unique().into(),
+        owner: Pubkey::new_unique().into(),
+        amount: U64String(100),
+        tlv: Some(TlvBuilder::new().transfer_fee_amount(5).build()),
+        ..Default::default()
+    };
+    let transaction = EventBundleBuilder::new()
+        .input(spent.clone())
+        .output(created.clone(), tree, 3, token_account(&token_data))
+        .changelog(changelog_event(
+            tree,
+            TREE_HEIGHT,
+            3,
+            &[(3, created.clone())],
+        ))
+        .build();
+
+    let state_update = parse_transaction(&transaction, 1).unwrap();
+    assert!(state_update.in_accounts.contains(&spent));
+    let account = &state_update.out_accounts[0];
+    assert_eq!(account.hash, created);
+    assert_eq!(account.leaf_index.0, 3);
+    assert_eq!(account.seq.0, 3);
+    let parsed = parse_token_data(account).unwrap().unwrap();
+    assert_eq!(parsed.amount, token_data.amount);
+    assert_eq!(
+        parsed.extensions,
+        Some(TokenExtensions {
+            withheld_transfer_fee: Some(U64String(5)),
+            ..Default::default()
+        })
+    );
+    assert_eq!(state_update.path_updates.len(), 1);
+}
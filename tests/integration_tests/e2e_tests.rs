@@ -7,7 +7,7 @@ use photon_indexer::api::method::get_multiple_new_address_proofs::AddressList;
 use photon_indexer::api::method::get_transaction_with_compression_info::get_transaction_helper;
 use photon_indexer::api::method::get_validity_proof::CompressedProof;
 use photon_indexer::common::typedefs::serializable_pubkey::SerializablePubkey;
-use photon_indexer::ingester::index_block;
+use photon_indexer::ingester::{index_block, IndexerContext};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 
@@ -67,7 +67,7 @@ fn all_indexing_methodologies(
                 reset_tables(db_conn.as_ref()).await.unwrap();
                 // HACK: We index a block so that API methods can fetch the current slot.
                 index_block(
-                    db_conn.as_ref(),
+                    &IndexerContext::new(db_conn.clone()),
                     &BlockInfo {
                         metadata: BlockMetadata {
                     slot: 0,
@@ -453,7 +453,7 @@ async fn test_index_block_metadata(
 
     let slot = 254170887;
     let block = cached_fetch_block(&setup.name, setup.client.clone(), slot).await;
-    index_block(&setup.db_conn, &block).await.unwrap();
+    index_block(&setup.indexer, &block).await.unwrap();
     let filter = blocks::Column::Slot.eq(block.metadata.slot);
 
     let block_model = blocks::Entity::find()
@@ -479,12 +479,12 @@ async fn test_index_block_metadata(
     assert_eq!(block_model.block_time, 1710441678);
 
     // Verify that we don't get an error if we try to index the same block again
-    index_block(&setup.db_conn, &block).await.unwrap();
+    index_block(&setup.indexer, &block).await.unwrap();
     assert_eq!(setup.api.get_indexer_slot().await.unwrap().0, slot);
 
     // Verify that get_indexer_slot() gets updated a new block is indexed.
     let block = cached_fetch_block(&setup.name, setup.client.clone(), slot + 1).await;
-    index_block(&setup.db_conn, &block).await.unwrap();
+    index_block(&setup.indexer, &block).await.unwrap();
     assert_eq!(setup.api.get_indexer_slot().await.unwrap().0, slot + 1);
 }
 
@@ -507,7 +507,7 @@ async fn test_get_latest_non_voting_signatures(
 
     let slot = 270893658;
     let block = cached_fetch_block(&setup.name, setup.client.clone(), slot).await;
-    index_block(&setup.db_conn, &block).await.unwrap();
+    index_block(&setup.indexer, &block).await.unwrap();
     let all_nonvoting_transactions = setup
         .api
         .get_latest_non_voting_signatures(GetLatestSignaturesRequest {
@@ -542,7 +542,7 @@ async fn test_get_latest_non_voting_signatures_with_failures(
 
     let slot = 279620356;
     let block = cached_fetch_block(&setup.name, setup.client.clone(), slot).await;
-    index_block(&setup.db_conn, &block).await.unwrap();
+    index_block(&setup.indexer, &block).await.unwrap();
     let all_nonvoting_transactions = setup
         .api
         .get_latest_non_voting_signatures(GetLatestSignaturesRequest {
@@ -8,3 +8,5 @@ mod open_api_tests;
 mod prod_tests;
 mod snapshot_tests;
 mod utils;
+mod webhook_tests;
+mod wire_compat_tests;
@@ -1,56 +1,183 @@
 use crate::utils::*;
 use ::borsh::{to_vec, BorshDeserialize, BorshSerialize};
 use function_name::named;
+use futures::{pin_mut, StreamExt};
+use photon_indexer::api::api::{ApiConfig, PhotonApi};
+use photon_indexer::api::cache::ApiCache;
+use photon_indexer::api::cors::CorsConfig;
+use photon_indexer::api::error::PhotonApiError;
+use photon_indexer::api::method::compressed_account_subscribe::{
+    compressed_account_subscribe, CompressedAccountEventType,
+};
 use photon_indexer::api::method::get_compressed_accounts_by_owner::{
-    DataSlice, FilterSelector, GetCompressedAccountsByOwnerRequest, Memcmp,
+    DataSlice, FilterSelector, GetCompressedAccountsByOwnerRequest, Memcmp, OwnerAccountsSummary,
 };
 use photon_indexer::api::method::get_compressed_balance_by_owner::GetCompressedBalanceByOwnerRequest;
+use photon_indexer::api::method::get_compressed_token_account_by_owner_and_mint::GetCompressedTokenAccountByOwnerAndMintRequest;
 use photon_indexer::api::method::get_compressed_token_balances_by_owner::GetCompressedTokenBalancesByOwnerRequest;
+use photon_indexer::api::method::get_compression_stats::{
+    CompressionStats, GetCompressionStatsRequest,
+};
+use photon_indexer::api::method::get_historical_compressed_account_proof::{
+    get_historical_compressed_account_proof, GetHistoricalCompressedAccountProofRequest,
+};
 use photon_indexer::api::method::get_multiple_compressed_accounts::GetMultipleCompressedAccountsRequest;
 use photon_indexer::api::method::get_validity_proof::{
     get_validity_proof, GetValidityProofRequest,
 };
+use photon_indexer::api::method::indexer_slot_subscribe::indexer_slot_subscribe;
 use photon_indexer::api::method::utils::{
     CompressedAccountRequest, GetCompressedTokenAccountsByDelegate,
-    GetCompressedTokenAccountsByOwner,
+    GetCompressedTokenAccountsByOwner, HashRequest, SortDirection, TokenAccountList,
+    TokenAccountSortBy,
+};
+use photon_indexer::api::proof_cache::{get_cached_proof, set_proof_cache_capacity};
+use photon_indexer::api::rate_limit::{RateLimit, RateLimitConfig};
+use photon_indexer::api::read_replicas::ReadReplicas;
+use photon_indexer::api::request_limits::{MethodFilter, RequestLimits};
+use photon_indexer::api::rpc_server::run_server;
+use photon_indexer::common::db_pool::DatabasePoolConfig;
+use photon_indexer::common::get_failover_rpc_client;
+use photon_indexer::common::get_rate_limited_rpc_client;
+use photon_indexer::common::metadata::{
+    get_feature_flag, get_indexing_start_slot, get_last_state_snapshot_slot,
+    get_or_create_cursor_secret, get_schema_fingerprint, get_sink_cursor,
+    record_indexing_start_slot, set_feature_flag, set_metadata, set_schema_fingerprint,
+    set_sink_cursor, MetadataKey,
 };
+use photon_indexer::common::rate_limited_rpc_sender::RpcRateLimitConfig;
+use photon_indexer::common::relative_project_path;
+use photon_indexer::common::setup_pg_pool_with_config;
+use photon_indexer::common::supervisor::supervise;
 use photon_indexer::common::typedefs::bs58_string::Base58String;
+use photon_indexer::common::typedefs::cursor::Cursor;
+use photon_indexer::common::unspent_hash_filter::BloomFilter;
+use photon_indexer::conformance::instructions::{
+    invoke_instruction, sol_pool_pda, InstructionDataInvoke,
+};
 use photon_indexer::ingester::persist::persisted_indexed_merkle_tree::{
     get_exclusion_range_with_proof, update_indexed_tree_leaves, validate_tree,
 };
+use photon_indexer::migration::{Migrator, MigratorTrait};
+use photon_indexer::monitor::next_slot_poll_interval;
+use photon_indexer::monitor::root_consistency::recompute_tree_roots;
 
+use photon_indexer::common::typedefs::u64_string::U64String;
+use photon_indexer::common::typedefs::unix_timestamp::UnixTimestamp;
 use photon_indexer::common::typedefs::unsigned_integer::UnsignedInteger;
 use photon_indexer::dao::generated::{indexed_trees, state_trees};
+use photon_indexer::ingester::persist::batch_size::SqlBatchConfig;
+use photon_indexer::ingester::persist::in_memory_tree::InMemoryTrees;
+use photon_indexer::ingester::persist::node_history::set_proof_history_window;
 use photon_indexer::ingester::persist::persisted_indexed_merkle_tree::multi_append;
 use photon_indexer::ingester::persist::persisted_state_tree::{
-    get_multiple_compressed_leaf_proofs, ZERO_BYTES,
+    get_multiple_compressed_leaf_proofs, get_multiple_compressed_leaf_proofs_by_tree, zero_hashes,
+    MAX_HEIGHT, ZERO_BYTES,
+};
+use photon_indexer::ingester::pruner::prune_spent_accounts;
+use sea_orm::{
+    ConnectionTrait, DbErr, Iterable, PaginatorTrait, QueryFilter, QueryOrder,
+    SqlxPostgresConnector, SqlxSqliteConnector, Statement, TransactionTrait,
 };
-use sea_orm::{QueryFilter, TransactionTrait};
 
+use photon_indexer::common::account_data_compression::set_account_data_compression;
+use photon_indexer::common::account_data_deduplication::set_account_data_deduplication;
 use photon_indexer::common::typedefs::account::Account;
 use photon_indexer::common::typedefs::bs64_string::Base64String;
 use photon_indexer::common::typedefs::{hash::Hash, serializable_pubkey::SerializablePubkey};
-use photon_indexer::dao::generated::accounts;
-use photon_indexer::ingester::index_block;
-use photon_indexer::ingester::parser::state_update::StateUpdate;
-use photon_indexer::ingester::persist::persisted_state_tree::{persist_leaf_nodes, LeafNode};
+use photon_indexer::dao::generated::{
+    account_data, account_transactions, accounts, blocks, pruned_accounts, token_accounts,
+    token_owner_balances, transactions,
+};
+use photon_indexer::ingester::backfill::{
+    backfill_slot_range, fetch_slots_to_backfill, BackfillBlockSource,
+};
+use photon_indexer::ingester::block_cache::{
+    cached_slots, load_cached_block, replay_cached_blocks, set_block_cache_dir,
+};
+use photon_indexer::ingester::checkpoint::fetch_checkpoint;
+use photon_indexer::ingester::dead_letter::{
+    dead_letter_block, fetch_failed_blocks, retry_failed_blocks, RetrySummary,
+};
+use photon_indexer::ingester::events::{subscribe, IngestionEvent, PendingAccountEvents};
+use photon_indexer::ingester::fetchers::bigtable::BigtableBlockFetcher;
+use photon_indexer::ingester::fetchers::fixtures::{get_fixture_block_stream, load_fixture_blocks};
+use photon_indexer::ingester::fetchers::logs::get_logs_block_stream_with_rpc_fallback;
+use photon_indexer::ingester::fetchers::signatures::get_signature_block_stream;
+use photon_indexer::ingester::fetchers::socket::{encode_block_frame, get_socket_block_stream};
+use photon_indexer::ingester::indexer::backpressure::Backpressure;
+use photon_indexer::ingester::indexer::{
+    fetch_last_indexed_slot_with_infinite_retry, index_block_stream, parse_block_stream,
+};
+use photon_indexer::ingester::parser::state_update::{
+    AccountTransaction, LeafNullification, StateUpdate, Transaction,
+};
+use photon_indexer::ingester::persist::persisted_state_tree::{
+    count_path_nodes, persist_leaf_nodes, LeafNode,
+};
+use photon_indexer::ingester::persist::rollback::rollback_to_slot;
+use photon_indexer::ingester::persist::token_data_monitor::TokenDataParseMonitor;
 use photon_indexer::ingester::persist::{
-    compute_parent_hash, persist_token_accounts, EnrichedTokenAccount,
+    compute_parent_hash, persist_state_update, persist_token_accounts, EnrichedTokenAccount,
+    COMPRESSED_TOKEN_PROGRAM,
+};
+use photon_indexer::ingester::reorg::detect_fork;
+use photon_indexer::ingester::{
+    index_block, index_block_batch, index_block_batch_with_infinite_retries, IndexerContext,
+};
+use photon_indexer::monitor::leaf_audit::{audit_tree_leaves, LeafAuditReport, LeafSelection};
+use prost::Message as _;
+use yellowstone_grpc_proto::solana::storage::confirmed_block::{
+    self, ConfirmedBlock, ConfirmedTransaction,
 };
 
-use photon_indexer::ingester::typedefs::block_info::{BlockInfo, BlockMetadata};
-use sea_orm::{EntityTrait, Set};
+use photon_indexer::ingester::error::IngesterError;
+use photon_indexer::ingester::parser::indexer_events::{
+    BatchedMerkleTreeEvent, ChangelogEvent, CompressedAccount, CompressedAccountData,
+    MerkleTreeEvent, MerkleTreeSequenceNumber, NullifierEvent,
+    OutputCompressedAccountWithPackedContext, PathNode, PublicTransactionEvent,
+};
+use photon_indexer::ingester::parser::{parse_transaction, ACCOUNT_COMPRESSION_PROGRAM_ID};
+use photon_indexer::ingester::typedefs::block_info::{
+    parse_sanitized_transaction, parse_ui_confirmed_blocked, BlockInfo, BlockMetadata, Instruction,
+    InstructionGroup, TransactionInfo,
+};
+use photon_indexer::snapshot::owner_snapshot::{owner_snapshot_at_slot, OwnerSnapshot};
+use photon_indexer::snapshot::state_snapshot::{
+    bootstrap_from_state_snapshot_url, export_state_snapshot, import_state_snapshot,
+    read_state_snapshot_slot, state_snapshot_checksum,
+};
+use sea_orm::{sea_query::Expr, EntityTrait, Set};
 use serial_test::serial;
+use solana_client::nonblocking::rpc_client::RpcClient;
 
 use photon_indexer::common::typedefs::account::AccountData;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
 
 use photon_indexer::common::typedefs::token_data::{AccountState, TokenData};
 use sqlx::types::Decimal;
 
+use jsonrpsee::types::error::CallError;
 use photon_indexer::api::method::utils::Limit;
 use sea_orm::ColumnTrait;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::{
+    instruction::CompiledInstruction,
+    message::{v0, Message, MessageHeader, VersionedMessage},
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
+    EncodedTransaction, EncodedTransactionWithStatusMeta, TransactionBinaryEncoding,
+    TransactionStatusMeta, UiCompiledInstruction, UiConfirmedBlock, UiInnerInstructions,
+    UiInstruction, UiLoadedAddresses, UiTransactionStatusMeta,
+};
+use std::str::FromStr;
+use std::time::Duration;
 use std::vec;
 
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
@@ -71,7 +198,7 @@ async fn test_persist_state_update_basic(
 
     // HACK: We index a block so that API methods can fetch the current slot.
     index_block(
-        &setup.db_conn,
+        &setup.indexer,
         &BlockInfo {
             metadata: BlockMetadata {
                 slot: 0,
@@ -93,7 +220,7 @@ async fn test_persist_state_update_basic(
             data_hash: Hash::new_unique(),
         }),
         owner: SerializablePubkey::new_unique(),
-        lamports: UnsignedInteger(1000),
+        lamports: U64String(1000),
         tree: SerializablePubkey::new_unique(),
         leaf_index: UnsignedInteger(0),
         seq: UnsignedInteger(0),
@@ -144,751 +271,7501 @@ async fn test_persist_state_update_basic(
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_multiple_accounts(
+async fn test_compressed_account_subscribe(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
     let name = trim_test_name(function_name!());
     let setup = setup(name, db_backend).await;
 
-    // HACK: We index a block so that API methods can fetch the current slot.
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: Some(SerializablePubkey::new_unique()),
+        data: None,
+        owner: SerializablePubkey::new_unique(),
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(5),
+    };
+    let other_account = Account {
+        hash: Hash::new_unique(),
+        address: Some(SerializablePubkey::new_unique()),
+        leaf_index: UnsignedInteger(1),
+        ..account.clone()
+    };
+
+    let notifications = compressed_account_subscribe(CompressedAccountRequest {
+        address: account.address,
+        hash: None,
+    })
+    .unwrap();
+    let mut notifications = Box::pin(notifications);
+
+    let block = |slot: u64| BlockMetadata {
+        slot,
+        block_time: 1_700_000_000 + slot as i64,
+        ..Default::default()
+    };
+
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts.push(account.clone());
+    state_update.out_accounts.push(other_account);
+    let events = PendingAccountEvents::collect(&state_update, &[&block(5)]).unwrap();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    events
+        .publish(&setup.db_conn, setup.indexer.sql_batch_config)
+        .await;
+
+    // Spends carry the slot of their own block when several blocks are committed at once.
     index_block(
-        &setup.db_conn,
+        &setup.indexer,
         &BlockInfo {
-            metadata: BlockMetadata {
-                slot: 0,
-                ..Default::default()
-            },
-            ..Default::default()
+            metadata: block(6),
+            transactions: vec![],
         },
     )
     .await
     .unwrap();
-
-    let owner1 = SerializablePubkey::new_unique();
-    let owner2 = SerializablePubkey::new_unique();
-    let mut state_update = StateUpdate::default();
-
-    let accounts = vec![
-        Account {
-            hash: Hash::new_unique(),
-            address: Some(SerializablePubkey::new_unique()),
-            data: Some(AccountData {
-                discriminator: UnsignedInteger(0),
-                data: Base64String(vec![1; 500]),
-                data_hash: Hash::new_unique(),
-            }),
-            owner: owner1,
-            lamports: UnsignedInteger(1000),
-            tree: SerializablePubkey::new_unique(),
-            leaf_index: UnsignedInteger(10),
-            seq: UnsignedInteger(1),
-            slot_created: UnsignedInteger(0),
-        },
-        Account {
-            hash: Hash::new_unique(),
-            address: None,
-            data: Some(AccountData {
-                discriminator: UnsignedInteger(1),
-                data: Base64String(vec![2; 500]),
-                data_hash: Hash::new_unique(),
-            }),
-            owner: owner1,
-            lamports: UnsignedInteger(1030),
-            tree: SerializablePubkey::new_unique(),
-            leaf_index: UnsignedInteger(11),
-            seq: UnsignedInteger(2),
-            slot_created: UnsignedInteger(0),
-        },
-        Account {
-            hash: Hash::new_unique(),
-            address: Some(SerializablePubkey::new_unique()),
-            data: Some(AccountData {
-                discriminator: UnsignedInteger(4),
-                data: Base64String(vec![4; 500]),
-                data_hash: Hash::new_unique(),
-            }),
-            owner: owner2,
-            lamports: UnsignedInteger(10020),
-            tree: SerializablePubkey::new_unique(),
-            leaf_index: UnsignedInteger(13),
-            seq: UnsignedInteger(3),
-            slot_created: UnsignedInteger(1),
-        },
-        Account {
-            hash: Hash::new_unique(),
-            address: Some(SerializablePubkey::new_unique()),
-            data: Some(AccountData {
-                discriminator: UnsignedInteger(10),
-                data: Base64String(vec![5; 500]),
-                data_hash: Hash::new_unique(),
-            }),
-            owner: owner2,
-            lamports: UnsignedInteger(10100),
-            tree: SerializablePubkey::new_unique(),
-            leaf_index: UnsignedInteger(23),
-            seq: UnsignedInteger(1),
-            slot_created: UnsignedInteger(0),
-        },
-    ];
-    state_update.out_accounts = accounts.clone();
+    let mut state_update = StateUpdate::new();
+    let signature = Signature::new_unique();
+    state_update.in_accounts.insert(account.hash.clone());
+    state_update.transactions.insert(Transaction {
+        signature,
+        slot: 6,
+        uses_compression: true,
+        error: None,
+        compressed_lamports: 0,
+        decompressed_lamports: 0,
+    });
+    state_update
+        .account_transactions
+        .insert(AccountTransaction {
+            hash: account.hash.clone(),
+            signature,
+            slot: 6,
+            spent: true,
+        });
+    let events = PendingAccountEvents::collect(&state_update, &[&block(6), &block(7)]).unwrap();
     persist_state_update_using_connection(&setup.db_conn, state_update)
         .await
         .unwrap();
+    events
+        .publish(&setup.db_conn, setup.indexer.sql_batch_config)
+        .await;
+
+    let created = notifications.next().await.unwrap();
+    assert_eq!(created.context.slot, 5);
+    assert_eq!(
+        created.value.event_type,
+        CompressedAccountEventType::Created
+    );
+    assert_eq!(created.value.account, account);
+    assert_eq!(created.value.block_time, UnixTimestamp(1_700_000_005));
+
+    let spent = notifications.next().await.unwrap();
+    assert_eq!(spent.context.slot, 6);
+    assert_eq!(spent.value.event_type, CompressedAccountEventType::Spent);
+    assert_eq!(spent.value.account.hash, account.hash);
+    assert_eq!(spent.value.block_time, UnixTimestamp(1_700_000_006));
+}
 
-    for owner in [owner1, owner2] {
-        let res = setup
-            .api
-            .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
-                owner,
-                ..Default::default()
-            })
-            .await
-            .unwrap()
-            .value;
-
-        let mut response_accounts = res.items;
-
-        let mut paginated_response_accounts = Vec::new();
-        let mut cursor = None;
-        loop {
-            let res = setup
-                .api
-                .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
-                    owner,
-                    cursor: cursor.clone(),
-                    limit: Some(Limit::new(1).unwrap()),
-                    ..Default::default()
-                })
-                .await
-                .unwrap()
-                .value;
-
-            paginated_response_accounts.extend(res.items.clone());
-            cursor = res.cursor;
-            if cursor.is_none() {
-                break;
-            }
-        }
-        assert_eq!(response_accounts, paginated_response_accounts);
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_indexer_slot_subscribe(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
 
-        let mut accounts_of_interest = accounts
-            .clone()
-            .into_iter()
-            .filter(|x| x.owner == owner)
-            .collect::<Vec<Account>>();
+    let block = |slot: u64| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut slots = Box::pin(indexer_slot_subscribe());
+    // Slot subscribers do not make the ingester collect account events.
+    assert!(PendingAccountEvents::collect(&StateUpdate::new(), &[]).is_none());
 
-        assert_account_response_list_matches_input(
-            &mut response_accounts,
-            &mut accounts_of_interest,
-        );
+    for slot in [1, 2] {
+        index_block(&setup.indexer, &block(slot)).await.unwrap();
+    }
+    // Batches publish the slot of each of their blocks.
+    index_block_batch(&setup.indexer, &[block(3), block(4)])
+        .await
+        .unwrap();
 
-        let total_balance = accounts_of_interest
-            .iter()
-            .fold(0, |acc, x| acc + x.lamports.0);
+    for slot in 1..=4 {
+        assert_eq!(slots.next().await, Some(UnsignedInteger(slot)));
+    }
+}
 
-        let res = setup
-            .api
-            .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest { owner })
-            .await
-            .unwrap()
-            .value;
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_readiness_after_warm_up(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let mut setup = setup(name, db_backend).await;
 
-        assert_eq!(res.0, total_balance);
-    }
+    set_proof_cache_capacity(100);
+    setup.api = setup.api.with_in_memory_trees(InMemoryTrees::new(1000));
 
-    let mut accounts_of_interest = vec![accounts[0].clone(), accounts[2].clone()];
-    let res = setup
-        .api
-        .get_multiple_compressed_accounts(GetMultipleCompressedAccountsRequest {
-            addresses: None,
-            hashes: Some(
-                accounts_of_interest
-                    .iter()
-                    .map(|x| x.hash.clone())
-                    .collect(),
-            ),
+    let tree = SerializablePubkey::new_unique();
+    let leaf_nodes = (0..2)
+        .map(|i| LeafNode {
+            hash: Hash::new_unique(),
+            leaf_index: i,
+            tree,
+            seq: i,
         })
+        .collect::<Vec<_>>();
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(&txn, setup.indexer.sql_batch_config, leaf_nodes.clone(), 5)
         .await
-        .unwrap()
-        .value;
+        .unwrap();
+    txn.commit().await.unwrap();
 
-    assert_account_response_list_matches_input(
-        &mut res.items.iter().map(|x| x.clone().unwrap()).collect(),
-        &mut accounts_of_interest,
-    );
+    assert_eq!(setup.api.readiness().await, Err(PhotonApiError::WarmingUp));
+    setup.api.start_warm_up().await.unwrap();
+    assert_eq!(setup.api.readiness().await, Ok(()));
+
+    // The proof of the latest leaf of each tree is cached against the current root.
+    assert!(get_cached_proof(tree, 1, &leaf_nodes[1].hash).is_some());
+    set_proof_cache_capacity(0);
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_persist_token_data(
+async fn test_backfill_from_bigtable(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
-    use photon_indexer::api::method::get_compressed_mint_token_holders::GetCompressedMintTokenHoldersRequest;
-
     let name = trim_test_name(function_name!());
     let setup = setup(name, db_backend).await;
-    let mint1 = SerializablePubkey::new_unique();
-    let mint2 = SerializablePubkey::new_unique();
-    let mint3 = SerializablePubkey::new_unique();
-    let owner1 = SerializablePubkey::new_unique();
-    let owner2 = SerializablePubkey::new_unique();
-    let owner3 = SerializablePubkey::new_unique();
-    let owner4 = SerializablePubkey::new_unique();
-    let delegate1 = SerializablePubkey::new_unique();
-    let delegate2 = SerializablePubkey::new_unique();
 
-    // HACK: We index a block so that API methods can fetch the current slot.
-    index_block(
-        &setup.db_conn,
-        &BlockInfo {
-            metadata: BlockMetadata {
-                slot: 0,
-                ..Default::default()
+    let slot = 1234;
+    let signature = Signature::new_unique();
+    let block = ConfirmedBlock {
+        previous_blockhash: Hash::new_unique().to_string(),
+        blockhash: Hash::new_unique().to_string(),
+        parent_slot: slot - 1,
+        transactions: vec![ConfirmedTransaction {
+            transaction: Some(confirmed_block::Transaction {
+                signatures: vec![signature.as_ref().to_vec()],
+                message: Some(confirmed_block::Message {
+                    account_keys: vec![
+                        Pubkey::new_unique().to_bytes().to_vec(),
+                        Pubkey::new_unique().to_bytes().to_vec(),
+                    ],
+                    instructions: vec![confirmed_block::CompiledInstruction {
+                        program_id_index: 1,
+                        accounts: vec![0],
+                        data: vec![1, 2, 3],
+                    }],
+                    ..Default::default()
+                }),
+            }),
+            meta: Some(confirmed_block::TransactionStatusMeta::default()),
+        }],
+        block_time: Some(confirmed_block::UnixTimestamp { timestamp: 100 }),
+        block_height: Some(confirmed_block::BlockHeight { block_height: 1000 }),
+        ..Default::default()
+    };
+    // Cells are prefixed with the compression method, zstd in this case.
+    let mut cell = 3u32.to_le_bytes().to_vec();
+    cell.extend(zstd::encode_all(block.encode_to_vec().as_slice(), 0).unwrap());
+    let (first_part, second_part) = cell.split_at(cell.len() / 2);
+    let encode =
+        |bytes: &[u8]| base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes);
+    let read_rows_response = serde_json::json!([{
+        "chunks": [
+            {
+                "rowKey": encode(format!("{:016x}", slot).as_bytes()),
+                "familyName": "x",
+                "qualifier": encode(b"proto"),
+                "timestampMicros": "1",
+                "value": encode(first_part),
+                "valueSize": cell.len(),
             },
-            ..Default::default()
-        },
+            { "value": encode(second_part), "commitRow": true },
+        ]
+    }]);
+    let url = serve_files(HashMap::from([
+        (
+            "/projects/project/instances/ledger/tables/blocks:readRows".to_string(),
+            read_rows_response.to_string().into_bytes(),
+        ),
+        (
+            "/projects/project/instances/empty/tables/blocks:readRows".to_string(),
+            b"[]".to_vec(),
+        ),
+    ]))
+    .await;
+
+    let empty_fetcher =
+        BigtableBlockFetcher::new(&url, "project", "empty", Some("token".to_string()));
+    assert_eq!(empty_fetcher.fetch_block(slot).await.unwrap(), None);
+
+    let fetcher = BigtableBlockFetcher::new(&url, "project", "ledger", Some("token".to_string()));
+    let fetched_block = fetcher.fetch_block(slot).await.unwrap().unwrap();
+    assert_eq!(fetched_block.metadata.slot, slot);
+    assert_eq!(fetched_block.metadata.parent_slot, slot - 1);
+    assert_eq!(fetched_block.metadata.block_height, 1000);
+    assert_eq!(fetched_block.transactions.len(), 1);
+    assert_eq!(fetched_block.transactions[0].signature, signature);
+
+    backfill_slot_range(
+        &setup.indexer,
+        BackfillBlockSource::Bigtable(Arc::new(fetcher)),
+        slot,
+        slot,
+        1,
     )
     .await
     .unwrap();
+    let indexed_block = blocks::Entity::find()
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(indexed_block.slot, slot as i64);
+    assert_eq!(
+        indexed_block.blockhash,
+        Hash::try_from(block.blockhash.as_str()).unwrap().to_vec()
+    );
+}
 
-    let token_data1 = TokenData {
-        mint: mint1,
-        owner: owner1,
-        amount: UnsignedInteger(1),
-        delegate: Some(delegate1),
-        state: AccountState::frozen,
-        tlv: None,
-    };
-
-    let token_data2 = TokenData {
-        mint: mint2,
-        owner: owner1,
-        amount: UnsignedInteger(2),
-        delegate: Some(delegate2),
-        state: AccountState::initialized,
-        tlv: None,
-    };
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_backfill_pruned_slots(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
 
-    let token_data3 = TokenData {
-        mint: mint3,
-        owner: owner2,
-        amount: UnsignedInteger(3),
-        delegate: Some(delegate1),
-        state: AccountState::frozen,
-        tlv: None,
-    };
-    let token_data4 = TokenData {
-        mint: mint1,
-        owner: owner2,
-        amount: UnsignedInteger(4),
-        delegate: Some(delegate1),
-        state: AccountState::frozen,
-        tlv: None,
-    };
-    let token_data5 = TokenData {
-        mint: mint1,
-        owner: owner3,
-        amount: UnsignedInteger(4),
-        delegate: Some(delegate1),
-        state: AccountState::frozen,
-        tlv: None,
-    };
-    let token_data6 = TokenData {
-        mint: mint1,
-        owner: owner4,
-        amount: UnsignedInteger(6),
-        delegate: Some(delegate1),
-        state: AccountState::frozen,
-        tlv: None,
-    };
-    let token_data7 = TokenData {
-        mint: mint1,
-        owner: owner2,
-        amount: UnsignedInteger(4),
-        delegate: Some(delegate1),
-        state: AccountState::frozen,
-        tlv: None,
+    let slot = 5;
+    let version_response = serde_json::json!({"result": {"solana-core": "1.18.22"}});
+    let pruned_response = serde_json::json!({
+        "error": {
+            "code": -32001,
+            "message": "Block 5 cleaned up, does not exist on node. First available block: 10",
+        },
+    });
+    let block_response = serde_json::json!({
+        "result": {
+            "blockhash": Hash::new_unique().to_string(),
+            "previousBlockhash": Hash::new_unique().to_string(),
+            "parentSlot": slot - 1,
+            "transactions": [],
+            "blockTime": 100,
+            "blockHeight": 1000,
+        },
+    });
+    let pruned_rpc_url = serve_rpc(HashMap::from([
+        ("getVersion", version_response.clone()),
+        ("getBlock", pruned_response),
+    ]))
+    .await;
+    let archival_rpc_url = serve_rpc(HashMap::from([
+        ("getVersion", version_response),
+        ("getBlock", block_response),
+    ]))
+    .await;
+    let rpc_client = |url: &str| Arc::new(RpcClient::new(url.to_string()));
+
+    let error = backfill_slot_range(
+        &setup.indexer,
+        BackfillBlockSource::Rpc {
+            rpc_client: rpc_client(&pruned_rpc_url),
+            archival_rpc_client: None,
+        },
+        slot,
+        slot,
+        1,
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        error,
+        IngesterError::SlotUnavailable { slot: 5, .. }
+    ));
+    assert!(fetch_slots_to_backfill(&setup.db_conn, slot, slot)
+        .await
+        .unwrap()
+        .contains(&slot));
+
+    backfill_slot_range(
+        &setup.indexer,
+        BackfillBlockSource::Rpc {
+            rpc_client: rpc_client(&pruned_rpc_url),
+            archival_rpc_client: Some(rpc_client(&archival_rpc_url)),
+        },
+        slot,
+        slot,
+        1,
+    )
+    .await
+    .unwrap();
+    assert!(fetch_slots_to_backfill(&setup.db_conn, slot, slot)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_rate_limited_rpc_client() {
+    // Fails the first two getSlot calls of each client.
+    let get_slot_calls = Arc::new(AtomicUsize::new(0));
+    let calls = get_slot_calls.clone();
+    let url = serve(move |_, body| {
+        let request: serde_json::Value = serde_json::from_slice(body).unwrap();
+        match request["method"].as_str().unwrap() {
+            "getVersion" => (
+                200,
+                rpc_response(
+                    &request,
+                    serde_json::json!({"result": {"solana-core": "1.18.22"}}),
+                ),
+            ),
+            "getSlot" if calls.fetch_add(1, Ordering::SeqCst) % 3 < 2 => (503, Vec::new()),
+            "getSlot" => (
+                200,
+                rpc_response(&request, serde_json::json!({"result": 42})),
+            ),
+            _ => (404, Vec::new()),
+        }
+    })
+    .await;
+    let config = |max_retries| RpcRateLimitConfig {
+        max_retries,
+        initial_backoff: Duration::from_millis(10),
+        ..Default::default()
     };
-    let all_token_data = vec![
-        token_data1,
-        token_data2,
-        token_data3,
-        token_data4,
-        token_data5,
-        token_data6,
-        token_data7,
-    ];
-    let hashes = all_token_data
-        .iter()
-        .map(|_| Hash::new_unique())
-        .collect::<HashSet<Hash>>();
 
-    let all_token_data: Vec<TokenDataWithHash> = all_token_data
-        .iter()
-        .zip(hashes.iter())
-        .map(|(token_data, hash)| TokenDataWithHash {
-            token_data: token_data.clone(),
-            hash: hash.clone(),
-        })
-        .collect();
+    let client = get_rate_limited_rpc_client(&url, config(3));
+    assert_eq!(client.get_slot().await.unwrap(), 42);
+    assert_eq!(get_slot_calls.load(Ordering::SeqCst), 3);
 
-    let mut mint_to_owner_to_balance = HashMap::new();
-    for token_data in all_token_data.clone() {
-        let token_data = token_data.token_data;
-        let mint = token_data.mint;
-        let owner = token_data.owner;
-        let mint_owner_balances = mint_to_owner_to_balance
-            .entry(mint)
-            .or_insert(HashMap::new());
-        let balance = mint_owner_balances.entry(owner).or_insert(0);
-        *balance += token_data.amount.0;
+    let client = get_rate_limited_rpc_client(&url, config(1));
+    assert!(client.get_slot().await.is_err());
+    assert_eq!(get_slot_calls.load(Ordering::SeqCst), 5);
+
+    let client = get_rate_limited_rpc_client(
+        &url,
+        RpcRateLimitConfig {
+            requests_per_second: Some(20),
+            ..Default::default()
+        },
+    );
+    let start = std::time::Instant::now();
+    for _ in 0..5 {
+        client.get_version().await.unwrap();
     }
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
 
-    let txn = sea_orm::TransactionTrait::begin(setup.db_conn.as_ref())
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_fetch_slots_to_backfill(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    for slot in [2, 3, 5] {
+        index_block(
+            &setup.indexer,
+            &BlockInfo {
+                metadata: BlockMetadata {
+                    slot,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
         .await
         .unwrap();
+    }
 
-    let mut token_datas = Vec::new();
+    let slots = fetch_slots_to_backfill(&setup.db_conn, 1, 6).await.unwrap();
+    assert_eq!(slots, vec![1, 4, 6]);
+    let slots = fetch_slots_to_backfill(&setup.db_conn, 2, 3).await.unwrap();
+    assert!(slots.is_empty());
+}
 
-    for (i, token_data) in all_token_data.iter().enumerate() {
-        let slot = 11;
-        let hash = token_data.hash.clone();
-        let token_data = token_data.token_data.clone();
-        let model = accounts::ActiveModel {
-            hash: Set(hash.clone().into()),
-            address: Set(Some(Pubkey::new_unique().to_bytes().to_vec())),
-            spent: Set(false),
-            data: Set(Some(to_vec(&token_data).unwrap())),
-            owner: Set(token_data.owner.to_bytes_vec()),
-            lamports: Set(Decimal::from(10)),
-            slot_created: Set(slot),
-            leaf_index: Set(i as i64),
-            discriminator: Set(Some(Decimal::from(1))),
-            data_hash: Set(Some(Hash::new_unique().to_vec())),
-            tree: Set(Pubkey::new_unique().to_bytes().to_vec()),
-            seq: Set(0),
-            ..Default::default()
-        };
-        accounts::Entity::insert(model).exec(&txn).await.unwrap();
-        token_datas.push(EnrichedTokenAccount {
-            hash,
-            token_data: token_data.clone(),
-        });
+fn compression_event_transaction(event_data: Vec<u8>) -> TransactionInfo {
+    let instruction = |program_id: Pubkey, data: Vec<u8>| Instruction {
+        program_id,
+        data,
+        accounts: vec![],
+    };
+    TransactionInfo {
+        instruction_groups: vec![InstructionGroup {
+            outer_instruction: instruction(ACCOUNT_COMPRESSION_PROGRAM_ID, vec![]),
+            inner_instructions: vec![
+                instruction(solana_sdk::system_program::ID, vec![]),
+                instruction(
+                    Pubkey::from_str("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV").unwrap(),
+                    event_data,
+                ),
+            ],
+        }],
+        signature: Signature::new_unique(),
+        error: None,
     }
+}
 
-    persist_token_accounts(&txn, token_datas).await.unwrap();
-    txn.commit().await.unwrap();
+fn merkle_tree_event_transaction(event: MerkleTreeEvent) -> TransactionInfo {
+    TransactionInfo {
+        instruction_groups: vec![InstructionGroup {
+            outer_instruction: Instruction {
+                program_id: ACCOUNT_COMPRESSION_PROGRAM_ID,
+                data: vec![],
+                accounts: vec![],
+            },
+            inner_instructions: vec![Instruction {
+                program_id: Pubkey::from_str("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV")
+                    .unwrap(),
+                data: to_vec(&event).unwrap(),
+                accounts: vec![],
+            }],
+        }],
+        signature: Signature::new_unique(),
+        error: None,
+    }
+}
 
-    let owner_tlv = all_token_data
-        .iter()
-        .filter(|x| x.token_data.owner == owner1 && x.token_data.mint == mint1)
-        .map(Clone::clone)
-        .collect();
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_malformed_transactions_fail_their_block(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
 
-    let res = setup
-        .api
-        .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
-            owner: owner1,
-            mint: Some(mint1),
+    let tree = Pubkey::new_unique();
+    let output_account = OutputCompressedAccountWithPackedContext {
+        compressed_account: CompressedAccount {
+            owner: Pubkey::new_unique(),
+            lamports: 1000,
+            address: None,
+            data: None,
+        },
+        merkle_tree_index: 0,
+    };
+    let valid_event = PublicTransactionEvent {
+        output_compressed_account_hashes: vec![Hash::new_unique().0],
+        output_compressed_accounts: vec![output_account.clone()],
+        output_leaf_indices: vec![0],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: 0,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let out_of_bounds_tree_event = PublicTransactionEvent {
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            merkle_tree_index: 3,
+            ..output_account
+        }],
+        pubkey_array: vec![],
+        ..valid_event.clone()
+    };
+    let block = |slot: u64, transactions: Vec<TransactionInfo>| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
             ..Default::default()
-        })
+        },
+        transactions,
+    };
+    let blocks = vec![
+        block(
+            1,
+            vec![
+                compression_event_transaction(vec![1, 2, 3]),
+                compression_event_transaction(to_vec(&valid_event).unwrap()),
+            ],
+        ),
+        block(
+            2,
+            vec![compression_event_transaction(
+                to_vec(&out_of_bounds_tree_event).unwrap(),
+            )],
+        ),
+        block(3, vec![]),
+    ];
+    let e = index_block(&setup.indexer, &blocks[0]).await.unwrap_err();
+    assert!(!e.retryable());
+
+    // Skipping the malformed transactions would diverge the indexed state from the chain, so
+    // their blocks are moved to the dead-letter queue without applying any of their transactions.
+    index_block_batch_with_infinite_retries(&setup.indexer, blocks).await;
+    let failed_slots = fetch_failed_blocks(&setup.db_conn)
         .await
         .unwrap()
-        .value;
-    verify_response_matches_input_token_data(res.clone(), owner_tlv);
+        .into_iter()
+        .map(|failed_block| failed_block.slot)
+        .collect::<Vec<_>>();
+    assert_eq!(failed_slots, vec![1, 2]);
+    assert!(transactions::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(accounts::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .is_empty());
+}
 
-    for owner in [owner2] {
-        let owner_tlv = all_token_data
-            .iter()
-            .filter(|x| x.token_data.owner == owner)
-            .map(Clone::clone)
-            .collect();
-        let res = setup
-            .api
-            .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
-                owner,
-                ..Default::default()
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_versioned_merkle_tree_events(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // Path from the leaf to the root of a tree of height 27. Only the leaf is persisted.
+    let path = |leaf_index: u32, hash: &Hash| {
+        (0..27)
+            .map(|level| PathNode {
+                node: if level == 0 { hash.0 } else { [0; 32] },
+                index: (2_u32.pow(26) + leaf_index) >> level,
             })
-            .await
-            .unwrap()
-            .value;
+            .collect::<Vec<_>>()
+    };
+    let first_tree = Pubkey::new_unique();
+    let second_tree = Pubkey::new_unique();
+    let hashes = (0..3).map(|_| Hash::new_unique()).collect::<Vec<_>>();
 
-        let mut paginated_res = Vec::new();
-        let mut cursor = None;
-        loop {
-            let res = setup
-                .api
-                .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
-                    owner,
-                    cursor: cursor.clone(),
-                    limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
-                    ..Default::default()
-                })
-                .await
-                .unwrap()
-                .value;
+    let v1_event = MerkleTreeEvent::V1(ChangelogEvent {
+        id: first_tree.to_bytes(),
+        paths: vec![path(0, &hashes[0])],
+        seq: 0,
+        index: 0,
+    });
+    let batched_event = MerkleTreeEvent::V4(BatchedMerkleTreeEvent {
+        changelogs: vec![
+            ChangelogEvent {
+                id: first_tree.to_bytes(),
+                paths: vec![path(1, &hashes[1])],
+                seq: 1,
+                index: 0,
+            },
+            ChangelogEvent {
+                id: second_tree.to_bytes(),
+                paths: vec![path(0, &hashes[2])],
+                seq: 0,
+                index: 0,
+            },
+        ],
+        nullifications: vec![NullifierEvent {
+            id: first_tree.to_bytes(),
+            nullified_leaves_indices: vec![0],
+            seq: 2,
+        }],
+    });
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![
+            merkle_tree_event_transaction(v1_event),
+            merkle_tree_event_transaction(batched_event),
+        ],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
 
-            paginated_res.extend(res.items.clone());
-            cursor = res.cursor;
+    let leaves = state_trees::Entity::find()
+        .filter(state_trees::Column::Level.eq(0))
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|leaf| {
+            (
+                (Pubkey::try_from(leaf.tree).unwrap(), leaf.leaf_idx.unwrap()),
+                (leaf.hash, leaf.seq),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let expected_leaves = HashMap::from([
+        ((first_tree, 0), (vec![0; 32], 2)),
+        ((first_tree, 1), (hashes[1].to_vec(), 1)),
+        ((second_tree, 0), (hashes[2].to_vec(), 0)),
+    ]);
+    assert_eq!(leaves, expected_leaves);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_nullifier_queue(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::dao::generated::nullifier_queue_elements;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let tree = SerializablePubkey::new_unique();
+    let accounts = (0..2)
+        .map(|i| Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner: SerializablePubkey::new_unique(),
+            lamports: U64String(1000),
+            tree,
+            leaf_index: UnsignedInteger(i),
+            seq: UnsignedInteger(i),
+            slot_created: UnsignedInteger(0),
+        })
+        .collect::<Vec<_>>();
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    let queued_leaves = || async {
+        nullifier_queue_elements::Entity::find()
+            .all(setup.db_conn.as_ref())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|element| (Hash::try_from(element.hash).unwrap(), element.leaf_index))
+            .collect::<HashSet<_>>()
+    };
+    assert!(queued_leaves().await.is_empty());
+
+    let mut state_update = StateUpdate::new();
+    state_update.in_accounts = accounts
+        .iter()
+        .map(|account| account.hash.clone())
+        .collect();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    assert_eq!(
+        queued_leaves().await,
+        HashSet::from([(accounts[0].hash.clone(), 0), (accounts[1].hash.clone(), 1)])
+    );
+
+    // Batched trees report nullified leaves by index.
+    let nullifier_event = MerkleTreeEvent::V4(BatchedMerkleTreeEvent {
+        changelogs: vec![],
+        nullifications: vec![NullifierEvent {
+            id: tree.0.to_bytes(),
+            nullified_leaves_indices: vec![0],
+            seq: 2,
+        }],
+    });
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![merkle_tree_event_transaction(nullifier_event)],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+    assert_eq!(
+        queued_leaves().await,
+        HashSet::from([(accounts[1].hash.clone(), 1)])
+    );
+
+    // V1 trees report the path of the leaf after it was set to zero.
+    let changelog_event = MerkleTreeEvent::V1(ChangelogEvent {
+        id: tree.0.to_bytes(),
+        paths: vec![(0..27)
+            .map(|level| PathNode {
+                node: [0; 32],
+                index: (2_u32.pow(26) + 1) >> level,
+            })
+            .collect()],
+        seq: 3,
+        index: 1,
+    });
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 2,
+            ..Default::default()
+        },
+        transactions: vec![merkle_tree_event_transaction(changelog_event)],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+    assert!(queued_leaves().await.is_empty());
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_index_block_skips_already_indexed_transactions(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let event = |leaf_index: u32| PublicTransactionEvent {
+        output_compressed_account_hashes: vec![Hash::new_unique().0],
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner,
+                lamports: 1000,
+                address: None,
+                data: None,
+            },
+            merkle_tree_index: 0,
+        }],
+        output_leaf_indices: vec![leaf_index],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: leaf_index as u64,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let block = |slot: u64, transaction: TransactionInfo| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: vec![transaction],
+    };
+    let first_event = event(0);
+    let transaction = compression_event_transaction(to_vec(&first_event).unwrap());
+    // A replay of the same transaction that would create a different account if it was applied.
+    let replayed_transaction = TransactionInfo {
+        signature: transaction.signature,
+        ..compression_event_transaction(to_vec(&event(1)).unwrap())
+    };
+
+    // Duplicates within a batch are only applied once.
+    index_block_batch(
+        &setup.indexer,
+        &[
+            block(1, transaction),
+            block(2, replayed_transaction.clone()),
+        ],
+    )
+    .await
+    .unwrap();
+    // Replays of persisted transactions are skipped, e.g. after a crash or in overlapping
+    // backfills.
+    index_block(&setup.indexer, &block(3, replayed_transaction))
+        .await
+        .unwrap();
+
+    let indexed_accounts = accounts::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(indexed_accounts.len(), 1);
+    assert_eq!(
+        indexed_accounts[0].hash,
+        first_event.output_compressed_account_hashes[0].to_vec()
+    );
+    let balance = setup
+        .api
+        .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest {
+            owner: SerializablePubkey::from(owner),
+        })
+        .await
+        .unwrap();
+    assert_eq!(balance.value, U64String(1000));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_index_block_batch_isolates_non_retryable_errors(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    // Token accounts whose data cannot be parsed fail persisting the block with a non-retryable
+    // error.
+    let invalid_token_account_event = PublicTransactionEvent {
+        output_compressed_account_hashes: vec![Hash::new_unique().0],
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner: Pubkey::from_str("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m").unwrap(),
+                lamports: 0,
+                address: None,
+                data: Some(CompressedAccountData {
+                    discriminator: [0; 8],
+                    data: vec![1, 2, 3],
+                    data_hash: [0; 32],
+                }),
+            },
+            merkle_tree_index: 0,
+        }],
+        output_leaf_indices: vec![0],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: 0,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let block = |slot: u64, transactions: Vec<TransactionInfo>| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions,
+    };
+    let blocks = vec![
+        block(1, vec![]),
+        block(
+            2,
+            vec![compression_event_transaction(
+                to_vec(&invalid_token_account_event).unwrap(),
+            )],
+        ),
+        block(3, vec![]),
+    ];
+
+    // The failing block is moved to the dead-letter queue instead of stalling indexing, and the
+    // blocks around it are committed.
+    index_block_batch_with_infinite_retries(&setup.indexer, blocks).await;
+    let indexed_slots = blocks::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|block| block.slot)
+        .collect::<Vec<_>>();
+    assert_eq!(indexed_slots, vec![1, 2, 3]);
+    let failed_blocks = fetch_failed_blocks(&setup.db_conn).await.unwrap();
+    assert_eq!(failed_blocks.len(), 1);
+    assert_eq!(failed_blocks[0].slot, 2);
+    assert!(failed_blocks[0]
+        .error
+        .contains("Failed to parse token data"));
+    assert!(accounts::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_index_block_stream(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let event = |leaf_index: u32| PublicTransactionEvent {
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner: Pubkey::new_unique(),
+                lamports: 1000,
+                address: None,
+                data: None,
+            },
+            merkle_tree_index: 0,
+        }],
+        output_compressed_account_hashes: vec![Hash::new_unique().0],
+        output_leaf_indices: vec![leaf_index],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: leaf_index as u64,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let block = |slot: u64| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: vec![compression_event_transaction(
+            to_vec(&event(slot as u32 - 1)).unwrap(),
+        )],
+    };
+    // More batches than fit into the queues between the stages of the pipeline.
+    let batches = (0..10)
+        .map(|batch| (1..=3).map(|i| block(batch * 3 + i)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    index_block_stream(
+        futures::stream::iter(batches),
+        setup.indexer.clone(),
+        setup.client.clone(),
+        0,
+        Some(30),
+    )
+    .await;
+
+    let indexed_slots = blocks::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|block| block.slot)
+        .collect::<Vec<_>>();
+    assert_eq!(indexed_slots, (1..=30).collect::<Vec<_>>());
+    let accounts = accounts::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(accounts.len(), 30);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_index_block_batch_with_several_trees(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // Every event appends two accounts to a tree, so each tree ends up with four leaves.
+    let trees = (0..6).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+    let mut account_hashes = HashMap::new();
+    let mut event = |tree: Pubkey, first_leaf_index: u32| {
+        let hashes = [Hash::new_unique(), Hash::new_unique()];
+        for hash in &hashes {
+            account_hashes.insert(hash.clone(), tree);
+        }
+        let output_account = OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner: Pubkey::new_unique(),
+                lamports: 1000,
+                address: None,
+                data: None,
+            },
+            merkle_tree_index: 0,
+        };
+        PublicTransactionEvent {
+            output_compressed_accounts: vec![output_account.clone(), output_account],
+            output_compressed_account_hashes: hashes.iter().map(|hash| hash.0).collect(),
+            output_leaf_indices: vec![first_leaf_index, first_leaf_index + 1],
+            sequence_numbers: vec![MerkleTreeSequenceNumber {
+                pubkey: tree,
+                seq: first_leaf_index as u64,
+            }],
+            pubkey_array: vec![tree],
+            ..Default::default()
+        }
+    };
+    let blocks = [0, 2]
+        .into_iter()
+        .enumerate()
+        .map(|(i, first_leaf_index)| BlockInfo {
+            metadata: BlockMetadata {
+                slot: i as u64 + 1,
+                parent_slot: i as u64,
+                ..Default::default()
+            },
+            transactions: trees
+                .iter()
+                .map(|tree| {
+                    compression_event_transaction(to_vec(&event(*tree, first_leaf_index)).unwrap())
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    // The trees of a batch are written in its transaction, so they roll back together with it.
+    let mut state_update = StateUpdate::merge_updates(
+        blocks
+            .iter()
+            .flat_map(|block| {
+                block
+                    .transactions
+                    .iter()
+                    .map(|tx| parse_transaction(tx, block.metadata.slot).unwrap())
+            })
+            .collect(),
+    );
+    // The blocks aren't indexed, so transactions can't reference them.
+    state_update.transactions.clear();
+    state_update.account_transactions.clear();
+    let txn = setup.db_conn.begin().await.unwrap();
+    persist_state_update(&txn, setup.indexer.sql_batch_config, state_update)
+        .await
+        .unwrap();
+    txn.rollback().await.unwrap();
+    assert_eq!(
+        state_trees::Entity::find()
+            .count(setup.db_conn.as_ref())
+            .await
+            .unwrap(),
+        0
+    );
+
+    index_block_batch(&setup.indexer, &blocks).await.unwrap();
+
+    let proofs = get_multiple_compressed_leaf_proofs(
+        &setup.db_conn.begin().await.unwrap(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        account_hashes.keys().cloned().collect(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(proofs.len(), account_hashes.len());
+    for proof in proofs {
+        assert_eq!(proof.merkleTree, account_hashes[&proof.hash].into());
+        assert_eq!(proof.rootSeq, 3);
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_repeated_spend_keeps_prev_spent(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner = SerializablePubkey::new_unique();
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: None,
+        data: None,
+        owner,
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(0),
+    };
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts.push(account.clone());
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    // The second spend stands for a replay of the spending transaction, which must not change the
+    // state that rolling back the spend restores.
+    for _ in 0..2 {
+        let mut state_update = StateUpdate::new();
+        state_update.in_accounts.insert(account.hash.clone());
+        persist_state_update_using_connection(&setup.db_conn, state_update)
+            .await
+            .unwrap();
+    }
+
+    let model = accounts::Entity::find_by_id(account.hash.to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(model.spent);
+    assert_eq!(model.prev_spent, Some(false));
+    let balance = setup
+        .api
+        .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest { owner })
+        .await
+        .unwrap();
+    assert_eq!(balance.value, U64String(0));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_account_transaction_signatures(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let token_data = TokenData {
+        mint: SerializablePubkey::new_unique(),
+        owner: SerializablePubkey::new_unique(),
+        amount: U64String(100),
+        delegate: None,
+        state: AccountState::initialized,
+        tlv: None,
+        extensions: None,
+    };
+    let hash = Hash::new_unique();
+    let create_transaction = compression_event_transaction(
+        to_vec(&PublicTransactionEvent {
+            output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                compressed_account: CompressedAccount {
+                    owner: Pubkey::from_str("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m").unwrap(),
+                    lamports: 0,
+                    address: None,
+                    data: Some(CompressedAccountData {
+                        discriminator: [2, 0, 0, 0, 0, 0, 0, 0],
+                        data: to_vec(&token_data).unwrap(),
+                        data_hash: [0; 32],
+                    }),
+                },
+                merkle_tree_index: 0,
+            }],
+            output_compressed_account_hashes: vec![hash.0],
+            output_leaf_indices: vec![0],
+            sequence_numbers: vec![MerkleTreeSequenceNumber {
+                pubkey: tree,
+                seq: 0,
+            }],
+            pubkey_array: vec![tree],
+            ..Default::default()
+        })
+        .unwrap(),
+    );
+    let spend_transaction = compression_event_transaction(
+        to_vec(&PublicTransactionEvent {
+            input_compressed_account_hashes: vec![hash.0],
+            ..Default::default()
+        })
+        .unwrap(),
+    );
+    // The account is created and spent in the same batch, so both of its transactions are part
+    // of the same state update.
+    let blocks = [create_transaction.clone(), spend_transaction.clone()]
+        .into_iter()
+        .enumerate()
+        .map(|(i, transaction)| BlockInfo {
+            metadata: BlockMetadata {
+                slot: i as u64 + 1,
+                parent_slot: i as u64,
+                ..Default::default()
+            },
+            transactions: vec![transaction],
+        })
+        .collect::<Vec<_>>();
+    index_block_batch(&setup.indexer, &blocks).await.unwrap();
+
+    let signature_bytes = |signature: Signature| Some(Into::<[u8; 64]>::into(signature).to_vec());
+    let account = accounts::Entity::find_by_id(hash.to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        account.tx_signature,
+        signature_bytes(create_transaction.signature)
+    );
+    assert_eq!(
+        account.spent_tx_signature,
+        signature_bytes(spend_transaction.signature)
+    );
+    assert_eq!((account.slot_created, account.slot_spent), (1, Some(2)));
+    let token_account = token_accounts::Entity::find_by_id(hash.to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        token_account.tx_signature,
+        signature_bytes(create_transaction.signature)
+    );
+    assert_eq!(
+        token_account.spent_tx_signature,
+        signature_bytes(spend_transaction.signature)
+    );
+    assert_eq!(token_account.slot_spent, Some(2));
+    let account_transactions = || async {
+        account_transactions::Entity::find()
+            .filter(account_transactions::Column::Hash.eq(hash.to_vec()))
+            .order_by_asc(account_transactions::Column::Slot)
+            .all(setup.db_conn.as_ref())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|model| (Some(model.signature), model.slot, model.spent))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        account_transactions().await,
+        vec![
+            (signature_bytes(create_transaction.signature), 1, false),
+            (signature_bytes(spend_transaction.signature), 2, true),
+        ]
+    );
+
+    // Rolling back the spend forgets its signature and slot.
+    let txn = setup.db_conn.begin().await.unwrap();
+    rollback_to_slot(&txn, setup.indexer.sql_batch_config, 1)
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+    let account = accounts::Entity::find_by_id(hash.to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!account.spent);
+    assert_eq!(account.spent_tx_signature, None);
+    assert_eq!(account.slot_spent, None);
+    assert_eq!(
+        account_transactions().await,
+        vec![(signature_bytes(create_transaction.signature), 1, false)]
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_index_blocks_out_of_order(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let token_data = TokenData {
+        mint: SerializablePubkey::new_unique(),
+        owner: SerializablePubkey::new_unique(),
+        amount: U64String(100),
+        delegate: None,
+        state: AccountState::initialized,
+        tlv: None,
+        extensions: None,
+    };
+    let hash = Hash::new_unique();
+    let create_transaction = compression_event_transaction(
+        to_vec(&PublicTransactionEvent {
+            output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                compressed_account: CompressedAccount {
+                    owner: COMPRESSED_TOKEN_PROGRAM,
+                    lamports: 1000,
+                    address: None,
+                    data: Some(CompressedAccountData {
+                        discriminator: [2, 0, 0, 0, 0, 0, 0, 0],
+                        data: to_vec(&token_data).unwrap(),
+                        data_hash: [0; 32],
+                    }),
+                },
+                merkle_tree_index: 0,
+            }],
+            output_compressed_account_hashes: vec![hash.0],
+            output_leaf_indices: vec![0],
+            sequence_numbers: vec![MerkleTreeSequenceNumber {
+                pubkey: tree,
+                seq: 0,
+            }],
+            pubkey_array: vec![tree],
+            ..Default::default()
+        })
+        .unwrap(),
+    );
+    let spend_transaction = compression_event_transaction(
+        to_vec(&PublicTransactionEvent {
+            input_compressed_account_hashes: vec![hash.0],
+            ..Default::default()
+        })
+        .unwrap(),
+    );
+    let block = |slot: u64, transactions: Vec<TransactionInfo>| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions,
+    };
+
+    // The spending block is indexed before the block that creates the account, e.g. because the
+    // creating block is backfilled.
+    index_block(&setup.indexer, &block(2, vec![spend_transaction.clone()]))
+        .await
+        .unwrap();
+    index_block(&setup.indexer, &block(1, vec![create_transaction]))
+        .await
+        .unwrap();
+
+    let spend_signature = Some(Into::<[u8; 64]>::into(spend_transaction.signature).to_vec());
+    let assert_spent = || async {
+        let account = accounts::Entity::find_by_id(hash.to_vec())
+            .one(setup.db_conn.as_ref())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(account.spent);
+        assert_eq!(account.slot_spent, Some(2));
+        assert_eq!(account.spent_tx_signature, spend_signature);
+        let token_account = token_accounts::Entity::find_by_id(hash.to_vec())
+            .one(setup.db_conn.as_ref())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(token_account.spent);
+        assert_eq!(token_account.slot_spent, Some(2));
+        assert_eq!(token_account.spent_tx_signature, spend_signature);
+
+        let balance = setup
+            .api
+            .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest {
+                owner: SerializablePubkey::from(COMPRESSED_TOKEN_PROGRAM),
+            })
+            .await
+            .unwrap();
+        assert_eq!(balance.value, U64String(0));
+        assert!(token_owner_balances::Entity::find()
+            .all(setup.db_conn.as_ref())
+            .await
+            .unwrap()
+            .iter()
+            .all(|balance| balance.amount == Decimal::from(0)));
+    };
+    assert_spent().await;
+
+    // Pruned accounts stay spent when their creating block is indexed again.
+    index_block(&setup.indexer, &block(20, vec![]))
+        .await
+        .unwrap();
+    assert_eq!(
+        prune_spent_accounts(setup.db_conn.as_ref(), setup.indexer.sql_batch_config, 10)
+            .await
+            .unwrap(),
+        1
+    );
+    let tombstone = pruned_accounts::Entity::find_by_id(hash.to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(tombstone.slot_spent, Some(2));
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts.push(Account {
+        hash: hash.clone(),
+        address: None,
+        data: Some(AccountData {
+            discriminator: UnsignedInteger(2),
+            data: Base64String(to_vec(&token_data).unwrap()),
+            data_hash: Hash::default(),
+        }),
+        owner: SerializablePubkey::from(COMPRESSED_TOKEN_PROGRAM),
+        lamports: U64String(1000),
+        tree: SerializablePubkey::from(tree),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(1),
+    });
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    assert_spent().await;
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_retry_failed_blocks(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let account_hash = Hash::new_unique();
+    let create_event = PublicTransactionEvent {
+        output_compressed_account_hashes: vec![account_hash.0],
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner,
+                lamports: 1000,
+                address: None,
+                data: None,
+            },
+            merkle_tree_index: 0,
+        }],
+        output_leaf_indices: vec![0],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: 0,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let spend_event = PublicTransactionEvent {
+        input_compressed_account_hashes: vec![account_hash.0],
+        ..Default::default()
+    };
+    let invalid_token_account_event = PublicTransactionEvent {
+        output_compressed_account_hashes: vec![Hash::new_unique().0],
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner: Pubkey::from_str("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m").unwrap(),
+                lamports: 0,
+                address: None,
+                data: Some(CompressedAccountData {
+                    discriminator: [0; 8],
+                    data: vec![1, 2, 3],
+                    data_hash: [0; 32],
+                }),
+            },
+            merkle_tree_index: 0,
+        }],
+        output_leaf_indices: vec![1],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: 1,
+        }],
+        ..create_event.clone()
+    };
+    let block = |slot: u64, event: &PublicTransactionEvent| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: vec![compression_event_transaction(to_vec(event).unwrap())],
+    };
+
+    // Simulates a block that failed because of a bug that has since been fixed. The block that
+    // spends the account it creates is indexed in the meantime, so the account has to be inserted
+    // as spent when the failed block is retried.
+    dead_letter_block(
+        &setup.indexer,
+        &block(1, &create_event),
+        &IngesterError::ParserError("Fixed bug".to_string()),
+    )
+    .await
+    .unwrap();
+    index_block(&setup.indexer, &block(2, &spend_event))
+        .await
+        .unwrap();
+    dead_letter_block(
+        &setup.indexer,
+        &block(3, &invalid_token_account_event),
+        &IngesterError::ParserError("Unfixed bug".to_string()),
+    )
+    .await
+    .unwrap();
+
+    // Retried blocks are published to subscribers and webhooks like any other indexed block.
+    let mut events = subscribe();
+    let summary = retry_failed_blocks(&setup.indexer, None).await.unwrap();
+    assert_eq!(
+        summary,
+        RetrySummary {
+            indexed: 1,
+            failed: 1
+        }
+    );
+    match events.try_recv().unwrap() {
+        IngestionEvent::AccountCreated { account, slot, .. } => {
+            assert_eq!(account.hash, account_hash);
+            assert_eq!(slot, 1);
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(events.try_recv().is_err());
+    let failed_blocks = fetch_failed_blocks(&setup.db_conn).await.unwrap();
+    assert_eq!(failed_blocks.len(), 1);
+    assert_eq!(failed_blocks[0].slot, 3);
+    assert!(failed_blocks[0]
+        .error
+        .contains("Failed to parse token data"));
+
+    let indexed_accounts = accounts::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(indexed_accounts.len(), 1);
+    assert_eq!(indexed_accounts[0].hash, account_hash.to_vec());
+    assert!(indexed_accounts[0].spent);
+    let balance = setup
+        .api
+        .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest {
+            owner: SerializablePubkey::from(owner),
+        })
+        .await
+        .unwrap();
+    assert_eq!(balance.value, U64String(0));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_owner_snapshot_at_slot(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let create_event = |hash: Hash, lamports: u64, leaf_index: u32| PublicTransactionEvent {
+        output_compressed_account_hashes: vec![hash.0],
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner,
+                lamports,
+                address: None,
+                data: None,
+            },
+            merkle_tree_index: 0,
+        }],
+        output_leaf_indices: vec![leaf_index],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: leaf_index as u64,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let block = |slot: u64, events: Vec<PublicTransactionEvent>| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: events
+            .iter()
+            .map(|event| compression_event_transaction(to_vec(event).unwrap()))
+            .collect(),
+    };
+    let first_hash = Hash::new_unique();
+    let second_hash = Hash::new_unique();
+    let unknown_spend_hash = Hash::new_unique();
+
+    index_block(
+        &setup.indexer,
+        &block(
+            1,
+            vec![
+                create_event(first_hash.clone(), 1000, 0),
+                create_event(unknown_spend_hash.clone(), 10, 1),
+            ],
+        ),
+    )
+    .await
+    .unwrap();
+    index_block(
+        &setup.indexer,
+        &block(2, vec![create_event(second_hash.clone(), 500, 2)]),
+    )
+    .await
+    .unwrap();
+    index_block(
+        &setup.indexer,
+        &block(
+            3,
+            vec![PublicTransactionEvent {
+                input_compressed_account_hashes: vec![first_hash.0],
+                ..Default::default()
+            }],
+        ),
+    )
+    .await
+    .unwrap();
+    // Spends an account without indexing the spending transaction, like a database restored from
+    // a state snapshot.
+    let mut state_update = StateUpdate::new();
+    state_update.in_accounts.insert(unknown_spend_hash.clone());
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let owner = SerializablePubkey::from(owner);
+    let snapshot_hashes = |snapshot: &OwnerSnapshot| {
+        snapshot
+            .accounts
+            .iter()
+            .map(|account| account.hash.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let snapshot = owner_snapshot_at_slot(&setup.db_conn, setup.indexer.sql_batch_config, owner, 1)
+        .await
+        .unwrap();
+    assert_eq!(snapshot_hashes(&snapshot), vec![first_hash.clone()]);
+    assert_eq!(snapshot.lamports, 1000);
+    assert_eq!(
+        snapshot.unknown_spend_slot,
+        vec![unknown_spend_hash.clone()]
+    );
+
+    let snapshot = owner_snapshot_at_slot(&setup.db_conn, setup.indexer.sql_batch_config, owner, 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        snapshot_hashes(&snapshot),
+        vec![first_hash.clone(), second_hash.clone()]
+    );
+    assert_eq!(snapshot.lamports, 1500);
+
+    let snapshot = owner_snapshot_at_slot(&setup.db_conn, setup.indexer.sql_batch_config, owner, 3)
+        .await
+        .unwrap();
+    assert_eq!(snapshot_hashes(&snapshot), vec![second_hash.clone()]);
+    assert_eq!(snapshot.lamports, 500);
+    assert!(snapshot.token_accounts.is_empty());
+
+    assert!(
+        owner_snapshot_at_slot(&setup.db_conn, setup.indexer.sql_batch_config, owner, 4)
+            .await
+            .is_err()
+    );
+
+    // Slots before indexing started cannot be reconstructed either.
+    record_indexing_start_slot(setup.db_conn.as_ref(), 1)
+        .await
+        .unwrap();
+    let error = owner_snapshot_at_slot(&setup.db_conn, setup.indexer.sql_batch_config, owner, 0)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<PhotonApiError>(),
+        Some(PhotonApiError::SlotNotIndexed {
+            slot: 0,
+            start_slot: 1
+        })
+    ));
+    assert!(
+        owner_snapshot_at_slot(&setup.db_conn, setup.indexer.sql_batch_config, owner, 1)
+            .await
+            .is_ok()
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_metadata(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let conn = setup.db_conn.as_ref();
+
+    assert_eq!(get_sink_cursor(conn, "kafka").await.unwrap(), None);
+    set_sink_cursor(conn, "kafka", 10).await.unwrap();
+    set_sink_cursor(conn, "kafka", 20).await.unwrap();
+    assert_eq!(get_sink_cursor(conn, "kafka").await.unwrap(), Some(20));
+    set_feature_flag(conn, "compress_account_data", true)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_feature_flag(conn, "compress_account_data")
+            .await
+            .unwrap(),
+        Some(true)
+    );
+    set_schema_fingerprint(conn, "abc".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        get_schema_fingerprint(conn).await.unwrap(),
+        Some("abc".to_string())
+    );
+
+    // Values that do not match the type of the accessor are reported instead of ignored.
+    set_metadata(
+        conn,
+        &MetadataKey::SinkCursor("invalid".to_string()),
+        "not a number".to_string(),
+    )
+    .await
+    .unwrap();
+    assert!(get_sink_cursor(conn, "invalid").await.is_err());
+
+    // The cursor secret is generated once and not exposed through the API.
+    let cursor_secret = get_or_create_cursor_secret(conn).await.unwrap();
+    assert_eq!(cursor_secret.len(), 64);
+    assert_eq!(
+        get_or_create_cursor_secret(conn).await.unwrap(),
+        cursor_secret
+    );
+
+    let metadata = setup.api.get_indexer_metadata().await.unwrap();
+    assert_eq!(
+        metadata.into_iter().collect::<Vec<_>>(),
+        vec![
+            (
+                "feature_flag:compress_account_data".to_string(),
+                "true".to_string()
+            ),
+            ("schema_fingerprint".to_string(), "abc".to_string()),
+            (
+                "sink_cursor:invalid".to_string(),
+                "not a number".to_string()
+            ),
+            ("sink_cursor:kafka".to_string(), "20".to_string()),
+        ]
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_streamed_exports(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let tree = SerializablePubkey::new_unique();
+    // More leaves than fit in a single page of the export.
+    let leaf_nodes: Vec<LeafNode> = (0..1100)
+        .map(|i| LeafNode {
+            hash: Hash::new_unique(),
+            leaf_index: i,
+            tree,
+            seq: i,
+        })
+        .collect();
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(&txn, setup.indexer.sql_batch_config, leaf_nodes.clone(), 12)
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+    let accounts = (0..3)
+        .map(|slot| Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner: SerializablePubkey::new_unique(),
+            lamports: U64String(1000),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(0),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(slot),
+        })
+        .collect::<Vec<_>>();
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let url = format!("http://127.0.0.1:{}", port);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/export/tree-leaves?tree={}", url, tree))
+        .header("accept", "application/x-ndjson")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let ndjson_leaves = response
+        .text()
+        .await
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let expected_leaves = leaf_nodes
+        .iter()
+        .map(|leaf_node| {
+            serde_json::json!({
+                "leafIndex": leaf_node.leaf_index,
+                "hash": leaf_node.hash.to_string(),
+                "seq": leaf_node.seq,
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ndjson_leaves, expected_leaves);
+
+    let json_leaves = client
+        .get(format!("{}/export/tree-leaves?tree={}", url, tree))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert_eq!(json_leaves, expected_leaves);
+
+    let exported_accounts = client
+        .get(format!("{}/export/accounts?fromSlot=1", url))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    let exported_hashes = exported_accounts
+        .iter()
+        .map(|account| {
+            assert_eq!(account["spent"], false);
+            account["hash"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    let expected_hashes = accounts[1..]
+        .iter()
+        .map(|account| account.hash.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(exported_hashes, expected_hashes);
+
+    let response = client
+        .get(format!("{}/export/accounts", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    server.stop().unwrap();
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_ndjson_list_queries(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+    let owner = SerializablePubkey::new_unique();
+    let accounts = (0..5)
+        .map(|_| Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner,
+            lamports: U64String(1000),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(0),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(1),
+        })
+        .collect::<Vec<_>>();
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let url = format!("http://127.0.0.1:{}/?format=ndjson", port);
+    let client = reqwest::Client::new();
+
+    // A page size smaller than the number of accounts, so that the rows span several pages.
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "getCompressedAccountsByOwner",
+            "params": {"owner": owner.to_string(), "limit": 2},
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let mut streamed_hashes = response
+        .text()
+        .await
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let account = serde_json::from_str::<serde_json::Value>(line).unwrap();
+            account["hash"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    let mut expected_hashes = accounts
+        .iter()
+        .map(|account| account.hash.to_string())
+        .collect::<Vec<_>>();
+    streamed_hashes.sort();
+    expected_hashes.sort();
+    assert_eq!(streamed_hashes, expected_hashes);
+
+    // Errors of the first page are returned as regular JSON-RPC errors.
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "getCompressedAccountsByOwner",
+            "params": {"owner": "invalid"},
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(response["id"], 2);
+    assert!(response["error"].is_object());
+
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "getCompressedAccount",
+            "params": {"hash": accounts[0].hash.to_string()},
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    server.stop().unwrap();
+}
+
+// Responses are compared byte for byte against a golden output that is shared by all database
+// backends, since consumers hash them and expect every replica to serve identical bytes.
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_deterministic_json_output(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name.clone(), db_backend).await;
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+    let owner = SerializablePubkey::from([1; 32]);
+    let accounts = vec![
+        Account {
+            hash: Hash::from([2; 32]),
+            address: Some(SerializablePubkey::from([3; 32])),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(98_765_432_109),
+                data: Base64String(vec![0, 1, 2, 255]),
+                data_hash: Hash::from([4; 32]),
+            }),
+            owner,
+            // Amounts are integers in the output. A float would be formatted as `123456789012.0`.
+            lamports: U64String(123_456_789_012),
+            tree: SerializablePubkey::from([5; 32]),
+            leaf_index: UnsignedInteger(7),
+            seq: UnsignedInteger(8),
+            slot_created: UnsignedInteger(1),
+        },
+        Account {
+            hash: Hash::from([6; 32]),
+            address: None,
+            data: None,
+            owner,
+            lamports: U64String(1),
+            tree: SerializablePubkey::from([5; 32]),
+            leaf_index: UnsignedInteger(9),
+            seq: UnsignedInteger(10),
+            slot_created: UnsignedInteger(1),
+        },
+    ];
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let client = reqwest::Client::new();
+    let requests = [
+        serde_json::json!({
+            "method": "getCompressedAccountsByOwner",
+            "params": {"owner": owner.to_string(), "withSummary": true},
+        }),
+        serde_json::json!({
+            "method": "getCompressedBalanceByOwner",
+            "params": {"owner": owner.to_string()},
+        }),
+        serde_json::json!({
+            "method": "getMultipleCompressedAccounts",
+            "params": {"hashes": [accounts[1].hash.to_string(), accounts[0].hash.to_string()]},
+        }),
+    ];
+    let mut responses = Vec::new();
+    for request in requests {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": request["method"],
+            "params": request["params"],
+        });
+        let response = client
+            .post(format!("http://127.0.0.1:{}", port))
+            .json(&body)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        responses.push(response);
+    }
+    server.stop().unwrap();
+    insta::assert_snapshot!(name, responses.join("\n"));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_prometheus_metrics_endpoint(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let url = format!("http://127.0.0.1:{}", port);
+    let client = reqwest::Client::new();
+    for method in ["getIndexerSlot", "notAMethod"] {
+        client
+            .post(&url)
+            .json(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": method}))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let response = client.get(format!("{}/metrics", url)).send().await.unwrap();
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let metrics = response.text().await.unwrap();
+    server.stop().unwrap();
+
+    let value = |series: &str| -> f64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("{} not found in:\n{}", series, metrics))
+            .parse()
+            .unwrap()
+    };
+    assert!(value("photon_blocks_indexed_total") >= 1.0);
+    assert!(value(r#"photon_api_requests_total{method="getIndexerSlot",result="success"}"#) >= 1.0);
+    assert!(value(r#"photon_api_request_duration_seconds_count{method="getIndexerSlot"}"#) >= 1.0);
+    assert!(
+        value(r#"photon_api_request_duration_seconds_bucket{method="getIndexerSlot",le="+Inf"}"#)
+            >= 1.0
+    );
+    assert!(!metrics.contains("notAMethod"));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_request_id_header(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let url = format!("http://127.0.0.1:{}", port);
+    let client = reqwest::Client::new();
+    let body = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "getIndexerSlot"});
+
+    let response = client
+        .post(&url)
+        .header("x-request-id", "client-request-id")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "client-request-id");
+
+    let response = client.post(&url).json(&body).send().await.unwrap();
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert_eq!(request_id.len(), 32);
+    assert!(request_id.chars().all(|c| c.is_ascii_hexdigit()));
+    server.stop().unwrap();
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_api_spec_endpoint(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let spec = reqwest::get(format!("http://127.0.0.1:{}/api-spec", port))
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    server.stop().unwrap();
+
+    assert_eq!(spec, photon_indexer::openapi::build_api_spec());
+    let method_names = spec["methods"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|method| method["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    for spec in PhotonApi::method_api_specs() {
+        assert!(method_names.contains(&spec.name.as_str()));
+    }
+}
+
+#[tokio::test]
+async fn test_socket_block_stream() {
+    let socket_path = std::env::temp_dir().join(format!("photon-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+    let blocks = (1..=3)
+        .map(|slot| BlockInfo {
+            metadata: BlockMetadata {
+                slot,
+                parent_slot: slot - 1,
+                blockhash: Hash::new_unique(),
+                ..Default::default()
+            },
+            transactions: vec![],
+        })
+        .collect::<Vec<_>>();
+    let frames = blocks
+        .iter()
+        .map(|block| encode_block_frame(block).unwrap())
+        .collect::<Vec<_>>();
+    tokio::spawn(async move {
+        // The first connection is closed in the middle of a frame, so the stream has to
+        // reconnect to receive the rest of the blocks.
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket.write_all(&frames[0]).await.unwrap();
+        socket.write_all(&frames[1][..5]).await.unwrap();
+        drop(socket);
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket.write_all(&frames[1]).await.unwrap();
+        socket.write_all(&frames[2]).await.unwrap();
+        std::future::pending::<()>().await;
+    });
+
+    let received_blocks = get_socket_block_stream(socket_path.to_str().unwrap().to_string())
+        .take(3)
+        .map(|mut block| {
+            assert!(block.metadata.received_at.is_some());
+            block.metadata.received_at = None;
+            block
+        })
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(received_blocks, blocks);
+    std::fs::remove_file(&socket_path).unwrap();
+}
+
+#[test]
+fn test_parse_sanitized_transaction() {
+    let payer = Keypair::new();
+    let account = Pubkey::new_unique();
+    let instruction = solana_sdk::instruction::Instruction::new_with_bytes(
+        ACCOUNT_COMPRESSION_PROGRAM_ID,
+        &[1, 2, 3],
+        vec![solana_sdk::instruction::AccountMeta::new(account, false)],
+    );
+    let transaction = solana_sdk::transaction::Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[&payer],
+        solana_sdk::hash::Hash::default(),
+    );
+    let sanitized_transaction =
+        solana_sdk::transaction::SanitizedTransaction::from_transaction_for_tests(
+            transaction.clone(),
+        );
+
+    let transaction_info =
+        parse_sanitized_transaction(&sanitized_transaction, &TransactionStatusMeta::default())
+            .unwrap();
+    assert_eq!(transaction_info.signature, transaction.signatures[0]);
+    assert_eq!(transaction_info.error, None);
+    assert_eq!(
+        transaction_info.instruction_groups,
+        vec![InstructionGroup {
+            outer_instruction: Instruction {
+                program_id: ACCOUNT_COMPRESSION_PROGRAM_ID,
+                data: vec![1, 2, 3],
+                accounts: vec![account],
+            },
+            inner_instructions: vec![],
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_conformance_invoke_instruction() {
+    let fee_payer = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let state_tree = Pubkey::new_unique();
+    let nullifier_queue = Pubkey::new_unique();
+    let output = light_sdk::compressed_account::OutputCompressedAccountWithPackedContext {
+        compressed_account: light_sdk::compressed_account::CompressedAccount {
+            owner: recipient,
+            lamports: 1000,
+            address: None,
+            data: None,
+        },
+        merkle_tree_index: 0,
+    };
+
+    let compress = InstructionDataInvoke {
+        output_compressed_accounts: vec![output.clone()],
+        compress_or_decompress_lamports: Some(1000),
+        is_compress: true,
+        ..Default::default()
+    };
+    let instruction = invoke_instruction(
+        fee_payer,
+        fee_payer,
+        &compress,
+        None,
+        &[state_tree, nullifier_queue],
+    );
+    assert_eq!(instruction.program_id, light_sdk::PROGRAM_ID_LIGHT_SYSTEM);
+    // The anchor discriminator of `invoke`, followed by the arguments as a length prefixed vector.
+    assert_eq!(instruction.data[..8], [26, 16, 169, 7, 21, 202, 242, 25]);
+    let arguments = &instruction.data[12..];
+    assert_eq!(
+        u32::from_le_bytes(instruction.data[8..12].try_into().unwrap()) as usize,
+        arguments.len()
+    );
+    assert_eq!(
+        InstructionDataInvoke::deserialize(&mut &arguments[..]).unwrap(),
+        compress
+    );
+    let accounts = instruction
+        .accounts
+        .iter()
+        .map(|account| (account.pubkey, account.is_signer, account.is_writable))
+        .collect::<Vec<_>>();
+    assert_eq!(accounts.len(), 11);
+    assert_eq!(accounts[0], (fee_payer, true, true));
+    assert_eq!(accounts[1], (fee_payer, true, false));
+    assert_eq!(accounts[3].0, light_sdk::PROGRAM_ID_NOOP);
+    assert_eq!(accounts[5].0, light_sdk::PROGRAM_ID_ACCOUNT_COMPRESSION);
+    assert_eq!(accounts[6], (sol_pool_pda(), false, true));
+    assert_eq!(
+        accounts[7],
+        (light_sdk::PROGRAM_ID_LIGHT_SYSTEM, false, false)
+    );
+    assert_eq!(accounts[8].0, solana_sdk::system_program::ID);
+    assert_eq!(
+        accounts[9..],
+        [(state_tree, false, true), (nullifier_queue, false, true)]
+    );
+
+    // Transfers neither use the sol pool nor a decompression recipient.
+    let transfer = InstructionDataInvoke {
+        output_compressed_accounts: vec![output],
+        ..Default::default()
+    };
+    let instruction = invoke_instruction(fee_payer, fee_payer, &transfer, None, &[state_tree]);
+    assert_eq!(
+        instruction.accounts[6].pubkey,
+        light_sdk::PROGRAM_ID_LIGHT_SYSTEM
+    );
+
+    let decompress = InstructionDataInvoke {
+        compress_or_decompress_lamports: Some(1000),
+        ..Default::default()
+    };
+    let instruction = invoke_instruction(
+        fee_payer,
+        recipient,
+        &decompress,
+        Some(fee_payer),
+        &[state_tree],
+    );
+    assert_eq!(instruction.accounts[1].pubkey, recipient);
+    assert!(instruction.accounts[7].pubkey == fee_payer && instruction.accounts[7].is_writable);
+}
+
+#[tokio::test]
+async fn test_error_classification() {
+    let call_error = |error: PhotonApiError| match jsonrpsee::core::Error::from(error) {
+        jsonrpsee::core::Error::Call(CallError::Custom(error)) => error,
+        error => panic!("Unexpected error: {:?}", error),
+    };
+    let stale_slot = call_error(PhotonApiError::StaleSlot(5));
+    assert_eq!(stale_slot.code(), -32000);
+    assert_eq!(stale_slot.message(), "Node is behind 5 slots");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(stale_slot.data().unwrap().get()).unwrap(),
+        serde_json::json!({ "version": 1, "kind": "stale_slot", "retryable": true })
+    );
+    let unexpected = call_error(PhotonApiError::UnexpectedError("secret".to_string()));
+    assert_eq!(unexpected.message(), "Internal server error");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(unexpected.data().unwrap().get()).unwrap(),
+        serde_json::json!({ "version": 1, "kind": "unexpected_error", "retryable": false })
+    );
+
+    assert!(IngesterError::DatabaseError("connection reset".to_string()).retryable());
+    let pool_timeout = IngesterError::from(DbErr::ConnectionAcquire);
+    assert!(pool_timeout.retryable());
+    assert_eq!(pool_timeout.kind(), "database_unavailable");
+    let constraint_violation = IngesterError::from(DbErr::Custom("constraint".to_string()));
+    assert!(constraint_violation.retryable());
+    assert_eq!(constraint_violation.kind(), "database_error");
+    let malformed_transaction = IngesterError::MalformedTransaction {
+        signature: Signature::new_unique().to_string(),
+        msg: "Account index 5 is out of bounds".to_string(),
+    };
+    assert!(!malformed_transaction.retryable());
+    assert_eq!(malformed_transaction.kind(), "malformed_transaction");
+}
+
+#[tokio::test]
+async fn test_parse_block_drops_malformed_transactions() {
+    let encode = |program_id_index: u8, inner_instruction: Option<(u8, u8)>| {
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::new_unique()],
+            message: VersionedMessage::Legacy(Message {
+                header: MessageHeader {
+                    num_required_signatures: 1,
+                    ..Default::default()
+                },
+                // Only transactions that reference the compression program are parsed in full.
+                account_keys: vec![Pubkey::new_unique(), ACCOUNT_COMPRESSION_PROGRAM_ID],
+                instructions: vec![CompiledInstruction {
+                    program_id_index,
+                    accounts: vec![0],
+                    data: vec![],
+                }],
+                ..Default::default()
+            }),
+        };
+        let mut meta = UiTransactionStatusMeta::from(TransactionStatusMeta::default());
+        if let Some((index, program_id_index)) = inner_instruction {
+            meta.inner_instructions = OptionSerializer::Some(vec![UiInnerInstructions {
+                index,
+                instructions: vec![UiInstruction::Compiled(UiCompiledInstruction {
+                    program_id_index,
+                    accounts: vec![0],
+                    data: bs58::encode([1, 2, 3]).into_string(),
+                    stack_height: None,
+                })],
+            }]);
+        }
+        EncodedTransactionWithStatusMeta {
+            transaction: EncodedTransaction::Binary(
+                base64::Engine::encode(
+                    &base64::engine::general_purpose::STANDARD,
+                    bincode::serialize(&transaction).unwrap(),
+                ),
+                TransactionBinaryEncoding::Base64,
+            ),
+            meta: Some(meta),
+            version: None,
+        }
+    };
+    let block = UiConfirmedBlock {
+        previous_blockhash: Hash::new_unique().to_string(),
+        blockhash: Hash::new_unique().to_string(),
+        parent_slot: 9,
+        transactions: Some(vec![
+            encode(1, Some((0, 1))),
+            // Program id index out of bounds.
+            encode(5, None),
+            // Inner instruction program id index out of bounds.
+            encode(1, Some((0, 5))),
+            // Inner instructions of an instruction that does not exist.
+            encode(1, Some((4, 1))),
+            EncodedTransactionWithStatusMeta {
+                transaction: EncodedTransaction::LegacyBinary("not base58!".to_string()),
+                meta: None,
+                version: None,
+            },
+        ]),
+        signatures: None,
+        rewards: None,
+        block_time: Some(0),
+        block_height: Some(10),
+    };
+
+    let parsed_block = parse_ui_confirmed_blocked(block.clone(), 10).unwrap();
+    assert_eq!(parsed_block.transactions.len(), 1);
+    assert_eq!(
+        parsed_block.transactions[0].instruction_groups[0]
+            .inner_instructions
+            .len(),
+        1
+    );
+
+    let malformed_block = UiConfirmedBlock {
+        blockhash: "invalid".to_string(),
+        ..block
+    };
+    assert!(matches!(
+        parse_ui_confirmed_blocked(malformed_block, 10),
+        Err(IngesterError::MalformedBlock { slot: 10, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_parse_block_prefilters_transactions() {
+    let encode = |message: VersionedMessage, meta: UiTransactionStatusMeta| {
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::new_unique()],
+            message,
+        };
+        EncodedTransactionWithStatusMeta {
+            transaction: EncodedTransaction::Binary(
+                base64::Engine::encode(
+                    &base64::engine::general_purpose::STANDARD,
+                    bincode::serialize(&transaction).unwrap(),
+                ),
+                TransactionBinaryEncoding::Base64,
+            ),
+            meta: Some(meta),
+            version: None,
+        }
+    };
+    let header = MessageHeader {
+        num_required_signatures: 1,
+        ..Default::default()
+    };
+    let instruction = |program_id_index: u8| CompiledInstruction {
+        program_id_index,
+        accounts: vec![0],
+        data: vec![1, 2, 3],
+    };
+    // The inner instruction references an account that the transaction does not load.
+    let mut meta = UiTransactionStatusMeta::from(TransactionStatusMeta::default());
+    meta.inner_instructions = OptionSerializer::Some(vec![UiInnerInstructions {
+        index: 0,
+        instructions: vec![UiInstruction::Compiled(UiCompiledInstruction {
+            program_id_index: 2,
+            accounts: vec![0],
+            data: bs58::encode([1, 2, 3]).into_string(),
+            stack_height: None,
+        })],
+    }]);
+    let unrelated_program = Pubkey::new_unique();
+    let unrelated_transaction = |program_id_index: u8| {
+        encode(
+            VersionedMessage::Legacy(Message {
+                header,
+                account_keys: vec![Pubkey::new_unique(), unrelated_program],
+                instructions: vec![instruction(program_id_index)],
+                ..Default::default()
+            }),
+            meta.clone(),
+        )
+    };
+    // The compression program is only loaded from a lookup table.
+    let mut lookup_meta = meta.clone();
+    lookup_meta.loaded_addresses = OptionSerializer::Some(UiLoadedAddresses {
+        writable: vec![],
+        readonly: vec![ACCOUNT_COMPRESSION_PROGRAM_ID.to_string()],
+    });
+    let lookup_transaction = encode(
+        VersionedMessage::V0(v0::Message {
+            header,
+            account_keys: vec![Pubkey::new_unique(), unrelated_program],
+            instructions: vec![instruction(1)],
+            address_table_lookups: vec![v0::MessageAddressTableLookup {
+                account_key: Pubkey::new_unique(),
+                writable_indexes: vec![],
+                readonly_indexes: vec![0],
+            }],
+            ..Default::default()
+        }),
+        lookup_meta,
+    );
+    let block = UiConfirmedBlock {
+        previous_blockhash: Hash::new_unique().to_string(),
+        blockhash: Hash::new_unique().to_string(),
+        parent_slot: 9,
+        transactions: Some(vec![
+            unrelated_transaction(1),
+            // Transactions that are not parsed in full are still dropped if their programs cannot
+            // be resolved.
+            unrelated_transaction(5),
+            lookup_transaction,
+        ]),
+        signatures: None,
+        rewards: None,
+        block_time: Some(0),
+        block_height: Some(10),
+    };
+
+    let parsed_block = parse_ui_confirmed_blocked(block, 10).unwrap();
+    assert_eq!(parsed_block.transactions.len(), 2);
+    let unrelated_group = &parsed_block.transactions[0].instruction_groups[0];
+    assert_eq!(
+        unrelated_group.outer_instruction.program_id,
+        unrelated_program
+    );
+    assert!(unrelated_group.inner_instructions.is_empty());
+    let lookup_group = &parsed_block.transactions[1].instruction_groups[0];
+    assert_eq!(lookup_group.outer_instruction.data, vec![1, 2, 3]);
+    assert_eq!(
+        lookup_group.inner_instructions[0].program_id,
+        ACCOUNT_COMPRESSION_PROGRAM_ID
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_detect_fork(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let blockhashes = [Hash::new_unique(), Hash::new_unique(), Hash::new_unique()];
+    for slot in [1, 2] {
+        index_block(
+            &setup.indexer,
+            &BlockInfo {
+                metadata: BlockMetadata {
+                    slot,
+                    parent_slot: slot - 1,
+                    blockhash: blockhashes[slot as usize].clone(),
+                    parent_blockhash: blockhashes[slot as usize - 1].clone(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let block = |slot, parent_slot, blockhash: &Hash, parent_blockhash: &Hash| BlockMetadata {
+        slot,
+        parent_slot,
+        blockhash: blockhash.clone(),
+        parent_blockhash: parent_blockhash.clone(),
+        ..Default::default()
+    };
+    let new_blockhash = Hash::new_unique();
+    // Extends the indexed chain.
+    let next_block = block(3, 2, &new_blockhash, &blockhashes[2]);
+    assert_eq!(
+        detect_fork(&setup.db_conn, &next_block).await.unwrap(),
+        None
+    );
+    // Already indexed.
+    let indexed_block = block(2, 1, &blockhashes[2], &blockhashes[1]);
+    assert_eq!(
+        detect_fork(&setup.db_conn, &indexed_block).await.unwrap(),
+        None
+    );
+    // Skips the indexed block at slot 2.
+    let skipping_block = block(3, 1, &new_blockhash, &blockhashes[1]);
+    assert_eq!(
+        detect_fork(&setup.db_conn, &skipping_block).await.unwrap(),
+        Some(1)
+    );
+    // Builds on another block at slot 2.
+    let forked_block = block(3, 2, &new_blockhash, &Hash::new_unique());
+    assert_eq!(
+        detect_fork(&setup.db_conn, &forked_block).await.unwrap(),
+        Some(1)
+    );
+
+    // Blocks above the slot are not part of the check, e.g. when the indexer restarts below the
+    // indexed tip and indexes slot 3 after slot 4.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: block(4, 3, &Hash::new_unique(), &new_blockhash),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        detect_fork(&setup.db_conn, &next_block).await.unwrap(),
+        None
+    );
+    assert_eq!(
+        detect_fork(&setup.db_conn, &forked_block).await.unwrap(),
+        Some(1)
+    );
+    let conflicting_block = block(4, 3, &Hash::new_unique(), &new_blockhash);
+    assert_eq!(
+        detect_fork(&setup.db_conn, &conflicting_block)
+            .await
+            .unwrap(),
+        Some(3)
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_state_snapshot_export_and_import(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 5,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let tree = SerializablePubkey::new_unique();
+    let owner = SerializablePubkey::new_unique();
+    let accounts = (0..3)
+        .map(|i| Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(i),
+                data: Base64String(vec![i as u8; 100]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner,
+            lamports: U64String(1000 + i),
+            tree,
+            leaf_index: UnsignedInteger(i),
+            seq: UnsignedInteger(i),
+            slot_created: UnsignedInteger(5),
+        })
+        .collect::<Vec<_>>();
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    let state_tree_nodes = state_trees::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+
+    let mut snapshot = Vec::new();
+    let summary = export_state_snapshot(&setup.db_conn, &mut snapshot)
+        .await
+        .unwrap();
+    assert_eq!(summary.slot, 5);
+    assert_eq!(
+        read_state_snapshot_slot(&mut snapshot.as_slice()).unwrap(),
+        5
+    );
+
+    reset_tables(&setup.db_conn).await.unwrap();
+    let imported = import_state_snapshot(
+        &setup.db_conn,
+        setup.indexer.sql_batch_config,
+        snapshot.as_slice(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(imported, summary);
+    assert_eq!(
+        get_last_state_snapshot_slot(setup.db_conn.as_ref())
+            .await
+            .unwrap(),
+        Some(summary.slot)
+    );
+    assert!(import_state_snapshot(
+        &setup.db_conn,
+        setup.indexer.sql_batch_config,
+        snapshot.as_slice()
+    )
+    .await
+    .is_err());
+
+    for account in accounts {
+        let res = setup
+            .api
+            .get_compressed_account(CompressedAccountRequest {
+                address: None,
+                hash: Some(account.hash.clone()),
+            })
+            .await
+            .unwrap()
+            .value;
+        assert_eq!(res, Some(account));
+    }
+    let balance = setup
+        .api
+        .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest { owner })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(balance.0, 3003);
+    let mut imported_state_tree_nodes = state_trees::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    imported_state_tree_nodes.sort_by_key(|node| node.node_idx);
+    let mut state_tree_nodes = state_tree_nodes;
+    state_tree_nodes.sort_by_key(|node| node.node_idx);
+    assert_eq!(imported_state_tree_nodes, state_tree_nodes);
+}
+
+/// Minimal HTTP server that serves `files` by path, regardless of the method and body of the request,
+/// and closes the connection after every response.
+async fn serve_files(files: HashMap<String, Vec<u8>>) -> String {
+    serve(move |path, _| match files.get(path) {
+        Some(body) => (200, body.clone()),
+        None => (404, Vec::new()),
+    })
+    .await
+}
+
+/// Minimal JSON-RPC server that answers every call of a method with the given response, which holds
+/// either a `result` or an `error`.
+async fn serve_rpc(responses: HashMap<&'static str, serde_json::Value>) -> String {
+    serve(move |_, body| {
+        let request: serde_json::Value = serde_json::from_slice(body).unwrap();
+        match responses.get(request["method"].as_str().unwrap()) {
+            Some(response) => (200, rpc_response(&request, response.clone())),
+            None => (404, Vec::new()),
+        }
+    })
+    .await
+}
+
+fn rpc_response(request: &serde_json::Value, mut response: serde_json::Value) -> Vec<u8> {
+    response["jsonrpc"] = "2.0".into();
+    response["id"] = request["id"].clone();
+    response.to_string().into_bytes()
+}
+
+/// Answers every request with the status code and body that `handler` returns for its path and
+/// body.
+async fn serve(handler: impl Fn(&str, &[u8]) -> (u16, Vec<u8>) + Send + 'static) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = tokio::io::BufReader::new(stream);
+            let mut request_line = String::new();
+            stream.read_line(&mut request_line).await.unwrap();
+            let mut content_length = 0;
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 2 {
+                if let Some((name, value)) = line.trim_end().split_once(": ") {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.parse().unwrap();
+                    }
+                }
+                line.clear();
+            }
+            let mut body = vec![0; content_length];
+            stream.read_exact(&mut body).await.unwrap();
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = handler(path, &body);
+            let response = [
+                format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .into_bytes(),
+                body,
+            ]
+            .concat();
+            stream.get_mut().write_all(&response).await.unwrap();
+        }
+    });
+    url
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_bootstrap_from_state_snapshot_url(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 7,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: None,
+        data: None,
+        owner: SerializablePubkey::new_unique(),
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(7),
+    };
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = vec![account.clone()];
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    let mut snapshot = Vec::new();
+    export_state_snapshot(&setup.db_conn, &mut snapshot)
+        .await
+        .unwrap();
+    let checksum = state_snapshot_checksum(snapshot.as_slice()).unwrap();
+
+    // An existing database is left untouched.
+    let url = serve_files(HashMap::new()).await;
+    let summary = bootstrap_from_state_snapshot_url(
+        &setup.db_conn,
+        setup.indexer.sql_batch_config,
+        &format!("{url}/snapshot"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(summary, None);
+
+    reset_tables(&setup.db_conn).await.unwrap();
+    let url = serve_files(HashMap::from([
+        ("/snapshot".to_string(), snapshot.clone()),
+        (
+            "/snapshot.sha256".to_string(),
+            format!("{checksum}  snapshot\n").into_bytes(),
+        ),
+        (
+            "/corrupted".to_string(),
+            snapshot[..snapshot.len() - 1].to_vec(),
+        ),
+        (
+            "/corrupted.sha256".to_string(),
+            format!("{checksum}  corrupted\n").into_bytes(),
+        ),
+    ]))
+    .await;
+    assert!(bootstrap_from_state_snapshot_url(
+        &setup.db_conn,
+        setup.indexer.sql_batch_config,
+        &format!("{url}/corrupted")
+    )
+    .await
+    .is_err());
+    assert!(bootstrap_from_state_snapshot_url(
+        &setup.db_conn,
+        setup.indexer.sql_batch_config,
+        &format!("{url}/missing")
+    )
+    .await
+    .is_err());
+    let summary = bootstrap_from_state_snapshot_url(
+        &setup.db_conn,
+        setup.indexer.sql_batch_config,
+        &format!("{url}/snapshot"),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(summary.slot, 7);
+
+    let res = setup
+        .api
+        .get_compressed_account(CompressedAccountRequest {
+            address: None,
+            hash: Some(account.hash.clone()),
+        })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(res, Some(account));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_rollback_to_slot(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    for slot in [1, 2] {
+        index_block(
+            &setup.indexer,
+            &BlockInfo {
+                metadata: BlockMetadata {
+                    slot,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let tree = SerializablePubkey::new_unique();
+    let owner = SerializablePubkey::new_unique();
+    let account = |leaf_index, seq, slot| Account {
+        hash: Hash::new_unique(),
+        address: None,
+        data: None,
+        owner,
+        lamports: U64String(1000),
+        tree,
+        leaf_index: UnsignedInteger(leaf_index),
+        seq: UnsignedInteger(seq),
+        slot_created: UnsignedInteger(slot),
+    };
+    let state_update = |slot, out_accounts: Vec<Account>, in_accounts: Vec<Hash>| {
+        let signature = Signature::new_unique();
+        let mut state_update = StateUpdate::new();
+        state_update.transactions.insert(Transaction {
+            signature,
+            slot,
+            uses_compression: true,
+            error: None,
+            compressed_lamports: 0,
+            decompressed_lamports: 0,
+        });
+        state_update.account_transactions = out_accounts
+            .iter()
+            .map(|account| (account.hash.clone(), false))
+            .chain(in_accounts.iter().map(|hash| (hash.clone(), true)))
+            .map(|(hash, spent)| AccountTransaction {
+                hash,
+                signature,
+                slot,
+                spent,
+            })
+            .collect();
+        state_update.in_accounts = in_accounts.into_iter().collect();
+        state_update.out_accounts = out_accounts;
+        state_update
+    };
+    let tree_root = || async {
+        state_trees::Entity::find()
+            .filter(state_trees::Column::Tree.eq(tree.to_bytes_vec()))
+            .filter(state_trees::Column::NodeIdx.eq(1))
+            .one(setup.db_conn.as_ref())
+            .await
+            .unwrap()
+            .unwrap()
+            .hash
+    };
+
+    let spent_account = account(0, 1, 1);
+    let kept_account = account(1, 2, 1);
+    persist_state_update_using_connection(
+        &setup.db_conn,
+        state_update(1, vec![spent_account.clone(), kept_account.clone()], vec![]),
+    )
+    .await
+    .unwrap();
+    let root_at_slot_1 = tree_root().await;
+
+    let created_account = account(2, 3, 2);
+    let mut orphaned_state_update = state_update(
+        2,
+        vec![created_account.clone()],
+        vec![spent_account.hash.clone()],
+    );
+    orphaned_state_update
+        .leaf_nullifications
+        .insert(LeafNullification {
+            tree: tree.0,
+            leaf_index: 0,
+            seq: 4,
+            signature: orphaned_state_update
+                .transactions
+                .iter()
+                .next()
+                .unwrap()
+                .signature,
+        });
+    persist_state_update_using_connection(&setup.db_conn, orphaned_state_update)
+        .await
+        .unwrap();
+    assert_ne!(tree_root().await, root_at_slot_1);
+
+    let txn = setup.db_conn.begin().await.unwrap();
+    rollback_to_slot(&txn, setup.indexer.sql_batch_config, 1)
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    assert_eq!(tree_root().await, root_at_slot_1);
+    for (account, expected) in [
+        (&spent_account, Some(spent_account.clone())),
+        (&kept_account, Some(kept_account.clone())),
+        (&created_account, None),
+    ] {
+        let res = setup
+            .api
+            .get_compressed_account(CompressedAccountRequest {
+                address: None,
+                hash: Some(account.hash.clone()),
+            })
+            .await
+            .unwrap()
+            .value;
+        assert_eq!(res, expected);
+    }
+    let balance = setup
+        .api
+        .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest { owner })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(balance.0, 2000);
+    let slots = fetch_slots_to_backfill(&setup.db_conn, 1, 2).await.unwrap();
+    assert_eq!(slots, vec![2]);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_multiple_accounts(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner1 = SerializablePubkey::new_unique();
+    let owner2 = SerializablePubkey::new_unique();
+    let mut state_update = StateUpdate::default();
+
+    let accounts = vec![
+        Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(0),
+                data: Base64String(vec![1; 500]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner: owner1,
+            lamports: U64String(1000),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(10),
+            seq: UnsignedInteger(1),
+            slot_created: UnsignedInteger(0),
+        },
+        Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(1),
+                data: Base64String(vec![2; 500]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner: owner1,
+            lamports: U64String(1030),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(11),
+            seq: UnsignedInteger(2),
+            slot_created: UnsignedInteger(0),
+        },
+        Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(4),
+                data: Base64String(vec![4; 500]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner: owner2,
+            lamports: U64String(10020),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(13),
+            seq: UnsignedInteger(3),
+            slot_created: UnsignedInteger(1),
+        },
+        Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(10),
+                data: Base64String(vec![5; 500]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner: owner2,
+            lamports: U64String(10100),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(23),
+            seq: UnsignedInteger(1),
+            slot_created: UnsignedInteger(0),
+        },
+    ];
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    for owner in [owner1, owner2] {
+        let res = setup
+            .api
+            .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+                owner,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .value;
+
+        let mut response_accounts = res.items;
+
+        let mut paginated_response_accounts = Vec::new();
+        let mut cursor = None;
+        loop {
+            let res = setup
+                .api
+                .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+                    owner,
+                    cursor: cursor.clone(),
+                    limit: Some(Limit::new(1).unwrap()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .value;
+
+            paginated_response_accounts.extend(res.items.clone());
+            cursor = res.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(response_accounts, paginated_response_accounts);
+
+        let mut accounts_of_interest = accounts
+            .clone()
+            .into_iter()
+            .filter(|x| x.owner == owner)
+            .collect::<Vec<Account>>();
+
+        assert_account_response_list_matches_input(
+            &mut response_accounts,
+            &mut accounts_of_interest,
+        );
+
+        let total_balance = accounts_of_interest
+            .iter()
+            .fold(0, |acc, x| acc + x.lamports.0);
+
+        let res = setup
+            .api
+            .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest { owner })
+            .await
+            .unwrap()
+            .value;
+
+        assert_eq!(res.0, total_balance);
+
+        // The summary covers all accounts of the owner, not only the requested page.
+        let res = setup
+            .api
+            .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+                owner,
+                limit: Some(Limit::new(1).unwrap()),
+                with_summary: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .value;
+        assert_eq!(res.items.len(), 1);
+        assert_eq!(
+            res.summary,
+            Some(OwnerAccountsSummary {
+                total_items: UnsignedInteger(accounts_of_interest.len() as u64),
+                total_lamports: U64String(total_balance),
+            })
+        );
+    }
+
+    let mut accounts_of_interest = vec![accounts[0].clone(), accounts[2].clone()];
+    let res = setup
+        .api
+        .get_multiple_compressed_accounts(GetMultipleCompressedAccountsRequest {
+            addresses: None,
+            hashes: Some(
+                accounts_of_interest
+                    .iter()
+                    .map(|x| x.hash.clone())
+                    .collect(),
+            ),
+        })
+        .await
+        .unwrap()
+        .value;
+
+    assert_account_response_list_matches_input(
+        &mut res.items.iter().map(|x| x.clone().unwrap()).collect(),
+        &mut accounts_of_interest,
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_persist_token_data(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::api::method::get_compressed_mint_token_holders::GetCompressedMintTokenHoldersRequest;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let mint1 = SerializablePubkey::new_unique();
+    let mint2 = SerializablePubkey::new_unique();
+    let mint3 = SerializablePubkey::new_unique();
+    let owner1 = SerializablePubkey::new_unique();
+    let owner2 = SerializablePubkey::new_unique();
+    let owner3 = SerializablePubkey::new_unique();
+    let owner4 = SerializablePubkey::new_unique();
+    let delegate1 = SerializablePubkey::new_unique();
+    let delegate2 = SerializablePubkey::new_unique();
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let token_data1 = TokenData {
+        mint: mint1,
+        owner: owner1,
+        amount: U64String(1),
+        delegate: Some(delegate1),
+        state: AccountState::frozen,
+        tlv: None,
+        extensions: None,
+    };
+
+    let token_data2 = TokenData {
+        mint: mint2,
+        owner: owner1,
+        amount: U64String(2),
+        delegate: Some(delegate2),
+        state: AccountState::initialized,
+        tlv: None,
+        extensions: None,
+    };
+
+    let token_data3 = TokenData {
+        mint: mint3,
+        owner: owner2,
+        amount: U64String(3),
+        delegate: Some(delegate1),
+        state: AccountState::frozen,
+        tlv: None,
+        extensions: None,
+    };
+    let token_data4 = TokenData {
+        mint: mint1,
+        owner: owner2,
+        amount: U64String(4),
+        delegate: Some(delegate1),
+        state: AccountState::frozen,
+        tlv: None,
+        extensions: None,
+    };
+    let token_data5 = TokenData {
+        mint: mint1,
+        owner: owner3,
+        amount: U64String(4),
+        delegate: Some(delegate1),
+        state: AccountState::frozen,
+        tlv: None,
+        extensions: None,
+    };
+    let token_data6 = TokenData {
+        mint: mint1,
+        owner: owner4,
+        amount: U64String(6),
+        delegate: Some(delegate1),
+        state: AccountState::frozen,
+        tlv: None,
+        extensions: None,
+    };
+    let token_data7 = TokenData {
+        mint: mint1,
+        owner: owner2,
+        amount: U64String(4),
+        delegate: Some(delegate1),
+        state: AccountState::frozen,
+        tlv: None,
+        extensions: None,
+    };
+    let token_data8 = TokenData {
+        mint: mint2,
+        owner: owner2,
+        amount: U64String(8),
+        delegate: None,
+        state: AccountState::initialized,
+        tlv: None,
+        extensions: None,
+    };
+    let all_token_data = vec![
+        token_data1,
+        token_data2,
+        token_data3,
+        token_data4,
+        token_data5,
+        token_data6,
+        token_data7,
+        token_data8,
+    ];
+    let hashes = all_token_data
+        .iter()
+        .map(|_| Hash::new_unique())
+        .collect::<HashSet<Hash>>();
+
+    let all_token_data: Vec<TokenDataWithHash> = all_token_data
+        .iter()
+        .zip(hashes.iter())
+        .map(|(token_data, hash)| TokenDataWithHash {
+            token_data: token_data.clone(),
+            hash: hash.clone(),
+        })
+        .collect();
+
+    let mut mint_to_owner_to_balance = HashMap::new();
+    for token_data in all_token_data.clone() {
+        let token_data = token_data.token_data;
+        let mint = token_data.mint;
+        let owner = token_data.owner;
+        let mint_owner_balances = mint_to_owner_to_balance
+            .entry(mint)
+            .or_insert(HashMap::new());
+        let balance = mint_owner_balances.entry(owner).or_insert(0);
+        *balance += token_data.amount.0;
+    }
+
+    let txn = sea_orm::TransactionTrait::begin(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+
+    let mut token_datas = Vec::new();
+
+    for (i, token_data) in all_token_data.iter().enumerate() {
+        let slot = 11 + i as i64;
+        let hash = token_data.hash.clone();
+        let token_data = token_data.token_data.clone();
+        let model = accounts::ActiveModel {
+            hash: Set(hash.clone().into()),
+            address: Set(Some(Pubkey::new_unique().to_bytes().to_vec())),
+            spent: Set(false),
+            data: Set(Some(to_vec(&token_data).unwrap())),
+            owner: Set(token_data.owner.to_bytes_vec()),
+            lamports: Set(Decimal::from(10)),
+            slot_created: Set(slot),
+            leaf_index: Set(i as i64),
+            discriminator: Set(Some(Decimal::from(1))),
+            data_hash: Set(Some(Hash::new_unique().to_vec())),
+            tree: Set(Pubkey::new_unique().to_bytes().to_vec()),
+            seq: Set(0),
+            ..Default::default()
+        };
+        accounts::Entity::insert(model).exec(&txn).await.unwrap();
+        token_datas.push(EnrichedTokenAccount {
+            hash,
+            token_data: token_data.clone(),
+            slot_created: slot as u64,
+            tx_signature: None,
+        });
+    }
+
+    persist_token_accounts(&txn, token_datas).await.unwrap();
+    txn.commit().await.unwrap();
+
+    let owner_tlv = all_token_data
+        .iter()
+        .filter(|x| x.token_data.owner == owner1 && x.token_data.mint == mint1)
+        .map(Clone::clone)
+        .collect();
+
+    let res = setup
+        .api
+        .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+            owner: owner1,
+            mint: Some(mint1),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .value;
+    verify_response_matches_input_token_data(res.clone(), owner_tlv);
+
+    for (mint, owner_to_balance) in mint_to_owner_to_balance.iter() {
+        for (owner, balance) in owner_to_balance.iter() {
+            let res = setup
+                .api
+                .get_compressed_token_account_by_owner_and_mint(
+                    GetCompressedTokenAccountByOwnerAndMintRequest {
+                        owner: *owner,
+                        mint: *mint,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap()
+                .value;
+            assert_eq!(res.total.0, *balance);
+            let expected = all_token_data
+                .iter()
+                .filter(|x| x.token_data.owner == *owner && x.token_data.mint == *mint)
+                .map(Clone::clone)
+                .collect();
+            verify_response_matches_input_token_data(
+                TokenAccountList {
+                    items: res.items,
+                    cursor: res.cursor,
+                },
+                expected,
+            );
+        }
+    }
+
+    for owner in [owner2] {
+        let owner_tlv = all_token_data
+            .iter()
+            .filter(|x| x.token_data.owner == owner)
+            .map(Clone::clone)
+            .collect();
+        let res = setup
+            .api
+            .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+                owner,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .value;
+
+        let mut paginated_res = Vec::new();
+        let mut cursor = None;
+        loop {
+            let res = setup
+                .api
+                .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+                    owner,
+                    cursor: cursor.clone(),
+                    limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .value;
+
+            paginated_res.extend(res.items.clone());
+            cursor = res.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(paginated_res, res.items);
+
+        for delegated in [true, false] {
+            let res = setup
+                .api
+                .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+                    owner,
+                    delegated: Some(delegated),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .value;
+            let expected = all_token_data
+                .iter()
+                .filter(|x| {
+                    x.token_data.owner == owner && x.token_data.delegate.is_some() == delegated
+                })
+                .cloned()
+                .collect();
+            verify_response_matches_input_token_data(res, expected);
+        }
+
+        let mut mint_to_balance: HashMap<SerializablePubkey, u64> = HashMap::new();
+
+        for token_account in paginated_res.iter() {
+            let balance = mint_to_balance
+                .entry(token_account.token_data.mint)
+                .or_insert(0);
+            *balance += token_account.token_data.amount.0;
+        }
+        for (mint, balance) in mint_to_balance.iter() {
+            let request = GetCompressedTokenBalancesByOwnerRequest {
+                owner,
+                mint: Some(*mint),
+                ..Default::default()
+            };
+            let res = setup
+                .api
+                .get_compressed_token_balances_by_owner(request.clone())
+                .await
+                .unwrap()
+                .value;
+            assert_eq!(res.token_balances[0].balance.0, *balance);
+
+            let res_v2 = setup
+                .api
+                .get_compressed_token_balances_by_owner_v2(request)
+                .await
+                .unwrap()
+                .value;
+            assert_eq!(res_v2.items, res.token_balances);
+        }
+
+        verify_response_matches_input_token_data(res.clone(), owner_tlv);
+        for token_account in res.items {
+            let request = CompressedAccountRequest {
+                address: None,
+                hash: Some(token_account.account.hash),
+            };
+            let balance = setup
+                .api
+                .get_compressed_token_account_balance(request)
+                .await
+                .unwrap()
+                .value;
+
+            assert_eq!(balance.amount, token_account.token_data.amount);
+        }
+    }
+    for delegate in [delegate1, delegate2] {
+        let delegate_tlv = all_token_data
+            .clone()
+            .into_iter()
+            .filter(|x| x.token_data.delegate == Some(delegate))
+            .collect();
+        let res = setup
+            .api
+            .get_compressed_token_accounts_by_delegate(GetCompressedTokenAccountsByDelegate {
+                delegate,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .value;
+        let mut paginated_res = Vec::new();
+        let mut cursor = None;
+        loop {
+            let res = setup
+                .api
+                .get_compressed_token_accounts_by_delegate(GetCompressedTokenAccountsByDelegate {
+                    delegate,
+                    cursor: cursor.clone(),
+                    limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .value;
+
+            paginated_res.extend(res.items.clone());
+            cursor = res.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(paginated_res, res.items);
+        verify_response_matches_input_token_data(res, delegate_tlv);
+
+        for frozen in [true, false] {
+            let res = setup
+                .api
+                .get_compressed_token_accounts_by_delegate(GetCompressedTokenAccountsByDelegate {
+                    delegate,
+                    frozen: Some(frozen),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .value;
+            let expected = all_token_data
+                .iter()
+                .filter(|x| {
+                    x.token_data.delegate == Some(delegate)
+                        && (x.token_data.state == AccountState::frozen) == frozen
+                })
+                .cloned()
+                .collect();
+            verify_response_matches_input_token_data(res, expected);
+        }
+    }
+
+    for (mint, owner_to_balance) in mint_to_owner_to_balance.iter() {
+        let mut items = Vec::new();
+
+        let mut cursor: Option<Cursor> = None;
+        loop {
+            let res = setup
+                .api
+                .get_compressed_mint_token_holders(GetCompressedMintTokenHoldersRequest {
+                    mint: mint.clone(),
+                    limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
+                    cursor,
+                })
+                .await
+                .unwrap()
+                .value;
+            for item in res.items.clone() {
+                items.push(item);
+            }
+            cursor = res.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        // Assert that items are in descending balance order
+        for i in 1..items.len() {
+            assert!(items[i].balance.0 <= items[i - 1].balance.0);
+        }
+        assert_eq!(items.len(), owner_to_balance.len());
+        for item in items {
+            assert_eq!(item.balance.0, *owner_to_balance.get(&item.owner).unwrap());
+        }
+    }
+
+    // Requests that still use the old name of the option sort by the creation slot.
+    assert_eq!(
+        serde_json::from_str::<TokenAccountSortBy>("\"slotUpdated\"").unwrap(),
+        TokenAccountSortBy::SlotCreated
+    );
+    for sort_by in [
+        TokenAccountSortBy::Mint,
+        TokenAccountSortBy::Amount,
+        TokenAccountSortBy::SlotCreated,
+    ] {
+        for sort_direction in [SortDirection::Asc, SortDirection::Desc] {
+            let res = setup
+                .api
+                .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+                    owner: owner2,
+                    sort_by: Some(sort_by),
+                    sort_direction: Some(sort_direction),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .value;
+            let sort_keys = res
+                .items
+                .iter()
+                .map(|item| match sort_by {
+                    TokenAccountSortBy::Mint => item.token_data.mint.to_bytes_vec(),
+                    TokenAccountSortBy::Amount => item.token_data.amount.0.to_be_bytes().to_vec(),
+                    TokenAccountSortBy::SlotCreated => {
+                        item.account.slot_created.0.to_be_bytes().to_vec()
+                    }
+                })
+                .collect::<Vec<_>>();
+            let mut expected_sort_keys = sort_keys.clone();
+            expected_sort_keys.sort();
+            if sort_direction == SortDirection::Desc {
+                expected_sort_keys.reverse();
+            }
+            assert_eq!(sort_keys, expected_sort_keys);
+
+            let mut paginated_res = Vec::new();
+            let mut cursor = None;
+            loop {
+                let res = setup
+                    .api
+                    .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+                        owner: owner2,
+                        cursor: cursor.clone(),
+                        limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
+                        sort_by: Some(sort_by),
+                        sort_direction: Some(sort_direction),
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap()
+                    .value;
+
+                paginated_res.extend(res.items.clone());
+                cursor = res.cursor;
+                if cursor.is_none() {
+                    break;
+                }
+            }
+            assert_eq!(paginated_res, res.items);
+        }
+    }
+
+    let mut owner_to_balances = HashMap::new();
+    for (mint, balances) in mint_to_owner_to_balance.into_iter() {
+        for (owner, balance) in balances.into_iter() {
+            owner_to_balances
+                .entry(owner)
+                .or_insert(HashMap::new())
+                .insert(mint, balance);
+        }
+    }
+    for owner in owner_to_balances.keys() {
+        let mut cursor = None;
+        let mut mint_to_balance = HashMap::new();
+
+        loop {
+            let request = GetCompressedTokenBalancesByOwnerRequest {
+                owner: owner.clone(),
+                cursor,
+                limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
+                ..Default::default()
+            };
+            let res = setup
+                .api
+                .get_compressed_token_balances_by_owner(request)
+                .await
+                .unwrap()
+                .value;
+            let token_balances = res.token_balances;
+            for token_balance in token_balances.iter() {
+                let balance = mint_to_balance.entry(token_balance.mint).or_insert(0);
+                *balance += token_balance.balance.0;
+            }
+            cursor = res.cursor;
             if cursor.is_none() {
                 break;
             }
         }
-        assert_eq!(paginated_res, res.items);
+        assert_eq!(mint_to_balance, *owner_to_balances.get(owner).unwrap());
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_get_top_compressed_sol_owners(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::api::method::get_top_compressed_sol_owners::GetTopCompressedSolOwnersRequest;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owners = (0..4)
+        .map(|_| SerializablePubkey::new_unique())
+        .collect::<Vec<_>>();
+    let balances = [
+        (owners[0], 10),
+        (owners[1], 30),
+        (owners[1], 5),
+        (owners[2], 40),
+        (owners[3], 20),
+    ];
+
+    let accounts = balances
+        .iter()
+        .enumerate()
+        .map(|(i, (owner, lamports))| Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner: *owner,
+            lamports: U64String(*lamports),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(i as u64),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        })
+        .collect::<Vec<Account>>();
+    let state_update = StateUpdate {
+        out_accounts: accounts.clone(),
+        ..Default::default()
+    };
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    // Spending the only account of the last owner should drop them from the ranking.
+    let mut state_update = StateUpdate::default();
+    state_update.in_accounts.insert(accounts[4].hash.clone());
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let res = setup
+        .api
+        .get_top_compressed_sol_owners(GetTopCompressedSolOwnersRequest::default())
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(res.cursor, None);
+    assert_eq!(
+        res.items
+            .iter()
+            .map(|item| (item.owner, item.balance.0))
+            .collect::<Vec<_>>(),
+        vec![(owners[2], 40), (owners[1], 35), (owners[0], 10)]
+    );
+
+    let mut paginated_items = Vec::new();
+    let mut cursor = None;
+    loop {
+        let res = setup
+            .api
+            .get_top_compressed_sol_owners(GetTopCompressedSolOwnersRequest {
+                cursor,
+                limit: Some(Limit::new(1).unwrap()),
+            })
+            .await
+            .unwrap()
+            .value;
+        paginated_items.extend(res.items);
+        cursor = res.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(paginated_items, res.items);
+}
+
+#[tokio::test]
+async fn test_compute_parent_hash() {
+    let child = ZERO_BYTES[0];
+    let parent = ZERO_BYTES[1];
+    let computed_parent = compute_parent_hash(child.to_vec(), child.to_vec()).unwrap();
+    assert_eq!(computed_parent, parent.to_vec());
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_load_unspent_hash_filter(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let accounts = (0..10)
+        .map(|i| Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner: SerializablePubkey::new_unique(),
+            lamports: U64String(1000),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(i),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        })
+        .collect::<Vec<_>>();
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let filter = BloomFilter::load_unspent_hashes(&setup.db_conn)
+        .await
+        .unwrap();
+    for account in accounts.iter() {
+        assert!(filter.may_contain(&account.hash));
+    }
+    let false_positives = (0..10_000)
+        .filter(|_| filter.may_contain(&Hash::new_unique()))
+        .count();
+    assert!(false_positives < 100);
+}
+
+#[tokio::test]
+async fn test_count_path_nodes() {
+    let tree = SerializablePubkey::new_unique();
+    let other_tree = SerializablePubkey::new_unique();
+    let tree_height = 3;
+
+    assert_eq!(count_path_nodes(vec![], tree_height), 0);
+    assert_eq!(count_path_nodes(vec![(tree, 0)], tree_height), 3);
+    // Sibling leaves share all ancestors.
+    assert_eq!(count_path_nodes(vec![(tree, 0), (tree, 1)], tree_height), 4);
+    assert_eq!(count_path_nodes(vec![(tree, 0), (tree, 3)], tree_height), 5);
+    assert_eq!(count_path_nodes(vec![(tree, 0), (tree, 0)], tree_height), 3);
+    assert_eq!(
+        count_path_nodes(vec![(tree, 0), (other_tree, 0)], tree_height),
+        6
+    );
+}
+
+#[tokio::test]
+async fn test_supervisor_restarts_panicked_component() {
+    let starts = Arc::new(AtomicUsize::new(0));
+    let handle = supervise("test component", {
+        let starts = starts.clone();
+        move || {
+            let starts = starts.clone();
+            tokio::spawn(async move {
+                if starts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first start fails");
+                }
+                std::future::pending::<()>().await;
+            })
+        }
+    });
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while starts.load(Ordering::SeqCst) < 2 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    handle.abort();
+    assert!(handle.await.unwrap_err().is_cancelled());
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_token_data_parse_monitor() {
+    let mut monitor = TokenDataParseMonitor::default();
+    for _ in 0..200 {
+        assert!(!monitor.record(true));
+    }
+    // A few sporadic failures stay below the alert threshold.
+    for _ in 0..5 {
+        assert!(!monitor.record(false));
+    }
+    assert!(!monitor.is_alerting());
+
+    // A layout change makes every subsequent parse fail. The alert is raised exactly once.
+    let alerts = (0..100).filter(|_| monitor.record(false)).count();
+    assert_eq!(alerts, 1);
+    assert!(monitor.is_alerting());
+
+    // The alert clears once the failures have been pushed out of the window.
+    for _ in 0..1000 {
+        assert!(!monitor.record(true));
+    }
+    assert!(!monitor.is_alerting());
+    assert_eq!(monitor.failure_rate(), 0.0);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_persisted_state_trees(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let tree = SerializablePubkey::new_unique();
+    let num_nodes = 5;
+
+    let leaf_nodes: Vec<LeafNode> = (0..num_nodes)
+        .map(|i| LeafNode {
+            hash: Hash::new_unique(),
+            leaf_index: i,
+            tree,
+            seq: i,
+        })
+        .collect();
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    let tree_height = 5;
+    persist_leaf_nodes(
+        &txn,
+        setup.indexer.sql_batch_config,
+        leaf_nodes.clone(),
+        tree_height,
+    )
+    .await
+    .unwrap();
+    txn.commit().await.unwrap();
+
+    let proofs = get_multiple_compressed_leaf_proofs(
+        &setup.db_conn.begin().await.unwrap(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        leaf_nodes
+            .iter()
+            .map(|x| Hash::try_from(x.hash.clone()).unwrap())
+            .collect(),
+    )
+    .await
+    .unwrap();
+
+    let proof_hashes: HashSet<Hash> = proofs.iter().map(|x| x.hash.clone()).collect();
+    let leaf_hashes: HashSet<Hash> = leaf_nodes.iter().map(|x| x.hash.clone()).collect();
+    assert_eq!(proof_hashes, leaf_hashes);
+
+    for proof in proofs {
+        assert_eq!(proof.merkleTree, tree);
+        assert_eq!(num_nodes as u64 - 1, proof.rootSeq);
+        assert_eq!(tree_height - 1, proof.proof.len() as u32);
+    }
+
+    // Repeat in order to test updates
+
+    let leaf_nodes: Vec<LeafNode> = (0..num_nodes)
+        .map(|i| LeafNode {
+            hash: Hash::new_unique(),
+            leaf_index: i,
+            tree,
+            seq: i + num_nodes,
+        })
+        .collect();
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(
+        &txn,
+        setup.indexer.sql_batch_config,
+        leaf_nodes.clone(),
+        tree_height,
+    )
+    .await
+    .unwrap();
+    txn.commit().await.unwrap();
+
+    let proofs = get_multiple_compressed_leaf_proofs(
+        &setup.db_conn.begin().await.unwrap(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        leaf_nodes
+            .iter()
+            .map(|x| Hash::try_from(x.hash.clone()).unwrap())
+            .collect(),
+    )
+    .await
+    .unwrap();
+
+    let proof_hashes: HashSet<Hash> = proofs.iter().map(|x| x.hash.clone()).collect();
+    let leaf_hashes: HashSet<Hash> = leaf_nodes.iter().map(|x| x.hash.clone()).collect();
+    assert_eq!(proof_hashes, leaf_hashes);
+
+    for proof in proofs {
+        assert_eq!(proof.merkleTree, tree);
+        assert_eq!(num_nodes as u64 - 1 + num_nodes as u64, proof.rootSeq);
+        assert_eq!(tree_height - 1, proof.proof.len() as u32);
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_leaf_proofs_across_trees(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let trees = (0..3)
+        .map(|_| SerializablePubkey::new_unique())
+        .collect::<Vec<_>>();
+    let tree_height = 5;
+
+    let leaf_nodes: Vec<LeafNode> = (0..4)
+        .flat_map(|i| {
+            trees.iter().map(move |tree| LeafNode {
+                hash: Hash::new_unique(),
+                leaf_index: i,
+                tree: *tree,
+                seq: i,
+            })
+        })
+        .collect();
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(
+        &txn,
+        setup.indexer.sql_batch_config,
+        leaf_nodes.clone(),
+        tree_height,
+    )
+    .await
+    .unwrap();
+    txn.commit().await.unwrap();
+
+    // The hashes alternate between the trees, so the proofs must be merged back into this order.
+    let hashes = leaf_nodes
+        .iter()
+        .rev()
+        .map(|leaf_node| leaf_node.hash.clone())
+        .collect::<Vec<_>>();
+    let proofs = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        hashes.clone(),
+    )
+    .await
+    .unwrap();
+    let expected_proofs = get_multiple_compressed_leaf_proofs(
+        &setup.db_conn.begin().await.unwrap(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        hashes.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(proofs, expected_proofs);
+    assert_eq!(
+        proofs
+            .iter()
+            .map(|proof| proof.hash.clone())
+            .collect::<Vec<_>>(),
+        hashes
+    );
+
+    let missing_hashes = vec![hashes[0].clone(), Hash::new_unique()];
+    assert!(matches!(
+        get_multiple_compressed_leaf_proofs_by_tree(
+            setup.db_conn.as_ref(),
+            setup.indexer.sql_batch_config,
+            &setup.indexer.in_memory_trees,
+            missing_hashes
+        )
+        .await,
+        Err(PhotonApiError::RecordNotFound(_))
+    ));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_indexed_merkle_trees(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::ingester::persist::persisted_indexed_merkle_tree::validate_tree;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let tree = SerializablePubkey::new_unique();
+    let num_nodes = 2;
+
+    let txn = sea_orm::TransactionTrait::begin(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+
+    let values = (0..num_nodes).map(|i| vec![i * 4 + 1]).collect();
+    let tree_height = 4;
+
+    multi_append(
+        &txn,
+        setup.indexer.sql_batch_config,
+        values,
+        tree.to_bytes_vec(),
+        tree_height,
+    )
+    .await
+    .unwrap();
+
+    txn.commit().await.unwrap();
+
+    let (model, _) = get_exclusion_range_with_proof(
+        &setup.db_conn.begin().await.unwrap(),
+        setup.indexer.sql_batch_config,
+        tree.to_bytes_vec(),
+        tree_height,
+        vec![3],
+    )
+    .await
+    .unwrap();
+
+    let expected_model = indexed_trees::Model {
+        tree: tree.to_bytes_vec(),
+        leaf_index: 2,
+        value: vec![1],
+        next_index: 3,
+        next_value: vec![5],
+        seq: 0,
+    };
+
+    assert_eq!(model, expected_model);
+
+    let txn = sea_orm::TransactionTrait::begin(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+
+    let values = vec![vec![3]];
+
+    multi_append(
+        &txn,
+        setup.indexer.sql_batch_config,
+        values,
+        tree.to_bytes_vec(),
+        tree_height,
+    )
+    .await
+    .unwrap();
+
+    txn.commit().await.unwrap();
+
+    validate_tree(setup.db_conn.as_ref(), tree).await;
+
+    let (model, _) = get_exclusion_range_with_proof(
+        &setup.db_conn.begin().await.unwrap(),
+        setup.indexer.sql_batch_config,
+        tree.to_bytes_vec(),
+        tree_height,
+        vec![4],
+    )
+    .await
+    .unwrap();
+
+    let expected_model = indexed_trees::Model {
+        tree: tree.to_bytes_vec(),
+        leaf_index: 4,
+        value: vec![3],
+        next_index: 3,
+        next_value: vec![5],
+        seq: 0,
+    };
+
+    assert_eq!(model, expected_model);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_get_multiple_new_address_proofs(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::api::method::get_multiple_new_address_proofs::{
+        get_multiple_new_address_proofs, AddressList,
+    };
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name.clone(), db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let addresses = vec![
+        SerializablePubkey::try_from("Fi6AXBGuGs7DRXP428hwhJJfTpJ4BVZD8DiUcX1cj35W").unwrap(),
+        SerializablePubkey::try_from("sH8ux4csv8wxiRejuHjpTCkrfGgeNtg6Y55AJJ9GJSd").unwrap(),
+    ];
+    let proof = get_multiple_new_address_proofs(
+        &setup.db_conn,
+        AddressList(addresses.clone()),
+        setup.indexer.sql_batch_config,
+    )
+    .await
+    .unwrap();
+    insta::assert_json_snapshot!(name, proof);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_get_compressed_address_info(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::api::method::get_compressed_address_info::GetCompressedAddressInfoRequest;
+    use photon_indexer::ingester::parser::indexer_events::{
+        IndexedMerkleTreeEvent, IndexedMerkleTreeUpdate, RawIndexedElement,
+    };
+    use photon_indexer::ingester::persist::persisted_indexed_merkle_tree::HIGHEST_ADDRESS_PLUS_ONE;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let mut address = [7; 32];
+    address[0] = 0;
+    let highest_address: [u8; 32] = [0]
+        .into_iter()
+        .chain(HIGHEST_ADDRESS_PLUS_ONE.to_bytes_be())
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap();
+    // Inserting the first address splits the initial range of the tree.
+    let event = MerkleTreeEvent::V3(IndexedMerkleTreeEvent {
+        id: tree.to_bytes(),
+        updates: vec![IndexedMerkleTreeUpdate {
+            new_low_element: RawIndexedElement {
+                value: [0; 32],
+                next_index: 2,
+                next_value: address,
+                index: 0,
+            },
+            new_low_element_hash: Hash::new_unique().0,
+            new_high_element: RawIndexedElement {
+                value: address,
+                next_index: 1,
+                next_value: highest_address,
+                index: 2,
+            },
+            new_high_element_hash: Hash::new_unique().0,
+        }],
+        seq: 3,
+    });
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 1,
+                ..Default::default()
+            },
+            transactions: vec![merkle_tree_event_transaction(event)],
+        },
+    )
+    .await
+    .unwrap();
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: Some(SerializablePubkey::from(address)),
+        data: None,
+        owner: SerializablePubkey::new_unique(),
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(1),
+    };
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = vec![account.clone()];
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let info = setup
+        .api
+        .get_compressed_address_info(GetCompressedAddressInfoRequest {
+            address: SerializablePubkey::from(address),
+        })
+        .await
+        .unwrap()
+        .value
+        .unwrap();
+    assert_eq!(info.tree, SerializablePubkey::from(tree));
+    assert_eq!(info.leaf_index, UnsignedInteger(2));
+    assert_eq!(info.seq, UnsignedInteger(4));
+    assert_eq!(info.account, Some(account));
+
+    let unknown_address = setup
+        .api
+        .get_compressed_address_info(GetCompressedAddressInfoRequest {
+            address: SerializablePubkey::new_unique(),
+        })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(unknown_address, None);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_get_multiple_new_address_proofs_interop(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::api::method::{
+        get_multiple_new_address_proofs::{
+            get_multiple_new_address_proofs, get_multiple_new_address_proofs_v2, AddressList,
+            AddressListWithTrees, AddressWithTree, ADDRESS_TREE_ADDRESS,
+        },
+        get_validity_proof::CompressedProof,
+    };
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name.clone(), db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let addresses = vec![SerializablePubkey::try_from(vec![
+        0, 2, 3, 4, 5, 6, 7, 8, 9, 10, 42, 42, 42, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
+        26, 27, 28, 29, 30, 31, 32,
+    ])
+    .unwrap()];
+    let proof = get_multiple_new_address_proofs(
+        &setup.db_conn,
+        AddressList(addresses.clone()),
+        setup.indexer.sql_batch_config,
+    )
+    .await
+    .unwrap();
+    insta::assert_json_snapshot!(name.clone(), proof);
+    let mut validity_proof = get_validity_proof(
+        &setup.db_conn,
+        &setup.prover_url,
+        GetValidityProofRequest {
+            newAddresses: addresses.clone(),
+            newAddressesWithTrees: vec![],
+            hashes: vec![],
+        },
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+    )
+    .await
+    .unwrap();
+    // The Gnark prover has some randomness.
+    validity_proof.value.compressedProof = CompressedProof::default();
+
+    insta::assert_json_snapshot!(format!("{}-validity-proof", name), validity_proof);
+
+    // Repeat with V2
+    let addresses_with_trees: Vec<AddressWithTree> = addresses
+        .clone()
+        .into_iter()
+        .map(|address| AddressWithTree {
+            address,
+            tree: SerializablePubkey::from(ADDRESS_TREE_ADDRESS),
+        })
+        .collect();
+    let proof_v2 = get_multiple_new_address_proofs_v2(
+        &setup.db_conn,
+        AddressListWithTrees(addresses_with_trees.clone()),
+        setup.indexer.sql_batch_config,
+    )
+    .await
+    .unwrap();
+
+    insta::assert_json_snapshot!(name.clone(), proof_v2);
+    let mut validity_proof_v2 = get_validity_proof(
+        &setup.db_conn,
+        &setup.prover_url,
+        GetValidityProofRequest {
+            newAddressesWithTrees: addresses_with_trees.clone(),
+            hashes: vec![],
+            newAddresses: vec![],
+        },
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+    )
+    .await
+    .unwrap();
+    // The Gnark prover has some randomness.
+    validity_proof_v2.value.compressedProof = CompressedProof::default();
+
+    insta::assert_json_snapshot!(format!("{}-validity-proof", name), validity_proof_v2);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+#[ignore]
+async fn load_test(#[values(DatabaseBackend::Postgres)] db_backend: DatabaseBackend) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name.clone(), db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let mut state_update = StateUpdate::default();
+
+    let tree = SerializablePubkey::new_unique();
+
+    fn generate_mock_account(leaf_index: u64, tree: SerializablePubkey) -> Account {
+        Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(1),
+                data: Base64String(vec![1; 500]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner: SerializablePubkey::new_unique(),
+            lamports: U64String(1000),
+            tree,
+            leaf_index: UnsignedInteger(leaf_index),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        }
+    }
+
+    for i in 0..100000 {
+        state_update
+            .out_accounts
+            .push(generate_mock_account(i, tree));
+    }
+    persist_state_update_using_connection(setup.db_conn.as_ref(), state_update)
+        .await
+        .unwrap();
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_persisted_state_trees_bug_with_latter_smaller_seq_values(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::{
+        api::method::get_multiple_new_address_proofs::{
+            get_multiple_new_address_proofs, AddressList,
+        },
+        ingester::persist::persisted_indexed_merkle_tree::validate_tree,
+    };
+
+    let name = trim_test_name(function_name!());
+
+    let setup = setup(name, db_backend).await;
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let tree =
+        SerializablePubkey::try_from("C83cpRN6oaafjNgMQJvaYgAz592EP5wunKvbokeTKPLn").unwrap();
+
+    let leaf_nodes_1 = vec![
+        LeafNode {
+            tree,
+            leaf_index: 0,
+            hash: Hash::try_from("34yinGSAmWKeXw61zZzd8hbE1ySB1pDmgiHzJhRtVwJY").unwrap(),
+            seq: 4,
+        },
+        LeafNode {
+            tree,
+            leaf_index: 1,
+            hash: Hash::try_from("34cMT7MjFrs8hLp2zHMrPJHKkUxBDBwBTNck77wLjjcY").unwrap(),
+            seq: 0,
+        },
+        LeafNode {
+            tree,
+            leaf_index: 2,
+            hash: Hash::try_from("TTSZiUJsGTcU7sXqYtw53yFY5Ag7DmHXR4GzEjVk7J7").unwrap(),
+            seq: 5,
+        },
+    ];
+    let leaf_nodes_2 = vec![
+        LeafNode {
+            tree,
+            leaf_index: 0,
+            hash: Hash::try_from("3hH3oNVj2bafrqqXLnZjLjkuDaoxKhyyvmxaSs939hws").unwrap(),
+            seq: 0,
+        },
+        LeafNode {
+            tree,
+            leaf_index: 1,
+            hash: Hash::try_from("34cMT7MjFrs8hLp2zHMrPJHKkUxBDBwBTNck77wLjjcY").unwrap(),
+            seq: 0,
+        },
+        LeafNode {
+            tree,
+            leaf_index: 2,
+            hash: Hash::try_from("25D2cs6h29NZgmDepVqc7bLLSWcNJnMvGoxeTpyZjF3u").unwrap(),
+            seq: 10,
+        },
+    ];
+    let leaf_node_chunks = vec![leaf_nodes_1, leaf_nodes_2];
+
+    let tree_height = 3;
+    for chunk in leaf_node_chunks {
+        let txn = setup.db_conn.as_ref().begin().await.unwrap();
+        persist_leaf_nodes(
+            &txn,
+            setup.indexer.sql_batch_config,
+            chunk.clone(),
+            tree_height,
+        )
+        .await
+        .unwrap();
+        txn.commit().await.unwrap();
+
+        let proof_address = "12prJNGB6sfTMrZM1Udv2Aamv9fLzpm5YfMqssTmGrWy";
+
+        let address_list = AddressList(vec![SerializablePubkey::try_from(proof_address).unwrap()]);
+
+        validate_tree(setup.db_conn.as_ref(), tree).await;
+        get_multiple_new_address_proofs(
+            &setup.db_conn,
+            address_list,
+            setup.indexer.sql_batch_config,
+        )
+        .await
+        .unwrap();
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_gpa_filters(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use log::info;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner1 = SerializablePubkey::new_unique();
+    let mut state_update = StateUpdate::default();
+
+    let accounts = vec![Account {
+        hash: Hash::new_unique(),
+        address: Some(SerializablePubkey::new_unique()),
+        data: Some(AccountData {
+            discriminator: UnsignedInteger(0),
+            data: Base64String(vec![1, 2, 3]),
+            data_hash: Hash::new_unique(),
+        }),
+        owner: owner1,
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(10),
+        seq: UnsignedInteger(1),
+        slot_created: UnsignedInteger(0),
+    }];
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let res = setup
+        .api
+        .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+            owner: owner1,
+
+            dataSlice: Some(DataSlice {
+                offset: 0,
+                length: 2,
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .value;
+
+    assert!(res.items[0].data.clone().unwrap().data.0 == vec![1, 2]);
+
+    let filters_and_expected_results = vec![
+        ((vec![1, 2], 0), 1),
+        ((vec![1, 2, 3], 0), 1),
+        ((vec![2, 3], 1), 1),
+        ((vec![2, 3], 0), 0),
+    ];
+
+    for (filter, expected_count) in filters_and_expected_results {
+        info!("Filter: {:?}", filter);
+        let res = setup
+            .api
+            .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+                owner: owner1,
+                filters: vec![FilterSelector {
+                    memcmp: Some(Memcmp {
+                        offset: filter.1,
+                        bytes: Base58String(filter.0.iter().map(|x| *x as u8).collect()),
+                    }),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .value;
+
+        assert_eq!(res.items.len(), expected_count);
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_compressed_account_data(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner = SerializablePubkey::new_unique();
+    let data = (0..1000).map(|i| (i % 10) as u8).collect::<Vec<u8>>();
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: Some(SerializablePubkey::new_unique()),
+        data: Some(AccountData {
+            discriminator: UnsignedInteger(1),
+            data: Base64String(data.clone()),
+            data_hash: Hash::new_unique(),
+        }),
+        owner,
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(0),
+    };
+    let mut state_update = StateUpdate::default();
+    state_update.out_accounts.push(account.clone());
+    set_account_data_compression(true);
+    let result = persist_state_update_using_connection(&setup.db_conn, state_update).await;
+    set_account_data_compression(false);
+    result.unwrap();
+
+    let model = accounts::Entity::find_by_id(account.hash.to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(model.data_compressed);
+    assert!(model.data.unwrap().len() < data.len());
+
+    let res = setup
+        .api
+        .get_compressed_account(CompressedAccountRequest {
+            hash: Some(account.hash.clone()),
+            address: None,
+        })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(res, Some(account.clone()));
+
+    let res = setup
+        .api
+        .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+            owner,
+            dataSlice: Some(DataSlice {
+                offset: 995,
+                length: 10,
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(
+        res.items[0].data.clone().unwrap().data.0,
+        vec![5, 6, 7, 8, 9]
+    );
+
+    let filters_and_expected_results = vec![
+        ((vec![2, 3], 12), 1),
+        ((vec![2, 3], 13), 0),
+        ((vec![9, 0], 999), 0),
+    ];
+    for ((bytes, offset), expected_count) in filters_and_expected_results {
+        let res = setup
+            .api
+            .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+                owner,
+                filters: vec![FilterSelector {
+                    memcmp: Some(Memcmp {
+                        offset,
+                        bytes: Base58String(bytes),
+                    }),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .value;
+        assert_eq!(res.items.len(), expected_count);
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_deduplicated_account_data(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner = SerializablePubkey::new_unique();
+    let shared_data = (0..100).collect::<Vec<u8>>();
+    let accounts = (0..3)
+        .map(|i| Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(1),
+                // The last account's data is too small to be deduplicated.
+                data: Base64String(if i < 2 {
+                    shared_data.clone()
+                } else {
+                    vec![1, 2, 3]
+                }),
+                data_hash: Hash::new_unique(),
+            }),
+            owner,
+            lamports: U64String(1000),
+            tree: SerializablePubkey::new_unique(),
+            leaf_index: UnsignedInteger(i),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        })
+        .collect::<Vec<_>>();
+    let state_update = StateUpdate {
+        out_accounts: accounts.clone(),
+        ..Default::default()
+    };
+    set_account_data_deduplication(true);
+    set_account_data_compression(true);
+    let result = persist_state_update_using_connection(&setup.db_conn, state_update).await;
+    set_account_data_deduplication(false);
+    set_account_data_compression(false);
+    result.unwrap();
+
+    let shared_data_models = account_data::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(shared_data_models.len(), 1);
+    let models = accounts::Entity::find()
+        .filter(accounts::Column::Owner.eq(owner.to_bytes_vec()))
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    let deduplicated = models
+        .iter()
+        .filter(|model| model.data.is_none() && model.data_ref.is_some())
+        .count();
+    assert_eq!(deduplicated, 2);
+
+    let mut expected_accounts = accounts.clone();
+    let res = setup
+        .api
+        .get_multiple_compressed_accounts(GetMultipleCompressedAccountsRequest {
+            addresses: None,
+            hashes: Some(accounts.iter().map(|x| x.hash.clone()).collect()),
+        })
+        .await
+        .unwrap()
+        .value;
+    assert_account_response_list_matches_input(
+        &mut res.items.iter().map(|x| x.clone().unwrap()).collect(),
+        &mut expected_accounts,
+    );
+
+    let res = setup
+        .api
+        .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+            owner,
+            filters: vec![FilterSelector {
+                memcmp: Some(Memcmp {
+                    offset: 1,
+                    bytes: Base58String(vec![1, 2]),
+                }),
+            }],
+            dataSlice: Some(DataSlice {
+                offset: 0,
+                length: 3,
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(res.items.len(), 2);
+    for item in res.items {
+        assert_eq!(item.data.unwrap().data.0, vec![0, 1, 2]);
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_persisted_state_trees_multiple_cases(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let tree = SerializablePubkey::new_unique();
+    let tree_height = 10;
+
+    info!("Test case 1: Sequential leaf nodes");
+    let leaf_nodes_1 = create_leaf_nodes(tree, 0..5, |i| i);
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_1, tree_height).await;
+
+    info!("Test case 2: Sequential leaf nodes with random seq order");
+    let leaf_nodes_2 = create_leaf_nodes(tree, 0..5, |i| vec![4, 2, 0, 1, 3][i as usize]);
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_2, tree_height).await;
+
+    info!("Test case 3: Duplicate leaf indices");
+    let leaf_nodes_3 = create_leaf_nodes(tree, vec![1, 1, 2, 2, 3, 2].into_iter(), |_| {
+        let mut rng = rand::thread_rng();
+        rng.gen_range(0..1000)
+    });
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_3, tree_height).await;
+
+    info!("Test case 4: Large gaps in sequence numbers");
+    let leaf_nodes_4 = create_leaf_nodes(tree, 0..5, |i| i * 1000);
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_4, tree_height).await;
+
+    info!("Test case 7: Very large tree");
+    let large_tree_height = 20;
+    let leaf_nodes_7 = create_leaf_nodes(tree, 0..20, |i| i);
+    test_persist_and_verify(
+        name.clone(),
+        db_backend,
+        tree,
+        leaf_nodes_7,
+        large_tree_height,
+    )
+    .await;
+
+    info!("Test case 10: Random sequence numbers");
+    use log::info;
+    use rand::Rng;
+    let leaf_nodes_10 = create_leaf_nodes(tree, 0..10, |_| {
+        let mut rng = rand::thread_rng();
+        rng.gen_range(0..1000)
+    });
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_10, tree_height).await;
+
+    info!("Test case 11: Maximum sequence number (u32::MAX)");
+    let leaf_nodes_11 = create_leaf_nodes(tree, 0..5, |i| if i == 2 { u32::MAX as u64 } else { i });
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_11, tree_height).await;
+
+    info!("Test case 12: Updating all leaves in reverse order");
+    let leaf_nodes_13b = create_leaf_nodes(tree, (0..=10).rev(), |i| i + 100);
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_13b, tree_height).await;
+
+    info!("Test case 13: Inserting leaves with all zero hashes");
+    let leaf_nodes_13 = (0..5)
+        .map(|i| LeafNode {
+            hash: Hash::try_from(vec![0; 32]).unwrap(),
+            leaf_index: i,
+            tree,
+            seq: i,
+        })
+        .collect::<Vec<_>>();
+    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_13, tree_height).await;
+}
+
+fn create_leaf_nodes<F>(
+    tree: SerializablePubkey,
+    range: impl Iterator<Item = u64>,
+    seq_fn: F,
+) -> Vec<LeafNode>
+where
+    F: Fn(u64) -> u64,
+{
+    range
+        .map(|i| LeafNode {
+            hash: Hash::new_unique(),
+            leaf_index: i as u32,
+            tree,
+            seq: seq_fn(i) as u32,
+        })
+        .collect()
+}
+
+async fn test_persist_and_verify(
+    name: String,
+    db_backend: DatabaseBackend,
+    tree: SerializablePubkey,
+    mut leaf_nodes: Vec<LeafNode>,
+    tree_height: u32,
+) {
+    for one_at_a_time in [true, false] {
+        let setup = setup(name.clone(), db_backend).await;
+
+        let txn = setup.db_conn.as_ref().begin().await.unwrap();
+        if one_at_a_time {
+            for leaf_node in leaf_nodes.clone() {
+                persist_leaf_nodes(
+                    &txn,
+                    setup.indexer.sql_batch_config,
+                    vec![leaf_node],
+                    tree_height,
+                )
+                .await
+                .unwrap();
+            }
+        } else {
+            persist_leaf_nodes(
+                &txn,
+                setup.indexer.sql_batch_config,
+                leaf_nodes.clone(),
+                tree_height,
+            )
+            .await
+            .unwrap();
+        }
+        txn.commit().await.unwrap();
+
+        let mut leaf_node_indexes = vec![];
+        let mut de_duplicated_leaf_nodes = vec![];
+
+        leaf_nodes.sort_by_key(|x| x.seq);
+        for leaf_node in leaf_nodes.into_iter().rev() {
+            if !leaf_node_indexes.contains(&leaf_node.leaf_index) {
+                leaf_node_indexes.push(leaf_node.leaf_index);
+                de_duplicated_leaf_nodes.push(leaf_node);
+            }
+        }
+
+        let state_tree_models = state_trees::Entity::find()
+            .filter(state_trees::Column::Level.eq(0))
+            .all(setup.db_conn.as_ref())
+            .await
+            .unwrap();
+
+        let hash_index_and_seq_tuples = state_tree_models
+            .iter()
+            .map(|x| {
+                (
+                    Hash::try_from(x.hash.clone()).unwrap(),
+                    x.leaf_idx.unwrap_or(0) as u64,
+                    x.seq as u64,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        println!(
+            "hash_index_and_seq_tuples: {:#?}",
+            hash_index_and_seq_tuples
+        );
+        leaf_nodes = de_duplicated_leaf_nodes;
+        let proofs = get_multiple_compressed_leaf_proofs(
+            &setup.db_conn.begin().await.unwrap(),
+            setup.indexer.sql_batch_config,
+            &setup.indexer.in_memory_trees,
+            leaf_nodes
+                .iter()
+                .map(|x| Hash::try_from(x.hash.clone()).unwrap())
+                .collect(),
+        )
+        .await
+        .unwrap();
+
+        let proof_hashes: HashSet<Hash> = proofs.iter().map(|x| x.hash.clone()).collect();
+        let leaf_hashes: HashSet<Hash> = leaf_nodes.iter().map(|x| x.hash.clone()).collect();
+        assert_eq!(
+            proof_hashes, leaf_hashes,
+            "Proof hashes should match leaf hashes"
+        );
+
+        let max_seq = leaf_nodes.iter().map(|x| x.seq).max().unwrap_or(0) as u64;
+
+        for proof in proofs {
+            assert_eq!(proof.merkleTree, tree, "Merkle tree should match");
+            assert_eq!(
+                max_seq, proof.rootSeq,
+                "Root sequence should be the maximum sequence number"
+            );
+            assert_eq!(
+                tree_height - 1,
+                proof.proof.len() as u32,
+                "Proof length should be tree height minus one"
+            );
+        }
+        validate_tree(&setup.db_conn, tree).await;
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_update_indexed_merkle_tree(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use itertools::Itertools;
+    use photon_indexer::ingester::parser::{
+        indexer_events::RawIndexedElement, state_update::IndexedTreeLeafUpdate,
+    };
+    let name = trim_test_name(function_name!());
+    let setup = setup(name.clone(), db_backend).await;
+    let tree = Pubkey::new_unique();
+    let tree_height = 10;
+    let index = 1;
+    let value = [1; 32];
+    let index_element_1 = RawIndexedElement {
+        value,
+        next_index: 3,
+        next_value: [2; 32],
+        index,
+    };
+    let index_element_2 = RawIndexedElement {
+        value,
+        next_index: 4,
+        next_value: [7; 32],
+        index,
+    };
+    let paramaeters = vec![(index_element_1, 0), (index_element_2, 1)];
+    for permutation in paramaeters.iter().permutations(2) {
+        let txn = setup.db_conn.as_ref().begin().await.unwrap();
+        for (indexed_element, seq) in permutation {
+            let mut indexed_leaf_updates = HashMap::new();
+            indexed_leaf_updates.insert(
+                (tree, index as u64),
+                IndexedTreeLeafUpdate {
+                    tree,
+                    leaf: indexed_element.clone(),
+                    hash: Hash::new_unique().into(), // HACK: We don't care about the hash
+                    seq: *seq as u64,
+                },
+            );
+            update_indexed_tree_leaves(
+                &txn,
+                setup.indexer.sql_batch_config,
+                indexed_leaf_updates,
+                tree_height,
+            )
+            .await
+            .unwrap();
+        }
+        txn.commit().await.unwrap();
+        let tree_model = indexed_trees::Entity::find()
+            .filter(
+                indexed_trees::Column::Tree
+                    .eq(tree.to_bytes().to_vec())
+                    .and(indexed_trees::Column::LeafIndex.eq(1)),
+            )
+            .one(setup.db_conn.as_ref())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tree_model.value, index_element_2.value);
+        assert_eq!(tree_model.next_value, index_element_2.next_value);
+        assert_eq!(tree_model.next_index, index_element_2.next_index as i64);
+        assert_eq!(tree_model.seq, 1 as i64);
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_indexer_syncing_error(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::api::error::SyncProgress;
+    use photon_indexer::ingester::indexer::{update_backfill_progress, BackfillProgress};
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1000,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+    let request = || GetCompressedAccountsByOwnerRequest {
+        owner: SerializablePubkey::new_unique(),
+        ..Default::default()
+    };
+
+    update_backfill_progress(Some(BackfillProgress {
+        current_slot: 1000,
+        target_slot: 5000,
+    }));
+    let err = setup
+        .api
+        .get_compressed_accounts_by_owner(request())
+        .await
+        .unwrap_err();
+    let slot = setup.api.get_indexer_slot().await;
+    update_backfill_progress(None);
+    assert_eq!(slot.unwrap().0, 1000);
+    assert_eq!(
+        err,
+        PhotonApiError::IndexerSyncing {
+            current_slot: 1000,
+            target_slot: 5000,
+        }
+    );
+    let data = err.data();
+    assert_eq!(data.kind, "indexer_syncing");
+    assert!(data.retryable);
+    assert_eq!(
+        data.progress,
+        Some(SyncProgress {
+            current_slot: 1000,
+            target_slot: 5000,
+        })
+    );
+
+    // Being a few slots behind at the end of the backfill is not reported.
+    update_backfill_progress(Some(BackfillProgress {
+        current_slot: 4990,
+        target_slot: 5000,
+    }));
+    let result = setup.api.get_compressed_accounts_by_owner(request()).await;
+    update_backfill_progress(None);
+    assert!(result.unwrap().value.items.is_empty());
+
+    let result = setup.api.get_compressed_accounts_by_owner(request()).await;
+    assert!(result.unwrap().value.items.is_empty());
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_token_extensions(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::common::typedefs::token_data::{MetadataPointer, TokenExtensions};
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+
+    let tlv_entry = |extension_type: u16, value: &[u8]| {
+        [
+            extension_type.to_le_bytes().as_slice(),
+            &(value.len() as u16).to_le_bytes(),
+            value,
+        ]
+        .concat()
+    };
+    let permanent_delegate = SerializablePubkey::new_unique();
+    let metadata_address = SerializablePubkey::new_unique();
+    let tlv = [
+        tlv_entry(2, &500_u64.to_le_bytes()),
+        // Unknown extensions are skipped.
+        tlv_entry(7, &[]),
+        tlv_entry(12, &permanent_delegate.to_bytes_vec()),
+        // A metadata pointer without authority.
+        tlv_entry(18, &[[0; 32], metadata_address.0.to_bytes()].concat()),
+    ]
+    .concat();
+    let owner = SerializablePubkey::new_unique();
+    let token_data = TokenData {
+        mint: SerializablePubkey::new_unique(),
+        owner,
+        amount: U64String(100),
+        delegate: None,
+        state: AccountState::initialized,
+        tlv: Some(Base64String(tlv)),
+        extensions: None,
+    };
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: None,
+        data: Some(AccountData {
+            discriminator: UnsignedInteger(2),
+            data: Base64String(to_vec(&token_data).unwrap()),
+            data_hash: Hash::new_unique(),
+        }),
+        owner: SerializablePubkey::from(
+            Pubkey::from_str("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m").unwrap(),
+        ),
+        lamports: U64String(0),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(1),
+    };
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = vec![account];
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let response = setup
+        .api
+        .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+            owner,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(response.value.items.len(), 1);
+    assert_eq!(
+        response.value.items[0].token_data.extensions,
+        Some(TokenExtensions {
+            withheld_transfer_fee: Some(U64String(500)),
+            permanent_delegate: Some(permanent_delegate),
+            metadata_pointer: Some(MetadataPointer {
+                authority: None,
+                metadata_address: Some(metadata_address),
+            }),
+        })
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_get_compressed_accounts_by_owner_and_discriminator(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner = SerializablePubkey::new_unique();
+    let account = |discriminator: Option<u64>| Account {
+        hash: Hash::new_unique(),
+        address: None,
+        data: discriminator.map(|discriminator| AccountData {
+            discriminator: UnsignedInteger(discriminator),
+            data: Base64String(vec![1, 2, 3]),
+            data_hash: Hash::new_unique(),
+        }),
+        owner,
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(0),
+    };
+    let accounts = vec![
+        account(Some(1)),
+        account(Some(2)),
+        account(None),
+        account(Some(2)),
+    ];
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    let mut items = setup
+        .api
+        .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+            owner,
+            discriminator: Some(UnsignedInteger(2)),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .value
+        .items;
+    let mut expected = vec![accounts[1].clone(), accounts[3].clone()];
+    assert_account_response_list_matches_input(&mut items, &mut expected);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_compressed_token_tlv(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+
+    // A transfer fee extension followed by an unknown extension with a compressible payload.
+    let payload = (0..1000).map(|i| (i % 10) as u8).collect::<Vec<u8>>();
+    let tlv = [
+        2_u16.to_le_bytes().as_slice(),
+        &8_u16.to_le_bytes(),
+        &500_u64.to_le_bytes(),
+        &7_u16.to_le_bytes(),
+        &(payload.len() as u16).to_le_bytes(),
+        &payload,
+    ]
+    .concat();
+    let owner = SerializablePubkey::new_unique();
+    let token_data = TokenData {
+        mint: SerializablePubkey::new_unique(),
+        owner,
+        amount: U64String(100),
+        delegate: None,
+        state: AccountState::initialized,
+        tlv: Some(Base64String(tlv.clone())),
+        extensions: None,
+    };
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: None,
+        data: Some(AccountData {
+            discriminator: UnsignedInteger(2),
+            data: Base64String(to_vec(&token_data).unwrap()),
+            data_hash: Hash::new_unique(),
+        }),
+        owner: SerializablePubkey::from(
+            Pubkey::from_str("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m").unwrap(),
+        ),
+        lamports: U64String(0),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(1),
+    };
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = vec![account.clone()];
+    set_account_data_compression(true);
+    let result = persist_state_update_using_connection(&setup.db_conn, state_update).await;
+    set_account_data_compression(false);
+    result.unwrap();
+
+    let model = token_accounts::Entity::find_by_id(account.hash.to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(model.tlv_compressed);
+    assert!(model.tlv.unwrap().len() < tlv.len());
+
+    let response = setup
+        .api
+        .get_compressed_token_accounts_by_owner(GetCompressedTokenAccountsByOwner {
+            owner,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(response.value.items.len(), 1);
+    let token_data = &response.value.items[0].token_data;
+    assert_eq!(token_data.tlv, Some(Base64String(tlv)));
+    assert_eq!(
+        token_data
+            .extensions
+            .as_ref()
+            .unwrap()
+            .withheld_transfer_fee,
+        Some(U64String(500))
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_compression_stats(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let event = |is_compress: bool, lamports: u64| PublicTransactionEvent {
+        is_compress,
+        compression_lamports: Some(lamports),
+        ..Default::default()
+    };
+    let block = |slot: u64, events: Vec<PublicTransactionEvent>| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: events
+            .iter()
+            .map(|event| compression_event_transaction(to_vec(event).unwrap()))
+            .collect(),
+    };
+    index_block(
+        &setup.indexer,
+        &block(1, vec![event(true, 5000), event(true, 3000)]),
+    )
+    .await
+    .unwrap();
+    index_block(&setup.indexer, &block(2, vec![event(false, 2000)]))
+        .await
+        .unwrap();
+
+    let stats = setup
+        .api
+        .get_compression_stats(GetCompressionStatsRequest::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        stats.value,
+        CompressionStats {
+            compressed_lamports: U64String(8000),
+            decompressed_lamports: U64String(2000),
+        }
+    );
+
+    let stats = setup
+        .api
+        .get_compression_stats(GetCompressionStatsRequest {
+            start_slot: Some(UnsignedInteger(2)),
+            end_slot: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        stats.value,
+        CompressionStats {
+            compressed_lamports: U64String(0),
+            decompressed_lamports: U64String(2000),
+        }
+    );
+
+    let stats = setup
+        .api
+        .get_compression_stats(GetCompressionStatsRequest {
+            start_slot: Some(UnsignedInteger(3)),
+            end_slot: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(stats.value, CompressionStats::default());
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_persist_large_state_update(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    // HACK: We index a block so that API methods can fetch the current slot.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    // Binding every value of these accounts as a separate parameter would exceed the parameter
+    // limit of a single statement many times over.
+    let num_accounts = 6000;
+    let owner = SerializablePubkey::new_unique();
+    let tree = SerializablePubkey::new_unique();
+    let signature = Signature::new_unique();
+    let accounts = (0..num_accounts)
+        .map(|i| Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(98_765_432_109),
+                data: Base64String(vec![i as u8; 32]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner,
+            lamports: U64String(1000 + i),
+            tree,
+            leaf_index: UnsignedInteger(i),
+            seq: UnsignedInteger(i),
+            slot_created: UnsignedInteger(0),
+        })
+        .collect::<Vec<_>>();
+    let mut state_update = StateUpdate::new();
+    state_update.transactions.insert(Transaction {
+        signature,
+        slot: 0,
+        uses_compression: true,
+        error: None,
+        compressed_lamports: 123_456_789_012,
+        decompressed_lamports: 0,
+    });
+    state_update.account_transactions = accounts
+        .iter()
+        .map(|account| AccountTransaction {
+            hash: account.hash.clone(),
+            signature,
+            slot: 0,
+            spent: false,
+        })
+        .collect();
+    state_update.out_accounts = accounts.clone();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        accounts::Entity::find()
+            .count(setup.db_conn.as_ref())
+            .await
+            .unwrap(),
+        num_accounts
+    );
+    assert_eq!(
+        account_transactions::Entity::find()
+            .count(setup.db_conn.as_ref())
+            .await
+            .unwrap(),
+        num_accounts
+    );
+    let balance = setup
+        .api
+        .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest { owner })
+        .await
+        .unwrap();
+    assert_eq!(
+        balance.value,
+        U64String(accounts.iter().map(|account| account.lamports.0).sum())
+    );
+    let stats = setup
+        .api
+        .get_compression_stats(GetCompressionStatsRequest::default())
+        .await
+        .unwrap();
+    assert_eq!(stats.value.compressed_lamports, U64String(123_456_789_012));
+    for account in [&accounts[0], &accounts[num_accounts as usize - 1]] {
+        let persisted_account = setup
+            .api
+            .get_compressed_account(CompressedAccountRequest {
+                address: None,
+                hash: Some(account.hash.clone()),
+            })
+            .await
+            .unwrap()
+            .value;
+        assert_eq!(persisted_account.as_ref(), Some(account));
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_sql_batch_size(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let batch_config = SqlBatchConfig::detect(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    let parameter_limit = batch_config.parameter_limit();
+    // The bundled SQLite library is newer than 3.32.0.
+    let expected_parameter_limit = match db_backend {
+        DatabaseBackend::Postgres => 65_535,
+        _ => 32_766,
+    };
+    assert_eq!(parameter_limit, expected_parameter_limit);
+    assert!(
+        batch_config.insert_batch_size::<accounts::Entity>() * accounts::Column::iter().count()
+            <= parameter_limit
+    );
+
+    // Statements of a few rows still persist the whole state update.
+    let owner = SerializablePubkey::new_unique();
+    let tree = SerializablePubkey::new_unique();
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts = (0..5)
+        .map(|i| Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner,
+            lamports: U64String(1000),
+            tree,
+            leaf_index: UnsignedInteger(i),
+            seq: UnsignedInteger(i),
+            slot_created: UnsignedInteger(0),
+        })
+        .collect();
+    let txn = setup.db_conn.begin().await.unwrap();
+    persist_state_update(
+        &txn,
+        batch_config.with_max_batch_size(Some(2)),
+        state_update,
+    )
+    .await
+    .unwrap();
+    txn.commit().await.unwrap();
+
+    assert_eq!(
+        accounts::Entity::find()
+            .count(setup.db_conn.as_ref())
+            .await
+            .unwrap(),
+        5
+    );
+    assert_eq!(
+        state_trees::Entity::find()
+            .filter(state_trees::Column::Tree.eq(tree.to_bytes_vec()))
+            .filter(state_trees::Column::Level.eq(0))
+            .count(setup.db_conn.as_ref())
+            .await
+            .unwrap(),
+        5
+    );
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_prune_spent_accounts(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let hashes = [Hash::new_unique(), Hash::new_unique(), Hash::new_unique()];
+    let create_event = PublicTransactionEvent {
+        output_compressed_account_hashes: hashes.iter().map(|hash| hash.0).collect(),
+        output_compressed_accounts: (0..3)
+            .map(|_| OutputCompressedAccountWithPackedContext {
+                compressed_account: CompressedAccount {
+                    owner,
+                    lamports: 1000,
+                    address: None,
+                    data: None,
+                },
+                merkle_tree_index: 0,
+            })
+            .collect(),
+        output_leaf_indices: vec![0, 1, 2],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: 0,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let spend_event = |hash: &Hash| PublicTransactionEvent {
+        input_compressed_account_hashes: vec![hash.0],
+        ..Default::default()
+    };
+    let block = |slot: u64, events: Vec<PublicTransactionEvent>| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: events
+            .iter()
+            .map(|event| compression_event_transaction(to_vec(event).unwrap()))
+            .collect(),
+    };
+    for block in [
+        block(1, vec![create_event]),
+        block(2, vec![spend_event(&hashes[0])]),
+        block(15, vec![spend_event(&hashes[1])]),
+        block(20, vec![]),
+    ] {
+        index_block(&setup.indexer, &block).await.unwrap();
+    }
+
+    // Only the account spent at slot 2 is spent for more than 10 slots.
+    let pruned_accounts =
+        prune_spent_accounts(setup.db_conn.as_ref(), setup.indexer.sql_batch_config, 10)
+            .await
+            .unwrap();
+    assert_eq!(pruned_accounts, 1);
+    let remaining_hashes = accounts::Entity::find()
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|account| account.hash)
+        .collect::<HashSet<_>>();
+    assert_eq!(
+        remaining_hashes,
+        HashSet::from([hashes[1].to_vec(), hashes[2].to_vec()])
+    );
+    assert!(account_transactions::Entity::find()
+        .filter(account_transactions::Column::Hash.eq(hashes[0].to_vec()))
+        .all(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .is_empty());
+    let balance = setup
+        .api
+        .get_compressed_balance_by_owner(GetCompressedBalanceByOwnerRequest {
+            owner: SerializablePubkey::from(owner),
+        })
+        .await
+        .unwrap();
+    assert_eq!(balance.value, U64String(1000));
+
+    // Pruning again does not find anything else.
+    assert_eq!(
+        prune_spent_accounts(setup.db_conn.as_ref(), setup.indexer.sql_batch_config, 10)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Accounts spent without an indexed spending transaction, e.g. after restoring a state
+    // snapshot, are pruned by the slot of their last transaction.
+    let mut state_update = StateUpdate::new();
+    state_update.in_accounts.insert(hashes[2].clone());
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    assert_eq!(
+        prune_spent_accounts(setup.db_conn.as_ref(), setup.indexer.sql_batch_config, 10)
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(accounts::Entity::find_by_id(hashes[2].to_vec())
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_read_replica_routing(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    // The replica only has to be a database of its own, so an in-memory SQLite database serves
+    // for either backend of the primary.
+    let replica = Arc::new(SqlxSqliteConnector::from_sqlx_sqlite_pool(
+        setup_sqllite_pool().await,
+    ));
+    Migrator::fresh(replica.as_ref()).await.unwrap();
+    let read_replicas = Arc::new(ReadReplicas::new(
+        setup.db_conn.clone(),
+        vec![replica.clone()],
+    ));
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    )
+    .with_read_replicas(read_replicas.clone());
+    let block = |slot: u64| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+
+    // The replica has indexed a slot that the primary has not, so reads that it serves see it.
+    index_block(&setup.indexer, &block(100)).await.unwrap();
+    index_block(&IndexerContext::new(replica.clone()), &block(110))
+        .await
+        .unwrap();
+    read_replicas.check_health().await;
+    assert_eq!(api.get_indexer_slot().await.unwrap(), UnsignedInteger(110));
+
+    // Reads fall back to the primary once the replica lags too far behind.
+    index_block(&setup.indexer, &block(200)).await.unwrap();
+    read_replicas.check_health().await;
+    assert_eq!(api.get_indexer_slot().await.unwrap(), UnsignedInteger(200));
+
+    // And return to the replica once it has caught up.
+    index_block(&IndexerContext::new(replica.clone()), &block(195))
+        .await
+        .unwrap();
+    read_replicas.check_health().await;
+    assert_eq!(api.get_indexer_slot().await.unwrap(), UnsignedInteger(195));
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_database_pool_timeout_errors(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let config = DatabasePoolConfig {
+        max_connections: 1,
+        acquire_timeout: Duration::from_millis(100),
+        idle_timeout: None,
+        statement_timeout: Some(Duration::from_millis(100)),
+    };
+    let db = match db_backend {
+        DatabaseBackend::Postgres => SqlxPostgresConnector::from_sqlx_postgres_pool(
+            setup_pg_pool_with_config(&std::env::var("TEST_DATABASE_URL").unwrap(), &config).await,
+        ),
+        DatabaseBackend::Sqlite => SqlxSqliteConnector::from_sqlx_sqlite_pool(
+            config
+                .pool_options()
+                .connect("sqlite::memory:")
+                .await
+                .unwrap(),
+        ),
+        _ => unimplemented!(),
+    };
+    let query = |sql: &str| Statement::from_string(db_backend, sql.to_string());
+
+    // The transaction holds the only connection of the pool.
+    let txn = db.begin().await.unwrap();
+    let error = db.execute(query("SELECT 1")).await.unwrap_err();
+    assert_eq!(PhotonApiError::from(error), PhotonApiError::DatabaseBusy);
+    assert!(PhotonApiError::DatabaseBusy.retryable());
+    txn.rollback().await.unwrap();
+    db.execute(query("SELECT 1")).await.unwrap();
+
+    if db_backend == DatabaseBackend::Postgres {
+        let error = db.execute(query("SELECT pg_sleep(1)")).await.unwrap_err();
+        assert_eq!(PhotonApiError::from(error), PhotonApiError::QueryTimeout);
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_api_cache_invalidation(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let mut setup = setup(name, db_backend).await;
+    let api_cache = ApiCache::new(100);
+    setup.indexer = setup.indexer.with_api_cache(api_cache.clone());
+    setup.api = setup.api.with_api_cache(api_cache);
+
+    let tree = Pubkey::new_unique();
+    let token_data = TokenData {
+        mint: SerializablePubkey::new_unique(),
+        owner: SerializablePubkey::new_unique(),
+        amount: U64String(100),
+        delegate: None,
+        state: AccountState::initialized,
+        tlv: None,
+        extensions: None,
+    };
+    let hash = Hash::new_unique();
+    let create_transaction = compression_event_transaction(
+        to_vec(&PublicTransactionEvent {
+            output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                compressed_account: CompressedAccount {
+                    owner: Pubkey::from_str("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m").unwrap(),
+                    lamports: 0,
+                    address: None,
+                    data: Some(CompressedAccountData {
+                        discriminator: [2, 0, 0, 0, 0, 0, 0, 0],
+                        data: to_vec(&token_data).unwrap(),
+                        data_hash: [0; 32],
+                    }),
+                },
+                merkle_tree_index: 0,
+            }],
+            output_compressed_account_hashes: vec![hash.0],
+            output_leaf_indices: vec![0],
+            sequence_numbers: vec![MerkleTreeSequenceNumber {
+                pubkey: tree,
+                seq: 0,
+            }],
+            pubkey_array: vec![tree],
+            ..Default::default()
+        })
+        .unwrap(),
+    );
+    let spend_transaction = compression_event_transaction(
+        to_vec(&PublicTransactionEvent {
+            input_compressed_account_hashes: vec![hash.0],
+            ..Default::default()
+        })
+        .unwrap(),
+    );
+    let block = |slot: u64, transaction| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: vec![transaction],
+    };
+    let account_request = CompressedAccountRequest {
+        hash: Some(hash.clone()),
+        address: None,
+    };
+    let balances_request = GetCompressedTokenBalancesByOwnerRequest {
+        owner: token_data.owner,
+        ..Default::default()
+    };
+
+    index_block(&setup.indexer, &block(1, create_transaction))
+        .await
+        .unwrap();
+    let account = setup
+        .api
+        .get_compressed_account(account_request.clone())
+        .await
+        .unwrap()
+        .value
+        .unwrap();
+    assert_eq!(account.lamports, U64String(0));
+    let token_balances = setup
+        .api
+        .get_compressed_token_balances_by_owner(balances_request.clone())
+        .await
+        .unwrap()
+        .value
+        .token_balances;
+    assert_eq!(token_balances[0].balance, U64String(100));
+    let balance = setup
+        .api
+        .get_compressed_token_account_balance(account_request.clone())
+        .await
+        .unwrap()
+        .value
+        .amount;
+    assert_eq!(balance, U64String(100));
+
+    // Writes that bypass the ingester are not seen while the results are cached.
+    accounts::Entity::update_many()
+        .col_expr(accounts::Column::Lamports, Expr::value(Decimal::from(1)))
+        .filter(accounts::Column::Hash.eq(hash.to_vec()))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    token_accounts::Entity::update_many()
+        .col_expr(
+            token_accounts::Column::Amount,
+            Expr::value(Decimal::from(1)),
+        )
+        .filter(token_accounts::Column::Hash.eq(hash.to_vec()))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    let account = setup
+        .api
+        .get_compressed_account(account_request.clone())
+        .await
+        .unwrap()
+        .value
+        .unwrap();
+    assert_eq!(account.lamports, U64String(0));
+    let balance = setup
+        .api
+        .get_compressed_token_account_balance(account_request.clone())
+        .await
+        .unwrap()
+        .value
+        .amount;
+    assert_eq!(balance, U64String(100));
+    // Spending subtracts the stored amount from the owner balance.
+    token_accounts::Entity::update_many()
+        .col_expr(
+            token_accounts::Column::Amount,
+            Expr::value(Decimal::from(100)),
+        )
+        .filter(token_accounts::Column::Hash.eq(hash.to_vec()))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+
+    // Spending the account invalidates every result that depends on it.
+    index_block(&setup.indexer, &block(2, spend_transaction))
+        .await
+        .unwrap();
+    let account = setup
+        .api
+        .get_compressed_account(account_request.clone())
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(account, None);
+    let token_balances = setup
+        .api
+        .get_compressed_token_balances_by_owner(balances_request)
+        .await
+        .unwrap()
+        .value
+        .token_balances;
+    assert!(token_balances
+        .iter()
+        .all(|token_balance| token_balance.balance == U64String(0)));
+    let balance = setup
+        .api
+        .get_compressed_token_account_balance(account_request)
+        .await
+        .unwrap()
+        .value
+        .amount;
+    assert_eq!(balance, U64String(0));
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_proof_cache(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    set_proof_cache_capacity(100);
+
+    let tree = SerializablePubkey::new_unique();
+    let tree_height = 5;
+    let leaf_nodes = (0..3)
+        .map(|i| LeafNode {
+            hash: Hash::new_unique(),
+            leaf_index: i,
+            tree,
+            seq: i,
+        })
+        .collect::<Vec<_>>();
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(
+        &txn,
+        setup.indexer.sql_batch_config,
+        leaf_nodes[..2].to_vec(),
+        tree_height,
+    )
+    .await
+    .unwrap();
+    txn.commit().await.unwrap();
+
+    let hashes = vec![leaf_nodes[0].hash.clone()];
+    let proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        hashes.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(proof[0].rootSeq, 1);
+    assert_eq!(proof[0].proof[0], leaf_nodes[1].hash);
+
+    // Writes that do not advance the root are not seen while the proof is cached.
+    let sibling_hash = Hash::new_unique();
+    state_trees::Entity::update_many()
+        .col_expr(
+            state_trees::Column::Hash,
+            Expr::value(sibling_hash.to_vec()),
+        )
+        .filter(state_trees::Column::Hash.eq(leaf_nodes[1].hash.to_vec()))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    let cached_proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        hashes.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(cached_proof, proof);
+    state_trees::Entity::update_many()
+        .col_expr(
+            state_trees::Column::Hash,
+            Expr::value(leaf_nodes[1].hash.to_vec()),
+        )
+        .filter(state_trees::Column::Hash.eq(sibling_hash.to_vec()))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(
+        &txn,
+        setup.indexer.sql_batch_config,
+        leaf_nodes[2..].to_vec(),
+        tree_height,
+    )
+    .await
+    .unwrap();
+    txn.commit().await.unwrap();
+    let advanced_proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        hashes,
+    )
+    .await
+    .unwrap();
+    assert_eq!(advanced_proof[0].rootSeq, 2);
+    assert_eq!(advanced_proof[0].proof[0], leaf_nodes[1].hash);
+    assert_ne!(advanced_proof[0].root, proof[0].root);
+
+    set_proof_cache_capacity(0);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_in_memory_trees(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let mut setup = setup(name, db_backend).await;
+    setup.indexer = setup.indexer.with_in_memory_trees(InMemoryTrees::new(1000));
+
+    let tree = Pubkey::new_unique();
+    let hashes = (0..3).map(|_| Hash::new_unique()).collect::<Vec<_>>();
+    let block = |slot: u64, leaf_indices: Vec<u32>| {
+        let event = PublicTransactionEvent {
+            output_compressed_accounts: leaf_indices
+                .iter()
+                .map(|_| OutputCompressedAccountWithPackedContext {
+                    compressed_account: CompressedAccount {
+                        owner: Pubkey::new_unique(),
+                        lamports: 1000,
+                        address: None,
+                        data: None,
+                    },
+                    merkle_tree_index: 0,
+                })
+                .collect(),
+            output_compressed_account_hashes: leaf_indices
+                .iter()
+                .map(|leaf_index| hashes[*leaf_index as usize].0)
+                .collect(),
+            output_leaf_indices: leaf_indices.clone(),
+            sequence_numbers: vec![MerkleTreeSequenceNumber {
+                pubkey: tree,
+                seq: leaf_indices[0] as u64,
+            }],
+            pubkey_array: vec![tree],
+            ..Default::default()
+        };
+        BlockInfo {
+            metadata: BlockMetadata {
+                slot,
+                parent_slot: slot - 1,
+                ..Default::default()
+            },
+            transactions: vec![compression_event_transaction(to_vec(&event).unwrap())],
+        }
+    };
+    let request = vec![hashes[0].clone()];
+
+    index_block(&setup.indexer, &block(1, vec![0, 1]))
+        .await
+        .unwrap();
+    let proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        request.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(proof[0].proof[0], hashes[1]);
+
+    // Proofs are read from memory, so writes that bypass the indexer are not seen.
+    state_trees::Entity::update_many()
+        .col_expr(
+            state_trees::Column::Hash,
+            Expr::value(Hash::new_unique().to_vec()),
+        )
+        .filter(state_trees::Column::Hash.eq(hashes[1].to_vec()))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    let in_memory_proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        request.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(in_memory_proof, proof);
+    state_trees::Entity::update_many()
+        .col_expr(state_trees::Column::Hash, Expr::value(hashes[1].to_vec()))
+        .filter(
+            state_trees::Column::Tree
+                .eq(tree.to_bytes().to_vec())
+                .and(state_trees::Column::Level.eq(0))
+                .and(state_trees::Column::LeafIdx.eq(1)),
+        )
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+
+    // The indexer updates the nodes that the new leaf changes.
+    index_block(&setup.indexer, &block(2, vec![2]))
+        .await
+        .unwrap();
+    let in_memory_proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        request.clone(),
+    )
+    .await
+    .unwrap();
+    let expected_proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &InMemoryTrees::default(),
+        request,
+    )
+    .await
+    .unwrap();
+    assert_eq!(in_memory_proof, expected_proof);
+    assert_ne!(in_memory_proof[0].root, proof[0].root);
+    assert_eq!(in_memory_proof[0].rootSeq, 2);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_recompute_tree_roots(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let tree = SerializablePubkey::new_unique();
+    // Sparse leaves, so that empty subtrees are hashed as well.
+    let leaf_nodes = [0, 1, 2, 5, 13]
+        .into_iter()
+        .enumerate()
+        .map(|(seq, leaf_index)| LeafNode {
+            hash: Hash::new_unique(),
+            leaf_index,
+            tree,
+            seq: seq as u32,
+        })
+        .collect::<Vec<_>>();
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(&txn, setup.indexer.sql_batch_config, leaf_nodes.clone(), 5)
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    let tree_roots = recompute_tree_roots(setup.db_conn.as_ref()).await.unwrap();
+    assert_eq!(tree_roots.len(), 1);
+    assert_eq!(SerializablePubkey::from(tree_roots[0].tree), tree);
+    assert_eq!(tree_roots[0].recomputed_root, tree_roots[0].persisted_root);
+
+    // A leaf that diverges from the persisted path nodes changes the recomputed root.
+    state_trees::Entity::update_many()
+        .col_expr(
+            state_trees::Column::Hash,
+            Expr::value(Hash::new_unique().to_vec()),
+        )
+        .filter(state_trees::Column::Hash.eq(leaf_nodes[3].hash.to_vec()))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    let tree_roots = recompute_tree_roots(setup.db_conn.as_ref()).await.unwrap();
+    assert_ne!(tree_roots[0].recomputed_root, tree_roots[0].persisted_root);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_historical_proofs(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    set_proof_history_window(100);
+
+    let tree = Pubkey::new_unique();
+    let hashes = (0..4).map(|_| Hash::new_unique()).collect::<Vec<_>>();
+    // Appends `hashes[i]` at leaf `leaf_index` for every `(i, leaf_index)` of `outputs`.
+    let block = |slot: u64, seq: u64, outputs: Vec<(usize, u32)>| {
+        let event = PublicTransactionEvent {
+            output_compressed_accounts: outputs
+                .iter()
+                .map(|_| OutputCompressedAccountWithPackedContext {
+                    compressed_account: CompressedAccount {
+                        owner: Pubkey::new_unique(),
+                        lamports: 1000,
+                        address: None,
+                        data: None,
+                    },
+                    merkle_tree_index: 0,
+                })
+                .collect(),
+            output_compressed_account_hashes: outputs
+                .iter()
+                .map(|(hash_index, _)| hashes[*hash_index].0)
+                .collect(),
+            output_leaf_indices: outputs.iter().map(|(_, leaf_index)| *leaf_index).collect(),
+            sequence_numbers: vec![MerkleTreeSequenceNumber { pubkey: tree, seq }],
+            pubkey_array: vec![tree],
+            ..Default::default()
+        };
+        BlockInfo {
+            metadata: BlockMetadata {
+                slot,
+                parent_slot: slot - 1,
+                ..Default::default()
+            },
+            transactions: vec![compression_event_transaction(to_vec(&event).unwrap())],
+        }
+    };
+    let historical_proof = |hash: Hash, root_seq: u64| {
+        get_historical_compressed_account_proof(
+            setup.db_conn.as_ref(),
+            GetHistoricalCompressedAccountProofRequest { hash, root_seq },
+            setup.indexer.sql_batch_config,
+        )
+    };
 
-        let mut mint_to_balance: HashMap<SerializablePubkey, u64> = HashMap::new();
+    index_block(&setup.indexer, &block(1, 0, vec![(0, 0), (1, 1)]))
+        .await
+        .unwrap();
+    let proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        vec![hashes[0].clone()],
+    )
+    .await
+    .unwrap()
+    .remove(0);
+    assert_eq!(proof.rootSeq, 1);
 
-        for token_account in paginated_res.iter() {
-            let balance = mint_to_balance
-                .entry(token_account.token_data.mint)
-                .or_insert(0);
-            *balance += token_account.token_data.amount.0;
-        }
-        for (mint, balance) in mint_to_balance.iter() {
-            let request = GetCompressedTokenBalancesByOwnerRequest {
-                owner,
-                mint: Some(*mint),
-                ..Default::default()
-            };
-            let res = setup
-                .api
-                .get_compressed_token_balances_by_owner(request.clone())
-                .await
-                .unwrap()
-                .value;
-            assert_eq!(res.token_balances[0].balance.0, *balance);
+    // Overwrites the first leaf.
+    index_block(&setup.indexer, &block(2, 2, vec![(2, 0)]))
+        .await
+        .unwrap();
+    assert_eq!(
+        historical_proof(hashes[0].clone(), 1).await.unwrap().value,
+        proof
+    );
+    // The second leaf was still empty at the first root.
+    let first_proof = historical_proof(hashes[0].clone(), 0).await.unwrap().value;
+    assert_eq!(first_proof.proof[0], Hash::from(ZERO_BYTES[0]));
+    assert!(matches!(
+        historical_proof(hashes[0].clone(), 2).await,
+        Err(PhotonApiError::RecordNotFound(_))
+    ));
+    assert!(matches!(
+        historical_proof(hashes[2].clone(), 3).await,
+        Err(PhotonApiError::ValidationError(_))
+    ));
+    let current_proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        vec![hashes[2].clone()],
+    )
+    .await
+    .unwrap()
+    .remove(0);
+    assert_eq!(
+        historical_proof(hashes[2].clone(), 2).await.unwrap().value,
+        current_proof
+    );
 
-            let res_v2 = setup
-                .api
-                .get_compressed_token_balances_by_owner_v2(request)
-                .await
-                .unwrap()
-                .value;
-            assert_eq!(res_v2.items, res.token_balances);
-        }
+    // Versions that fall out of the window are pruned.
+    set_proof_history_window(1);
+    index_block(&setup.indexer, &block(3, 3, vec![(3, 1)]))
+        .await
+        .unwrap();
+    assert!(historical_proof(hashes[2].clone(), 2).await.is_ok());
+    assert!(matches!(
+        historical_proof(hashes[0].clone(), 1).await,
+        Err(PhotonApiError::ValidationError(_))
+    ));
 
-        verify_response_matches_input_token_data(res.clone(), owner_tlv);
-        for token_account in res.items {
-            let request = CompressedAccountRequest {
-                address: None,
-                hash: Some(token_account.account.hash),
-            };
-            let balance = setup
-                .api
-                .get_compressed_token_account_balance(request)
-                .await
-                .unwrap()
-                .value;
+    set_proof_history_window(0);
+}
 
-            assert_eq!(balance.amount, token_account.token_data.amount);
-        }
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_zero_hash_proof_nodes(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let tree_height = 26;
+    let empty_subtrees = zero_hashes(tree_height).unwrap();
+    assert_eq!(empty_subtrees.len(), tree_height as usize);
+    for level in 1..empty_subtrees.len() {
+        assert_eq!(
+            compute_parent_hash(
+                empty_subtrees[level - 1].to_vec(),
+                empty_subtrees[level - 1].to_vec()
+            )
+            .unwrap(),
+            empty_subtrees[level].to_vec()
+        );
     }
-    for delegate in [delegate1, delegate2] {
-        let delegate_tlv = all_token_data
-            .clone()
-            .into_iter()
-            .filter(|x| x.token_data.delegate == Some(delegate))
-            .collect();
-        let res = setup
-            .api
-            .get_compressed_token_accounts_by_delegate(GetCompressedTokenAccountsByDelegate {
-                delegate,
-                ..Default::default()
-            })
-            .await
-            .unwrap()
-            .value;
-        let mut paginated_res = Vec::new();
-        let mut cursor = None;
-        loop {
-            let res = setup
-                .api
-                .get_compressed_token_accounts_by_delegate(GetCompressedTokenAccountsByDelegate {
-                    delegate,
-                    cursor: cursor.clone(),
-                    limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
-                    ..Default::default()
-                })
-                .await
-                .unwrap()
-                .value;
+    assert!(zero_hashes(MAX_HEIGHT as u32 + 2).is_err());
 
-            paginated_res.extend(res.items.clone());
-            cursor = res.cursor;
-            if cursor.is_none() {
-                break;
-            }
-        }
-        assert_eq!(paginated_res, res.items);
-        verify_response_matches_input_token_data(res, delegate_tlv);
+    // None of the siblings of a lone leaf are persisted.
+    let leaf_node = LeafNode {
+        hash: Hash::new_unique(),
+        leaf_index: 5,
+        tree: SerializablePubkey::new_unique(),
+        seq: 0,
+    };
+    let txn = setup.db_conn.as_ref().begin().await.unwrap();
+    persist_leaf_nodes(
+        &txn,
+        setup.indexer.sql_batch_config,
+        vec![leaf_node.clone()],
+        tree_height,
+    )
+    .await
+    .unwrap();
+    txn.commit().await.unwrap();
+    let proof = get_multiple_compressed_leaf_proofs_by_tree(
+        setup.db_conn.as_ref(),
+        setup.indexer.sql_batch_config,
+        &setup.indexer.in_memory_trees,
+        vec![leaf_node.hash],
+    )
+    .await
+    .unwrap()
+    .remove(0);
+    assert_eq!(
+        proof.proof,
+        empty_subtrees[..tree_height as usize - 1]
+            .iter()
+            .copied()
+            .map(Hash::from)
+            .collect::<Vec<_>>()
+    );
+}
+
+// A state tree account of height 20 with a canopy depth of 10 and 1400 changelog entries.
+fn state_tree_account() -> solana_sdk::account::Account {
+    use light_concurrent_merkle_tree::{
+        light_hasher::Poseidon, zero_copy::ConcurrentMerkleTreeZeroCopyMut, ConcurrentMerkleTree,
+    };
+    use light_sdk::state::MerkleTreeMetadata;
+
+    let (height, canopy_depth, changelog_size, roots_size) = (20, 10, 1400, 2400);
+    let offset = 8 + std::mem::size_of::<MerkleTreeMetadata>();
+    let mut data = vec![
+        0;
+        offset
+            + ConcurrentMerkleTree::<Poseidon, 20>::size_in_account(
+                height,
+                changelog_size,
+                roots_size,
+                canopy_depth
+            )
+    ];
+    ConcurrentMerkleTreeZeroCopyMut::<Poseidon, 20>::from_bytes_zero_copy_init(
+        &mut data[offset..],
+        height,
+        canopy_depth,
+        changelog_size,
+        roots_size,
+    )
+    .unwrap()
+    .init()
+    .unwrap();
+    solana_sdk::account::Account {
+        data,
+        ..Default::default()
     }
+}
 
-    for (mint, owner_to_balance) in mint_to_owner_to_balance.iter() {
-        let mut items = Vec::new();
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_tree_info_detection(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::ingester::parser::state_update::{PathNode, PathUpdate};
+    use photon_indexer::ingester::persist::persisted_state_tree::node_idx_to_leaf_idx;
+    use photon_indexer::ingester::tree_info::{
+        parse_merkle_tree_account, store_tree_info, tree_height, TreeInfo,
+    };
 
-        let mut cursor: Option<Base58String> = None;
-        loop {
-            let res = setup
-                .api
-                .get_compressed_mint_token_holders(GetCompressedMintTokenHoldersRequest {
-                    mint: mint.clone(),
-                    limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
-                    cursor,
-                })
-                .await
-                .unwrap()
-                .value;
-            for item in res.items.clone() {
-                items.push(item);
-            }
-            cursor = res.cursor;
-            if cursor.is_none() {
-                break;
-            }
-        }
-        // Assert that items are in descending balance order
-        for i in 1..items.len() {
-            assert!(items[i].balance.0 <= items[i - 1].balance.0);
-        }
-        assert_eq!(items.len(), owner_to_balance.len());
-        for item in items {
-            assert_eq!(item.balance.0, *owner_to_balance.get(&item.owner).unwrap());
-        }
-    }
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
 
-    let mut owner_to_balances = HashMap::new();
-    for (mint, balances) in mint_to_owner_to_balance.into_iter() {
-        for (owner, balance) in balances.into_iter() {
-            owner_to_balances
-                .entry(owner)
-                .or_insert(HashMap::new())
-                .insert(mint, balance);
+    let account = state_tree_account();
+    let (info, roots) = parse_merkle_tree_account(&account).unwrap();
+    assert_eq!(
+        info,
+        TreeInfo {
+            height: 20,
+            canopy_depth: 10,
+            changelog_size: 1400,
         }
-    }
-    for owner in owner_to_balances.keys() {
-        let mut cursor = None;
-        let mut mint_to_balance = HashMap::new();
+    );
+    assert_eq!(roots, vec![Hash::from(ZERO_BYTES[20])]);
 
-        loop {
-            let request = GetCompressedTokenBalancesByOwnerRequest {
-                owner: owner.clone(),
-                cursor,
-                limit: Some(photon_indexer::api::method::utils::Limit::new(1).unwrap()),
+    let tree = Pubkey::new_unique();
+    assert_eq!(tree_height(&tree), 27);
+    store_tree_info(setup.db_conn.as_ref(), tree, info)
+        .await
+        .unwrap();
+    assert_eq!(tree_height(&tree), 21);
+
+    assert_eq!(node_idx_to_leaf_idx(2_i64.pow(20) + 5, 21), Some(5));
+    assert_eq!(node_idx_to_leaf_idx(2_i64.pow(20) - 1, 21), None);
+    assert_eq!(node_idx_to_leaf_idx(2_i64.pow(21), 21), None);
+    // Paths of changelog events have the length of the detected height.
+    let path = (0..21)
+        .map(|level| PathNode {
+            node: [0; 32],
+            index: (2_u32.pow(20) + 5) >> level,
+        })
+        .collect::<Vec<_>>();
+    let path_update = PathUpdate {
+        tree: tree.to_bytes(),
+        path,
+        seq: 0,
+        signature: Signature::new_unique(),
+    };
+    let leaf_node = LeafNode::try_from(path_update.clone()).unwrap();
+    assert_eq!(leaf_node.leaf_index, 5);
+    let mut truncated_path_update = path_update;
+    truncated_path_update.path.pop();
+    assert!(LeafNode::try_from(truncated_path_update).is_err());
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_indexing_fetches_tree_infos(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::ingester::tree_info::tree_height;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    let account = state_tree_account();
+    let encode =
+        |bytes: &[u8]| base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes);
+    let rpc_url = serve_rpc(HashMap::from([
+        (
+            "getVersion",
+            serde_json::json!({"result": {"solana-core": "1.18.22"}}),
+        ),
+        (
+            "getMultipleAccounts",
+            serde_json::json!({
+                "result": {
+                    "context": {"slot": 1},
+                    "value": [{
+                        "lamports": account.lamports,
+                        "data": [encode(&account.data), "base64"],
+                        "owner": account.owner.to_string(),
+                        "executable": false,
+                        "rentEpoch": 0,
+                        "space": account.data.len(),
+                    }],
+                },
+            }),
+        ),
+    ]))
+    .await;
+    let indexer = setup
+        .indexer
+        .clone()
+        .with_rpc_client(Arc::new(RpcClient::new(rpc_url)));
+
+    // Backfills and replays index through the same path as the live indexer, so the leaves of a
+    // tree that is not known yet are placed according to its height on chain.
+    let tree = Pubkey::new_unique();
+    let hash = Hash::new_unique();
+    let event = PublicTransactionEvent {
+        output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+            compressed_account: CompressedAccount {
+                owner: Pubkey::new_unique(),
+                lamports: 1000,
+                address: None,
+                data: None,
+            },
+            merkle_tree_index: 0,
+        }],
+        output_compressed_account_hashes: vec![hash.0],
+        output_leaf_indices: vec![5],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: 5,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![compression_event_transaction(to_vec(&event).unwrap())],
+    };
+    index_block_batch_with_infinite_retries(&indexer, vec![block]).await;
+
+    assert_eq!(tree_height(&tree), 21);
+    let leaf = state_trees::Entity::find()
+        .filter(state_trees::Column::Hash.eq(hash.to_vec()))
+        .one(setup.db_conn.as_ref())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(leaf.leaf_idx, Some(5));
+    assert_eq!(leaf.node_idx, 2_i64.pow(20) + 5);
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_grpc_api(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::api::grpc::proto::{
+        get_compressed_account_request::Identifier, photon_client::PhotonClient,
+        GetCompressedAccountRequest, GetCompressedAccountsByOwnerRequest,
+    };
+    use photon_indexer::api::grpc::run_grpc_server;
+
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
                 ..Default::default()
-            };
-            let res = setup
-                .api
-                .get_compressed_token_balances_by_owner(request)
-                .await
-                .unwrap()
-                .value;
-            let token_balances = res.token_balances;
-            for token_balance in token_balances.iter() {
-                let balance = mint_to_balance.entry(token_balance.mint).or_insert(0);
-                *balance += token_balance.balance.0;
-            }
-            cursor = res.cursor;
-            if cursor.is_none() {
-                break;
-            }
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner = SerializablePubkey::new_unique();
+    let tree = SerializablePubkey::new_unique();
+    let mut state_update = StateUpdate::new();
+    for leaf_index in 0..3 {
+        state_update.out_accounts.push(Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner,
+            lamports: U64String(1000),
+            tree,
+            leaf_index: UnsignedInteger(leaf_index),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        });
+    }
+    persist_state_update_using_connection(&setup.db_conn, state_update.clone())
+        .await
+        .unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = tokio::spawn(run_grpc_server(api, port));
+    let mut client = loop {
+        match PhotonClient::connect(format!("http://127.0.0.1:{}", port)).await {
+            Ok(client) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
         }
-        assert_eq!(mint_to_balance, *owner_to_balances.get(owner).unwrap());
+    };
+
+    let account = &state_update.out_accounts[0];
+    let response = client
+        .get_compressed_account(GetCompressedAccountRequest {
+            identifier: Some(Identifier::Hash(account.hash.to_vec())),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let fetched = response.account.unwrap();
+    assert_eq!(fetched.hash, account.hash.to_vec());
+    assert_eq!(fetched.owner, owner.0.to_bytes().to_vec());
+    assert_eq!(fetched.lamports, 1000);
+
+    let by_owner_request = GetCompressedAccountsByOwnerRequest {
+        owner: owner.0.to_bytes().to_vec(),
+        cursor: None,
+        limit: Some(2),
+        discriminator: None,
+    };
+    let page = client
+        .get_compressed_accounts_by_owner(by_owner_request.clone())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(page.accounts.len(), 2);
+    assert!(page.cursor.is_some());
+
+    let mut stream = client
+        .stream_compressed_accounts_by_owner(by_owner_request)
+        .await
+        .unwrap()
+        .into_inner();
+    let mut streamed_hashes = Vec::new();
+    while let Some(account) = stream.message().await.unwrap() {
+        streamed_hashes.push(account.hash);
     }
-}
+    let mut expected_hashes = state_update
+        .out_accounts
+        .iter()
+        .map(|account| account.hash.to_vec())
+        .collect::<Vec<_>>();
+    streamed_hashes.sort();
+    expected_hashes.sort();
+    assert_eq!(streamed_hashes, expected_hashes);
 
-#[tokio::test]
-async fn test_compute_parent_hash() {
-    let child = ZERO_BYTES[0];
-    let parent = ZERO_BYTES[1];
-    let computed_parent = compute_parent_hash(child.to_vec(), child.to_vec()).unwrap();
-    assert_eq!(computed_parent, parent.to_vec());
+    let status = client
+        .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+            owner: vec![1; 5],
+            cursor: None,
+            limit: None,
+            discriminator: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    server.abort();
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_persisted_state_trees(
+async fn test_graphql_api(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
+    use photon_indexer::api::graphql::GraphQLLimits;
+
     let name = trim_test_name(function_name!());
     let setup = setup(name, db_backend).await;
-    let tree = SerializablePubkey::new_unique();
-    let num_nodes = 5;
 
-    let leaf_nodes: Vec<LeafNode> = (0..num_nodes)
-        .map(|i| LeafNode {
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner = SerializablePubkey::new_unique();
+    let tree = SerializablePubkey::new_unique();
+    let mut state_update = StateUpdate::new();
+    for leaf_index in 0..3 {
+        state_update.out_accounts.push(Account {
             hash: Hash::new_unique(),
-            leaf_index: i,
+            address: None,
+            data: None,
+            owner,
+            lamports: U64String(1000),
             tree,
-            seq: i,
-        })
-        .collect();
-    let txn = setup.db_conn.as_ref().begin().await.unwrap();
-    let tree_height = 5;
-    persist_leaf_nodes(&txn, leaf_nodes.clone(), tree_height)
+            leaf_index: UnsignedInteger(leaf_index),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        });
+    }
+    persist_state_update_using_connection(&setup.db_conn, state_update.clone())
         .await
         .unwrap();
-    txn.commit().await.unwrap();
 
-    let proofs = get_multiple_compressed_leaf_proofs(
-        &setup.db_conn.begin().await.unwrap(),
-        leaf_nodes
-            .iter()
-            .map(|x| Hash::try_from(x.hash.clone()).unwrap())
-            .collect(),
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
     )
-    .await
-    .unwrap();
-
-    let proof_hashes: HashSet<Hash> = proofs.iter().map(|x| x.hash.clone()).collect();
-    let leaf_hashes: HashSet<Hash> = leaf_nodes.iter().map(|x| x.hash.clone()).collect();
-    assert_eq!(proof_hashes, leaf_hashes);
+    .with_graphql(GraphQLLimits::default());
+    let server = run_server(api, port).await.unwrap();
+    let url = format!("http://127.0.0.1:{}/graphql", port);
+    let client = reqwest::Client::new();
+    let query = |query: String| {
+        let request = client
+            .post(&url)
+            .json(&serde_json::json!({ "query": query }));
+        async move {
+            request
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap()
+        }
+    };
 
-    for proof in proofs {
-        assert_eq!(proof.merkleTree, tree);
-        assert_eq!(num_nodes as u64 - 1, proof.rootSeq);
-        assert_eq!(tree_height - 1, proof.proof.len() as u32);
+    let response = query(format!(
+        r#"{{ indexerSlot accountsByOwner(owner: "{}", limit: 2) {{
+            items {{ hash owner lamports leafIndex tokenData {{ amount }} signatures {{ signature }} }}
+            cursor
+        }} }}"#,
+        owner
+    ))
+    .await;
+    assert_eq!(response.get("errors"), None);
+    assert_eq!(response["data"]["indexerSlot"], 0);
+    let page = &response["data"]["accountsByOwner"];
+    let items = page["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert!(page["cursor"].is_string());
+    for item in items {
+        assert_eq!(item["owner"], owner.to_string());
+        assert_eq!(item["lamports"], "1000");
+        assert_eq!(item["tokenData"], serde_json::Value::Null);
+        assert_eq!(item["signatures"], serde_json::json!([]));
     }
 
-    // Repeat in order to test updates
+    let account = &state_update.out_accounts[0];
+    let response = query(format!(
+        r#"{{ account(hash: "{}") {{ hash leafIndex }} }}"#,
+        account.hash
+    ))
+    .await;
+    assert_eq!(
+        response["data"]["account"],
+        serde_json::json!({ "hash": account.hash.to_string(), "leafIndex": 0 })
+    );
 
-    let leaf_nodes: Vec<LeafNode> = (0..num_nodes)
-        .map(|i| LeafNode {
-            hash: Hash::new_unique(),
-            leaf_index: i,
-            tree,
-            seq: i + num_nodes,
-        })
-        .collect();
-    let txn = setup.db_conn.as_ref().begin().await.unwrap();
-    persist_leaf_nodes(&txn, leaf_nodes.clone(), tree_height)
-        .await
-        .unwrap();
-    txn.commit().await.unwrap();
+    let response = query(r#"{ accountsByOwner(owner: "invalid") { cursor } }"#.to_string()).await;
+    assert_eq!(response["data"], serde_json::Value::Null);
+    assert!(response["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("Invalid public key"));
 
-    let proofs = get_multiple_compressed_leaf_proofs(
-        &setup.db_conn.begin().await.unwrap(),
-        leaf_nodes
-            .iter()
-            .map(|x| Hash::try_from(x.hash.clone()).unwrap())
-            .collect(),
-    )
-    .await
-    .unwrap();
+    // A full page of accounts with their signatures exceeds the complexity limit.
+    let response = query(format!(
+        r#"{{ accountsByOwner(owner: "{}", limit: 1000) {{ items {{ signatures {{ signature }} }} }} }}"#,
+        owner
+    ))
+    .await;
+    assert!(response["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("too complex"));
 
-    let proof_hashes: HashSet<Hash> = proofs.iter().map(|x| x.hash.clone()).collect();
-    let leaf_hashes: HashSet<Hash> = leaf_nodes.iter().map(|x| x.hash.clone()).collect();
-    assert_eq!(proof_hashes, leaf_hashes);
+    let sdl = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    assert!(sdl.contains("type Account"));
 
-    for proof in proofs {
-        assert_eq!(proof.merkleTree, tree);
-        assert_eq!(num_nodes as u64 - 1 + num_nodes as u64, proof.rootSeq);
-        assert_eq!(tree_height - 1, proof.proof.len() as u32);
-    }
+    server.stop().unwrap();
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_indexed_merkle_trees(
+async fn test_rest_routes(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
-    use photon_indexer::ingester::persist::persisted_indexed_merkle_tree::validate_tree;
-
     let name = trim_test_name(function_name!());
     let setup = setup(name, db_backend).await;
-    let tree = SerializablePubkey::new_unique();
-    let num_nodes = 2;
 
-    let txn = sea_orm::TransactionTrait::begin(setup.db_conn.as_ref())
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let owner = SerializablePubkey::new_unique();
+    let tree = SerializablePubkey::new_unique();
+    let mut state_update = StateUpdate::new();
+    for leaf_index in 0..3 {
+        state_update.out_accounts.push(Account {
+            hash: Hash::new_unique(),
+            address: None,
+            data: None,
+            owner,
+            lamports: U64String(1000),
+            tree,
+            leaf_index: UnsignedInteger(leaf_index),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        });
+    }
+    persist_state_update_using_connection(&setup.db_conn, state_update.clone())
         .await
         .unwrap();
 
-    let values = (0..num_nodes).map(|i| vec![i * 4 + 1]).collect();
-    let tree_height = 4;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let get = |path: String| async move {
+        let response = reqwest::get(format!("http://127.0.0.1:{}{}", port, path))
+            .await
+            .unwrap();
+        let status = response.status();
+        (status, response.json::<serde_json::Value>().await.unwrap())
+    };
 
-    multi_append(&txn, values, tree.to_bytes_vec(), tree_height)
-        .await
-        .unwrap();
+    let account = &state_update.out_accounts[0];
+    let (status, body) = get(format!("/accounts/{}", account.hash)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body["value"], serde_json::to_value(account).unwrap());
+
+    let (status, body) = get(format!("/accounts/{}/balance", account.hash)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body["value"], "1000");
+
+    let (status, body) = get(format!("/owners/{}/accounts?limit=2", owner)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body["value"]["items"].as_array().unwrap().len(), 2);
+    let cursor = body["value"]["cursor"].as_str().unwrap();
+    let cursor = form_urlencoded::byte_serialize(cursor.as_bytes()).collect::<String>();
+    let (status, body) = get(format!("/owners/{}/accounts?cursor={}", owner, cursor)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body["value"]["items"].as_array().unwrap().len(), 1);
+
+    let (status, _) = get(format!("/owners/{}/accounts?mint={}", owner, owner)).await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let (status, body) = get(format!("/owners/{}/token-accounts?frozen=true", owner)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert!(body["value"]["items"].as_array().unwrap().is_empty());
+    let (status, _) = get(format!("/owners/{}/token-accounts?frozen=yes", owner)).await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let (status, _) = get(format!("/owners/{}/token-accounts?delegated=false", owner)).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let (status, _) = get(format!(
+        "/delegates/{}/token-accounts?delegated=false",
+        owner
+    ))
+    .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let (status, _) = get("/owners/invalid/accounts".to_string()).await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let (status, body) = get(format!("/accounts/{}/proof", Hash::new_unique())).await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    assert_eq!(body["data"]["kind"], "record_not_found");
+
+    server.stop().unwrap();
+}
 
-    txn.commit().await.unwrap();
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_photon_client(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    use photon_indexer::client::{ClientError, PhotonClient};
 
-    let (model, _) = get_exclusion_range_with_proof(
-        &setup.db_conn.begin().await.unwrap(),
-        tree.to_bytes_vec(),
-        tree_height,
-        vec![3],
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
     )
     .await
     .unwrap();
 
-    let expected_model = indexed_trees::Model {
-        tree: tree.to_bytes_vec(),
-        leaf_index: 2,
-        value: vec![1],
-        next_index: 3,
-        next_value: vec![5],
-        seq: 0,
-    };
-
-    assert_eq!(model, expected_model);
-
-    let txn = sea_orm::TransactionTrait::begin(setup.db_conn.as_ref())
+    let owner = SerializablePubkey::new_unique();
+    let tree = SerializablePubkey::new_unique();
+    let mut state_update = StateUpdate::new();
+    for leaf_index in 0..3 {
+        state_update.out_accounts.push(Account {
+            hash: Hash::new_unique(),
+            address: Some(SerializablePubkey::new_unique()),
+            data: Some(AccountData {
+                discriminator: UnsignedInteger(1),
+                data: Base64String(vec![leaf_index as u8; 10]),
+                data_hash: Hash::new_unique(),
+            }),
+            owner,
+            lamports: U64String(1000),
+            tree,
+            leaf_index: UnsignedInteger(leaf_index),
+            seq: UnsignedInteger(0),
+            slot_created: UnsignedInteger(0),
+        });
+    }
+    persist_state_update_using_connection(&setup.db_conn, state_update.clone())
         .await
         .unwrap();
 
-    let values = vec![vec![3]];
-
-    multi_append(&txn, values, tree.to_bytes_vec(), tree_height)
-        .await
-        .unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    );
+    let server = run_server(api, port).await.unwrap();
+    let client = PhotonClient::new(format!("http://127.0.0.1:{}", port));
 
-    txn.commit().await.unwrap();
+    assert_eq!(client.get_indexer_slot().await.unwrap(), UnsignedInteger(0));
 
-    validate_tree(setup.db_conn.as_ref(), tree).await;
+    let account = &state_update.out_accounts[0];
+    let request = CompressedAccountRequest {
+        hash: Some(account.hash.clone()),
+        address: None,
+    };
+    assert_eq!(
+        client
+            .get_compressed_account(request.clone())
+            .await
+            .unwrap(),
+        setup.api.get_compressed_account(request).await.unwrap()
+    );
 
-    let (model, _) = get_exclusion_range_with_proof(
-        &setup.db_conn.begin().await.unwrap(),
-        tree.to_bytes_vec(),
-        tree_height,
-        vec![4],
-    )
-    .await
-    .unwrap();
+    let request = GetMultipleCompressedAccountsRequest {
+        hashes: None,
+        addresses: Some(
+            state_update
+                .out_accounts
+                .iter()
+                .map(|account| account.address.unwrap())
+                .collect(),
+        ),
+    };
+    assert_eq!(
+        client
+            .get_multiple_compressed_accounts(request.clone())
+            .await
+            .unwrap(),
+        setup
+            .api
+            .get_multiple_compressed_accounts(request)
+            .await
+            .unwrap()
+    );
 
-    let expected_model = indexed_trees::Model {
-        tree: tree.to_bytes_vec(),
-        leaf_index: 4,
-        value: vec![3],
-        next_index: 3,
-        next_value: vec![5],
-        seq: 0,
+    let request = GetCompressedAccountsByOwnerRequest {
+        owner,
+        limit: Some(Limit::new(2).unwrap()),
+        ..Default::default()
     };
+    let page = client
+        .get_compressed_accounts_by_owner(request.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        page,
+        setup
+            .api
+            .get_compressed_accounts_by_owner(request)
+            .await
+            .unwrap()
+    );
+    assert_eq!(page.value.items.len(), 2);
 
-    assert_eq!(model, expected_model);
+    let error = client
+        .get_compressed_account_proof(HashRequest {
+            hash: Hash::new_unique(),
+        })
+        .await
+        .unwrap_err();
+    match &error {
+        ClientError::Rpc { data, .. } => {
+            assert_eq!(data.as_ref().unwrap()["kind"], "record_not_found")
+        }
+        _ => panic!("Unexpected error: {}", error),
+    }
+    assert!(!error.is_retryable());
+
+    server.stop().unwrap();
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_get_multiple_new_address_proofs(
+async fn test_rate_limiting(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
-    use photon_indexer::api::method::get_multiple_new_address_proofs::{
-        get_multiple_new_address_proofs, AddressList,
+    use photon_indexer::api::grpc::proto::{
+        photon_client::PhotonClient, GetCompressedAccountProofRequest,
     };
+    use photon_indexer::api::grpc::run_grpc_server;
 
     let name = trim_test_name(function_name!());
-    let setup = setup(name.clone(), db_backend).await;
+    let setup = setup(name, db_backend).await;
 
-    // HACK: We index a block so that API methods can fetch the current slot.
     index_block(
-        &setup.db_conn,
+        &setup.indexer,
         &BlockInfo {
             metadata: BlockMetadata {
                 slot: 0,
@@ -900,182 +7777,345 @@ async fn test_get_multiple_new_address_proofs(
     .await
     .unwrap();
 
-    let addresses = vec![
-        SerializablePubkey::try_from("Fi6AXBGuGs7DRXP428hwhJJfTpJ4BVZD8DiUcX1cj35W").unwrap(),
-        SerializablePubkey::try_from("sH8ux4csv8wxiRejuHjpTCkrfGgeNtg6Y55AJJ9GJSd").unwrap(),
-    ];
-    let proof = get_multiple_new_address_proofs(&setup.db_conn, AddressList(addresses.clone()))
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    )
+    .with_rate_limits(RateLimitConfig {
+        reads: Some(RateLimit {
+            requests_per_second: 1,
+            burst: 2,
+        }),
+        proofs: Some(RateLimit {
+            requests_per_second: 1,
+            burst: 1,
+        }),
+        api_keys: HashSet::from(["a".to_string(), "b".to_string()]),
+        trusted_proxies: 1,
+    });
+    let grpc_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let grpc_server = tokio::spawn(run_grpc_server(api.clone(), grpc_port));
+    let server = run_server(api, port).await.unwrap();
+    let http = reqwest::Client::new();
+    let call = |api_key: &'static str, method: &'static str, params: serde_json::Value| {
+        let request = http
+            .post(format!("http://127.0.0.1:{}", port))
+            .header("x-api-key", api_key)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 7,
+                "method": method,
+                "params": params,
+            }));
+        async move {
+            let response = request.send().await.unwrap();
+            let status = response.status();
+            let retry_after = response.headers().get("retry-after").cloned();
+            let body = response.json::<serde_json::Value>().await.unwrap();
+            (status, retry_after, body)
+        }
+    };
+
+    for _ in 0..2 {
+        let (status, _, body) = call("a", "getIndexerSlot", serde_json::json!({})).await;
+        assert_eq!(status, reqwest::StatusCode::OK);
+        assert_eq!(body["result"], 0);
+    }
+    let (status, retry_after, body) = call("a", "getIndexerSlot", serde_json::json!({})).await;
+    assert_eq!(status, reqwest::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(retry_after.unwrap(), "1");
+    assert_eq!(body["id"], 7);
+    assert_eq!(body["error"]["data"]["kind"], "rate_limited");
+    assert_eq!(body["error"]["data"]["retryable"], true);
+
+    // Clients have their own buckets, and proofs are limited separately from reads.
+    let (status, _, _) = call("b", "getIndexerSlot", serde_json::json!({})).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let proof_params = serde_json::json!({ "hash": Hash::new_unique().to_string() });
+    let (status, _, body) = call("a", "getCompressedAccountProof", proof_params.clone()).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body["error"]["data"]["kind"], "record_not_found");
+    let (status, _, body) = call("a", "getCompressedAccountProof", proof_params).await;
+    assert_eq!(status, reqwest::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"]["data"]["kind"], "rate_limited");
+
+    // Unknown API keys and the entries that clients prepend to `X-Forwarded-For` are ignored, so
+    // that changing them does not reset the limit of a client.
+    let call_from = |api_key: String, forwarded_for: String| {
+        let request = http
+            .post(format!("http://127.0.0.1:{}", port))
+            .header("x-api-key", api_key)
+            .header("x-forwarded-for", forwarded_for)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 7,
+                "method": "getIndexerSlot",
+                "params": {},
+            }));
+        async move { request.send().await.unwrap().status() }
+    };
+    for i in 0..2 {
+        let status = call_from(format!("unknown-{}", i), format!("10.0.0.{}, 10.1.0.1", i)).await;
+        assert_eq!(status, reqwest::StatusCode::OK);
+    }
+    let status = call_from("unknown-2".to_string(), "10.0.0.2, 10.1.0.1".to_string()).await;
+    assert_eq!(status, reqwest::StatusCode::TOO_MANY_REQUESTS);
+    let status = call_from("unknown-3".to_string(), "10.1.0.2".to_string()).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+
+    // gRPC calls take tokens from the same buckets, and clients without an API key are identified
+    // by the address of their connection.
+    let mut grpc_client = loop {
+        match PhotonClient::connect(format!("http://127.0.0.1:{}", grpc_port)).await {
+            Ok(client) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    };
+    let proof_request = |api_key: Option<&str>| {
+        let mut request = tonic::Request::new(GetCompressedAccountProofRequest {
+            hash: Hash::new_unique().to_vec(),
+        });
+        if let Some(api_key) = api_key {
+            request
+                .metadata_mut()
+                .insert("x-api-key", api_key.parse().unwrap());
+        }
+        request
+    };
+    let status = grpc_client
+        .get_compressed_account_proof(proof_request(Some("a")))
         .await
-        .unwrap();
-    insta::assert_json_snapshot!(name, proof);
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(status.metadata().get("retry-after").unwrap(), "1");
+    let status = grpc_client
+        .get_compressed_account_proof(proof_request(None))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    let status = grpc_client
+        .get_compressed_account_proof(proof_request(None))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+    // Health checks are never throttled.
+    for _ in 0..3 {
+        let response = http
+            .get(format!("http://127.0.0.1:{}/liveness", port))
+            .header("x-api-key", "a")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    grpc_server.abort();
+    server.stop().unwrap();
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_get_multiple_new_address_proofs_interop(
+async fn test_api_config_applies_to_grpc(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
-    use photon_indexer::api::method::{
-        get_multiple_new_address_proofs::{
-            get_multiple_new_address_proofs, get_multiple_new_address_proofs_v2, AddressList,
-            AddressListWithTrees, AddressWithTree, ADDRESS_TREE_ADDRESS,
-        },
-        get_validity_proof::CompressedProof,
+    use photon_indexer::api::grpc::proto::{
+        get_compressed_account_request::Identifier, photon_client::PhotonClient,
+        GetCompressedAccountProofRequest, GetCompressedAccountRequest,
     };
+    use photon_indexer::api::grpc::run_grpc_server;
 
     let name = trim_test_name(function_name!());
-    let setup = setup(name.clone(), db_backend).await;
+    let setup = setup(name, db_backend).await;
 
-    // HACK: We index a block so that API methods can fetch the current slot.
-    index_block(
-        &setup.db_conn,
-        &BlockInfo {
-            metadata: BlockMetadata {
-                slot: 0,
-                ..Default::default()
-            },
+    // Like `photon serve`, the HTTP and gRPC servers run clones of one configured API.
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    )
+    .with_config(ApiConfig {
+        rate_limits: Some(RateLimitConfig {
+            proofs: Some(RateLimit {
+                requests_per_second: 1,
+                burst: 1,
+            }),
+            api_keys: HashSet::from(["a".to_string()]),
+            trusted_proxies: 1,
+            ..Default::default()
+        }),
+        method_filter: MethodFilter {
+            disabled: HashSet::from(["getCompressedAccount".to_string()]),
             ..Default::default()
         },
-    )
-    .await
-    .unwrap();
+        ..Default::default()
+    });
+    let free_port = || {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    };
+    let (port, grpc_port) = (free_port(), free_port());
+    let grpc_server = tokio::spawn(run_grpc_server(api.clone(), grpc_port));
+    let server = run_server(api, port).await.unwrap();
+    let mut grpc_client = loop {
+        match PhotonClient::connect(format!("http://127.0.0.1:{}", grpc_port)).await {
+            Ok(client) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    };
 
-    let addresses = vec![SerializablePubkey::try_from(vec![
-        0, 2, 3, 4, 5, 6, 7, 8, 9, 10, 42, 42, 42, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
-        26, 27, 28, 29, 30, 31, 32,
-    ])
-    .unwrap()];
-    let proof = get_multiple_new_address_proofs(&setup.db_conn, AddressList(addresses.clone()))
+    let status = grpc_client
+        .get_compressed_account(GetCompressedAccountRequest {
+            identifier: Some(Identifier::Hash(Hash::new_unique().to_vec())),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unimplemented);
+
+    // A proof requested over HTTP uses up the bucket of the client on gRPC as well.
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}", port))
+        .header("x-api-key", "a")
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "getCompressedAccountProof",
+            "params": { "hash": Hash::new_unique().to_string() },
+        }))
+        .send()
         .await
         .unwrap();
-    insta::assert_json_snapshot!(name.clone(), proof);
-    let mut validity_proof = get_validity_proof(
-        &setup.db_conn,
-        &setup.prover_url,
-        GetValidityProofRequest {
-            newAddresses: addresses.clone(),
-            newAddressesWithTrees: vec![],
-            hashes: vec![],
-        },
-    )
-    .await
-    .unwrap();
-    // The Gnark prover has some randomness.
-    validity_proof.value.compressedProof = CompressedProof::default();
-
-    insta::assert_json_snapshot!(format!("{}-validity-proof", name), validity_proof);
-
-    // Repeat with V2
-    let addresses_with_trees: Vec<AddressWithTree> = addresses
-        .clone()
-        .into_iter()
-        .map(|address| AddressWithTree {
-            address,
-            tree: SerializablePubkey::from(ADDRESS_TREE_ADDRESS),
-        })
-        .collect();
-    let proof_v2 = get_multiple_new_address_proofs_v2(
-        &setup.db_conn,
-        AddressListWithTrees(addresses_with_trees.clone()),
-    )
-    .await
-    .unwrap();
-
-    insta::assert_json_snapshot!(name.clone(), proof_v2);
-    let mut validity_proof_v2 = get_validity_proof(
-        &setup.db_conn,
-        &setup.prover_url,
-        GetValidityProofRequest {
-            newAddressesWithTrees: addresses_with_trees.clone(),
-            hashes: vec![],
-            newAddresses: vec![],
-        },
-    )
-    .await
-    .unwrap();
-    // The Gnark prover has some randomness.
-    validity_proof_v2.value.compressedProof = CompressedProof::default();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let mut request = tonic::Request::new(GetCompressedAccountProofRequest {
+        hash: Hash::new_unique().to_vec(),
+    });
+    request
+        .metadata_mut()
+        .insert("x-api-key", "a".parse().unwrap());
+    let status = grpc_client
+        .get_compressed_account_proof(request)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
 
-    insta::assert_json_snapshot!(format!("{}-validity-proof", name), validity_proof_v2);
+    grpc_server.abort();
+    server.stop().unwrap();
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-#[ignore]
-async fn load_test(#[values(DatabaseBackend::Postgres)] db_backend: DatabaseBackend) {
+async fn test_cors(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
     let name = trim_test_name(function_name!());
-    let setup = setup(name.clone(), db_backend).await;
+    let setup = setup(name, db_backend).await;
 
-    // HACK: We index a block so that API methods can fetch the current slot.
-    index_block(
-        &setup.db_conn,
-        &BlockInfo {
-            metadata: BlockMetadata {
-                slot: 0,
-                ..Default::default()
-            },
-            ..Default::default()
-        },
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
     )
-    .await
-    .unwrap();
-
-    let mut state_update = StateUpdate::default();
-
-    let tree = SerializablePubkey::new_unique();
-
-    fn generate_mock_account(leaf_index: u64, tree: SerializablePubkey) -> Account {
-        Account {
-            hash: Hash::new_unique(),
-            address: Some(SerializablePubkey::new_unique()),
-            data: Some(AccountData {
-                discriminator: UnsignedInteger(1),
-                data: Base64String(vec![1; 500]),
-                data_hash: Hash::new_unique(),
-            }),
-            owner: SerializablePubkey::new_unique(),
-            lamports: UnsignedInteger(1000),
-            tree,
-            leaf_index: UnsignedInteger(leaf_index),
-            seq: UnsignedInteger(0),
-            slot_created: UnsignedInteger(0),
-        }
-    }
+    .with_cors(CorsConfig {
+        allowed_origins: vec!["https://app.example.com".parse().unwrap()],
+        allowed_headers: vec!["x-custom".parse().unwrap()],
+        ..Default::default()
+    });
+    let server = run_server(api, port).await.unwrap();
+    let http = reqwest::Client::new();
+    let preflight = |origin: &'static str| {
+        http.request(
+            reqwest::Method::OPTIONS,
+            format!("http://127.0.0.1:{}", port),
+        )
+        .header("origin", origin)
+        .header("access-control-request-method", "POST")
+        .header("access-control-request-headers", "content-type,x-custom")
+        .send()
+    };
 
-    for i in 0..100000 {
-        state_update
-            .out_accounts
-            .push(generate_mock_account(i, tree));
-    }
-    persist_state_update_using_connection(setup.db_conn.as_ref(), state_update)
+    let response = preflight("https://app.example.com").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(
+        headers["access-control-allow-origin"],
+        "https://app.example.com"
+    );
+    let allowed_headers = headers["access-control-allow-headers"].to_str().unwrap();
+    assert!(allowed_headers.contains("content-type"));
+    assert!(allowed_headers.contains("x-custom"));
+    assert_eq!(headers["access-control-max-age"], "600");
+
+    let response = preflight("https://other.example.com").await.unwrap();
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+
+    let response = http
+        .post(format!("http://127.0.0.1:{}", port))
+        .header("origin", "https://app.example.com")
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "liveness",
+        }))
+        .send()
         .await
         .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "https://app.example.com"
+    );
+    assert!(response.headers()["access-control-expose-headers"]
+        .to_str()
+        .unwrap()
+        .contains("retry-after"));
+
+    server.stop().unwrap();
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_persisted_state_trees_bug_with_latter_smaller_seq_values(
+async fn test_method_filter_and_request_limits(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
-    use photon_indexer::{
-        api::method::get_multiple_new_address_proofs::{
-            get_multiple_new_address_proofs, AddressList,
-        },
-        ingester::persist::persisted_indexed_merkle_tree::validate_tree,
+    use photon_indexer::api::graphql::GraphQLLimits;
+    use photon_indexer::api::grpc::proto::{
+        get_compressed_account_request::Identifier, photon_client::PhotonClient,
+        GetCompressedAccountRequest,
     };
+    use photon_indexer::api::grpc::run_grpc_server;
 
     let name = trim_test_name(function_name!());
-
     let setup = setup(name, db_backend).await;
-    // HACK: We index a block so that API methods can fetch the current slot.
+
     index_block(
-        &setup.db_conn,
+        &setup.indexer,
         &BlockInfo {
             metadata: BlockMetadata {
                 slot: 0,
@@ -1083,88 +8123,126 @@ async fn test_persisted_state_trees_bug_with_latter_smaller_seq_values(
             },
             ..Default::default()
         },
-    )
-    .await
-    .unwrap();
-    let tree =
-        SerializablePubkey::try_from("C83cpRN6oaafjNgMQJvaYgAz592EP5wunKvbokeTKPLn").unwrap();
-
-    let leaf_nodes_1 = vec![
-        LeafNode {
-            tree,
-            leaf_index: 0,
-            hash: Hash::try_from("34yinGSAmWKeXw61zZzd8hbE1ySB1pDmgiHzJhRtVwJY").unwrap(),
-            seq: 4,
-        },
-        LeafNode {
-            tree,
-            leaf_index: 1,
-            hash: Hash::try_from("34cMT7MjFrs8hLp2zHMrPJHKkUxBDBwBTNck77wLjjcY").unwrap(),
-            seq: 0,
-        },
-        LeafNode {
-            tree,
-            leaf_index: 2,
-            hash: Hash::try_from("TTSZiUJsGTcU7sXqYtw53yFY5Ag7DmHXR4GzEjVk7J7").unwrap(),
-            seq: 5,
-        },
-    ];
-    let leaf_nodes_2 = vec![
-        LeafNode {
-            tree,
-            leaf_index: 0,
-            hash: Hash::try_from("3hH3oNVj2bafrqqXLnZjLjkuDaoxKhyyvmxaSs939hws").unwrap(),
-            seq: 0,
-        },
-        LeafNode {
-            tree,
-            leaf_index: 1,
-            hash: Hash::try_from("34cMT7MjFrs8hLp2zHMrPJHKkUxBDBwBTNck77wLjjcY").unwrap(),
-            seq: 0,
-        },
-        LeafNode {
-            tree,
-            leaf_index: 2,
-            hash: Hash::try_from("25D2cs6h29NZgmDepVqc7bLLSWcNJnMvGoxeTpyZjF3u").unwrap(),
-            seq: 10,
-        },
-    ];
-    let leaf_node_chunks = vec![leaf_nodes_1, leaf_nodes_2];
+    )
+    .await
+    .unwrap();
 
-    let tree_height = 3;
-    for chunk in leaf_node_chunks {
-        let txn = setup.db_conn.as_ref().begin().await.unwrap();
-        persist_leaf_nodes(&txn, chunk.clone(), tree_height)
-            .await
-            .unwrap();
-        txn.commit().await.unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let api = PhotonApi::new(
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        setup.prover_url.clone(),
+    )
+    .with_method_filter(MethodFilter {
+        enabled: None,
+        disabled: ["getIndexerSlot", "getCompressedAccount"]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+    })
+    .with_request_limits(RequestLimits {
+        max_body_size: 1024,
+        max_batch_len: Some(2),
+    })
+    .with_graphql(GraphQLLimits::default());
+    let grpc_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let grpc_server = tokio::spawn(run_grpc_server(api.clone(), grpc_port));
+    let server = run_server(api, port).await.unwrap();
+    let http = reqwest::Client::new();
+    let url = format!("http://127.0.0.1:{}", port);
+    let call =
+        |method: &str, id: u64| serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method });
+    let post = |body: serde_json::Value| {
+        let request = http.post(&url).json(&body);
+        async move {
+            let response = request.send().await.unwrap();
+            let status = response.status();
+            (status, response.json::<serde_json::Value>().await.unwrap())
+        }
+    };
 
-        let proof_address = "12prJNGB6sfTMrZM1Udv2Aamv9fLzpm5YfMqssTmGrWy";
+    let (_, body) = post(call("getIndexerSlot", 1)).await;
+    assert_eq!(body["error"]["data"]["kind"], "method_disabled");
+    let response = http.get(format!("{}/slot", url)).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    // Health checks cannot be disabled.
+    let (_, body) = post(call("liveness", 1)).await;
+    assert!(body["error"].is_null());
+
+    let (status, body) = post(serde_json::json!([
+        call("liveness", 1),
+        call("liveness", 2)
+    ]))
+    .await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    let (status, body) = post(serde_json::json!([
+        call("liveness", 1),
+        call("liveness", 2),
+        call("liveness", 3)
+    ]))
+    .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "Batch of 3 calls exceeds the limit of 2 calls"
+    );
 
-        let address_list = AddressList(vec![SerializablePubkey::try_from(proof_address).unwrap()]);
+    let mut request = call("getCompressedAccount", 1);
+    request["params"] = serde_json::json!({ "hash": "1".repeat(2048) });
+    let (status, body) = post(request).await;
+    assert_eq!(status, reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"]["code"], -32701);
+
+    // The filter applies to the methods that answer gRPC calls and GraphQL fields as well.
+    let mut grpc_client = loop {
+        match PhotonClient::connect(format!("http://127.0.0.1:{}", grpc_port)).await {
+            Ok(client) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    };
+    let status = grpc_client
+        .get_compressed_account(GetCompressedAccountRequest {
+            identifier: Some(Identifier::Hash(Hash::new_unique().to_vec())),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unimplemented);
+    let body = http
+        .post(format!("{}/graphql", url))
+        .json(&serde_json::json!({ "query": "{ indexerSlot }" }))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(body["errors"][0]["extensions"]["kind"], "method_disabled");
 
-        validate_tree(setup.db_conn.as_ref(), tree).await;
-        get_multiple_new_address_proofs(&setup.db_conn, address_list)
-            .await
-            .unwrap();
-    }
+    grpc_server.abort();
+    server.stop().unwrap();
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_gpa_filters(
+async fn test_livez_and_readyz(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
-    use log::info;
-
     let name = trim_test_name(function_name!());
     let setup = setup(name, db_backend).await;
 
-    // HACK: We index a block so that API methods can fetch the current slot.
     index_block(
-        &setup.db_conn,
+        &setup.indexer,
         &BlockInfo {
             metadata: BlockMetadata {
                 slot: 0,
@@ -1175,318 +8253,848 @@ async fn test_gpa_filters(
     )
     .await
     .unwrap();
+    let rpc_url = serve_rpc(HashMap::from([
+        (
+            "getVersion",
+            serde_json::json!({ "result": { "solana-core": "1.18.22" } }),
+        ),
+        ("getSlot", serde_json::json!({ "result": 30 })),
+    ]))
+    .await;
 
-    let owner1 = SerializablePubkey::new_unique();
-    let mut state_update = StateUpdate::default();
+    for (max_slots_behind, ready) in [(50, true), (20, false)] {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let api = PhotonApi::new(
+            setup.db_conn.clone(),
+            Arc::new(RpcClient::new(rpc_url.clone())),
+            setup.prover_url.clone(),
+        )
+        .with_max_slots_behind(max_slots_behind);
+        let server = run_server(api, port).await.unwrap();
+        let get = |path: &'static str| async move {
+            let response = reqwest::get(format!("http://127.0.0.1:{}{}", port, path))
+                .await
+                .unwrap();
+            let status = response.status();
+            (status, response.json::<serde_json::Value>().await.unwrap())
+        };
 
-    let accounts = vec![Account {
-        hash: Hash::new_unique(),
-        address: Some(SerializablePubkey::new_unique()),
-        data: Some(AccountData {
-            discriminator: UnsignedInteger(0),
-            data: Base64String(vec![1, 2, 3]),
-            data_hash: Hash::new_unique(),
-        }),
-        owner: owner1,
-        lamports: UnsignedInteger(1000),
-        tree: SerializablePubkey::new_unique(),
-        leaf_index: UnsignedInteger(10),
-        seq: UnsignedInteger(1),
-        slot_created: UnsignedInteger(0),
-    }];
-    state_update.out_accounts = accounts.clone();
-    persist_state_update_using_connection(&setup.db_conn, state_update)
-        .await
-        .unwrap();
+        let (status, _) = get("/livez").await;
+        assert_eq!(status, reqwest::StatusCode::OK);
 
-    let res = setup
+        // The node is not ready until the database is warmed up.
+        let mut readyz = get("/readyz").await;
+        for _ in 0..50 {
+            if readyz.1["kind"] != "warming_up" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            readyz = get("/readyz").await;
+        }
+        let (status, body) = readyz;
+        if ready {
+            assert_eq!(status, reqwest::StatusCode::OK);
+            assert_eq!(body["slot"], 0);
+        } else {
+            assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(body["kind"], "stale_slot");
+        }
+
+        server.stop().unwrap();
+    }
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_fixture_block_stream(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name.clone(), db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let block = |slot: u64| {
+        let event = PublicTransactionEvent {
+            output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                compressed_account: CompressedAccount {
+                    owner,
+                    lamports: 1000,
+                    address: None,
+                    data: None,
+                },
+                merkle_tree_index: 0,
+            }],
+            output_compressed_account_hashes: vec![Hash::new_unique().0],
+            output_leaf_indices: vec![slot as u32 - 1],
+            sequence_numbers: vec![MerkleTreeSequenceNumber {
+                pubkey: tree,
+                seq: slot - 1,
+            }],
+            pubkey_array: vec![tree],
+            ..Default::default()
+        };
+        BlockInfo {
+            metadata: BlockMetadata {
+                slot,
+                parent_slot: slot - 1,
+                ..Default::default()
+            },
+            transactions: vec![compression_event_transaction(to_vec(&event).unwrap())],
+        }
+    };
+    let dir = std::env::temp_dir().join(format!("photon_{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for slot in [3, 1, 2] {
+        std::fs::write(
+            dir.join(slot.to_string()),
+            serde_json::to_vec(&block(slot)).unwrap(),
+        )
+        .unwrap();
+    }
+    let blocks = load_fixture_blocks(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let slots = |blocks: &[BlockInfo]| {
+        blocks
+            .iter()
+            .map(|block| block.metadata.slot)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(slots(&blocks), vec![1, 2, 3]);
+
+    // Fetch, parse and persist the blocks, and serve them through the API.
+    index_block_stream(
+        get_fixture_block_stream(blocks.clone(), 0),
+        setup.indexer.clone(),
+        setup.client.clone(),
+        0,
+        Some(3),
+    )
+    .await;
+    assert_eq!(setup.api.get_indexer_slot().await.unwrap().0, 3);
+    let accounts = setup
         .api
         .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
-            owner: owner1,
-
-            dataSlice: Some(DataSlice {
-                offset: 0,
-                length: 2,
-            }),
+            owner: SerializablePubkey::from(owner),
             ..Default::default()
         })
         .await
         .unwrap()
         .value;
+    assert_eq!(accounts.items.len(), 3);
 
-    assert!(res.items[0].data.clone().unwrap().data.0 == vec![1, 2]);
+    // Like the network sources, the stream resumes after the last indexed slot.
+    let resumed = get_fixture_block_stream(blocks, 2)
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+    assert_eq!(slots(&resumed), vec![3]);
 
-    let filters_and_expected_results = vec![
-        ((vec![1, 2], 0), 1),
-        ((vec![1, 2, 3], 0), 1),
-        ((vec![2, 3], 1), 1),
-        ((vec![2, 3], 0), 0),
-    ];
+    // Blocks recorded from `getBlock` are loaded as well.
+    let recorded_blocks = load_fixture_blocks(&relative_project_path(
+        "tests/data/blocks/index_block_metadata",
+    ))
+    .unwrap();
+    assert_eq!(slots(&recorded_blocks), vec![254170887, 254170888]);
+}
 
-    for (filter, expected_count) in filters_and_expected_results {
-        info!("Filter: {:?}", filter);
-        let res = setup
-            .api
-            .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
-                owner: owner1,
-                filters: vec![FilterSelector {
-                    memcmp: Some(Memcmp {
-                        offset: filter.1,
-                        bytes: Base58String(filter.0.iter().map(|x| *x as u8).collect()),
-                    }),
-                }],
-                ..Default::default()
-            })
-            .await
-            .unwrap()
-            .value;
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_max_accounts_by_owner_page_size(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
 
-        assert_eq!(res.items.len(), expected_count);
+    let tree = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let event = PublicTransactionEvent {
+        output_compressed_accounts: (0..3)
+            .map(|_| OutputCompressedAccountWithPackedContext {
+                compressed_account: CompressedAccount {
+                    owner,
+                    lamports: 1000,
+                    address: None,
+                    data: None,
+                },
+                merkle_tree_index: 0,
+            })
+            .collect(),
+        output_compressed_account_hashes: (0..3).map(|_| Hash::new_unique().0).collect(),
+        output_leaf_indices: vec![0, 1, 2],
+        sequence_numbers: vec![MerkleTreeSequenceNumber {
+            pubkey: tree,
+            seq: 0,
+        }],
+        pubkey_array: vec![tree],
+        ..Default::default()
+    };
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 1,
+            ..Default::default()
+        },
+        transactions: vec![compression_event_transaction(to_vec(&event).unwrap())],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
+
+    let api = setup.api.clone().with_max_accounts_by_owner_page_size(2);
+    let get_page = |limit: Option<u64>, cursor: Option<Cursor>| {
+        api.get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+            owner: SerializablePubkey::from(owner),
+            limit: limit.map(|limit| Limit::new(limit).unwrap()),
+            cursor,
+            ..Default::default()
+        })
+    };
+    // Pages are capped whether or not a larger limit is requested, and can be followed with the
+    // cursor.
+    for limit in [None, Some(1000)] {
+        let first_page = get_page(limit, None).await.unwrap().value;
+        assert_eq!(first_page.items.len(), 2);
+        let second_page = get_page(limit, first_page.cursor).await.unwrap().value;
+        assert_eq!(second_page.items.len(), 1);
+        assert_eq!(second_page.cursor, None);
     }
+    // Smaller pages than the maximum can still be requested.
+    let page = get_page(Some(1), None).await.unwrap().value;
+    assert_eq!(page.items.len(), 1);
+    assert!(page.cursor.is_some());
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_persisted_state_trees_multiple_cases(
+async fn test_ingestion_checkpoint(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
     let name = trim_test_name(function_name!());
-    let tree = SerializablePubkey::new_unique();
-    let tree_height = 10;
+    let setup = setup(name.clone(), db_backend).await;
+    let block = |slot: u64| BlockInfo {
+        metadata: BlockMetadata {
+            slot,
+            parent_slot: slot - 1,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    let last_indexed_slot = || fetch_last_indexed_slot_with_infinite_retry(&setup.db_conn);
 
-    info!("Test case 1: Sequential leaf nodes");
-    let leaf_nodes_1 = create_leaf_nodes(tree, 0..5, |i| i);
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_1, tree_height).await;
+    assert_eq!(
+        fetch_checkpoint(setup.db_conn.as_ref()).await.unwrap(),
+        None
+    );
+    assert_eq!(last_indexed_slot().await, None);
 
-    info!("Test case 2: Sequential leaf nodes with random seq order");
-    let leaf_nodes_2 = create_leaf_nodes(tree, 0..5, |i| vec![4, 2, 0, 1, 3][i as usize]);
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_2, tree_height).await;
+    index_block_batch(&setup.indexer, &[block(4), block(5)])
+        .await
+        .unwrap();
+    assert_eq!(
+        fetch_checkpoint(setup.db_conn.as_ref()).await.unwrap(),
+        Some(5)
+    );
 
-    info!("Test case 3: Duplicate leaf indices");
-    let leaf_nodes_3 = create_leaf_nodes(tree, vec![1, 1, 2, 2, 3, 2].into_iter(), |_| {
-        let mut rng = rand::thread_rng();
-        rng.gen_range(0..1000)
-    });
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_3, tree_height).await;
+    // Filling a gap below the checkpoint does not move it back.
+    index_block(&setup.indexer, &block(2)).await.unwrap();
+    assert_eq!(last_indexed_slot().await, Some(5));
 
-    info!("Test case 4: Large gaps in sequence numbers");
-    let leaf_nodes_4 = create_leaf_nodes(tree, 0..5, |i| i * 1000);
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_4, tree_height).await;
+    // Rolling back a fork rewinds the checkpoint along with the blocks.
+    let txn = setup.db_conn.begin().await.unwrap();
+    rollback_to_slot(&txn, setup.indexer.sql_batch_config, 4)
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+    assert_eq!(last_indexed_slot().await, Some(4));
 
-    info!("Test case 7: Very large tree");
-    let large_tree_height = 20;
-    let leaf_nodes_7 = create_leaf_nodes(tree, 0..20, |i| i);
-    test_persist_and_verify(
-        name.clone(),
-        db_backend,
-        tree,
-        leaf_nodes_7,
-        large_tree_height,
-    )
-    .await;
+    index_block(&setup.indexer, &block(6)).await.unwrap();
+    assert_eq!(last_indexed_slot().await, Some(6));
+}
 
-    info!("Test case 10: Random sequence numbers");
-    use log::info;
-    use rand::Rng;
-    let leaf_nodes_10 = create_leaf_nodes(tree, 0..10, |_| {
-        let mut rng = rand::thread_rng();
-        rng.gen_range(0..1000)
-    });
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_10, tree_height).await;
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_indexing_start_slot(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name.clone(), db_backend).await;
+    let stats_from = |slot: u64| {
+        setup.api.get_compression_stats(GetCompressionStatsRequest {
+            start_slot: Some(UnsignedInteger(slot)),
+            end_slot: None,
+        })
+    };
 
-    info!("Test case 11: Maximum sequence number (u32::MAX)");
-    let leaf_nodes_11 = create_leaf_nodes(tree, 0..5, |i| if i == 2 { u32::MAX as u64 } else { i });
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_11, tree_height).await;
+    let block = BlockInfo {
+        metadata: BlockMetadata {
+            slot: 150,
+            parent_slot: 149,
+            ..Default::default()
+        },
+        transactions: vec![],
+    };
+    index_block(&setup.indexer, &block).await.unwrap();
 
-    info!("Test case 12: Updating all leaves in reverse order");
-    let leaf_nodes_13b = create_leaf_nodes(tree, (0..=10).rev(), |i| i + 100);
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_13b, tree_height).await;
+    // Without a recorded start, all slots count as indexed.
+    assert!(stats_from(0).await.is_ok());
+
+    record_indexing_start_slot(setup.db_conn.as_ref(), 100)
+        .await
+        .unwrap();
+    // Restarting from a later slot keeps the earlier start.
+    record_indexing_start_slot(setup.db_conn.as_ref(), 200)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_indexing_start_slot(setup.db_conn.as_ref())
+            .await
+            .unwrap(),
+        Some(100)
+    );
+
+    let error = stats_from(99).await.unwrap_err();
+    assert_eq!(
+        error,
+        PhotonApiError::SlotNotIndexed {
+            slot: 99,
+            start_slot: 100
+        }
+    );
+    assert_eq!(error.kind(), "slot_not_indexed");
+    let error = setup
+        .api
+        .get_compression_stats(GetCompressionStatsRequest {
+            start_slot: None,
+            end_slot: Some(UnsignedInteger(50)),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), "slot_not_indexed");
+    assert_eq!(
+        stats_from(100).await.unwrap().value,
+        CompressionStats::default()
+    );
+
+    // Indexing from an earlier slot moves the start back.
+    record_indexing_start_slot(setup.db_conn.as_ref(), 0)
+        .await
+        .unwrap();
+    assert!(stats_from(99).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_signature_block_stream() {
+    let signature = Signature::new_unique();
+    let transaction = VersionedTransaction {
+        signatures: vec![signature],
+        message: VersionedMessage::Legacy(Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                ..Default::default()
+            },
+            account_keys: vec![Pubkey::new_unique(), ACCOUNT_COMPRESSION_PROGRAM_ID],
+            instructions: vec![CompiledInstruction {
+                program_id_index: 1,
+                accounts: vec![0],
+                data: vec![],
+            }],
+            ..Default::default()
+        }),
+    };
+    let blockhash = Hash::new_unique();
+    let encoded_transaction = EncodedTransactionWithStatusMeta {
+        transaction: EncodedTransaction::Binary(
+            base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                bincode::serialize(&transaction).unwrap(),
+            ),
+            TransactionBinaryEncoding::Base64,
+        ),
+        meta: Some(UiTransactionStatusMeta::from(
+            TransactionStatusMeta::default(),
+        )),
+        version: None,
+    };
+    let rpc_url = serve_rpc(HashMap::from([
+        (
+            "getVersion",
+            serde_json::json!({"result": {"solana-core": "1.18.22"}}),
+        ),
+        // Nothing happened after the last indexed slot but the compression transaction.
+        ("getSlot", serde_json::json!({"result": 2})),
+        (
+            "getSignaturesForAddress",
+            serde_json::json!({"result": [{
+                "signature": signature.to_string(),
+                "slot": 3,
+                "err": null,
+                "memo": null,
+                "blockTime": 0,
+            }]}),
+        ),
+        (
+            "getBlock",
+            serde_json::json!({"result": {
+                "previousBlockhash": Hash::new_unique().to_string(),
+                "blockhash": blockhash.to_string(),
+                "parentSlot": 1,
+                "blockTime": 0,
+                "blockHeight": 3,
+            }}),
+        ),
+        (
+            "getTransaction",
+            serde_json::json!({
+                "result": EncodedConfirmedTransactionWithStatusMeta {
+                    slot: 3,
+                    transaction: encoded_transaction,
+                    block_time: Some(0),
+                },
+            }),
+        ),
+    ]))
+    .await;
+    let rpc_client = Arc::new(RpcClient::new(rpc_url));
+
+    let stream = get_signature_block_stream(rpc_client, 2, 10);
+    pin_mut!(stream);
+    let blocks = stream.next().await.unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].metadata.slot, 3);
+    assert_eq!(blocks[0].metadata.parent_slot, 1);
+    assert_eq!(blocks[0].metadata.blockhash, blockhash);
+    assert_eq!(blocks[0].transactions.len(), 1);
+    assert_eq!(blocks[0].transactions[0].signature, signature);
+    assert_eq!(
+        blocks[0].transactions[0].instruction_groups[0]
+            .outer_instruction
+            .program_id,
+        ACCOUNT_COMPRESSION_PROGRAM_ID
+    );
+}
 
-    info!("Test case 13: Inserting leaves with all zero hashes");
-    let leaf_nodes_13 = (0..5)
-        .map(|i| LeafNode {
-            hash: Hash::try_from(vec![0; 32]).unwrap(),
-            leaf_index: i,
-            tree,
-            seq: i,
+#[tokio::test]
+async fn test_logs_block_stream() {
+    let missed_signature = Signature::new_unique();
+    let signature = Signature::new_unique();
+    let transaction = VersionedTransaction {
+        signatures: vec![signature],
+        message: VersionedMessage::Legacy(Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                ..Default::default()
+            },
+            account_keys: vec![Pubkey::new_unique(), ACCOUNT_COMPRESSION_PROGRAM_ID],
+            instructions: vec![CompiledInstruction {
+                program_id_index: 1,
+                accounts: vec![0],
+                data: vec![],
+            }],
+            ..Default::default()
+        }),
+    };
+    let encoded_transaction = EncodedTransactionWithStatusMeta {
+        transaction: EncodedTransaction::Binary(
+            base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                bincode::serialize(&transaction).unwrap(),
+            ),
+            TransactionBinaryEncoding::Base64,
+        ),
+        meta: Some(UiTransactionStatusMeta::from(
+            TransactionStatusMeta::default(),
+        )),
+        version: None,
+    };
+    let rpc_url = serve_rpc(HashMap::from([
+        (
+            "getVersion",
+            serde_json::json!({"result": {"solana-core": "1.18.22"}}),
+        ),
+        ("getSlot", serde_json::json!({"result": 3})),
+        (
+            "getBlock",
+            serde_json::json!({"result": {
+                "previousBlockhash": Hash::new_unique().to_string(),
+                "blockhash": Hash::new_unique().to_string(),
+                "parentSlot": 2,
+                "blockTime": 0,
+                "blockHeight": 3,
+            }}),
+        ),
+        (
+            "getTransaction",
+            serde_json::json!({
+                "result": EncodedConfirmedTransactionWithStatusMeta {
+                    slot: 4,
+                    transaction: encoded_transaction,
+                    block_time: Some(0),
+                },
+            }),
+        ),
+    ]))
+    .await;
+    let rpc_client = Arc::new(RpcClient::new(rpc_url));
+
+    // The first notification does not show whether earlier transactions of slot 3 were missed, so
+    // slot 3 is polled and only the transaction of slot 4 is fetched.
+    let notifications = [(3, missed_signature), (4, signature)].map(|(slot, signature)| {
+        serde_json::json!({
+            "context": {"slot": slot},
+            "value": {"signature": signature.to_string(), "err": null, "logs": []},
         })
-        .collect::<Vec<_>>();
-    test_persist_and_verify(name.clone(), db_backend, tree, leaf_nodes_13, tree_height).await;
+    });
+    let mut module = jsonrpsee::RpcModule::new(());
+    module
+        .register_subscription(
+            "logsSubscribe",
+            "logsNotification",
+            "logsUnsubscribe",
+            move |_, mut sink, _| {
+                let notifications =
+                    futures::stream::iter(notifications.clone()).chain(futures::stream::pending());
+                tokio::spawn(async move {
+                    sink.pipe_from_stream(Box::pin(notifications)).await;
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    let ws_server = jsonrpsee::server::ServerBuilder::default()
+        .build("127.0.0.1:0")
+        .await
+        .unwrap();
+    let ws_url = format!("ws://{}", ws_server.local_addr().unwrap());
+    let _ws_server_handle = ws_server.start(module).unwrap();
+
+    let stream = get_logs_block_stream_with_rpc_fallback(ws_url, rpc_client, 2, 10);
+    pin_mut!(stream);
+    let blocks = stream.next().await.unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].metadata.slot, 3);
+    let blocks = stream.next().await.unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].metadata.slot, 4);
+    assert_eq!(blocks[0].transactions.len(), 1);
+    assert_eq!(blocks[0].transactions[0].signature, signature);
 }
 
-fn create_leaf_nodes<F>(
-    tree: SerializablePubkey,
-    range: impl Iterator<Item = u64>,
-    seq_fn: F,
-) -> Vec<LeafNode>
-where
-    F: Fn(u64) -> u64,
-{
-    range
-        .map(|i| LeafNode {
-            hash: Hash::new_unique(),
-            leaf_index: i as u32,
-            tree,
-            seq: seq_fn(i) as u32,
+#[test]
+fn test_next_slot_poll_interval() {
+    let (min, max) = (Duration::from_millis(100), Duration::from_millis(400));
+    let next = |interval_ms, indexer_lag| {
+        next_slot_poll_interval(Duration::from_millis(interval_ms), indexer_lag, min, max)
+            .as_millis()
+    };
+    // An indexer that is behind gets the interval tightened down to the minimum.
+    assert_eq!(next(400, Some(3)), 200);
+    assert_eq!(next(150, Some(1000)), 100);
+    // Once it has caught up, the interval backs off up to the maximum.
+    assert_eq!(next(100, Some(0)), 200);
+    assert_eq!(next(300, Some(2)), 400);
+    assert_eq!(next(400, Some(1)), 400);
+    // Without an indexer, there is no reason to poll quickly.
+    assert_eq!(next(100, None), 200);
+}
+
+#[tokio::test]
+async fn test_parse_block_stream() {
+    let batches = vec![vec![1, 2, 3], vec![4], vec![5, 6]];
+    let block_stream = futures::stream::iter(batches.clone()).map(|slots| {
+        slots
+            .into_iter()
+            .map(|slot| BlockInfo {
+                metadata: BlockMetadata {
+                    slot,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect::<Vec<_>>()
+    });
+    // More workers than blocks per batch, so that batches are parsed concurrently.
+    let parsed_slots = parse_block_stream(block_stream, 4)
+        .map(|blocks| {
+            blocks
+                .into_iter()
+                .map(|block| block.block.metadata.slot)
+                .collect::<Vec<_>>()
         })
-        .collect()
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(parsed_slots, batches);
 }
 
-async fn test_persist_and_verify(
-    name: String,
-    db_backend: DatabaseBackend,
-    tree: SerializablePubkey,
-    mut leaf_nodes: Vec<LeafNode>,
-    tree_height: u32,
-) {
-    for one_at_a_time in [true, false] {
-        let setup = setup(name.clone(), db_backend).await;
+#[tokio::test]
+async fn test_pipeline_backpressure() {
+    let backpressure = Arc::new(Backpressure::new(4, 2));
+    backpressure.push(3);
+    tokio::time::timeout(Duration::from_secs(1), backpressure.wait_for_capacity())
+        .await
+        .unwrap();
 
-        let txn = setup.db_conn.as_ref().begin().await.unwrap();
-        if one_at_a_time {
-            for leaf_node in leaf_nodes.clone() {
-                persist_leaf_nodes(&txn, vec![leaf_node], tree_height)
-                    .await
-                    .unwrap();
+    // Fetching pauses at the high watermark until persistence drains to the low watermark.
+    backpressure.push(2);
+    let fetch = tokio::spawn({
+        let backpressure = backpressure.clone();
+        async move { backpressure.wait_for_capacity().await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    backpressure.pop(2);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!fetch.is_finished());
+    backpressure.pop(1);
+    tokio::time::timeout(Duration::from_secs(1), fetch)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(backpressure.queued_blocks(), 2);
+}
+
+#[tokio::test]
+async fn test_failover_rpc_client() {
+    // Counts the requests of an endpoint other than getVersion, and answers getSlot with `slot`
+    // and getBlock with a skipped slot error, or fails them with `status`.
+    async fn serve_endpoint(slot: u64, status: u16) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = serve(move |_, body| {
+            let request: serde_json::Value = serde_json::from_slice(body).unwrap();
+            let method = request["method"].as_str().unwrap();
+            if method == "getVersion" {
+                return (
+                    200,
+                    rpc_response(
+                        &request,
+                        serde_json::json!({"result": {"solana-core": "1.18.22"}}),
+                    ),
+                );
             }
-        } else {
-            persist_leaf_nodes(&txn, leaf_nodes.clone(), tree_height)
-                .await
-                .unwrap();
-        }
-        txn.commit().await.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            match method {
+                _ if status != 200 => (status, Vec::new()),
+                "getSlot" => (
+                    200,
+                    rpc_response(&request, serde_json::json!({"result": slot})),
+                ),
+                _ => (
+                    200,
+                    rpc_response(
+                        &request,
+                        serde_json::json!({"error": {"code": -32007, "message": "Slot skipped"}}),
+                    ),
+                ),
+            }
+        })
+        .await;
+        (url, requests)
+    }
+    let (failing_url, failing_requests) = serve_endpoint(1, 503).await;
+    let (healthy_url, healthy_requests) = serve_endpoint(2, 200).await;
+    let client = get_failover_rpc_client(
+        &[failing_url, healthy_url],
+        RpcRateLimitConfig {
+            max_retries: 0,
+            ..Default::default()
+        },
+    );
 
-        let mut leaf_node_indexes = vec![];
-        let mut de_duplicated_leaf_nodes = vec![];
+    assert_eq!(client.get_slot().await.unwrap(), 2);
+    assert_eq!(failing_requests.load(Ordering::SeqCst), 1);
+    // The failing endpoint is avoided during its cooldown.
+    assert_eq!(client.get_slot().await.unwrap(), 2);
+    assert_eq!(failing_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(healthy_requests.load(Ordering::SeqCst), 2);
+
+    // Errors that do not depend on the endpoint are not failed over.
+    let (other_url, other_requests) = serve_endpoint(3, 200).await;
+    let (unused_url, unused_requests) = serve_endpoint(4, 200).await;
+    let client = get_failover_rpc_client(&[other_url, unused_url], RpcRateLimitConfig::default());
+    assert!(client
+        .send::<serde_json::Value>(
+            solana_client::rpc_request::RpcRequest::GetBlock,
+            serde_json::json!([5]),
+        )
+        .await
+        .is_err());
+    assert_eq!(other_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(unused_requests.load(Ordering::SeqCst), 0);
+}
 
-        leaf_nodes.sort_by_key(|x| x.seq);
-        for leaf_node in leaf_nodes.into_iter().rev() {
-            if !leaf_node_indexes.contains(&leaf_node.leaf_index) {
-                leaf_node_indexes.push(leaf_node.leaf_index);
-                de_duplicated_leaf_nodes.push(leaf_node);
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_block_cache_replay(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let indexed_setup = setup(name.clone(), db_backend).await;
+
+    let tree = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let blocks = (1..=3)
+        .map(|slot: u64| {
+            let event = PublicTransactionEvent {
+                output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                    compressed_account: CompressedAccount {
+                        owner,
+                        lamports: 1000,
+                        address: None,
+                        data: None,
+                    },
+                    merkle_tree_index: 0,
+                }],
+                output_compressed_account_hashes: vec![Hash::new_unique().0],
+                output_leaf_indices: vec![slot as u32 - 1],
+                sequence_numbers: vec![MerkleTreeSequenceNumber {
+                    pubkey: tree,
+                    seq: slot - 1,
+                }],
+                pubkey_array: vec![tree],
+                ..Default::default()
+            };
+            BlockInfo {
+                metadata: BlockMetadata {
+                    slot,
+                    parent_slot: slot - 1,
+                    ..Default::default()
+                },
+                transactions: vec![compression_event_transaction(to_vec(&event).unwrap())],
             }
-        }
+        })
+        .collect::<Vec<_>>();
 
-        let state_tree_models = state_trees::Entity::find()
-            .filter(state_trees::Column::Level.eq(0))
-            .all(setup.db_conn.as_ref())
-            .await
-            .unwrap();
+    let dir = std::env::temp_dir().join(format!("photon_{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    set_block_cache_dir(Some(dir.clone()));
+    index_block_batch_with_infinite_retries(&indexed_setup.indexer, blocks.clone()).await;
+    set_block_cache_dir(None);
 
-        let hash_index_and_seq_tuples = state_tree_models
-            .iter()
-            .map(|x| {
-                (
-                    Hash::try_from(x.hash.clone()).unwrap(),
-                    x.leaf_idx.unwrap_or(0) as u64,
-                    x.seq as u64,
-                )
-            })
-            .collect::<Vec<_>>();
+    assert_eq!(cached_slots(&dir, 0, u64::MAX).unwrap(), vec![1, 2, 3]);
+    assert_eq!(cached_slots(&dir, 2, 2).unwrap(), vec![2]);
+    assert_eq!(load_cached_block(&dir, 2).unwrap(), blocks[1]);
 
-        println!(
-            "hash_index_and_seq_tuples: {:#?}",
-            hash_index_and_seq_tuples
-        );
-        leaf_nodes = de_duplicated_leaf_nodes;
-        let proofs = get_multiple_compressed_leaf_proofs(
-            &setup.db_conn.begin().await.unwrap(),
-            leaf_nodes
-                .iter()
-                .map(|x| Hash::try_from(x.hash.clone()).unwrap())
-                .collect(),
-        )
+    // Replaying into a fresh database restores the same state without fetching the blocks.
+    let replay_setup = setup(format!("{}_replayed", name), db_backend).await;
+    let replayed_blocks = replay_cached_blocks(&replay_setup.indexer, &dir, 0, u64::MAX)
         .await
         .unwrap();
-
-        let proof_hashes: HashSet<Hash> = proofs.iter().map(|x| x.hash.clone()).collect();
-        let leaf_hashes: HashSet<Hash> = leaf_nodes.iter().map(|x| x.hash.clone()).collect();
-        assert_eq!(
-            proof_hashes, leaf_hashes,
-            "Proof hashes should match leaf hashes"
-        );
-
-        let max_seq = leaf_nodes.iter().map(|x| x.seq).max().unwrap_or(0) as u64;
-
-        for proof in proofs {
-            assert_eq!(proof.merkleTree, tree, "Merkle tree should match");
-            assert_eq!(
-                max_seq, proof.rootSeq,
-                "Root sequence should be the maximum sequence number"
-            );
-            assert_eq!(
-                tree_height - 1,
-                proof.proof.len() as u32,
-                "Proof length should be tree height minus one"
-            );
-        }
-        validate_tree(&setup.db_conn, tree).await;
-    }
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(replayed_blocks, 3);
+    assert_eq!(replay_setup.api.get_indexer_slot().await.unwrap().0, 3);
+    let accounts = replay_setup
+        .api
+        .get_compressed_accounts_by_owner(GetCompressedAccountsByOwnerRequest {
+            owner: SerializablePubkey::from(owner),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .value;
+    assert_eq!(accounts.items.len(), 3);
 }
 
 #[named]
 #[rstest]
 #[tokio::test]
 #[serial]
-async fn test_update_indexed_merkle_tree(
+async fn test_leaf_audit(
     #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
 ) {
-    use itertools::Itertools;
-    use photon_indexer::ingester::parser::{
-        indexer_events::RawIndexedElement, state_update::IndexedTreeLeafUpdate,
-    };
     let name = trim_test_name(function_name!());
-    let setup = setup(name.clone(), db_backend).await;
-    let tree = Pubkey::new_unique();
-    let tree_height = 10;
-    let index = 1;
-    let value = [1; 32];
-    let index_element_1 = RawIndexedElement {
-        value,
-        next_index: 3,
-        next_value: [2; 32],
-        index,
-    };
-    let index_element_2 = RawIndexedElement {
-        value,
-        next_index: 4,
-        next_value: [7; 32],
-        index,
-    };
-    let paramaeters = vec![(index_element_1, 0), (index_element_2, 1)];
-    for permutation in paramaeters.iter().permutations(2) {
-        let txn = setup.db_conn.as_ref().begin().await.unwrap();
-        for (indexed_element, seq) in permutation {
-            let mut indexed_leaf_updates = HashMap::new();
-            indexed_leaf_updates.insert(
-                (tree, index as u64),
-                IndexedTreeLeafUpdate {
-                    tree,
-                    leaf: indexed_element.clone(),
-                    hash: Hash::new_unique().into(), // HACK: We don't care about the hash
-                    seq: *seq as u64,
-                },
-            );
-            update_indexed_tree_leaves(&txn, indexed_leaf_updates, tree_height)
+    let setup = setup(name, db_backend).await;
+
+    // Recorded transactions are indexed at slot 0.
+    index_block(
+        &setup.indexer,
+        &BlockInfo {
+            metadata: BlockMetadata {
+                slot: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    // A recorded compress transaction that creates a single account.
+    let recording = "nullfiier_and_address_queue_transactions";
+    let compress_tx =
+        "35zJYUMreV5BRzuzSSfqSVWzLtMqPtWtLBkkH2CP24gFWijju46Vi3ARawzxs22GqZPbXo6uzSosaUXGLgRA9Hth";
+    index_transaction(
+        recording,
+        setup.db_conn.clone(),
+        setup.client.clone(),
+        compress_tx,
+    )
+    .await;
+    let transaction: serde_json::Value = serde_json::from_slice(
+        &std::fs::read(relative_project_path(&format!(
+            "tests/data/transactions/{}/{}",
+            recording, compress_tx
+        )))
+        .unwrap(),
+    )
+    .unwrap();
+    let audit = |transaction: serde_json::Value, selection: LeafSelection| {
+        let db_conn = setup.db_conn.clone();
+        async move {
+            let rpc_url = serve_rpc(HashMap::from([
+                (
+                    "getVersion",
+                    serde_json::json!({"result": {"solana-core": "1.18.22"}}),
+                ),
+                (
+                    "getTransaction",
+                    serde_json::json!({ "result": transaction }),
+                ),
+            ]))
+            .await;
+            audit_tree_leaves(db_conn.as_ref(), &RpcClient::new(rpc_url), selection)
                 .await
-                .unwrap();
+                .unwrap()
         }
-        txn.commit().await.unwrap();
-        let tree_model = indexed_trees::Entity::find()
-            .filter(
-                indexed_trees::Column::Tree
-                    .eq(tree.to_bytes().to_vec())
-                    .and(indexed_trees::Column::LeafIndex.eq(1)),
-            )
-            .one(setup.db_conn.as_ref())
-            .await
-            .unwrap()
-            .unwrap();
-        assert_eq!(tree_model.value, index_element_2.value);
-        assert_eq!(tree_model.next_value, index_element_2.next_value);
-        assert_eq!(tree_model.next_index, index_element_2.next_index as i64);
-        assert_eq!(tree_model.seq, 1 as i64);
-    }
+    };
+
+    assert_eq!(
+        audit(transaction.clone(), LeafSelection::Sample(10)).await,
+        LeafAuditReport {
+            verified_leaves: 1,
+            unverified_leaves: 0,
+            discrepancies: 0,
+        }
+    );
+    // Transactions that the RPC node no longer has cannot be checked.
+    assert_eq!(
+        audit(serde_json::Value::Null, LeafSelection::All).await,
+        LeafAuditReport {
+            verified_leaves: 0,
+            unverified_leaves: 1,
+            discrepancies: 0,
+        }
+    );
+
+    // An account indexed at another leaf than the one that holds it is reported.
+    accounts::Entity::update_many()
+        .col_expr(accounts::Column::LeafIndex, Expr::value(1000))
+        .exec(setup.db_conn.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(
+        audit(transaction, LeafSelection::All).await.discrepancies,
+        1
+    );
 }
@@ -1,6 +1,11 @@
-use photon_indexer::openapi::update_docs;
+use photon_indexer::openapi::{update_api_spec, update_docs};
 
 #[test]
 pub fn test_documentation_generation() {
     update_docs(true);
 }
+
+#[test]
+pub fn test_api_spec_is_up_to_date() {
+    update_api_spec(true);
+}
@@ -77,6 +77,7 @@ async fn test_basic_snapshotting() {
                     blockhash: Hash::default(),
                     parent_blockhash: Hash::default(),
                     block_height: i,
+                    received_at: None,
                 },
                 transactions: vec![],
             })
@@ -11,8 +11,9 @@ use photon_indexer::{
     },
     ingester::{
         parser::{parse_transaction, state_update::StateUpdate},
-        persist::persist_state_update,
+        persist::{batch_size::SqlBatchConfig, persist_state_update},
         typedefs::block_info::{parse_ui_confirmed_blocked, BlockInfo, TransactionInfo},
+        IndexerContext,
     },
 };
 pub use sea_orm::DatabaseBackend;
@@ -79,6 +80,7 @@ async fn run_one_time_setup(db: &DatabaseConnection) {
 
 pub struct TestSetup {
     pub db_conn: Arc<DatabaseConnection>,
+    pub indexer: IndexerContext,
     pub api: PhotonApi,
     pub name: String,
     pub client: Arc<RpcClient>,
@@ -140,6 +142,7 @@ pub async fn setup_with_options(name: String, opts: TestSetupOptions) -> TestSet
     let api = PhotonApi::new(db_conn.clone(), client.clone(), prover_url.clone());
     TestSetup {
         name,
+        indexer: IndexerContext::new(db_conn.clone()),
         db_conn,
         api,
         client: client.clone(),
@@ -363,7 +366,13 @@ pub async fn persist_state_update_using_connection(
     state_update: StateUpdate,
 ) -> Result<(), sea_orm::DbErr> {
     let txn = db.begin().await.unwrap();
-    persist_state_update(&txn, state_update).await.unwrap();
+    persist_state_update(
+        &txn,
+        SqlBatchConfig::new(db.get_database_backend()),
+        state_update,
+    )
+    .await
+    .unwrap();
     txn.commit().await.unwrap();
     Ok(())
 }
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use crate::utils::*;
+use function_name::named;
+use photon_indexer::common::typedefs::account::Account;
+use photon_indexer::common::typedefs::hash::Hash;
+use photon_indexer::common::typedefs::serializable_pubkey::SerializablePubkey;
+use photon_indexer::common::typedefs::u64_string::U64String;
+use photon_indexer::common::typedefs::unsigned_integer::UnsignedInteger;
+use photon_indexer::ingester::events::PendingAccountEvents;
+use photon_indexer::ingester::parser::state_update::StateUpdate;
+use photon_indexer::ingester::typedefs::block_info::BlockMetadata;
+use photon_indexer::ingester::webhooks::{
+    sign_payload, start_webhook_dispatcher, WebhookConfig, SIGNATURE_HEADER,
+};
+use serial_test::serial;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+struct ReceivedRequest {
+    signature: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Minimal HTTP server that fails the first request with a 500 to exercise retries and accepts
+/// every following one.
+async fn start_webhook_receiver() -> (String, mpsc::UnboundedReceiver<ReceivedRequest>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut requests_served = 0;
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            loop {
+                let mut content_length = 0;
+                let mut signature = None;
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if stream.read_line(&mut line).await.unwrap() == 0 {
+                        break;
+                    }
+                    let header = line.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(": ") {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.parse().unwrap();
+                        }
+                        if name.eq_ignore_ascii_case(SIGNATURE_HEADER) {
+                            signature = Some(value.to_string());
+                        }
+                    }
+                }
+                if line.is_empty() {
+                    break;
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).await.unwrap();
+                let status = if requests_served == 0 {
+                    "500 Internal Server Error"
+                } else {
+                    "200 OK"
+                };
+                requests_served += 1;
+                stream
+                    .get_mut()
+                    .write_all(format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n").as_bytes())
+                    .await
+                    .unwrap();
+                sender.send(ReceivedRequest { signature, body }).unwrap();
+            }
+        }
+    });
+    (url, receiver)
+}
+
+#[named]
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_webhook_delivery(
+    #[values(DatabaseBackend::Sqlite, DatabaseBackend::Postgres)] db_backend: DatabaseBackend,
+) {
+    let name = trim_test_name(function_name!());
+    let setup = setup(name, db_backend).await;
+    let (url, mut requests) = start_webhook_receiver().await;
+
+    let account = Account {
+        hash: Hash::new_unique(),
+        address: Some(SerializablePubkey::new_unique()),
+        data: None,
+        owner: SerializablePubkey::new_unique(),
+        lamports: U64String(1000),
+        tree: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(0),
+        seq: UnsignedInteger(0),
+        slot_created: UnsignedInteger(5),
+    };
+    let unwatched_account = Account {
+        hash: Hash::new_unique(),
+        owner: SerializablePubkey::new_unique(),
+        leaf_index: UnsignedInteger(1),
+        ..account.clone()
+    };
+    let secret = "secret".to_string();
+    let dispatcher = start_webhook_dispatcher(vec![WebhookConfig {
+        url,
+        secret: Some(secret.clone()),
+        owners: vec![account.owner],
+        mints: vec![],
+        trees: vec![],
+    }]);
+
+    let mut state_update = StateUpdate::new();
+    state_update.out_accounts.push(account.clone());
+    state_update.out_accounts.push(unwatched_account);
+    let block = BlockMetadata {
+        slot: 5,
+        block_time: 1_700_000_000,
+        ..Default::default()
+    };
+    let events = PendingAccountEvents::collect(&state_update, &[&block]).unwrap();
+    persist_state_update_using_connection(&setup.db_conn, state_update)
+        .await
+        .unwrap();
+    events
+        .publish(&setup.db_conn, setup.indexer.sql_batch_config)
+        .await;
+
+    // The first delivery attempt is rejected, so the same payload must be posted again.
+    let failed_attempt = requests.recv().await.unwrap();
+    let retried_attempt = requests.recv().await.unwrap();
+    assert_eq!(failed_attempt.body, retried_attempt.body);
+    assert_eq!(
+        retried_attempt.signature,
+        Some(sign_payload(&secret, &retried_attempt.body))
+    );
+
+    let payload: serde_json::Value = serde_json::from_slice(&retried_attempt.body).unwrap();
+    assert_eq!(payload["eventType"], "accountCreated");
+    assert_eq!(payload["slot"], 5);
+    assert_eq!(payload["blockTime"], 1_700_000_000);
+    assert_eq!(payload["account"], serde_json::to_value(&account).unwrap());
+
+    // The account of the other owner does not match the filter.
+    assert!(
+        tokio::time::timeout(Duration::from_secs(1), requests.recv())
+            .await
+            .is_err()
+    );
+    dispatcher.abort();
+}
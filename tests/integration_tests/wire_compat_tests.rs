@@ -0,0 +1,86 @@
+use photon_indexer::common::relative_project_path;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// A recorded JSON-RPC request to replay against both endpoints.
+#[derive(Deserialize)]
+struct RecordedRequest {
+    method: String,
+    params: Value,
+}
+
+async fn send_request(client: &reqwest::Client, url: &str, request: &RecordedRequest) -> Value {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": "wire-compat",
+        "method": request.method,
+        "params": request.params,
+    });
+    let response = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap_or_else(|e| panic!("Failed to send {} to {}: {}", request.method, url, e))
+        .text()
+        .await
+        .unwrap();
+    serde_json::from_str(&response)
+        .unwrap_or_else(|e| panic!("Invalid JSON response from {}: {} {}", url, e, response))
+}
+
+/// Removes the parts of a response that legitimately differ between two indexers, such as the
+/// slot the response was served at.
+fn normalize_response(mut response: Value) -> Value {
+    if let Some(result) = response.get_mut("result").and_then(Value::as_object_mut) {
+        result.remove("context");
+    }
+    if let Some(error) = response.get_mut("error").and_then(Value::as_object_mut) {
+        // Error messages are not part of the wire format, only the codes are.
+        error.remove("message");
+    }
+    response
+}
+
+// Replays the requests recorded in tests/data/wire_compat/requests.json against the Photon
+// build under test and a reference deployment and fails on any difference in the responses.
+// Both instances should be pointed at the same network and be roughly caught up.
+//
+// PHOTON_URL=http://localhost:8784 REFERENCE_PHOTON_URL=<url> \
+//     cargo test wire_compat -- --ignored
+#[tokio::test]
+#[ignore]
+async fn test_wire_compatibility_with_reference() {
+    let photon_url = std::env::var("PHOTON_URL").unwrap_or("http://localhost:8784".to_string());
+    let reference_url =
+        std::env::var("REFERENCE_PHOTON_URL").expect("REFERENCE_PHOTON_URL must be set");
+    let requests_path = std::env::var("WIRE_COMPAT_REQUESTS").map_or_else(
+        |_| relative_project_path("tests/data/wire_compat/requests.json"),
+        Into::into,
+    );
+    let requests: Vec<RecordedRequest> =
+        serde_json::from_slice(&std::fs::read(&requests_path).unwrap()).unwrap();
+
+    let client = reqwest::Client::new();
+    let mut mismatches = Vec::new();
+    for request in requests.iter() {
+        let response = normalize_response(send_request(&client, &photon_url, request).await);
+        let reference_response =
+            normalize_response(send_request(&client, &reference_url, request).await);
+        if response != reference_response {
+            mismatches.push(format!(
+                "{} {}\n  photon:    {}\n  reference: {}",
+                request.method, request.params, response, reference_response
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{} of {} requests returned different responses:\n{}",
+        mismatches.len(),
+        requests.len(),
+        mismatches.join("\n")
+    );
+}